@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use grovedb::{Element, PathQuery};
+use pyo3::{
+    prelude::*,
+    types::{PyBytes, PyDict, PyList, PyTuple},
+};
+
+use crate::GroveDbError;
+
+fn element_to_string(element: &Element) -> &'static str {
+    match element {
+        Element::Item(_) => "item",
+        Element::Reference(_) => "reference",
+        Element::Tree(_) => "tree",
+        Element::PrunedTree(_) => "pruned_tree",
+        Element::DedupItem(_) => "dedup_item",
+    }
+}
+
+pub fn py_dict_to_element(dict: &PyDict) -> PyResult<Element> {
+    let element_type: String = dict
+        .get_item("type")
+        .ok_or_else(|| GroveDbError::new_err("element has no type"))?
+        .extract()?;
+    let value = dict
+        .get_item("value")
+        .ok_or_else(|| GroveDbError::new_err("element has no value"))?;
+
+    match element_type.as_str() {
+        "item" => Ok(Element::Item(value.extract()?)),
+        "reference" => Ok(Element::Reference(value.extract()?)),
+        "tree" => {
+            let tree: Vec<u8> = value.extract()?;
+            Ok(Element::Tree(tree.try_into().map_err(|v: Vec<u8>| {
+                GroveDbError::new_err(format!(
+                    "Tree value is expected to be 32 bytes long, but got {}",
+                    v.len()
+                ))
+            })?))
+        }
+        _ => Err(GroveDbError::new_err(format!(
+            "Unexpected element type {}",
+            element_type
+        ))),
+    }
+}
+
+pub fn element_to_py_dict(py: Python, element: Element) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("type", element_to_string(&element))?;
+    let value: PyObject = match element {
+        Element::Item(item) => PyBytes::new(py, &item).into(),
+        Element::Reference(reference) => nested_vecs_to_py(py, reference),
+        Element::Tree(tree) | Element::PrunedTree(tree) | Element::DedupItem(tree) => {
+            PyBytes::new(py, &tree).into()
+        }
+    };
+    dict.set_item("value", value)?;
+    Ok(dict.into())
+}
+
+pub fn nested_vecs_to_py(py: Python, v: Vec<Vec<u8>>) -> PyObject {
+    PyList::new(py, v.iter().map(|bytes| PyBytes::new(py, bytes))).into()
+}
+
+pub fn str_to_path_query(query: &str) -> PyResult<PathQuery> {
+    query
+        .parse()
+        .map_err(|e: grovedb::Error| GroveDbError::new_err(e.to_string()))
+}
+
+/// Converts results of an executed proof into a dict of lists of key-value
+/// pairs keyed by subtree paths as tuples
+pub fn proof_results_to_py_dict(
+    py: Python,
+    results: HashMap<Vec<Vec<u8>>, Vec<(Vec<u8>, Vec<u8>)>>,
+) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    for (path, entries) in results {
+        let path = PyTuple::new(py, path.iter().map(|segment| PyBytes::new(py, segment)));
+        let entries = PyList::new(
+            py,
+            entries
+                .iter()
+                .map(|(key, value)| (PyBytes::new(py, key), PyBytes::new(py, value)).to_object(py)),
+        );
+        dict.set_item(path, entries)?;
+    }
+    Ok(dict.into())
+}
@@ -0,0 +1,314 @@
+mod converter;
+
+use std::sync::mpsc;
+
+use grovedb::{GroveDb, Transaction, TransactionArg};
+use pyo3::{
+    create_exception,
+    exceptions::PyException,
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+
+use crate::converter::{
+    element_to_py_dict, proof_results_to_py_dict, py_dict_to_element, str_to_path_query,
+};
+
+create_exception!(grovedb_py, GroveDbError, PyException);
+
+type DbCallback = Box<dyn for<'a> FnOnce(&'a GroveDb, TransactionArg) + Send>;
+type Reply = mpsc::Sender<PyResult<()>>;
+
+// Messages sent on the database channel
+enum DbMessage {
+    // Callback to be executed
+    Callback(DbCallback),
+    StartTransaction(Reply),
+    CommitTransaction(Reply),
+    RollbackTransaction(Reply),
+}
+
+fn to_py_err(error: grovedb::Error) -> PyErr {
+    GroveDbError::new_err(error.to_string())
+}
+
+fn closed_err() -> PyErr {
+    GroveDbError::new_err("database is closed")
+}
+
+// Same as node.js bindings, a dedicated thread owns the GroveDb instance and
+// its open transaction, which borrows it, so neither has to be shared with
+// Python objects
+fn run_db_thread(grove_db: GroveDb, rx: mpsc::Receiver<DbMessage>) {
+    let mut transaction: Option<Transaction> = None;
+
+    // The loop ends once all senders are dropped, closing the database
+    while let Ok(message) = rx.recv() {
+        match message {
+            DbMessage::Callback(callback) => callback(&grove_db, transaction.as_ref()),
+            DbMessage::StartTransaction(reply) => {
+                let result = if transaction.is_some() {
+                    Err(GroveDbError::new_err("transaction is already started"))
+                } else {
+                    transaction = Some(grove_db.start_transaction());
+                    Ok(())
+                };
+                let _ = reply.send(result);
+            }
+            DbMessage::CommitTransaction(reply) => {
+                let result = match transaction.take() {
+                    Some(tx) => grove_db.commit_transaction(tx).map_err(to_py_err),
+                    None => Err(GroveDbError::new_err("transaction is not started")),
+                };
+                let _ = reply.send(result);
+            }
+            DbMessage::RollbackTransaction(reply) => {
+                let result = match transaction.take() {
+                    Some(tx) => grove_db.rollback_transaction(&tx).map_err(to_py_err),
+                    None => Err(GroveDbError::new_err("transaction is not started")),
+                };
+                let _ = reply.send(result);
+            }
+        }
+    }
+}
+
+/// Sends a message with a reply channel and waits for the reply without
+/// holding the GIL
+fn request(
+    py: Python,
+    tx: &mpsc::Sender<DbMessage>,
+    message: impl FnOnce(Reply) -> DbMessage,
+) -> PyResult<()> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    tx.send(message(reply_tx)).map_err(|_| closed_err())?;
+    py.allow_threads(|| reply_rx.recv())
+        .map_err(|_| closed_err())?
+}
+
+/// GroveDB handle, operations run in the transaction while one is started
+/// with `transaction()`
+#[pyclass(name = "GroveDb")]
+struct GroveDbWrapper {
+    tx: Option<mpsc::Sender<DbMessage>>,
+}
+
+impl GroveDbWrapper {
+    fn sender(&self) -> PyResult<&mpsc::Sender<DbMessage>> {
+        self.tx.as_ref().ok_or_else(closed_err)
+    }
+
+    fn call<T: Send + 'static>(
+        &self,
+        py: Python,
+        callback: impl for<'a> FnOnce(&'a GroveDb, TransactionArg) -> PyResult<T> + Send + 'static,
+    ) -> PyResult<T> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.sender()?
+            .send(DbMessage::Callback(Box::new(
+                move |grove_db, transaction| {
+                    let _ = reply_tx.send(callback(grove_db, transaction));
+                },
+            )))
+            .map_err(|_| closed_err())?;
+        py.allow_threads(|| reply_rx.recv())
+            .map_err(|_| closed_err())?
+    }
+}
+
+#[pymethods]
+impl GroveDbWrapper {
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        let grove_db = GroveDb::open(path).map_err(to_py_err)?;
+        let (tx, rx) = mpsc::channel::<DbMessage>();
+        std::thread::spawn(move || run_db_thread(grove_db, rx));
+        Ok(Self { tx: Some(tx) })
+    }
+
+    fn insert(
+        &self,
+        py: Python,
+        path: Vec<Vec<u8>>,
+        key: Vec<u8>,
+        element: &PyDict,
+    ) -> PyResult<()> {
+        let element = py_dict_to_element(element)?;
+        self.call(py, move |grove_db, transaction| {
+            grove_db
+                .insert(
+                    path.iter().map(|x| x.as_slice()),
+                    &key,
+                    element,
+                    transaction,
+                )
+                .map_err(to_py_err)
+        })
+    }
+
+    fn insert_if_not_exists(
+        &self,
+        py: Python,
+        path: Vec<Vec<u8>>,
+        key: Vec<u8>,
+        element: &PyDict,
+    ) -> PyResult<bool> {
+        let element = py_dict_to_element(element)?;
+        self.call(py, move |grove_db, transaction| {
+            grove_db
+                .insert_if_not_exists(
+                    path.iter().map(|x| x.as_slice()),
+                    &key,
+                    element,
+                    transaction,
+                )
+                .map_err(to_py_err)
+        })
+    }
+
+    fn get(&self, py: Python, path: Vec<Vec<u8>>, key: Vec<u8>) -> PyResult<PyObject> {
+        let element = self.call(py, move |grove_db, transaction| {
+            grove_db
+                .get(path.iter().map(|x| x.as_slice()), &key, transaction)
+                .map_err(to_py_err)
+        })?;
+        element_to_py_dict(py, element)
+    }
+
+    fn delete(&self, py: Python, path: Vec<Vec<u8>>, key: Vec<u8>) -> PyResult<()> {
+        self.call(py, move |grove_db, transaction| {
+            grove_db
+                .delete(path.iter().map(|x| x.as_slice()), &key, transaction)
+                .map_err(to_py_err)
+        })
+    }
+
+    fn put_aux(&self, py: Python, key: Vec<u8>, value: Vec<u8>) -> PyResult<()> {
+        self.call(py, move |grove_db, transaction| {
+            grove_db
+                .put_aux(&key, &value, transaction)
+                .map_err(to_py_err)
+        })
+    }
+
+    fn get_aux(&self, py: Python, key: Vec<u8>) -> PyResult<Option<PyObject>> {
+        let value = self.call(py, move |grove_db, transaction| {
+            grove_db.get_aux(&key, transaction).map_err(to_py_err)
+        })?;
+        Ok(value.map(|value| PyBytes::new(py, &value).into()))
+    }
+
+    /// Runs a query written in the textual query syntax, e.g.
+    /// `path: a/b, range: [k1, k2), limit 10`, and returns values of found
+    /// items
+    fn query(&self, py: Python, query: &str) -> PyResult<Vec<PyObject>> {
+        let path_query = str_to_path_query(query)?;
+        let (values, _) = self.call(py, move |grove_db, transaction| {
+            grove_db
+                .get_path_query(&path_query, transaction)
+                .map_err(to_py_err)
+        })?;
+        Ok(values
+            .iter()
+            .map(|value| PyBytes::new(py, value).into())
+            .collect())
+    }
+
+    /// Proves queries written in the textual query syntax
+    fn prove(&self, py: Python, queries: Vec<String>) -> PyResult<PyObject> {
+        let path_queries = queries
+            .iter()
+            .map(|query| str_to_path_query(query))
+            .collect::<PyResult<Vec<_>>>()?;
+        let proof = self.call(py, move |grove_db, transaction| {
+            grove_db
+                .prove(&path_queries, transaction)
+                .map_err(to_py_err)
+        })?;
+        Ok(PyBytes::new(py, &proof).into())
+    }
+
+    /// Verifies a proof and returns the root hash with proved key-value
+    /// pairs keyed by subtree paths
+    #[staticmethod]
+    fn verify(py: Python, proof: Vec<u8>) -> PyResult<(PyObject, PyObject)> {
+        let (root_hash, results) = py
+            .allow_threads(|| GroveDb::execute_proof(&proof))
+            .map_err(to_py_err)?;
+        let results = results
+            .into_iter()
+            .map(|(path, map)| {
+                let entries = map
+                    .all()
+                    .map(|(key, (_, value))| (key.clone(), value.clone()))
+                    .collect();
+                (path, entries)
+            })
+            .collect();
+        Ok((
+            PyBytes::new(py, &root_hash).into(),
+            proof_results_to_py_dict(py, results)?,
+        ))
+    }
+
+    fn root_hash(&self, py: Python) -> PyResult<Option<PyObject>> {
+        let root_hash = self.call(py, |grove_db, transaction| {
+            grove_db.root_hash(transaction).map_err(to_py_err)
+        })?;
+        Ok(root_hash.map(|root_hash| PyBytes::new(py, &root_hash).into()))
+    }
+
+    fn flush(&self, py: Python) -> PyResult<()> {
+        self.call(py, |grove_db, _| grove_db.flush().map_err(to_py_err))
+    }
+
+    /// Returns a context manager starting a transaction on enter, which is
+    /// committed on exit or rolled back if an exception was raised
+    fn transaction(&self) -> PyResult<TransactionContext> {
+        Ok(TransactionContext {
+            tx: self.sender()?.clone(),
+        })
+    }
+
+    /// Closes the database, an open transaction is dropped
+    fn close(&mut self) {
+        self.tx = None;
+    }
+}
+
+#[pyclass(name = "Transaction")]
+struct TransactionContext {
+    tx: mpsc::Sender<DbMessage>,
+}
+
+#[pymethods]
+impl TransactionContext {
+    fn __enter__(slf: PyRef<Self>, py: Python) -> PyResult<PyRef<Self>> {
+        request(py, &slf.tx, DbMessage::StartTransaction)?;
+        Ok(slf)
+    }
+
+    fn __exit__(
+        &self,
+        py: Python,
+        exc_type: Option<&PyAny>,
+        _exc_value: Option<&PyAny>,
+        _traceback: Option<&PyAny>,
+    ) -> PyResult<bool> {
+        if exc_type.is_none() {
+            request(py, &self.tx, DbMessage::CommitTransaction)?;
+        } else {
+            request(py, &self.tx, DbMessage::RollbackTransaction)?;
+        }
+        // Exceptions are not suppressed
+        Ok(false)
+    }
+}
+
+#[pymodule]
+fn grovedb_py(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<GroveDbWrapper>()?;
+    m.add_class::<TransactionContext>()?;
+    m.add("GroveDbError", py.get_type::<GroveDbError>())?;
+    Ok(())
+}
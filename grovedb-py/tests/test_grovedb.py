@@ -0,0 +1,62 @@
+import pytest
+
+import grovedb_py
+
+TREE = {"type": "tree", "value": bytes(32)}
+
+
+@pytest.fixture
+def db(tmp_path):
+    db = grovedb_py.GroveDb(str(tmp_path / "test.db"))
+    db.insert([], b"test_tree", TREE)
+    yield db
+    db.close()
+
+
+def test_store_and_retrieve_a_value(db):
+    db.insert([b"test_tree"], b"key", {"type": "item", "value": b"value"})
+
+    assert db.get([b"test_tree"], b"key") == {"type": "item", "value": b"value"}
+
+
+def test_store_and_delete_a_value(db):
+    db.insert([b"test_tree"], b"key", {"type": "item", "value": b"value"})
+    db.delete([b"test_tree"], b"key")
+
+    with pytest.raises(grovedb_py.GroveDbError):
+        db.get([b"test_tree"], b"key")
+
+
+def test_transaction_is_committed_on_exit(db):
+    with db.transaction():
+        db.insert([b"test_tree"], b"key", {"type": "item", "value": b"value"})
+
+    assert db.get([b"test_tree"], b"key")["value"] == b"value"
+
+
+def test_transaction_is_rolled_back_on_exception(db):
+    with pytest.raises(RuntimeError):
+        with db.transaction():
+            db.insert([b"test_tree"], b"key", {"type": "item", "value": b"value"})
+            raise RuntimeError()
+
+    with pytest.raises(grovedb_py.GroveDbError):
+        db.get([b"test_tree"], b"key")
+
+
+def test_query_prove_and_verify(db):
+    for key in [b"a", b"b", b"c"]:
+        db.insert([b"test_tree"], key, {"type": "item", "value": key})
+
+    assert db.query("path: test_tree, range: [a, c), desc") == [b"b", b"a"]
+
+    proof = db.prove(["path: test_tree, range: [a, c)"])
+    root_hash, results = grovedb_py.GroveDb.verify(proof)
+    assert root_hash == db.root_hash()
+    assert [key for key, _ in results[(b"test_tree",)]] == [b"a", b"b"]
+
+
+def test_aux(db):
+    assert db.get_aux(b"key") is None
+    db.put_aux(b"key", b"value")
+    assert db.get_aux(b"key") == b"value"
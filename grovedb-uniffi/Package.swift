@@ -0,0 +1,17 @@
+// swift-tools-version:5.5
+import PackageDescription
+
+// Run swift/build-xcframework.sh first to build the Rust library and
+// generate Swift bindings
+let package = Package(
+    name: "GroveDB",
+    platforms: [.iOS(.v13), .macOS(.v11)],
+    products: [
+        .library(name: "GroveDB", targets: ["GroveDB"]),
+    ],
+    targets: [
+        .binaryTarget(name: "GroveDBFFI", path: "swift/GroveDBFFI.xcframework"),
+        .target(name: "GroveDB", dependencies: ["GroveDBFFI"], path: "swift/Sources/GroveDB"),
+        .testTarget(name: "GroveDBTests", dependencies: ["GroveDB"], path: "swift/Tests/GroveDBTests"),
+    ]
+)
@@ -0,0 +1,303 @@
+//! GroveDB bindings for mobile platforms generated with UniFFI. Light clients
+//! build path queries and verify proofs returned by full nodes with the same
+//! code as the server, without a database of their own. Subqueries are not
+//! supported yet.
+
+use std::sync::Mutex;
+
+use grovedb::{Query, SizedQuery};
+
+#[derive(Debug, thiserror::Error)]
+pub enum GroveDbError {
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
+    #[error("invalid proof: {0}")]
+    InvalidProof(String),
+    #[error("invalid root hash: {0}")]
+    InvalidRootHash(String),
+    #[error("proof root hash doesn't match the trusted root hash")]
+    RootHashMismatch,
+}
+
+pub enum QueryItem {
+    Key { key: Vec<u8> },
+    Range { start: Vec<u8>, end: Vec<u8> },
+    RangeInclusive { start: Vec<u8>, end: Vec<u8> },
+    RangeFull,
+    RangeFrom { start: Vec<u8> },
+    RangeTo { end: Vec<u8> },
+    RangeToInclusive { end: Vec<u8> },
+    RangeAfter { start: Vec<u8> },
+    RangeAfterTo { start: Vec<u8>, end: Vec<u8> },
+    RangeAfterToInclusive { start: Vec<u8>, end: Vec<u8> },
+}
+
+impl From<QueryItem> for grovedb::QueryItem {
+    fn from(item: QueryItem) -> Self {
+        match item {
+            QueryItem::Key { key } => Self::Key(key),
+            QueryItem::Range { start, end } => Self::Range(start..end),
+            QueryItem::RangeInclusive { start, end } => Self::RangeInclusive(start..=end),
+            QueryItem::RangeFull => Self::RangeFull(..),
+            QueryItem::RangeFrom { start } => Self::RangeFrom(start..),
+            QueryItem::RangeTo { end } => Self::RangeTo(..end),
+            QueryItem::RangeToInclusive { end } => Self::RangeToInclusive(..=end),
+            QueryItem::RangeAfter { start } => Self::RangeAfter(start..),
+            QueryItem::RangeAfterTo { start, end } => Self::RangeAfterTo(start..end),
+            QueryItem::RangeAfterToInclusive { start, end } => {
+                Self::RangeAfterToInclusive(start..=end)
+            }
+        }
+    }
+}
+
+impl From<&grovedb::QueryItem> for QueryItem {
+    fn from(item: &grovedb::QueryItem) -> Self {
+        use grovedb::QueryItem as Item;
+        match item.clone() {
+            Item::Key(key) => Self::Key { key },
+            Item::Range(range) => Self::Range {
+                start: range.start,
+                end: range.end,
+            },
+            Item::RangeInclusive(range) => {
+                let (start, end) = range.into_inner();
+                Self::RangeInclusive { start, end }
+            }
+            Item::RangeFull(_) => Self::RangeFull,
+            Item::RangeFrom(range) => Self::RangeFrom { start: range.start },
+            Item::RangeTo(range) => Self::RangeTo { end: range.end },
+            Item::RangeToInclusive(range) => Self::RangeToInclusive { end: range.end },
+            Item::RangeAfter(range) => Self::RangeAfter { start: range.start },
+            Item::RangeAfterTo(range) => Self::RangeAfterTo {
+                start: range.start,
+                end: range.end,
+            },
+            Item::RangeAfterToInclusive(range) => {
+                let (start, end) = range.into_inner();
+                Self::RangeAfterToInclusive { start, end }
+            }
+        }
+    }
+}
+
+pub struct PathQuery {
+    pub path: Vec<Vec<u8>>,
+    pub items: Vec<QueryItem>,
+    pub limit: Option<u16>,
+    pub offset: Option<u16>,
+    pub left_to_right: bool,
+}
+
+impl From<PathQuery> for grovedb::PathQuery {
+    fn from(path_query: PathQuery) -> Self {
+        let mut query = Query::new_with_direction(path_query.left_to_right);
+        for item in path_query.items {
+            query.insert_item(item.into());
+        }
+        grovedb::PathQuery::new(
+            path_query.path,
+            SizedQuery::new(query, path_query.limit, path_query.offset),
+        )
+    }
+}
+
+impl From<&grovedb::PathQuery> for PathQuery {
+    fn from(path_query: &grovedb::PathQuery) -> Self {
+        let sized_query = path_query.query();
+        PathQuery {
+            path: path_query.path().to_vec(),
+            items: sized_query.query().iter().map(QueryItem::from).collect(),
+            limit: sized_query.limit(),
+            offset: sized_query.offset(),
+            left_to_right: sized_query.query().left_to_right,
+        }
+    }
+}
+
+pub struct ProvedEntry {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+pub struct ProvedSubtree {
+    pub path: Vec<Vec<u8>>,
+    pub entries: Vec<ProvedEntry>,
+}
+
+pub struct VerifiedProof {
+    pub root_hash: Vec<u8>,
+    pub subtrees: Vec<ProvedSubtree>,
+}
+
+/// Parses a path query written in the textual query syntax, e.g.
+/// `path: a/b, range: [k1, k2), limit 10`
+pub fn parse_path_query(query: String) -> Result<PathQuery, GroveDbError> {
+    let path_query: grovedb::PathQuery = query
+        .parse()
+        .map_err(|e: grovedb::Error| GroveDbError::InvalidQuery(e.to_string()))?;
+    Ok(PathQuery::from(&path_query))
+}
+
+/// Encodes a path query the way full nodes expect it in requests for proofs
+pub fn encode_path_query(query: PathQuery) -> Vec<u8> {
+    bincode::serialize(&grovedb::PathQuery::from(query))
+        .expect("path query serialization doesn't fail")
+}
+
+/// Verifies a proof and returns the root hash it commits to with proved
+/// key-value pairs of every subtree ordered by subtree path
+pub fn verify_proof(proof: Vec<u8>) -> Result<VerifiedProof, GroveDbError> {
+    let (root_hash, results) =
+        grovedb::execute_proof(&proof).map_err(|e| GroveDbError::InvalidProof(e.to_string()))?;
+    let mut subtrees: Vec<ProvedSubtree> = results
+        .into_iter()
+        .map(|(path, map)| ProvedSubtree {
+            path,
+            entries: map
+                .all()
+                .map(|(key, (_, value))| ProvedEntry {
+                    key: key.clone(),
+                    value: value.clone(),
+                })
+                .collect(),
+        })
+        .collect();
+    subtrees.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(VerifiedProof {
+        root_hash: root_hash.to_vec(),
+        subtrees,
+    })
+}
+
+/// Keeps the latest trusted root hash, e.g. from a verified block header, and
+/// verifies proofs against it
+#[derive(Default)]
+pub struct RootHashTracker {
+    trusted: Mutex<Option<(u64, [u8; 32])>>,
+}
+
+impl RootHashTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the trusted root hash at the height, heights must not go back
+    pub fn set_trusted_root_hash(
+        &self,
+        height: u64,
+        root_hash: Vec<u8>,
+    ) -> Result<(), GroveDbError> {
+        let root_hash: [u8; 32] = root_hash.try_into().map_err(|v: Vec<u8>| {
+            GroveDbError::InvalidRootHash(format!("expected 32 bytes, got {}", v.len()))
+        })?;
+        let mut trusted = self.trusted.lock().expect("tracker lock is not poisoned");
+        if let Some((trusted_height, _)) = *trusted {
+            if height < trusted_height {
+                return Err(GroveDbError::InvalidRootHash(format!(
+                    "height {} is below the trusted height {}",
+                    height, trusted_height
+                )));
+            }
+        }
+        *trusted = Some((height, root_hash));
+        Ok(())
+    }
+
+    pub fn height(&self) -> Option<u64> {
+        self.trusted
+            .lock()
+            .expect("tracker lock is not poisoned")
+            .map(|(height, _)| height)
+    }
+
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.trusted
+            .lock()
+            .expect("tracker lock is not poisoned")
+            .map(|(_, root_hash)| root_hash.to_vec())
+    }
+
+    /// Verifies a proof like [`verify_proof`] and checks that it commits to
+    /// the trusted root hash
+    pub fn verify_proof(&self, proof: Vec<u8>) -> Result<VerifiedProof, GroveDbError> {
+        let verified = verify_proof(proof)?;
+        match self.root_hash() {
+            Some(root_hash) if root_hash == verified.root_hash => Ok(verified),
+            _ => Err(GroveDbError::RootHashMismatch),
+        }
+    }
+}
+
+uniffi_macros::include_scaffolding!("grovedb");
+
+#[cfg(test)]
+mod tests {
+    use grovedb::{Element, GroveDb};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_and_verify() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let db = GroveDb::open(tmp_dir.path()).unwrap();
+        db.insert([], b"tree", Element::empty_tree(), None)
+            .expect("successful subtree insert");
+        for key in [b"a", b"b", b"c"] {
+            db.insert([b"tree".as_ref()], key, Element::Item(key.to_vec()), None)
+                .expect("successful item insert");
+        }
+
+        let path_query =
+            parse_path_query("path: tree, range: [a, c), limit 5".to_owned()).expect("valid query");
+        assert_eq!(path_query.path, vec![b"tree".to_vec()]);
+        assert_eq!(path_query.limit, Some(5));
+        assert!(path_query.left_to_right);
+
+        let encoded = encode_path_query(path_query);
+        let path_query: grovedb::PathQuery =
+            bincode::deserialize(&encoded).expect("valid encoded query");
+        let proof = db.prove(&[path_query], None).expect("successful prove");
+
+        let verified = verify_proof(proof.clone()).expect("valid proof");
+        assert_eq!(
+            verified.root_hash,
+            db.root_hash(None).unwrap().unwrap().to_vec()
+        );
+        let subtree = verified
+            .subtrees
+            .iter()
+            .find(|subtree| subtree.path == vec![b"tree".to_vec()])
+            .expect("proved subtree");
+        let keys: Vec<&[u8]> = subtree.entries.iter().map(|e| e.key.as_slice()).collect();
+        assert_eq!(keys, vec![b"a".as_ref(), b"b".as_ref()]);
+
+        let tracker = RootHashTracker::new();
+        assert!(matches!(
+            tracker.verify_proof(proof.clone()),
+            Err(GroveDbError::RootHashMismatch)
+        ));
+        tracker
+            .set_trusted_root_hash(10, verified.root_hash.clone())
+            .expect("valid root hash");
+        assert_eq!(tracker.height(), Some(10));
+        tracker.verify_proof(proof).expect("valid proof");
+        assert!(matches!(
+            tracker.set_trusted_root_hash(9, vec![0; 32]),
+            Err(GroveDbError::InvalidRootHash(_))
+        ));
+        assert!(matches!(
+            tracker.set_trusted_root_hash(11, vec![0; 31]),
+            Err(GroveDbError::InvalidRootHash(_))
+        ));
+
+        assert!(matches!(
+            verify_proof(vec![1, 2, 3]),
+            Err(GroveDbError::InvalidProof(_))
+        ));
+        assert!(matches!(
+            parse_path_query("path: tree".to_owned()),
+            Err(GroveDbError::InvalidQuery(_))
+        ));
+    }
+}
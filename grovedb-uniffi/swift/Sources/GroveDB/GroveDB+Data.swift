@@ -0,0 +1,18 @@
+import Foundation
+
+// Conveniences taking `Data`, as returned by network APIs, over the generated
+// byte array functions
+
+public func verifyProof(_ proof: Data) throws -> VerifiedProof {
+    try verifyProof(proof: [UInt8](proof))
+}
+
+public extension RootHashTracker {
+    func setTrustedRootHash(height: UInt64, rootHash: Data) throws {
+        try setTrustedRootHash(height: height, rootHash: [UInt8](rootHash))
+    }
+
+    func verifyProof(_ proof: Data) throws -> VerifiedProof {
+        try verifyProof(proof: [UInt8](proof))
+    }
+}
@@ -0,0 +1,20 @@
+import XCTest
+@testable import GroveDB
+
+final class GroveDBTests: XCTestCase {
+    func testParsePathQuery() throws {
+        let query = try parsePathQuery(query: "path: tree, range: [a, c), limit 5")
+        XCTAssertEqual(query.path, [Array("tree".utf8)])
+        XCTAssertEqual(query.limit, 5)
+        XCTAssertFalse(encodePathQuery(query: query).isEmpty)
+    }
+
+    func testRootHashTracker() throws {
+        let tracker = RootHashTracker()
+        XCTAssertNil(tracker.height())
+        try tracker.setTrustedRootHash(height: 10, rootHash: Data(count: 32))
+        XCTAssertEqual(tracker.height(), 10)
+        XCTAssertThrowsError(try tracker.setTrustedRootHash(height: 9, rootHash: Data(count: 32)))
+        XCTAssertThrowsError(try tracker.verifyProof(Data([1, 2, 3])))
+    }
+}
@@ -0,0 +1,45 @@
+#!/bin/sh
+# Builds GroveDBFFI.xcframework for iOS devices, iOS simulators and macOS and
+# generates Swift bindings into the GroveDB target
+set -e
+
+cd "$(dirname "$0")/.."
+TARGET_DIR=../target
+OUT_DIR=swift/generated
+
+# Only proof verification goes into the library, RocksDB doesn't build for iOS
+if cargo tree -p grovedb-uniffi -e normal -i rocksdb >/dev/null 2>&1; then
+    echo "grovedb-uniffi must not depend on RocksDB" >&2
+    exit 1
+fi
+
+for target in aarch64-apple-ios aarch64-apple-ios-sim x86_64-apple-ios \
+    aarch64-apple-darwin x86_64-apple-darwin; do
+    cargo build --release -p grovedb-uniffi --target "$target"
+done
+
+rm -rf "$OUT_DIR" swift/GroveDBFFI.xcframework
+mkdir -p "$OUT_DIR/headers" "$OUT_DIR/ios-sim" "$OUT_DIR/macos"
+uniffi-bindgen generate src/grovedb.udl --language swift --config-path uniffi.toml \
+    --out-dir "$OUT_DIR"
+mv "$OUT_DIR/GroveDB.swift" swift/Sources/GroveDB/GroveDB.swift
+mv "$OUT_DIR/GroveDBFFI.h" "$OUT_DIR/headers/"
+mv "$OUT_DIR/GroveDBFFI.modulemap" "$OUT_DIR/headers/module.modulemap"
+
+lipo -create \
+    "$TARGET_DIR/aarch64-apple-ios-sim/release/libgrovedb_uniffi.a" \
+    "$TARGET_DIR/x86_64-apple-ios/release/libgrovedb_uniffi.a" \
+    -output "$OUT_DIR/ios-sim/libgrovedb_uniffi.a"
+lipo -create \
+    "$TARGET_DIR/aarch64-apple-darwin/release/libgrovedb_uniffi.a" \
+    "$TARGET_DIR/x86_64-apple-darwin/release/libgrovedb_uniffi.a" \
+    -output "$OUT_DIR/macos/libgrovedb_uniffi.a"
+
+xcodebuild -create-xcframework \
+    -library "$TARGET_DIR/aarch64-apple-ios/release/libgrovedb_uniffi.a" \
+    -headers "$OUT_DIR/headers" \
+    -library "$OUT_DIR/ios-sim/libgrovedb_uniffi.a" \
+    -headers "$OUT_DIR/headers" \
+    -library "$OUT_DIR/macos/libgrovedb_uniffi.a" \
+    -headers "$OUT_DIR/headers" \
+    -output swift/GroveDBFFI.xcframework
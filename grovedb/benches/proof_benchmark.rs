@@ -0,0 +1,50 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use grovedb::{Element, Feature, GroveDb, PathQuery, Query};
+use tempfile::TempDir;
+
+const N_ITEMS: u32 = 1_000;
+
+pub fn proof_compression_benchmark(c: &mut Criterion) {
+    let dir = TempDir::new().unwrap();
+    let db = GroveDb::open(dir.path()).unwrap();
+    db.enable_feature(Feature::ProofCompression, None).unwrap();
+    let test_leaf: &[u8] = b"leaf1";
+    db.insert([], test_leaf, Element::empty_tree(), None)
+        .unwrap();
+    // Serialized documents of the same schema differ in a few bytes only
+    for i in 0..N_ITEMS {
+        let mut document = br#"{"$type":"note","$ownerId":"owner","message":""#.to_vec();
+        document.extend(i.to_string().as_bytes());
+        document.extend(br#""}"#);
+        db.insert([test_leaf], &i.to_be_bytes(), Element::Item(document), None)
+            .unwrap();
+    }
+    let mut query = Query::new();
+    query.insert_all();
+    let path_queries = [PathQuery::new_unsized(vec![test_leaf.to_vec()], query)];
+
+    let plain = db.prove(&path_queries, None).unwrap();
+    let compressed = db.prove_compressed(&path_queries, None).unwrap();
+    println!(
+        "proof of {} similar items: {} bytes plain, {} bytes compressed",
+        N_ITEMS,
+        plain.len(),
+        compressed.len()
+    );
+
+    c.bench_function("plain proof generation", |b| {
+        b.iter(|| db.prove(&path_queries, None).unwrap())
+    });
+    c.bench_function("compressed proof generation", |b| {
+        b.iter(|| db.prove_compressed(&path_queries, None).unwrap())
+    });
+    c.bench_function("plain proof verification", |b| {
+        b.iter(|| GroveDb::execute_proof(&plain).unwrap())
+    });
+    c.bench_function("compressed proof verification", |b| {
+        b.iter(|| GroveDb::execute_proof(&compressed).unwrap())
+    });
+}
+
+criterion_group!(benches, proof_compression_benchmark);
+criterion_main!(benches);
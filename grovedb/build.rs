@@ -0,0 +1,11 @@
+fn main() {
+    #[cfg(feature = "proto")]
+    {
+        std::env::set_var(
+            "PROTOC",
+            protoc_bin_vendored::protoc_bin_path().expect("protoc is vendored for this platform"),
+        );
+        prost_build::compile_protos(&["proto/grovedb.proto"], &["proto"])
+            .expect("cannot compile protobuf messages");
+    }
+}
@@ -0,0 +1,115 @@
+//! Module for access control of mutations.
+//! Multi-tenant embedders can restrict which subtrees a caller may change with
+//! an [`AccessPolicy`] consulted by every mutating operation before anything
+//! is written. A caller is identified by opaque context bytes set with
+//! [`GroveDb::with_caller_context`] for operations run on the current thread.
+
+use std::cell::RefCell;
+
+use crate::{Error, GroveDb};
+
+/// A kind of mutation checked by an [`AccessPolicy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MutationKind {
+    /// Insertion or replacement of an element
+    Insert,
+    /// Deletion of an element, including subtrees
+    Delete,
+    /// Pruning of a subtree, the key is the last path segment of the subtree
+    Prune,
+    /// Freezing of a subtree, the key is the last path segment of the subtree
+    Freeze,
+    /// Eviction of a subtree to the archive, the key is the last path segment
+    /// of the subtree
+    Evict,
+    /// Mutation of auxiliary data, the path is empty and the key is the aux
+    /// key
+    Aux,
+    /// Loading of a dump into a subtree or importing values of its
+    /// deduplicated items, the key is the last path segment of the subtree
+    /// and is empty for the root tree
+    Load,
+    /// Garbage collection of orphaned storage, the path and the key are empty
+    CollectGarbage,
+    /// Enabling of a format-affecting feature, the path is empty and the key
+    /// is the feature flag in big-endian bytes
+    EnableFeature,
+}
+
+/// Decides whether a caller may mutate an element under the key of the
+/// subtree at the path. Denied mutations fail with [`Error::AccessDenied`].
+pub trait AccessPolicy: Send + Sync {
+    fn allows(&self, path: &[&[u8]], key: &[u8], kind: MutationKind, caller: &[u8]) -> bool;
+}
+
+thread_local! {
+    static CALLER_CONTEXT: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+}
+
+/// Restores the previous caller context, even if the scoped closure panics
+struct CallerContextGuard(Vec<u8>);
+
+impl Drop for CallerContextGuard {
+    fn drop(&mut self) {
+        let previous = std::mem::take(&mut self.0);
+        CALLER_CONTEXT.with(|context| *context.borrow_mut() = previous);
+    }
+}
+
+impl GroveDb {
+    /// Sets the policy consulted on every mutation
+    pub fn set_access_policy(&mut self, policy: Box<dyn AccessPolicy>) {
+        self.access_policy = Some(policy);
+    }
+
+    /// Runs the closure with the caller context passed to the access policy
+    /// for mutations made on the current thread. Outside of it the context
+    /// is empty.
+    pub fn with_caller_context<T>(caller: &[u8], f: impl FnOnce() -> T) -> T {
+        let previous = CALLER_CONTEXT.with(|context| context.replace(caller.to_vec()));
+        let _guard = CallerContextGuard(previous);
+        f()
+    }
+
+    /// Fails with [`Error::AccessDenied`] if the access policy doesn't allow
+    /// the current caller the mutation under the key of the subtree at the
+    /// path
+    pub(crate) fn check_access<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        kind: MutationKind,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let policy = match &self.access_policy {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+        let path: Vec<&[u8]> = path.into_iter().collect();
+        let allowed =
+            CALLER_CONTEXT.with(|context| policy.allows(&path, key, kind, &context.borrow()));
+        if allowed {
+            Ok(())
+        } else {
+            Err(Error::AccessDenied)
+        }
+    }
+
+    /// Checks access like [`GroveDb::check_access`] for a mutation of the
+    /// whole subtree at the path, the key is its last path segment
+    pub(crate) fn check_subtree_access<'p, P>(
+        &self,
+        path: P,
+        kind: MutationKind,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator,
+    {
+        let mut parent_path = path.into_iter();
+        let key = parent_path.next_back().unwrap_or_default();
+        self.check_access(parent_path, key, kind)
+    }
+}
@@ -0,0 +1,54 @@
+//! Module for commitments of GroveDB state to external consensus.
+//! Chains commit to the grove root hash together with the previous block's
+//! app hash, and often with quorum signatures or other external data too.
+//! Every integrator combining these their own way makes app hashes
+//! incompatible, so the combination is defined here with a version byte in
+//! every hashed preimage.
+
+use merk::tree::{value_hash, NULL_HASH};
+
+use crate::{Error, GroveDb, TransactionArg};
+
+/// Version of the app hash scheme, the first byte of every preimage after
+/// the domain tag
+pub const APP_HASH_VERSION: u8 = 1;
+/// Domain tag of app hashes combining the root hash and the previous app hash
+const APP_HASH_TAG: &[u8] = b"grovedb/app-hash";
+/// Domain tag of app hashes combined with an external commitment
+const APP_HASH_COMMITMENT_TAG: &[u8] = b"grovedb/app-hash-commitment";
+
+fn tagged_hash(tag: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut preimage = tag.to_vec();
+    preimage.push(APP_HASH_VERSION);
+    for part in parts {
+        // Parts are length prefixed, so their boundaries are unambiguous
+        preimage.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        preimage.extend_from_slice(part);
+    }
+    value_hash(&preimage)
+}
+
+impl GroveDb {
+    /// Returns the app hash chaining the root hash of the transaction's
+    /// state, or of committed data if there is none, to the previous app
+    /// hash, see [`GroveDb::combine_app_hash`]
+    pub fn root_hash_with_app_hash(
+        &self,
+        prev_app_hash: &[u8; 32],
+        transaction: TransactionArg,
+    ) -> Result<[u8; 32], Error> {
+        let root_hash = self.root_hash(transaction)?.unwrap_or(NULL_HASH);
+        Ok(Self::combine_app_hash(prev_app_hash, &root_hash))
+    }
+
+    /// Hashes the previous app hash and a root hash into an app hash. The
+    /// root hash of an empty GroveDB is all zeroes.
+    pub fn combine_app_hash(prev_app_hash: &[u8; 32], root_hash: &[u8; 32]) -> [u8; 32] {
+        tagged_hash(APP_HASH_TAG, &[prev_app_hash, root_hash])
+    }
+
+    /// Binds an external commitment, e.g. a quorum signature, to an app hash
+    pub fn combine_app_hash_with_commitment(app_hash: &[u8; 32], commitment: &[u8]) -> [u8; 32] {
+        tagged_hash(APP_HASH_COMMITMENT_TAG, &[app_hash, commitment])
+    }
+}
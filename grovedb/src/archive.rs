@@ -0,0 +1,305 @@
+//! Module for tiered storage.
+//! Rarely accessed subtrees may be marked cold and evicted to an archive, e.g.
+//! an object store, through an [`ArchiveUploader`]. Only the root hash of a
+//! cold subtree is kept locally in its parent tree element, the subtree is
+//! fetched back and verified against this hash on the next direct access, so
+//! archival-heavy deployments need less local disk space.
+
+use std::collections::{BTreeSet, HashMap};
+
+use merk::{
+    tree::{kv_hash, Tree},
+    Merk, ROOT_KEY_KEY,
+};
+use serde::{Deserialize, Serialize};
+use storage::{RawIterator, Storage, StorageContext};
+
+use crate::{
+    util::merk_optional_tx, BoxedStorage, Element, Error, GroveDb, MutationKind, RocksDbStorage,
+    TransactionArg,
+};
+
+/// A prefix of keys in meta storage to mark cold subtrees, followed by a
+/// subtree prefix
+const COLD_SUBTREE_KEY_PREFIX: &[u8] = b"cold_subtree";
+
+/// Object store to keep evicted subtrees. Subtree data is addressed by the
+/// subtree root hash, so uploading the same subtree again is harmless.
+pub trait ArchiveUploader: Send + Sync {
+    /// Stores serialized subtree data under the subtree root hash
+    fn upload(&self, root_hash: &[u8; 32], data: &[u8]) -> Result<(), Error>;
+
+    /// Returns subtree data stored under the root hash, `None` if missing
+    fn fetch(&self, root_hash: &[u8; 32]) -> Result<Option<Vec<u8>>, Error>;
+}
+
+/// Merk nodes of an evicted subtree as they are in storage
+#[derive(Serialize, Deserialize)]
+struct ArchivedSubtree {
+    root_key: Option<Vec<u8>>,
+    nodes: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl ArchivedSubtree {
+    /// Checks hashes of all nodes reachable from the root and returns the
+    /// root hash with keys of the reachable nodes, others are never written
+    /// back to storage
+    fn verify(&self) -> Result<([u8; 32], BTreeSet<&[u8]>), Error> {
+        let mut reachable = BTreeSet::new();
+        let root_key = match &self.root_key {
+            Some(root_key) => root_key,
+            None => return Ok((Default::default(), reachable)),
+        };
+        let mut nodes = HashMap::new();
+        for (key, bytes) in &self.nodes {
+            let node = Tree::decode_raw(bytes).map_err(|e| Error::CorruptedData(e.to_string()))?;
+            if kv_hash(key, node.value()) != *node.kv_hash() {
+                return Err(Error::CorruptedData(String::from(
+                    "archived subtree node hash mismatch",
+                )));
+            }
+            if nodes.insert(key.as_slice(), node).is_some() {
+                return Err(Error::CorruptedData(String::from(
+                    "archived subtree node is duplicated",
+                )));
+            }
+        }
+        let root = nodes.get(root_key.as_slice()).ok_or_else(|| {
+            Error::CorruptedData(String::from("archived subtree root node is missing"))
+        })?;
+        reachable.insert(root_key.as_slice());
+        let mut to_check = vec![root];
+        while let Some(node) = to_check.pop() {
+            for link in [node.link(true), node.link(false)].into_iter().flatten() {
+                match nodes.get_key_value(link.key()) {
+                    Some((key, child)) if child.hash() == *link.hash() => {
+                        // A node linked twice makes the nodes not a tree
+                        if !reachable.insert(*key) {
+                            return Err(Error::CorruptedData(String::from(
+                                "archived subtree node is linked twice",
+                            )));
+                        }
+                        to_check.push(child);
+                    }
+                    _ => {
+                        return Err(Error::CorruptedData(String::from(
+                            "archived subtree child hash mismatch",
+                        )))
+                    }
+                }
+            }
+        }
+        Ok((root.hash(), reachable))
+    }
+}
+
+pub(crate) fn cold_subtree_key<'p, P>(path: P) -> Vec<u8>
+where
+    P: IntoIterator<Item = &'p [u8]>,
+{
+    let mut key = COLD_SUBTREE_KEY_PREFIX.to_vec();
+    key.extend(RocksDbStorage::build_prefix(path));
+    key
+}
+
+impl GroveDb {
+    /// Sets the archive which cold subtrees are evicted to and fetched from
+    pub fn set_archive(&mut self, archive: Box<dyn ArchiveUploader>) {
+        self.archive = Some(archive);
+    }
+
+    /// Returns whether the subtree at the path is evicted to the archive
+    pub fn is_cold<'p, P>(&self, path: P) -> Result<bool, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        Ok(self.cold_subtree_hash(path)?.is_some())
+    }
+
+    /// Uploads the subtree at the path to the archive and deletes its data
+    /// locally. Only subtrees without nested subtrees can be evicted, frozen
+    /// subtrees can't be. Queries don't fetch cold subtrees reached by
+    /// subqueries but fail, so such subtrees should be accessed directly
+    /// first. Eviction is made outside of any transaction in its own one
+    /// reading from a snapshot, so it fails if the subtree is changed
+    /// concurrently.
+    pub fn evict_subtree<'p, P>(&self, path: P) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let archive = self
+            .archive
+            .as_ref()
+            .ok_or_else(|| Error::ArchiveError(String::from("archive is not configured")))?;
+        let path_iter = path.into_iter();
+        let mut parent_path = path_iter.clone();
+        let key = parent_path
+            .next_back()
+            .ok_or(Error::InvalidPath("root tree cannot be evicted"))?;
+        self.check_not_frozen(parent_path.clone(), key)?;
+        self.check_access(parent_path, key, MutationKind::Evict)?;
+        if self.is_cold(path_iter.clone())? {
+            return Ok(());
+        }
+
+        let tx = self.start_snapshot_transaction();
+        self.check_subtree_exists_invalid_path(path_iter.clone(), Some(&tx))?;
+        let (archived, root_hash) = {
+            let storage = self
+                .db
+                .get_transactional_storage_context(path_iter.clone(), &tx);
+            let mut elements = Element::iterator(storage.raw_iter());
+            while let Some((_, element)) = elements.next()? {
+                if let Element::Tree(_) = element {
+                    return Err(Error::InvalidPath(
+                        "subtree with nested subtrees cannot be evicted",
+                    ));
+                }
+            }
+            let mut nodes = Vec::new();
+            let mut raw_iter = storage.raw_iter();
+            raw_iter.seek_to_first();
+            while let (Some(key), Some(value)) = (raw_iter.key(), raw_iter.value()) {
+                nodes.push((key.to_vec(), value.to_vec()));
+                raw_iter.next();
+            }
+            raw_iter.status()?;
+            let archived = ArchivedSubtree {
+                root_key: storage.get_root(ROOT_KEY_KEY)?,
+                nodes,
+            };
+            let root_hash = Merk::open(storage)
+                .map_err(|_| Error::CorruptedData("cannot open a subtree".to_owned()))?
+                .root_hash();
+            (archived, root_hash)
+        };
+        let data = bincode::serialize(&archived)
+            .map_err(|_| Error::CorruptedData(String::from("unable to serialize subtree")))?;
+        archive.upload(&root_hash, &data)?;
+
+        let meta_storage = self
+            .db
+            .get_transactional_storage_context(std::iter::empty(), &tx);
+        meta_storage.put_meta(cold_subtree_key(path_iter.clone()), &root_hash)?;
+        let storage = self.db.get_transactional_storage_context(path_iter, &tx);
+        storage.clear()?;
+        storage.delete_root(ROOT_KEY_KEY)?;
+        self.commit_transaction(tx)
+    }
+
+    /// Fetches the subtree at the path from the archive if it is cold. The
+    /// fetched subtree must have the root hash stored in its parent element,
+    /// only its nodes reachable from the root are written.
+    /// It is written outside of any transaction, as its data is the same as
+    /// before eviction.
+    pub(crate) fn fetch_if_cold<'p, P>(&self, path: P) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        let root_hash = match self.cold_subtree_hash(path_iter.clone())? {
+            Some(root_hash) => root_hash,
+            None => return Ok(()),
+        };
+
+        // The mark is stale if the subtree was deleted or replaced since eviction
+        let mut parent_path = path_iter.clone();
+        let parent_key = parent_path
+            .next_back()
+            .ok_or(Error::CorruptedPath("root tree cannot be cold"))?;
+        let parent = Merk::open(self.db.get_storage_context(parent_path))
+            .map_err(|_| Error::CorruptedData("cannot open a subtree".to_owned()))?;
+        match Element::get(&parent, parent_key) {
+            Ok(Element::Tree(hash)) if hash == root_hash => {}
+            Ok(_) | Err(Error::PathKeyNotFound(_)) => {
+                let meta_storage = self.db.get_storage_context(std::iter::empty());
+                meta_storage.delete_meta(cold_subtree_key(path_iter))?;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        }
+
+        let archive = self.archive.as_ref().ok_or_else(|| {
+            Error::ArchiveError(String::from(
+                "subtree is cold, but archive is not configured",
+            ))
+        })?;
+        let data = archive.fetch(&root_hash)?.ok_or_else(|| {
+            Error::ArchiveError(String::from("cold subtree is missing in archive"))
+        })?;
+        let archived: ArchivedSubtree = bincode::deserialize(&data).map_err(|_| {
+            Error::CorruptedData(String::from("unable to deserialize archived subtree"))
+        })?;
+        let (archived_root_hash, reachable) = archived.verify()?;
+        if archived_root_hash != root_hash {
+            return Err(Error::CorruptedData(String::from(
+                "archived subtree root hash mismatch",
+            )));
+        }
+
+        let tx = self.db.start_transaction();
+        let storage = self
+            .db
+            .get_transactional_storage_context(path_iter.clone(), &tx);
+        for (key, value) in &archived.nodes {
+            if reachable.contains(key.as_slice()) {
+                storage.put(key, value)?;
+            }
+        }
+        if let Some(root_key) = &archived.root_key {
+            storage.put_root(ROOT_KEY_KEY, root_key)?;
+        }
+        let meta_storage = self
+            .db
+            .get_transactional_storage_context(std::iter::empty(), &tx);
+        meta_storage.delete_meta(cold_subtree_key(path_iter))?;
+        self.db.commit_transaction(tx)?;
+        Ok(())
+    }
+
+    pub(crate) fn cold_subtree_hash<'p, P>(&self, path: P) -> Result<Option<[u8; 32]>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let meta_storage = self.db.get_storage_context(std::iter::empty());
+        meta_storage
+            .get_meta(cold_subtree_key(path))?
+            .map(|hash| {
+                hash.try_into().map_err(|_| {
+                    Error::CorruptedData(String::from("invalid cold subtree root hash"))
+                })
+            })
+            .transpose()
+    }
+}
+
+/// Fails if a query reaches the subtree at the path through a subquery while
+/// it is cold, as queries have no access to the archive. A cold mark is stale
+/// if the parent element has another root hash.
+pub(crate) fn check_not_cold_for_query(
+    storage: &BoxedStorage,
+    path: &[&[u8]],
+    transaction: TransactionArg,
+) -> Result<(), Error> {
+    let meta_storage = storage.get_storage_context(std::iter::empty());
+    let cold_hash = match meta_storage.get_meta(cold_subtree_key(path.iter().copied()))? {
+        Some(cold_hash) => cold_hash,
+        None => return Ok(()),
+    };
+    let (key, parent_path) = match path.split_last() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    let element = merk_optional_tx!(storage, parent_path.iter().copied(), transaction, parent, {
+        Element::get(&parent, key)
+    });
+    match element {
+        Ok(Element::Tree(hash)) if hash[..] == cold_hash[..] => Err(Error::ArchiveError(
+            String::from("subquery reaches a cold subtree, it must be accessed directly first"),
+        )),
+        Ok(_) | Err(Error::PathKeyNotFound(_)) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
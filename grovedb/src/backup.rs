@@ -0,0 +1,76 @@
+//! Module for scheduled node backups.
+//! Backups are made with RocksDB's backup engine into a directory which may
+//! be synced to an object storage: each new backup copies only files which
+//! are not shared with the previous ones.
+
+use std::path::Path;
+
+use crate::{Error, GroveDb, RocksDbStorage};
+
+/// Progress of a backup or restore operation reported to a callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupProgress {
+    /// Memtables are flushed and a new backup is being written
+    CreatingBackup,
+    /// A new backup with the id is written
+    BackupCreated(u32),
+    /// Backups older than the number of most recent ones to keep are deleted
+    OldBackupsPurged { kept: usize },
+    /// Database files are being restored from a backup, the latest one if id
+    /// is `None`
+    Restoring(Option<u32>),
+    /// Database files are restored
+    Restored,
+}
+
+impl GroveDb {
+    /// Creates a new incremental backup in `backup_dir` and deletes all
+    /// backups there except `num_backups_to_keep` most recent ones, `0` keeps
+    /// every backup. Returns the id of the new backup.
+    pub fn backup_to<P, F>(
+        &self,
+        backup_dir: P,
+        num_backups_to_keep: usize,
+        mut progress: F,
+    ) -> Result<u32, Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(BackupProgress),
+    {
+        progress(BackupProgress::CreatingBackup);
+        let backup_id = self.rocksdb()?.create_backup(&backup_dir)?;
+        progress(BackupProgress::BackupCreated(backup_id));
+        if num_backups_to_keep > 0 {
+            RocksDbStorage::purge_old_backups(&backup_dir, num_backups_to_keep)?;
+            progress(BackupProgress::OldBackupsPurged {
+                kept: num_backups_to_keep,
+            });
+        }
+        Ok(backup_id)
+    }
+
+    /// Returns ids of backups available in `backup_dir`, oldest first
+    pub fn backup_ids<P: AsRef<Path>>(backup_dir: P) -> Result<Vec<u32>, Error> {
+        Ok(RocksDbStorage::backup_ids(backup_dir)?)
+    }
+
+    /// Restores a backup from `backup_dir` into `dest` and opens it, the
+    /// latest backup is used if `backup_id` is `None`. No GroveDB must be
+    /// open at `dest`.
+    pub fn restore_from<P, Q, F>(
+        backup_dir: P,
+        dest: Q,
+        backup_id: Option<u32>,
+        mut progress: F,
+    ) -> Result<GroveDb, Error>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(BackupProgress),
+    {
+        progress(BackupProgress::Restoring(backup_id));
+        RocksDbStorage::restore_backup(backup_dir, &dest, backup_id)?;
+        progress(BackupProgress::Restored);
+        GroveDb::open(dest)
+    }
+}
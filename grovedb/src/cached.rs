@@ -0,0 +1,156 @@
+//! Module for a read-through cache over GroveDB.
+//! RPC services are asked identical queries many times between blocks.
+//! [`CachedGroveDb`] answers repeated point reads and small query results from
+//! memory. Cached results are valid for the root hash they were read at, it
+//! is checked on every read, so any commit invalidates them whichever way it
+//! is made. Results are read from a snapshot with its root hash and cached
+//! only if the cache is still for that root hash.
+
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{Element, Error, GroveDb, PathQuery, Transaction};
+
+/// Query results with more items are not cached
+const MAX_CACHED_QUERY_RESULT_ITEMS: usize = 128;
+
+#[derive(Default)]
+struct ReadCache {
+    root_hash: Option<[u8; 32]>,
+    elements: HashMap<(Vec<Vec<u8>>, Vec<u8>), Element>,
+    queries: HashMap<Vec<u8>, (Vec<Vec<u8>>, u16)>,
+}
+
+impl ReadCache {
+    fn len(&self) -> usize {
+        self.elements.len() + self.queries.len()
+    }
+
+    fn clear(&mut self) {
+        self.elements.clear();
+        self.queries.clear();
+    }
+}
+
+/// GroveDB wrapper caching non-transactional point reads and query results.
+/// Other operations, including writes, are available through `Deref`. The
+/// cache is cleared once it holds `capacity` entries.
+pub struct CachedGroveDb {
+    db: GroveDb,
+    cache: Mutex<ReadCache>,
+    capacity: usize,
+}
+
+impl CachedGroveDb {
+    pub fn new(db: GroveDb, capacity: usize) -> Result<Self, Error> {
+        let root_hash = db.root_hash(None)?;
+        Ok(CachedGroveDb {
+            db,
+            cache: Mutex::new(ReadCache {
+                root_hash,
+                ..Default::default()
+            }),
+            capacity,
+        })
+    }
+
+    /// Returns the wrapped GroveDB
+    pub fn into_inner(self) -> GroveDb {
+        self.db
+    }
+
+    /// Gets an element like [`GroveDb::get`] outside of any transaction
+    pub fn get<'p, P>(&self, path: P, key: &'p [u8]) -> Result<Element, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        let cache_key = (
+            path_iter.clone().map(|x| x.to_vec()).collect(),
+            key.to_vec(),
+        );
+        if let Some(element) = self.valid_cache()?.elements.get(&cache_key) {
+            return Ok(element.clone());
+        }
+        // Cold subtrees are fetched outside of the snapshot read from
+        self.db.fetch_if_cold(path_iter.clone())?;
+        let (element, root_hash) =
+            self.read_at_snapshot(|tx| self.db.get(path_iter, key, Some(tx)))?;
+        if let Some(mut cache) = self.cache_at(root_hash) {
+            cache.elements.insert(cache_key, element.clone());
+        }
+        Ok(element)
+    }
+
+    /// Runs a path query like [`GroveDb::get_path_query`] outside of any
+    /// transaction
+    pub fn get_path_query(&self, path_query: &PathQuery) -> Result<(Vec<Vec<u8>>, u16), Error> {
+        let cache_key = path_query.cache_key();
+        if let Some(result) = self.valid_cache()?.queries.get(&cache_key) {
+            return Ok(result.clone());
+        }
+        self.db
+            .fetch_if_cold(path_query.path.iter().map(|x| x.as_slice()))?;
+        let (result, root_hash) =
+            self.read_at_snapshot(|tx| self.db.get_path_query(path_query, Some(tx)))?;
+        if result.0.len() <= MAX_CACHED_QUERY_RESULT_ITEMS {
+            if let Some(mut cache) = self.cache_at(root_hash) {
+                cache.queries.insert(cache_key, result.clone());
+            }
+        }
+        Ok(result)
+    }
+
+    /// Runs a read on a snapshot and returns its result with the root hash of
+    /// the same snapshot
+    fn read_at_snapshot<T, F>(&self, read: F) -> Result<(T, Option<[u8; 32]>), Error>
+    where
+        F: FnOnce(&Transaction) -> Result<T, Error>,
+    {
+        let snapshot = self.db.start_snapshot_transaction();
+        let root_hash = self.db.root_hash(Some(&snapshot))?;
+        Ok((read(&snapshot)?, root_hash))
+    }
+
+    /// Returns the cache to insert a result read at the root hash into, or
+    /// `None` if the cache is for another root hash, as something was
+    /// committed in between
+    fn cache_at(&self, root_hash: Option<[u8; 32]>) -> Option<MutexGuard<ReadCache>> {
+        let mut cache = self.cache();
+        if cache.root_hash != root_hash {
+            return None;
+        }
+        if cache.len() >= self.capacity {
+            cache.clear();
+        }
+        Some(cache)
+    }
+
+    fn cache(&self) -> MutexGuard<ReadCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the cache, cleared if the root hash has changed since it was
+    /// filled
+    fn valid_cache(&self) -> Result<MutexGuard<ReadCache>, Error> {
+        let root_hash = self.db.root_hash(None)?;
+        let mut cache = self.cache();
+        if root_hash != cache.root_hash {
+            cache.clear();
+            cache.root_hash = root_hash;
+        }
+        Ok(cache)
+    }
+}
+
+impl Deref for CachedGroveDb {
+    type Target = GroveDb;
+
+    fn deref(&self) -> &GroveDb {
+        &self.db
+    }
+}
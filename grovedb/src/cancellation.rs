@@ -0,0 +1,63 @@
+//! Module for cooperative cancellation of queries and proof generation.
+//! An RPC server gives up on a request once its deadline passes, but the
+//! query keeps running and consuming IO unless it is told to stop. Queries
+//! check a [`CancellationToken`] every few iterated keys and fail with
+//! [`Error::Cancelled`] once it's cancelled.
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+
+use crate::Error;
+
+/// Number of iterated keys between checks of the cancellation flag
+const CANCELLATION_CHECK_INTERVAL: usize = 64;
+
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    checks: AtomicUsize,
+}
+
+/// Token cancelling the queries it's passed to, clones share the state so it
+/// may be cancelled from another thread, see
+/// [`crate::QueryOptions::cancellation`]
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<CancellationState>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fails with [`Error::Cancelled`] if the token is cancelled, the flag is
+    /// read on every [`CANCELLATION_CHECK_INTERVAL`]th call only
+    pub(crate) fn check_periodically(&self) -> Result<(), Error> {
+        let checks = self.state.checks.fetch_add(1, Ordering::Relaxed);
+        if checks % CANCELLATION_CHECK_INTERVAL == 0 {
+            self.check()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fails with [`Error::Cancelled`] if the token is cancelled
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
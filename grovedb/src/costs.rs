@@ -0,0 +1,130 @@
+//! Module for cost determinism checks.
+//! Fee logic of downstream projects charges operations by estimated costs,
+//! which are only fair as long as they agree with the work the implementation
+//! actually does. [`verify_determinism`] applies operations to temporary
+//! GroveDBs measuring storage work of each, so these assumptions can be
+//! tested in CI against every GroveDB version.
+
+use storage::rocksdb_storage::DataAccessCounters;
+use tempfile::TempDir;
+
+use crate::{
+    Element, Error, EstimatedLayerInfo, GroveDb, GroveDbOp, QueryCost, WorstCaseLayerInfo,
+};
+
+/// Measured and estimated costs of an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpCostReport {
+    /// Subtree data reads and writes made by the operation
+    pub actual: QueryCost,
+    /// Cost estimated with layer info of the state the operation is applied
+    /// to, see [`GroveDb::estimated_case_cost_for_op`]
+    pub estimated: QueryCost,
+    /// Cost bound with layer limits of the state the operation is applied to,
+    /// see [`GroveDb::worst_case_cost_for_op`]
+    pub worst_case: QueryCost,
+    /// Whether written bytes are bounded by the worst case, which is not the
+    /// case for deletions of subtrees
+    pub bounded: bool,
+}
+
+/// Applies operations in order to a new temporary GroveDB twice and returns
+/// their costs. Fails with [`Error::CostCheckFailed`] if costs of an
+/// operation differ between runs or bytes it has written exceed the worst
+/// case bound. Reads are reported but not checked, since GroveDB checks read
+/// more than the estimators account for.
+pub fn verify_determinism(ops: &[GroveDbOp]) -> Result<Vec<OpCostReport>, Error> {
+    let reports = apply_measured(ops)?;
+    let repeated = apply_measured(ops)?;
+    for (idx, (report, repeated)) in reports.iter().zip(&repeated).enumerate() {
+        if report.actual != repeated.actual {
+            return Err(Error::CostCheckFailed(format!(
+                "operation {} cost {:?} and then {:?}",
+                idx, report.actual, repeated.actual
+            )));
+        }
+        if report.bounded && report.actual.written_bytes > report.worst_case.written_bytes {
+            return Err(Error::CostCheckFailed(format!(
+                "operation {} has written {} bytes over the worst case bound of {}",
+                idx, report.actual.written_bytes, report.worst_case.written_bytes
+            )));
+        }
+    }
+    Ok(reports)
+}
+
+fn apply_measured(ops: &[GroveDbOp]) -> Result<Vec<OpCostReport>, Error> {
+    let tmp_dir = TempDir::new()?;
+    let db = GroveDb::open(tmp_dir.path())?;
+    let mut reports = Vec::with_capacity(ops.len());
+    for op in ops {
+        let (path, key) = match op {
+            GroveDbOp::Insert { path, key, .. } | GroveDbOp::Delete { path, key } => (path, key),
+        };
+        let mut estimated_layers = Vec::with_capacity(path.len() + 1);
+        let mut worst_case_layers = Vec::with_capacity(path.len() + 1);
+        for depth in 0..=path.len() {
+            let (estimated, worst_case) = layer_info(&db, &path[..depth])?;
+            estimated_layers.push(estimated);
+            worst_case_layers.push(worst_case);
+        }
+        if let Some(layer) = worst_case_layers.last_mut() {
+            layer.max_key_size = layer.max_key_size.max(key.len() as u32);
+        }
+        let path_slices = path.iter().map(|x| x.as_slice());
+        let bounded = !matches!(
+            (op, db.get_raw_optional(path_slices.clone(), key, None)?),
+            (GroveDbOp::Delete { .. }, Some(Element::Tree(_)))
+        );
+
+        let start = DataAccessCounters::current();
+        match op {
+            GroveDbOp::Insert { element, .. } => {
+                db.insert(path_slices, key, element.clone(), None)?
+            }
+            GroveDbOp::Delete { .. } => db.delete(path_slices, key, None)?,
+        }
+        let counters = DataAccessCounters::current().since(&start);
+
+        reports.push(OpCostReport {
+            actual: QueryCost {
+                seek_count: counters.get_count,
+                loaded_bytes: counters.loaded_bytes,
+                written_bytes: counters.written_bytes,
+            },
+            estimated: GroveDb::estimated_case_cost_for_op(op, &estimated_layers)?,
+            worst_case: GroveDb::worst_case_cost_for_op(op, &worst_case_layers)?,
+            bounded,
+        });
+    }
+    Ok(reports)
+}
+
+/// Returns the average and the worst case layer info of the subtree
+fn layer_info(
+    db: &GroveDb,
+    path: &[Vec<u8>],
+) -> Result<(EstimatedLayerInfo, WorstCaseLayerInfo), Error> {
+    let elements = db.iter(path.iter().map(|x| x.as_slice()), false, None)?;
+    let count = elements.len() as u64;
+    let mut key_sizes = 0;
+    let mut value_sizes = 0;
+    let mut worst_case = WorstCaseLayerInfo {
+        max_element_count: count,
+        max_key_size: 0,
+        max_value_size: 0,
+    };
+    for (key, element) in &elements {
+        let value_size = element.as_ref().map_or(0, Element::byte_size) as u32;
+        key_sizes += key.len() as u64;
+        value_sizes += u64::from(value_size);
+        worst_case.max_key_size = worst_case.max_key_size.max(key.len() as u32);
+        worst_case.max_value_size = worst_case.max_value_size.max(value_size);
+    }
+    let estimated = EstimatedLayerInfo {
+        element_count: count,
+        average_key_size: key_sizes.checked_div(count).unwrap_or(0) as u32,
+        average_value_size: value_sizes.checked_div(count).unwrap_or(0) as u32,
+    };
+    Ok((estimated, worst_case))
+}
@@ -0,0 +1,237 @@
+//! Module for deduplicated item storage.
+//! Subtrees indexing the same document under many keys would keep a copy of
+//! it under each key. Items inserted with [`GroveDb::insert_deduplicated`] are
+//! stored once in a separate column family under the hash of the value with a
+//! count of elements referring to it, and [`Element::DedupItem`] elements keep
+//! the hash only. Reads resolve such elements into ordinary items, proofs
+//! carry the values of deduplicated items they prove, and Merk chunks of a
+//! subtree need the values transferred alongside them for state sync.
+
+use std::collections::{BTreeSet, HashMap};
+
+use storage::StorageContext;
+
+use crate::{
+    util::storage_context_optional_tx, Element, Error, Feature, GroveDb, MutationKind, Transaction,
+    TransactionArg,
+};
+
+/// A key in roots storage of a subtree to flag references of its deduplicated
+/// items as imported
+const DEDUP_VALUES_IMPORTED_KEY: &[u8] = b"dedup_values_imported";
+
+impl GroveDb {
+    /// Inserts an item which value is stored once for all items inserted with
+    /// the same value, see [`Element::DedupItem`]. Requires
+    /// [`Feature::ItemDeduplication`] to be enabled.
+    pub fn insert_deduplicated<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        value: &[u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
+    {
+        self.require_feature(Feature::ItemDeduplication)?;
+        let path_iter = path.into_iter();
+        if transaction.is_none() {
+            // The element and the reference to its value are written atomically
+            let tx = self.start_transaction();
+            self.insert_deduplicated(path_iter, key, value, Some(&tx))?;
+            return self.commit_transaction(tx);
+        }
+
+        // The reference is added once the element is in place, so a failed
+        // insert doesn't leave it behind
+        let hash = merk::tree::value_hash(value);
+        self.insert_element(path_iter, key, Element::DedupItem(hash), false, transaction)?;
+        self.rocksdb()?.add_dedup_reference(
+            &hash,
+            value,
+            transaction.map(Transaction::rocksdb).transpose()?,
+        )?;
+        Ok(())
+    }
+
+    /// Returns values of deduplicated items of the subtree at the path, not
+    /// including nested subtrees. Merk chunks of the subtree carry the hashes
+    /// of the values only, so state sync has to transfer the values alongside
+    /// to be imported by [`GroveDb::import_dedup_values`].
+    pub fn export_dedup_values<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<Vec<Vec<u8>>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let hashes: BTreeSet<[u8; 32]> = self
+            .subtree_dedup_hashes(path, transaction)?
+            .into_iter()
+            .collect();
+        hashes
+            .iter()
+            .map(|hash| self.dedup_value(hash, transaction))
+            .collect()
+    }
+
+    /// Adds references of deduplicated items of the subtree at the path,
+    /// restored from Merk chunks, to their values. Values not stored yet are
+    /// taken from `values` exported by [`GroveDb::export_dedup_values`], which
+    /// are checked against the hashes of the items. References are added once,
+    /// importing values of the same subtree again does nothing.
+    pub fn import_dedup_values<'p, P>(
+        &self,
+        path: P,
+        values: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + Clone,
+    {
+        self.require_feature(Feature::ItemDeduplication)?;
+        let path = path.into_iter();
+        self.check_subtree_access(path.clone(), MutationKind::Load)?;
+        if transaction.is_none() {
+            // References of all items are added atomically
+            let tx = self.start_transaction();
+            self.import_dedup_values(path, values, Some(&tx))?;
+            return self.commit_transaction(tx);
+        }
+
+        let imported = storage_context_optional_tx!(self.db, path.clone(), transaction, storage, {
+            storage.get_root(DEDUP_VALUES_IMPORTED_KEY)?
+        });
+        if imported.is_some() {
+            return Ok(());
+        }
+        let values: HashMap<[u8; 32], &[u8]> = values
+            .iter()
+            .map(|value| (merk::tree::value_hash(value), value.as_slice()))
+            .collect();
+        for hash in self.subtree_dedup_hashes(path.clone(), transaction)? {
+            let value = match values.get(&hash) {
+                Some(value) => value.to_vec(),
+                None => self.dedup_value(&hash, transaction)?,
+            };
+            self.rocksdb()?.add_dedup_reference(
+                &hash,
+                &value,
+                transaction.map(Transaction::rocksdb).transpose()?,
+            )?;
+        }
+        storage_context_optional_tx!(self.db, path, transaction, storage, {
+            storage.put_root(DEDUP_VALUES_IMPORTED_KEY, &[1])?;
+        });
+        Ok(())
+    }
+
+    /// Returns the number of elements referring to a deduplicated value by its
+    /// hash
+    pub fn dedup_reference_count(
+        &self,
+        hash: &[u8; 32],
+        transaction: TransactionArg,
+    ) -> Result<u64, Error> {
+        Ok(self
+            .rocksdb()?
+            .dedup_reference_count(hash, transaction.map(Transaction::rocksdb).transpose()?)?)
+    }
+
+    /// Returns the value of a deduplicated item by its hash
+    pub(crate) fn dedup_value(
+        &self,
+        hash: &[u8; 32],
+        transaction: TransactionArg,
+    ) -> Result<Vec<u8>, Error> {
+        let value = match self.db.read_only_rocksdb() {
+            Some(db) => db.get_dedup_value(hash)?,
+            None => self
+                .rocksdb()?
+                .get_dedup_value(hash, transaction.map(Transaction::rocksdb).transpose()?)?,
+        };
+        value.ok_or_else(|| Error::CorruptedData(String::from("deduplicated value not found")))
+    }
+
+    /// Resolves a deduplicated item into an ordinary item, other elements are
+    /// returned as is
+    pub(crate) fn resolve_dedup_item(
+        &self,
+        element: Element,
+        transaction: TransactionArg,
+    ) -> Result<Element, Error> {
+        match element {
+            Element::DedupItem(hash) => Ok(Element::Item(self.dedup_value(&hash, transaction)?)),
+            other => Ok(other),
+        }
+    }
+
+    /// Removes the reference of a deduplicated item which is deleted or
+    /// replaced to its value
+    pub(crate) fn release_dedup_item(
+        &self,
+        element: Option<&Element>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        if let Some(Element::DedupItem(hash)) = element {
+            if !self.is_feature_enabled(Feature::ItemDeduplication)? {
+                return Ok(());
+            }
+            self.rocksdb()?
+                .remove_dedup_reference(hash, transaction.map(Transaction::rocksdb).transpose()?)?;
+        }
+        Ok(())
+    }
+
+    /// Releases values of deduplicated items of a subtree which is cleared
+    pub(crate) fn release_subtree_dedup_items<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone,
+    {
+        if !self.is_feature_enabled(Feature::ItemDeduplication)? {
+            return Ok(());
+        }
+        let path = path.into_iter();
+        for hash in self.subtree_dedup_hashes(path.clone(), transaction)? {
+            self.rocksdb()?.remove_dedup_reference(
+                &hash,
+                transaction.map(Transaction::rocksdb).transpose()?,
+            )?;
+        }
+        storage_context_optional_tx!(self.db, path, transaction, storage, {
+            storage.delete_root(DEDUP_VALUES_IMPORTED_KEY)?;
+        });
+        Ok(())
+    }
+
+    /// Returns value hashes of deduplicated items of the subtree at the path,
+    /// one per item
+    fn subtree_dedup_hashes<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<Vec<[u8; 32]>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut hashes = Vec::new();
+        storage_context_optional_tx!(self.db, path, transaction, storage, {
+            let mut elements = Element::iterator(storage.raw_iter());
+            while let Some((_, element)) = elements.next()? {
+                if let Element::DedupItem(hash) = element {
+                    hashes.push(hash);
+                }
+            }
+        });
+        Ok(hashes)
+    }
+}
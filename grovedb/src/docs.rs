@@ -0,0 +1,553 @@
+//! Module for storing schema-described documents in GroveDB.
+//! Documents are JSON values encoded as CBOR and stored under
+//! `<path>/<document type>/documents/<id>`. Every indexed field of a document
+//! type gets an index subtree `<path>/<document type>/indices/<field>` in which
+//! each encountered field value is a subtree of references to the documents
+//! holding that value. A document and its index entries are written in one
+//! transaction, an internal one if none is passed.
+
+use std::collections::BTreeSet;
+
+use serde_json::Value;
+
+use crate::{Element, Error, GroveDb, PathQuery, Query, TransactionArg};
+
+/// Key of a subtree with documents themselves
+const DOCUMENTS_KEY: &[u8] = b"documents";
+/// Key of a subtree with index subtrees, one per indexed field
+const INDICES_KEY: &[u8] = b"indices";
+
+/// Description of a document type: its name, which is also the key of its
+/// subtree, and the fields to maintain indices for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentType {
+    name: String,
+    indexed_fields: Vec<String>,
+}
+
+/// Condition on a document field used by [`GroveDb::query_documents`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum WhereClause {
+    /// Field value is equal to the given one
+    Equal(String, Value),
+    /// Field value is one of the given ones
+    In(String, Vec<Value>),
+}
+
+impl WhereClause {
+    fn field(&self) -> &str {
+        match self {
+            WhereClause::Equal(field, _) | WhereClause::In(field, _) => field,
+        }
+    }
+
+    fn values(&self) -> Vec<&Value> {
+        match self {
+            WhereClause::Equal(_, value) => vec![value],
+            WhereClause::In(_, values) => values.iter().collect(),
+        }
+    }
+
+    fn matches(&self, document: &Value) -> bool {
+        document
+            .get(self.field())
+            .map(|value| self.values().contains(&value))
+            .unwrap_or(false)
+    }
+}
+
+impl DocumentType {
+    pub fn new(name: String, indexed_fields: Vec<String>) -> Self {
+        DocumentType {
+            name,
+            indexed_fields,
+        }
+    }
+
+    /// Build a document type from its JSON Schema. Indexed fields are taken
+    /// from the `indices` schema property, where each index is an object with
+    /// `properties` array of single-entry objects (`{"field": "asc"}`); only
+    /// the first property of an index is used as GroveDB indices are
+    /// single-field.
+    pub fn from_json_schema(name: String, schema: &Value) -> Result<Self, Error> {
+        let mut indexed_fields = Vec::new();
+        if let Some(indices) = schema.get("indices") {
+            let indices = indices
+                .as_array()
+                .ok_or(Error::InvalidQuery("schema indices must be an array"))?;
+            for index in indices {
+                let field = index
+                    .get("properties")
+                    .and_then(Value::as_array)
+                    .and_then(|properties| properties.first())
+                    .and_then(Value::as_object)
+                    .and_then(|property| property.keys().next())
+                    .ok_or(Error::InvalidQuery(
+                        "schema index must have at least one property",
+                    ))?;
+                if !indexed_fields.contains(field) {
+                    indexed_fields.push(field.clone());
+                }
+            }
+        }
+        Ok(DocumentType::new(name, indexed_fields))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn indexed_fields(&self) -> &[String] {
+        &self.indexed_fields
+    }
+
+    fn is_indexed(&self, field: &str) -> bool {
+        self.indexed_fields.iter().any(|f| f == field)
+    }
+}
+
+fn encode_document(document: &Value) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    ciborium::ser::into_writer(document, &mut bytes)
+        .map_err(|_| Error::CorruptedData(String::from("unable to serialize document")))?;
+    Ok(bytes)
+}
+
+fn decode_document(bytes: &[u8]) -> Result<Value, Error> {
+    ciborium::de::from_reader(bytes)
+        .map_err(|_| Error::CorruptedData(String::from("unable to deserialize document")))
+}
+
+/// Helper to pass owned path segments to GroveDB methods
+fn path_slices(path: &[Vec<u8>]) -> Vec<&[u8]> {
+    path.iter().map(|x| x.as_slice()).collect()
+}
+
+impl GroveDb {
+    /// Inserts or replaces a document of `document_type` under `path`,
+    /// updating index subtrees of all indexed fields.
+    pub fn insert_document(
+        &self,
+        path: &[&[u8]],
+        document_type: &DocumentType,
+        id: &[u8],
+        document: &Value,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        if transaction.is_none() {
+            let tx = self.start_transaction();
+            self.insert_document(path, document_type, id, document, Some(&tx))?;
+            return self.commit_transaction(tx);
+        }
+
+        self.create_document_type_trees(path, document_type, transaction)?;
+        let type_path = Self::document_type_path(path, document_type);
+        let documents_path = Self::documents_path(path, document_type);
+
+        if let Some(old_document) = self.get_document(path, document_type, id, transaction)? {
+            self.remove_document_from_indices(
+                &type_path,
+                document_type,
+                id,
+                &old_document,
+                transaction,
+            )?;
+        }
+
+        self.insert(
+            path_slices(&documents_path),
+            id,
+            Element::Item(encode_document(document)?),
+            transaction,
+        )?;
+
+        let mut document_path = documents_path;
+        document_path.push(id.to_vec());
+        for field in document_type.indexed_fields() {
+            if let Some(value) = document.get(field) {
+                let mut field_path = type_path.clone();
+                field_path.extend([INDICES_KEY.to_vec(), field.as_bytes().to_vec()]);
+                let value_key = encode_document(value)?;
+                self.insert_if_not_exists(
+                    path_slices(&field_path),
+                    &value_key,
+                    Element::empty_tree(),
+                    transaction,
+                )?;
+                field_path.push(value_key);
+                self.insert(
+                    path_slices(&field_path),
+                    id,
+                    Element::Reference(document_path.clone()),
+                    transaction,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes a document and its index entries. Returns `false` if there was
+    /// no document with such `id`.
+    pub fn delete_document(
+        &self,
+        path: &[&[u8]],
+        document_type: &DocumentType,
+        id: &[u8],
+        transaction: TransactionArg,
+    ) -> Result<bool, Error> {
+        if transaction.is_none() {
+            let tx = self.start_transaction();
+            let deleted = self.delete_document(path, document_type, id, Some(&tx))?;
+            self.commit_transaction(tx)?;
+            return Ok(deleted);
+        }
+
+        if let Some(document) = self.get_document(path, document_type, id, transaction)? {
+            let type_path = Self::document_type_path(path, document_type);
+            self.remove_document_from_indices(
+                &type_path,
+                document_type,
+                id,
+                &document,
+                transaction,
+            )?;
+            self.delete(
+                path_slices(&Self::documents_path(path, document_type)),
+                id,
+                transaction,
+            )?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Gets a document by its `id`, `None` is returned if there is no such
+    /// document or the document type subtrees were not created yet.
+    pub fn get_document(
+        &self,
+        path: &[&[u8]],
+        document_type: &DocumentType,
+        id: &[u8],
+        transaction: TransactionArg,
+    ) -> Result<Option<Value>, Error> {
+        let documents_path = Self::documents_path(path, document_type);
+        match self.get(path_slices(&documents_path), id, transaction) {
+            Ok(Element::Item(bytes)) => Ok(Some(decode_document(&bytes)?)),
+            Ok(_) => Err(Error::CorruptedData(String::from(
+                "document must be stored as an item",
+            ))),
+            Err(Error::PathKeyNotFound(_) | Error::PathNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Queries documents matching all `where_clauses`. With no clauses all
+    /// documents are returned ordered by id; otherwise at least one clause
+    /// must be on an indexed field, the first such clause is resolved using
+    /// the index and the rest are applied to the fetched documents.
+    pub fn query_documents(
+        &self,
+        path: &[&[u8]],
+        document_type: &DocumentType,
+        where_clauses: &[WhereClause],
+        transaction: TransactionArg,
+    ) -> Result<Vec<Value>, Error> {
+        let mut query = Query::new();
+        query.insert_all();
+
+        let items = if where_clauses.is_empty() {
+            let documents_path = Self::documents_path(path, document_type);
+            self.query_document_items(PathQuery::new_unsized(documents_path, query), transaction)?
+        } else {
+            let index_clause = where_clauses
+                .iter()
+                .find(|clause| document_type.is_indexed(clause.field()))
+                .ok_or(Error::InvalidQuery(
+                    "at least one where clause must use an indexed field",
+                ))?;
+            let mut field_path = Self::document_type_path(path, document_type);
+            field_path.extend([
+                INDICES_KEY.to_vec(),
+                index_clause.field().as_bytes().to_vec(),
+            ]);
+
+            // Several values may be equal after encoding, so deduplicate them
+            let value_keys = index_clause
+                .values()
+                .into_iter()
+                .map(encode_document)
+                .collect::<Result<BTreeSet<Vec<u8>>, Error>>()?;
+            let mut items = Vec::new();
+            for value_key in value_keys {
+                let mut value_path = field_path.clone();
+                value_path.push(value_key);
+                items.extend(self.query_document_items(
+                    PathQuery::new_unsized(value_path, query.clone()),
+                    transaction,
+                )?);
+            }
+            items
+        };
+
+        let mut documents = Vec::with_capacity(items.len());
+        for item in items {
+            let document = decode_document(&item)?;
+            if where_clauses.iter().all(|clause| clause.matches(&document)) {
+                documents.push(document);
+            }
+        }
+        Ok(documents)
+    }
+
+    fn query_document_items(
+        &self,
+        path_query: PathQuery,
+        transaction: TransactionArg,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        match self.get_path_query(&path_query, transaction) {
+            Ok((items, _)) => Ok(items),
+            Err(Error::PathNotFound(_)) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn remove_document_from_indices(
+        &self,
+        type_path: &[Vec<u8>],
+        document_type: &DocumentType,
+        id: &[u8],
+        document: &Value,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        for field in document_type.indexed_fields() {
+            if let Some(value) = document.get(field) {
+                let mut field_path = type_path.to_vec();
+                field_path.extend([INDICES_KEY.to_vec(), field.as_bytes().to_vec()]);
+                let value_key = encode_document(value)?;
+                let mut value_path = field_path.clone();
+                value_path.push(value_key.clone());
+                self.delete(path_slices(&value_path), id, transaction)?;
+                self.delete_if_empty_tree(path_slices(&field_path), &value_key, transaction)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn create_document_type_trees(
+        &self,
+        path: &[&[u8]],
+        document_type: &DocumentType,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        let type_path = Self::document_type_path(path, document_type);
+        let (type_key, parent_path) = type_path.split_last().expect("type path is not empty");
+        self.insert_if_not_exists(
+            path_slices(parent_path),
+            type_key,
+            Element::empty_tree(),
+            transaction,
+        )?;
+        for key in [DOCUMENTS_KEY, INDICES_KEY] {
+            self.insert_if_not_exists(
+                path_slices(&type_path),
+                key,
+                Element::empty_tree(),
+                transaction,
+            )?;
+        }
+        let mut indices_path = type_path;
+        indices_path.push(INDICES_KEY.to_vec());
+        for field in document_type.indexed_fields() {
+            self.insert_if_not_exists(
+                path_slices(&indices_path),
+                field.as_bytes(),
+                Element::empty_tree(),
+                transaction,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn document_type_path(path: &[&[u8]], document_type: &DocumentType) -> Vec<Vec<u8>> {
+        let mut type_path: Vec<Vec<u8>> = path.iter().map(|x| x.to_vec()).collect();
+        type_path.push(document_type.name().as_bytes().to_vec());
+        type_path
+    }
+
+    fn documents_path(path: &[&[u8]], document_type: &DocumentType) -> Vec<Vec<u8>> {
+        let mut documents_path = Self::document_type_path(path, document_type);
+        documents_path.push(DOCUMENTS_KEY.to_vec());
+        documents_path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::tests::{make_grovedb, TEST_LEAF};
+
+    fn person_type() -> DocumentType {
+        DocumentType::from_json_schema(
+            "person".to_owned(),
+            &json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string" },
+                    "age": { "type": "integer" },
+                },
+                "indices": [
+                    { "properties": [{ "age": "asc" }] },
+                    { "properties": [{ "name": "asc" }, { "age": "asc" }] },
+                ],
+            }),
+        )
+        .expect("valid schema")
+    }
+
+    #[test]
+    fn test_document_type_from_schema() {
+        assert_eq!(
+            person_type().indexed_fields(),
+            &["age".to_owned(), "name".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_insert_and_query_documents() {
+        let db = make_grovedb();
+        let person = person_type();
+        let alice = json!({ "name": "alice", "age": 30 });
+        let bob = json!({ "name": "bob", "age": 25 });
+        let carol = json!({ "name": "carol", "age": 30 });
+
+        db.insert_document(&[TEST_LEAF], &person, b"1", &alice, None)
+            .expect("successful document insert");
+        db.insert_document(&[TEST_LEAF], &person, b"2", &bob, None)
+            .expect("successful document insert");
+        db.insert_document(&[TEST_LEAF], &person, b"3", &carol, None)
+            .expect("successful document insert");
+
+        assert_eq!(
+            db.get_document(&[TEST_LEAF], &person, b"2", None)
+                .expect("successful get"),
+            Some(bob.clone())
+        );
+        assert_eq!(
+            db.query_documents(&[TEST_LEAF], &person, &[], None)
+                .expect("successful query"),
+            vec![alice.clone(), bob.clone(), carol.clone()]
+        );
+        assert_eq!(
+            db.query_documents(
+                &[TEST_LEAF],
+                &person,
+                &[WhereClause::Equal("age".to_owned(), json!(30))],
+                None
+            )
+            .expect("successful query"),
+            vec![alice.clone(), carol.clone()]
+        );
+        assert_eq!(
+            db.query_documents(
+                &[TEST_LEAF],
+                &person,
+                &[
+                    WhereClause::Equal("age".to_owned(), json!(30)),
+                    WhereClause::In("name".to_owned(), vec![json!("carol"), json!("bob")]),
+                ],
+                None
+            )
+            .expect("successful query"),
+            vec![carol]
+        );
+        assert!(matches!(
+            db.query_documents(
+                &[TEST_LEAF],
+                &DocumentType::new("person".to_owned(), vec![]),
+                &[WhereClause::Equal("age".to_owned(), json!(30))],
+                None
+            ),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_update_and_delete_document_maintain_indices() {
+        let db = make_grovedb();
+        let person = person_type();
+
+        db.insert_document(
+            &[TEST_LEAF],
+            &person,
+            b"1",
+            &json!({ "name": "alice", "age": 30 }),
+            None,
+        )
+        .expect("successful document insert");
+        let older_alice = json!({ "name": "alice", "age": 31 });
+        db.insert_document(&[TEST_LEAF], &person, b"1", &older_alice, None)
+            .expect("successful document update");
+
+        let by_age = |age| {
+            db.query_documents(
+                &[TEST_LEAF],
+                &person,
+                &[WhereClause::Equal("age".to_owned(), json!(age))],
+                None,
+            )
+            .expect("successful query")
+        };
+        assert!(by_age(30).is_empty());
+        assert_eq!(by_age(31), vec![older_alice]);
+
+        assert!(db
+            .delete_document(&[TEST_LEAF], &person, b"1", None)
+            .expect("successful document delete"));
+        assert!(by_age(31).is_empty());
+        assert!(!db
+            .delete_document(&[TEST_LEAF], &person, b"1", None)
+            .expect("successful document delete"));
+    }
+
+    #[test]
+    fn test_failed_document_insert_writes_nothing() {
+        let db = make_grovedb();
+        let person = person_type();
+        let alice = json!({ "name": "alice", "age": 30 });
+        db.insert_document(&[TEST_LEAF], &person, b"1", &alice, None)
+            .expect("successful document insert");
+        db.freeze_subtree([TEST_LEAF, b"person", INDICES_KEY, b"name"])
+            .expect("successful freeze");
+        let root_hash = db.root_hash(None).expect("successful root hash");
+
+        // The document is written before its index entries, which fail
+        assert!(matches!(
+            db.insert_document(
+                &[TEST_LEAF],
+                &person,
+                b"2",
+                &json!({ "name": "bob", "age": 25 }),
+                None
+            ),
+            Err(Error::SubtreeFrozen)
+        ));
+        assert!(matches!(
+            db.delete_document(&[TEST_LEAF], &person, b"1", None),
+            Err(Error::SubtreeFrozen)
+        ));
+        assert_eq!(
+            db.get_document(&[TEST_LEAF], &person, b"2", None)
+                .expect("successful get"),
+            None
+        );
+        assert_eq!(
+            db.get_document(&[TEST_LEAF], &person, b"1", None)
+                .expect("successful get"),
+            Some(alice)
+        );
+        assert_eq!(db.root_hash(None).expect("successful root hash"), root_hash);
+    }
+}
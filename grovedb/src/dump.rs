@@ -0,0 +1,242 @@
+//! Module for subtree dumps.
+//! Test fixtures and migrations need subtree contents in a form which can be
+//! read, diffed and loaded into another GroveDB. A dump is a canonical nested
+//! document of elements by hex encoded keys in key order, with nested
+//! subtrees dumped in place. Loading a dump doesn't verify any hashes.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Element, Error, GroveDb, MutationKind, TransactionArg};
+
+/// Encoding of a dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Json,
+    Cbor,
+}
+
+/// An element of a dump, items deduplicated in storage are dumped as items
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum DumpedElement {
+    Item { value: String },
+    Reference { path: Vec<String> },
+    Tree { elements: DumpedSubtree },
+}
+
+type DumpedSubtree = BTreeMap<String, DumpedElement>;
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(hex).map_err(|_| Error::CorruptedData(format!("invalid hex in dump: {}", hex)))
+}
+
+impl GroveDb {
+    /// Writes elements of the subtree at the path, including nested subtrees,
+    /// as a dump in the format. Pruned subtrees can't be dumped.
+    pub fn dump_subtree<'p, P, W>(
+        &self,
+        path: P,
+        format: DumpFormat,
+        writer: W,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        W: Write,
+    {
+        let path: Vec<Vec<u8>> = path.into_iter().map(|x| x.to_vec()).collect();
+        let dump = self.dump_elements(&path, transaction)?;
+        match format {
+            DumpFormat::Json => serde_json::to_writer_pretty(writer, &dump)
+                .map_err(|e| Error::CorruptedData(format!("unable to write dump: {}", e))),
+            DumpFormat::Cbor => ciborium::ser::into_writer(&dump, writer)
+                .map_err(|e| Error::CorruptedData(format!("unable to write dump: {}", e))),
+        }
+    }
+
+    fn dump_elements(
+        &self,
+        path: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> Result<DumpedSubtree, Error> {
+        let mut dump = DumpedSubtree::new();
+        for (key, element) in self.iter(path.iter().map(|x| x.as_slice()), false, transaction)? {
+            let element = element.expect("elements are read with values");
+            let dumped = match self.resolve_dedup_item(element, transaction)? {
+                Element::Item(value) => DumpedElement::Item {
+                    value: hex::encode(value),
+                },
+                Element::Reference(reference_path) => DumpedElement::Reference {
+                    path: reference_path.iter().map(hex::encode).collect(),
+                },
+                Element::Tree(_) => {
+                    let mut subtree_path = path.to_vec();
+                    subtree_path.push(key.clone());
+                    DumpedElement::Tree {
+                        elements: self.dump_elements(&subtree_path, transaction)?,
+                    }
+                }
+                Element::PrunedTree(_) => return Err(Error::SubtreePruned),
+                Element::DedupItem(_) => {
+                    return Err(Error::CorruptedData(String::from(
+                        "deduplicated item is not resolved",
+                    )))
+                }
+            };
+            dump.insert(hex::encode(key), dumped);
+        }
+        Ok(dump)
+    }
+
+    /// Inserts elements of a dump in the format into the subtree at the path,
+    /// which must exist and not be frozen. References are inserted after all
+    /// other elements, so they may refer to elements of the dump regardless
+    /// of their order. The access policy is checked for the whole subtree
+    /// before anything is inserted and then for every element.
+    pub fn load_subtree<'p, P, R>(
+        &self,
+        path: P,
+        format: DumpFormat,
+        reader: R,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        R: Read,
+    {
+        let dump: DumpedSubtree = match format {
+            DumpFormat::Json => serde_json::from_reader(reader)
+                .map_err(|e| Error::CorruptedData(format!("unable to read dump: {}", e)))?,
+            DumpFormat::Cbor => ciborium::de::from_reader(reader)
+                .map_err(|e| Error::CorruptedData(format!("unable to read dump: {}", e)))?,
+        };
+        let path: Vec<Vec<u8>> = path.into_iter().map(|x| x.to_vec()).collect();
+        // Fail before anything is inserted rather than on the first element
+        self.check_subtree_access(path.iter().map(|x| x.as_slice()), MutationKind::Load)?;
+        if self.is_subtree_frozen(path.iter().map(|x| x.as_slice()))? {
+            return Err(Error::SubtreeFrozen);
+        }
+        let mut references = Vec::new();
+        self.load_elements(&path, dump, &mut references, transaction)?;
+        for (path, key, element) in references {
+            self.insert(
+                path.iter().map(|x| x.as_slice()),
+                &key,
+                element,
+                transaction,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn load_elements(
+        &self,
+        path: &[Vec<u8>],
+        dump: DumpedSubtree,
+        references: &mut Vec<(Vec<Vec<u8>>, Vec<u8>, Element)>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        for (key, dumped) in dump {
+            let key = decode_hex(&key)?;
+            let path_slices = path.iter().map(|x| x.as_slice());
+            match dumped {
+                DumpedElement::Item { value } => self.insert(
+                    path_slices,
+                    &key,
+                    Element::Item(decode_hex(&value)?),
+                    transaction,
+                )?,
+                DumpedElement::Reference {
+                    path: reference_path,
+                } => {
+                    let reference_path = reference_path
+                        .iter()
+                        .map(|segment| decode_hex(segment))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    references.push((path.to_vec(), key, Element::Reference(reference_path)));
+                }
+                DumpedElement::Tree { elements } => {
+                    self.insert(path_slices, &key, Element::empty_tree(), transaction)?;
+                    let mut subtree_path = path.to_vec();
+                    subtree_path.push(key);
+                    self.load_elements(&subtree_path, elements, references, transaction)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_grovedb, TEST_LEAF};
+
+    #[test]
+    fn test_dump_and_load_subtree() {
+        let db = make_grovedb();
+        db.insert(
+            [TEST_LEAF],
+            b"key1",
+            Element::Item(b"value1".to_vec()),
+            None,
+        )
+        .expect("successful item insert");
+        db.insert([TEST_LEAF], b"tree", Element::empty_tree(), None)
+            .expect("successful subtree insert");
+        db.insert(
+            [TEST_LEAF, b"tree".as_slice()],
+            b"key2",
+            Element::Item(b"value2".to_vec()),
+            None,
+        )
+        .expect("successful item insert");
+        // The reference precedes its target in key order
+        db.insert(
+            [TEST_LEAF],
+            b"a_ref",
+            Element::Reference(vec![TEST_LEAF.to_vec(), b"key1".to_vec()]),
+            None,
+        )
+        .expect("successful reference insert");
+
+        for format in [DumpFormat::Json, DumpFormat::Cbor] {
+            let mut dump = Vec::new();
+            db.dump_subtree([TEST_LEAF], format, &mut dump, None)
+                .expect("successful dump");
+
+            let other_db = make_grovedb();
+            other_db
+                .load_subtree([TEST_LEAF], format, dump.as_slice(), None)
+                .expect("successful load");
+            assert_eq!(
+                other_db
+                    .get([TEST_LEAF, b"tree".as_slice()], b"key2", None)
+                    .expect("successful get"),
+                Element::Item(b"value2".to_vec())
+            );
+            assert_eq!(
+                other_db
+                    .get([TEST_LEAF], b"a_ref", None)
+                    .expect("successful get"),
+                Element::Item(b"value1".to_vec())
+            );
+
+            let mut reloaded_dump = Vec::new();
+            other_db
+                .dump_subtree([TEST_LEAF], format, &mut reloaded_dump, None)
+                .expect("successful dump");
+            assert_eq!(dump, reloaded_dump);
+        }
+
+        assert!(matches!(
+            db.load_subtree([TEST_LEAF], DumpFormat::Json, b"{".as_slice(), None),
+            Err(Error::CorruptedData(_))
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+//! Module for estimation of operation costs from tree shape hints.
+//! Fees have to be known before an operation is applied, and bounding them
+//! by the largest trees possible overcharges operations on the usual small
+//! ones. Callers knowing approximate element counts and sizes of layers on
+//! the path of an operation get an estimate of the storage work it does in
+//! the average case instead. Bounds of the storage writes of an operation
+//! come from limits of layer sizes.
+
+use merk::HASH_LENGTH;
+
+use crate::{Element, Error, GroveDb, GroveDbOp, QueryCost};
+
+/// Bytes of a child link of an encoded tree node besides the child key: the
+/// link flag, the key length, the child hash and child heights
+const LINK_OVERHEAD_BYTES: u64 = HASH_LENGTH as u64 + 4;
+/// Number of nodes written per level of a layer in the worst case, a node on
+/// the path to the updated one may be rotated with its children
+const WORST_CASE_NODES_PER_LEVEL: u64 = 3;
+
+/// Approximate shape of a layer, i.e. a subtree, on the path of an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EstimatedLayerInfo {
+    /// Number of elements in the subtree
+    pub element_count: u64,
+    /// Average size of keys of the subtree in bytes
+    pub average_key_size: u32,
+    /// Average size of serialized elements of the subtree in bytes
+    pub average_value_size: u32,
+}
+
+impl EstimatedLayerInfo {
+    /// Returns the number of nodes on a path from the root to a leaf of a
+    /// balanced tree of the layer's elements, at least the updated one
+    fn height(&self) -> u64 {
+        u64::from(64 - self.element_count.leading_zeros()).max(1)
+    }
+
+    fn node_bytes(&self, value_size: u64) -> u64 {
+        node_bytes(u64::from(self.average_key_size), value_size)
+    }
+
+    /// Returns the cost of updating a value of `value_size` in the layer,
+    /// which loads and rewrites every node on the path to it
+    fn update_cost(&self, value_size: u64) -> QueryCost {
+        let height = self.height();
+        let path_bytes = (height - 1) * self.node_bytes(u64::from(self.average_value_size))
+            + self.node_bytes(value_size);
+        QueryCost {
+            seek_count: height,
+            loaded_bytes: path_bytes,
+            written_bytes: path_bytes,
+        }
+    }
+}
+
+/// Limits of a layer, i.e. a subtree, on the path of an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorstCaseLayerInfo {
+    /// Maximum number of elements in the subtree
+    pub max_element_count: u64,
+    /// Maximum size of keys of the subtree in bytes
+    pub max_key_size: u32,
+    /// Maximum size of serialized elements of the subtree in bytes
+    pub max_value_size: u32,
+}
+
+impl WorstCaseLayerInfo {
+    /// Returns the maximum height of an AVL tree of the layer's elements with
+    /// one more inserted
+    fn height(&self) -> u64 {
+        let count = self.max_element_count.saturating_add(2) as f64;
+        ((1.4405 * count.log2() - 0.3277).floor() as u64).max(1)
+    }
+
+    /// Returns the cost of updating a value of `value_size` in the layer
+    fn update_cost(&self, value_size: u64) -> QueryCost {
+        let nodes = WORST_CASE_NODES_PER_LEVEL * self.height();
+        let value_size = value_size.max(u64::from(self.max_value_size));
+        let bytes = nodes * node_bytes(u64::from(self.max_key_size), value_size);
+        QueryCost {
+            seek_count: nodes,
+            loaded_bytes: bytes,
+            written_bytes: bytes,
+        }
+    }
+}
+
+/// Returns the size of an encoded tree node with two children
+fn node_bytes(key_size: u64, value_size: u64) -> u64 {
+    key_size + value_size + HASH_LENGTH as u64 + 2 * (key_size + LINK_OVERHEAD_BYTES)
+}
+
+/// Returns the path of the operation and the size of the element it writes
+fn op_path_and_value_size(op: &GroveDbOp) -> (&Vec<Vec<u8>>, u64) {
+    match op {
+        GroveDbOp::Insert { path, element, .. } => (path, element.byte_size() as u64),
+        // A deletion rewrites nodes on the path to the deleted one without it
+        GroveDbOp::Delete { path, .. } => (path, 0),
+    }
+}
+
+/// Sums costs of updates of layers on the path, the last layer is updated
+/// with the element of the operation and others with child subtree hashes
+fn path_update_cost(
+    path_len: usize,
+    value_size: u64,
+    layer_count: usize,
+    layer_cost: impl Fn(usize, u64) -> QueryCost,
+) -> Result<QueryCost, Error> {
+    if layer_count != path_len + 1 {
+        return Err(Error::InvalidQuery(
+            "layer info must be given for every subtree on the path",
+        ));
+    }
+    let tree_value_size = Element::empty_tree().byte_size() as u64;
+    let mut cost = QueryCost::default();
+    for depth in 0..layer_count {
+        let value_size = if depth == path_len {
+            value_size
+        } else {
+            tree_value_size
+        };
+        let layer_cost = layer_cost(depth, value_size);
+        cost.seek_count += layer_cost.seek_count;
+        cost.loaded_bytes += layer_cost.loaded_bytes;
+        cost.written_bytes += layer_cost.written_bytes;
+    }
+    Ok(cost)
+}
+
+impl GroveDb {
+    /// Estimates the cost of applying the operation in the average case.
+    /// `layers` describe subtrees on the operation path starting from the
+    /// root tree, the last one is the subtree the operation is applied to.
+    /// Every ancestor layer pays for the update of the root hash of its
+    /// child.
+    pub fn estimated_case_cost_for_op(
+        op: &GroveDbOp,
+        layers: &[EstimatedLayerInfo],
+    ) -> Result<QueryCost, Error> {
+        let (path, value_size) = op_path_and_value_size(op);
+        path_update_cost(path.len(), value_size, layers.len(), |depth, value_size| {
+            layers[depth].update_cost(value_size)
+        })
+    }
+
+    /// Bounds the cost of applying the operation like
+    /// [`GroveDb::estimated_case_cost_for_op`] from limits of layers. Written
+    /// bytes are bounded for insertions and deletions of items and empty
+    /// subtrees, a deletion of a subtree also removes its data. Reads made by
+    /// GroveDB checks, e.g. of the previous element, come on top of the seeks
+    /// and loaded bytes.
+    pub fn worst_case_cost_for_op(
+        op: &GroveDbOp,
+        layers: &[WorstCaseLayerInfo],
+    ) -> Result<QueryCost, Error> {
+        let (path, value_size) = op_path_and_value_size(op);
+        path_update_cost(path.len(), value_size, layers.len(), |depth, value_size| {
+            layers[depth].update_cost(value_size)
+        })
+    }
+}
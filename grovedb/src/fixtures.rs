@@ -0,0 +1,115 @@
+//! Module for building GroveDB states for tests and benchmarks.
+//! Downstream tests set up their groves with long sequences of inserts,
+//! copied from one repository to another. [`TreeBuilder`] describes a grove
+//! declaratively instead and inserts its elements in the order they were
+//! declared, so the same description always builds the same state.
+
+use crate::{Element, Error, GroveDb, TransactionArg};
+
+#[derive(Debug, Clone)]
+enum FixtureElement {
+    Item(Vec<u8>),
+    Reference(Vec<Vec<u8>>),
+    Tree(TreeBuilder),
+}
+
+/// Description of subtree contents, nested subtrees are described with their
+/// own builders
+#[derive(Debug, Clone, Default)]
+pub struct TreeBuilder {
+    elements: Vec<(Vec<u8>, FixtureElement)>,
+}
+
+impl TreeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn item(mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Self {
+        self.elements.push((
+            key.as_ref().to_vec(),
+            FixtureElement::Item(value.as_ref().to_vec()),
+        ));
+        self
+    }
+
+    /// Adds a reference to the element at the absolute path
+    pub fn reference<S: AsRef<[u8]>>(
+        mut self,
+        key: impl AsRef<[u8]>,
+        path: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let path = path.into_iter().map(|x| x.as_ref().to_vec()).collect();
+        self.elements
+            .push((key.as_ref().to_vec(), FixtureElement::Reference(path)));
+        self
+    }
+
+    /// Adds a subtree with contents described by `build`
+    pub fn tree(mut self, key: impl AsRef<[u8]>, build: impl FnOnce(Self) -> Self) -> Self {
+        self.elements.push((
+            key.as_ref().to_vec(),
+            FixtureElement::Tree(build(Self::new())),
+        ));
+        self
+    }
+
+    /// Inserts the described elements into the root tree, which holds
+    /// subtrees only
+    pub fn build(&self, db: &GroveDb) -> Result<(), Error> {
+        self.build_at(db, [], None)
+    }
+
+    /// Inserts the described elements into the existing subtree at the path.
+    /// References are inserted after all other elements, so they may refer
+    /// to elements declared after them.
+    pub fn build_at<'p, P>(
+        &self,
+        db: &GroveDb,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path: Vec<Vec<u8>> = path.into_iter().map(|x| x.to_vec()).collect();
+        let mut references = Vec::new();
+        self.insert_elements(db, &path, &mut references, transaction)?;
+        for (path, key, reference_path) in references {
+            db.insert(
+                path.iter().map(|x| x.as_slice()),
+                &key,
+                Element::Reference(reference_path),
+                transaction,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn insert_elements(
+        &self,
+        db: &GroveDb,
+        path: &[Vec<u8>],
+        references: &mut Vec<(Vec<Vec<u8>>, Vec<u8>, Vec<Vec<u8>>)>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        for (key, element) in &self.elements {
+            let path_slices = path.iter().map(|x| x.as_slice());
+            match element {
+                FixtureElement::Item(value) => {
+                    db.insert(path_slices, key, Element::Item(value.clone()), transaction)?
+                }
+                FixtureElement::Reference(reference_path) => {
+                    references.push((path.to_vec(), key.clone(), reference_path.clone()))
+                }
+                FixtureElement::Tree(subtree) => {
+                    db.insert(path_slices, key, Element::empty_tree(), transaction)?;
+                    let mut subtree_path = path.to_vec();
+                    subtree_path.push(key.clone());
+                    subtree.insert_elements(db, &subtree_path, references, transaction)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
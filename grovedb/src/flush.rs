@@ -0,0 +1,92 @@
+//! Module for flush control.
+//! RocksDB flushes memtables of all column families atomically once they fill
+//! up. A flush policy adds explicit flushes after commits, and column families
+//! can be flushed one by one, so operators control when write amplification
+//! happens.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{rocksdb_storage::ColumnFamily, Error, GroveDb};
+
+/// When GroveDB flushes memtables in addition to flushes made by RocksDB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushPolicy {
+    /// Only on [`GroveDb::flush`] and [`GroveDb::flush_cf`] calls
+    #[default]
+    Manual,
+    /// After every transaction commit and every write made outside of a
+    /// transaction
+    OnCommit,
+    /// After a transaction commit or a write made outside of a transaction if
+    /// the last flush was at least this long ago
+    Periodic(Duration),
+}
+
+pub(crate) struct FlushState {
+    policy: FlushPolicy,
+    last_flush: Mutex<Instant>,
+}
+
+impl Default for FlushState {
+    fn default() -> Self {
+        FlushState {
+            policy: FlushPolicy::default(),
+            last_flush: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl FlushState {
+    pub(crate) fn flushed(&self) {
+        *self.last_flush.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+}
+
+impl GroveDb {
+    /// Sets when memtables are flushed in addition to flushes made by RocksDB
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.flush_state.policy = policy;
+    }
+
+    /// Returns the flush policy
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.flush_state.policy
+    }
+
+    /// Forces data of one column family to be written from memtables to
+    /// storage files
+    pub fn flush_cf(&self, cf: ColumnFamily) -> Result<(), Error> {
+        let started = Instant::now();
+        self.rocksdb()?.flush_cf(cf)?;
+        self.flush_completed(Some(cf), started.elapsed());
+        Ok(())
+    }
+
+    /// Flushes memtables after a commit if the flush policy says so. The
+    /// commit has taken effect even if the flush fails, so a failure doesn't
+    /// fail the commit but is reported to maintenance event listeners.
+    pub(crate) fn flush_after_commit(&self) {
+        let due = match self.flush_state.policy {
+            FlushPolicy::Manual => false,
+            FlushPolicy::OnCommit => true,
+            FlushPolicy::Periodic(period) => {
+                self.flush_state
+                    .last_flush
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .elapsed()
+                    >= period
+            }
+        };
+        if due {
+            if let Err(e) = self.flush() {
+                for listener in &self.maintenance_events {
+                    listener.on_flush_failed(&e);
+                }
+            }
+        }
+    }
+}
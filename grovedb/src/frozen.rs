@@ -0,0 +1,155 @@
+//! Module for frozen subtrees.
+//! Finalized data, such as historical data contracts, can be frozen so it
+//! never changes: a marker kept in meta storage makes every mutation of a
+//! frozen subtree, its descendants and the elements on the path to it fail
+//! with [`Error::SubtreeFrozen`].
+
+use std::{
+    collections::BTreeSet,
+    sync::{RwLock, RwLockWriteGuard},
+};
+
+use storage::{Storage, StorageContext};
+
+use crate::{Error, GroveDb, MutationKind};
+
+/// A key in meta storage to store paths of frozen subtrees. The key is
+/// shorter than a subtree prefix, so it's never taken for data of a subtree.
+const FROZEN_SUBTREES_KEY: &[u8] = b"frozen";
+
+/// Paths of frozen subtrees, read from meta storage on first use. Freezing
+/// is the only way to change them, so they are never reread.
+#[derive(Default)]
+pub(crate) struct FrozenSubtrees {
+    paths: RwLock<Option<BTreeSet<Vec<Vec<u8>>>>>,
+}
+
+fn is_prefix<'p, P>(prefix: &[Vec<u8>], path: P) -> bool
+where
+    P: IntoIterator<Item = &'p [u8]>,
+    <P as IntoIterator>::IntoIter: ExactSizeIterator,
+{
+    let path_iter = path.into_iter();
+    path_iter.len() >= prefix.len()
+        && path_iter
+            .zip(prefix)
+            .all(|(segment, prefix_segment)| segment == prefix_segment.as_slice())
+}
+
+impl GroveDb {
+    /// Freezes the subtree at the path, so it and its descendants can't be
+    /// changed anymore, including deletion of the subtree itself or any of
+    /// its ancestors. Freezing can't be undone. The marker is written outside
+    /// of any transaction.
+    pub fn freeze_subtree<'p, P>(&self, path: P) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        if path_iter.len() == 0 {
+            return Err(Error::InvalidPath("root tree cannot be frozen"));
+        }
+        let mut parent_path = path_iter.clone();
+        if let Some(key) = parent_path.next_back() {
+            self.check_access(parent_path, key, MutationKind::Freeze)?;
+        }
+        if !self.is_subtree(path_iter.clone(), None)? {
+            return Err(Error::PathNotFound("subtree doesn't exist"));
+        }
+        let mut paths = self.frozen_subtrees_mut()?;
+        let frozen = paths.as_mut().expect("frozen subtrees are loaded");
+        let path: Vec<Vec<u8>> = path_iter.map(|x| x.to_vec()).collect();
+        if !frozen.contains(&path) {
+            let mut updated = frozen.clone();
+            updated.insert(path);
+            let serialized = bincode::serialize(&updated).map_err(|_| {
+                Error::CorruptedData(String::from("unable to serialize frozen subtrees"))
+            })?;
+            let meta_storage = self.db.get_storage_context(std::iter::empty());
+            meta_storage.put_meta(FROZEN_SUBTREES_KEY, &serialized)?;
+            *frozen = updated;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the subtree at the path or any of its ancestors is
+    /// frozen
+    pub fn is_subtree_frozen<'p, P>(&self, path: P) -> Result<bool, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        self.with_frozen_subtrees(|frozen| {
+            frozen
+                .iter()
+                .any(|frozen_path| is_prefix(frozen_path, path_iter.clone()))
+        })
+    }
+
+    /// Fails with [`Error::SubtreeFrozen`] if an element under the key of the
+    /// subtree at the path can't be changed because it is in a frozen subtree,
+    /// is a frozen subtree itself or has one nested
+    pub(crate) fn check_not_frozen<'p, P>(&self, path: P, key: &'p [u8]) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let element_path: Vec<&[u8]> = path.into_iter().chain(std::iter::once(key)).collect();
+        let element_path_owned: Vec<Vec<u8>> = element_path.iter().map(|x| x.to_vec()).collect();
+        let frozen = self.with_frozen_subtrees(|frozen| {
+            frozen.iter().any(|frozen_path| {
+                is_prefix(frozen_path, element_path.iter().copied())
+                    || is_prefix(
+                        &element_path_owned,
+                        frozen_path.iter().map(|x| x.as_slice()),
+                    )
+            })
+        })?;
+        if frozen {
+            Err(Error::SubtreeFrozen)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn with_frozen_subtrees<T>(
+        &self,
+        f: impl FnOnce(&BTreeSet<Vec<Vec<u8>>>) -> T,
+    ) -> Result<T, Error> {
+        {
+            let paths = self
+                .frozen_subtrees
+                .paths
+                .read()
+                .unwrap_or_else(|e| e.into_inner());
+            if let Some(frozen) = paths.as_ref() {
+                return Ok(f(frozen));
+            }
+        }
+        let paths = self.frozen_subtrees_mut()?;
+        Ok(f(paths.as_ref().expect("frozen subtrees are loaded")))
+    }
+
+    /// Returns frozen subtrees locked for writing, read from meta storage if
+    /// they are not yet
+    fn frozen_subtrees_mut(
+        &self,
+    ) -> Result<RwLockWriteGuard<Option<BTreeSet<Vec<Vec<u8>>>>>, Error> {
+        let mut paths = self
+            .frozen_subtrees
+            .paths
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        if paths.is_none() {
+            let meta_storage = self.db.get_storage_context(std::iter::empty());
+            *paths = Some(match meta_storage.get_meta(FROZEN_SUBTREES_KEY)? {
+                Some(serialized) => bincode::deserialize(&serialized).map_err(|_| {
+                    Error::CorruptedData(String::from("unable to deserialize frozen subtrees"))
+                })?,
+                None => BTreeSet::new(),
+            });
+        }
+        Ok(paths)
+    }
+}
@@ -0,0 +1,91 @@
+//! Module for garbage collection of orphaned storage.
+//! Subtrees are stored under their identifiers, so entries of a subtree which
+//! is no longer reachable from the root tree, e.g. left by a crash in the
+//! middle of a deletion, are never read again and only take disk space until
+//! collected.
+
+use std::collections::HashSet;
+
+use storage::{dyn_storage::subtree_id_key, StorageContext};
+
+use crate::{
+    archive::cold_subtree_key, operations::prune::pruned_subtree_key, Element, Error, GroveDb,
+    MutationKind, RocksDbStorage,
+};
+
+/// Result of [`GroveDb::collect_garbage`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CollectedGarbage {
+    /// Number of deleted prefixes not reachable from the root tree
+    pub orphaned_prefixes: usize,
+    /// Size of deleted keys and values in bytes, disk space is reclaimed on
+    /// compaction
+    pub reclaimed_bytes: u64,
+}
+
+impl GroveDb {
+    /// Deletes storage entries under prefixes of subtrees which are not
+    /// reachable from the root tree. Subtrees created by uncommitted
+    /// transactions are not reachable yet, so it fails with
+    /// [`Error::TransactionsInProgress`] if any transaction is open, and
+    /// transactions started while it runs wait for it. It fails the same way
+    /// while a prepared transaction is pending, as its writes may be under
+    /// prefixes which are not reachable until it is committed. Other writes
+    /// are blocked while it runs, and reachability is decided on the same
+    /// snapshot entries are deleted from. Pruned and cold subtrees are kept
+    /// reachable to restore their data into but are not descended into.
+    pub fn collect_garbage(&self) -> Result<CollectedGarbage, Error> {
+        self.check_access(std::iter::empty(), &[], MutationKind::CollectGarbage)?;
+        let open_transactions = self
+            .open_transactions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let db = self.rocksdb()?;
+        if *open_transactions != 0 || !db.prepared_transactions()?.is_empty() {
+            return Err(Error::TransactionsInProgress);
+        }
+        let blocked = db.block_writes();
+        let reader = blocked.start_transaction();
+        let meta = reader.storage_context(&[]);
+
+        let mut reachable = HashSet::new();
+        let mut queue: Vec<Vec<Vec<u8>>> = vec![Vec::new()];
+        while let Some(path) = queue.pop() {
+            let path_slices: Vec<&[u8]> = path.iter().map(|x| x.as_slice()).collect();
+            let storage = reader.storage_context(&path_slices);
+            // Subtree data is under its identifier if it has one, otherwise
+            // under the path-derived prefix
+            let path_prefix = RocksDbStorage::build_prefix(path_slices.iter().copied());
+            let id = meta
+                .get_meta(&subtree_id_key(&path_prefix))?
+                .unwrap_or_else(|| path_prefix.clone());
+            reachable.insert(path_prefix);
+            reachable.insert(id);
+            if let Some((key, parent_path)) = path_slices.split_last() {
+                let pruned = meta
+                    .get_meta(&pruned_subtree_key(parent_path.iter().copied(), key))?
+                    .is_some();
+                let cold = meta
+                    .get_meta(&cold_subtree_key(path_slices.iter().copied()))?
+                    .is_some();
+                if pruned || cold {
+                    continue;
+                }
+            }
+            let mut elements = Element::iterator(storage.raw_iter());
+            while let Some((key, element)) = elements.next()? {
+                if let Element::Tree(_) = element {
+                    let mut child_path = path.clone();
+                    child_path.push(key);
+                    queue.push(child_path);
+                }
+            }
+        }
+
+        let deleted = blocked.delete_unreachable_prefixes(|prefix| reachable.contains(prefix))?;
+        Ok(CollectedGarbage {
+            orphaned_prefixes: deleted.len(),
+            reclaimed_bytes: deleted.values().sum(),
+        })
+    }
+}
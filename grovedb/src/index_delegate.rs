@@ -0,0 +1,62 @@
+//! Module for secondary index maintenance hooks.
+//! Index delegates are notified about every element change made by GroveDB
+//! insertions and deletions and receive the same transaction, so derived
+//! index subtrees can be updated atomically with the primary write. A write
+//! made without a transaction runs in an internal one while delegates are
+//! registered, so it is committed together with delegate writes or not at all.
+
+use crate::{Element, Error, GroveDb, TransactionArg};
+
+/// Hook to maintain derived data on element changes.
+///
+/// Delegate is called after the change is written, `old_element` is `None`
+/// for a new key and `new_element` is `None` for a deletion. Writes made by
+/// a delegate through `db` trigger delegates as well, so a delegate should
+/// filter paths it is interested in to avoid endless recursion.
+pub trait IndexDelegate: Send + Sync {
+    fn on_element_change(
+        &self,
+        db: &GroveDb,
+        path: &[&[u8]],
+        key: &[u8],
+        old_element: Option<&Element>,
+        new_element: Option<&Element>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>;
+}
+
+impl GroveDb {
+    /// Registers an index delegate which will be called on every insertion
+    /// and deletion in the order of registration.
+    pub fn register_index_delegate(&mut self, delegate: Box<dyn IndexDelegate>) {
+        self.index_delegates.push(delegate);
+    }
+
+    pub(crate) fn notify_index_delegates<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        old_element: Option<&Element>,
+        new_element: Option<&Element>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        if self.index_delegates.is_empty() {
+            return Ok(());
+        }
+        let path_vec: Vec<&[u8]> = path.into_iter().collect();
+        for delegate in &self.index_delegates {
+            delegate.on_element_change(
+                self,
+                &path_vec,
+                key,
+                old_element,
+                new_element,
+                transaction,
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+//! Module for proofs of how a key evolved.
+//! Auditors checking a record at past states would otherwise query every
+//! checkpoint separately and trust the answers. A key history is a proof of
+//! the key's value or absence in each of the checkpoints, which verifies
+//! against root hashes of these past states.
+
+use std::path::Path;
+
+use crate::{Element, Error, GroveDb, GroveDbReader, PathQuery, Query};
+
+impl GroveDb {
+    /// Generates a proof of the element at the key, or of its absence, in
+    /// each checkpoint made with [`GroveDb::checkpoint`], in the order of the
+    /// checkpoints. The subtree at the path must exist in every checkpoint.
+    pub fn prove_key_history<C: AsRef<Path>>(
+        checkpoints: &[C],
+        path: Vec<Vec<u8>>,
+        key: Vec<u8>,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let mut query = Query::new();
+        query.insert_key(key);
+        let path_query = PathQuery::new_unsized(path, query);
+        checkpoints
+            .iter()
+            .map(|checkpoint| {
+                GroveDbReader::open(checkpoint)?.prove(std::slice::from_ref(&path_query))
+            })
+            .collect()
+    }
+
+    /// Verifies proofs made with [`GroveDb::prove_key_history`] against
+    /// trusted root hashes of the checkpoints and returns the element at the
+    /// key in each of them
+    pub fn verify_key_history(
+        proofs: &[Vec<u8>],
+        root_hashes: &[[u8; 32]],
+        path: &[Vec<u8>],
+        key: &[u8],
+    ) -> Result<Vec<Option<Element>>, Error> {
+        if proofs.len() != root_hashes.len() {
+            return Err(Error::InvalidProof(
+                "proof count differs from root hash count",
+            ));
+        }
+        proofs
+            .iter()
+            .zip(root_hashes)
+            .map(|(proof, expected_root_hash)| {
+                let (root_hash, results) = Self::execute_proof(proof)?;
+                if root_hash != *expected_root_hash {
+                    return Err(Error::InvalidProof("root hashes mismatch"));
+                }
+                let element_bytes = results
+                    .get(path)
+                    .ok_or(Error::InvalidProof("subtree is not proved"))?
+                    .get(key)
+                    .map_err(|_| Error::InvalidProof("key is not proved"))?;
+                element_bytes
+                    .map(|bytes| {
+                        bincode::deserialize(bytes)
+                            .map_err(|_| Error::InvalidProof("unable to deserialize element"))
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+}
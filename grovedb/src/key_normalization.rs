@@ -0,0 +1,247 @@
+//! Module for normalized keys.
+//! Name-registry style subtrees need keys differing only by case or unicode
+//! form to be the same key. Elements inserted with a [`KeyNormalizer`] are
+//! stored under the normalized key and the original key is kept in the
+//! subtree's roots storage under a dedicated prefix, apart from user auxiliary
+//! data, queries are normalized the same way so they match regardless of the
+//! form of the queried keys. Elements have no flags, so keeping the original
+//! key apart doesn't change element encoding and hashes. The entry is removed
+//! when its element is deleted, replaced or swapped, or its subtree deleted.
+
+use merk::proofs::query::QueryItem;
+use storage::{RawIterator, StorageContext};
+
+use crate::{
+    util::storage_context_optional_tx, Element, Error, GroveDb, PathQuery, Query, SizedQuery,
+    TransactionArg,
+};
+
+/// A key in roots storage of a subtree to flag it as having elements inserted
+/// with normalized keys, so writes to other subtrees don't look for original
+/// keys to remove
+const NORMALIZED_KEYS_KEY: &[u8] = b"normalized_keys";
+/// A prefix of keys in roots storage of a subtree to keep original keys,
+/// followed by a normalized key
+const ORIGINAL_KEY_PREFIX: &[u8] = b"original_key/";
+
+/// Maps keys to their normalized form, e.g. lowercasing or unicode NFC.
+/// Normalization must be idempotent and should preserve order for range
+/// queries to match the same keys as without normalization.
+pub trait KeyNormalizer: Send + Sync {
+    fn normalize(&self, key: &[u8]) -> Vec<u8>;
+}
+
+/// Normalizer lowercasing ASCII letters, other bytes are kept as is
+pub struct AsciiLowercase;
+
+impl KeyNormalizer for AsciiLowercase {
+    fn normalize(&self, key: &[u8]) -> Vec<u8> {
+        key.to_ascii_lowercase()
+    }
+}
+
+fn original_key_root_key(normalized_key: &[u8]) -> Vec<u8> {
+    let mut root_key = ORIGINAL_KEY_PREFIX.to_vec();
+    root_key.extend_from_slice(normalized_key);
+    root_key
+}
+
+fn normalize_query_item(item: &QueryItem, normalizer: &dyn KeyNormalizer) -> QueryItem {
+    let n = |key: &Vec<u8>| normalizer.normalize(key);
+    match item {
+        QueryItem::Key(key) => QueryItem::Key(n(key)),
+        QueryItem::Range(range) => QueryItem::Range(n(&range.start)..n(&range.end)),
+        QueryItem::RangeInclusive(range) => {
+            QueryItem::RangeInclusive(n(range.start())..=n(range.end()))
+        }
+        QueryItem::RangeFull(range) => QueryItem::RangeFull(*range),
+        QueryItem::RangeFrom(range) => QueryItem::RangeFrom(n(&range.start)..),
+        QueryItem::RangeTo(range) => QueryItem::RangeTo(..n(&range.end)),
+        QueryItem::RangeToInclusive(range) => QueryItem::RangeToInclusive(..=n(&range.end)),
+        QueryItem::RangeAfter(range) => QueryItem::RangeAfter(n(&range.start)..),
+        QueryItem::RangeAfterTo(range) => QueryItem::RangeAfterTo(n(&range.start)..n(&range.end)),
+        QueryItem::RangeAfterToInclusive(range) => {
+            QueryItem::RangeAfterToInclusive(n(range.start())..=n(range.end()))
+        }
+    }
+}
+
+impl GroveDb {
+    /// Inserts an element under the normalized form of the key and keeps the
+    /// original key, see [`GroveDb::get_original_key`]. An element inserted
+    /// before under a key with the same normalized form is replaced.
+    pub fn insert_normalized<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        element: Element,
+        normalizer: &dyn KeyNormalizer,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path: Vec<Vec<u8>> = path.into_iter().map(|x| x.to_vec()).collect();
+        if transaction.is_none() {
+            // Element and its original key are written atomically
+            let tx = self.start_transaction();
+            self.insert_normalized(
+                path.iter().map(|x| x.as_slice()),
+                key,
+                element,
+                normalizer,
+                Some(&tx),
+            )?;
+            return self.commit_transaction(tx);
+        }
+
+        let normalized_key = normalizer.normalize(key);
+        self.insert(
+            path.iter().map(|x| x.as_slice()),
+            &normalized_key,
+            element,
+            transaction,
+        )?;
+        storage_context_optional_tx!(
+            self.db,
+            path.iter().map(|x| x.as_slice()),
+            transaction,
+            storage,
+            {
+                storage.put_root(NORMALIZED_KEYS_KEY, &[1])?;
+                storage.put_root(original_key_root_key(&normalized_key), key)?;
+            }
+        );
+        Ok(())
+    }
+
+    /// Removes the original key kept for an element which is deleted,
+    /// replaced or moved
+    pub(crate) fn remove_original_key<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        storage_context_optional_tx!(self.db, path, transaction, storage, {
+            if storage.get_root(NORMALIZED_KEYS_KEY)?.is_some() {
+                storage.delete_root(original_key_root_key(key))?;
+            }
+        });
+        Ok(())
+    }
+
+    /// Removes original keys of all elements of a subtree which is being
+    /// deleted and drops its flag
+    pub(crate) fn drop_original_keys(
+        &self,
+        path: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        storage_context_optional_tx!(
+            self.db,
+            path.iter().map(|x| x.as_slice()),
+            transaction,
+            storage,
+            {
+                if storage.get_root(NORMALIZED_KEYS_KEY)?.is_none() {
+                    return Ok(());
+                }
+                let mut root_keys = Vec::new();
+                let mut raw_iter = storage.raw_iter_roots();
+                raw_iter.seek(ORIGINAL_KEY_PREFIX);
+                while let Some(root_key) = raw_iter
+                    .key()
+                    .filter(|root_key| root_key.starts_with(ORIGINAL_KEY_PREFIX))
+                {
+                    root_keys.push(root_key.to_vec());
+                    raw_iter.next();
+                }
+                raw_iter.status()?;
+                drop(raw_iter);
+                for root_key in root_keys {
+                    storage.delete_root(root_key)?;
+                }
+                storage.delete_root(NORMALIZED_KEYS_KEY)?;
+            }
+        );
+        Ok(())
+    }
+
+    /// Gets an element by any form of a key inserted with
+    /// [`GroveDb::insert_normalized`]
+    pub fn get_normalized<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        normalizer: &dyn KeyNormalizer,
+        transaction: TransactionArg,
+    ) -> Result<Element, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path: Vec<Vec<u8>> = path.into_iter().map(|x| x.to_vec()).collect();
+        let normalized_key = normalizer.normalize(key);
+        self.get(
+            path.iter().map(|x| x.as_slice()),
+            &normalized_key,
+            transaction,
+        )
+    }
+
+    /// Returns the key an element was inserted with by
+    /// [`GroveDb::insert_normalized`] under the normalized key, `None` if the
+    /// element was inserted without normalization or is deleted
+    pub fn get_original_key<'p, P>(
+        &self,
+        path: P,
+        normalized_key: &[u8],
+        transaction: TransactionArg,
+    ) -> Result<Option<Vec<u8>>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        storage_context_optional_tx!(self.db, path, transaction, storage, {
+            Ok(storage.get_root(original_key_root_key(normalized_key))?)
+        })
+    }
+
+    /// Runs a path query like [`GroveDb::get_path_query`] with keys and range
+    /// bounds of the query and its subquery conditions normalized. Subqueries
+    /// are not normalized, as they apply to nested subtrees.
+    ///
+    /// Query items which overlap once normalized are merged, so a key found
+    /// by a merged item is matched against subquery conditions in the order
+    /// they were added, and the first one containing it applies, as it would
+    /// without normalization. Conditions normalized to the same item keep the
+    /// subquery of the first one.
+    pub fn query_normalized(
+        &self,
+        path_query: &PathQuery,
+        normalizer: &dyn KeyNormalizer,
+        transaction: TransactionArg,
+    ) -> Result<(Vec<Vec<u8>>, u16), Error> {
+        let query = &path_query.query.query;
+        let mut normalized = Query::new_with_direction(query.left_to_right);
+        for item in query.iter() {
+            normalized.insert_item(normalize_query_item(item, normalizer));
+        }
+        normalized.default_subquery_branch = query.default_subquery_branch.clone();
+        for (item, branch) in &query.conditional_subquery_branches {
+            // Collecting into the map would replace the subquery of an earlier
+            // condition normalized to the same item with a later one
+            normalized
+                .conditional_subquery_branches
+                .entry(normalize_query_item(item, normalizer))
+                .or_insert_with(|| branch.clone());
+        }
+        let normalized_path_query = PathQuery::new(
+            path_query.path.clone(),
+            SizedQuery::new(normalized, path_query.query.limit, path_query.query.offset),
+        );
+        self.get_path_query(&normalized_path_query, transaction)
+    }
+}
@@ -1,33 +1,214 @@
+#[cfg(feature = "full")]
+mod access_policy;
+#[cfg(feature = "full")]
+mod app_hash;
+#[cfg(feature = "full")]
+mod archive;
+#[cfg(feature = "full")]
+mod backup;
+#[cfg(feature = "full")]
+mod cached;
+#[cfg(feature = "full")]
+mod cancellation;
+#[cfg(feature = "full")]
+pub mod costs;
+#[cfg(feature = "full")]
+mod dedup;
+#[cfg(feature = "docs")]
+pub mod docs;
+#[cfg(feature = "dump")]
+mod dump;
+#[cfg(feature = "full")]
+mod estimated_costs;
+#[cfg(feature = "full")]
+pub mod fixtures;
+#[cfg(feature = "full")]
+mod flush;
+#[cfg(feature = "full")]
+mod frozen;
+#[cfg(feature = "full")]
+mod garbage_collection;
+#[cfg(feature = "full")]
+mod index_delegate;
+#[cfg(feature = "full")]
+mod key_history;
+#[cfg(feature = "full")]
+mod key_normalization;
+mod limits;
+#[cfg(feature = "full")]
+mod maintenance;
+#[cfg(feature = "full")]
 mod operations;
+mod path_display;
+#[cfg(feature = "full")]
+mod perf;
+#[cfg(feature = "proto")]
+pub mod proto;
+#[cfg(feature = "full")]
+mod quarantine;
+mod query_cost;
+mod query_dsl;
+#[cfg(feature = "full")]
+mod query_limiter;
+#[cfg(feature = "full")]
+mod query_memo;
+#[cfg(feature = "full")]
+mod query_result;
+#[cfg(feature = "full")]
+mod reader;
+#[cfg(feature = "full")]
+mod references;
+#[cfg(feature = "full")]
+mod root_layer;
+#[cfg(feature = "full")]
+mod scoped_transaction;
+#[cfg(feature = "full")]
+mod slow_operations;
+#[cfg(feature = "full")]
+mod state_bundle;
+#[cfg(feature = "full")]
+mod storage_events;
+#[cfg(feature = "full")]
+mod subscriptions;
 mod subtree;
-#[cfg(test)]
+#[cfg(feature = "full")]
+mod subtree_ids;
+#[cfg(feature = "full")]
+mod subtree_locks;
+#[cfg(feature = "full")]
+mod subtrees_index;
+#[cfg(feature = "full")]
+mod tenant;
+#[cfg(all(test, feature = "full"))]
 mod tests;
+#[cfg(feature = "full")]
+mod two_phase_commit;
+#[cfg(feature = "full")]
 mod util;
+#[cfg(feature = "full")]
+mod value_hash_index;
+mod verify;
+#[cfg(feature = "full")]
+mod version;
 #[cfg(feature = "visualize")]
 mod visualize;
+use std::collections::HashMap;
+#[cfg(feature = "full")]
 use std::{
-    collections::{BTreeMap, HashMap},
+    ops::Deref,
     path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
 };
 
-pub use merk::proofs::{query::QueryItem, Query};
+#[cfg(feature = "full")]
+pub use access_policy::{AccessPolicy, MutationKind};
+#[cfg(feature = "full")]
+pub use app_hash::APP_HASH_VERSION;
+#[cfg(feature = "full")]
+pub use archive::ArchiveUploader;
+#[cfg(feature = "full")]
+pub use backup::BackupProgress;
+#[cfg(feature = "full")]
+pub use cached::CachedGroveDb;
+#[cfg(feature = "full")]
+pub use cancellation::CancellationToken;
+#[cfg(feature = "dump")]
+pub use dump::DumpFormat;
+#[cfg(feature = "full")]
+pub use estimated_costs::{EstimatedLayerInfo, WorstCaseLayerInfo};
+#[cfg(feature = "full")]
+pub use flush::FlushPolicy;
+#[cfg(feature = "full")]
+use flush::FlushState;
+#[cfg(feature = "full")]
+use frozen::FrozenSubtrees;
+#[cfg(feature = "full")]
+pub use garbage_collection::CollectedGarbage;
+#[cfg(feature = "full")]
+pub use index_delegate::IndexDelegate;
+#[cfg(feature = "full")]
+pub use key_normalization::{AsciiLowercase, KeyNormalizer};
+pub use limits::PathLimits;
+#[cfg(feature = "full")]
+pub use maintenance::{MaintenanceHandle, MaintenancePolicy};
+#[cfg(feature = "full")]
 use merk::{self, Merk};
-use rs_merkle::{algorithms::Sha256, MerkleTree};
+#[cfg(feature = "full")]
+pub use merk::{handshake::SyncHandshake, BalanceInfo};
+pub use merk::{
+    proofs::{query::QueryItem, Query},
+    ProofLimits,
+};
+#[cfg(feature = "full")]
+pub use operations::{
+    aux::AuxOp,
+    batch::{GroveDbOp, ReferenceIndexSpec},
+    iter::SubtreeIter,
+    list::ListedElement,
+};
+pub use path_display::{ByteEncoding, BytesDisplay, PathDisplay};
+#[cfg(feature = "full")]
+pub use perf::PerfReport;
+#[cfg(feature = "full")]
+pub use query_cost::CostMeter;
+pub use query_cost::QueryCost;
+#[cfg(feature = "full")]
+use query_limiter::QueryLimiter;
+#[cfg(feature = "full")]
+use query_memo::QueryMemo;
+#[cfg(feature = "full")]
+pub use query_memo::DEFAULT_QUERY_MEMO_CAPACITY;
+#[cfg(feature = "full")]
+pub use query_result::{QueryResultElement, QueryResultElements};
+#[cfg(feature = "full")]
+pub use reader::GroveDbReader;
+#[cfg(feature = "full")]
+pub use references::ReferentialIntegrity;
+#[cfg(feature = "full")]
+pub use root_layer::RootLayer;
+#[cfg(feature = "full")]
+pub use scoped_transaction::ScopedTransaction;
+#[cfg(feature = "full")]
+use scoped_transaction::TransactionScopes;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "full")]
+pub use state_bundle::ProvedStateBundle;
+#[cfg(feature = "full")]
 pub use storage::{
-    rocksdb_storage::{self, RocksDbStorage},
+    dyn_storage::{BoxedStorage, DynStorage, DynTransaction},
+    recording::{RecordingStorage, ReplayStorage},
+    rocksdb_storage::{self, ColumnFamily, RocksDbStorage},
     Storage, StorageContext,
 };
-pub use subtree::Element;
+#[cfg(feature = "full")]
+pub use storage_events::{CompactionInfo, FlushInfo, MaintenanceEvents};
+#[cfg(feature = "full")]
+pub use subscriptions::{KeyChange, KeyChangeOp, RootHashChange};
+#[cfg(feature = "full")]
+use subscriptions::{Subscriptions, WrittenSubtrees};
+pub use subtree::{Element, ElementType};
+#[cfg(feature = "full")]
+pub use subtree_ids::SubtreeId;
+#[cfg(feature = "full")]
+use subtree_locks::SubtreeLocks;
+#[cfg(feature = "full")]
+pub use subtree_locks::{LockWait, SubtreeLockGuard};
+#[cfg(feature = "full")]
+pub use tenant::TenantView;
+#[cfg(feature = "full")]
+pub use two_phase_commit::PreparedToken;
+pub use verify::{execute_proof, verify_query_with_limits, ProofOp};
+#[cfg(feature = "full")]
+pub use version::{Feature, GroveVersion, GROVE_FORMAT_VERSION};
 #[cfg(feature = "visualize")]
 pub use visualize::{visualize_stderr, visualize_stdout, Drawer, Visualize};
 
-use crate::util::{merk_optional_tx, meta_storage_context_optional_tx};
-
-/// A key to store serialized data about subtree prefixes to restore HADS
-/// structure
-/// A key to store serialized data about root tree leafs keys and order
-const ROOT_LEAFS_SERIALIZED_KEY: &[u8] = b"rootLeafsSerialized";
+#[cfg(feature = "full")]
+use crate::util::merk_optional_tx;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -36,10 +217,38 @@ pub enum Error {
     CyclicReference,
     #[error("reference hops limit exceeded")]
     ReferenceLimit,
+    #[error("subtree is locked")]
+    SubtreeLocked,
+    #[error("subtree is pruned")]
+    SubtreePruned,
+    #[error("path is out of transaction scope")]
+    OutOfScope,
+    #[error("feature is not enabled: {0}")]
+    FeatureNotEnabled(&'static str),
+    #[error("referential integrity violation: {0}")]
+    ReferentialIntegrity(&'static str),
     #[error("internal error: {0}")]
     InternalError(&'static str),
     #[error("invalid proof: {0}")]
     InvalidProof(&'static str),
+    #[error("prepared transaction not found")]
+    PreparedTransactionNotFound,
+    #[error("subtree is frozen")]
+    SubtreeFrozen,
+    #[error("access denied")]
+    AccessDenied,
+    #[error("too many concurrent queries")]
+    TooManyQueries,
+    #[error("transaction conflicts with writes or a pending prepared transaction")]
+    PreparedTransactionConflict,
+    #[error("not supported by the storage backend: {0}")]
+    NotSupported(&'static str),
+    #[error("transactions are in progress")]
+    TransactionsInProgress,
+    #[error("root subtree of the tenant view is not found")]
+    TenantRootNotFound,
+    #[error("reference leads out of the tenant view")]
+    ReferenceOutOfView,
 
     // Path errors
 
@@ -61,14 +270,115 @@ pub enum Error {
     InvalidQuery(&'static str),
     #[error("missing parameter: {0}")]
     MissingParameter(&'static str),
+    #[error("unexpected element: {0}")]
+    UnexpectedElement(String),
+    #[error("cost limit exceeded: {0:?}")]
+    CostLimitExceeded(QueryCost),
+    #[error("operation cancelled")]
+    Cancelled,
+    #[error("cost check failed: {0}")]
+    CostCheckFailed(String),
     // Irrecoverable errors
+    #[cfg(feature = "full")]
     #[error("storage error: {0}")]
     StorageError(#[from] rocksdb_storage::Error),
+    #[cfg(feature = "full")]
+    #[error("storage backend error: {0}")]
+    BackendError(#[source] storage::dyn_storage::DynStorageError),
     #[error("data corruption error: {0}")]
     CorruptedData(String),
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("archive error: {0}")]
+    ArchiveError(String),
+    #[error("incompatible version: {0}")]
+    IncompatibleVersion(String),
+}
+
+impl Error {
+    /// Returns a numeric code of the error kind. Codes are stable across
+    /// releases: a code is never reused or changed, new kinds get new codes.
+    /// Codes are grouped by category: input data errors are `1xx`, path
+    /// errors are `2xx`, query errors are `3xx` and irrecoverable errors are
+    /// `4xx`.
+    pub fn code(&self) -> u32 {
+        match self {
+            Error::CyclicReference => 100,
+            Error::ReferenceLimit => 101,
+            Error::SubtreeLocked => 102,
+            Error::SubtreePruned => 103,
+            Error::OutOfScope => 104,
+            Error::FeatureNotEnabled(_) => 105,
+            Error::ReferentialIntegrity(_) => 106,
+            Error::InternalError(_) => 107,
+            Error::InvalidProof(_) => 108,
+            Error::PreparedTransactionNotFound => 109,
+            Error::SubtreeFrozen => 110,
+            Error::AccessDenied => 111,
+            Error::TooManyQueries => 112,
+            Error::PreparedTransactionConflict => 113,
+            Error::NotSupported(_) => 114,
+            Error::TransactionsInProgress => 115,
+            Error::TenantRootNotFound => 116,
+            Error::ReferenceOutOfView => 117,
+            Error::PathKeyNotFound(_) => 200,
+            Error::PathNotFound(_) => 201,
+            Error::InvalidPath(_) => 202,
+            Error::CorruptedPath(_) => 203,
+            Error::InvalidQuery(_) => 300,
+            Error::MissingParameter(_) => 301,
+            Error::UnexpectedElement(_) => 302,
+            Error::CostLimitExceeded(_) => 303,
+            Error::Cancelled => 304,
+            Error::CostCheckFailed(_) => 305,
+            #[cfg(feature = "full")]
+            Error::StorageError(_) => 400,
+            Error::CorruptedData(_) => 401,
+            Error::IoError(_) => 402,
+            Error::ArchiveError(_) => 403,
+            Error::IncompatibleVersion(_) => 404,
+            #[cfg(feature = "full")]
+            Error::BackendError(_) => 405,
+        }
+    }
+}
+
+/// Writes of a storage backend conflicting with a pending prepared
+/// transaction are reported as such rather than as backend failures
+#[cfg(feature = "full")]
+impl From<storage::dyn_storage::DynStorageError> for Error {
+    fn from(error: storage::dyn_storage::DynStorageError) -> Self {
+        match error.downcast_ref::<rocksdb_storage::WriteError>() {
+            Some(rocksdb_storage::WriteError::PreparedTransactionConflict) => {
+                Error::PreparedTransactionConflict
+            }
+            _ => Error::BackendError(error),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[cfg(feature = "full")]
+impl From<rocksdb_storage::WriteError> for Error {
+    fn from(error: rocksdb_storage::WriteError) -> Self {
+        match error {
+            rocksdb_storage::WriteError::RocksDb(error) => Error::StorageError(error),
+            rocksdb_storage::WriteError::PreparedTransactionConflict => {
+                Error::PreparedTransactionConflict
+            }
+        }
+    }
+}
+
+/// Storage batches which cannot fail, such as RocksDB write batches, report
+/// `Infallible` errors
+impl From<std::convert::Infallible> for Error {
+    fn from(error: std::convert::Infallible) -> Self {
+        match error {}
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(any(feature = "full", feature = "serde"), derive(Serialize, Deserialize))]
 pub struct PathQuery {
     // TODO: Make generic over path type
     path: Vec<Vec<u8>>,
@@ -79,7 +389,8 @@ pub struct PathQuery {
 // limit should be applied to the elements returned by the subquery
 // offset should be applied to the first item that will subqueried (first in the
 // case of a range)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(any(feature = "full", feature = "serde"), derive(Serialize, Deserialize))]
 pub struct SizedQuery {
     query: Query,
     limit: Option<u16>,
@@ -94,6 +405,18 @@ impl SizedQuery {
             offset,
         }
     }
+
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    pub fn limit(&self) -> Option<u16> {
+        self.limit
+    }
+
+    pub fn offset(&self) -> Option<u16> {
+        self.offset
+    }
 }
 
 impl PathQuery {
@@ -105,104 +428,457 @@ impl PathQuery {
         let query = SizedQuery::new(query, None, None);
         Self { path, query }
     }
+
+    pub fn path(&self) -> &[Vec<u8>] {
+        &self.path
+    }
+
+    pub fn query(&self) -> &SizedQuery {
+        &self.query
+    }
+
+    #[cfg(feature = "full")]
+    /// Returns a key identifying the path query in caches of query results,
+    /// which is the serialized path query
+    pub(crate) fn cache_key(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("path queries are always serializable")
+    }
+}
+
+#[cfg(feature = "full")]
+/// Options to open GroveDB with, see [`GroveDb::open_with_opts`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroveDbOpts {
+    /// Limit of storage flush and compaction writes in bytes per second, not
+    /// limited if `None`
+    pub rate_limit_bytes_per_sec: Option<i64>,
+    /// Whether RocksDB statistics are collected, see [`GroveDb::statistics`]
+    pub enable_statistics: bool,
+}
+
+#[cfg(feature = "full")]
+/// Storage read options for range iterations of a query. Large analytical
+/// scans should disable `fill_cache` to keep the block cache hot for other
+/// reads, and may set `readahead_bytes` to speed up sequential reads.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryOptions<'a> {
+    /// Read-ahead size in bytes, `0` keeps RocksDB default
+    pub readahead_bytes: usize,
+    /// Whether blocks read by the query are put into the block cache
+    pub fill_cache: bool,
+    /// Meter to account storage reads of the query to, the query is aborted
+    /// once its cost limit is exceeded. Metered queries bypass memoized
+    /// results, see [`GroveDb::set_query_memo_capacity`].
+    pub cost_meter: Option<&'a CostMeter>,
+    /// Token aborting the query with [`Error::Cancelled`] once it's cancelled
+    pub cancellation: Option<&'a CancellationToken>,
+}
+
+#[cfg(feature = "full")]
+impl Default for QueryOptions<'_> {
+    fn default() -> Self {
+        Self {
+            readahead_bytes: 0,
+            fill_cache: true,
+            cost_meter: None,
+            cancellation: None,
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl QueryOptions<'_> {
+    /// Accounts a storage seek loading `loaded_bytes` to the cost meter and
+    /// checks for cancellation
+    pub(crate) fn add_seek(&self, loaded_bytes: usize) -> Result<(), Error> {
+        if let Some(cancellation) = self.cancellation {
+            cancellation.check_periodically()?;
+        }
+        match self.cost_meter {
+            Some(meter) => meter.add_seek(loaded_bytes),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Proof {
     query_paths: Vec<Vec<Vec<u8>>>,
     proofs: HashMap<Vec<u8>, Vec<u8>>,
-    root_proof: Vec<u8>,
-    root_leaf_keys: HashMap<Vec<u8>, usize>,
+    /// Encoded push operations occurring more than once in subtree proofs,
+    /// which are referenced by index there
+    shared_nodes: Vec<Vec<u8>>,
+    /// Values of deduplicated items in proofs of queried subtrees
+    dedup_values: Vec<Vec<u8>>,
 }
 
+#[cfg(feature = "full")]
 pub struct GroveDb {
-    db: RocksDbStorage,
+    db: BoxedStorage,
+    index_delegates: Vec<Box<dyn IndexDelegate>>,
+    referential_integrity: ReferentialIntegrity,
+    quarantine_mode: bool,
+    subtree_locks: SubtreeLocks,
+    maintenance_events: Vec<Box<dyn MaintenanceEvents>>,
+    archive: Option<Box<dyn ArchiveUploader>>,
+    transaction_scopes: TransactionScopes,
+    subscriptions: Subscriptions,
+    query_memo: QueryMemo,
+    query_limiter: QueryLimiter,
+    path_limits: PathLimits,
+    flush_state: FlushState,
+    frozen_subtrees: FrozenSubtrees,
+    access_policy: Option<Box<dyn AccessPolicy>>,
+    slow_operation_threshold: Option<Duration>,
+    /// Number of open transactions, see [`OpenTransaction`]
+    open_transactions: Mutex<usize>,
+}
+
+/// Source of IDs of transactions, see [`Transaction::id`]
+#[cfg(feature = "full")]
+static NEXT_TRANSACTION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Registration of an open transaction, removed when the transaction is
+/// dropped, which committing or preparing it does. Changes recorded for the
+/// transaction and not sent or prepared by then are discarded.
+#[cfg(feature = "full")]
+struct OpenTransaction<'db> {
+    db: &'db GroveDb,
+    id: u64,
 }
 
-pub type Transaction<'db> = <RocksDbStorage as Storage<'db>>::Transaction;
+#[cfg(feature = "full")]
+impl<'db> OpenTransaction<'db> {
+    /// Registers a transaction about to be started, waits for garbage
+    /// collection to finish if it is running
+    fn register(db: &'db GroveDb, id: u64) -> Self {
+        *db.open_transactions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) += 1;
+        Self { db, id }
+    }
+}
+
+#[cfg(feature = "full")]
+impl Drop for OpenTransaction<'_> {
+    fn drop(&mut self) {
+        self.db.discard_transaction_changes(self.id);
+        *self
+            .db
+            .open_transactions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) -= 1;
+    }
+}
+
+/// State of a transaction to restore on rollback to a savepoint, see
+/// [`GroveDb::set_savepoint`]
+#[cfg(feature = "full")]
+struct Savepoint {
+    /// Number of changes recorded for the transaction
+    key_changes: usize,
+    written_subtrees: WrittenSubtrees,
+}
+
+/// Storage transaction with an ID, state kept by GroveDB for an open
+/// transaction is looked up by its ID
+#[cfg(feature = "full")]
+pub struct Transaction<'db> {
+    inner: <BoxedStorage as Storage<'db>>::Transaction,
+    id: u64,
+    /// Storage write version read before the transaction was started, see
+    /// [`RocksDbStorage::write_version`]
+    write_version: u64,
+    written_subtrees: Mutex<WrittenSubtrees>,
+    savepoints: Mutex<Vec<Savepoint>>,
+    _open: OpenTransaction<'db>,
+}
+
+#[cfg(feature = "full")]
+impl<'db> Transaction<'db> {
+    /// Registers and starts a transaction with `start`
+    fn new<F>(db: &'db GroveDb, start: F) -> Self
+    where
+        F: FnOnce() -> <BoxedStorage as Storage<'db>>::Transaction,
+    {
+        let id = NEXT_TRANSACTION_ID.fetch_add(1, Ordering::Relaxed);
+        let open = OpenTransaction::register(db, id);
+        let write_version = db.write_version();
+        Self {
+            inner: start(),
+            id,
+            write_version,
+            written_subtrees: Mutex::default(),
+            savepoints: Mutex::default(),
+            _open: open,
+        }
+    }
+
+    /// Returns the ID of the transaction, unique among transactions started
+    /// by the process and never reused. It doesn't change as the transaction
+    /// is moved, unlike its address.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the RocksDB transaction for features specific to RocksDB, see
+    /// [`GroveDb::open_with_storage`]
+    pub(crate) fn rocksdb(&self) -> Result<&<RocksDbStorage as Storage<'_>>::Transaction, Error> {
+        self.inner
+            .rocksdb()
+            .ok_or(Error::NotSupported("transaction is not a RocksDB one"))
+    }
+
+    /// Records a subtree written in the transaction, so only subscribers of
+    /// related subtrees are checked on commit
+    fn record_written_subtree(&self, path: Vec<Vec<u8>>) {
+        self.written_subtrees
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(path);
+    }
+
+    /// Takes subtrees written in the transaction so far
+    pub(crate) fn take_written_subtrees(&self) -> WrittenSubtrees {
+        std::mem::take(
+            &mut *self
+                .written_subtrees
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        )
+    }
+}
+
+#[cfg(feature = "full")]
+impl<'db> Deref for Transaction<'db> {
+    type Target = <BoxedStorage as Storage<'db>>::Transaction;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+#[cfg(feature = "full")]
 pub type TransactionArg<'db, 'a> = Option<&'a Transaction<'db>>;
 
+#[cfg(feature = "full")]
 impl GroveDb {
+    /// Opens GroveDB at the path, creating it if missing. Only the storage is
+    /// opened here: subtrees, including root tree leaves, are opened from
+    /// storage on first access by each operation, so open time doesn't depend
+    /// on the number of subtrees. Data of an older storage format, such as
+    /// root tree leaves kept outside of the root tree Merk, is migrated.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         let db = RocksDbStorage::default_rocksdb_with_path(path)?;
-        Ok(GroveDb { db })
+        Self::open_storage(Box::new(db))
     }
 
-    // TODO: Checkpoints are currently not implemented for the transactional DB
-    // pub fn checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<GroveDb, Error> {
-    //     // let snapshot = self.db.transaction().snapshot();
-    //
-    //     storage::rocksdb_storage::Checkpoint::new(&self.db)
-    //         .and_then(|x| x.create_checkpoint(&path))
-    //         .map_err(PrefixedRocksDbStorageError::RocksDbError)?;
-    //     GroveDb::open(path)
-    // }
+    /// Opens GroveDB at the path like [`GroveDb::open`], but with a block
+    /// cache which may be shared with other GroveDB instances to bound their
+    /// total memory usage instead of allocating a cache per instance, see
+    /// [`rocksdb_storage::Cache`].
+    pub fn open_with_cache<P: AsRef<Path>>(
+        path: P,
+        cache: &rocksdb_storage::Cache,
+    ) -> Result<Self, Error> {
+        let db = RocksDbStorage::default_rocksdb_with_path_and_cache(path, cache)?;
+        Self::open_storage(Box::new(db))
+    }
 
-    /// Returns root hash of GroveDb.
-    /// Will be `None` if GroveDb is empty.
-    pub fn root_hash(&self, transaction: TransactionArg) -> Result<Option<[u8; 32]>, Error> {
-        Ok(Self::get_root_tree_internal(&self.db, transaction)?.root())
+    /// Opens GroveDB at the path like [`GroveDb::open`] with options
+    pub fn open_with_opts<P: AsRef<Path>>(path: P, opts: &GroveDbOpts) -> Result<Self, Error> {
+        let db = RocksDbStorage::default_rocksdb_with_path_and_tuning(
+            path,
+            &rocksdb_storage::RocksDbTuning {
+                rate_limit_bytes_per_sec: opts.rate_limit_bytes_per_sec,
+                enable_statistics: opts.enable_statistics,
+                ..Default::default()
+            },
+        )?;
+        Self::open_storage(Box::new(db))
     }
 
-    fn get_root_leaf_keys_internal<'db, 'ctx, S>(
-        meta_storage: &S,
-    ) -> Result<BTreeMap<Vec<u8>, usize>, Error>
-    where
-        S: StorageContext<'db, 'ctx>,
-        Error: From<<S as StorageContext<'db, 'ctx>>::Error>,
-    {
-        let root_leaf_keys: BTreeMap<Vec<u8>, usize> = if let Some(root_leaf_keys_serialized) =
-            meta_storage.get_meta(ROOT_LEAFS_SERIALIZED_KEY)?
-        {
-            bincode::deserialize(&root_leaf_keys_serialized).map_err(|_| {
-                Error::CorruptedData(String::from("unable to deserialize root leafs"))
-            })?
-        } else {
-            BTreeMap::new()
-        };
-        Ok(root_leaf_keys)
+    /// Opens GroveDB at the path like [`GroveDb::open`] replaying storage
+    /// write-ahead log in the recovery `mode`, to salvage a node after an
+    /// unclean shutdown. Once opened and before anything is written, hashes
+    /// of all Merk nodes are recomputed and checked against the stored ones
+    /// and every subtree's root hash against the hash stored in its parent,
+    /// so data lost by a lenient mode is reported as [`Error::CorruptedData`]
+    /// instead of showing up later.
+    pub fn open_with_recovery<P: AsRef<Path>>(
+        path: P,
+        mode: rocksdb_storage::WalRecoveryMode,
+    ) -> Result<Self, Error> {
+        let db = RocksDbStorage::default_rocksdb_with_path_and_tuning(
+            path,
+            &rocksdb_storage::RocksDbTuning {
+                wal_recovery_mode: Some(mode),
+                ..Default::default()
+            },
+        )?;
+        let db = Self::new(Box::new(db));
+        db.verify_subtree_hashes(&[], None)?;
+        db.check_version(true)?;
+        Ok(db)
     }
 
-    fn get_root_leaf_keys(
-        &self,
-        transaction: TransactionArg,
-    ) -> Result<BTreeMap<Vec<u8>, usize>, Error> {
-        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
-            Self::get_root_leaf_keys_internal(&meta_storage)
-        })
+    /// Opens GroveDB over a storage backend chosen by the caller, such as a
+    /// decorator wrapping RocksDB storage to record or inject faults into its
+    /// operations. Features specific to RocksDB, such as checkpoints, backups
+    /// and two-phase commits, fail with [`Error::NotSupported`] unless the
+    /// backend is RocksDB storage itself.
+    pub fn open_with_storage(storage: BoxedStorage) -> Result<Self, Error> {
+        Self::open_storage(storage)
     }
 
-    fn get_root_tree_internal(
-        db: &RocksDbStorage,
-        transaction: TransactionArg,
-    ) -> Result<MerkleTree<Sha256>, Error> {
-        let root_leaf_keys = meta_storage_context_optional_tx!(db, transaction, meta_storage, {
-            Self::get_root_leaf_keys_internal(&meta_storage)?
-        });
+    /// Opens GroveDB at the path like [`GroveDb::open`] logging every storage
+    /// operation, including transaction boundaries, to a new file at
+    /// `log_path`, see [`RecordingStorage`]. Buffered records are written to
+    /// the file on [`GroveDb::flush`].
+    pub fn open_with_recording<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        log_path: Q,
+    ) -> Result<Self, Error> {
+        let db = RocksDbStorage::default_rocksdb_with_path(path)?;
+        Self::open_storage(Box::new(RecordingStorage::new(Box::new(db), log_path)?))
+    }
+
+    /// Opens GroveDB over a recording made by GroveDB opened with
+    /// [`GroveDb::open_with_recording`] to reproduce the recorded execution.
+    /// Operations must be repeated in the recorded order, as reads of data the
+    /// recording doesn't have fail, and writes are kept in memory only.
+    pub fn open_replay<P: AsRef<Path>>(log_path: P) -> Result<Self, Error> {
+        Self::open_storage(Box::new(ReplayStorage::open(log_path)?))
+    }
+
+    /// Opens GroveDB at the path over sled storage instead of RocksDB, for
+    /// platforms where building RocksDB is impractical. Features specific to
+    /// RocksDB fail with [`Error::NotSupported`] like with
+    /// [`GroveDb::open_with_storage`].
+    #[cfg(feature = "sled-backend")]
+    pub fn open_sled<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let db = storage::sled_storage::SledStorage::default_sled_with_path(path)
+            .map_err(|e| Error::BackendError(storage::dyn_storage::DynStorageError::new(e)))?;
+        Self::open_storage(Box::new(db))
+    }
 
-        let mut leaf_hashes: Vec<[u8; 32]> = vec![[0; 32]; root_leaf_keys.len()];
-        for (subtree_path, root_leaf_idx) in root_leaf_keys {
-            merk_optional_tx!(db, [subtree_path.as_slice()], transaction, subtree, {
-                leaf_hashes[root_leaf_idx] = subtree.root_hash();
-            });
+    fn open_storage(db: BoxedStorage) -> Result<Self, Error> {
+        let db = Self::new(db);
+        db.check_version(true)?;
+        Ok(db)
+    }
+
+    /// Makes GroveDB over storage with default settings, without checking the
+    /// storage format version
+    pub(crate) fn new(db: BoxedStorage) -> Self {
+        GroveDb {
+            db,
+            index_delegates: Vec::new(),
+            referential_integrity: ReferentialIntegrity::default(),
+            quarantine_mode: false,
+            subtree_locks: SubtreeLocks::default(),
+            maintenance_events: Vec::new(),
+            archive: None,
+            transaction_scopes: TransactionScopes::default(),
+            subscriptions: Subscriptions::default(),
+            query_memo: QueryMemo::default(),
+            query_limiter: QueryLimiter::default(),
+            path_limits: PathLimits::default(),
+            flush_state: FlushState::default(),
+            frozen_subtrees: FrozenSubtrees::default(),
+            access_policy: None,
+            slow_operation_threshold: None,
+            open_transactions: Mutex::default(),
         }
-        Ok(MerkleTree::<Sha256>::from_leaves(&leaf_hashes))
     }
 
-    pub fn get_root_tree(&self, transaction: TransactionArg) -> Result<MerkleTree<Sha256>, Error> {
-        Self::get_root_tree_internal(&self.db, transaction)
+    /// Creates a checkpoint of the current state at `path`, which must not
+    /// exist, hard-linking table files of the database where possible. With
+    /// `sync` files of the checkpoint and its directory are fsynced before
+    /// returning. With `verify` the checkpoint is reopened, its subtree hashes
+    /// are checked and its root hash is compared with the one of this
+    /// GroveDB, so no writes should be made while a verified checkpoint is
+    /// being created.
+    /// The checkpoint can be opened with [`GroveDbReader`] or
+    /// [`GroveDb::open`].
+    pub fn checkpoint<P: AsRef<Path>>(
+        &self,
+        path: P,
+        sync: bool,
+        verify: bool,
+    ) -> Result<(), Error> {
+        let root_hash = self.root_hash(None)?;
+        self.rocksdb()?.checkpoint(&path)?;
+        if sync {
+            for entry in std::fs::read_dir(&path)? {
+                std::fs::File::open(entry?.path())?.sync_all()?;
+            }
+            std::fs::File::open(&path)?.sync_all()?;
+        }
+        if verify && GroveDbReader::open(&path)?.verify()? != root_hash {
+            return Err(Error::CorruptedData(String::from(
+                "checkpoint root hash mismatch",
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reports memory used by the storage to diagnose RSS growth. Subtrees
+    /// are opened on demand and not cached, so storage is the only
+    /// significant memory consumer of GroveDB.
+    pub fn memory_usage(&self) -> Result<rocksdb_storage::MemoryUsage, Error> {
+        Ok(self.rocksdb()?.memory_usage()?)
+    }
+
+    /// Returns RocksDB storage for features specific to RocksDB, see
+    /// [`GroveDb::open_with_storage`]
+    pub(crate) fn rocksdb(&self) -> Result<&RocksDbStorage, Error> {
+        self.db
+            .rocksdb()
+            .ok_or(Error::NotSupported("storage backend is not RocksDB"))
     }
 
-    /// Method to propagate updated subtree root hashes up to GroveDB root
+    /// Returns the storage write version, which is always `0` unless the
+    /// storage backend is RocksDB, see [`RocksDbStorage::write_version`]
+    fn write_version(&self) -> u64 {
+        self.db.rocksdb().map_or(0, RocksDbStorage::write_version)
+    }
+
+    /// Returns the sequence number of the latest write to storage, which is
+    /// always `0` unless the storage backend is RocksDB
+    pub(crate) fn latest_sequence_number(&self) -> u64 {
+        self.db
+            .rocksdb()
+            .map_or(0, RocksDbStorage::latest_sequence_number)
+    }
+
+    /// Returns root hash of GroveDb.
+    /// Will be `None` if GroveDb is empty.
+    pub fn root_hash(&self, transaction: TransactionArg) -> Result<Option<[u8; 32]>, Error> {
+        merk_optional_tx!(self.db, std::iter::empty(), transaction, root_tree, {
+            if root_tree.is_empty_tree() {
+                Ok(None)
+            } else {
+                Ok(Some(root_tree.root_hash()))
+            }
+        })
+    }
+
+    /// Method to propagate updated subtree root hashes up to GroveDB root, or
+    /// up to a scope subtree for a scoped transaction
     fn propagate_changes<'p, P>(&self, path: P, transaction: TransactionArg) -> Result<(), Error>
     where
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
     {
-        // Go up until only one element in path, which means a key of a root tree
+        // Go up until the root tree, which has an empty path
         let mut path_iter = path.into_iter();
+        let stop_height = self
+            .check_transaction_scope(path_iter.clone(), transaction)?
+            .unwrap_or(0);
+        let written_path: Vec<Vec<u8>> = path_iter.clone().map(|x| x.to_vec()).collect();
 
-        while path_iter.len() > 1 {
+        while path_iter.len() > stop_height {
             if let Some(tx) = transaction {
                 let subtree_storage = self
                     .db
@@ -230,20 +906,22 @@ impl GroveDb {
             }
         }
 
+        match transaction {
+            Some(tx) => tx.record_written_subtree(written_path),
+            None => {
+                self.notify_subscribers(Some(&WrittenSubtrees::from([written_path])));
+                self.flush_after_commit();
+            }
+        }
         Ok(())
     }
 
-    pub fn flush(&self) -> Result<(), Error> {
-        Ok(self.db.flush()?)
-    }
-
     /// Starts database transaction. Please note that you have to start
     /// underlying storage transaction manually.
     ///
     /// ## Examples:
     /// ```
     /// # use grovedb::{Element, Error, GroveDb};
-    /// # use rs_merkle::{MerkleTree, MerkleProof, algorithms::Sha256, Hasher, utils};
     /// # use std::convert::TryFrom;
     /// # use tempfile::TempDir;
     /// #
@@ -276,19 +954,109 @@ impl GroveDb {
     /// # }
     /// ```
     pub fn start_transaction(&self) -> Transaction {
-        self.db.start_transaction()
+        Transaction::new(self, || self.db.start_transaction())
+    }
+
+    /// Starts a transaction reading from a snapshot taken at its start, see
+    /// [`DynStorage::start_snapshot_transaction`]
+    pub(crate) fn start_snapshot_transaction(&self) -> Transaction {
+        Transaction::new(self, || DynStorage::start_snapshot_transaction(&*self.db))
     }
 
     /// Commits previously started db transaction. For more details on the
     /// transaction usage, please check [`GroveDb::start_transaction`]
     pub fn commit_transaction(&self, transaction: Transaction) -> Result<(), Error> {
-        Ok(self.db.commit_transaction(transaction)?)
+        let id = transaction.id();
+        let written_subtrees = transaction.take_written_subtrees();
+        if let Err(e) = self.db.commit_transaction(transaction.inner) {
+            self.discard_transaction_changes(id);
+            return Err(e.into());
+        }
+        self.notify_subscribers(Some(&written_subtrees));
+        self.send_transaction_changes(id);
+        self.flush_after_commit();
+        Ok(())
+    }
+
+    /// Commits a transaction like [`GroveDb::commit_transaction`] after
+    /// calling `hook` with the root hash the commit results in. A failing hook
+    /// rolls the transaction back and its error is returned, so writes to an
+    /// external store made by the hook are coupled with the commit. If the
+    /// commit itself fails after the hook succeeded, e.g. on a conflict, the
+    /// commit error is returned for the caller to revert the external writes.
+    /// Any error returned means nothing was committed: a failure of a flush
+    /// made after the commit by the flush policy is only reported to storage
+    /// event listeners.
+    pub fn commit_transaction_with_hook<F>(
+        &self,
+        transaction: Transaction,
+        hook: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(Option<[u8; 32]>) -> Result<(), Error>,
+    {
+        let root_hash = self.root_hash(Some(&transaction))?;
+        if let Err(e) = hook(root_hash) {
+            self.rollback_transaction(&transaction)?;
+            return Err(e);
+        }
+        self.commit_transaction(transaction)
     }
 
     /// Rollbacks previously started db transaction to initial state.
+    /// Subtrees are opened from storage on every operation and are not cached,
+    /// so no subtree state outlives a rollback.
     /// For more details on the transaction usage, please check
     /// [`GroveDb::start_transaction`]
     pub fn rollback_transaction(&self, transaction: &Transaction) -> Result<(), Error> {
-        Ok(self.db.rollback_transaction(transaction)?)
+        self.db.rollback_transaction(transaction)?;
+        self.discard_transaction_changes(transaction.id());
+        transaction.take_written_subtrees();
+        // Rolling back clears savepoints of the storage transaction
+        transaction
+            .savepoints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        Ok(())
+    }
+
+    /// Sets a savepoint of the transaction, changes recorded for the
+    /// transaction and subtrees it wrote are restored with its writes on
+    /// rollback to the savepoint. Savepoints are specific to RocksDB.
+    pub(crate) fn set_savepoint(&self, transaction: &Transaction) -> Result<(), Error> {
+        transaction.rocksdb()?.set_savepoint();
+        let savepoint = Savepoint {
+            key_changes: self.transaction_changes_count(transaction.id()),
+            written_subtrees: transaction
+                .written_subtrees
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        };
+        transaction
+            .savepoints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(savepoint);
+        Ok(())
+    }
+
+    /// Rolls the transaction back to its latest savepoint and removes it
+    pub(crate) fn rollback_to_savepoint(&self, transaction: &Transaction) -> Result<(), Error> {
+        transaction.rocksdb()?.rollback_to_savepoint()?;
+        let savepoint = transaction
+            .savepoints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop();
+        if let Some(savepoint) = savepoint {
+            self.truncate_transaction_changes(transaction.id(), savepoint.key_changes);
+            *transaction
+                .written_subtrees
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()) = savepoint.written_subtrees;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,98 @@
+//! Module for path and query nesting limits.
+//! Paths and subqueries come from untrusted clients, and their processing
+//! recurses into subtrees, so their depth and segment sizes are limited with
+//! clear errors before any work is done.
+
+#[cfg(feature = "full")]
+use crate::GroveDb;
+use crate::{Error, PathQuery, Query};
+
+/// Limits of paths and queries, see [`GroveDb::set_path_limits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathLimits {
+    /// Maximum number of segments of a path, including the key of an
+    /// inserted element and keys of subqueries
+    pub max_path_depth: usize,
+    /// Maximum length of a path segment or a key in bytes
+    pub max_segment_bytes: usize,
+    /// Maximum number of nested subquery levels
+    pub max_subquery_depth: usize,
+}
+
+impl Default for PathLimits {
+    fn default() -> Self {
+        PathLimits {
+            max_path_depth: 64,
+            // Proofs encode keys with a one byte length
+            max_segment_bytes: 255,
+            max_subquery_depth: 16,
+        }
+    }
+}
+
+impl PathLimits {
+    pub(crate) fn check_path<'p, P>(&self, path: P, key: Option<&[u8]>) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut depth = 0;
+        for segment in path.into_iter().chain(key) {
+            depth += 1;
+            if depth > self.max_path_depth {
+                return Err(Error::InvalidPath("path depth limit exceeded"));
+            }
+            if segment.len() > self.max_segment_bytes {
+                return Err(Error::InvalidPath("path segment size limit exceeded"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the path of the query and nesting of its subqueries. Queries
+    /// are traversed without recursion, so deeply nested ones are rejected
+    /// instead of overflowing the stack.
+    pub(crate) fn check_path_query(&self, path_query: &PathQuery) -> Result<(), Error> {
+        self.check_path(path_query.path.iter().map(|x| x.as_slice()), None)?;
+        let mut queries: Vec<(&Query, usize, usize)> =
+            vec![(&path_query.query.query, 0, path_query.path.len())];
+        while let Some((query, subquery_depth, path_depth)) = queries.pop() {
+            let branches = std::iter::once(&query.default_subquery_branch)
+                .chain(query.conditional_subquery_branches.values());
+            for branch in branches {
+                let mut branch_path_depth = path_depth + 1;
+                if let Some(subquery_key) = &branch.subquery_key {
+                    if subquery_key.len() > self.max_segment_bytes {
+                        return Err(Error::InvalidQuery("subquery key size limit exceeded"));
+                    }
+                    branch_path_depth += 1;
+                }
+                if branch.subquery_key.is_none() && branch.subquery.is_none() {
+                    continue;
+                }
+                if branch_path_depth > self.max_path_depth {
+                    return Err(Error::InvalidQuery("path depth limit exceeded"));
+                }
+                if let Some(subquery) = &branch.subquery {
+                    if subquery_depth + 1 > self.max_subquery_depth {
+                        return Err(Error::InvalidQuery("subquery depth limit exceeded"));
+                    }
+                    queries.push((subquery, subquery_depth + 1, branch_path_depth));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "full")]
+impl GroveDb {
+    /// Sets limits of paths and queries checked on insertion and querying
+    pub fn set_path_limits(&mut self, limits: PathLimits) {
+        self.path_limits = limits;
+    }
+
+    /// Returns limits of paths and queries
+    pub fn path_limits(&self) -> PathLimits {
+        self.path_limits
+    }
+}
@@ -0,0 +1,134 @@
+//! Module for background storage maintenance.
+//! A maintenance thread watches write activity and, once GroveDB has been
+//! idle for a configured period, flushes memtables and optionally compacts
+//! storage, so this IO doesn't happen in the middle of block processing.
+
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::GroveDb;
+
+/// Configuration of background maintenance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaintenancePolicy {
+    /// GroveDB is considered idle if there were no writes during this period,
+    /// write activity is checked once per period
+    pub idle_period: Duration,
+    /// Whether storage is compacted after flush in an idle window
+    pub compact: bool,
+}
+
+#[derive(Default)]
+struct MaintenanceState {
+    paused: bool,
+    stopped: bool,
+    runs: u64,
+}
+
+/// Handle to control a maintenance thread started by
+/// [`GroveDb::start_maintenance`], the thread is stopped on drop
+pub struct MaintenanceHandle {
+    state: Arc<(Mutex<MaintenanceState>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MaintenanceHandle {
+    /// Pauses maintenance, e.g. during block processing. A run in progress is
+    /// not interrupted.
+    pub fn pause(&self) {
+        self.state
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .paused = true;
+    }
+
+    /// Resumes paused maintenance
+    pub fn resume(&self) {
+        self.state
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .paused = false;
+        self.state.1.notify_all();
+    }
+
+    /// Returns the number of completed maintenance runs
+    pub fn runs(&self) -> u64 {
+        self.state.0.lock().unwrap_or_else(|e| e.into_inner()).runs
+    }
+
+    /// Stops maintenance and waits for the thread to finish
+    pub fn stop(mut self) {
+        self.stop_thread();
+    }
+
+    fn stop_thread(&mut self) {
+        self.state
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .stopped = true;
+        self.state.1.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MaintenanceHandle {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+impl GroveDb {
+    /// Starts a background thread which flushes memtables and, if configured,
+    /// compacts storage each time GroveDB becomes idle per `policy`. Nothing
+    /// is done again until there are new writes.
+    pub fn start_maintenance(self: &Arc<Self>, policy: MaintenancePolicy) -> MaintenanceHandle {
+        let state = Arc::new((Mutex::new(MaintenanceState::default()), Condvar::new()));
+        let thread_state = state.clone();
+        let db = self.clone();
+        let thread = std::thread::spawn(move || {
+            let (lock, cvar) = &*thread_state;
+            let mut last_seen = db.latest_sequence_number();
+            let mut last_maintained = None;
+            let mut guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+            loop {
+                guard = cvar
+                    .wait_timeout(guard, policy.idle_period)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+                if guard.stopped {
+                    return;
+                }
+                let current = db.latest_sequence_number();
+                let idle = current == last_seen;
+                last_seen = current;
+                if guard.paused || !idle || last_maintained == Some(current) {
+                    continue;
+                }
+                drop(guard);
+                let flushed = db.flush().is_ok();
+                if flushed {
+                    if policy.compact {
+                        db.compact();
+                    }
+                    last_maintained = Some(db.latest_sequence_number());
+                }
+                guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+                if flushed {
+                    guard.runs += 1;
+                }
+            }
+        });
+        MaintenanceHandle {
+            state,
+            thread: Some(thread),
+        }
+    }
+}
@@ -1,6 +1,14 @@
+pub(crate) mod audit;
 pub(crate) mod aux;
+pub(crate) mod balance;
+pub(crate) mod batch;
 pub(crate) mod delete;
 pub(crate) mod get;
 pub(crate) mod insert;
 pub(crate) mod is_empty_tree;
-// pub(crate) mod proof;
+pub(crate) mod iter;
+pub(crate) mod list;
+pub(crate) mod nodes;
+pub(crate) mod proof;
+pub(crate) mod prune;
+pub(crate) mod swap;
@@ -0,0 +1,139 @@
+use std::{collections::HashMap, io::Write};
+
+use merk::tree::{kv_hash, value_hash, Tree};
+use storage::{RawIterator, StorageContext};
+
+use crate::{
+    util::{merk_optional_tx, storage_context_optional_tx},
+    Element, Error, GroveDb, TransactionArg,
+};
+
+impl GroveDb {
+    /// Writes a record for every Merk node of the whole grove, one per line:
+    /// subtree path segments joined with `/`, key, value hash and node hash,
+    /// all hex encoded and separated by spaces. Subtrees are visited depth
+    /// first and nodes of a subtree are written in key order before its
+    /// nested subtrees, so two nodes with the same data produce the same
+    /// stream and the first differing line points to the divergence. The
+    /// node hash of the root tree's root node is GroveDB root hash. Returns
+    /// the number of records written.
+    pub fn audit_stream<W: Write>(
+        &self,
+        writer: &mut W,
+        transaction: TransactionArg,
+    ) -> Result<usize, Error> {
+        let count = self.audit_subtree(&[], writer, transaction)?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    fn audit_subtree<W: Write>(
+        &self,
+        path: &[Vec<u8>],
+        writer: &mut W,
+        transaction: TransactionArg,
+    ) -> Result<usize, Error> {
+        let path_hex = path.iter().map(hex::encode).collect::<Vec<_>>().join("/");
+        let mut count = 0;
+        let mut child_subtrees = Vec::new();
+        let path_iter = path.iter().map(|x| x.as_slice());
+        storage_context_optional_tx!(self.db, path_iter, transaction, storage, {
+            let mut raw_iter = storage.raw_iter();
+            raw_iter.seek_to_first();
+            while let Some(bytes) = raw_iter.value() {
+                let node =
+                    Tree::decode_raw(bytes).map_err(|e| Error::CorruptedData(e.to_string()))?;
+                writeln!(
+                    writer,
+                    "{} {} {} {}",
+                    path_hex,
+                    hex::encode(node.key()),
+                    hex::encode(value_hash(node.value())),
+                    hex::encode(node.hash()),
+                )?;
+                count += 1;
+                let element: Element = bincode::deserialize(node.value()).map_err(|_| {
+                    Error::CorruptedData(String::from("unable to deserialize element"))
+                })?;
+                if let Element::Tree(_) = element {
+                    child_subtrees.push(node.key().to_vec());
+                }
+                raw_iter.next();
+            }
+            raw_iter.status()?;
+        });
+        for key in child_subtrees {
+            let mut child_path = path.to_vec();
+            child_path.push(key);
+            count += self.audit_subtree(&child_path, writer, transaction)?;
+        }
+        Ok(count)
+    }
+
+    /// Recomputes hashes of all Merk nodes of nested subtrees recursively and
+    /// checks them against the hashes stored with the nodes, in links to the
+    /// nodes and in parents' tree elements, so corrupted nodes are detected
+    /// and not only mismatched subtree roots
+    pub(crate) fn verify_subtree_hashes(
+        &self,
+        path: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        let corrupted = |what: &str, path: &[Vec<u8>]| {
+            Error::CorruptedData(format!(
+                "{} at {}",
+                what,
+                path.iter().map(hex::encode).collect::<Vec<_>>().join("/")
+            ))
+        };
+        let mut child_subtrees = Vec::new();
+        let mut node_hashes = HashMap::new();
+        let mut links = Vec::new();
+        let path_iter = path.iter().map(|x| x.as_slice());
+        storage_context_optional_tx!(self.db, path_iter, transaction, storage, {
+            let mut raw_iter = storage.raw_iter();
+            raw_iter.seek_to_first();
+            while let Some((key, bytes)) = raw_iter.key().zip(raw_iter.value()) {
+                let node =
+                    Tree::decode_raw(bytes).map_err(|e| Error::CorruptedData(e.to_string()))?;
+                if kv_hash(key, node.value()) != *node.kv_hash() {
+                    return Err(corrupted("node hash mismatch", path));
+                }
+                for link in [node.link(true), node.link(false)].into_iter().flatten() {
+                    links.push((link.key().to_vec(), *link.hash()));
+                }
+                let element: Element = bincode::deserialize(node.value()).map_err(|_| {
+                    Error::CorruptedData(String::from("unable to deserialize element"))
+                })?;
+                if let Element::Tree(hash) = element {
+                    child_subtrees.push((key.to_vec(), hash));
+                }
+                node_hashes.insert(key.to_vec(), node.hash());
+                raw_iter.next();
+            }
+            raw_iter.status()?;
+        });
+        for (key, hash) in links {
+            if node_hashes.get(&key) != Some(&hash) {
+                return Err(corrupted("child node hash mismatch", path));
+            }
+        }
+        for (key, hash) in child_subtrees {
+            let mut child_path = path.to_vec();
+            child_path.push(key);
+            let child_path_iter = child_path.iter().map(|x| x.as_slice());
+            // Cold subtrees are verified against their hash on fetch
+            if self.cold_subtree_hash(child_path_iter.clone())? == Some(hash) {
+                continue;
+            }
+            let child_hash = merk_optional_tx!(self.db, child_path_iter, transaction, subtree, {
+                subtree.root_hash()
+            });
+            if child_hash != hash {
+                return Err(corrupted("subtree hash mismatch", &child_path));
+            }
+            self.verify_subtree_hashes(&child_path, transaction)?;
+        }
+        Ok(())
+    }
+}
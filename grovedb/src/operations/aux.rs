@@ -1,6 +1,36 @@
-use storage::StorageContext;
+use storage::{Batch, RawIterator, StorageContext};
 
-use crate::{util::meta_storage_context_optional_tx, Error, GroveDb, TransactionArg};
+use crate::{
+    util::meta_storage_context_optional_tx, CostMeter, Error, GroveDb, MutationKind, TransactionArg,
+};
+
+/// An auxiliary data mutation to be applied as a part of an aux batch
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuxOp {
+    Put { key: Vec<u8>, value: Vec<u8> },
+    Delete { key: Vec<u8> },
+}
+
+/// Iterator over auxiliary data entries with keys starting with a prefix
+struct AuxPrefixIterator<I: RawIterator> {
+    raw_iter: I,
+    prefix: Vec<u8>,
+}
+
+impl<I: RawIterator> Iterator for AuxPrefixIterator<I> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self
+            .raw_iter
+            .key()
+            .zip(self.raw_iter.value())
+            .filter(|(key, _)| key.starts_with(&self.prefix))
+            .map(|(key, value)| (key.to_vec(), value.to_vec()))?;
+        self.raw_iter.next();
+        Some(entry)
+    }
+}
 
 impl GroveDb {
     pub fn put_aux<K: AsRef<[u8]>>(
@@ -9,6 +39,33 @@ impl GroveDb {
         value: &[u8],
         transaction: TransactionArg,
     ) -> Result<(), Error> {
+        self.put_aux_internal(key, value, None, transaction)
+    }
+
+    /// Puts auxiliary data like [`GroveDb::put_aux`] accounting the written
+    /// key and value to the meter. Nothing is written if the meter's cost
+    /// limit is exceeded.
+    pub fn put_aux_with_cost_meter<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        value: &[u8],
+        cost_meter: &CostMeter,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        self.put_aux_internal(key, value, Some(cost_meter), transaction)
+    }
+
+    fn put_aux_internal<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        value: &[u8],
+        cost_meter: Option<&CostMeter>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        self.check_access(std::iter::empty(), key.as_ref(), MutationKind::Aux)?;
+        if let Some(meter) = cost_meter {
+            meter.add_write(key.as_ref().len() + value.len())?;
+        }
         meta_storage_context_optional_tx!(self.db, transaction, aux_storage, {
             aux_storage.put_aux(key, value)?;
         });
@@ -20,12 +77,91 @@ impl GroveDb {
         key: K,
         transaction: TransactionArg,
     ) -> Result<(), Error> {
+        self.delete_aux_internal(key, None, transaction)
+    }
+
+    /// Deletes auxiliary data like [`GroveDb::delete_aux`] accounting the
+    /// written key to the meter
+    pub fn delete_aux_with_cost_meter<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        cost_meter: &CostMeter,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        self.delete_aux_internal(key, Some(cost_meter), transaction)
+    }
+
+    fn delete_aux_internal<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        cost_meter: Option<&CostMeter>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        self.check_access(std::iter::empty(), key.as_ref(), MutationKind::Aux)?;
+        if let Some(meter) = cost_meter {
+            meter.add_write(key.as_ref().len())?;
+        }
         meta_storage_context_optional_tx!(self.db, transaction, aux_storage, {
             aux_storage.delete_aux(key)?;
         });
         Ok(())
     }
 
+    /// Applies auxiliary data mutations in order within a single write, so
+    /// either all of them or none are applied. With a transaction they become
+    /// visible on its commit as the main data does.
+    pub fn apply_aux_batch(
+        &self,
+        ops: Vec<AuxOp>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        self.apply_aux_batch_internal(ops, None, transaction)
+    }
+
+    /// Applies an aux batch like [`GroveDb::apply_aux_batch`] accounting
+    /// writes of all its operations to the meter before any is applied
+    pub fn apply_aux_batch_with_cost_meter(
+        &self,
+        ops: Vec<AuxOp>,
+        cost_meter: &CostMeter,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        self.apply_aux_batch_internal(ops, Some(cost_meter), transaction)
+    }
+
+    fn apply_aux_batch_internal(
+        &self,
+        ops: Vec<AuxOp>,
+        cost_meter: Option<&CostMeter>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        for op in &ops {
+            let key = match op {
+                AuxOp::Put { key, .. } | AuxOp::Delete { key } => key,
+            };
+            self.check_access(std::iter::empty(), key, MutationKind::Aux)?;
+        }
+        if let Some(meter) = cost_meter {
+            for op in &ops {
+                match op {
+                    AuxOp::Put { key, value } => meter.add_write(key.len() + value.len())?,
+                    AuxOp::Delete { key } => meter.add_write(key.len())?,
+                }
+            }
+        }
+        meta_storage_context_optional_tx!(self.db, transaction, aux_storage, {
+            let mut batch = aux_storage.new_batch();
+            for op in ops {
+                match op {
+                    AuxOp::Put { key, value } => batch.put_aux(key, &value)?,
+                    AuxOp::Delete { key } => batch.delete_aux(key)?,
+                }
+            }
+            aux_storage.commit_batch(batch)?;
+        });
+        Ok(())
+    }
+
     pub fn get_aux<K: AsRef<[u8]>>(
         &self,
         key: K,
@@ -35,4 +171,39 @@ impl GroveDb {
             Ok(aux_storage.get_aux(key)?)
         })
     }
+
+    /// Gets auxiliary data like [`GroveDb::get_aux`] accounting a seek loading
+    /// the key and the value to the meter
+    pub fn get_aux_with_cost_meter<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        cost_meter: &CostMeter,
+        transaction: TransactionArg,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let key_len = key.as_ref().len();
+        let value = self.get_aux(key, transaction)?;
+        cost_meter.add_seek(key_len + value.as_ref().map_or(0, Vec::len))?;
+        Ok(value)
+    }
+
+    /// Returns auxiliary data entries with keys starting with `prefix` in key
+    /// order, an empty prefix iterates over all auxiliary data. Only entries
+    /// written with aux operations are returned, as GroveDB keeps its own
+    /// bookkeeping in roots and meta storage.
+    pub fn aux_iter<'db>(
+        &'db self,
+        prefix: &[u8],
+        transaction: TransactionArg<'db, 'db>,
+    ) -> Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'db> {
+        meta_storage_context_optional_tx!(self.db, transaction, aux_storage, {
+            let mut raw_iter = aux_storage.raw_iter_aux();
+            raw_iter.seek(prefix);
+            let iter: Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'db> =
+                Box::new(AuxPrefixIterator {
+                    raw_iter,
+                    prefix: prefix.to_vec(),
+                });
+            iter
+        })
+    }
 }
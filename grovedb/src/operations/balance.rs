@@ -0,0 +1,80 @@
+use merk::BalanceInfo;
+
+use crate::{util::merk_optional_tx, Error, GroveDb, TransactionArg};
+
+impl GroveDb {
+    /// Returns statistics on the shape of a subtree's Merk, which is useful to
+    /// find subtrees degenerated by pathological insertion orders.
+    pub fn subtree_balance_info<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<BalanceInfo, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone + DoubleEndedIterator + ExactSizeIterator,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        merk_optional_tx!(self.db, path_iter, transaction, subtree, {
+            subtree
+                .balance_info()
+                .map_err(|e| Error::CorruptedData(e.to_string()))
+        })
+    }
+
+    /// Returns the height of a subtree's Merk, zero for an empty subtree
+    pub fn subtree_height<'p, P>(&self, path: P, transaction: TransactionArg) -> Result<u8, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone + DoubleEndedIterator + ExactSizeIterator,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        merk_optional_tx!(self.db, path_iter, transaction, subtree, {
+            Ok(subtree.height())
+        })
+    }
+
+    /// Returns the largest number of Merk nodes a proof of an element in the
+    /// subtree at the path can contain on the way from GroveDB root, which is
+    /// the sum of heights of the subtree and all of its ancestors. Fee models
+    /// can use it instead of assuming a tree shape.
+    pub fn worst_case_proof_depth<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<usize, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone + DoubleEndedIterator + ExactSizeIterator,
+    {
+        let path: Vec<&[u8]> = path.into_iter().collect();
+        let mut depth = 0;
+        for len in 0..=path.len() {
+            depth += self.subtree_height(path[..len].iter().copied(), transaction)? as usize;
+        }
+        Ok(depth)
+    }
+
+    /// Rewrites a subtree's Merk into a balanced form. Elements stay the same,
+    /// but as Merk root hash depends on its structure the change is propagated
+    /// up to GroveDB root.
+    pub fn rebuild_subtree<'p, P>(&self, path: P, transaction: TransactionArg) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone + DoubleEndedIterator + ExactSizeIterator,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        if self.is_subtree_frozen(path_iter.clone())? {
+            return Err(Error::SubtreeFrozen);
+        }
+        merk_optional_tx!(self.db, path_iter.clone(), transaction, mut subtree, {
+            subtree
+                .rebuild()
+                .map_err(|e| Error::CorruptedData(format!("unable to rebuild subtree: {}", e)))?;
+        });
+        self.propagate_changes(path_iter, transaction)
+    }
+}
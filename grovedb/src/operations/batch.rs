@@ -0,0 +1,263 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use storage::{Storage, StorageContext};
+
+use crate::{util::meta_storage_context_optional_tx, Element, Error, GroveDb, TransactionArg};
+
+/// A prefix of keys in meta storage recording IDs of applied operations,
+/// followed by an operation ID. Records are kept until deleted with
+/// [`GroveDb::forget_applied_op`].
+const APPLIED_OP_ID_PREFIX: &[u8] = b"applied_op_id/";
+
+/// An operation to be applied as a part of a batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GroveDbOp {
+    Insert {
+        path: Vec<Vec<u8>>,
+        key: Vec<u8>,
+        element: Element,
+    },
+    Delete {
+        path: Vec<Vec<u8>>,
+        key: Vec<u8>,
+    },
+}
+
+/// Location of a reference to an inserted element, see
+/// [`GroveDbOp::insert_with_references`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceIndexSpec {
+    /// Path of the index subtree
+    pub path: Vec<Vec<u8>>,
+    pub key: Vec<u8>,
+}
+
+impl GroveDbOp {
+    /// Returns an insertion of the item followed by insertions of references
+    /// to it at every index location, so the item is never inserted without
+    /// its index entries. The element can't be a subtree and index locations
+    /// must be distinct and differ from the location of the element.
+    pub fn insert_with_references(
+        path: Vec<Vec<u8>>,
+        key: Vec<u8>,
+        element: Element,
+        index_specs: Vec<ReferenceIndexSpec>,
+    ) -> Result<Vec<GroveDbOp>, Error> {
+        if matches!(element, Element::Tree(_) | Element::PrunedTree(_)) {
+            return Err(Error::InvalidQuery("subtrees cannot be referenced"));
+        }
+        let mut reference_path = path.clone();
+        reference_path.push(key.clone());
+        let mut locations = HashSet::from([reference_path.clone()]);
+        let mut ops = Vec::with_capacity(index_specs.len() + 1);
+        ops.push(GroveDbOp::Insert { path, key, element });
+        for ReferenceIndexSpec { path, key } in index_specs {
+            let mut location = path.clone();
+            location.push(key.clone());
+            if !locations.insert(location) {
+                return Err(Error::InvalidQuery(
+                    "reference index locations must be distinct",
+                ));
+            }
+            ops.push(GroveDbOp::Insert {
+                path,
+                key,
+                element: Element::Reference(reference_path.clone()),
+            });
+        }
+        Ok(ops)
+    }
+}
+
+impl GroveDb {
+    /// Applies operations in order atomically, within the transaction or
+    /// without one in a transaction of its own. A failing operation fails the
+    /// batch and, without a transaction, nothing is applied.
+    pub fn apply_batch(
+        &self,
+        ops: Vec<GroveDbOp>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        match transaction {
+            Some(tx) => self.apply_ops(ops, tx),
+            None => {
+                let tx = self.start_transaction();
+                self.apply_ops(ops, &tx)?;
+                self.commit_transaction(tx)
+            }
+        }
+    }
+
+    fn apply_ops(&self, ops: Vec<GroveDbOp>, tx: &crate::Transaction) -> Result<(), Error> {
+        for op in ops {
+            self.apply_op(op, tx)?;
+        }
+        Ok(())
+    }
+
+    /// Applies operations in order, recording IDs of operations having one in
+    /// meta storage together with the operation. Operations with IDs
+    /// recorded before are skipped, so an external system replaying its
+    /// instructions after a crash doesn't apply them twice. Without a
+    /// transaction the batch is committed in one of its own. Returns the
+    /// number of skipped operations.
+    pub fn apply_batch_with_op_ids(
+        &self,
+        ops: Vec<(Option<Vec<u8>>, GroveDbOp)>,
+        transaction: TransactionArg,
+    ) -> Result<usize, Error> {
+        match transaction {
+            Some(tx) => self.apply_ops_with_ids(ops, tx),
+            None => {
+                let tx = self.start_transaction();
+                let skipped = self.apply_ops_with_ids(ops, &tx)?;
+                self.commit_transaction(tx)?;
+                Ok(skipped)
+            }
+        }
+    }
+
+    fn apply_ops_with_ids(
+        &self,
+        ops: Vec<(Option<Vec<u8>>, GroveDbOp)>,
+        tx: &crate::Transaction,
+    ) -> Result<usize, Error> {
+        let mut skipped = 0;
+        for (op_id, op) in ops {
+            if let Some(op_id) = &op_id {
+                if self.is_op_applied(op_id, Some(tx))? {
+                    skipped += 1;
+                    continue;
+                }
+            }
+            self.apply_op(op, tx)?;
+            if let Some(op_id) = op_id {
+                let tx_meta_storage = self
+                    .db
+                    .get_transactional_storage_context(std::iter::empty(), tx);
+                tx_meta_storage.put_meta(Self::applied_op_key(&op_id), &[])?;
+            }
+        }
+        Ok(skipped)
+    }
+
+    /// Checks whether an operation with the ID was applied by
+    /// [`GroveDb::apply_batch_with_op_ids`]
+    pub fn is_op_applied(&self, op_id: &[u8], transaction: TransactionArg) -> Result<bool, Error> {
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            Ok(meta_storage
+                .get_meta(Self::applied_op_key(op_id))?
+                .is_some())
+        })
+    }
+
+    /// Deletes the record of an applied operation ID, so an operation with
+    /// the ID is applied again
+    pub fn forget_applied_op(
+        &self,
+        op_id: &[u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            meta_storage.delete_meta(Self::applied_op_key(op_id))?;
+        });
+        Ok(())
+    }
+
+    fn applied_op_key(op_id: &[u8]) -> Vec<u8> {
+        [APPLIED_OP_ID_PREFIX, op_id].concat()
+    }
+
+    /// Returns the root hash the operations would produce if applied on top of
+    /// the transaction, or committed data if there is none, without changing
+    /// anything. Operations are applied within a transaction savepoint which
+    /// is rolled back afterwards, so block proposers can get the post-state
+    /// root hash before committing. Savepoints are specific to RocksDB, so
+    /// other backends fail with [`Error::NotSupported`].
+    pub fn dry_run_batch(
+        &self,
+        ops: Vec<GroveDbOp>,
+        transaction: TransactionArg,
+    ) -> Result<Option<[u8; 32]>, Error> {
+        match transaction {
+            Some(tx) => {
+                self.set_savepoint(tx)?;
+                let root_hash = self.apply_ops_root_hash(ops, tx);
+                self.rollback_to_savepoint(tx)?;
+                root_hash
+            }
+            // Changes are discarded as the transaction is dropped uncommitted
+            None => self.apply_ops_root_hash(ops, &self.start_transaction()),
+        }
+    }
+
+    /// Checks operations without changing anything, returning errors of
+    /// failing operations with their indices. Operations are applied in order
+    /// within transaction savepoints, so every check an application makes is
+    /// run and later operations see effects of earlier valid ones, e.g.
+    /// insertions into a subtree inserted by the batch. A failing operation is
+    /// rolled back alone and checking goes on, everything is rolled back
+    /// afterwards. Like [`GroveDb::dry_run_batch`] it needs RocksDB.
+    pub fn validate_batch(
+        &self,
+        ops: Vec<GroveDbOp>,
+        transaction: TransactionArg,
+    ) -> Result<Vec<(usize, Error)>, Error> {
+        match transaction {
+            Some(tx) => {
+                // Savepoints of applied operations are stacked on top of this
+                // one and rolling back pops a savepoint
+                self.set_savepoint(tx)?;
+                let (errors, applied) = self.validate_ops(ops, tx)?;
+                for _ in 0..=applied {
+                    self.rollback_to_savepoint(tx)?;
+                }
+                Ok(errors)
+            }
+            None => Ok(self.validate_ops(ops, &self.start_transaction())?.0),
+        }
+    }
+
+    /// Applies operations rolling back failing ones, returns their errors
+    /// and the number of applied operations
+    fn validate_ops(
+        &self,
+        ops: Vec<GroveDbOp>,
+        tx: &crate::Transaction,
+    ) -> Result<(Vec<(usize, Error)>, usize), Error> {
+        let mut errors = Vec::new();
+        let mut applied = 0;
+        for (idx, op) in ops.into_iter().enumerate() {
+            self.set_savepoint(tx)?;
+            match self.apply_op(op, tx) {
+                Ok(()) => applied += 1,
+                Err(e) => {
+                    self.rollback_to_savepoint(tx)?;
+                    errors.push((idx, e));
+                }
+            }
+        }
+        Ok((errors, applied))
+    }
+
+    fn apply_ops_root_hash(
+        &self,
+        ops: Vec<GroveDbOp>,
+        tx: &crate::Transaction,
+    ) -> Result<Option<[u8; 32]>, Error> {
+        self.apply_ops(ops, tx)?;
+        self.root_hash(Some(tx))
+    }
+
+    fn apply_op(&self, op: GroveDbOp, tx: &crate::Transaction) -> Result<(), Error> {
+        match op {
+            GroveDbOp::Insert { path, key, element } => {
+                self.insert(path.iter().map(|x| x.as_slice()), &key, element, Some(tx))
+            }
+            GroveDbOp::Delete { path, key } => {
+                self.delete(path.iter().map(|x| x.as_slice()), &key, Some(tx))
+            }
+        }
+    }
+}
@@ -1,8 +1,5 @@
-use storage::StorageContext;
-
 use crate::{
-    util::{merk_optional_tx, storage_context_optional_tx},
-    Element, Error, GroveDb, TransactionArg,
+    util::merk_optional_tx, Element, Error, GroveDb, KeyChangeOp, MutationKind, TransactionArg,
 };
 
 impl GroveDb {
@@ -18,7 +15,7 @@ impl GroveDb {
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
     {
         let mut path_iter = path.into_iter();
-        self.check_subtree_exists_path_not_found(path_iter.clone(), Some(key), transaction)?;
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
         if let Some(stop_path_height) = stop_path_height {
             if stop_path_height == path_iter.clone().len() as u16 {
                 return Ok(0);
@@ -46,7 +43,10 @@ impl GroveDb {
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
     {
-        self.delete_internal(path, key, false, transaction)?;
+        let path_iter = path.into_iter();
+        self.observe_slow("delete", path_iter.clone(), Some(key), None, || {
+            self.delete_internal(path_iter, key, false, transaction)
+        })?;
         Ok(())
     }
 
@@ -60,7 +60,10 @@ impl GroveDb {
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
     {
-        self.delete_internal(path, key, true, transaction)
+        let path_iter = path.into_iter();
+        self.observe_slow("delete", path_iter.clone(), Some(key), None, || {
+            self.delete_internal(path_iter, key, true, transaction)
+        })
     }
 
     fn delete_internal<'p, P>(
@@ -74,61 +77,101 @@ impl GroveDb {
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
     {
+        if transaction.is_none() {
+            // Back references, indices and delegate writes are committed together
+            // with the deletion, so a failure part way through leaves nothing behind
+            let tx = self.start_transaction();
+            let deleted = self.delete_internal(path, key, only_delete_tree_if_empty, Some(&tx))?;
+            self.commit_transaction(tx)?;
+            return Ok(deleted);
+        }
+
         let path_iter = path.into_iter();
-        if path_iter.len() == 0 {
-            // Attempt to delete a root tree leaf
-            Err(Error::InvalidPath(
-                "root tree leafs currently cannot be deleted",
-            ))
-        } else {
-            self.check_subtree_exists_path_not_found(path_iter.clone(), Some(key), transaction)?;
-            let element = self.get_raw(path_iter.clone(), key.as_ref(), transaction)?;
-            let delete_element = || -> Result<(), Error> {
-                merk_optional_tx!(self.db, path_iter.clone(), transaction, mut parent_merk, {
-                    Element::delete(&mut parent_merk, &key)?;
-                    Ok(())
-                })
-            };
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        self.check_not_frozen(path_iter.clone(), key)?;
+        self.check_access(path_iter.clone(), key, MutationKind::Delete)?;
+        let element = self.get_raw(path_iter.clone(), key.as_ref(), transaction)?;
+        let delete_element = || -> Result<(), Error> {
+            merk_optional_tx!(self.db, path_iter.clone(), transaction, mut parent_merk, {
+                Element::delete(&mut parent_merk, &key)?;
+                Ok(())
+            })
+        };
 
-            if let Element::Tree(_) = element {
-                let subtree_merk_path = path_iter.clone().chain(std::iter::once(key));
-                let subtrees_paths = self.find_subtrees(subtree_merk_path.clone(), transaction)?;
-                let is_empty =
-                    merk_optional_tx!(self.db, subtree_merk_path, transaction, subtree, {
-                        subtree.is_empty_tree()
-                    });
+        if let Element::Tree(_) = element {
+            let subtree_merk_path = path_iter.clone().chain(std::iter::once(key));
+            // An evicted subtree has no local data, so it would be taken for empty
+            self.fetch_if_cold(subtree_merk_path.clone())?;
+            let is_empty = merk_optional_tx!(self.db, subtree_merk_path, transaction, subtree, {
+                subtree.is_empty_tree()
+            });
 
-                if only_delete_tree_if_empty && !is_empty {
-                    return Ok(false);
-                } else {
-                    // TODO: dumb traversal should not be tolerated
-                    for subtree_path in subtrees_paths {
-                        merk_optional_tx!(
-                            self.db,
-                            subtree_path.iter().map(|x| x.as_slice()),
-                            transaction,
-                            mut subtree,
-                            {
-                                subtree.clear().map_err(|e| {
-                                    Error::CorruptedData(format!(
-                                        "unable to cleanup tree from storage: {}",
-                                        e
-                                    ))
-                                })?;
-                            }
-                        );
-                    }
-                    delete_element()?;
-                }
+            if only_delete_tree_if_empty && !is_empty {
+                return Ok(false);
             } else {
+                self.check_deletion_references(path_iter.clone(), key, transaction)?;
+                self.clear_subtree(path_iter.clone(), key, transaction)?;
                 delete_element()?;
+                self.remove_child_subtree(path_iter.clone(), key, transaction)?;
+            }
+        } else {
+            self.check_deletion_references(path_iter.clone(), key, transaction)?;
+            delete_element()?;
+            if let Element::PrunedTree(_) = element {
+                self.unmark_pruned(path_iter.clone(), key, transaction)?;
             }
-            self.propagate_changes(path_iter, transaction)?;
-            Ok(true)
         }
+        self.update_back_references(path_iter.clone(), key, Some(&element), None, transaction)?;
+        self.propagate_changes(path_iter.clone(), transaction)?;
+        self.update_value_hash_index(path_iter.clone(), key, Some(&element), None, transaction)?;
+        self.release_dedup_item(Some(&element), transaction)?;
+        self.remove_original_key(path_iter.clone(), key, transaction)?;
+        self.record_key_change(path_iter.clone(), key, KeyChangeOp::Delete, transaction);
+        self.notify_index_delegates(path_iter, key, Some(&element), None, transaction)?;
+        Ok(true)
+    }
+
+    /// Deletes data and identifiers of the subtree under the key and of all
+    /// its nested subtrees, the subtree element itself is kept
+    pub(crate) fn clear_subtree<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let subtree_merk_path = path.into_iter().chain(std::iter::once(key));
+        let subtrees_paths = self.find_subtrees(subtree_merk_path, transaction)?;
+        // TODO: dumb traversal should not be tolerated
+        for subtree_path in subtrees_paths {
+            // Cold subtrees are fetched, so their deduplicated items are
+            // released and their cold marks are removed
+            self.fetch_if_cold(subtree_path.iter().map(|x| x.as_slice()))?;
+            self.drop_value_hash_index(&subtree_path, transaction)?;
+            self.drop_original_keys(&subtree_path, transaction)?;
+            self.release_subtree_dedup_items(
+                subtree_path.iter().map(|x| x.as_slice()),
+                transaction,
+            )?;
+            merk_optional_tx!(
+                self.db,
+                subtree_path.iter().map(|x| x.as_slice()),
+                transaction,
+                mut subtree,
+                {
+                    subtree.clear().map_err(|e| {
+                        Error::CorruptedData(format!("unable to cleanup tree from storage: {}", e))
+                    })?;
+                }
+            );
+            self.clear_child_subtrees(subtree_path.iter().map(|x| x.as_slice()), transaction)?;
+            self.remove_subtree_id(subtree_path.iter().map(|x| x.as_slice()), transaction)?;
+        }
+        Ok(())
     }
 
-    // TODO: dumb traversal should not be tolerated
     /// Finds keys which are trees for a given subtree recursively.
     /// One element means a key of a `merk`, n > 1 elements mean relative path
     /// for a deeply nested subtree.
@@ -140,32 +183,18 @@ impl GroveDb {
     where
         P: IntoIterator<Item = &'p [u8]>,
     {
-        // TODO: remove conversion to vec;
-        // However, it's not easy for a reason:
-        // new keys to enqueue are taken from raw iterator which returns Vec<u8>;
-        // changing that to slice is hard as cursor should be moved for next iteration
-        // which requires exclusive (&mut) reference, also there is no guarantee that
-        // slice which points into storage internals will remain valid if raw iterator
-        // got altered so why that reference should be exclusive;
-
         let mut queue: Vec<Vec<Vec<u8>>> =
             vec![path.into_iter().map(|x| x.as_ref().to_vec()).collect()];
         let mut result: Vec<Vec<Vec<u8>>> = queue.clone();
 
         while let Some(q) = queue.pop() {
-            // Get the correct subtree with q_ref as path
-            let path_iter = q.iter().map(|x| x.as_slice());
-            storage_context_optional_tx!(self.db, path_iter.clone(), transaction, storage, {
-                let mut raw_iter = Element::iterator(storage.raw_iter());
-                while let Some((key, value)) = raw_iter.next()? {
-                    if let Element::Tree(_) = value {
-                        let mut sub_path = q.clone();
-                        sub_path.push(key.to_vec());
-                        queue.push(sub_path.clone());
-                        result.push(sub_path);
-                    }
-                }
-            })
+            let children = self.get_child_subtrees(q.iter().map(|x| x.as_slice()), transaction)?;
+            for child in children {
+                let mut sub_path = q.clone();
+                sub_path.push(child);
+                queue.push(sub_path.clone());
+                result.push(sub_path);
+            }
         }
         Ok(result)
     }
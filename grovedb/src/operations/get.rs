@@ -1,8 +1,8 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 use crate::{
-    util::{merk_optional_tx, meta_storage_context_optional_tx},
-    Element, Error, GroveDb, PathQuery, TransactionArg,
+    subtree::raw_decode, util::merk_optional_tx, CostMeter, Element, ElementType, Error, GroveDb,
+    PathQuery, QueryCost, QueryOptions, QueryResultElements, TransactionArg,
 };
 
 /// Limit of possible indirections
@@ -19,17 +19,98 @@ impl GroveDb {
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
     {
-        match self.get_raw(path, key, transaction)? {
-            Element::Reference(reference_path) => {
-                self.follow_reference(reference_path, transaction)
+        self.get_within(path, key, &[], transaction)
+    }
+
+    /// Gets an element like [`GroveDb::get`], failing with
+    /// [`Error::ReferenceOutOfView`] if a reference leads out of the subtree
+    /// at `root_path`
+    pub(crate) fn get_within<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        root_path: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> Result<Element, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        self.observe_slow("get", path_iter.clone(), Some(key), None, || {
+            match self.get_raw(path_iter, key, transaction)? {
+                Element::Reference(reference_path) => {
+                    self.follow_reference(reference_path, root_path, transaction)
+                }
+                other => self.resolve_dedup_item(other, transaction),
+            }
+        })
+    }
+
+    /// Gets elements by many paths and keys at once following references.
+    /// Lookups are grouped by subtree, so each subtree is opened once and its
+    /// keys are read with one batched storage read.
+    /// Results are in input order, `None` means there is no such key or
+    /// subtree.
+    pub fn get_many_by_paths(
+        &self,
+        lookups: Vec<(Vec<Vec<u8>>, Vec<u8>)>,
+        transaction: TransactionArg,
+    ) -> Result<Vec<Option<Element>>, Error> {
+        let mut lookups_by_subtree: BTreeMap<&[Vec<u8>], Vec<(usize, &[u8])>> = BTreeMap::new();
+        for (idx, (path, key)) in lookups.iter().enumerate() {
+            lookups_by_subtree
+                .entry(path.as_slice())
+                .or_default()
+                .push((idx, key.as_slice()));
+        }
+
+        let mut results = vec![None; lookups.len()];
+        for (path, keys) in lookups_by_subtree {
+            let path_iter = path.iter().map(|x| x.as_slice());
+            match self.check_subtree_exists_path_not_found(path_iter.clone(), transaction) {
+                Err(Error::PathNotFound(_)) => continue,
+                result => result?,
+            }
+            let subtree_keys: Vec<&[u8]> = keys.iter().map(|(_, key)| *key).collect();
+            let values = merk_optional_tx!(self.db, path_iter.clone(), transaction, subtree, {
+                subtree.get_many(&subtree_keys)
+            });
+            let values = match values {
+                Ok(values) => values,
+                Err(e) => {
+                    // A batched read doesn't tell which node is corrupted
+                    let e = Error::CorruptedData(e.to_string());
+                    self.quarantine_subtree_on_corruption(path_iter.clone(), &e)?;
+                    return Err(e);
+                }
+            };
+            for ((idx, key), value) in keys.into_iter().zip(values) {
+                let element = match value.map(|value| raw_decode(&value)).transpose() {
+                    Ok(element) => element,
+                    Err(e) => {
+                        self.quarantine_on_corruption(path_iter.clone(), key, &e)?;
+                        return Err(e);
+                    }
+                };
+                results[idx] = match element {
+                    Some(Element::Reference(reference_path)) => {
+                        Some(self.follow_reference(reference_path, &[], transaction)?)
+                    }
+                    Some(element) => Some(self.resolve_dedup_item(element, transaction)?),
+                    None => None,
+                };
             }
-            other => Ok(other),
         }
+        Ok(results)
     }
 
+    /// Follows a reference to the element it leads to, every element on the
+    /// way must be in the subtree at `root_path`
     fn follow_reference(
         &self,
         mut path: Vec<Vec<u8>>,
+        root_path: &[Vec<u8>],
         transaction: TransactionArg,
     ) -> Result<Element, Error> {
         let mut hops_left = MAX_REFERENCE_HOPS;
@@ -40,6 +121,11 @@ impl GroveDb {
             if visited.contains(&path) {
                 return Err(Error::CyclicReference);
             }
+            if !root_path.is_empty()
+                && (path.len() <= root_path.len() || !path.starts_with(root_path))
+            {
+                return Err(Error::ReferenceOutOfView);
+            }
             if let Some((key, path_slice)) = path.split_last() {
                 current_element =
                     self.get_raw(path_slice.iter().map(|x| x.as_slice()), key, transaction)?;
@@ -49,7 +135,7 @@ impl GroveDb {
             visited.insert(path);
             match current_element {
                 Element::Reference(reference_path) => path = reference_path,
-                other => return Ok(other),
+                other => return self.resolve_dedup_item(other, transaction),
             }
             hops_left -= 1;
         }
@@ -68,15 +154,86 @@ impl GroveDb {
         <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
     {
         let path_iter = path.into_iter();
-        self.check_subtree_exists_path_not_found(path_iter.clone(), Some(key), transaction)?;
-        if path_iter.len() == 0 {
-            merk_optional_tx!(self.db, [key], transaction, subtree, {
-                Ok(Element::Tree(subtree.root_hash()))
-            })
-        } else {
-            merk_optional_tx!(self.db, path_iter, transaction, subtree, {
-                Element::get(&subtree, key)
-            })
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        match self.get_from_subtree(path_iter.clone(), key, transaction) {
+            Ok(element) => self.with_pruned_status(path_iter, key, element, transaction),
+            Err(e) => {
+                self.quarantine_on_corruption(path_iter, key, &e)?;
+                Err(e)
+            }
+        }
+    }
+
+    fn get_from_subtree<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<Element, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        merk_optional_tx!(self.db, path, transaction, subtree, {
+            Element::get(&subtree, key)
+        })
+    }
+
+    /// Get a kind of an element without following references and without
+    /// deserializing its data
+    pub fn element_type<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<ElementType, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        let element_type = merk_optional_tx!(self.db, path_iter.clone(), transaction, subtree, {
+            Element::get_type(&subtree, key)
+        })?;
+        if element_type == ElementType::Tree && self.is_pruned(path_iter, key, transaction)? {
+            return Ok(ElementType::PrunedTree);
+        }
+        Ok(element_type)
+    }
+
+    /// Checks if there is a subtree at the path, the root tree always exists
+    pub fn is_subtree<'p, P>(&self, path: P, transaction: TransactionArg) -> Result<bool, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
+    {
+        let mut path_iter = path.into_iter();
+        match path_iter.next_back() {
+            None => Ok(true),
+            Some(key) => match self.element_type(path_iter, key, transaction) {
+                Ok(element_type) => Ok(element_type == ElementType::Tree),
+                Err(Error::PathKeyNotFound(_) | Error::PathNotFound(_)) => Ok(false),
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    /// Get tree item without following references, `None` is returned if
+    /// there is no such key or subtree
+    pub(crate) fn get_raw_optional<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<Option<Element>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
+    {
+        match self.get_raw(path, key, transaction) {
+            Ok(element) => Ok(Some(element)),
+            Err(Error::PathKeyNotFound(_) | Error::PathNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
         }
     }
 
@@ -123,25 +280,125 @@ impl GroveDb {
         path_query: &PathQuery,
         transaction: TransactionArg,
     ) -> Result<(Vec<Vec<u8>>, u16), Error> {
-        let (elements, skipped) = self.get_path_query_raw(path_query, transaction)?;
-        let results = elements
-            .into_iter()
-            .map(|element| match element {
-                Element::Reference(reference_path) => {
-                    let maybe_item = self.follow_reference(reference_path, transaction)?;
-                    if let Element::Item(item) = maybe_item {
-                        Ok(item)
-                    } else {
-                        Err(Error::InvalidQuery("the reference must result in an item"))
+        self.get_path_query_with_options(path_query, QueryOptions::default(), transaction)
+    }
+
+    /// Runs path queries like [`GroveDb::get_path_query`] and returns results
+    /// of each query in the order of queries. All queries see the same state:
+    /// without a transaction they read from one snapshot.
+    pub fn query_many(
+        &self,
+        path_queries: Vec<PathQuery>,
+        transaction: TransactionArg,
+    ) -> Result<Vec<(Vec<Vec<u8>>, u16)>, Error> {
+        if transaction.is_none() {
+            let snapshot = self.start_snapshot_transaction();
+            return self.query_many(path_queries, Some(&snapshot));
+        }
+        path_queries
+            .iter()
+            .map(|path_query| self.get_path_query(path_query, transaction))
+            .collect()
+    }
+
+    /// Runs a path query like [`GroveDb::get_path_query`], aborting it with
+    /// [`Error::CostLimitExceeded`] holding the cost so far once its cost
+    /// exceeds `max_cost`. Returns the cost of the query with its results.
+    pub fn get_path_query_with_cost_limit(
+        &self,
+        path_query: &PathQuery,
+        max_cost: u64,
+        transaction: TransactionArg,
+    ) -> Result<((Vec<Vec<u8>>, u16), QueryCost), Error> {
+        let meter = CostMeter::new(max_cost);
+        let options = QueryOptions {
+            cost_meter: Some(&meter),
+            ..Default::default()
+        };
+        let result = self.get_path_query_with_options(path_query, options, transaction)?;
+        Ok((result, meter.cost()))
+    }
+
+    /// Same as [`GroveDb::get_path_query`] with storage read options applied
+    /// to range iterations
+    pub fn get_path_query_with_options(
+        &self,
+        path_query: &PathQuery,
+        options: QueryOptions,
+        transaction: TransactionArg,
+    ) -> Result<(Vec<Vec<u8>>, u16), Error> {
+        self.get_path_query_within(path_query, &[], options, transaction)
+    }
+
+    /// Runs a path query like [`GroveDb::get_path_query_with_options`],
+    /// failing with [`Error::ReferenceOutOfView`] if a found reference leads
+    /// out of the subtree at `root_path`
+    pub(crate) fn get_path_query_within(
+        &self,
+        path_query: &PathQuery,
+        root_path: &[Vec<u8>],
+        options: QueryOptions,
+        transaction: TransactionArg,
+    ) -> Result<(Vec<Vec<u8>>, u16), Error> {
+        if transaction.is_none() {
+            // A non-transactional query runs over a snapshot, so it never
+            // observes a commit made in the middle of it
+            let snapshot = self.start_snapshot_transaction();
+            return self.get_path_query_within(path_query, root_path, options, Some(&snapshot));
+        }
+        // Memoized results come with no storage reads to charge, so queries
+        // metered by the caller are always run
+        let memoize = options.cost_meter.is_none();
+        let slow_operation_meter = self.slow_operation_meter();
+        let options = QueryOptions {
+            cost_meter: options.cost_meter.or(slow_operation_meter.as_ref()),
+            ..options
+        };
+        let path = path_query.path.iter().map(|x| x.as_slice());
+        self.observe_slow("query", path, None, options.cost_meter, || {
+            if let Some(cancellation) = options.cancellation {
+                cancellation.check()?;
+            }
+            self.path_limits.check_path_query(path_query)?;
+            let (memoized, memo_key) = if memoize {
+                self.memoized_path_query(path_query, transaction)?
+            } else {
+                (None, None)
+            };
+            if let Some(result) = memoized {
+                return Ok(result);
+            }
+            let (elements, skipped) =
+                self.get_path_query_raw_with_options(path_query, options, transaction)?;
+            // Referenced items may be out of the queried subtree, so results with
+            // references don't depend on the subtree root hash only
+            let has_references = elements
+                .iter()
+                .any(|element| matches!(element, Element::Reference(_)));
+            let results = elements
+                .into_iter()
+                .map(|element| match element {
+                    Element::Reference(reference_path) => {
+                        let maybe_item =
+                            self.follow_reference(reference_path, root_path, transaction)?;
+                        if let Element::Item(item) = maybe_item {
+                            Ok(item)
+                        } else {
+                            Err(Error::InvalidQuery("the reference must result in an item"))
+                        }
                     }
-                }
-                Element::Item(item) => Ok(item),
-                Element::Tree(_) => Err(Error::InvalidQuery(
-                    "path_queries can only refer to items and references",
-                )),
-            })
-            .collect::<Result<Vec<Vec<u8>>, Error>>()?;
-        Ok((results, skipped))
+                    Element::Item(item) => Ok(item),
+                    Element::DedupItem(hash) => self.dedup_value(&hash, transaction),
+                    Element::Tree(_) | Element::PrunedTree(_) => Err(Error::InvalidQuery(
+                        "path_queries can only refer to items and references",
+                    )),
+                })
+                .collect::<Result<Vec<Vec<u8>>, Error>>()?;
+            if let (Some(memo_key), false) = (memo_key, has_references) {
+                self.memoize_path_query(memo_key, (results.clone(), skipped));
+            }
+            Ok((results, skipped))
+        })
     }
 
     pub fn get_path_query_raw(
@@ -149,18 +406,75 @@ impl GroveDb {
         path_query: &PathQuery,
         transaction: TransactionArg,
     ) -> Result<(Vec<Element>, u16), Error> {
+        self.get_path_query_raw_with_options(path_query, QueryOptions::default(), transaction)
+    }
+
+    /// Same as [`GroveDb::get_path_query_raw`] keeping paths and keys of found
+    /// elements, which can be extracted with [`QueryResultElements`] helpers
+    pub fn get_path_query_result_elements(
+        &self,
+        path_query: &PathQuery,
+        transaction: TransactionArg,
+    ) -> Result<(QueryResultElements, u16), Error> {
+        if transaction.is_none() {
+            let snapshot = self.start_snapshot_transaction();
+            return self.get_path_query_result_elements(path_query, Some(&snapshot));
+        }
+        self.path_limits.check_path_query(path_query)?;
         let path_slices = path_query
             .path
             .iter()
             .map(|x| x.as_slice())
             .collect::<Vec<_>>();
-        Element::get_path_query(&self.db, &path_slices, path_query, transaction)
+        let result = Element::get_path_query_result_elements(
+            &self.db,
+            &path_slices,
+            path_query,
+            QueryOptions::default(),
+            transaction,
+        );
+        if let Err(e) = &result {
+            self.quarantine_subtree_on_corruption(path_slices.iter().copied(), e)?;
+        }
+        let (elements, skipped) = result?;
+        Ok((QueryResultElements { elements }, skipped))
+    }
+
+    /// Same as [`GroveDb::get_path_query_raw`] with storage read options
+    /// applied to range iterations
+    pub fn get_path_query_raw_with_options(
+        &self,
+        path_query: &PathQuery,
+        options: QueryOptions,
+        transaction: TransactionArg,
+    ) -> Result<(Vec<Element>, u16), Error> {
+        if transaction.is_none() {
+            let snapshot = self.start_snapshot_transaction();
+            return self.get_path_query_raw_with_options(path_query, options, Some(&snapshot));
+        }
+        self.path_limits.check_path_query(path_query)?;
+        let _slot = self.acquire_query_slot()?;
+        let path_slices = path_query
+            .path
+            .iter()
+            .map(|x| x.as_slice())
+            .collect::<Vec<_>>();
+        let result = Element::get_path_query_with_options(
+            &self.db,
+            &path_slices,
+            path_query,
+            options,
+            transaction,
+        );
+        if let Err(e) = &result {
+            self.quarantine_subtree_on_corruption(path_slices.iter().copied(), e)?;
+        }
+        result
     }
 
     fn check_subtree_exists<'p, P>(
         &self,
         path: P,
-        key: Option<&'p [u8]>,
         transaction: TransactionArg,
         error: Error,
     ) -> Result<(), Error>
@@ -168,32 +482,22 @@ impl GroveDb {
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
     {
-        let mut path_iter = path.into_iter();
-        if path_iter.len() == 0 {
-            meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
-                let root_leaf_keys = Self::get_root_leaf_keys_internal(&meta_storage)?;
-                if !root_leaf_keys.contains_key(key.ok_or(Error::MissingParameter("key"))?) {
-                    return Err(error);
-                }
-            });
-        } else if path_iter.len() == 1 {
-            meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
-                let root_leaf_keys = Self::get_root_leaf_keys_internal(&meta_storage)?;
-                if !root_leaf_keys.contains_key(path_iter.next().expect("must contain an item")) {
-                    return Err(error);
-                }
-            });
-        } else {
-            let mut parent_iter = path_iter;
-            let parent_key = parent_iter.next_back().expect("path is not empty");
-            merk_optional_tx!(self.db, parent_iter, transaction, parent, {
-                if matches!(
-                    Element::get(&parent, parent_key),
-                    Err(Error::PathKeyNotFound(_))
-                ) {
-                    return Err(error);
+        let path_iter = path.into_iter();
+        let mut parent_path_iter = path_iter.clone();
+        // The root tree always exists
+        if let Some(parent_key) = parent_path_iter.next_back() {
+            merk_optional_tx!(self.db, parent_path_iter.clone(), transaction, parent, {
+                match Element::get(&parent, parent_key) {
+                    Err(Error::PathKeyNotFound(_)) => return Err(error),
+                    Ok(Element::Tree(_))
+                        if self.is_pruned(parent_path_iter, parent_key, transaction)? =>
+                    {
+                        return Err(Error::SubtreePruned)
+                    }
+                    _ => {}
                 }
             });
+            self.fetch_if_cold(path_iter)?;
         }
         Ok(())
     }
@@ -201,7 +505,6 @@ impl GroveDb {
     pub fn check_subtree_exists_path_not_found<'p, P>(
         &self,
         path: P,
-        key: Option<&'p [u8]>,
         transaction: TransactionArg,
     ) -> Result<(), Error>
     where
@@ -210,7 +513,6 @@ impl GroveDb {
     {
         self.check_subtree_exists(
             path,
-            key,
             transaction,
             Error::PathNotFound("subtree doesn't exist"),
         )
@@ -219,7 +521,6 @@ impl GroveDb {
     pub fn check_subtree_exists_invalid_path<'p, P>(
         &self,
         path: P,
-        key: Option<&'p [u8]>,
         transaction: TransactionArg,
     ) -> Result<(), Error>
     where
@@ -228,7 +529,6 @@ impl GroveDb {
     {
         self.check_subtree_exists(
             path,
-            key,
             transaction,
             Error::InvalidPath("subtree doesn't exist"),
         )
@@ -1,9 +1,9 @@
 use merk::Merk;
-use storage::{Storage, StorageContext};
+use storage::Storage;
 
 use crate::{
-    util::{merk_optional_tx, meta_storage_context_optional_tx},
-    Element, Error, GroveDb, TransactionArg, ROOT_LEAFS_SERIALIZED_KEY,
+    util::merk_optional_tx, Element, Error, Feature, GroveDb, KeyChangeOp, MutationKind,
+    TransactionArg,
 };
 
 impl GroveDb {
@@ -18,14 +18,89 @@ impl GroveDb {
         P: IntoIterator<Item = &'p [u8]>,
         <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
     {
+        if let Element::DedupItem(_) = element {
+            return Err(Error::InvalidQuery(
+                "deduplicated items can only be inserted with their values",
+            ));
+        }
         let path_iter = path.into_iter();
+        self.observe_slow("insert", path_iter.clone(), Some(key), None, || {
+            self.insert_element(path_iter, key, element, false, transaction)
+        })
+    }
+
+    /// Inserts an item stored compressed with zstd, so large documents take
+    /// less space. The item is decompressed transparently on reads, and hashes
+    /// and proofs are the same as for the item inserted with
+    /// [`GroveDb::insert`]. Requires [`Feature::ItemCompression`] to be
+    /// enabled.
+    pub fn insert_compressed<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        element: Element,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
+    {
+        if !matches!(element, Element::Item(_)) {
+            return Err(Error::InvalidQuery("only items can be stored compressed"));
+        }
+        self.require_feature(Feature::ItemCompression)?;
+        let path_iter = path.into_iter();
+        self.observe_slow("insert", path_iter.clone(), Some(key), None, || {
+            self.insert_element(path_iter, key, element, true, transaction)
+        })
+    }
+
+    pub(crate) fn insert_element<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        element: Element,
+        compressed: bool,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
+    {
+        if transaction.is_none() {
+            // Back references, indices and delegate writes are committed together
+            // with the element, so a failure part way through leaves nothing behind
+            let tx = self.start_transaction();
+            self.insert_element(path, key, element, compressed, Some(&tx))?;
+            return self.commit_transaction(tx);
+        }
+
+        let path_iter = path.into_iter();
+        self.path_limits.check_path(path_iter.clone(), Some(key))?;
+        self.check_not_frozen(path_iter.clone(), key)?;
+        self.check_access(path_iter.clone(), key, MutationKind::Insert)?;
+        if let Element::Reference(reference_path) = &element {
+            self.check_reference_target(reference_path, transaction)?;
+        }
+        // Previous element is needed to keep back references and indices up to date
+        let old_element = self.get_raw_optional(path_iter.clone(), key, transaction)?;
         match element {
             Element::Tree(_) => {
-                if path_iter.len() == 0 {
-                    self.add_root_leaf(key, transaction)?;
-                } else {
-                    self.add_non_root_subtree(path_iter, key, transaction)?;
+                // A tree inserted over a tree keeps the existing subtree and its
+                // identifier
+                if !matches!(old_element, Some(Element::Tree(_))) {
+                    self.assign_subtree_id(
+                        path_iter.clone().chain(std::iter::once(key)),
+                        transaction,
+                    )?;
                 }
+                self.add_subtree(path_iter.clone(), key, transaction)?;
+                self.add_child_subtree(path_iter.clone(), key, transaction)?;
+            }
+            Element::PrunedTree(_) => {
+                return Err(Error::InvalidQuery(
+                    "pruned trees can only be made by pruning a subtree",
+                ));
             }
             _ => {
                 // If path is empty that means there is an attempt to insert
@@ -36,38 +111,58 @@ impl GroveDb {
                         "only subtrees are allowed as root tree's leafs",
                     ));
                 }
-                self.check_subtree_exists_invalid_path(path_iter.clone(), Some(key), transaction)?;
+                self.check_subtree_exists_invalid_path(path_iter.clone(), transaction)?;
                 merk_optional_tx!(self.db, path_iter.clone(), transaction, mut subtree, {
-                    element.insert(&mut subtree, key)?;
+                    if compressed {
+                        element.insert_compressed(&mut subtree, key)?;
+                    } else {
+                        element.insert(&mut subtree, key)?;
+                    }
                 });
-                self.propagate_changes(path_iter, transaction)?;
+                self.propagate_changes(path_iter.clone(), transaction)?;
+                if let Some(Element::Tree(_)) = old_element {
+                    self.remove_child_subtree(path_iter.clone(), key, transaction)?;
+                }
             }
         }
+        self.update_back_references(
+            path_iter.clone(),
+            key,
+            old_element.as_ref(),
+            Some(&element),
+            transaction,
+        )?;
+        self.update_value_hash_index(
+            path_iter.clone(),
+            key,
+            old_element.as_ref(),
+            Some(&element),
+            transaction,
+        )?;
+        self.release_dedup_item(old_element.as_ref(), transaction)?;
+        if old_element.is_some() {
+            self.remove_original_key(path_iter.clone(), key, transaction)?;
+        }
+        if let Some(Element::PrunedTree(_)) = old_element {
+            self.unmark_pruned(path_iter.clone(), key, transaction)?;
+        }
+        self.record_key_change(path_iter.clone(), key, KeyChangeOp::Put, transaction);
+        self.notify_index_delegates(
+            path_iter,
+            key,
+            old_element.as_ref(),
+            Some(&element),
+            transaction,
+        )?;
         Ok(())
     }
 
-    /// Add subtree to the root tree
-    fn add_root_leaf(&self, key: &[u8], transaction: TransactionArg) -> Result<(), Error> {
-        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
-            let mut root_leaf_keys = Self::get_root_leaf_keys_internal(&meta_storage)?;
-            if root_leaf_keys.get(&key.to_vec()).is_none() {
-                root_leaf_keys.insert(key.to_vec(), root_leaf_keys.len());
-            }
-            let value = bincode::serialize(&root_leaf_keys).map_err(|_| {
-                Error::CorruptedData(String::from("unable to serialize root leaves data"))
-            })?;
-            meta_storage.put_meta(ROOT_LEAFS_SERIALIZED_KEY, &value)?;
-        });
-
-        Ok(())
-    }
-
-    /// Add subtree to another subtree.
-    /// We want to add a new empty merk to another merk at a key
+    /// Add subtree to another subtree, which is the root tree for an empty
+    /// path. We want to add a new empty merk to another merk at a key
     /// first make sure other merk exist
     /// if it exists, then create merk to be inserted, and get root hash
     /// we only care about root hash of merk to be inserted
-    fn add_non_root_subtree<'p, P>(
+    fn add_subtree<'p, P>(
         &self,
         path: P,
         key: &'p [u8],
@@ -78,7 +173,7 @@ impl GroveDb {
         <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
     {
         let path_iter = path.into_iter();
-        self.check_subtree_exists_invalid_path(path_iter.clone(), Some(key), transaction)?;
+        self.check_subtree_exists_invalid_path(path_iter.clone(), transaction)?;
         if let Some(tx) = transaction {
             let parent_storage = self
                 .db
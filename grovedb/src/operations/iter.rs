@@ -0,0 +1,85 @@
+use storage::{dyn_storage::DynRawIterator, RawIterator, Storage, StorageContext};
+
+use crate::{subtree::raw_decode, Element, Error, GroveDb, TransactionArg};
+
+/// Iterator over keys of a subtree in order with their elements, see
+/// [`GroveDb::iter`]
+pub struct SubtreeIter<'a> {
+    db: &'a GroveDb,
+    path: Vec<Vec<u8>>,
+    raw_iter: Box<dyn DynRawIterator + 'a>,
+    keys_only: bool,
+    failed: bool,
+}
+
+impl Iterator for SubtreeIter<'_> {
+    type Item = Result<(Vec<u8>, Option<Element>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let key = match self.raw_iter.key() {
+            Some(key) => key.to_vec(),
+            None => {
+                // An error makes the raw iterator invalid like the end of data
+                self.failed = true;
+                return self.raw_iter.status().err().map(|e| Err(e.into()));
+            }
+        };
+        let element = if self.keys_only {
+            None
+        } else {
+            match raw_decode(self.raw_iter.value()?) {
+                Ok(element) => Some(element),
+                Err(e) => {
+                    // Iteration stops at a corrupted element
+                    self.failed = true;
+                    let path = self.path.iter().map(|x| x.as_slice());
+                    return Some(self.db.quarantine_on_corruption(path, &key, &e).and(Err(e)));
+                }
+            }
+        };
+        self.raw_iter.next();
+        Some(Ok((key, element)))
+    }
+}
+
+impl GroveDb {
+    /// Returns an iterator over all keys of a subtree in order with their
+    /// elements, which are read as the iterator advances. With `keys_only`
+    /// elements are `None` and values are neither returned by storage nor
+    /// decoded, which is enough for existence scans and key-set diffs.
+    pub fn iter<'a, 'p, P>(
+        &'a self,
+        path: P,
+        keys_only: bool,
+        transaction: TransactionArg<'a, 'a>,
+    ) -> Result<SubtreeIter<'a>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone + DoubleEndedIterator + ExactSizeIterator,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        let storage = match transaction {
+            Some(tx) => self
+                .db
+                .get_transactional_storage_context(path_iter.clone(), tx),
+            None => self.db.get_storage_context(path_iter.clone()),
+        };
+        let mut raw_iter = if keys_only {
+            storage.raw_iter_keys_only()
+        } else {
+            storage.raw_iter()
+        };
+        raw_iter.seek_to_first();
+        Ok(SubtreeIter {
+            db: self,
+            path: path_iter.map(|x| x.to_vec()).collect(),
+            raw_iter,
+            keys_only,
+            failed: false,
+        })
+    }
+}
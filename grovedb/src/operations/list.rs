@@ -0,0 +1,96 @@
+use storage::StorageContext;
+
+use crate::{util::storage_context_optional_tx, Element, Error, GroveDb, TransactionArg};
+
+/// An element found by [`GroveDb::list_recursive`] with elements of its
+/// subtree if the element is a tree within the depth limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedElement {
+    pub key: Vec<u8>,
+    pub element: Element,
+    pub children: Vec<ListedElement>,
+}
+
+impl GroveDb {
+    /// Lists elements of a subtree and its nested subtrees depth first.
+    /// `max_depth` is the number of levels to list, so `1` returns only
+    /// elements of the subtree itself, and `max_items` limits the total number
+    /// of elements on all levels, elements of a subtree are taken before
+    /// elements of its nested subtrees. The flag returned is `true` if any of
+    /// the limits cut the listing short.
+    pub fn list_recursive<'p, P>(
+        &self,
+        path: P,
+        max_depth: usize,
+        max_items: usize,
+        transaction: TransactionArg,
+    ) -> Result<(Vec<ListedElement>, bool), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone + DoubleEndedIterator + ExactSizeIterator,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        let path: Vec<Vec<u8>> = path_iter.map(|x| x.to_vec()).collect();
+        let mut items_left = max_items;
+        let mut truncated = false;
+        let listed = self.list_level(
+            &path,
+            max_depth,
+            &mut items_left,
+            &mut truncated,
+            transaction,
+        )?;
+        Ok((listed, truncated))
+    }
+
+    fn list_level(
+        &self,
+        path: &[Vec<u8>],
+        depth_left: usize,
+        items_left: &mut usize,
+        truncated: &mut bool,
+        transaction: TransactionArg,
+    ) -> Result<Vec<ListedElement>, Error> {
+        if depth_left == 0 {
+            *truncated |= !self.is_empty_tree(path.iter().map(|x| x.as_slice()), transaction)?;
+            return Ok(Vec::new());
+        }
+        let mut elements = Vec::new();
+        let path_iter = path.iter().map(|x| x.as_slice());
+        storage_context_optional_tx!(self.db, path_iter, transaction, storage, {
+            let mut raw_iter = Element::iterator(storage.raw_iter());
+            while let Some((key, element)) = raw_iter.next()? {
+                if elements.len() == *items_left {
+                    *truncated = true;
+                    break;
+                }
+                elements.push((key, element));
+            }
+        });
+        *items_left -= elements.len();
+
+        let mut listed = Vec::with_capacity(elements.len());
+        for (key, element) in elements {
+            let children = if let Element::Tree(_) = element {
+                let mut child_path = path.to_vec();
+                child_path.push(key.clone());
+                self.list_level(
+                    &child_path,
+                    depth_left - 1,
+                    items_left,
+                    truncated,
+                    transaction,
+                )?
+            } else {
+                Vec::new()
+            };
+            listed.push(ListedElement {
+                key,
+                element,
+                children,
+            });
+        }
+        Ok(listed)
+    }
+}
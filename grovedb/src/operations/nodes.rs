@@ -0,0 +1,47 @@
+use merk::ROOT_KEY_KEY;
+use storage::StorageContext;
+
+use crate::{util::storage_context_optional_tx, Error, GroveDb, TransactionArg};
+
+impl GroveDb {
+    /// Returns the Merk node stored under the key of the subtree at the path
+    /// as it's encoded in storage: child links with their keys, hashes and
+    /// heights, the key-value hash and the serialized element. It can be
+    /// decoded with `merk::tree::Tree::decode`. `None` is returned if there
+    /// is no such key.
+    pub fn get_node_raw<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<Option<Vec<u8>>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        storage_context_optional_tx!(self.db, path_iter, transaction, storage, {
+            Ok(storage.get(key)?)
+        })
+    }
+
+    /// Returns the key of the root node of the subtree at the path to start
+    /// traversing its nodes with [`GroveDb::get_node_raw`] from, `None` is
+    /// returned for an empty subtree
+    pub fn get_root_node_key<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<Option<Vec<u8>>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + DoubleEndedIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        storage_context_optional_tx!(self.db, path_iter, transaction, storage, {
+            Ok(storage.get_root(ROOT_KEY_KEY)?)
+        })
+    }
+}
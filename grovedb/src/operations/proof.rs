@@ -1,261 +1,511 @@
-// use std::collections::HashMap;
-//
-// use merk::proofs::query::Map;
-// use rs_merkle::{algorithms::Sha256, MerkleProof};
-//
-// use crate::{Element, Error, GroveDb, PathQuery, Proof, Query};
-//
-// impl GroveDb {
-//     pub fn proof(&mut self, proof_queries: Vec<PathQuery>) -> Result<Vec<u8>,
-// Error> {         // To prove a path we need to return a proof for each node
-// on the path including         // the root. With multiple paths, nodes can
-// overlap i.e two or more paths can         // share the same nodes. We should
-// only have one proof for each node,         // if a node forks into multiple
-// relevant paths then we should create a         // combined proof for that
-// node with all the relevant keys         let mut query_paths = Vec::new();
-//         let mut intermediate_proof_spec: HashMap<Vec<u8>, Query> =
-// HashMap::new();         let mut root_keys: Vec<Vec<u8>> = Vec::new();
-//         let mut proofs: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
-//
-//         // For each unique node including the root
-//         // determine what keys would need to be included in the proof
-//         for proof_query in proof_queries.iter() {
-//             query_paths.push(
-//                 proof_query
-//                     .path
-//                     .iter()
-//                     .map(|x| x.to_vec())
-//                     .collect::<Vec<_>>(),
-//             );
-//
-//             let mut split_path = proof_query.path.split_last();
-//             while let Some((key, path_slice)) = split_path {
-//                 if path_slice.is_empty() {
-//                     // We have gotten to the root node
-//                     let compressed_path = GroveDb::compress_subtree_key(&[],
-// Some(key));                     root_keys.push(compressed_path);
-//                 } else {
-//                     let compressed_path =
-// GroveDb::compress_subtree_key(path_slice, None);                     if let
-// Some(path_query) = intermediate_proof_spec.get_mut(&compressed_path) {
-//                         path_query.insert_key(key.to_vec());
-//                     } else {
-//                         let mut path_query = Query::new();
-//                         path_query.insert_key(key.to_vec());
-//                         intermediate_proof_spec.insert(compressed_path,
-// path_query);                     }
-//                 }
-//                 split_path = path_slice.split_last();
-//             }
-//         }
-//
-//         // Construct the path proofs
-//         for (path, query) in intermediate_proof_spec {
-//             let proof = self.prove_item(&path, query)?;
-//             proofs.insert(path, proof);
-//         }
-//
-//         // Construct the leaf proofs
-//         for proof_query in proof_queries {
-//             let path = proof_query.path;
-//
-//             // If there is a subquery with a limit it's possible that we only
-// need a reduced             // proof for this leaf.
-//             let reduced_proof_query = proof_query;
-//
-//             // First we must get elements
-//
-//             if let Some(subquery_key) =
-// reduced_proof_query.query.query.subquery_key.clone() {
-// self.get_path_queries_raw(&[&reduced_proof_query], None)?;
-//
-//                 let mut path_vec = path.to_vec();
-//                 path_vec.push(subquery_key.as_slice());
-//                 let compressed_path =
-// GroveDb::compress_subtree_key(path_vec.as_slice(), None);             }
-//
-//             // Now we must insert the final proof for the sub leaves
-//             let compressed_path = GroveDb::compress_subtree_key(path, None);
-//             let proof = self.prove_path_item(&compressed_path,
-// reduced_proof_query)?;             proofs.insert(compressed_path, proof);
-//         }
-//
-//         // Construct the root proof
-//         let mut root_index: Vec<usize> = Vec::new();
-//         for key in root_keys {
-//             let index = self
-//                 .root_leaf_keys
-//                 .get(&key)
-//                 .ok_or(Error::InvalidPath("root key not found"))?;
-//             root_index.push(*index);
-//         }
-//         let root_proof = self.root_tree.proof(&root_index).to_bytes();
-//
-//         let proof = Proof {
-//             query_paths,
-//             proofs,
-//             root_proof,
-//             root_leaf_keys: self.root_leaf_keys.clone(),
-//         };
-//
-//         let serialized_proof = bincode::serialize(&proof)
-//             .map_err(|_| Error::CorruptedData(String::from("unable to
-// serialize proof")))?;
-//
-//         Ok(serialized_proof)
-//     }
-//
-//     fn prove_path_item(
-//         &self,
-//         compressed_path: &[u8],
-//         path_query: PathQuery,
-//     ) -> Result<Vec<u8>, Error> {
-//         let merk = self
-//             .subtrees
-//             .get(compressed_path)
-//             .ok_or(Error::InvalidPath("no subtree found under that path"))?;
-//
-//         let sized_query = path_query.query;
-//
-//         if sized_query.query.subquery.is_none() {
-//             // then limit should be applied directly to the proof here
-//             let proof_result = merk
-//                 .prove(
-//                     sized_query.query,
-//                     sized_query.limit,
-//                     sized_query.offset,
-//                 )
-//                 .expect("should prove both inclusion and absence");
-//             Ok(proof_result)
-//         } else {
-//             let proof_result = merk
-//                 .prove(sized_query.query, None, None)
-//                 .expect("should prove both inclusion and absence");
-//             Ok(proof_result)
-//         }
-//     }
-//
-//     fn prove_item(&self, path: &[u8], query: Query) -> Result<Vec<u8>,
-// Error> {         let merk = self
-//             .subtrees
-//             .get(path)
-//             .ok_or(Error::InvalidPath("no subtree found under that path"))?;
-//
-//         // then limit should be applied directly to the proof here
-//         let proof_result = merk
-//             .prove(query, None, None)
-//             .expect("should prove both inclusion and absence");
-//         Ok(proof_result)
-//     }
-//
-//     pub fn execute_proof(proof: Vec<u8>) -> Result<([u8; 32],
-// HashMap<Vec<u8>, Map>), Error> {         // Deserialize the proof
-//         let proof: Proof = bincode::deserialize(&proof)
-//             .map_err(|_| Error::CorruptedData(String::from("unable to
-// deserialize proof")))?;
-//
-//         // Required to execute the root proof
-//         let mut root_keys_index: Vec<usize> = Vec::new();
-//         let mut root_hashes: Vec<[u8; 32]> = Vec::new();
-//
-//         // Collects the result map for each query
-//         let mut result_map: HashMap<Vec<u8>, Map> = HashMap::new();
-//
-//         for path in proof.query_paths {
-//             let path = path.iter().map(|x| x.as_slice()).collect::<Vec<_>>();
-//             // For each query path, get the result map after execution
-//             // and store hash + index for later root proof execution
-//             let root_key = &path[0];
-//             let (hash, proof_result_map) = GroveDb::execute_path(&path,
-// &proof.proofs)?;             let compressed_root_key_path =
-// GroveDb::compress_subtree_key(&[], Some(root_key));             let
-// compressed_query_path = GroveDb::compress_subtree_key(&path, None);
-//
-//             let index = proof
-//                 .root_leaf_keys
-//                 .get(&compressed_root_key_path)
-//                 .ok_or(Error::InvalidPath("Bad path"))?;
-//             if !root_keys_index.contains(index) {
-//                 root_keys_index.push(*index);
-//                 root_hashes.push(hash);
-//             }
-//
-//             result_map.insert(compressed_query_path, proof_result_map);
-//         }
-//
-//         let root_proof = match
-// MerkleProof::<Sha256>::try_from(proof.root_proof) {             Ok(proof) =>
-// Ok(proof),             Err(_) => Err(Error::InvalidProof("Invalid proof
-// element")),         }?;
-//
-//         let root_hash =
-//             match root_proof.root(&root_keys_index, &root_hashes,
-// proof.root_leaf_keys.len()) {                 Ok(hash) => Ok(hash),
-//                 Err(_) => Err(Error::InvalidProof("Invalid proof element")),
-//             }?;
-//
-//         Ok((root_hash, result_map))
-//     }
-//
-//     // Given a query path and a set of proofs
-//     // execute_path validates that the nodes represented by the paths
-//     // are connected to one another i.e root hash of child node is in parent
-// node     // at the correct key.
-//     // If path is valid, it returns the root hash of topmost merk and result
-// map of     // leaf merk.
-//     fn execute_path(
-//         path: &[&[u8]],
-//         proofs: &HashMap<Vec<u8>, Vec<u8>>,
-//     ) -> Result<([u8; 32], Map), Error> {
-//         let compressed_path = GroveDb::compress_subtree_key(path, None);
-//         let proof = proofs
-//             .get(&compressed_path)
-//             .ok_or(Error::InvalidPath("Bad path"))?;
-//
-//         // Execute the leaf merk proof
-//         let (mut last_root_hash, result_map) = match
-// merk::execute_proof(&proof[..]) {             Ok(result) => Ok(result),
-//             Err(_) => Err(Error::InvalidPath("Invalid proof element")),
-//         }?;
-//
-//         // Validate the path
-//         let mut split_path = path.split_last();
-//         while let Some((key, path_slice)) = split_path {
-//             if !path_slice.is_empty() {
-//                 let compressed_path =
-// GroveDb::compress_subtree_key(path_slice, None);                 let proof =
-// proofs                     .get(&compressed_path)
-//                     .ok_or(Error::InvalidPath("Bad path"))?;
-//
-//                 let proof_result = match merk::execute_proof(&proof[..]) {
-//                     Ok(result) => Ok(result),
-//                     Err(_) => Err(Error::InvalidPath("Invalid proof
-// element")),                 }?;
-//
-//                 let result_map = proof_result.1;
-//                 // TODO: Handle the error better here
-//                 let elem: Element =
-//
-// bincode::deserialize(result_map.get(key).unwrap().unwrap()).unwrap();
-//                 let merk_root_hash = match elem {
-//                     Element::Tree(hash) => Ok(hash),
-//                     _ => Err(Error::InvalidProof(
-//                         "Intermediate proofs should be for trees",
-//                     )),
-//                 }?;
-//
-//                 if merk_root_hash != last_root_hash {
-//                     return Err(Error::InvalidProof("Bad path"));
-//                 }
-//
-//                 last_root_hash = proof_result.0;
-//             } else {
-//                 break;
-//             }
-//
-//             split_path = path_slice.split_last();
-//         }
-//
-//         Ok((last_root_hash, result_map))
-//     }
-// }
+use std::collections::{BTreeMap, HashMap};
+
+use merk::proofs::{self, query::Map};
+use rayon::prelude::*;
+use storage::DynStorage;
+
+use crate::{
+    operations::get::MAX_REFERENCE_HOPS,
+    util::merk_optional_tx,
+    verify::{
+        self, PROOF_FORMAT_VERSION, PROOF_NODE_REF, PROOF_NODE_REF_LENGTH, PROOF_UNCOMPRESSED,
+        PROOF_ZSTD,
+    },
+    version::Feature,
+    CancellationToken, CostMeter, Element, Error, GroveDb, PathQuery, Proof, ProofLimits, Query,
+    QueryCost, SizedQuery, Transaction, TransactionArg,
+};
+
+/// Zstd compression level of proofs
+const PROOF_ZSTD_LEVEL: i32 = 3;
+
+impl GroveDb {
+    /// Generates a proof for path queries. To prove a queried subtree the
+    /// proof includes proofs of every subtree on its path starting from the
+    /// root tree, each one proving the child subtree key. A query with
+    /// subqueries is proved as queries of every subtree it reaches, so the
+    /// proof has a query path for each of them, subqueries can't be combined
+    /// with a limit or an offset. Nodes occurring in several subtree proofs
+    /// are encoded once.
+    pub fn prove(
+        &self,
+        path_queries: &[PathQuery],
+        transaction: TransactionArg,
+    ) -> Result<Vec<u8>, Error> {
+        let meter = self.slow_operation_meter();
+        self.observe_slow("prove", [], None, meter.as_ref(), || {
+            self.prove_internal(path_queries, false, meter.as_ref(), None, transaction)
+        })
+    }
+
+    /// Generates a proof like [`GroveDb::prove`], aborting with
+    /// [`Error::CostLimitExceeded`] once the cost of proving exceeds
+    /// `max_cost`. Proving a subtree costs a seek and the length of its proof.
+    /// Returns the cost with the proof.
+    pub fn prove_with_cost_limit(
+        &self,
+        path_queries: &[PathQuery],
+        max_cost: u64,
+        transaction: TransactionArg,
+    ) -> Result<(Vec<u8>, QueryCost), Error> {
+        let meter = CostMeter::new(max_cost);
+        let proof = self.observe_slow("prove", [], None, Some(&meter), || {
+            self.prove_internal(path_queries, false, Some(&meter), None, transaction)
+        })?;
+        Ok((proof, meter.cost()))
+    }
+
+    /// Generates a proof like [`GroveDb::prove`] compressed with zstd, which
+    /// pays off for proofs of many similar elements. Compression is flagged
+    /// in the proof header, so the proof is verified the same way. Requires
+    /// [`Feature::ProofCompression`] to be enabled.
+    pub fn prove_compressed(
+        &self,
+        path_queries: &[PathQuery],
+        transaction: TransactionArg,
+    ) -> Result<Vec<u8>, Error> {
+        self.require_feature(Feature::ProofCompression)?;
+        let meter = self.slow_operation_meter();
+        self.observe_slow("prove", [], None, meter.as_ref(), || {
+            self.prove_internal(path_queries, true, meter.as_ref(), None, transaction)
+        })
+    }
+
+    /// Generates a proof like [`GroveDb::prove`], aborting with
+    /// [`Error::Cancelled`] once the token is cancelled. The token is checked
+    /// before proving each subtree.
+    pub fn prove_with_cancellation(
+        &self,
+        path_queries: &[PathQuery],
+        cancellation: &CancellationToken,
+        transaction: TransactionArg,
+    ) -> Result<Vec<u8>, Error> {
+        let meter = self.slow_operation_meter();
+        self.observe_slow("prove", [], None, meter.as_ref(), || {
+            self.prove_internal(
+                path_queries,
+                false,
+                meter.as_ref(),
+                Some(cancellation),
+                transaction,
+            )
+        })
+    }
+
+    /// Generates a proof like [`GroveDb::prove`] that also proves elements
+    /// referenced by queried references, following chains of references, so
+    /// references can be resolved from the proof alone. Targets are proved as
+    /// queried keys of their subtrees, which may be queried subtrees
+    /// themselves unless those are queried with a limit or an offset.
+    pub fn prove_with_reference_targets(
+        &self,
+        path_queries: &[PathQuery],
+        transaction: TransactionArg,
+    ) -> Result<Vec<u8>, Error> {
+        if transaction.is_none() {
+            // Targets have to be looked up in the state being proved
+            let snapshot = self.start_snapshot_transaction();
+            return self.prove_with_reference_targets(path_queries, Some(&snapshot));
+        }
+        let mut target_queries: BTreeMap<Vec<Vec<u8>>, Query> = BTreeMap::new();
+        for path_query in path_queries {
+            let (elements, _) = self.get_path_query_raw(path_query, transaction)?;
+            for element in elements {
+                if let Element::Reference(reference_path) = element {
+                    self.collect_reference_targets(
+                        reference_path,
+                        &mut target_queries,
+                        transaction,
+                    )?;
+                }
+            }
+        }
+
+        let mut path_queries = path_queries.to_vec();
+        for (path, target_query) in target_queries {
+            match path_queries.iter_mut().find(|q| q.path == path) {
+                Some(path_query) => {
+                    if path_query.query.limit.is_some() || path_query.query.offset.is_some() {
+                        return Err(Error::InvalidQuery(
+                            "reference targets in a subtree queried with limit or offset cannot \
+                             be proved",
+                        ));
+                    }
+                    for item in target_query.iter() {
+                        path_query.query.query.insert_item(item.clone());
+                    }
+                }
+                None => path_queries.push(PathQuery::new_unsized(path, target_query)),
+            }
+        }
+
+        let meter = self.slow_operation_meter();
+        self.observe_slow("prove", [], None, meter.as_ref(), || {
+            self.prove_internal(&path_queries, false, meter.as_ref(), None, transaction)
+        })
+    }
+
+    /// Adds values of deduplicated items proved by the subtree proof by their
+    /// hashes, as the items themselves keep the hashes only
+    fn collect_dedup_values(
+        &self,
+        subtree_proof: &[u8],
+        values: &mut BTreeMap<[u8; 32], Vec<u8>>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        for op in proofs::Decoder::new(subtree_proof) {
+            let op = op.map_err(|e| {
+                Error::CorruptedData(format!("unable to decode subtree proof: {}", e))
+            })?;
+            if let proofs::Op::Push(proofs::Node::KV(_, value)) = op {
+                if let Ok(Element::DedupItem(hash)) = bincode::deserialize(&value) {
+                    if !values.contains_key(&hash) {
+                        values.insert(hash, self.dedup_value(&hash, transaction)?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds keys of elements on the chain of references starting at the path
+    /// to queries of their subtrees
+    fn collect_reference_targets(
+        &self,
+        mut path: Vec<Vec<u8>>,
+        target_queries: &mut BTreeMap<Vec<Vec<u8>>, Query>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        for _ in 0..MAX_REFERENCE_HOPS {
+            let (key, subtree_path) = path
+                .split_last()
+                .ok_or(Error::CorruptedPath("empty path"))?;
+            let subtree_path_iter = subtree_path.iter().map(|x| x.as_slice());
+            if !self.is_subtree(subtree_path_iter.clone(), transaction)? {
+                // Targets in missing subtrees cannot be proved
+                return Ok(());
+            }
+            target_queries
+                .entry(subtree_path.to_vec())
+                .or_insert_with(Query::new)
+                .insert_key(key.clone());
+            match self.get_raw_optional(subtree_path_iter, key, transaction)? {
+                Some(Element::Reference(reference_path)) => path = reference_path,
+                // A dangling reference is proved by absence of its target
+                _ => return Ok(()),
+            }
+        }
+        Err(Error::ReferenceLimit)
+    }
+
+    fn prove_internal(
+        &self,
+        path_queries: &[PathQuery],
+        compress: bool,
+        cost_meter: Option<&CostMeter>,
+        cancellation: Option<&CancellationToken>,
+        transaction: TransactionArg,
+    ) -> Result<Vec<u8>, Error> {
+        if transaction.is_none() {
+            // Subqueries have to be expanded in the state being proved
+            let snapshot = self.start_snapshot_transaction();
+            return self.prove_internal(
+                path_queries,
+                compress,
+                cost_meter,
+                cancellation,
+                Some(&snapshot),
+            );
+        }
+        let _slot = self.acquire_query_slot()?;
+        let mut expanded_queries = Vec::with_capacity(path_queries.len());
+        for path_query in path_queries {
+            self.path_limits.check_path_query(path_query)?;
+            self.expand_subqueries(path_query.clone(), &mut expanded_queries, transaction)?;
+        }
+
+        let mut query_paths = Vec::with_capacity(expanded_queries.len());
+        // Subtrees on paths to queried subtrees with keys to prove
+        let mut intermediate_queries: BTreeMap<Vec<Vec<u8>>, Query> = BTreeMap::new();
+        let mut leaf_queries: BTreeMap<Vec<Vec<u8>>, &SizedQuery> = BTreeMap::new();
+
+        for path_query in &expanded_queries {
+            let path = &path_query.path;
+            for i in 0..path.len() {
+                intermediate_queries
+                    .entry(path[..i].to_vec())
+                    .or_insert_with(Query::new)
+                    .insert_key(path[i].clone());
+            }
+            if leaf_queries
+                .insert(path.clone(), &path_query.query)
+                .is_some()
+            {
+                return Err(Error::InvalidQuery(
+                    "only one query per subtree can be proved",
+                ));
+            }
+            query_paths.push(path.clone());
+        }
+
+        let mut proofs = HashMap::new();
+        for (path, mut query) in intermediate_queries {
+            if let Some(leaf_query) = leaf_queries.remove(&path) {
+                // The subtree is both queried and on a path to another queried
+                // subtree, so both have to be in one proof
+                if leaf_query.limit.is_some() || leaf_query.offset.is_some() {
+                    return Err(Error::InvalidQuery(
+                        "subtree on a path of another query cannot be proved with limit or offset",
+                    ));
+                }
+                for item in leaf_query.query.iter() {
+                    query.insert_item(item.clone());
+                }
+            }
+            if let Some(cancellation) = cancellation {
+                cancellation.check()?;
+            }
+            let proof = self.prove_subtree(&path, query, None, None, transaction)?;
+            if let Some(meter) = cost_meter {
+                meter.add_seek(proof.len())?;
+            }
+            proofs.insert(Self::subtree_prefix(&path), proof);
+        }
+        for (path, sized_query) in leaf_queries {
+            if let Some(cancellation) = cancellation {
+                cancellation.check()?;
+            }
+            let proof = self.prove_subtree(
+                &path,
+                sized_query.query.clone(),
+                sized_query.limit,
+                sized_query.offset,
+                transaction,
+            )?;
+            if let Some(meter) = cost_meter {
+                meter.add_seek(proof.len())?;
+            }
+            proofs.insert(Self::subtree_prefix(&path), proof);
+        }
+
+        let mut dedup_values = BTreeMap::new();
+        for path in &query_paths {
+            if let Some(subtree_proof) = proofs.get(&Self::subtree_prefix(path)) {
+                self.collect_dedup_values(subtree_proof, &mut dedup_values, transaction)?;
+            }
+        }
+
+        let mut proof = Proof {
+            query_paths,
+            proofs,
+            shared_nodes: Vec::new(),
+            dedup_values: dedup_values.into_values().collect(),
+        };
+        proof.share_nodes()?;
+        proof.to_bytes(compress)
+    }
+
+    /// Generates a separate proof for each path query in parallel on rayon
+    /// thread pool, proofs are returned in the order of queries. All proofs
+    /// are made from one storage snapshot, so they are made against the same
+    /// root hash. Queries are proved one by one if the storage backend can't
+    /// share a snapshot between threads.
+    pub fn prove_queries_parallel(&self, queries: Vec<PathQuery>) -> Result<Vec<Vec<u8>>, Error> {
+        match DynStorage::snapshot(&*self.db) {
+            Some(snapshot) => queries
+                .par_iter()
+                .map(|query| {
+                    let transaction = Transaction::new(self, || snapshot.start_transaction());
+                    self.prove(std::slice::from_ref(query), Some(&transaction))
+                })
+                .collect(),
+            None => {
+                let snapshot = self.start_snapshot_transaction();
+                queries
+                    .iter()
+                    .map(|query| self.prove(std::slice::from_ref(query), Some(&snapshot)))
+                    .collect()
+            }
+        }
+    }
+
+    /// Verifies a proof made with [`GroveDb::prove`], see
+    /// [`crate::execute_proof`]
+    pub fn execute_proof(proof: &[u8]) -> Result<([u8; 32], HashMap<Vec<Vec<u8>>, Map>), Error> {
+        verify::execute_proof(proof)
+    }
+
+    /// Executes a proof within `limits`, see
+    /// [`crate::verify_query_with_limits`]
+    pub fn verify_query_with_limits(
+        proof: &[u8],
+        limits: &ProofLimits,
+    ) -> Result<([u8; 32], HashMap<Vec<Vec<u8>>, Map>), Error> {
+        verify::verify_query_with_limits(proof, limits)
+    }
+
+    /// Adds the path query to `expanded` if it has no subqueries. Otherwise
+    /// adds the query of its subtree without subqueries, which proves the
+    /// subtrees the subqueries are applied to, and expands queries of those
+    /// subtrees in turn.
+    fn expand_subqueries(
+        &self,
+        path_query: PathQuery,
+        expanded: &mut Vec<PathQuery>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        let query = &path_query.query.query;
+        if query.default_subquery_branch.subquery_key.is_none()
+            && query.default_subquery_branch.subquery.is_none()
+            && query.conditional_subquery_branches.is_empty()
+        {
+            expanded.push(path_query);
+            return Ok(());
+        }
+        if path_query.query.limit.is_some() || path_query.query.offset.is_some() {
+            return Err(Error::InvalidQuery(
+                "subqueries with limit or offset cannot be proved",
+            ));
+        }
+
+        let mut subtree_query = Query::new_with_direction(query.left_to_right);
+        for item in query.iter() {
+            subtree_query.insert_item(item.clone());
+        }
+        let subtree_query = PathQuery::new_unsized(path_query.path.clone(), subtree_query);
+        let (results, _) = self.get_path_query_result_elements(&subtree_query, transaction)?;
+        expanded.push(subtree_query);
+
+        for result in results.elements {
+            if !matches!(result.element, Element::Tree(_)) {
+                continue;
+            }
+            let mut path = path_query.path.clone();
+            path.push(result.key.clone());
+            match Element::subquery_paths_for_sized_query(&path_query.query, &result.key) {
+                (Some(subquery_key), Some(subquery)) => {
+                    let mut subquery_path = path.clone();
+                    subquery_path.push(subquery_key.clone());
+                    if self.is_subtree(subquery_path.iter().map(|x| x.as_slice()), transaction)? {
+                        let subquery = PathQuery::new_unsized(subquery_path, subquery);
+                        self.expand_subqueries(subquery, expanded, transaction)?;
+                    } else {
+                        // Proves the subquery key isn't a subtree
+                        let mut query = Query::new();
+                        query.insert_key(subquery_key);
+                        expanded.push(PathQuery::new_unsized(path, query));
+                    }
+                }
+                (None, Some(subquery)) => {
+                    let subquery = PathQuery::new_unsized(path, subquery);
+                    self.expand_subqueries(subquery, expanded, transaction)?;
+                }
+                (Some(subquery_key), None) => {
+                    let mut query = Query::new();
+                    query.insert_key(subquery_key);
+                    expanded.push(PathQuery::new_unsized(path, query));
+                }
+                (None, None) => {
+                    return Err(Error::InvalidPath(
+                        "you must provide a subquery or a subquery_key when interacting with a \
+                         tree of trees",
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn prove_subtree(
+        &self,
+        path: &[Vec<u8>],
+        mut query: Query,
+        limit: Option<u16>,
+        offset: Option<u16>,
+        transaction: TransactionArg,
+    ) -> Result<Vec<u8>, Error> {
+        // Redundant query items would add duplicate nodes to the proof
+        query.normalize();
+        let path_iter = path.iter().map(|x| x.as_slice());
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        let proof = merk_optional_tx!(self.db, path_iter.clone(), transaction, subtree, {
+            if subtree.is_empty_tree() {
+                Ok(Vec::new())
+            } else {
+                subtree
+                    .prove(query, limit, offset)
+                    .map_err(|e| Error::CorruptedData(format!("unable to generate proof: {}", e)))
+            }
+        });
+        if let Err(e) = &proof {
+            self.quarantine_subtree_on_corruption(path_iter, e)?;
+        }
+        proof
+    }
+}
+
+impl Proof {
+    /// Moves push operations occurring more than once into shared nodes and
+    /// replaces them with references, unless a node is shorter than a
+    /// reference
+    pub(crate) fn share_nodes(&mut self) -> Result<(), Error> {
+        let mut subtree_proofs: Vec<_> = self.proofs.iter_mut().collect();
+        subtree_proofs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut decoded = Vec::with_capacity(subtree_proofs.len());
+        let mut occurrences: HashMap<Vec<u8>, usize> = HashMap::new();
+        for (_, subtree_proof) in &subtree_proofs {
+            let mut ops = Vec::new();
+            for op in proofs::Decoder::new(subtree_proof) {
+                let op = op.map_err(|e| {
+                    Error::CorruptedData(format!("unable to decode subtree proof: {}", e))
+                })?;
+                let mut encoded = Vec::new();
+                proofs::encode_into(std::iter::once(&op), &mut encoded);
+                if matches!(op, proofs::Op::Push(_)) && encoded.len() > PROOF_NODE_REF_LENGTH {
+                    *occurrences.entry(encoded.clone()).or_default() += 1;
+                }
+                ops.push(encoded);
+            }
+            decoded.push(ops);
+        }
+
+        let mut shared_indices: HashMap<Vec<u8>, u32> = HashMap::new();
+        for ((_, subtree_proof), ops) in subtree_proofs.into_iter().zip(decoded) {
+            subtree_proof.clear();
+            for encoded in ops {
+                if occurrences.get(&encoded).copied().unwrap_or_default() > 1 {
+                    let index = match shared_indices.get(&encoded) {
+                        Some(index) => *index,
+                        None => {
+                            let index = self.shared_nodes.len() as u32;
+                            shared_indices.insert(encoded.clone(), index);
+                            self.shared_nodes.push(encoded);
+                            index
+                        }
+                    };
+                    subtree_proof.push(PROOF_NODE_REF);
+                    subtree_proof.extend(index.to_be_bytes());
+                } else {
+                    subtree_proof.extend(encoded);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_bytes(&self, compress: bool) -> Result<Vec<u8>, Error> {
+        let serialized = bincode::serialize(self)
+            .map_err(|_| Error::CorruptedData(String::from("unable to serialize proof")))?;
+        if compress {
+            let mut bytes = vec![PROOF_FORMAT_VERSION, PROOF_ZSTD];
+            zstd::stream::copy_encode(serialized.as_slice(), &mut bytes, PROOF_ZSTD_LEVEL)?;
+            Ok(bytes)
+        } else {
+            let mut bytes = Vec::with_capacity(serialized.len() + 2);
+            bytes.extend([PROOF_FORMAT_VERSION, PROOF_UNCOMPRESSED]);
+            bytes.extend(serialized);
+            Ok(bytes)
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use storage::{rocksdb_storage::RocksDbStorage, StorageContext};
+
+use crate::{
+    util::meta_storage_context_optional_tx, version::Feature, Element, Error, GroveDb, KeyChangeOp,
+    MutationKind, TransactionArg,
+};
+
+/// A prefix of keys in meta storage to mark pruned subtrees, followed by a
+/// subtree prefix. The element of a pruned subtree is kept as is, so the
+/// pruned status is not hashed into its parent.
+const PRUNED_SUBTREE_KEY_PREFIX: &[u8] = b"pruned_subtree";
+
+pub(crate) fn pruned_subtree_key<'p, P>(path: P, key: &'p [u8]) -> Vec<u8>
+where
+    P: IntoIterator<Item = &'p [u8]>,
+{
+    let mut meta_key = PRUNED_SUBTREE_KEY_PREFIX.to_vec();
+    meta_key.extend(RocksDbStorage::build_prefix(
+        path.into_iter().chain(std::iter::once(key)),
+    ));
+    meta_key
+}
+
+impl GroveDb {
+    /// Removes data of the subtree at the path and of all its nested subtrees.
+    /// With `keep_root_hash` the subtree element is kept and the subtree is
+    /// marked as pruned outside of hashed data, so the root hash doesn't
+    /// change and the pruned digest stays provable through the parent. The
+    /// element is read as [`Element::PrunedTree`] with the subtree root hash
+    /// then. Otherwise the subtree is deleted. Pruned subtrees can't be
+    /// accessed, but may be replaced by inserting a new element under the
+    /// key. Keeping the root hash requires [`Feature::PrunedTrees`] to be
+    /// enabled.
+    pub fn prune_subtree<'p, P>(
+        &self,
+        path: P,
+        keep_root_hash: bool,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        if transaction.is_none() {
+            // Back references, indices and delegate writes are committed together
+            // with the pruning, so a failure part way through leaves nothing behind
+            let tx = self.start_transaction();
+            self.prune_subtree(path, keep_root_hash, Some(&tx))?;
+            return self.commit_transaction(tx);
+        }
+
+        let mut path_iter = path.into_iter();
+        let key = path_iter
+            .next_back()
+            .ok_or(Error::InvalidPath("root tree cannot be pruned"))?;
+        self.check_not_frozen(path_iter.clone(), key)?;
+        self.check_access(path_iter.clone(), key, MutationKind::Prune)?;
+        let element = self.get_raw(path_iter.clone(), key, transaction)?;
+        let root_hash = match element {
+            Element::Tree(root_hash) => root_hash,
+            _ => return Err(Error::InvalidPath("only subtrees can be pruned")),
+        };
+        if !keep_root_hash {
+            return self.delete(path_iter, key, transaction);
+        }
+        self.require_feature(Feature::PrunedTrees)?;
+
+        self.check_deletion_references(path_iter.clone(), key, transaction)?;
+        self.clear_subtree(path_iter.clone(), key, transaction)?;
+        self.remove_child_subtree(path_iter.clone(), key, transaction)?;
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            meta_storage.put_meta(pruned_subtree_key(path_iter.clone(), key), &[])?;
+        });
+        let pruned = Element::PrunedTree(root_hash);
+        self.record_key_change(path_iter.clone(), key, KeyChangeOp::Put, transaction);
+        self.notify_index_delegates(path_iter, key, Some(&element), Some(&pruned), transaction)?;
+        Ok(())
+    }
+
+    /// Returns whether the subtree under the key is pruned
+    pub(crate) fn is_pruned<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<bool, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            Ok(meta_storage
+                .get_meta(pruned_subtree_key(path, key))?
+                .is_some())
+        })
+    }
+
+    /// Removes the pruned mark of the subtree under the key once its element
+    /// is replaced or deleted
+    pub(crate) fn unmark_pruned<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            meta_storage.delete_meta(pruned_subtree_key(path, key))?;
+        });
+        Ok(())
+    }
+
+    /// Reads a stored subtree element as [`Element::PrunedTree`] if the
+    /// subtree is pruned
+    pub(crate) fn with_pruned_status<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        element: Element,
+        transaction: TransactionArg,
+    ) -> Result<Element, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        match element {
+            Element::Tree(root_hash) if self.is_pruned(path, key, transaction)? => {
+                Ok(Element::PrunedTree(root_hash))
+            }
+            element => Ok(element),
+        }
+    }
+}
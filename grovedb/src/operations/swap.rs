@@ -0,0 +1,202 @@
+use merk::{Merk, Op};
+use storage::Storage;
+
+use crate::{Element, Error, GroveDb, KeyChangeOp, MutationKind, TransactionArg};
+
+impl GroveDb {
+    /// Swaps elements at two keys, which may be in different subtrees, so
+    /// neither side is ever observed moved alone. Both elements must exist
+    /// and be items or references, subtrees can't be moved. Both sides are
+    /// checked before anything is written, so a swap rejected by the checks
+    /// leaves the transaction unchanged, and root hashes are propagated in one
+    /// pass.
+    /// Deduplicated items are moved as they are, keeping their reference
+    /// counts. Without a transaction the swap is committed in one of its own.
+    pub fn swap<'p, P, Q>(
+        &self,
+        path_a: P,
+        key_a: &'p [u8],
+        path_b: Q,
+        key_b: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+        Q: IntoIterator<Item = &'p [u8]>,
+        <Q as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let path_a: Vec<&[u8]> = path_a.into_iter().collect();
+        let path_b: Vec<&[u8]> = path_b.into_iter().collect();
+        match transaction {
+            Some(tx) => self.swap_in_transaction(&path_a, key_a, &path_b, key_b, tx),
+            None => {
+                let tx = self.start_transaction();
+                self.swap_in_transaction(&path_a, key_a, &path_b, key_b, &tx)?;
+                self.commit_transaction(tx)
+            }
+        }
+    }
+
+    fn swap_in_transaction(
+        &self,
+        path_a: &[&[u8]],
+        key_a: &[u8],
+        path_b: &[&[u8]],
+        key_b: &[u8],
+        tx: &crate::Transaction,
+    ) -> Result<(), Error> {
+        let element_a = self.swapped_element(path_a, key_a, tx)?;
+        let element_b = self.swapped_element(path_b, key_b, tx)?;
+        if path_a == path_b && key_a == key_b {
+            return Ok(());
+        }
+
+        let serialized_a = serialize_element(&element_a)?;
+        let serialized_b = serialize_element(&element_b)?;
+        if path_a == path_b {
+            // Merk takes a batch sorted by key
+            let mut batch = [
+                (key_a, Op::Put(serialized_b)),
+                (key_b, Op::Put(serialized_a)),
+            ];
+            batch.sort_by(|(a, _), (b, _)| a.cmp(b));
+            self.apply_swap_batch(path_a, &batch, tx)?;
+        } else {
+            self.apply_swap_batch(path_a, &[(key_a, Op::Put(serialized_b))], tx)?;
+            self.apply_swap_batch(path_b, &[(key_b, Op::Put(serialized_a))], tx)?;
+        }
+        self.propagate_swap(path_a, path_b, tx)?;
+
+        self.update_swapped_location(path_a, key_a, &element_a, &element_b, tx)?;
+        self.update_swapped_location(path_b, key_b, &element_b, &element_a, tx)
+    }
+
+    /// Checks a side of a swap may be written and returns its element as
+    /// stored
+    fn swapped_element(
+        &self,
+        path: &[&[u8]],
+        key: &[u8],
+        tx: &crate::Transaction,
+    ) -> Result<Element, Error> {
+        self.path_limits
+            .check_path(path.iter().copied(), Some(key))?;
+        self.check_not_frozen(path.iter().copied(), key)?;
+        self.check_access(path.iter().copied(), key, MutationKind::Insert)?;
+        self.check_transaction_scope(path.iter().copied(), Some(tx))?;
+        match self.get_raw(path.iter().copied(), key, Some(tx))? {
+            Element::Tree(_) | Element::PrunedTree(_) => {
+                Err(Error::InvalidQuery("subtrees cannot be swapped"))
+            }
+            element => Ok(element),
+        }
+    }
+
+    fn apply_swap_batch(
+        &self,
+        path: &[&[u8]],
+        batch: &[(&[u8], Op)],
+        tx: &crate::Transaction,
+    ) -> Result<(), Error> {
+        let storage = self
+            .db
+            .get_transactional_storage_context(path.iter().copied(), tx);
+        let mut subtree = Merk::open(storage)
+            .map_err(|_| Error::CorruptedData("cannot open a subtree".to_owned()))?;
+        subtree
+            .apply::<_, Vec<u8>>(batch, &[])
+            .map_err(|e| Error::CorruptedData(e.to_string()))
+    }
+
+    /// Propagates root hashes of both swapped subtrees, the deeper one first,
+    /// until their paths meet, so common ancestors are updated once
+    fn propagate_swap(
+        &self,
+        path_a: &[&[u8]],
+        path_b: &[&[u8]],
+        tx: &crate::Transaction,
+    ) -> Result<(), Error> {
+        let stop_a = self
+            .check_transaction_scope(path_a.iter().copied(), Some(tx))?
+            .unwrap_or(0);
+        let stop_b = self
+            .check_transaction_scope(path_b.iter().copied(), Some(tx))?
+            .unwrap_or(0);
+        let (mut len_a, mut len_b) = (path_a.len(), path_b.len());
+        while path_a[..len_a] != path_b[..len_b] {
+            if len_a >= len_b && len_a > stop_a {
+                self.propagate_subtree_root_hash(&path_a[..len_a], tx)?;
+                len_a -= 1;
+            } else if len_b > stop_b {
+                self.propagate_subtree_root_hash(&path_b[..len_b], tx)?;
+                len_b -= 1;
+            } else {
+                return Ok(());
+            }
+        }
+        self.propagate_changes(path_a[..len_a].iter().copied(), Some(tx))
+    }
+
+    /// Writes the root hash of a subtree into its parent
+    fn propagate_subtree_root_hash(
+        &self,
+        path: &[&[u8]],
+        tx: &crate::Transaction,
+    ) -> Result<(), Error> {
+        let (key, parent_path) = path.split_last().expect("path is not empty");
+        let subtree_storage = self
+            .db
+            .get_transactional_storage_context(path.iter().copied(), tx);
+        let subtree = Merk::open(subtree_storage)
+            .map_err(|_| Error::CorruptedData("cannot open a subtree".to_owned()))?;
+        let parent_storage = self
+            .db
+            .get_transactional_storage_context(parent_path.iter().copied(), tx);
+        let mut parent_tree = Merk::open(parent_storage)
+            .map_err(|_| Error::CorruptedData("cannot open a subtree".to_owned()))?;
+        Element::Tree(subtree.root_hash()).insert(&mut parent_tree, key)
+    }
+
+    /// Updates back references, indices, original keys and subscribers of a
+    /// swapped key.
+    /// Deduplicated values aren't released as both elements are still stored.
+    fn update_swapped_location(
+        &self,
+        path: &[&[u8]],
+        key: &[u8],
+        old_element: &Element,
+        new_element: &Element,
+        tx: &crate::Transaction,
+    ) -> Result<(), Error> {
+        self.update_back_references(
+            path.iter().copied(),
+            key,
+            Some(old_element),
+            Some(new_element),
+            Some(tx),
+        )?;
+        self.update_value_hash_index(
+            path.iter().copied(),
+            key,
+            Some(old_element),
+            Some(new_element),
+            Some(tx),
+        )?;
+        // An original key is of the element's normalized key, not the other one
+        self.remove_original_key(path.iter().copied(), key, Some(tx))?;
+        self.record_key_change(path.iter().copied(), key, KeyChangeOp::Put, Some(tx));
+        self.notify_index_delegates(
+            path.iter().copied(),
+            key,
+            Some(old_element),
+            Some(new_element),
+            Some(tx),
+        )
+    }
+}
+
+fn serialize_element(element: &Element) -> Result<Vec<u8>, Error> {
+    bincode::serialize(element)
+        .map_err(|_| Error::CorruptedData(String::from("unable to serialize element")))
+}
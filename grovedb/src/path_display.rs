@@ -0,0 +1,109 @@
+//! Module for readable paths and keys.
+//! Keys are often text, hashes or integers, and byte array debug output of
+//! either is hard to read. [`BytesDisplay`] and [`PathDisplay`] render them
+//! in a configurable encoding, by default as text if it's printable and as
+//! hex otherwise, in the form accepted by the textual query syntax.
+
+use std::fmt;
+
+/// Encoding of bytes of keys and path segments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteEncoding {
+    /// Text if the bytes are printable UTF-8, `0x` prefixed hex otherwise
+    Utf8IfPrintable,
+    /// `0x` prefixed hex
+    Hex,
+    /// Base58 with the Bitcoin alphabet
+    Base58,
+}
+
+impl Default for ByteEncoding {
+    fn default() -> Self {
+        ByteEncoding::Utf8IfPrintable
+    }
+}
+
+/// Returns the bytes as text if they are non-empty UTF-8 without control
+/// characters, which can't be mistaken for hex or a path separator
+pub(crate) fn printable_str(bytes: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    let printable = !text.is_empty()
+        && !text.starts_with("0x")
+        && text.chars().all(|c| !c.is_control() && c != '/');
+    printable.then(|| text)
+}
+
+/// Displays bytes in an encoding, see [`ByteEncoding`]
+#[derive(Debug, Clone, Copy)]
+pub struct BytesDisplay<'a> {
+    bytes: &'a [u8],
+    encoding: ByteEncoding,
+}
+
+impl<'a> BytesDisplay<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        BytesDisplay {
+            bytes,
+            encoding: ByteEncoding::default(),
+        }
+    }
+
+    pub fn with_encoding(mut self, encoding: ByteEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+}
+
+impl fmt::Display for BytesDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.encoding {
+            ByteEncoding::Utf8IfPrintable => match printable_str(self.bytes) {
+                Some(text) => f.write_str(text),
+                None => write!(f, "0x{}", hex::encode(self.bytes)),
+            },
+            ByteEncoding::Hex => write!(f, "0x{}", hex::encode(self.bytes)),
+            ByteEncoding::Base58 => f.write_str(&bs58::encode(self.bytes).into_string()),
+        }
+    }
+}
+
+/// Displays a path as segments separated by slashes after a leading one, so
+/// the root path is `/`
+#[derive(Debug, Clone)]
+pub struct PathDisplay<'a> {
+    segments: Vec<&'a [u8]>,
+    encoding: ByteEncoding,
+}
+
+impl<'a> PathDisplay<'a> {
+    pub fn new<P>(path: P) -> Self
+    where
+        P: IntoIterator<Item = &'a [u8]>,
+    {
+        PathDisplay {
+            segments: path.into_iter().collect(),
+            encoding: ByteEncoding::default(),
+        }
+    }
+
+    pub fn with_encoding(mut self, encoding: ByteEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+}
+
+impl fmt::Display for PathDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.segments.is_empty() {
+            return f.write_str("/");
+        }
+        for segment in &self.segments {
+            write!(
+                f,
+                "/{}",
+                BytesDisplay::new(segment).with_encoding(self.encoding)
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+//! Module for RocksDB perf context capture.
+//! Tells where the time of a group of operations went, e.g. whether reads
+//! missed the block cache or writes were stalled by compaction.
+
+use std::time::{Duration, Instant};
+
+use storage::rocksdb_storage::PerfScope;
+
+use crate::GroveDb;
+
+/// RocksDB work attributable to operations run by [`GroveDb::with_perf`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PerfReport {
+    /// Number of blocks found in the block cache
+    pub block_cache_hit_count: u64,
+    /// Number of blocks read from storage files, i.e. block cache misses
+    pub block_read_count: u64,
+    /// Number of bytes of blocks read from storage files
+    pub block_read_bytes: u64,
+    /// Number of bytes of values read by point lookups and iterators
+    pub read_bytes: u64,
+    /// Number of seeks in storage files
+    pub seek_count: u64,
+    /// Time writes were delayed or stopped by write stalls
+    pub write_stall: Duration,
+    /// Wall time of the scope
+    pub duration: Duration,
+}
+
+impl GroveDb {
+    /// Runs the closure with RocksDB perf context collection enabled and
+    /// returns its result with the counters of the scope. Only operations
+    /// made on the current thread are counted.
+    pub fn with_perf<T>(&self, f: impl FnOnce(&Self) -> T) -> (T, PerfReport) {
+        let perf = PerfScope::start();
+        let started = Instant::now();
+        let result = f(self);
+        let duration = started.elapsed();
+        let counters = perf.finish();
+        let report = PerfReport {
+            block_cache_hit_count: counters.block_cache_hit_count,
+            block_read_count: counters.block_read_count,
+            block_read_bytes: counters.block_read_bytes,
+            read_bytes: counters.read_bytes,
+            seek_count: counters.seek_count,
+            write_stall: Duration::from_nanos(counters.write_stall_nanos),
+            duration,
+        };
+        (result, report)
+    }
+}
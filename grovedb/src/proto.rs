@@ -0,0 +1,294 @@
+//! Module for the protobuf representation of path queries and proofs.
+//! gRPC based platforms exchange queries and proofs as messages defined in
+//! `proto/grovedb.proto`. Types of this module are generated from it by
+//! prost-build and convert from and to GroveDB types.
+
+use std::collections::HashMap;
+
+use merk::proofs::query::SubqueryBranch as MerkSubqueryBranch;
+
+use crate::Error;
+
+include!(concat!(env!("OUT_DIR"), "/grovedb.rs"));
+
+impl From<crate::QueryItem> for QueryItem {
+    fn from(item: crate::QueryItem) -> Self {
+        use query_item::Item;
+        let range = |start, end| Range { start, end };
+        let item = match item {
+            crate::QueryItem::Key(key) => Item::Key(key),
+            crate::QueryItem::Range(r) => Item::Range(range(r.start, r.end)),
+            crate::QueryItem::RangeInclusive(r) => {
+                let (start, end) = r.into_inner();
+                Item::RangeInclusive(range(start, end))
+            }
+            crate::QueryItem::RangeFull(_) => Item::RangeFull(RangeFull {}),
+            crate::QueryItem::RangeFrom(r) => Item::RangeFrom(r.start),
+            crate::QueryItem::RangeTo(r) => Item::RangeTo(r.end),
+            crate::QueryItem::RangeToInclusive(r) => Item::RangeToInclusive(r.end),
+            crate::QueryItem::RangeAfter(r) => Item::RangeAfter(r.start),
+            crate::QueryItem::RangeAfterTo(r) => Item::RangeAfterTo(range(r.start, r.end)),
+            crate::QueryItem::RangeAfterToInclusive(r) => {
+                let (start, end) = r.into_inner();
+                Item::RangeAfterToInclusive(range(start, end))
+            }
+        };
+        QueryItem { item: Some(item) }
+    }
+}
+
+impl TryFrom<QueryItem> for crate::QueryItem {
+    type Error = Error;
+
+    fn try_from(item: QueryItem) -> Result<Self, Self::Error> {
+        use query_item::Item;
+        let item = match item
+            .item
+            .ok_or(Error::InvalidQuery("query item is missing"))?
+        {
+            Item::Key(key) => Self::Key(key),
+            Item::Range(r) => Self::Range(r.start..r.end),
+            Item::RangeInclusive(r) => Self::RangeInclusive(r.start..=r.end),
+            Item::RangeFull(_) => Self::RangeFull(..),
+            Item::RangeFrom(start) => Self::RangeFrom(start..),
+            Item::RangeTo(end) => Self::RangeTo(..end),
+            Item::RangeToInclusive(end) => Self::RangeToInclusive(..=end),
+            Item::RangeAfter(start) => Self::RangeAfter(start..),
+            Item::RangeAfterTo(r) => Self::RangeAfterTo(r.start..r.end),
+            Item::RangeAfterToInclusive(r) => Self::RangeAfterToInclusive(r.start..=r.end),
+        };
+        Ok(item)
+    }
+}
+
+impl From<MerkSubqueryBranch> for SubqueryBranch {
+    fn from(branch: MerkSubqueryBranch) -> Self {
+        SubqueryBranch {
+            subquery_key: branch.subquery_key,
+            subquery: branch.subquery.map(|query| Box::new((*query).into())),
+        }
+    }
+}
+
+impl TryFrom<SubqueryBranch> for MerkSubqueryBranch {
+    type Error = Error;
+
+    fn try_from(branch: SubqueryBranch) -> Result<Self, Self::Error> {
+        Ok(MerkSubqueryBranch {
+            subquery_key: branch.subquery_key,
+            subquery: branch
+                .subquery
+                .map(|query| (*query).try_into().map(Box::new))
+                .transpose()?,
+        })
+    }
+}
+
+impl From<crate::Query> for Query {
+    fn from(query: crate::Query) -> Self {
+        Query {
+            items: query.iter().cloned().map(Into::into).collect(),
+            default_subquery_branch: Some(Box::new(query.default_subquery_branch.into())),
+            conditional_subquery_branches: query
+                .conditional_subquery_branches
+                .into_iter()
+                .map(|(item, branch)| ConditionalSubqueryBranch {
+                    item: Some(item.into()),
+                    branch: Some(Box::new(branch.into())),
+                })
+                .collect(),
+            left_to_right: query.left_to_right,
+        }
+    }
+}
+
+impl TryFrom<Query> for crate::Query {
+    type Error = Error;
+
+    fn try_from(query: Query) -> Result<Self, Self::Error> {
+        let mut result = crate::Query::new_with_direction(query.left_to_right);
+        for item in query.items {
+            result.insert_item(item.try_into()?);
+        }
+        if let Some(branch) = query.default_subquery_branch {
+            result.default_subquery_branch = (*branch).try_into()?;
+        }
+        for conditional in query.conditional_subquery_branches {
+            let item = conditional
+                .item
+                .ok_or(Error::InvalidQuery("conditional subquery item is missing"))?;
+            let branch = conditional.branch.ok_or(Error::InvalidQuery(
+                "conditional subquery branch is missing",
+            ))?;
+            result
+                .conditional_subquery_branches
+                .insert(item.try_into()?, (*branch).try_into()?);
+        }
+        Ok(result)
+    }
+}
+
+impl From<crate::SizedQuery> for SizedQuery {
+    fn from(sized_query: crate::SizedQuery) -> Self {
+        SizedQuery {
+            query: Some(sized_query.query.into()),
+            limit: sized_query.limit.map(u32::from),
+            offset: sized_query.offset.map(u32::from),
+        }
+    }
+}
+
+impl TryFrom<SizedQuery> for crate::SizedQuery {
+    type Error = Error;
+
+    fn try_from(sized_query: SizedQuery) -> Result<Self, Self::Error> {
+        let to_u16 = |value: Option<u32>| {
+            value
+                .map(u16::try_from)
+                .transpose()
+                .map_err(|_| Error::InvalidQuery("query limit or offset is too large"))
+        };
+        let query = sized_query
+            .query
+            .ok_or(Error::InvalidQuery("query is missing"))?;
+        Ok(crate::SizedQuery::new(
+            query.try_into()?,
+            to_u16(sized_query.limit)?,
+            to_u16(sized_query.offset)?,
+        ))
+    }
+}
+
+impl From<crate::PathQuery> for PathQuery {
+    fn from(path_query: crate::PathQuery) -> Self {
+        PathQuery {
+            path: path_query.path,
+            query: Some(path_query.query.into()),
+        }
+    }
+}
+
+impl TryFrom<PathQuery> for crate::PathQuery {
+    type Error = Error;
+
+    fn try_from(path_query: PathQuery) -> Result<Self, Self::Error> {
+        let query = path_query
+            .query
+            .ok_or(Error::InvalidQuery("query is missing"))?;
+        Ok(crate::PathQuery::new(path_query.path, query.try_into()?))
+    }
+}
+
+impl Proof {
+    /// Converts a proof made with [`GroveDb::prove`](crate::GroveDb::prove),
+    /// compressed or not. Nothing is verified.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut proof = crate::Proof::from_bytes(bytes, &crate::ProofLimits::default())?;
+        // Shared nodes were expanded on deserialization
+        proof.share_nodes()?;
+        let mut subtree_proofs: Vec<SubtreeProof> = proof
+            .proofs
+            .into_iter()
+            .map(|(prefix, proof)| SubtreeProof { prefix, proof })
+            .collect();
+        subtree_proofs.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+        Ok(Proof {
+            query_paths: proof
+                .query_paths
+                .into_iter()
+                .map(|segments| Path { segments })
+                .collect(),
+            subtree_proofs,
+            shared_nodes: proof.shared_nodes,
+            dedup_values: proof.dedup_values,
+        })
+    }
+
+    /// Converts the proof back to bytes accepted by proof verification
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let proof = crate::Proof {
+            query_paths: self
+                .query_paths
+                .iter()
+                .map(|path| path.segments.clone())
+                .collect(),
+            proofs: self
+                .subtree_proofs
+                .iter()
+                .map(|subtree_proof| (subtree_proof.prefix.clone(), subtree_proof.proof.clone()))
+                .collect::<HashMap<_, _>>(),
+            shared_nodes: self.shared_nodes.clone(),
+            dedup_values: self.dedup_values.clone(),
+        };
+        proof.to_bytes(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use super::*;
+    use crate::{
+        tests::{make_grovedb, TEST_LEAF},
+        Element, GroveDb,
+    };
+
+    #[test]
+    fn test_path_query_roundtrip() {
+        let mut subquery = crate::Query::new();
+        subquery.insert_range_after(b"a".to_vec()..);
+        let mut query = crate::Query::new_with_direction(false);
+        query.insert_key(b"key".to_vec());
+        query.insert_range_inclusive(b"b".to_vec()..=b"c".to_vec());
+        query.set_subquery(subquery);
+        let path_query = crate::PathQuery::new(
+            vec![TEST_LEAF.to_vec()],
+            crate::SizedQuery::new(query, Some(5), None),
+        );
+
+        let encoded = PathQuery::from(path_query.clone()).encode_to_vec();
+        let decoded: crate::PathQuery = PathQuery::decode(encoded.as_slice())
+            .expect("valid message")
+            .try_into()
+            .expect("valid path query");
+        assert_eq!(decoded.cache_key(), path_query.cache_key());
+
+        let too_large = PathQuery {
+            path: vec![],
+            query: Some(SizedQuery {
+                query: Some(Query::default()),
+                limit: Some(u32::MAX),
+                offset: None,
+            }),
+        };
+        assert!(matches!(
+            crate::PathQuery::try_from(too_large),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_proof_roundtrip() {
+        let db = make_grovedb();
+        db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+            .expect("successful item insert");
+        let mut query = crate::Query::new();
+        query.insert_key(b"key".to_vec());
+        let path_query = crate::PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+        let proof = db.prove(&[path_query], None).expect("successful prove");
+
+        let message = Proof::from_bytes(&proof).expect("valid proof");
+        let decoded = Proof::decode(message.encode_to_vec().as_slice()).expect("valid message");
+        let (root_hash, results) =
+            GroveDb::execute_proof(&decoded.to_bytes().expect("successful conversion"))
+                .expect("valid proof");
+        assert_eq!(root_hash, db.root_hash(None).unwrap().unwrap());
+        let value = results[&vec![TEST_LEAF.to_vec()]]
+            .get(b"key")
+            .expect("valid result map")
+            .expect("proved key");
+        let element: Element = bincode::deserialize(value).expect("valid element");
+        assert_eq!(element, Element::Item(b"value".to_vec()));
+    }
+}
@@ -0,0 +1,76 @@
+//! Module for corrupted data bookkeeping.
+//! If quarantine mode is enabled, elements which fail to be read because of
+//! data corruption are recorded in meta storage, so the corruption doesn't go
+//! unnoticed while unaffected subtrees keep being served. Gets and iteration
+//! record the corrupted element, while queries and proofs, which don't tell
+//! it, record the queried subtree.
+
+use storage::{Storage, StorageContext};
+
+use crate::{Error, GroveDb, RocksDbStorage};
+
+/// A key in meta storage to store quarantined entries
+const QUARANTINE_KEY: &[u8] = b"quarantine";
+
+impl GroveDb {
+    /// Enables or disables recording of corrupted elements for all following
+    /// reads, see [`GroveDb::quarantined`]
+    pub fn set_quarantine_mode(&mut self, enabled: bool) {
+        self.quarantine_mode = enabled;
+    }
+
+    /// Returns entries recorded as corrupted in quarantine mode, each one is a
+    /// subtree prefix and a key of an element, the key is empty if the whole
+    /// subtree is recorded
+    pub fn quarantined(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Error> {
+        let meta_storage = self.db.get_storage_context(std::iter::empty());
+        if let Some(serialized) = meta_storage.get_meta(QUARANTINE_KEY)? {
+            bincode::deserialize(&serialized)
+                .map_err(|_| Error::CorruptedData(String::from("unable to deserialize quarantine")))
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Records an element in quarantine if quarantine mode is enabled and
+    /// the error is caused by data corruption. Quarantine is written outside
+    /// of any transaction to keep it on rollback.
+    pub(crate) fn quarantine_on_corruption<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        error: &Error,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        if !self.quarantine_mode || !matches!(error, Error::CorruptedData(_)) {
+            return Ok(());
+        }
+        let entry = (RocksDbStorage::build_prefix(path), key.to_vec());
+        let mut entries = self.quarantined()?;
+        if entries.contains(&entry) {
+            return Ok(());
+        }
+        entries.push(entry);
+        let serialized = bincode::serialize(&entries)
+            .map_err(|_| Error::CorruptedData(String::from("unable to serialize quarantine")))?;
+        let meta_storage = self.db.get_storage_context(std::iter::empty());
+        meta_storage.put_meta(QUARANTINE_KEY, &serialized)?;
+        Ok(())
+    }
+
+    /// Records the subtree at the path in quarantine like
+    /// [`GroveDb::quarantine_on_corruption`], for reads which don't tell the
+    /// corrupted element
+    pub(crate) fn quarantine_subtree_on_corruption<'p, P>(
+        &self,
+        path: P,
+        error: &Error,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        self.quarantine_on_corruption(path, &[], error)
+    }
+}
@@ -0,0 +1,90 @@
+//! Module for cost-limited query execution.
+//! Public RPC endpoints run queries from untrusted clients, so a pathological
+//! query is aborted once its cost exceeds a limit instead of running until it
+//! is done. Costs are counted as storage seeks and bytes loaded by a query.
+//! Aux data operations may be metered too, with writes counted as written
+//! bytes, so bookkeeping kept in aux storage is charged like tree data.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::Error;
+
+/// Cost of a storage seek in units of loaded bytes
+const SEEK_COST: u64 = 64;
+
+/// Storage work done by a query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryCost {
+    /// Number of storage seeks, including iterator steps
+    pub seek_count: u64,
+    /// Number of bytes of keys and values loaded from storage
+    pub loaded_bytes: u64,
+    /// Number of bytes of keys and values written to storage, a deletion
+    /// writes its key
+    pub written_bytes: u64,
+}
+
+impl QueryCost {
+    /// Returns the total cost, a seek costs as much as loading 64 bytes and
+    /// a written byte as much as a loaded one
+    pub fn total(&self) -> u64 {
+        self.seek_count
+            .saturating_mul(SEEK_COST)
+            .saturating_add(self.loaded_bytes)
+            .saturating_add(self.written_bytes)
+    }
+}
+
+/// Accumulates the cost of a query and fails it once the total cost exceeds
+/// the limit, see [`crate::QueryOptions::cost_meter`]
+#[derive(Debug, Default)]
+pub struct CostMeter {
+    max_cost: u64,
+    seek_count: AtomicU64,
+    loaded_bytes: AtomicU64,
+    written_bytes: AtomicU64,
+}
+
+impl CostMeter {
+    pub fn new(max_cost: u64) -> Self {
+        CostMeter {
+            max_cost,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the cost accumulated so far
+    pub fn cost(&self) -> QueryCost {
+        QueryCost {
+            seek_count: self.seek_count.load(Ordering::Relaxed),
+            loaded_bytes: self.loaded_bytes.load(Ordering::Relaxed),
+            written_bytes: self.written_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Adds a seek loading `loaded_bytes`, fails with
+    /// [`Error::CostLimitExceeded`] and the cost so far if the limit is
+    /// exceeded
+    pub(crate) fn add_seek(&self, loaded_bytes: usize) -> Result<(), Error> {
+        self.seek_count.fetch_add(1, Ordering::Relaxed);
+        self.loaded_bytes
+            .fetch_add(loaded_bytes as u64, Ordering::Relaxed);
+        self.check_limit()
+    }
+
+    /// Adds a write of `written_bytes`, fails like [`CostMeter::add_seek`]
+    pub(crate) fn add_write(&self, written_bytes: usize) -> Result<(), Error> {
+        self.written_bytes
+            .fetch_add(written_bytes as u64, Ordering::Relaxed);
+        self.check_limit()
+    }
+
+    fn check_limit(&self) -> Result<(), Error> {
+        let cost = self.cost();
+        if cost.total() > self.max_cost {
+            Err(Error::CostLimitExceeded(cost))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Module for the textual query syntax.
+//! Path queries can be written as comma separated clauses, for example
+//! `path: a/b/c, range: [k1, k2), limit 10, desc`, and parsed into a
+//! [`PathQuery`] with [`str::parse`]. Supported clauses are:
+//!
+//! * `path: a/b/c` is the path of the queried subtree, `path: /` or no path
+//!   clause query the root tree;
+//! * `key: k` queries a single key;
+//! * `range: [k1, k2)` queries a range of keys, a square bracket includes a
+//!   bound and a parenthesis excludes it, a missing bound leaves the range
+//!   unbounded on that side, e.g. `range: [k1, )`;
+//! * `all` queries all keys;
+//! * `limit 10` and `offset 5`, a colon after the name is optional;
+//! * `asc` and `desc` set the direction, ascending by default.
+//!
+//! Path segments and keys are taken as UTF-8 bytes, unless prefixed with `0x`
+//! to be decoded from hex.
+
+use std::str::FromStr;
+
+use merk::proofs::query::QueryItem;
+
+use crate::{Error, PathQuery, Query, SizedQuery};
+
+/// Splits text by commas outside of range brackets
+fn split_clauses(text: &str) -> Result<Vec<&str>, Error> {
+    let mut clauses = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or(Error::InvalidQuery("unbalanced range brackets"))?
+            }
+            ',' if depth == 0 => {
+                clauses.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(Error::InvalidQuery("unbalanced range brackets"));
+    }
+    clauses.push(text[start..].trim());
+    Ok(clauses
+        .into_iter()
+        .filter(|clause| !clause.is_empty())
+        .collect())
+}
+
+/// Splits a clause into its name and value, the value is separated by a
+/// colon or whitespace
+fn split_clause(clause: &str) -> (&str, &str) {
+    let end = clause
+        .find(|c: char| c == ':' || c.is_whitespace())
+        .unwrap_or(clause.len());
+    let (name, value) = clause.split_at(end);
+    let value = value.trim_start();
+    let value = value.strip_prefix(':').unwrap_or(value);
+    (name, value.trim())
+}
+
+fn parse_bytes(text: &str) -> Result<Vec<u8>, Error> {
+    match text.strip_prefix("0x") {
+        Some(hex_text) => {
+            hex::decode(hex_text).map_err(|_| Error::InvalidQuery("invalid hex in query"))
+        }
+        None => Ok(text.as_bytes().to_vec()),
+    }
+}
+
+fn parse_path(text: &str) -> Result<Vec<Vec<u8>>, Error> {
+    text.split('/')
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .map(parse_bytes)
+        .collect()
+}
+
+fn parse_number(text: &str) -> Result<u16, Error> {
+    text.parse()
+        .map_err(|_| Error::InvalidQuery("invalid number in query"))
+}
+
+fn parse_range(text: &str) -> Result<QueryItem, Error> {
+    let start_inclusive = match text.chars().next() {
+        Some('[') => true,
+        Some('(') => false,
+        _ => return Err(Error::InvalidQuery("range must start with [ or (")),
+    };
+    let end_inclusive = match text.chars().last() {
+        Some(']') => true,
+        Some(')') => false,
+        _ => return Err(Error::InvalidQuery("range must end with ] or )")),
+    };
+    let (start, end) = text[1..text.len() - 1]
+        .split_once(',')
+        .ok_or(Error::InvalidQuery("range must have two bounds"))?;
+    let bound = |bound: &str| -> Result<Option<Vec<u8>>, Error> {
+        let bound = bound.trim();
+        if bound.is_empty() {
+            Ok(None)
+        } else {
+            parse_bytes(bound).map(Some)
+        }
+    };
+    let item = match (bound(start)?, bound(end)?) {
+        (Some(start), Some(end)) => match (start_inclusive, end_inclusive) {
+            (true, false) => QueryItem::Range(start..end),
+            (true, true) => QueryItem::RangeInclusive(start..=end),
+            (false, false) => QueryItem::RangeAfterTo(start..end),
+            (false, true) => QueryItem::RangeAfterToInclusive(start..=end),
+        },
+        (Some(start), None) if start_inclusive => QueryItem::RangeFrom(start..),
+        (Some(start), None) => QueryItem::RangeAfter(start..),
+        (None, Some(end)) if end_inclusive => QueryItem::RangeToInclusive(..=end),
+        (None, Some(end)) => QueryItem::RangeTo(..end),
+        (None, None) => QueryItem::RangeFull(..),
+    };
+    Ok(item)
+}
+
+impl FromStr for PathQuery {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut path = Vec::new();
+        let mut query = Query::new();
+        let mut has_items = false;
+        let mut limit = None;
+        let mut offset = None;
+        for clause in split_clauses(text)? {
+            let (name, value) = split_clause(clause);
+            match name {
+                "path" => path = parse_path(value)?,
+                "key" => query.insert_key(parse_bytes(value)?),
+                "range" => query.insert_item(parse_range(value)?),
+                "all" => query.insert_all(),
+                "limit" => limit = Some(parse_number(value)?),
+                "offset" => offset = Some(parse_number(value)?),
+                "asc" => query.left_to_right = true,
+                "desc" => query.left_to_right = false,
+                _ => return Err(Error::InvalidQuery("unknown query clause")),
+            }
+            has_items |= matches!(name, "key" | "range" | "all");
+        }
+        if !has_items {
+            return Err(Error::InvalidQuery("query has no keys or ranges"));
+        }
+        Ok(PathQuery::new(path, SizedQuery::new(query, limit, offset)))
+    }
+}
@@ -0,0 +1,90 @@
+//! Module for limiting of concurrent queries and proof generation.
+//! A burst of expensive proof requests competes with block processing
+//! writes for storage IO, so the number of queries and proofs running at once
+//! may be capped. Excess ones wait for a slot or fail fast with
+//! [`Error::TooManyQueries`] to be retried later.
+
+use std::{
+    cell::Cell,
+    sync::{Condvar, Mutex},
+};
+
+use crate::{Error, GroveDb, LockWait};
+
+thread_local! {
+    /// Whether the thread holds a query slot, so queries made by a query
+    /// don't wait for a slot of their own
+    static HOLDS_SLOT: Cell<bool> = Cell::new(false);
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    max_concurrent: Option<usize>,
+    wait: Option<LockWait>,
+    running: usize,
+}
+
+/// Number of running queries with their limit
+#[derive(Debug, Default)]
+pub(crate) struct QueryLimiter {
+    state: Mutex<LimiterState>,
+    released: Condvar,
+}
+
+/// Guard of a query slot, the slot is released on drop
+pub(crate) struct QuerySlot<'db> {
+    limiter: Option<&'db QueryLimiter>,
+}
+
+impl Drop for QuerySlot<'_> {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter {
+            HOLDS_SLOT.with(|holds| holds.set(false));
+            let mut state = limiter.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.running -= 1;
+            limiter.released.notify_one();
+        }
+    }
+}
+
+impl GroveDb {
+    /// Limits the number of queries and proof generations running at once,
+    /// `None` removes the limit. Queries over the limit wait for a running
+    /// one to finish or fail depending on `wait`.
+    pub fn set_max_concurrent_queries(&mut self, max_concurrent: Option<usize>, wait: LockWait) {
+        let state = self
+            .query_limiter
+            .state
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner());
+        state.max_concurrent = max_concurrent;
+        state.wait = Some(wait);
+    }
+
+    /// Takes a query slot until the returned guard is dropped
+    pub(crate) fn acquire_query_slot(&self) -> Result<QuerySlot, Error> {
+        if HOLDS_SLOT.with(|holds| holds.get()) {
+            return Ok(QuerySlot { limiter: None });
+        }
+        let limiter = &self.query_limiter;
+        let mut state = limiter.state.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            match (state.max_concurrent, state.wait) {
+                (None, _) => return Ok(QuerySlot { limiter: None }),
+                (Some(max_concurrent), _) if state.running < max_concurrent => break,
+                (_, Some(LockWait::FailFast)) => return Err(Error::TooManyQueries),
+                _ => {
+                    state = limiter
+                        .released
+                        .wait(state)
+                        .unwrap_or_else(|e| e.into_inner());
+                }
+            }
+        }
+        state.running += 1;
+        HOLDS_SLOT.with(|holds| holds.set(true));
+        Ok(QuerySlot {
+            limiter: Some(limiter),
+        })
+    }
+}
@@ -0,0 +1,127 @@
+//! Module for query result memoization.
+//! Between commits affecting other subtrees the same query is frequently
+//! asked again with an identical answer. Results of a query depend only on
+//! the data of the queried subtree and its descendants, which is committed to
+//! by the subtree root hash, so results are memoized by the root hash and the
+//! serialized query, and never need to be invalidated.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{util::merk_optional_tx, Error, GroveDb, PathQuery, TransactionArg};
+
+/// Number of query results memoized by default
+pub const DEFAULT_QUERY_MEMO_CAPACITY: usize = 1024;
+
+/// Root hash of an empty subtree, which is the same as of a missing one
+const EMPTY_SUBTREE_HASH: [u8; 32] = [0; 32];
+
+type MemoKey = ([u8; 32], Vec<u8>);
+type QueryResult = (Vec<Vec<u8>>, u16);
+
+struct MemoEntries {
+    capacity: usize,
+    /// Incremented on every access to order entries by recency
+    tick: u64,
+    entries: HashMap<MemoKey, (QueryResult, u64)>,
+    recency: BTreeMap<u64, MemoKey>,
+}
+
+/// Least recently used query results
+pub(crate) struct QueryMemo {
+    entries: Mutex<MemoEntries>,
+}
+
+impl Default for QueryMemo {
+    fn default() -> Self {
+        QueryMemo {
+            entries: Mutex::new(MemoEntries {
+                capacity: DEFAULT_QUERY_MEMO_CAPACITY,
+                tick: 0,
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+            }),
+        }
+    }
+}
+
+impl QueryMemo {
+    fn entries(&self) -> MutexGuard<MemoEntries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, key: &MemoKey) -> Option<QueryResult> {
+        let mut memo = self.entries();
+        memo.tick += 1;
+        let tick = memo.tick;
+        let (result, last_used) = memo.entries.get_mut(key)?;
+        let previous = std::mem::replace(last_used, tick);
+        let result = result.clone();
+        memo.recency.remove(&previous);
+        memo.recency.insert(tick, key.clone());
+        Some(result)
+    }
+
+    fn insert(&self, key: MemoKey, result: QueryResult) {
+        let mut memo = self.entries();
+        if memo.capacity == 0 {
+            return;
+        }
+        memo.tick += 1;
+        let tick = memo.tick;
+        if let Some((_, previous)) = memo.entries.insert(key.clone(), (result, tick)) {
+            memo.recency.remove(&previous);
+        }
+        memo.recency.insert(tick, key);
+        while memo.entries.len() > memo.capacity {
+            let (_, oldest) = memo
+                .recency
+                .pop_first()
+                .expect("every entry has a recency record");
+            memo.entries.remove(&oldest);
+        }
+    }
+}
+
+impl GroveDb {
+    /// Sets the number of memoized query results, `0` disables memoization.
+    /// Least recently used results are dropped if there are more.
+    pub fn set_query_memo_capacity(&mut self, capacity: usize) {
+        let mut memo = self.query_memo.entries();
+        memo.capacity = capacity;
+        memo.tick = 0;
+        memo.entries.clear();
+        memo.recency.clear();
+    }
+
+    /// Returns memoized results of the path query, if any, and the key to
+    /// memoize its results with. Queries of empty subtrees are not memoized,
+    /// as a missing subtree has the same root hash.
+    pub(crate) fn memoized_path_query(
+        &self,
+        path_query: &PathQuery,
+        transaction: TransactionArg,
+    ) -> Result<(Option<QueryResult>, Option<MemoKey>), Error> {
+        if self.query_memo.entries().capacity == 0 {
+            return Ok((None, None));
+        }
+        let root_hash = merk_optional_tx!(
+            self.db,
+            path_query.path.iter().map(|x| x.as_slice()),
+            transaction,
+            subtree,
+            { subtree.root_hash() }
+        );
+        if root_hash == EMPTY_SUBTREE_HASH {
+            return Ok((None, None));
+        }
+        let key = (root_hash, path_query.cache_key());
+        Ok((self.query_memo.get(&key), Some(key)))
+    }
+
+    pub(crate) fn memoize_path_query(&self, key: MemoKey, result: QueryResult) {
+        self.query_memo.insert(key, result);
+    }
+}
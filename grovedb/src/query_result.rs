@@ -0,0 +1,81 @@
+//! Module for typed query results.
+//! Query results keep paths and keys of found elements, so consumers can
+//! extract the data they expect in one call instead of matching every element
+//! by hand, with an error on the first element of an unexpected kind.
+
+use crate::{BytesDisplay, Element, Error, PathDisplay};
+
+/// An element found by a query with its subtree path and key
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResultElement {
+    pub path: Vec<Vec<u8>>,
+    pub key: Vec<u8>,
+    pub element: Element,
+}
+
+impl QueryResultElement {
+    fn unexpected(&self, expected: &str) -> Error {
+        Error::UnexpectedElement(format!(
+            "expected {}, found {:?} under key {} of path {}",
+            expected,
+            self.element.element_type(),
+            BytesDisplay::new(&self.key),
+            PathDisplay::new(self.path.iter().map(|x| x.as_slice()))
+        ))
+    }
+}
+
+/// Elements found by a query in the order of the query
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QueryResultElements {
+    pub elements: Vec<QueryResultElement>,
+}
+
+impl QueryResultElements {
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Returns values of items, fails if any element is not an item
+    pub fn into_items_bytes(self) -> Result<Vec<Vec<u8>>, Error> {
+        self.elements
+            .into_iter()
+            .map(|result| match result.element {
+                Element::Item(bytes) => Ok(bytes),
+                _ => Err(result.unexpected("an item")),
+            })
+            .collect()
+    }
+
+    /// Returns keys with elements of any kind
+    pub fn into_key_elements(self) -> Vec<(Vec<u8>, Element)> {
+        self.elements
+            .into_iter()
+            .map(|result| (result.key, result.element))
+            .collect()
+    }
+
+    /// Returns paths of referenced elements, fails if any element is not a
+    /// reference
+    pub fn into_paths(self) -> Result<Vec<Vec<Vec<u8>>>, Error> {
+        self.elements
+            .into_iter()
+            .map(|result| match result.element {
+                Element::Reference(path) => Ok(path),
+                _ => Err(result.unexpected("a reference")),
+            })
+            .collect()
+    }
+
+    /// Returns elements without their paths and keys
+    pub fn into_elements(self) -> Vec<Element> {
+        self.elements
+            .into_iter()
+            .map(|result| result.element)
+            .collect()
+    }
+}
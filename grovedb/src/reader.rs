@@ -0,0 +1,72 @@
+//! Module for read-only access to GroveDB checkpoints.
+//! `GroveDbReader` opens an existing database directory read-only with a
+//! minimal RocksDB configuration and exposes only read, query and prove
+//! operations, so many historical checkpoints can be served at once.
+
+use std::path::Path;
+
+use storage::rocksdb_storage::ReadOnlyRocksDbStorage;
+
+use crate::{Element, Error, GroveDb, PathQuery};
+
+/// Read-only handle to a GroveDB checkpoint
+pub struct GroveDbReader {
+    db: GroveDb,
+}
+
+impl GroveDbReader {
+    /// Opens a checkpoint directory for reading, fails if there is no
+    /// database at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let reader = GroveDbReader {
+            db: GroveDb::new(Box::new(ReadOnlyRocksDbStorage::open(path)?)),
+        };
+        reader.db.check_version(false)?;
+        Ok(reader)
+    }
+
+    /// Returns root hash of the checkpoint, `None` if it is empty
+    pub fn root_hash(&self) -> Result<Option<[u8; 32]>, Error> {
+        self.db.root_hash(None)
+    }
+
+    pub fn get<'p, P>(&self, path: P, key: &'p [u8]) -> Result<Element, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        self.db.get(path, key, None)
+    }
+
+    pub fn get_path_query(&self, path_query: &PathQuery) -> Result<(Vec<Vec<u8>>, u16), Error> {
+        self.db.get_path_query(path_query, None)
+    }
+
+    pub fn get_path_query_raw(&self, path_query: &PathQuery) -> Result<(Vec<Element>, u16), Error> {
+        self.db.get_path_query_raw(path_query, None)
+    }
+
+    pub fn get_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Error> {
+        self.db.get_aux(key, None)
+    }
+
+    pub fn is_empty_tree<'p, P>(&self, path: P) -> Result<bool, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        self.db.is_empty_tree(path, None)
+    }
+
+    /// Checks every subtree's root hash matches the hash stored in its parent
+    /// and returns the root hash of the checkpoint
+    pub fn verify(&self) -> Result<Option<[u8; 32]>, Error> {
+        self.db.verify_subtree_hashes(&[], None)?;
+        self.root_hash()
+    }
+
+    /// Generates a proof for path queries, see [`GroveDb::prove`]
+    pub fn prove(&self, path_queries: &[PathQuery]) -> Result<Vec<u8>, Error> {
+        self.db.prove(path_queries, None)
+    }
+}
@@ -0,0 +1,319 @@
+//! Module for references bookkeeping.
+//! For every reference target GroveDB keeps a list of paths of references
+//! pointing to it (back references). The list is stored in roots storage of
+//! the target's subtree under a dedicated prefix followed by the target's key,
+//! apart from auxiliary data of users, is updated on every reference insertion
+//! and deletion and is used to keep referential integrity if it is enabled.
+
+use std::collections::BTreeMap;
+
+use storage::{RawIterator, Storage, StorageContext};
+
+use crate::{util::storage_context_optional_tx, Element, Error, GroveDb, TransactionArg};
+
+/// A prefix of keys in roots storage of a subtree to keep back references of
+/// its elements, followed by an element key
+const BACK_REFERENCES_PREFIX: &[u8] = b"back_references/";
+
+fn back_references_key(key: &[u8]) -> Vec<u8> {
+    let mut back_references_key = BACK_REFERENCES_PREFIX.to_vec();
+    back_references_key.extend_from_slice(key);
+    back_references_key
+}
+
+/// What to do with references to elements which are inserted or deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferentialIntegrity {
+    /// No checks are made, references may point to missing elements
+    Disabled,
+    /// Reference insertion fails if its target doesn't exist and deletion of
+    /// an element fails if there are references to it
+    Enforce,
+    /// Same as `Enforce`, but instead of failing on deletion all references
+    /// pointing to the element are deleted as well
+    Cascade,
+}
+
+impl Default for ReferentialIntegrity {
+    fn default() -> Self {
+        ReferentialIntegrity::Disabled
+    }
+}
+
+impl GroveDb {
+    /// Sets referential integrity mode for all following operations
+    pub fn set_referential_integrity(&mut self, mode: ReferentialIntegrity) {
+        self.referential_integrity = mode;
+    }
+
+    pub fn referential_integrity(&self) -> ReferentialIntegrity {
+        self.referential_integrity
+    }
+
+    /// Checks a reference to be inserted points to an existing element if
+    /// referential integrity is enabled
+    pub(crate) fn check_reference_target(
+        &self,
+        reference_path: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        if self.referential_integrity == ReferentialIntegrity::Disabled {
+            return Ok(());
+        }
+        let (key, path) = reference_path
+            .split_last()
+            .ok_or(Error::CorruptedPath("empty path"))?;
+        if self
+            .get_raw_optional(path.iter().map(|x| x.as_slice()), key, transaction)?
+            .is_none()
+        {
+            return Err(Error::ReferentialIntegrity(
+                "reference target doesn't exist",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fails or deletes references pointing to an element which is about to
+    /// be deleted, depending on referential integrity mode. For a subtree
+    /// references to elements of it and of its nested subtrees count as well,
+    /// unless they are in the subtree themselves and are deleted with it.
+    pub(crate) fn check_deletion_references<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone,
+    {
+        if self.referential_integrity == ReferentialIntegrity::Disabled {
+            return Ok(());
+        }
+        let path_iter = path.into_iter();
+        let mut references = self.references_to(path_iter.clone(), key, transaction)?;
+        let mut deleted_path: Vec<Vec<u8>> = path_iter.map(|x| x.to_vec()).collect();
+        deleted_path.push(key.to_vec());
+        for subtree_path in
+            self.find_subtrees(deleted_path.iter().map(|x| x.as_slice()), transaction)?
+        {
+            for target_key in self.referenced_keys(&subtree_path, transaction)? {
+                references.extend(
+                    self.references_to(
+                        subtree_path.iter().map(|x| x.as_slice()),
+                        &target_key,
+                        transaction,
+                    )?
+                    .into_iter()
+                    .filter(|reference_path| !reference_path.starts_with(&deleted_path)),
+                );
+            }
+        }
+        if references.is_empty() {
+            return Ok(());
+        }
+        if self.referential_integrity == ReferentialIntegrity::Enforce {
+            return Err(Error::ReferentialIntegrity(
+                "element is referenced and cannot be deleted",
+            ));
+        }
+        for reference_path in references {
+            let (reference_key, reference_parent) = reference_path
+                .split_last()
+                .expect("back reference paths are not empty");
+            // Path is collected to avoid instantiating `delete` with an iterator
+            // type which depends on `P` as the call is recursive
+            let reference_parent: Vec<&[u8]> =
+                reference_parent.iter().map(|x| x.as_slice()).collect();
+            self.delete(reference_parent, reference_key, transaction)?;
+        }
+        Ok(())
+    }
+
+    /// Updates back references on an element change at `path` and `key`
+    pub(crate) fn update_back_references<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        old_element: Option<&Element>,
+        new_element: Option<&Element>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let old_target = match old_element {
+            Some(Element::Reference(target)) => Some(target),
+            _ => None,
+        };
+        let new_target = match new_element {
+            Some(Element::Reference(target)) => Some(target),
+            _ => None,
+        };
+        if old_target == new_target {
+            return Ok(());
+        }
+        let mut reference_path: Vec<Vec<u8>> = path.into_iter().map(|x| x.to_vec()).collect();
+        reference_path.push(key.to_vec());
+        if let Some(target) = old_target {
+            self.modify_back_references(target, transaction, |references| {
+                references.retain(|r| r != &reference_path)
+            })?;
+        }
+        if let Some(target) = new_target {
+            self.modify_back_references(target, transaction, |references| {
+                if !references.contains(&reference_path) {
+                    references.push(reference_path.clone());
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Returns full paths (including keys) of references pointing to the
+    /// element at `path` and `key`. Back references are tracked regardless
+    /// of referential integrity mode. Stored back references may be outdated
+    /// as deletion of a subtree doesn't update back references of references
+    /// in it, so each one is checked to still point to the element.
+    pub fn references_to<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<Vec<Vec<Vec<u8>>>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut target: Vec<Vec<u8>> = path.into_iter().map(|x| x.to_vec()).collect();
+        target.push(key.to_vec());
+        let mut live_references = Vec::new();
+        for reference_path in self.get_back_references(&target, transaction)? {
+            let (reference_key, reference_parent) =
+                reference_path
+                    .split_last()
+                    .ok_or(Error::CorruptedData(String::from(
+                        "empty back reference path",
+                    )))?;
+            let element = self.get_raw_optional(
+                reference_parent.iter().map(|x| x.as_slice()),
+                reference_key,
+                transaction,
+            )?;
+            if matches!(element, Some(Element::Reference(ref t)) if t == &target) {
+                live_references.push(reference_path);
+            }
+        }
+        Ok(live_references)
+    }
+
+    fn get_back_references(
+        &self,
+        target: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> Result<Vec<Vec<Vec<u8>>>, Error> {
+        let (key, path) = target
+            .split_last()
+            .ok_or(Error::CorruptedPath("empty path"))?;
+        let path_iter = path.iter().map(|x| x.as_slice());
+        let serialized = storage_context_optional_tx!(self.db, path_iter, transaction, storage, {
+            storage.get_root(back_references_key(key))?
+        });
+        if let Some(serialized) = serialized {
+            bincode::deserialize(&serialized).map_err(|_| {
+                Error::CorruptedData(String::from("unable to deserialize back references"))
+            })
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn modify_back_references(
+        &self,
+        target: &[Vec<u8>],
+        transaction: TransactionArg,
+        f: impl FnOnce(&mut Vec<Vec<Vec<u8>>>),
+    ) -> Result<(), Error> {
+        let mut references = self.get_back_references(target, transaction)?;
+        f(&mut references);
+        self.put_back_references(target, &references, transaction)
+    }
+
+    fn put_back_references(
+        &self,
+        target: &[Vec<u8>],
+        references: &[Vec<Vec<u8>>],
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        let (key, path) = target
+            .split_last()
+            .ok_or(Error::CorruptedPath("empty path"))?;
+        let path_iter = path.iter().map(|x| x.as_slice());
+        storage_context_optional_tx!(self.db, path_iter, transaction, storage, {
+            if references.is_empty() {
+                storage.delete_root(back_references_key(key))?;
+            } else {
+                let serialized = bincode::serialize(references).map_err(|_| {
+                    Error::CorruptedData(String::from("unable to serialize back references"))
+                })?;
+                storage.put_root(back_references_key(key), &serialized)?;
+            }
+        });
+        Ok(())
+    }
+
+    /// Returns keys of elements of the subtree at the path which have back
+    /// references stored, live or not
+    fn referenced_keys(
+        &self,
+        path: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let mut keys = Vec::new();
+        let path_iter = path.iter().map(|x| x.as_slice());
+        storage_context_optional_tx!(self.db, path_iter, transaction, storage, {
+            let mut raw_iter = storage.raw_iter_roots();
+            raw_iter.seek(BACK_REFERENCES_PREFIX);
+            while let Some(key) = raw_iter
+                .key()
+                .and_then(|key| key.strip_prefix(BACK_REFERENCES_PREFIX))
+            {
+                keys.push(key.to_vec());
+                raw_iter.next();
+            }
+            raw_iter.status()?;
+        });
+        Ok(keys)
+    }
+
+    /// Rebuilds back references of all references, which were stored in
+    /// auxiliary storage under target keys before format version 2. The old
+    /// records are left in place, as auxiliary data under the same keys may
+    /// belong to users. Safe to repeat, as the lists are rebuilt as a whole.
+    pub(crate) fn backfill_back_references(&self) -> Result<(), Error> {
+        let transaction = self.start_transaction();
+        let mut back_references: BTreeMap<Vec<Vec<u8>>, Vec<Vec<Vec<u8>>>> = BTreeMap::new();
+        for path in self.find_subtrees(std::iter::empty(), Some(&transaction))? {
+            let storage = self
+                .db
+                .get_transactional_storage_context(path.iter().map(|x| x.as_slice()), &transaction);
+            let mut raw_iter = Element::iterator(storage.raw_iter());
+            while let Some((key, value)) = raw_iter.next()? {
+                if let Element::Reference(target) = value {
+                    let mut reference_path = path.clone();
+                    reference_path.push(key);
+                    back_references
+                        .entry(target)
+                        .or_default()
+                        .push(reference_path);
+                }
+            }
+        }
+        for (target, references) in back_references {
+            if !target.is_empty() {
+                self.put_back_references(&target, &references, Some(&transaction))?;
+            }
+        }
+        self.commit_transaction(transaction)
+    }
+}
@@ -0,0 +1,74 @@
+//! Module for root layer export.
+//! A new light node needs to trust the hierarchy of subtrees before syncing
+//! any of their contents. The root layer proves all elements of the root tree,
+//! that is root hashes of first-level subtrees, against the GroveDB root hash,
+//! so it can be checked against a root hash agreed on by consensus alone.
+
+use std::collections::BTreeMap;
+
+use crate::{Element, Error, GroveDb, Query, TransactionArg};
+
+/// Version byte of root layer blobs
+const ROOT_LAYER_VERSION: u8 = 0;
+
+/// Root tree contents verified by [`GroveDb::verify_root_layer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootLayer {
+    /// GroveDB root hash the subtree hashes are proved against, `None` for
+    /// an empty GroveDB
+    pub root_hash: Option<[u8; 32]>,
+    /// Root hashes of first-level subtrees by their keys, including pruned
+    /// subtrees
+    pub subtrees: BTreeMap<Vec<u8>, [u8; 32]>,
+}
+
+impl GroveDb {
+    /// Exports the root tree with root hashes of first-level subtrees in a
+    /// blob which proves them against the root hash, see
+    /// [`GroveDb::verify_root_layer`]
+    pub fn export_root_layer(&self, transaction: TransactionArg) -> Result<Vec<u8>, Error> {
+        let mut query = Query::new();
+        query.insert_all();
+        let proof = self.prove_subtree(&[], query, None, None, transaction)?;
+        let mut blob = Vec::with_capacity(proof.len() + 1);
+        blob.push(ROOT_LAYER_VERSION);
+        blob.extend_from_slice(&proof);
+        Ok(blob)
+    }
+
+    /// Verifies a blob made by [`GroveDb::export_root_layer`]. The returned
+    /// root hash is computed from the blob, so subtree hashes are to be
+    /// trusted only once it matches a trusted root hash.
+    pub fn verify_root_layer(blob: &[u8]) -> Result<RootLayer, Error> {
+        let proof = match blob.split_first() {
+            Some((&ROOT_LAYER_VERSION, proof)) => proof,
+            _ => return Err(Error::InvalidProof("unsupported root layer version")),
+        };
+        let mut subtrees = BTreeMap::new();
+        if proof.is_empty() {
+            // An empty root tree cannot be proved by Merk
+            return Ok(RootLayer {
+                root_hash: None,
+                subtrees,
+            });
+        }
+        let (root_hash, map) = merk::execute_proof(proof)
+            .map_err(|_| Error::InvalidProof("invalid root layer proof"))?;
+        for entry in map.range(..) {
+            let (key, value) =
+                entry.map_err(|_| Error::InvalidProof("root layer proof is incomplete"))?;
+            let element: Element = bincode::deserialize(value)
+                .map_err(|_| Error::InvalidProof("unable to deserialize element"))?;
+            match element {
+                Element::Tree(hash) | Element::PrunedTree(hash) => {
+                    subtrees.insert(key.to_vec(), hash);
+                }
+                _ => return Err(Error::InvalidProof("root tree elements must be subtrees")),
+            }
+        }
+        Ok(RootLayer {
+            root_hash: Some(root_hash),
+            subtrees,
+        })
+    }
+}
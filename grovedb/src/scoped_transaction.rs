@@ -0,0 +1,209 @@
+//! Module for path-scoped transactions.
+//! A scoped transaction declares subtrees it writes to, so transactions with
+//! non-overlapping scopes can be executed and committed in parallel, e.g. for
+//! independent transitions of a block. Changes of a scoped transaction are
+//! propagated only up to its scope subtrees while it is open, ancestors of
+//! scope subtrees are updated in the transaction when it is committed, one
+//! commit at a time, so the transactions don't conflict on writing shared
+//! ancestors.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{Error, GroveDb, LockWait, SubtreeLockGuard, Transaction, TransactionArg};
+
+struct Scope {
+    paths: Vec<Vec<Vec<u8>>>,
+    violated: bool,
+}
+
+/// Scopes of open scoped transactions by transaction ID
+#[derive(Default)]
+pub(crate) struct TransactionScopes {
+    scopes: Mutex<HashMap<u64, Scope>>,
+    commit: Mutex<()>,
+}
+
+impl TransactionScopes {
+    fn scopes(&self) -> MutexGuard<HashMap<u64, Scope>> {
+        self.scopes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn path_starts_with<'p, P>(path: P, prefix: &[Vec<u8>]) -> bool
+where
+    P: IntoIterator<Item = &'p [u8]>,
+    <P as IntoIterator>::IntoIter: ExactSizeIterator,
+{
+    let path_iter = path.into_iter();
+    path_iter.len() >= prefix.len()
+        && path_iter
+            .zip(prefix)
+            .all(|(segment, prefix_segment)| segment == prefix_segment.as_slice())
+}
+
+/// Transaction allowed to write into its scope subtrees and their descendants
+/// only. Rolled back on drop unless committed.
+pub struct ScopedTransaction<'db> {
+    db: &'db GroveDb,
+    transaction: Option<Transaction<'db>>,
+    scope: Vec<Vec<Vec<u8>>>,
+    _locks: Vec<SubtreeLockGuard<'db>>,
+}
+
+impl<'db> ScopedTransaction<'db> {
+    /// Returns the underlying transaction to pass to GroveDB operations
+    pub fn transaction(&self) -> &Transaction<'db> {
+        self.transaction
+            .as_ref()
+            .expect("transaction is taken on commit only")
+    }
+
+    /// Returns paths of the scope subtrees
+    pub fn scope(&self) -> &[Vec<Vec<u8>>] {
+        &self.scope
+    }
+
+    /// Updates ancestors of the scope subtrees in the transaction and commits
+    /// it, so either both or none are visible.
+    /// Fails with [`Error::OutOfScope`] without committing if the transaction
+    /// has written outside of its scope.
+    pub fn commit(mut self) -> Result<(), Error> {
+        let transaction = self
+            .transaction
+            .take()
+            .expect("transaction is taken on commit only");
+        let scope = self
+            .db
+            .transaction_scopes
+            .scopes()
+            .remove(&transaction.id());
+        if scope.map_or(false, |scope| scope.violated) {
+            return Err(Error::OutOfScope);
+        }
+        let _commit = self
+            .db
+            .transaction_scopes
+            .commit
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        // The scope is removed, so changes are propagated up to the root tree
+        // within the transaction and committed atomically with it
+        for path in &self.scope {
+            self.db
+                .propagate_changes(path.iter().map(|x| x.as_slice()), Some(&transaction))?;
+        }
+        self.db.commit_transaction(transaction)
+    }
+}
+
+impl Drop for ScopedTransaction<'_> {
+    fn drop(&mut self) {
+        if let Some(transaction) = &self.transaction {
+            self.db
+                .transaction_scopes
+                .scopes()
+                .remove(&transaction.id());
+        }
+    }
+}
+
+impl<'db> SubtreeLockGuard<'db> {
+    /// Starts a transaction which may only write into the locked subtree and
+    /// its descendants, like [`GroveDb::transaction_scoped`], keeping the lock
+    /// until the transaction is committed or dropped. Ancestors of the subtree
+    /// are updated on commit one commit at a time, so lockers of disjoint
+    /// subtrees apply their batches concurrently without conflicts or lost
+    /// updates of shared ancestors. The root tree can't be in a scope.
+    pub fn into_transaction(self) -> Result<ScopedTransaction<'db>, Error> {
+        if self.path.is_empty() {
+            return Err(Error::InvalidPath(
+                "root tree cannot be in a transaction scope",
+            ));
+        }
+        let paths = vec![self.path.clone()];
+        Ok(self.db.start_scoped_transaction(paths, vec![self]))
+    }
+}
+
+impl GroveDb {
+    /// Starts a transaction which may only write into the subtrees at `paths`
+    /// and their descendants, writes outside of the scope fail with
+    /// [`Error::OutOfScope`]. Scope subtrees are locked until the transaction
+    /// is committed or dropped, so a scope overlapping with the one of another
+    /// open scoped transaction fails with [`Error::SubtreeLocked`]. The root
+    /// tree can't be in a scope. While the transaction is open, hashes of
+    /// scope subtrees are not propagated to their ancestors, even for reads
+    /// through this transaction.
+    pub fn transaction_scoped(&self, paths: Vec<Vec<Vec<u8>>>) -> Result<ScopedTransaction, Error> {
+        if paths.iter().any(|path| path.is_empty()) {
+            return Err(Error::InvalidPath(
+                "root tree cannot be in a transaction scope",
+            ));
+        }
+        let mut locks = Vec::with_capacity(paths.len());
+        for path in &paths {
+            locks.push(self.lock_subtree(path.iter().map(|x| x.as_slice()), LockWait::FailFast)?);
+        }
+        Ok(self.start_scoped_transaction(paths, locks))
+    }
+
+    /// Starts a transaction scoped to `paths` holding their `locks`
+    fn start_scoped_transaction<'db>(
+        &'db self,
+        paths: Vec<Vec<Vec<u8>>>,
+        locks: Vec<SubtreeLockGuard<'db>>,
+    ) -> ScopedTransaction<'db> {
+        let transaction = self.start_transaction();
+        self.transaction_scopes.scopes().insert(
+            transaction.id(),
+            Scope {
+                paths: paths.clone(),
+                violated: false,
+            },
+        );
+        ScopedTransaction {
+            db: self,
+            transaction: Some(transaction),
+            scope: paths,
+            _locks: locks,
+        }
+    }
+
+    /// Checks that changes of the subtree at the path are allowed by the scope
+    /// of the transaction. Returns the height of the scope subtree containing
+    /// the path to stop propagation at, `None` for unscoped transactions.
+    pub(crate) fn check_transaction_scope<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<Option<usize>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: ExactSizeIterator + Clone,
+    {
+        let transaction = match transaction {
+            Some(transaction) => transaction,
+            None => return Ok(None),
+        };
+        let mut scopes = self.transaction_scopes.scopes();
+        let scope = match scopes.get_mut(&transaction.id()) {
+            Some(scope) => scope,
+            None => return Ok(None),
+        };
+        let path_iter = path.into_iter();
+        match scope
+            .paths
+            .iter()
+            .find(|scope_path| path_starts_with(path_iter.clone(), scope_path))
+        {
+            Some(scope_path) => Ok(Some(scope_path.len())),
+            None => {
+                scope.violated = true;
+                Err(Error::OutOfScope)
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Module for logging of slow operations.
+//! Once a threshold is set, operations taking longer emit a `tracing` event
+//! with target `grovedb::slow_operation` holding the operation, its path and
+//! key, its cost and RocksDB perf counters, which tell block cache misses
+//! from slow iterations when a latency spike is investigated.
+
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use storage::rocksdb_storage::{PerfCounters, PerfScope};
+
+use crate::{BytesDisplay, CostMeter, Error, GroveDb, PathDisplay, QueryCost};
+
+thread_local! {
+    /// Whether an operation is observed on the thread, so operations made by
+    /// it are not logged on their own
+    static OBSERVING: Cell<bool> = Cell::new(false);
+}
+
+/// Clears the observing flag, even if the observed operation panics
+struct ObservingGuard;
+
+impl Drop for ObservingGuard {
+    fn drop(&mut self) {
+        OBSERVING.with(|observing| observing.set(false));
+    }
+}
+
+impl GroveDb {
+    /// Sets the duration above which operations are logged, `None` disables
+    /// the logging
+    pub fn set_slow_operation_threshold(&mut self, threshold: Option<Duration>) {
+        self.slow_operation_threshold = threshold;
+    }
+
+    pub fn slow_operation_threshold(&self) -> Option<Duration> {
+        self.slow_operation_threshold
+    }
+
+    /// Returns a meter to account the cost of an operation to if slow
+    /// operations are logged
+    pub(crate) fn slow_operation_meter(&self) -> Option<CostMeter> {
+        self.slow_operation_threshold
+            .map(|_| CostMeter::new(u64::MAX))
+    }
+
+    /// Runs the operation and logs it if it's slower than the threshold, with
+    /// the cost accounted to `cost_meter` if any
+    pub(crate) fn observe_slow<'p, P, T>(
+        &self,
+        operation: &'static str,
+        path: P,
+        key: Option<&[u8]>,
+        cost_meter: Option<&CostMeter>,
+        f: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let threshold = match self.slow_operation_threshold {
+            Some(threshold) if !OBSERVING.with(|observing| observing.get()) => threshold,
+            _ => return f(),
+        };
+        OBSERVING.with(|observing| observing.set(true));
+        let _guard = ObservingGuard;
+
+        let perf = PerfScope::start();
+        let started = Instant::now();
+        let result = f();
+        let duration = started.elapsed();
+        let perf = perf.finish();
+        if duration >= threshold {
+            let cost = cost_meter.map(|meter| meter.cost()).unwrap_or_default();
+            log_slow_operation(operation, path, key, &result, duration, cost, perf);
+        }
+        result
+    }
+}
+
+fn log_slow_operation<'p, P, T>(
+    operation: &'static str,
+    path: P,
+    key: Option<&[u8]>,
+    result: &Result<T, Error>,
+    duration: Duration,
+    cost: QueryCost,
+    perf: PerfCounters,
+) where
+    P: IntoIterator<Item = &'p [u8]>,
+{
+    tracing::warn!(
+        target: "grovedb::slow_operation",
+        operation,
+        path = %PathDisplay::new(path),
+        key = key.map(|key| BytesDisplay::new(key).to_string()).as_deref(),
+        succeeded = result.is_ok(),
+        duration_ms = duration.as_secs_f64() * 1000.0,
+        seek_count = cost.seek_count,
+        loaded_bytes = cost.loaded_bytes,
+        written_bytes = cost.written_bytes,
+        block_read_count = perf.block_read_count,
+        block_read_bytes = perf.block_read_bytes,
+        block_cache_hit_count = perf.block_cache_hit_count,
+        storage_seek_count = perf.seek_count,
+        write_stall_ms = perf.write_stall_nanos as f64 / 1_000_000.0,
+        "slow operation"
+    );
+}
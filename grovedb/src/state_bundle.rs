@@ -0,0 +1,92 @@
+//! Module for bundles of proofs of one state.
+//! An API response proving several queries is only meaningful if all proofs
+//! lead to the same root hash, which separately generated proofs don't
+//! guarantee while blocks are being applied. A [`ProvedStateBundle`] is
+//! generated from one snapshot and bound together by a commitment.
+
+use std::collections::HashMap;
+
+use merk::{
+    proofs::query::Map,
+    tree::{value_hash, NULL_HASH},
+};
+
+use crate::{Error, GroveDb, PathQuery, TransactionArg};
+
+/// Domain tag of state bundle commitments, changed with the commitment scheme
+const STATE_BUNDLE_COMMITMENT_TAG: &[u8] = b"grovedb/state-bundle/v1";
+
+/// Root hash with proofs of queries against it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvedStateBundle {
+    pub root_hash: [u8; 32],
+    /// Proofs in the order of proved queries, see [`GroveDb::prove`]
+    pub proofs: Vec<Vec<u8>>,
+    /// Hash of the root hash and the proofs, see
+    /// [`ProvedStateBundle::compute_commitment`]
+    pub commitment: [u8; 32],
+}
+
+impl ProvedStateBundle {
+    /// Hashes the domain tag, the root hash, the number of proofs and hashes
+    /// of the proofs in order
+    pub fn compute_commitment(root_hash: &[u8; 32], proofs: &[Vec<u8>]) -> [u8; 32] {
+        let mut preimage = STATE_BUNDLE_COMMITMENT_TAG.to_vec();
+        preimage.extend_from_slice(root_hash);
+        preimage.extend_from_slice(&(proofs.len() as u32).to_be_bytes());
+        for proof in proofs {
+            preimage.extend_from_slice(&value_hash(proof));
+        }
+        value_hash(&preimage)
+    }
+}
+
+impl GroveDb {
+    /// Generates a proof of each path query and the root hash from one
+    /// snapshot, or from the transaction if one is given
+    pub fn prove_state_bundle(
+        &self,
+        path_queries: &[PathQuery],
+        transaction: TransactionArg,
+    ) -> Result<ProvedStateBundle, Error> {
+        if transaction.is_none() {
+            let snapshot = self.start_snapshot_transaction();
+            return self.prove_state_bundle(path_queries, Some(&snapshot));
+        }
+        let root_hash = self.root_hash(transaction)?.unwrap_or(NULL_HASH);
+        let proofs = path_queries
+            .iter()
+            .map(|path_query| self.prove(std::slice::from_ref(path_query), transaction))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let commitment = ProvedStateBundle::compute_commitment(&root_hash, &proofs);
+        Ok(ProvedStateBundle {
+            root_hash,
+            proofs,
+            commitment,
+        })
+    }
+
+    /// Executes proofs of the bundle, checking that they lead to its root hash
+    /// and that the commitment is valid. Returns results of the proofs in
+    /// their order. The root hash is to be compared with a trusted one.
+    pub fn verify_state_bundle(
+        bundle: &ProvedStateBundle,
+    ) -> Result<Vec<HashMap<Vec<Vec<u8>>, Map>>, Error> {
+        if ProvedStateBundle::compute_commitment(&bundle.root_hash, &bundle.proofs)
+            != bundle.commitment
+        {
+            return Err(Error::InvalidProof("state bundle commitment mismatch"));
+        }
+        bundle
+            .proofs
+            .iter()
+            .map(|proof| {
+                let (root_hash, results) = Self::execute_proof(proof)?;
+                if root_hash != bundle.root_hash {
+                    return Err(Error::InvalidProof("root hashes mismatch"));
+                }
+                Ok(results)
+            })
+            .collect()
+    }
+}
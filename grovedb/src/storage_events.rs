@@ -0,0 +1,102 @@
+//! Module for storage behavior observability.
+//! Maintenance event listeners are notified about flushes and compactions
+//! GroveDB initiates, that is ones made by [`GroveDb::flush`],
+//! [`GroveDb::flush_cf`], [`GroveDb::compact`] and the flush policy. RocksDB
+//! statistics tickers and histograms are exposed when enabled with
+//! [`crate::GroveDbOpts`], so understanding storage behavior doesn't require
+//! scraping RocksDB LOG files.
+//!
+//! Flushes, compactions and write stalls RocksDB runs into in background on
+//! its own are not delivered to listeners, as the RocksDB bindings don't
+//! expose its event listeners. They are only counted by statistics, e.g. by
+//! `rocksdb.db.flush.micros`, `rocksdb.compact.write.bytes` and
+//! `rocksdb.stall.micros`.
+
+use std::time::{Duration, Instant};
+
+use storage::Storage;
+
+use crate::{
+    rocksdb_storage::{ColumnFamily, StorageStatistics},
+    Error, GroveDb, RocksDbStorage,
+};
+
+/// Flush of memtables initiated by GroveDB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlushInfo {
+    /// The flushed column family, `None` if all of them were flushed
+    pub column_family: Option<ColumnFamily>,
+    /// Time the flush took
+    pub duration: Duration,
+}
+
+/// Compaction of the whole storage initiated by GroveDB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionInfo {
+    /// Time the compaction took
+    pub duration: Duration,
+}
+
+/// Listener of flushes and compactions initiated by GroveDB, all methods do
+/// nothing by default. Ones RocksDB runs in background are not reported.
+pub trait MaintenanceEvents: Send + Sync {
+    /// Called after memtables are flushed by GroveDB
+    fn on_flush_completed(&self, _info: &FlushInfo) {}
+
+    /// Called if a flush made by the flush policy after a commit fails, the
+    /// commit itself has succeeded
+    fn on_flush_failed(&self, _error: &Error) {}
+
+    /// Called after storage is compacted by [`GroveDb::compact`]
+    fn on_compaction_completed(&self, _info: &CompactionInfo) {}
+}
+
+impl GroveDb {
+    /// Registers a listener of flushes and compactions initiated by GroveDB
+    /// which will be called in the order of registration
+    pub fn register_maintenance_events(&mut self, listener: Box<dyn MaintenanceEvents>) {
+        self.maintenance_events.push(listener);
+    }
+
+    /// Returns RocksDB statistics collected since open, `None` if statistics
+    /// are not enabled or the storage backend is not RocksDB
+    pub fn statistics(&self) -> Option<StorageStatistics> {
+        self.db.rocksdb().and_then(RocksDbStorage::statistics)
+    }
+
+    /// Forces data to be written from memtables to storage files
+    pub fn flush(&self) -> Result<(), Error> {
+        let started = Instant::now();
+        self.db.flush()?;
+        self.flush_state.flushed();
+        self.flush_completed(None, started.elapsed());
+        Ok(())
+    }
+
+    /// Compacts the whole storage, nothing is done unless the storage backend
+    /// is RocksDB
+    pub fn compact(&self) {
+        let db = match self.db.rocksdb() {
+            Some(db) => db,
+            None => return,
+        };
+        let started = Instant::now();
+        db.compact();
+        let info = CompactionInfo {
+            duration: started.elapsed(),
+        };
+        for listener in &self.maintenance_events {
+            listener.on_compaction_completed(&info);
+        }
+    }
+
+    pub(crate) fn flush_completed(&self, column_family: Option<ColumnFamily>, duration: Duration) {
+        let info = FlushInfo {
+            column_family,
+            duration,
+        };
+        for listener in &self.maintenance_events {
+            listener.on_flush_completed(&info);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! Module for commit subscriptions.
+//! Index builders and push services need to react to changes of particular
+//! subtrees. Subscribers get a notification whenever a commit changes the root
+//! hash of their subtree, so they don't have to poll for it. Cache layers in
+//! front of GroveDB get keys changed by each commit instead, so they can
+//! invalidate exactly the changed entries.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Mutex, MutexGuard,
+    },
+};
+
+use merk::Merk;
+use storage::Storage;
+
+use crate::{Error, GroveDb, PreparedToken, TransactionArg};
+
+/// Root hash change of a subscribed subtree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootHashChange {
+    pub path: Vec<Vec<u8>>,
+    pub old_hash: [u8; 32],
+    pub new_hash: [u8; 32],
+    /// RocksDB sequence number of the latest write when the change was
+    /// observed, it increases with every write and is always `0` with other
+    /// storage backends. It is not a block height.
+    pub sequence_number: u64,
+}
+
+/// Kind of a key change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyChangeOp {
+    /// An element was inserted or replaced
+    Put,
+    /// An element was deleted
+    Delete,
+}
+
+/// Element change made by a commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyChange {
+    pub path: Vec<Vec<u8>>,
+    pub key: Vec<u8>,
+    pub op: KeyChangeOp,
+}
+
+/// Paths of subtrees written by a commit or a non-transactional write
+pub(crate) type WrittenSubtrees = BTreeSet<Vec<Vec<u8>>>;
+
+struct Subscriber {
+    path: Vec<Vec<u8>>,
+    root_hash: [u8; 32],
+    sender: Sender<RootHashChange>,
+}
+
+/// Changes of a prepared transaction kept until it is resolved
+struct PreparedChanges {
+    changes: Vec<KeyChange>,
+    written_subtrees: WrittenSubtrees,
+}
+
+/// Key changes of open transactions by transaction ID and changes of prepared
+/// transactions by prepared transaction id
+#[derive(Default)]
+struct PendingChanges {
+    transactions: HashMap<u64, Vec<KeyChange>>,
+    prepared: HashMap<u64, PreparedChanges>,
+}
+
+/// Subscribers to root hash changes of subtrees and to key changes
+#[derive(Default)]
+pub(crate) struct Subscriptions {
+    subscribers: Mutex<Vec<Subscriber>>,
+    /// Serializes notifications and subscribing, so root hashes read by them
+    /// are compared in the order they were read
+    notifying: Mutex<()>,
+    change_listeners: Mutex<Vec<Sender<Vec<KeyChange>>>>,
+    pending_changes: Mutex<PendingChanges>,
+}
+
+impl Subscriptions {
+    fn subscribers(&self) -> MutexGuard<Vec<Subscriber>> {
+        self.subscribers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn notifying(&self) -> MutexGuard<()> {
+        self.notifying.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn change_listeners(&self) -> MutexGuard<Vec<Sender<Vec<KeyChange>>>> {
+        self.change_listeners
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn pending_changes(&self) -> MutexGuard<PendingChanges> {
+        self.pending_changes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn send_changes(&self, changes: Vec<KeyChange>) {
+        if changes.is_empty() {
+            return;
+        }
+        self.change_listeners()
+            .retain(|listener| listener.send(changes.clone()).is_ok());
+    }
+}
+
+impl GroveDb {
+    /// Subscribes to root hash changes of the subtree at the path. A
+    /// notification is sent after each commit or non-transactional write
+    /// changing the subtree, changes made in a transaction are observed on
+    /// its commit. Dropping the receiver unsubscribes.
+    pub fn subscribe<'p, P>(&self, path: P) -> Result<Receiver<RootHashChange>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), None)?;
+        let _notifying = self.subscriptions.notifying();
+        let root_hash = self.committed_subtree_root_hash(path_iter.clone())?;
+        let (sender, receiver) = channel();
+        self.subscriptions.subscribers().push(Subscriber {
+            path: path_iter.map(|x| x.to_vec()).collect(),
+            root_hash,
+            sender,
+        });
+        Ok(receiver)
+    }
+
+    /// Sends notifications to subscribers whose subtree root hashes changed
+    /// since they were notified last time. Only subscribers of subtrees which
+    /// are ancestors or descendants of a written subtree are checked, all are
+    /// if written subtrees are not known. Subtrees are opened once per path
+    /// and without holding the subscribers lock. Subscribers with dropped
+    /// receivers are removed, ones whose subtree can't be opened are skipped,
+    /// as the write being notified about is done already.
+    pub(crate) fn notify_subscribers(&self, written_subtrees: Option<&WrittenSubtrees>) {
+        let _notifying = self.subscriptions.notifying();
+        let paths: BTreeSet<Vec<Vec<u8>>> = self
+            .subscriptions
+            .subscribers()
+            .iter()
+            .filter(|subscriber| {
+                written_subtrees.map_or(true, |written| {
+                    written
+                        .iter()
+                        .any(|path| is_related(&subscriber.path, path))
+                })
+            })
+            .map(|subscriber| subscriber.path.clone())
+            .collect();
+        if paths.is_empty() {
+            return;
+        }
+        let new_hashes: HashMap<Vec<Vec<u8>>, [u8; 32]> = paths
+            .into_iter()
+            .filter_map(|path| {
+                let new_hash = self
+                    .committed_subtree_root_hash(path.iter().map(|x| x.as_slice()))
+                    .ok()?;
+                Some((path, new_hash))
+            })
+            .collect();
+        let sequence_number = self.latest_sequence_number();
+        self.subscriptions.subscribers().retain_mut(|subscriber| {
+            let new_hash = match new_hashes.get(&subscriber.path) {
+                Some(new_hash) if *new_hash != subscriber.root_hash => *new_hash,
+                _ => return true,
+            };
+            let change = RootHashChange {
+                path: subscriber.path.clone(),
+                old_hash: subscriber.root_hash,
+                new_hash,
+                sequence_number,
+            };
+            subscriber.root_hash = new_hash;
+            subscriber.sender.send(change).is_ok()
+        });
+    }
+
+    fn committed_subtree_root_hash<'p, P>(&self, path: P) -> Result<[u8; 32], Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let subtree = Merk::open(self.db.get_storage_context(path))
+            .map_err(|_| Error::CorruptedData("cannot open a subtree".to_owned()))?;
+        Ok(subtree.root_hash())
+    }
+
+    /// Subscribes to element changes, a list of changes is sent for every
+    /// commit or non-transactional write. Tree elements of ancestors updated
+    /// with new root hashes are not listed. Changes made in a transaction are
+    /// tracked until it is committed, rolled back or dropped, and ones rolled
+    /// back to a savepoint are dropped. Dropping the receiver unsubscribes.
+    pub fn subscribe_changes(&self) -> Receiver<Vec<KeyChange>> {
+        let (sender, receiver) = channel();
+        self.subscriptions.change_listeners().push(sender);
+        receiver
+    }
+
+    pub(crate) fn record_key_change<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        op: KeyChangeOp,
+        transaction: TransactionArg,
+    ) where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        if self.subscriptions.change_listeners().is_empty() {
+            return;
+        }
+        let change = KeyChange {
+            path: path.into_iter().map(|x| x.to_vec()).collect(),
+            key: key.to_vec(),
+            op,
+        };
+        match transaction {
+            Some(tx) => self
+                .subscriptions
+                .pending_changes()
+                .transactions
+                .entry(tx.id())
+                .or_default()
+                .push(change),
+            None => self.subscriptions.send_changes(vec![change]),
+        }
+    }
+
+    /// Sends changes recorded for a committed transaction, transactions are
+    /// identified by [`crate::Transaction::id`] as they are consumed by commits
+    pub(crate) fn send_transaction_changes(&self, transaction_id: u64) {
+        let changes = self.discard_transaction_changes(transaction_id);
+        self.subscriptions.send_changes(changes);
+    }
+
+    /// Returns the number of changes recorded for the transaction
+    pub(crate) fn transaction_changes_count(&self, transaction_id: u64) -> usize {
+        self.subscriptions
+            .pending_changes()
+            .transactions
+            .get(&transaction_id)
+            .map_or(0, Vec::len)
+    }
+
+    /// Drops changes recorded for the transaction after the first `count`
+    pub(crate) fn truncate_transaction_changes(&self, transaction_id: u64, count: usize) {
+        if let Some(changes) = self
+            .subscriptions
+            .pending_changes()
+            .transactions
+            .get_mut(&transaction_id)
+        {
+            changes.truncate(count);
+        }
+    }
+
+    /// Drops changes recorded for the transaction, returning them
+    pub(crate) fn discard_transaction_changes(&self, transaction_id: u64) -> Vec<KeyChange> {
+        self.subscriptions
+            .pending_changes()
+            .transactions
+            .remove(&transaction_id)
+            .unwrap_or_default()
+    }
+
+    /// Keeps changes of a prepared transaction until it is committed or
+    /// rolled back
+    pub(crate) fn prepare_transaction_changes(
+        &self,
+        changes: Vec<KeyChange>,
+        written_subtrees: WrittenSubtrees,
+        token: PreparedToken,
+    ) {
+        self.subscriptions.pending_changes().prepared.insert(
+            token.id(),
+            PreparedChanges {
+                changes,
+                written_subtrees,
+            },
+        );
+    }
+
+    /// Sends changes of a prepared transaction if `committed` and returns
+    /// subtrees it wrote, drops them otherwise. Changes of transactions
+    /// prepared before a restart are not known.
+    pub(crate) fn resolve_prepared_changes(
+        &self,
+        token: PreparedToken,
+        committed: bool,
+    ) -> Option<WrittenSubtrees> {
+        let prepared = self
+            .subscriptions
+            .pending_changes()
+            .prepared
+            .remove(&token.id())?;
+        if !committed {
+            return None;
+        }
+        self.subscriptions.send_changes(prepared.changes);
+        Some(prepared.written_subtrees)
+    }
+}
+
+/// Whether a subtree is an ancestor or a descendant of another one or the
+/// same subtree, a write to one may change the root hash of the other then
+fn is_related(a: &[Vec<u8>], b: &[Vec<u8>]) -> bool {
+    a.iter().zip(b).all(|(x, y)| x == y)
+}
@@ -2,21 +2,28 @@
 //! Subtrees handling is isolated so basically this module is about adapting
 //! Merk API to GroveDB needs.
 
+use std::fmt;
+
+#[cfg(feature = "full")]
 use merk::{
     proofs::{query::QueryItem, Query},
     tree::Tree,
     Op,
 };
 use serde::{Deserialize, Serialize};
-use storage::{rocksdb_storage::RocksDbStorage, RawIterator, StorageContext};
+#[cfg(feature = "full")]
+use storage::{BoxedStorage, RawIterator, StorageContext};
 
+#[cfg(feature = "full")]
 use crate::{
+    archive::check_not_cold_for_query,
     util::{merk_optional_tx, storage_context_optional_tx},
-    Error, Merk, PathQuery, SizedQuery, TransactionArg,
+    Error, Merk, PathQuery, QueryOptions, QueryResultElement, SizedQuery, TransactionArg,
 };
+use crate::{BytesDisplay, PathDisplay};
 
 /// Variants of GroveDB stored entities
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub enum Element {
     /// An ordinary value
     Item(Vec<u8>),
@@ -26,13 +33,49 @@ pub enum Element {
     /// Hash is stored to make Merk become different when its subtrees have
     /// changed, otherwise changes won't be reflected in parent trees.
     Tree([u8; 32]),
+    /// A subtree whose data was removed by [`crate::GroveDb::prune_subtree`],
+    /// contains the root hash the subtree had when it was pruned. It is
+    /// stored and hashed as a [`Element::Tree`] with the same root hash.
+    PrunedTree([u8; 32]),
+    /// An item which value is stored once for all items with the same value
+    /// by [`crate::GroveDb::insert_deduplicated`], contains the value hash
+    DedupItem([u8; 32]),
+}
+
+/// Keys and paths are shown with [`BytesDisplay`] and [`PathDisplay`], hashes
+/// as hex
+impl fmt::Debug for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, value) = match self {
+            Element::Item(value) => ("Item", BytesDisplay::new(value).to_string()),
+            Element::Reference(path) => (
+                "Reference",
+                PathDisplay::new(path.iter().map(|x| x.as_slice())).to_string(),
+            ),
+            Element::Tree(hash) => ("Tree", hex::encode(hash)),
+            Element::PrunedTree(hash) => ("PrunedTree", hex::encode(hash)),
+            Element::DedupItem(hash) => ("DedupItem", hex::encode(hash)),
+        };
+        write!(f, "{}({})", name, value)
+    }
+}
+
+/// Kind of an [`Element`] without its data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    Item,
+    Reference,
+    Tree,
+    PrunedTree,
+    DedupItem,
 }
 
+#[cfg(feature = "full")]
 pub struct PathQueryPushArgs<'db, 'ctx, 'a>
 where
     'db: 'ctx,
 {
-    pub storage: &'db RocksDbStorage,
+    pub storage: &'db BoxedStorage,
     pub transaction: TransactionArg<'db, 'ctx>,
     pub key: Option<&'a [u8]>,
     pub element: Element,
@@ -40,9 +83,10 @@ where
     pub subquery_key: Option<Vec<u8>>,
     pub subquery: Option<Query>,
     pub left_to_right: bool,
-    pub results: &'a mut Vec<Element>,
+    pub results: &'a mut Vec<QueryResultElement>,
     pub limit: &'a mut Option<u16>,
     pub offset: &'a mut Option<u16>,
+    pub options: QueryOptions<'a>,
 }
 
 impl Element {
@@ -51,6 +95,25 @@ impl Element {
         Element::Tree(Default::default())
     }
 
+    /// Returns the kind of the element
+    pub fn element_type(&self) -> ElementType {
+        match self {
+            Element::Item(_) => ElementType::Item,
+            Element::Reference(_) => ElementType::Reference,
+            Element::Tree(_) => ElementType::Tree,
+            Element::PrunedTree(_) => ElementType::PrunedTree,
+            Element::DedupItem(_) => ElementType::DedupItem,
+        }
+    }
+
+    /// Returns the length of the serialized element in bytes
+    pub fn byte_size(&self) -> usize {
+        bincode::serialized_size(self).map_or(0, |size| size as usize)
+    }
+}
+
+#[cfg(feature = "full")]
+impl Element {
     /// Delete an element from Merk under a key
     pub fn delete<'db, 'ctx, K: AsRef<[u8]>, S: StorageContext<'db, 'ctx> + 'ctx>(
         merk: &'ctx mut Merk<S>,
@@ -62,6 +125,38 @@ impl Element {
             .map_err(|e| Error::CorruptedData(e.to_string()))
     }
 
+    /// Get a kind of an element from Merk under a key by its serialized
+    /// variant tag, so the element's data is not deserialized
+    pub fn get_type<'db, 'ctx, K: AsRef<[u8]>, S: StorageContext<'db, 'ctx> + 'ctx>(
+        merk: &Merk<S>,
+        key: K,
+    ) -> Result<ElementType, Error> {
+        let bytes = merk
+            .get(key.as_ref())
+            .map_err(|e| Error::CorruptedData(e.to_string()))?
+            .ok_or_else(|| {
+                Error::PathKeyNotFound(format!(
+                    "key not found in Merk: {}",
+                    BytesDisplay::new(key.as_ref())
+                ))
+            })?;
+        // Bincode encodes enum variant index as a little endian u32
+        let tag: [u8; 4] = bytes
+            .get(..4)
+            .and_then(|tag| tag.try_into().ok())
+            .ok_or_else(|| Error::CorruptedData(String::from("unable to deserialize element")))?;
+        match u32::from_le_bytes(tag) {
+            0 => Ok(ElementType::Item),
+            1 => Ok(ElementType::Reference),
+            2 => Ok(ElementType::Tree),
+            3 => Ok(ElementType::PrunedTree),
+            4 => Ok(ElementType::DedupItem),
+            _ => Err(Error::CorruptedData(String::from(
+                "unable to deserialize element",
+            ))),
+        }
+    }
+
     /// Get an element from Merk under a key; path should be resolved and proper
     /// Merk should be loaded by this moment
     pub fn get<'db, 'ctx, K: AsRef<[u8]>, S: StorageContext<'db, 'ctx> + 'ctx>(
@@ -72,7 +167,10 @@ impl Element {
             merk.get(key.as_ref())
                 .map_err(|e| Error::CorruptedData(e.to_string()))?
                 .ok_or_else(|| {
-                    Error::PathKeyNotFound(format!("key not found in Merk: {}", hex::encode(key)))
+                    Error::PathKeyNotFound(format!(
+                        "key not found in Merk: {}",
+                        BytesDisplay::new(key.as_ref())
+                    ))
                 })?
                 .as_slice(),
         )
@@ -81,7 +179,7 @@ impl Element {
     }
 
     pub fn get_query(
-        storage: &RocksDbStorage,
+        storage: &BoxedStorage,
         merk_path: &[&[u8]],
         query: &Query,
         transaction: TransactionArg,
@@ -94,14 +192,22 @@ impl Element {
 
     fn basic_push(args: PathQueryPushArgs) -> Result<(), Error> {
         let PathQueryPushArgs {
+            key,
             element,
+            path,
             results,
             limit,
             offset,
             ..
         } = args;
         if offset.unwrap_or(0) == 0 {
-            results.push(element);
+            results.push(QueryResultElement {
+                path: path
+                    .map(|path| path.iter().map(|x| x.to_vec()).collect())
+                    .unwrap_or_default(),
+                key: key.map(|key| key.to_vec()).unwrap_or_default(),
+                element,
+            });
             if let Some(limit) = limit {
                 *limit -= 1;
             }
@@ -124,6 +230,7 @@ impl Element {
             results,
             limit,
             offset,
+            options,
         } = args;
         match element {
             Element::Tree(_) => {
@@ -135,20 +242,23 @@ impl Element {
                 path_vec.push(key.ok_or(Error::MissingParameter(
                     "the key must be provided when using a subquery key",
                 ))?);
+                check_not_cold_for_query(storage, &path_vec, transaction)?;
 
                 if let Some(subquery) = subquery {
                     if let Some(subquery_key) = &subquery_key {
                         path_vec.push(subquery_key.as_slice());
+                        check_not_cold_for_query(storage, &path_vec, transaction)?;
                     }
 
                     let inner_query = SizedQuery::new(subquery, *limit, *offset);
                     let path_vec_owned = path_vec.iter().map(|x| x.to_vec()).collect();
                     let inner_path_query = PathQuery::new(path_vec_owned, inner_query);
 
-                    let (mut sub_elements, skipped) = Element::get_path_query(
+                    let (mut sub_elements, skipped) = Element::get_path_query_result_elements(
                         storage,
                         &path_vec,
                         &inner_path_query,
+                        options,
                         transaction,
                     )?;
 
@@ -167,7 +277,13 @@ impl Element {
                             transaction,
                             subtree,
                             {
-                                results.push(Element::get(&subtree, subquery_key.as_slice())?);
+                                let element = Element::get(&subtree, subquery_key.as_slice())?;
+                                options.add_seek(subquery_key.len() + element.byte_size())?;
+                                results.push(QueryResultElement {
+                                    path: path_vec.iter().map(|x| x.to_vec()).collect(),
+                                    key: subquery_key.clone(),
+                                    element,
+                                });
                             }
                         );
                         if let Some(limit) = limit {
@@ -196,13 +312,14 @@ impl Element {
                     results,
                     limit,
                     offset,
+                    options,
                 })?;
             }
         }
         Ok(())
     }
 
-    fn subquery_paths_for_sized_query(
+    pub(crate) fn subquery_paths_for_sized_query(
         sized_query: &SizedQuery,
         key: &[u8],
     ) -> (Option<Vec<u8>>, Option<Query>) {
@@ -231,15 +348,16 @@ impl Element {
     }
 
     fn query_item(
-        storage: &RocksDbStorage,
+        storage: &BoxedStorage,
         item: &QueryItem,
-        results: &mut Vec<Element>,
+        results: &mut Vec<QueryResultElement>,
         merk_path: &[&[u8]],
         sized_query: &SizedQuery,
         path: Option<&[&[u8]]>,
         transaction: TransactionArg,
         limit: &mut Option<u16>,
         offset: &mut Option<u16>,
+        options: QueryOptions,
         add_element_function: fn(PathQueryPushArgs) -> Result<(), Error>,
     ) -> Result<(), Error> {
         if !item.is_range() {
@@ -249,6 +367,7 @@ impl Element {
                     merk_optional_tx!(storage, merk_path.iter().copied(), transaction, subtree, {
                         Element::get(&subtree, key)
                     });
+                options.add_seek(key.len() + element_res.as_ref().map_or(0, Element::byte_size))?;
                 match element_res {
                     Ok(element) => {
                         let (subquery_key, subquery) =
@@ -265,6 +384,7 @@ impl Element {
                             results,
                             limit,
                             offset,
+                            options,
                         })
                     },
                     Err(Error::PathKeyNotFound(_)) => Ok(()),
@@ -278,14 +398,15 @@ impl Element {
         } else {
             // this is a query on a range
             storage_context_optional_tx!(storage, merk_path.iter().copied(), transaction, ctx, {
-                let mut iter = ctx.raw_iter();
+                let mut iter = ctx.raw_iter_opt(options.readahead_bytes, options.fill_cache);
 
                 item.seek_for_iter(&mut iter, sized_query.query.left_to_right);
 
                 while item.iter_is_valid_for_type(&iter, *limit, sized_query.query.left_to_right) {
-                    let element =
-                        raw_decode(iter.value().expect("if key exists then value should too"))?;
+                    let value = iter.value().expect("if key exists then value should too");
                     let key = iter.key().expect("key should exist");
+                    options.add_seek(key.len() + value.len())?;
+                    let element = raw_decode(value)?;
                     let (subquery_key, subquery) =
                             Self::subquery_paths_for_sized_query(sized_query, key);
                     add_element_function(PathQueryPushArgs {
@@ -300,6 +421,7 @@ impl Element {
                         results,
                         limit,
                         offset,
+                        options,
                     })?;
                     if sized_query.query.left_to_right {
                         iter.next();
@@ -307,27 +429,35 @@ impl Element {
                         iter.prev();
                     }
                 }
+                iter.status()?;
                 Ok(())
             })
         }
     }
 
     pub fn get_query_apply_function(
-        storage: &RocksDbStorage,
+        storage: &BoxedStorage,
         merk_path: &[&[u8]],
         sized_query: &SizedQuery,
         path: Option<&[&[u8]]>,
         transaction: TransactionArg,
+        options: QueryOptions,
         add_element_function: fn(PathQueryPushArgs) -> Result<(), Error>,
-    ) -> Result<(Vec<Element>, u16), Error> {
+    ) -> Result<(Vec<QueryResultElement>, u16), Error> {
         let mut results = Vec::new();
 
         let mut limit = sized_query.limit;
         let original_offset = sized_query.offset;
         let mut offset = original_offset;
 
-        if sized_query.query.left_to_right {
-            for item in sized_query.query.iter() {
+        // Overlapping query items are merged so no element is returned twice
+        let mut query = sized_query.query.clone();
+        if !query.normalize() {
+            return Ok((results, 0));
+        }
+
+        if query.left_to_right {
+            for item in query.iter() {
                 Self::query_item(
                     storage,
                     item,
@@ -338,6 +468,7 @@ impl Element {
                     transaction,
                     &mut limit,
                     &mut offset,
+                    options,
                     add_element_function,
                 )?;
                 if limit == Some(0) {
@@ -345,7 +476,7 @@ impl Element {
                 }
             }
         } else {
-            for item in sized_query.query.rev_iter() {
+            for item in query.rev_iter() {
                 Self::query_item(
                     storage,
                     item,
@@ -356,6 +487,7 @@ impl Element {
                     transaction,
                     &mut limit,
                     &mut offset,
+                    options,
                     add_element_function,
                 )?;
                 if limit == Some(0) {
@@ -374,11 +506,51 @@ impl Element {
 
     // Returns a vector of elements, and the number of skipped elements
     pub fn get_path_query(
-        storage: &RocksDbStorage,
+        storage: &BoxedStorage,
         merk_path: &[&[u8]],
         path_query: &PathQuery,
         transaction: TransactionArg,
     ) -> Result<(Vec<Element>, u16), Error> {
+        Element::get_path_query_with_options(
+            storage,
+            merk_path,
+            path_query,
+            QueryOptions::default(),
+            transaction,
+        )
+    }
+
+    /// Same as [`Element::get_path_query`] with storage read options applied
+    /// to range iterations
+    pub fn get_path_query_with_options(
+        storage: &BoxedStorage,
+        merk_path: &[&[u8]],
+        path_query: &PathQuery,
+        options: QueryOptions,
+        transaction: TransactionArg,
+    ) -> Result<(Vec<Element>, u16), Error> {
+        let (results, skipped) = Element::get_path_query_result_elements(
+            storage,
+            merk_path,
+            path_query,
+            options,
+            transaction,
+        )?;
+        Ok((
+            results.into_iter().map(|result| result.element).collect(),
+            skipped,
+        ))
+    }
+
+    /// Same as [`Element::get_path_query_with_options`] keeping paths and
+    /// keys of found elements
+    pub fn get_path_query_result_elements(
+        storage: &BoxedStorage,
+        merk_path: &[&[u8]],
+        path_query: &PathQuery,
+        options: QueryOptions,
+        transaction: TransactionArg,
+    ) -> Result<(Vec<QueryResultElement>, u16), Error> {
         let path_slices = path_query
             .path
             .iter()
@@ -390,25 +562,31 @@ impl Element {
             &path_query.query,
             Some(path_slices.as_slice()),
             transaction,
+            options,
             Element::path_query_push,
         )
     }
 
     /// Returns a vector of elements, and the number of skipped elements
     pub fn get_sized_query(
-        storage: &RocksDbStorage,
+        storage: &BoxedStorage,
         merk_path: &[&[u8]],
         sized_query: &SizedQuery,
         transaction: TransactionArg,
     ) -> Result<(Vec<Element>, u16), Error> {
-        Element::get_query_apply_function(
+        let (results, skipped) = Element::get_query_apply_function(
             storage,
             merk_path,
             sized_query,
             None,
             transaction,
+            QueryOptions::default(),
             Element::path_query_push,
-        )
+        )?;
+        Ok((
+            results.into_iter().map(|result| result.element).collect(),
+            skipped,
+        ))
     }
 
     /// Insert an element in Merk under a key; path should be resolved and
@@ -432,16 +610,33 @@ impl Element {
             .map_err(|e| Error::CorruptedData(e.to_string()))
     }
 
+    /// Insert an element in Merk under a key like [`Element::insert`], but
+    /// stored compressed. Hashes are computed over the uncompressed element,
+    /// so they don't depend on whether the element is compressed.
+    pub fn insert_compressed<'db, 'ctx, K: AsRef<[u8]>, S: StorageContext<'db, 'ctx>>(
+        &self,
+        merk: &'ctx mut Merk<S>,
+        key: K,
+    ) -> Result<(), Error> {
+        let serialized = bincode::serialize(self)
+            .map_err(|_| Error::CorruptedData(String::from("unable to serialize element")))?;
+        let batch_operations = [(key, Op::PutCompressed(serialized))];
+        merk.apply::<_, Vec<u8>>(&batch_operations, &[])
+            .map_err(|e| Error::CorruptedData(e.to_string()))
+    }
+
     pub fn iterator<I: RawIterator>(mut raw_iter: I) -> ElementsIterator<I> {
         raw_iter.seek_to_first();
         ElementsIterator::new(raw_iter)
     }
 }
 
+#[cfg(feature = "full")]
 pub struct ElementsIterator<I: RawIterator> {
     raw_iter: I,
 }
 
+#[cfg(feature = "full")]
 pub fn raw_decode(bytes: &[u8]) -> Result<Element, Error> {
     let tree = Tree::decode_raw(bytes).map_err(|e| Error::CorruptedData(e.to_string()))?;
     let element: Element = bincode::deserialize(tree.value())
@@ -449,6 +644,7 @@ pub fn raw_decode(bytes: &[u8]) -> Result<Element, Error> {
     Ok(element)
 }
 
+#[cfg(feature = "full")]
 impl<I: RawIterator> ElementsIterator<I> {
     pub fn new(raw_iter: I) -> Self {
         ElementsIterator { raw_iter }
@@ -465,12 +661,14 @@ impl<I: RawIterator> ElementsIterator<I> {
                 None
             }
         } else {
+            // An error makes the raw iterator invalid like the end of data
+            self.raw_iter.status()?;
             None
         })
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "full"))]
 mod tests {
     use merk::test_utils::TempMerk;
     use storage::Storage;
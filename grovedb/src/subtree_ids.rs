@@ -0,0 +1,95 @@
+//! Module for stable subtree identifiers.
+//! A subtree is allocated an identifier from a counter when it is created and
+//! its data is stored under the identifier instead of a prefix derived from
+//! its path. The identifier is kept in meta storage under the path-derived
+//! prefix, see [`subtree_id_key`], and storage contexts look it up, so a
+//! subtree moved to another path keeps its data by storing the identifier for
+//! the new path, without rewriting every descendant key. The identifier is
+//! removed with the subtree, so a subtree created again at the same path gets
+//! a new one. Subtrees created before identifiers were allocated have none and
+//! are addressed by the path-derived prefix.
+
+use storage::{dyn_storage::subtree_id_key, rocksdb_storage::RocksDbStorage, StorageContext};
+
+use crate::{util::meta_storage_context_optional_tx, Error, GroveDb, TransactionArg};
+
+/// A key in meta storage of the counter to allocate the next subtree
+/// identifier from
+const NEXT_SUBTREE_ID_KEY: &[u8] = b"next_subtree_id";
+
+/// Identifier of a subtree, stable for the subtree's lifetime
+pub type SubtreeId = [u8; 32];
+
+impl GroveDb {
+    /// Returns the identifier of the subtree at the path. Subtrees created
+    /// before identifiers were allocated are identified by their path-derived
+    /// prefix, which their data is stored under.
+    pub fn subtree_id<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<SubtreeId, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: DoubleEndedIterator + ExactSizeIterator + Clone,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        let path_prefix = RocksDbStorage::build_prefix(path_iter);
+        let stored = meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            meta_storage.get_meta(subtree_id_key(&path_prefix))?
+        });
+        stored
+            .unwrap_or(path_prefix)
+            .try_into()
+            .map_err(|_| Error::CorruptedData(String::from("invalid subtree identifier")))
+    }
+
+    /// Allocates an identifier for a subtree being created at the path, it
+    /// must be done before any data of the subtree is written
+    pub(crate) fn assign_subtree_id<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path_prefix = RocksDbStorage::build_prefix(path);
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            let next = match meta_storage.get_meta(NEXT_SUBTREE_ID_KEY)? {
+                Some(bytes) => u64::from_be_bytes(bytes.try_into().map_err(|_| {
+                    Error::CorruptedData(String::from("invalid subtree identifier counter"))
+                })?),
+                None => 0,
+            };
+            meta_storage.put_meta(NEXT_SUBTREE_ID_KEY, &(next + 1).to_be_bytes())?;
+            meta_storage.put_meta(subtree_id_key(&path_prefix), &allocated_subtree_id(next))?;
+        });
+        Ok(())
+    }
+
+    /// Removes the identifier of a subtree once its data is deleted
+    pub(crate) fn remove_subtree_id<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path_prefix = RocksDbStorage::build_prefix(path);
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            meta_storage.delete_meta(subtree_id_key(&path_prefix))?;
+        });
+        Ok(())
+    }
+}
+
+/// Makes an identifier from a counter value. Path-derived prefixes are
+/// hashes, so they practically never have the leading zero bytes.
+fn allocated_subtree_id(counter: u64) -> SubtreeId {
+    let mut id = SubtreeId::default();
+    id[24..].copy_from_slice(&counter.to_be_bytes());
+    id
+}
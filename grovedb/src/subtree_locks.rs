@@ -0,0 +1,90 @@
+//! Module for subtree-level write locks.
+//! Locks are advisory and held in memory: threads building and applying
+//! independent batches lock the subtrees they touch, so batches touching
+//! disjoint subtrees run concurrently while overlapping ones are serialized.
+//! A lock on a subtree conflicts with locks on its ancestors and descendants.
+//! Disjoint subtrees still share ancestors, so batches are applied in a
+//! transaction scoped to the locked subtree, see
+//! [`SubtreeLockGuard::into_transaction`], which updates ancestors on commit
+//! one commit at a time.
+
+use std::sync::{Condvar, Mutex};
+
+use crate::{Error, GroveDb};
+
+/// Behavior of [`GroveDb::lock_subtree`] on a conflicting lock, and of
+/// queries over the limit set with [`GroveDb::set_max_concurrent_queries`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockWait {
+    /// Wait until conflicting locks are released
+    Block,
+    /// Return [`Error::SubtreeLocked`] immediately
+    FailFast,
+}
+
+/// Paths of currently locked subtrees
+#[derive(Default)]
+pub(crate) struct SubtreeLocks {
+    locked: Mutex<Vec<Vec<Vec<u8>>>>,
+    released: Condvar,
+}
+
+impl SubtreeLocks {
+    fn conflicts(locked: &[Vec<Vec<u8>>], path: &[Vec<u8>]) -> bool {
+        locked
+            .iter()
+            .any(|other| other.starts_with(path) || path.starts_with(other))
+    }
+}
+
+/// Guard of a subtree lock, the lock is released on drop
+pub struct SubtreeLockGuard<'db> {
+    pub(crate) db: &'db GroveDb,
+    pub(crate) path: Vec<Vec<u8>>,
+}
+
+impl Drop for SubtreeLockGuard<'_> {
+    fn drop(&mut self) {
+        let locks = &self.db.subtree_locks;
+        let mut locked = locks.locked.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(idx) = locked.iter().position(|other| other == &self.path) {
+            locked.swap_remove(idx);
+        }
+        locks.released.notify_all();
+    }
+}
+
+impl GroveDb {
+    /// Locks a subtree for writing until the returned guard is dropped. The
+    /// lock conflicts with locks on the subtree's ancestors and descendants,
+    /// on conflict it waits or fails depending on `wait`. Locks are only
+    /// respected by other lockers, GroveDB operations don't check them.
+    /// Writes of lockers to disjoint subtrees made with separate transactions
+    /// conflict on shared ancestors, so they should be made in the transaction
+    /// returned by [`SubtreeLockGuard::into_transaction`].
+    pub fn lock_subtree<'p, P>(&self, path: P, wait: LockWait) -> Result<SubtreeLockGuard, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path: Vec<Vec<u8>> = path.into_iter().map(|x| x.to_vec()).collect();
+        let mut locked = self
+            .subtree_locks
+            .locked
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        while SubtreeLocks::conflicts(&locked, &path) {
+            match wait {
+                LockWait::FailFast => return Err(Error::SubtreeLocked),
+                LockWait::Block => {
+                    locked = self
+                        .subtree_locks
+                        .released
+                        .wait(locked)
+                        .unwrap_or_else(|e| e.into_inner());
+                }
+            }
+        }
+        locked.push(path.clone());
+        Ok(SubtreeLockGuard { db: self, path })
+    }
+}
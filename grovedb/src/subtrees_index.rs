@@ -0,0 +1,128 @@
+//! Module for child subtrees bookkeeping.
+//! Every subtree keeps an entry per direct child subtree in roots storage,
+//! keyed by the child key under a dedicated prefix, so descendant subtrees can
+//! be enumerated with a prefix scan of small records instead of iterating over
+//! and deserializing all elements of every subtree, and maintaining the index
+//! doesn't rewrite a record growing with the number of children.
+
+use storage::{RawIterator, StorageContext};
+
+use crate::{util::storage_context_optional_tx, Element, Error, GroveDb, TransactionArg};
+
+/// A prefix of keys in roots storage of a subtree marking its child subtrees
+const CHILD_SUBTREE_PREFIX: &[u8] = b"child_subtree/";
+/// A key in roots storage of a subtree the keys of its child subtrees were
+/// stored under in a single record before format version 2
+const LEGACY_CHILD_SUBTREES_KEY: &[u8] = b"child_subtrees";
+
+fn child_subtree_key(key: &[u8]) -> Vec<u8> {
+    let mut child_key = CHILD_SUBTREE_PREFIX.to_vec();
+    child_key.extend_from_slice(key);
+    child_key
+}
+
+impl GroveDb {
+    /// Returns keys of direct child subtrees of a subtree at the path in key
+    /// order
+    pub(crate) fn get_child_subtrees<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<Vec<Vec<u8>>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut children = Vec::new();
+        storage_context_optional_tx!(self.db, path, transaction, storage, {
+            let mut raw_iter = storage.raw_iter_roots();
+            raw_iter.seek(CHILD_SUBTREE_PREFIX);
+            while let Some(key) = raw_iter
+                .key()
+                .and_then(|key| key.strip_prefix(CHILD_SUBTREE_PREFIX))
+            {
+                children.push(key.to_vec());
+                raw_iter.next();
+            }
+            raw_iter.status()?;
+        });
+        Ok(children)
+    }
+
+    pub(crate) fn add_child_subtree<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        storage_context_optional_tx!(self.db, path, transaction, storage, {
+            storage.put_root(child_subtree_key(key), &[])?;
+        });
+        Ok(())
+    }
+
+    pub(crate) fn remove_child_subtree<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        storage_context_optional_tx!(self.db, path, transaction, storage, {
+            storage.delete_root(child_subtree_key(key))?;
+        });
+        Ok(())
+    }
+
+    /// Drops child subtrees of a subtree which is being deleted
+    pub(crate) fn clear_child_subtrees<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone,
+    {
+        let path_iter = path.into_iter();
+        for key in self.get_child_subtrees(path_iter.clone(), transaction)? {
+            self.remove_child_subtree(path_iter.clone(), &key, transaction)?;
+        }
+        Ok(())
+    }
+
+    /// Builds the child subtrees index of all subtrees by iterating over their
+    /// elements, dropping records of the index kept before format version 2.
+    /// Safe to repeat, as entries are put by child keys.
+    pub(crate) fn backfill_child_subtrees(&self) -> Result<(), Error> {
+        let transaction = self.start_transaction();
+        let mut queue: Vec<Vec<Vec<u8>>> = vec![Vec::new()];
+        while let Some(path) = queue.pop() {
+            let path_iter = path.iter().map(|x| x.as_slice());
+            let mut children = Vec::new();
+            {
+                let storage = self
+                    .db
+                    .get_transactional_storage_context(path_iter.clone(), &transaction);
+                storage.delete_root(LEGACY_CHILD_SUBTREES_KEY)?;
+                let mut raw_iter = Element::iterator(storage.raw_iter());
+                while let Some((key, value)) = raw_iter.next()? {
+                    if let Element::Tree(_) = value {
+                        children.push(key);
+                    }
+                }
+            }
+            for key in children {
+                self.add_child_subtree(path_iter.clone(), &key, Some(&transaction))?;
+                let mut child_path = path.clone();
+                child_path.push(key);
+                queue.push(child_path);
+            }
+        }
+        self.commit_transaction(transaction)
+    }
+}
@@ -0,0 +1,324 @@
+//! Module for tenant views.
+//! Multi-tenant embedders keep every tenant's data in its own subtree.
+//! [`TenantView`] is a handle to such a subtree: paths of its operations are
+//! relative to the tenant subtree, so application code can't reach data of
+//! other tenants by forgetting to prefix a path. References are rooted at the
+//! tenant subtree when inserted and can't be followed out of it, and auxiliary
+//! data keys are prefixed per tenant.
+
+use crate::{
+    util::merk_optional_tx, Element, Error, GroveDb, GroveDbOp, PathQuery, QueryOptions,
+    RocksDbStorage, TransactionArg,
+};
+
+/// Handle rooted at a subtree, see [`GroveDb::tenant_view`]. Paths of all
+/// operations, including reference paths of inserted elements and paths of
+/// queries, are relative to the root subtree. Operations fail with
+/// [`Error::TenantRootNotFound`] once the root subtree is deleted.
+pub struct TenantView<'db> {
+    db: &'db GroveDb,
+    root_path: Vec<Vec<u8>>,
+    /// Prefix of auxiliary data keys of the view, the prefix of the root
+    /// subtree
+    aux_prefix: Vec<u8>,
+}
+
+impl<'db> TenantView<'db> {
+    /// Returns the path of the root subtree of the view
+    pub fn root_path(&self) -> &[Vec<u8>] {
+        &self.root_path
+    }
+
+    /// Returns root hash of the root subtree of the view
+    pub fn root_hash(&self, transaction: TransactionArg) -> Result<[u8; 32], Error> {
+        self.check_root(transaction)?;
+        let path = self.full_path(std::iter::empty::<&[u8]>());
+        merk_optional_tx!(self.db.db, path, transaction, subtree, {
+            Ok(subtree.root_hash())
+        })
+    }
+
+    /// Inserts an element like [`GroveDb::insert`], a reference is rooted at
+    /// the root subtree of the view
+    pub fn insert<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        element: Element,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        self.check_root(transaction)?;
+        let element = self.root_element(element);
+        self.db
+            .insert(self.full_path(path), key, element, transaction)
+    }
+
+    /// Inserts an element like [`GroveDb::insert_if_not_exists`], a reference
+    /// is rooted at the root subtree of the view
+    pub fn insert_if_not_exists<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        element: Element,
+        transaction: TransactionArg,
+    ) -> Result<bool, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        self.check_root(transaction)?;
+        let element = self.root_element(element);
+        self.db
+            .insert_if_not_exists(self.full_path(path), key, element, transaction)
+    }
+
+    /// Gets an element following references like [`GroveDb::get`]. Fails with
+    /// [`Error::ReferenceOutOfView`] if a reference leads out of the view.
+    pub fn get<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<Element, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        self.check_root(transaction)?;
+        self.db
+            .get_within(self.full_path(path), key, &self.root_path, transaction)
+    }
+
+    /// Deletes an element like [`GroveDb::delete`]
+    pub fn delete<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        self.check_root(transaction)?;
+        self.db.delete(self.full_path(path), key, transaction)
+    }
+
+    /// Deletes an element and its emptied ancestors like
+    /// [`GroveDb::delete_up_tree_while_empty`], `stop_path_height` is relative
+    /// to the root subtree. The root subtree itself is never deleted.
+    pub fn delete_up_tree_while_empty<'p, P>(
+        &self,
+        path: P,
+        key: &'p [u8],
+        stop_path_height: Option<u16>,
+        transaction: TransactionArg,
+    ) -> Result<u16, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        self.check_root(transaction)?;
+        let root_height = self.root_path.len() as u16;
+        // Deletion stops at subtrees of the stop height, the root subtree is
+        // one level below its parent
+        let stop_path_height = match stop_path_height {
+            Some(height) => Some(root_height + height),
+            None => root_height.checked_sub(1),
+        };
+        self.db
+            .delete_up_tree_while_empty(self.full_path(path), key, stop_path_height, transaction)
+    }
+
+    /// Returns whether the subtree at the path is empty, the empty path is the
+    /// root subtree
+    pub fn is_empty_tree<'p, P>(&self, path: P, transaction: TransactionArg) -> Result<bool, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        self.check_root(transaction)?;
+        self.db.is_empty_tree(self.full_path(path), transaction)
+    }
+
+    /// Runs a path query like [`GroveDb::get_path_query`]. Fails with
+    /// [`Error::ReferenceOutOfView`] if a found reference leads out of the
+    /// view.
+    pub fn get_path_query(
+        &self,
+        path_query: &PathQuery,
+        transaction: TransactionArg,
+    ) -> Result<(Vec<Vec<u8>>, u16), Error> {
+        self.check_root(transaction)?;
+        self.db.get_path_query_within(
+            &self.root_path_query(path_query.clone()),
+            &self.root_path,
+            QueryOptions::default(),
+            transaction,
+        )
+    }
+
+    /// Runs path queries like [`GroveDb::query_many`], all queries see the
+    /// same state
+    pub fn query_many(
+        &self,
+        path_queries: Vec<PathQuery>,
+        transaction: TransactionArg,
+    ) -> Result<Vec<(Vec<Vec<u8>>, u16)>, Error> {
+        if transaction.is_none() {
+            let snapshot = self.db.start_snapshot_transaction();
+            return self.query_many(path_queries, Some(&snapshot));
+        }
+        path_queries
+            .iter()
+            .map(|path_query| self.get_path_query(path_query, transaction))
+            .collect()
+    }
+
+    /// Generates a proof for path queries like [`GroveDb::prove`]. The proof
+    /// is verified against the GroveDB root hash, so it holds paths from the
+    /// GroveDB root and hashes of subtrees on the way to the root subtree.
+    pub fn prove(
+        &self,
+        path_queries: &[PathQuery],
+        transaction: TransactionArg,
+    ) -> Result<Vec<u8>, Error> {
+        self.check_root(transaction)?;
+        let path_queries: Vec<PathQuery> = path_queries
+            .iter()
+            .map(|path_query| self.root_path_query(path_query.clone()))
+            .collect();
+        self.db.prove(&path_queries, transaction)
+    }
+
+    /// Applies operations atomically like [`GroveDb::apply_batch`], paths of
+    /// operations and references are relative to the root subtree
+    pub fn apply_batch(
+        &self,
+        ops: Vec<GroveDbOp>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        self.check_root(transaction)?;
+        let ops = ops
+            .into_iter()
+            .map(|op| match op {
+                GroveDbOp::Insert { path, key, element } => GroveDbOp::Insert {
+                    path: self.rooted_path(path),
+                    key,
+                    element: self.root_element(element),
+                },
+                GroveDbOp::Delete { path, key } => GroveDbOp::Delete {
+                    path: self.rooted_path(path),
+                    key,
+                },
+            })
+            .collect();
+        self.db.apply_batch(ops, transaction)
+    }
+
+    /// Puts auxiliary data like [`GroveDb::put_aux`] under a key space of the
+    /// view
+    pub fn put_aux<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        value: &[u8],
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        self.check_root(transaction)?;
+        self.db.put_aux(self.aux_key(key), value, transaction)
+    }
+
+    /// Deletes auxiliary data of the view like [`GroveDb::delete_aux`]
+    pub fn delete_aux<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        self.check_root(transaction)?;
+        self.db.delete_aux(self.aux_key(key), transaction)
+    }
+
+    /// Gets auxiliary data of the view like [`GroveDb::get_aux`]
+    pub fn get_aux<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        transaction: TransactionArg,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        self.check_root(transaction)?;
+        self.db.get_aux(self.aux_key(key), transaction)
+    }
+
+    fn full_path<'a, 'p: 'a, P>(&'a self, path: P) -> Vec<&'a [u8]>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let mut full_path: Vec<&[u8]> = self.root_path.iter().map(|x| x.as_slice()).collect();
+        for segment in path {
+            full_path.push(segment);
+        }
+        full_path
+    }
+
+    fn rooted_path(&self, path: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        self.root_path.iter().cloned().chain(path).collect()
+    }
+
+    fn root_path_query(&self, mut path_query: PathQuery) -> PathQuery {
+        path_query.path = self.rooted_path(path_query.path);
+        path_query
+    }
+
+    /// Roots paths of references, so they can't point outside of the view
+    fn root_element(&self, element: Element) -> Element {
+        match element {
+            Element::Reference(reference_path) => {
+                Element::Reference(self.rooted_path(reference_path))
+            }
+            other => other,
+        }
+    }
+
+    fn aux_key<K: AsRef<[u8]>>(&self, key: K) -> Vec<u8> {
+        let mut aux_key = self.aux_prefix.clone();
+        aux_key.extend_from_slice(key.as_ref());
+        aux_key
+    }
+
+    /// Fails with [`Error::TenantRootNotFound`] if the root subtree is gone
+    fn check_root(&self, transaction: TransactionArg) -> Result<(), Error> {
+        check_tenant_root(self.db, &self.root_path, transaction)
+    }
+}
+
+impl GroveDb {
+    /// Returns a handle whose operations are rooted at the subtree at the
+    /// path, fails with [`Error::TenantRootNotFound`] if there is no subtree
+    /// there within the transaction
+    pub fn tenant_view<'p, P>(
+        &self,
+        root_path: P,
+        transaction: TransactionArg,
+    ) -> Result<TenantView<'_>, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let root_path: Vec<Vec<u8>> = root_path.into_iter().map(|x| x.to_vec()).collect();
+        check_tenant_root(self, &root_path, transaction)?;
+        Ok(TenantView {
+            db: self,
+            aux_prefix: RocksDbStorage::build_prefix(root_path.iter().map(|x| x.as_slice())),
+            root_path,
+        })
+    }
+}
+
+fn check_tenant_root(
+    db: &GroveDb,
+    root_path: &[Vec<u8>],
+    transaction: TransactionArg,
+) -> Result<(), Error> {
+    match db
+        .check_subtree_exists_path_not_found(root_path.iter().map(|x| x.as_slice()), transaction)
+    {
+        Err(Error::PathNotFound(_)) => Err(Error::TenantRootNotFound),
+        result => result,
+    }
+}
@@ -246,11 +246,61 @@ fn test_tree_structure_is_persistent() {
 #[test]
 fn test_root_tree_leafs_are_noted() {
     let db = make_grovedb();
-    let mut hm = BTreeMap::new();
-    hm.insert(TEST_LEAF.to_vec(), 0);
-    hm.insert(ANOTHER_TEST_LEAF.to_vec(), 1);
-    assert_eq!(db.get_root_leaf_keys(None).unwrap(), hm);
-    assert_eq!(db.get_root_tree(None).unwrap().leaves_len(), 2);
+    let (listed, _) = db
+        .list_recursive([], 1, 10, None)
+        .expect("successful listing");
+    let keys: Vec<Vec<u8>> = listed.into_iter().map(|l| l.key).collect();
+    assert_eq!(keys, vec![TEST_LEAF.to_vec(), ANOTHER_TEST_LEAF.to_vec()]);
+}
+
+#[test]
+fn test_root_leaf_deletion() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"innertree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"innertree"],
+        b"key",
+        Element::Item(b"ayy".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let root_hash = db.root_hash(None).unwrap();
+
+    db.insert([], b"new_leaf", Element::empty_tree(), None)
+        .expect("successful root leaf insert");
+    assert_ne!(db.root_hash(None).unwrap(), root_hash);
+    db.delete([], b"new_leaf", None)
+        .expect("successful root leaf delete");
+    assert_eq!(db.root_hash(None).unwrap(), root_hash);
+
+    db.delete([], TEST_LEAF, None)
+        .expect("successful root leaf delete");
+    assert!(matches!(
+        db.get([], TEST_LEAF, None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    assert!(matches!(
+        db.get([TEST_LEAF, b"innertree"], b"key", None),
+        Err(Error::PathNotFound(_))
+    ));
+
+    let mut query = Query::new();
+    query.insert_all();
+    let proof = db
+        .prove(&[PathQuery::new_unsized(vec![], query)], None)
+        .expect("successful proof generation");
+    let (root_hash, results) = GroveDb::execute_proof(&proof).expect("successful proof execution");
+    assert_eq!(Some(root_hash), db.root_hash(None).unwrap());
+    let root_leaves: Vec<Vec<u8>> = results[&Vec::<Vec<u8>>::new()]
+        .all()
+        .map(|(key, _)| key.clone())
+        .collect();
+    assert_eq!(root_leaves, vec![ANOTHER_TEST_LEAF.to_vec()]);
+
+    db.delete([], ANOTHER_TEST_LEAF, None)
+        .expect("successful root leaf delete");
+    assert_eq!(db.root_hash(None).unwrap(), None);
 }
 
 // #[test]
@@ -636,81 +686,44 @@ fn test_root_tree_leafs_are_noted() {
 //     assert_eq!(elem, Element::Item(b"value3".to_vec()));
 // }
 
-// #[test]
-// fn test_checkpoint() {
-//     let mut db = make_grovedb();
-//     let element1 = Element::Item(b"ayy".to_vec());
-//
-//     db.insert([], b"key1", Element::empty_tree())
-//         .expect("cannot insert a subtree 1 into GroveDB");
-//     db.insert([b"key1"], b"key2", Element::empty_tree())
-//         .expect("cannot insert a subtree 2 into GroveDB");
-//     db.insert([b"key1", b"key2"], b"key3", element1.clone())
-//         .expect("cannot insert an item into GroveDB");
-//
-//     assert_eq!(
-//         db.get([b"key1", b"key2"], b"key3")
-//             .expect("cannot get from grovedb"),
-//         element1
-//     );
-//
-//     let checkpoint_tempdir = TempDir::new("checkpoint").expect("cannot open
-// tempdir");     let mut checkpoint = db
-//         .checkpoint(checkpoint_tempdir.path().join("checkpoint"))
-//         .expect("cannot create a checkpoint");
-//
-//     assert_eq!(
-//         db.get([b"key1", b"key2"], b"key3")
-//             .expect("cannot get from grovedb"),
-//         element1
-//     );
-//     assert_eq!(
-//         checkpoint
-//             .get(&[b"key1", b"key2"], b"key3")
-//             .expect("cannot get from checkpoint"),
-//         element1
-//     );
-//
-//     let element2 = Element::Item(b"ayy2".to_vec());
-//     let element3 = Element::Item(b"ayy3".to_vec());
-//
-//     checkpoint
-//         .insert([b"key1"], b"key4", element2.clone())
-//         .expect("cannot insert into checkpoint");
-//
-//     db.insert([b"key1"], b"key4", element3.clone())
-//         .expect("cannot insert into GroveDB");
-//
-//     assert_eq!(
-//         checkpoint
-//             .get(&[b"key1"], b"key4")
-//             .expect("cannot get from checkpoint"),
-//         element2,
-//     );
-//
-//     assert_eq!(
-//         db.get([b"key1"], b"key4")
-//             .expect("cannot get from GroveDB"),
-//         element3
-//     );
-//
-//     checkpoint
-//         .insert([b"key1"], b"key5", element3.clone())
-//         .expect("cannot insert into checkpoint");
-//
-//     db.insert([b"key1"], b"key6", element3.clone())
-//         .expect("cannot insert into GroveDB");
-//
-//     assert!(matches!(
-//         checkpoint.get(&[b"key1"], b"key6"),
-//         Err(Error::InvalidPath(_))
-//     ));
-//
-//     assert!(matches!(
-//         db.get([b"key1"], b"key5"),
-//         Err(Error::InvalidPath(_))
-//     ));
-// }
+#[test]
+fn test_checkpoint() {
+    let db = make_grovedb();
+    let element1 = Element::Item(b"ayy".to_vec());
+
+    db.insert([TEST_LEAF], b"key1", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert([TEST_LEAF, b"key1"], b"key2", element1.clone(), None)
+        .expect("successful item insert");
+
+    let checkpoint_tempdir = TempDir::new().unwrap();
+    let checkpoint_path = checkpoint_tempdir.path().join("checkpoint");
+    db.checkpoint(&checkpoint_path, true, true)
+        .expect("successful checkpoint");
+
+    db.insert([TEST_LEAF, b"key1"], b"key3", element1.clone(), None)
+        .expect("successful item insert");
+
+    let checkpoint = GroveDbReader::open(&checkpoint_path).expect("successful checkpoint open");
+    assert_eq!(
+        checkpoint
+            .get([TEST_LEAF, b"key1"], b"key2")
+            .expect("successful get from checkpoint"),
+        element1
+    );
+    assert!(matches!(
+        checkpoint.get([TEST_LEAF, b"key1"], b"key3"),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    assert_ne!(
+        checkpoint
+            .verify()
+            .expect("successful checkpoint verification"),
+        db.root_hash(None).expect("successful root hash")
+    );
+
+    assert!(db.checkpoint(&checkpoint_path, false, false).is_err());
+}
 
 #[test]
 fn test_insert_if_not_exists() {
@@ -850,6 +863,57 @@ fn transaction_should_be_aborted_when_rollback_is_called() {
     assert!(matches!(result, Err(Error::PathKeyNotFound(_))));
 }
 
+#[test]
+fn subtrees_should_be_rolled_back_with_transaction() {
+    let db = make_grovedb();
+    let root_hash = db.root_hash(None).unwrap();
+    let transaction = db.start_transaction();
+
+    db.insert(
+        [TEST_LEAF],
+        b"tree",
+        Element::empty_tree(),
+        Some(&transaction),
+    )
+    .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"tree"],
+        b"key",
+        Element::Item(b"ayy".to_vec()),
+        Some(&transaction),
+    )
+    .expect("successful item insert");
+    assert!(db
+        .is_subtree([TEST_LEAF, b"tree"], Some(&transaction))
+        .unwrap());
+
+    db.rollback_transaction(&transaction).unwrap();
+
+    assert!(!db
+        .is_subtree([TEST_LEAF, b"tree"], Some(&transaction))
+        .unwrap());
+    assert_eq!(
+        db.find_subtrees(vec![TEST_LEAF], Some(&transaction))
+            .expect("cannot get subtrees"),
+        vec![vec![TEST_LEAF]]
+    );
+    assert_eq!(db.root_hash(Some(&transaction)).unwrap(), root_hash);
+
+    // Subtree can be created again in the same transaction
+    db.insert(
+        [TEST_LEAF],
+        b"tree",
+        Element::empty_tree(),
+        Some(&transaction),
+    )
+    .expect("successful subtree insert");
+    db.commit_transaction(transaction).unwrap();
+    assert!(db.is_subtree([TEST_LEAF, b"tree"], None).unwrap());
+    assert!(db
+        .is_empty_tree([TEST_LEAF, b"tree"], None)
+        .expect("successful emptiness check"));
+}
+
 #[test]
 fn transaction_should_be_aborted() {
     let db = make_grovedb();
@@ -976,6 +1040,94 @@ fn test_find_subtrees() {
     );
 }
 
+#[test]
+fn test_find_subtrees_after_removals() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"key1", Element::empty_tree(), None)
+        .expect("successful subtree 1 insert");
+    db.insert([TEST_LEAF, b"key1"], b"key2", Element::empty_tree(), None)
+        .expect("successful subtree 2 insert");
+    db.insert([TEST_LEAF], b"key3", Element::empty_tree(), None)
+        .expect("successful subtree 3 insert");
+
+    db.delete([TEST_LEAF], b"key1", None)
+        .expect("successful subtree delete");
+    db.insert([TEST_LEAF], b"key3", Element::Item(b"ayy".to_vec()), None)
+        .expect("successful subtree overwrite");
+    assert_eq!(
+        db.find_subtrees(vec![TEST_LEAF], None)
+            .expect("cannot get subtrees"),
+        vec![vec![TEST_LEAF]]
+    );
+
+    // A subtree recreated at the same key doesn't inherit old children
+    db.insert([TEST_LEAF], b"key1", Element::empty_tree(), None)
+        .expect("successful subtree 1 insert");
+    assert_eq!(
+        db.find_subtrees(vec![TEST_LEAF], None)
+            .expect("cannot get subtrees"),
+        vec![vec![TEST_LEAF], vec![TEST_LEAF, b"key1"]]
+    );
+}
+
+#[test]
+fn test_child_subtrees_backfill() {
+    let tmp_dir = TempDir::new().unwrap();
+    {
+        let db = GroveDb::open(tmp_dir.path()).unwrap();
+        db.insert([], TEST_LEAF, Element::empty_tree(), None)
+            .expect("successful root tree leaf insert");
+        db.insert([TEST_LEAF], b"key1", Element::empty_tree(), None)
+            .expect("successful subtree 1 insert");
+        db.insert([TEST_LEAF, b"key1"], b"key2", Element::empty_tree(), None)
+            .expect("successful subtree 2 insert");
+
+        // Pretend the data was written by format version 1 keeping child
+        // subtrees in a single record
+        for path in [vec![], vec![TEST_LEAF], vec![TEST_LEAF, b"key1"]] {
+            db.clear_child_subtrees(path.iter().copied(), None)
+                .expect("successful child subtrees clear");
+        }
+        db.db
+            .get_storage_context([TEST_LEAF])
+            .put_root(
+                b"child_subtrees",
+                &bincode::serialize(&vec![b"key1".to_vec()]).unwrap(),
+            )
+            .expect("successful legacy record write");
+        let version = GroveVersion {
+            format_version: 1,
+            features: Default::default(),
+        };
+        db.db
+            .get_storage_context(std::iter::empty())
+            .put_meta(b"grove_version", &bincode::serialize(&version).unwrap())
+            .expect("successful version write");
+        assert_eq!(
+            db.find_subtrees(vec![TEST_LEAF], None)
+                .expect("cannot get subtrees"),
+            vec![vec![TEST_LEAF]]
+        );
+    }
+
+    let db = GroveDb::open(tmp_dir.path()).unwrap();
+    assert_eq!(
+        db.find_subtrees(vec![TEST_LEAF], None)
+            .expect("cannot get subtrees"),
+        vec![
+            vec![TEST_LEAF],
+            vec![TEST_LEAF, b"key1"],
+            vec![TEST_LEAF, b"key1", b"key2"]
+        ]
+    );
+    assert!(db
+        .db
+        .get_storage_context([TEST_LEAF])
+        .get_root(b"child_subtrees")
+        .expect("successful roots read")
+        .is_none());
+}
+
 #[test]
 fn test_get_subtree() {
     let db = make_grovedb();
@@ -2513,3 +2665,5451 @@ fn test_get_non_existing_root_leaf() {
     let db = make_grovedb();
     assert!(matches!(db.get([], b"ayy", None), Err(_)));
 }
+
+#[test]
+fn test_path_query_serde_roundtrip() {
+    let db = make_grovedb();
+    populate_tree_for_non_unique_range_subquery(&db);
+
+    let mut subquery = Query::new();
+    subquery.insert_all();
+    let mut query = Query::new();
+    query.insert_range_after_to_inclusive(
+        1988_u32.to_be_bytes().to_vec()..=1990_u32.to_be_bytes().to_vec(),
+    );
+    query.insert_key(1995_u32.to_be_bytes().to_vec());
+    query.set_subquery_key(b"\0".to_vec());
+    query.set_subquery(subquery);
+    let path_query = PathQuery::new(
+        vec![TEST_LEAF.to_vec()],
+        SizedQuery::new(query, Some(10), Some(2)),
+    );
+
+    let serialized = bincode::serialize(&path_query).expect("should serialize path query");
+    let deserialized: PathQuery =
+        bincode::deserialize(&serialized).expect("should deserialize path query");
+    assert_eq!(
+        bincode::serialize(&deserialized).expect("should serialize path query"),
+        serialized
+    );
+
+    let (elements, skipped) = db
+        .get_path_query(&path_query, None)
+        .expect("expected successful get_path_query");
+    let (elements_deserialized, skipped_deserialized) = db
+        .get_path_query(&deserialized, None)
+        .expect("expected successful get_path_query");
+    assert_eq!(elements, elements_deserialized);
+    assert_eq!(skipped, skipped_deserialized);
+}
+
+#[test]
+fn test_query_serde_roundtrip_with_overlapping_ranges() {
+    let mut merged = Query::new();
+    merged.insert_range(b"a".to_vec()..b"f".to_vec());
+    let merged = bincode::serialize(&merged).expect("should serialize query");
+
+    let mut query = Query::new();
+    query.insert_range(b"a".to_vec()..b"d".to_vec());
+    query.insert_range(b"c".to_vec()..b"f".to_vec());
+    query.insert_key(b"e".to_vec());
+    let serialized = bincode::serialize(&query).expect("should serialize query");
+    assert_eq!(serialized, merged);
+    let deserialized: Query = bincode::deserialize(&serialized).expect("should deserialize query");
+    assert_eq!(
+        bincode::serialize(&deserialized).expect("should serialize query"),
+        merged
+    );
+
+    // Overlapping items, which a query never holds, are merged on
+    // deserialization instead of dropped
+    let first = QueryItem::Range(b"a".to_vec()..b"d".to_vec());
+    let second = QueryItem::Range(b"c".to_vec()..b"f".to_vec());
+    let mut overlapping = 2u64.to_le_bytes().to_vec();
+    overlapping.extend(bincode::serialize(&first).expect("should serialize query item"));
+    overlapping.extend(bincode::serialize(&second).expect("should serialize query item"));
+    overlapping.extend(&bincode::serialize(&Query::new()).expect("should serialize query")[8..]);
+    let deserialized: Query = bincode::deserialize(&overlapping).expect("should deserialize query");
+    assert_eq!(
+        bincode::serialize(&deserialized).expect("should serialize query"),
+        merged
+    );
+}
+
+/// Index delegate maintaining `ANOTHER_TEST_LEAF` as an index of
+/// `TEST_LEAF` items by their values
+struct ValueIndexDelegate;
+
+impl IndexDelegate for ValueIndexDelegate {
+    fn on_element_change(
+        &self,
+        db: &GroveDb,
+        path: &[&[u8]],
+        key: &[u8],
+        old_element: Option<&Element>,
+        new_element: Option<&Element>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        if path != [TEST_LEAF] {
+            return Ok(());
+        }
+        if let Some(Element::Item(old_value)) = old_element {
+            db.delete([ANOTHER_TEST_LEAF], old_value, transaction)?;
+        }
+        if let Some(Element::Item(new_value)) = new_element {
+            db.insert(
+                [ANOTHER_TEST_LEAF],
+                new_value,
+                Element::Reference(vec![TEST_LEAF.to_vec(), key.to_vec()]),
+                transaction,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_index_delegate_maintains_index_in_transaction() {
+    let mut db = make_grovedb();
+    db.register_index_delegate(Box::new(ValueIndexDelegate));
+
+    let transaction = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        Some(&transaction),
+    )
+    .expect("successful insert");
+    assert_eq!(
+        db.get([ANOTHER_TEST_LEAF], b"value1", Some(&transaction))
+            .expect("index entry should exist"),
+        Element::Item(b"value1".to_vec())
+    );
+    assert!(matches!(
+        db.get([ANOTHER_TEST_LEAF], b"value1", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    db.commit_transaction(transaction)
+        .expect("cannot commit transaction");
+
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value2".to_vec()),
+        None,
+    )
+    .expect("successful update");
+    assert!(matches!(
+        db.get([ANOTHER_TEST_LEAF], b"value1", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    assert_eq!(
+        db.get([ANOTHER_TEST_LEAF], b"value2", None)
+            .expect("index entry should exist"),
+        Element::Item(b"value2".to_vec())
+    );
+
+    db.delete([TEST_LEAF], b"key1", None)
+        .expect("successful delete");
+    assert!(matches!(
+        db.get([ANOTHER_TEST_LEAF], b"value2", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+}
+
+/// Index delegate rejecting every change after it is written
+struct FailingIndexDelegate;
+
+impl IndexDelegate for FailingIndexDelegate {
+    fn on_element_change(
+        &self,
+        _db: &GroveDb,
+        _path: &[&[u8]],
+        _key: &[u8],
+        _old_element: Option<&Element>,
+        _new_element: Option<&Element>,
+        _transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        Err(Error::InvalidQuery("change is rejected by the index"))
+    }
+}
+
+#[test]
+fn test_index_delegate_failure_without_transaction() {
+    let mut db = make_grovedb();
+    db.insert([TEST_LEAF], b"key1", Element::Item(b"ayy".to_vec()), None)
+        .expect("successful insert");
+    db.register_index_delegate(Box::new(FailingIndexDelegate));
+    let root_hash = db.root_hash(None).expect("successful root hash");
+
+    assert!(matches!(
+        db.insert([TEST_LEAF], b"key2", Element::Item(b"lmao".to_vec()), None),
+        Err(Error::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        db.delete([TEST_LEAF], b"key1", None),
+        Err(Error::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        db.get([TEST_LEAF], b"key2", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    assert_eq!(
+        db.get([TEST_LEAF], b"key1", None).expect("successful get"),
+        Element::Item(b"ayy".to_vec())
+    );
+    assert_eq!(
+        db.root_hash(None).expect("successful root hash"),
+        root_hash
+    );
+}
+
+#[test]
+fn test_referential_integrity_enforce() {
+    let mut db = make_grovedb();
+    db.set_referential_integrity(ReferentialIntegrity::Enforce);
+
+    let reference = Element::Reference(vec![TEST_LEAF.to_vec(), b"key1".to_vec()]);
+    assert!(matches!(
+        db.insert([ANOTHER_TEST_LEAF], b"ref", reference.clone(), None),
+        Err(Error::ReferentialIntegrity(_))
+    ));
+
+    db.insert([TEST_LEAF], b"key1", Element::Item(b"ayy".to_vec()), None)
+        .expect("successful item insert");
+    db.insert([ANOTHER_TEST_LEAF], b"ref", reference, None)
+        .expect("successful reference insert");
+    assert!(matches!(
+        db.delete([TEST_LEAF], b"key1", None),
+        Err(Error::ReferentialIntegrity(_))
+    ));
+
+    // Once the reference is overwritten the target is free to go
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"ref",
+        Element::Item(b"not a reference".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.delete([TEST_LEAF], b"key1", None)
+        .expect("successful delete");
+
+    // References to elements of nested subtrees prevent subtree deletion,
+    // unless the references are deleted with the subtree as well
+    db.insert([TEST_LEAF], b"tree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert([TEST_LEAF, b"tree"], b"nested", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"tree", b"nested"],
+        b"key2",
+        Element::Item(b"ayy".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let nested_reference = Element::Reference(vec![
+        TEST_LEAF.to_vec(),
+        b"tree".to_vec(),
+        b"nested".to_vec(),
+        b"key2".to_vec(),
+    ]);
+    db.insert([TEST_LEAF, b"tree"], b"ref", nested_reference.clone(), None)
+        .expect("successful reference insert");
+    db.insert([ANOTHER_TEST_LEAF], b"ref", nested_reference, None)
+        .expect("successful reference insert");
+    assert!(matches!(
+        db.delete([TEST_LEAF], b"tree", None),
+        Err(Error::ReferentialIntegrity(_))
+    ));
+    db.delete([ANOTHER_TEST_LEAF], b"ref", None)
+        .expect("successful delete");
+    db.delete([TEST_LEAF], b"tree", None)
+        .expect("successful subtree delete");
+
+    // Back references are kept apart from auxiliary data
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"ref",
+        Element::Reference(vec![TEST_LEAF.to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+    assert_eq!(db.get_aux(TEST_LEAF, None).expect("successful aux get"), None);
+}
+
+#[test]
+fn test_referential_integrity_cascade() {
+    let mut db = make_grovedb();
+    db.set_referential_integrity(ReferentialIntegrity::Cascade);
+
+    db.insert([TEST_LEAF], b"key1", Element::Item(b"ayy".to_vec()), None)
+        .expect("successful item insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"ref1",
+        Element::Reference(vec![TEST_LEAF.to_vec(), b"key1".to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"ref2",
+        Element::Reference(vec![ANOTHER_TEST_LEAF.to_vec(), b"ref1".to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+
+    db.delete([TEST_LEAF], b"key1", None)
+        .expect("successful delete");
+    assert!(matches!(
+        db.get([ANOTHER_TEST_LEAF], b"ref1", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    assert!(matches!(
+        db.get([ANOTHER_TEST_LEAF], b"ref2", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+}
+
+#[test]
+fn test_references_to() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"key1", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert([TEST_LEAF], b"key2", Element::Item(b"ayy".to_vec()), None)
+        .expect("successful item insert");
+    let target = vec![TEST_LEAF.to_vec(), b"key2".to_vec()];
+
+    // One of the references is inside of a subtree to be deleted later
+    db.insert(
+        [TEST_LEAF, b"key1"],
+        b"ref1",
+        Element::Reference(target.clone()),
+        None,
+    )
+    .expect("successful reference insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"ref2",
+        Element::Reference(target.clone()),
+        None,
+    )
+    .expect("successful reference insert");
+    assert_eq!(
+        db.references_to([TEST_LEAF], b"key2", None)
+            .expect("successful references lookup"),
+        vec![
+            vec![TEST_LEAF.to_vec(), b"key1".to_vec(), b"ref1".to_vec()],
+            vec![ANOTHER_TEST_LEAF.to_vec(), b"ref2".to_vec()],
+        ]
+    );
+
+    let transaction = db.start_transaction();
+    db.delete([ANOTHER_TEST_LEAF], b"ref2", Some(&transaction))
+        .expect("successful delete");
+    assert_eq!(
+        db.references_to([TEST_LEAF], b"key2", Some(&transaction))
+            .expect("successful references lookup"),
+        vec![vec![TEST_LEAF.to_vec(), b"key1".to_vec(), b"ref1".to_vec()]]
+    );
+    assert_eq!(
+        db.references_to([TEST_LEAF], b"key2", None)
+            .expect("successful references lookup")
+            .len(),
+        2
+    );
+    db.commit_transaction(transaction)
+        .expect("cannot commit transaction");
+
+    // Deletion of a subtree makes references inside of it gone as well
+    db.delete([TEST_LEAF], b"key1", None)
+        .expect("successful subtree delete");
+    assert!(db
+        .references_to([TEST_LEAF], b"key2", None)
+        .expect("successful references lookup")
+        .is_empty());
+}
+
+#[test]
+fn test_subtree_balance_info_and_rebuild() {
+    let db = make_grovedb();
+    for i in 0u32..100 {
+        db.insert(
+            [TEST_LEAF],
+            &i.to_be_bytes(),
+            Element::Item(i.to_le_bytes().to_vec()),
+            None,
+        )
+        .expect("successful item insert");
+    }
+    let info = db
+        .subtree_balance_info([TEST_LEAF], None)
+        .expect("successful balance info");
+    assert_eq!(info.node_count, 100);
+    assert_eq!(info.optimal_height, 7);
+    assert_eq!(info.unbalanced_nodes, 0);
+
+    db.rebuild_subtree([TEST_LEAF], None)
+        .expect("successful rebuild");
+    let info = db
+        .subtree_balance_info([TEST_LEAF], None)
+        .expect("successful balance info");
+    assert_eq!(info.height, info.optimal_height);
+    for i in 0u32..100 {
+        assert_eq!(
+            db.get([TEST_LEAF], &i.to_be_bytes(), None)
+                .expect("successful get"),
+            Element::Item(i.to_le_bytes().to_vec())
+        );
+    }
+
+    assert!(matches!(
+        db.subtree_balance_info([TEST_LEAF, b"missing"], None),
+        Err(Error::PathNotFound(_))
+    ));
+}
+
+#[test]
+fn test_prove_and_execute_proof() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"innertree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"innertree"],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [TEST_LEAF, b"innertree"],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+
+    let mut query = Query::new();
+    query.insert_key(b"key1".to_vec());
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec(), b"innertree".to_vec()], query);
+    let mut another_query = Query::new();
+    another_query.insert_all();
+    let another_path_query =
+        PathQuery::new_unsized(vec![ANOTHER_TEST_LEAF.to_vec()], another_query);
+
+    let proof = db
+        .prove(&[path_query.clone(), another_path_query.clone()], None)
+        .expect("successful proof generation");
+    let (root_hash, results) = GroveDb::execute_proof(&proof).expect("successful proof execution");
+    assert_eq!(
+        Some(root_hash),
+        db.root_hash(None).expect("successful root hash")
+    );
+    let element_bytes = results[&path_query.path]
+        .get(b"key1")
+        .expect("key should be proved")
+        .expect("key should exist");
+    assert_eq!(
+        bincode::deserialize::<Element>(element_bytes).expect("successful deserialization"),
+        Element::Item(b"value1".to_vec())
+    );
+    assert!(results.contains_key(&another_path_query.path));
+
+    let mut subquery = Query::new();
+    subquery.insert_all();
+    let mut query = Query::new();
+    query.insert_all();
+    query.set_subquery(subquery);
+    assert!(matches!(
+        db.prove(
+            &[PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query)],
+            None
+        ),
+        Err(Error::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn test_prove_queries_parallel() {
+    let db = make_grovedb();
+    for i in 0u8..10 {
+        db.insert([TEST_LEAF], &[i], Element::Item(vec![i]), None)
+            .expect("successful item insert");
+    }
+    let queries: Vec<PathQuery> = (0u8..10)
+        .map(|i| {
+            let mut query = Query::new();
+            query.insert_key(vec![i]);
+            PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query)
+        })
+        .collect();
+
+    let proofs = db
+        .prove_queries_parallel(queries)
+        .expect("successful parallel proof generation");
+    assert_eq!(proofs.len(), 10);
+    let expected_root_hash = db.root_hash(None).expect("successful root hash");
+    for (i, proof) in proofs.iter().enumerate() {
+        let (root_hash, results) =
+            GroveDb::execute_proof(proof).expect("successful proof execution");
+        assert_eq!(Some(root_hash), expected_root_hash);
+        let element_bytes = results[&vec![TEST_LEAF.to_vec()]]
+            .get(&[i as u8])
+            .expect("key should be proved")
+            .expect("key should exist");
+        assert_eq!(
+            bincode::deserialize::<Element>(element_bytes).expect("successful deserialization"),
+            Element::Item(vec![i as u8])
+        );
+    }
+}
+
+#[test]
+fn test_prove_subqueries() {
+    let db = make_grovedb();
+    for subtree in [b"a", b"b"] {
+        db.insert([TEST_LEAF], subtree, Element::empty_tree(), None)
+            .expect("successful subtree insert");
+        db.insert(
+            [TEST_LEAF, subtree],
+            b"key",
+            Element::Item(subtree.to_vec()),
+            None,
+        )
+        .expect("successful item insert");
+    }
+    let mut subquery = Query::new();
+    subquery.insert_all();
+    let mut query = Query::new();
+    query.insert_all();
+    query.set_subquery(subquery);
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query.clone());
+
+    let proofs = db
+        .prove_queries_parallel(vec![path_query])
+        .expect("successful parallel proof generation");
+    let (root_hash, results) =
+        GroveDb::execute_proof(&proofs[0]).expect("successful proof execution");
+    assert_eq!(
+        Some(root_hash),
+        db.root_hash(None).expect("successful root hash")
+    );
+    for subtree in [b"a", b"b"] {
+        let element_bytes = results[&vec![TEST_LEAF.to_vec(), subtree.to_vec()]]
+            .get(b"key")
+            .expect("key should be proved")
+            .expect("key should exist");
+        assert_eq!(
+            bincode::deserialize::<Element>(element_bytes).expect("successful deserialization"),
+            Element::Item(subtree.to_vec())
+        );
+    }
+
+    let limited = PathQuery::new(
+        vec![TEST_LEAF.to_vec()],
+        SizedQuery::new(query, Some(1), None),
+    );
+    assert!(matches!(
+        db.prove(&[limited], None),
+        Err(Error::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn test_reader_over_closed_database() {
+    let tmp_dir = TempDir::new().unwrap();
+    let root_hash = {
+        let mut db = GroveDb::open(tmp_dir.path()).unwrap();
+        add_test_leafs(&mut db);
+        db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+            .expect("successful item insert");
+        db.put_aux(b"aux_key", b"aux_value", None)
+            .expect("successful aux insert");
+        db.flush().expect("successful flush");
+        db.root_hash(None).expect("successful root hash")
+    };
+
+    let reader = GroveDbReader::open(tmp_dir.path()).expect("successful reader open");
+    assert_eq!(reader.root_hash().expect("successful root hash"), root_hash);
+    assert_eq!(
+        reader.get([TEST_LEAF], b"key").expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+    assert_eq!(
+        reader.get_aux(b"aux_key").expect("successful aux get"),
+        Some(b"aux_value".to_vec())
+    );
+    assert!(reader
+        .is_empty_tree([ANOTHER_TEST_LEAF])
+        .expect("successful emptiness check"));
+
+    let mut query = Query::new();
+    query.insert_all();
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    let (items, _) = reader
+        .get_path_query(&path_query)
+        .expect("successful path query");
+    assert_eq!(items, vec![b"value".to_vec()]);
+    let proof = reader
+        .prove(&[path_query])
+        .expect("successful proof generation");
+    let (proof_root_hash, _) = GroveDb::execute_proof(&proof).expect("successful proof execution");
+    assert_eq!(Some(proof_root_hash), root_hash);
+
+    let missing_dir = TempDir::new().unwrap();
+    assert!(GroveDbReader::open(missing_dir.path().join("missing")).is_err());
+}
+
+#[test]
+fn test_reader_over_open_database() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut db = GroveDb::open(tmp_dir.path()).unwrap();
+    add_test_leafs(&mut db);
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+        .expect("successful item insert");
+    db.flush().expect("successful flush");
+
+    // The reader opens the database read-only, so it doesn't conflict with
+    // the writer holding it
+    let reader = GroveDbReader::open(tmp_dir.path()).expect("successful reader open");
+    assert_eq!(
+        reader.root_hash().expect("successful root hash"),
+        db.root_hash(None).expect("successful root hash")
+    );
+    assert_eq!(
+        reader.get([TEST_LEAF], b"key").expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+}
+
+#[test]
+fn test_element_type_and_is_subtree() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"innertree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert([TEST_LEAF], b"item", Element::Item(b"value".to_vec()), None)
+        .expect("successful item insert");
+    db.insert(
+        [TEST_LEAF],
+        b"reference",
+        Element::Reference(vec![TEST_LEAF.to_vec(), b"item".to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+
+    assert_eq!(
+        db.element_type([], TEST_LEAF, None)
+            .expect("successful element type"),
+        ElementType::Tree
+    );
+    assert_eq!(
+        db.element_type([TEST_LEAF], b"innertree", None)
+            .expect("successful element type"),
+        ElementType::Tree
+    );
+    assert_eq!(
+        db.element_type([TEST_LEAF], b"item", None)
+            .expect("successful element type"),
+        ElementType::Item
+    );
+    assert_eq!(
+        db.element_type([TEST_LEAF], b"reference", None)
+            .expect("successful element type"),
+        ElementType::Reference
+    );
+    assert!(matches!(
+        db.element_type([TEST_LEAF], b"missing", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+
+    assert!(db.is_subtree([], None).expect("successful subtree check"));
+    assert!(db
+        .is_subtree([TEST_LEAF], None)
+        .expect("successful subtree check"));
+    assert!(db
+        .is_subtree([TEST_LEAF, b"innertree"], None)
+        .expect("successful subtree check"));
+    assert!(!db
+        .is_subtree([TEST_LEAF, b"item"], None)
+        .expect("successful subtree check"));
+    assert!(!db
+        .is_subtree([TEST_LEAF, b"missing", b"deeper"], None)
+        .expect("successful subtree check"));
+    assert!(!db
+        .is_subtree([b"missing_leaf".as_ref()], None)
+        .expect("successful subtree check"));
+}
+
+#[test]
+fn test_list_recursive() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"a", Element::Item(b"a".to_vec()), None)
+        .expect("successful item insert");
+    db.insert([TEST_LEAF], b"b", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert([TEST_LEAF, b"b"], b"c", Element::Item(b"c".to_vec()), None)
+        .expect("successful item insert");
+    db.insert([TEST_LEAF, b"b"], b"d", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"b", b"d"],
+        b"e",
+        Element::Item(b"e".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+
+    let keys = |listed: &[ListedElement]| -> Vec<Vec<u8>> {
+        listed.iter().map(|l| l.key.clone()).collect()
+    };
+
+    let (listed, truncated) = db
+        .list_recursive([TEST_LEAF], 10, 100, None)
+        .expect("successful listing");
+    assert!(!truncated);
+    assert_eq!(keys(&listed), vec![b"a".to_vec(), b"b".to_vec()]);
+    assert_eq!(listed[0].element, Element::Item(b"a".to_vec()));
+    assert!(listed[0].children.is_empty());
+    assert_eq!(
+        keys(&listed[1].children),
+        vec![b"c".to_vec(), b"d".to_vec()]
+    );
+    assert_eq!(keys(&listed[1].children[1].children), vec![b"e".to_vec()]);
+
+    let (listed, truncated) = db
+        .list_recursive([TEST_LEAF], 2, 100, None)
+        .expect("successful listing");
+    assert!(truncated);
+    assert_eq!(
+        keys(&listed[1].children),
+        vec![b"c".to_vec(), b"d".to_vec()]
+    );
+    assert!(listed[1].children[1].children.is_empty());
+
+    let (listed, truncated) = db
+        .list_recursive([TEST_LEAF], 10, 3, None)
+        .expect("successful listing");
+    assert!(truncated);
+    assert_eq!(keys(&listed[1].children), vec![b"c".to_vec()]);
+
+    let (listed, truncated) = db
+        .list_recursive([], 1, 100, None)
+        .expect("successful listing");
+    assert!(truncated);
+    assert_eq!(
+        keys(&listed),
+        vec![TEST_LEAF.to_vec(), ANOTHER_TEST_LEAF.to_vec()]
+    );
+
+    assert!(matches!(
+        db.list_recursive([TEST_LEAF, b"missing"], 10, 100, None),
+        Err(Error::PathNotFound(_))
+    ));
+}
+
+#[test]
+fn test_audit_stream() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"innertree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"innertree"],
+        b"key",
+        Element::Item(b"ayy".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key",
+        Element::Item(b"ayy".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+
+    let mut stream = Vec::new();
+    let count = db
+        .audit_stream(&mut stream, None)
+        .expect("successful audit stream");
+    let stream = String::from_utf8(stream).expect("audit stream is text");
+    let lines: Vec<&str> = stream.lines().collect();
+    // Two root leaves, a subtree in the first one and an item in each subtree
+    assert_eq!(count, 5);
+    assert_eq!(lines.len(), 5);
+    let paths: Vec<&str> = lines
+        .iter()
+        .map(|line| line.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            "",
+            "",
+            hex::encode(TEST_LEAF).as_str(),
+            format!("{}/{}", hex::encode(TEST_LEAF), hex::encode(b"innertree")).as_str(),
+            hex::encode(ANOTHER_TEST_LEAF).as_str(),
+        ]
+    );
+
+    // Root tree root node hash is GroveDB root hash
+    let root_hash = hex::encode(db.root_hash(None).unwrap().unwrap());
+    assert!(lines[..2]
+        .iter()
+        .any(|line| line.split(' ').nth(3) == Some(root_hash.as_str())));
+
+    let mut same_stream = Vec::new();
+    db.audit_stream(&mut same_stream, None)
+        .expect("successful audit stream");
+    assert_eq!(same_stream, stream.into_bytes());
+}
+
+#[test]
+fn test_quarantine_mode() {
+    let mut db = make_grovedb();
+    db.insert([TEST_LEAF], b"key", Element::Item(b"ayy".to_vec()), None)
+        .expect("successful item insert");
+    // Put a value which is not an element bypassing GroveDB
+    {
+        let storage = db.db.db.get_storage_context([TEST_LEAF]);
+        let mut merk = Merk::open(storage).expect("cannot open merk");
+        merk.apply::<_, Vec<u8>>(&[(b"corrupted", merk::Op::Put(b"garbage".to_vec()))], &[])
+            .expect("cannot apply batch");
+    }
+
+    // Nothing is recorded unless quarantine mode is enabled
+    assert!(matches!(
+        db.get([TEST_LEAF], b"corrupted", None),
+        Err(Error::CorruptedData(_))
+    ));
+    assert!(db
+        .quarantined()
+        .expect("successful quarantine read")
+        .is_empty());
+
+    db.set_quarantine_mode(true);
+    assert!(matches!(
+        db.get([TEST_LEAF], b"corrupted", None),
+        Err(Error::CorruptedData(_))
+    ));
+    assert!(matches!(
+        db.get([TEST_LEAF], b"corrupted", None),
+        Err(Error::CorruptedData(_))
+    ));
+    assert_eq!(
+        db.quarantined().expect("successful quarantine read"),
+        vec![(
+            RocksDbStorage::build_prefix([TEST_LEAF]),
+            b"corrupted".to_vec()
+        )]
+    );
+
+    // Unaffected elements are still served
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(b"ayy".to_vec())
+    );
+    assert!(matches!(
+        db.get([TEST_LEAF], b"missing", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    assert_eq!(
+        db.quarantined().expect("successful quarantine read").len(),
+        1
+    );
+
+    // Iteration records the element, a query records the queried subtree
+    assert!(matches!(
+        db.iter([TEST_LEAF], false, None)
+            .expect("successful iteration")
+            .collect::<Result<Vec<_>, Error>>(),
+        Err(Error::CorruptedData(_))
+    ));
+    let mut query = Query::new();
+    query.insert_all();
+    assert!(matches!(
+        db.get_path_query(&PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query), None),
+        Err(Error::CorruptedData(_))
+    ));
+    assert_eq!(
+        db.quarantined().expect("successful quarantine read"),
+        vec![
+            (
+                RocksDbStorage::build_prefix([TEST_LEAF]),
+                b"corrupted".to_vec()
+            ),
+            (RocksDbStorage::build_prefix([TEST_LEAF]), Vec::new()),
+        ]
+    );
+}
+
+#[test]
+fn test_apply_batch() {
+    let db = make_grovedb();
+    db.insert(
+        [TEST_LEAF],
+        b"to_delete",
+        Element::Item(b"ayy".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+
+    let mut ops = vec![
+        GroveDbOp::Insert {
+            path: vec![TEST_LEAF.to_vec()],
+            key: b"innertree".to_vec(),
+            element: Element::empty_tree(),
+        },
+        GroveDbOp::Delete {
+            path: vec![TEST_LEAF.to_vec()],
+            key: b"to_delete".to_vec(),
+        },
+    ];
+    for i in 0u8..50 {
+        ops.push(GroveDbOp::Insert {
+            path: vec![TEST_LEAF.to_vec(), b"innertree".to_vec()],
+            key: vec![i],
+            element: Element::Item(vec![i; 100]),
+        });
+    }
+    db.apply_batch(ops, None).expect("successful batch");
+
+    for i in 0u8..50 {
+        assert_eq!(
+            db.get([TEST_LEAF, b"innertree"], &[i], None)
+                .expect("successful get"),
+            Element::Item(vec![i; 100])
+        );
+    }
+    assert!(matches!(
+        db.get([TEST_LEAF], b"to_delete", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+
+    // A failing operation leaves nothing applied
+    let ops = vec![
+        GroveDbOp::Insert {
+            path: vec![TEST_LEAF.to_vec()],
+            key: b"applied".to_vec(),
+            element: Element::Item(vec![0; 400]),
+        },
+        GroveDbOp::Insert {
+            path: vec![TEST_LEAF.to_vec(), b"missing".to_vec()],
+            key: b"key".to_vec(),
+            element: Element::Item(vec![0; 400]),
+        },
+    ];
+    assert!(db.apply_batch(ops, None).is_err());
+    assert!(matches!(
+        db.get([TEST_LEAF], b"applied", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+
+    // Within a transaction the batch is visible only through it
+    let tx = db.start_transaction();
+    db.apply_batch(
+        vec![GroveDbOp::Insert {
+            path: vec![TEST_LEAF.to_vec()],
+            key: b"applied".to_vec(),
+            element: Element::Item(b"ayy".to_vec()),
+        }],
+        Some(&tx),
+    )
+    .expect("successful batch");
+    assert!(db.get([TEST_LEAF], b"applied", None).is_err());
+    db.commit_transaction(tx).expect("successful commit");
+    assert_eq!(
+        db.get([TEST_LEAF], b"applied", None).expect("successful get"),
+        Element::Item(b"ayy".to_vec())
+    );
+}
+
+#[test]
+fn test_backup_and_restore() {
+    let db = make_grovedb();
+    let backup_dir = TempDir::new().unwrap();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let first_root_hash = db.root_hash(None).expect("successful root hash");
+
+    let mut events = Vec::new();
+    let first_backup = db
+        .backup_to(backup_dir.path(), 0, |p| events.push(p))
+        .expect("successful backup");
+    assert_eq!(
+        events,
+        vec![
+            BackupProgress::CreatingBackup,
+            BackupProgress::BackupCreated(first_backup)
+        ]
+    );
+
+    db.insert(
+        [TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let second_root_hash = db.root_hash(None).expect("successful root hash");
+    let second_backup = db
+        .backup_to(backup_dir.path(), 0, |_| {})
+        .expect("successful backup");
+    assert_eq!(
+        GroveDb::backup_ids(backup_dir.path()).expect("successful backups listing"),
+        vec![first_backup, second_backup]
+    );
+
+    let restore_dir = TempDir::new().unwrap();
+    let restored = GroveDb::restore_from(
+        backup_dir.path(),
+        restore_dir.path().join("latest"),
+        None,
+        |_| {},
+    )
+    .expect("successful restore");
+    assert_eq!(
+        restored.root_hash(None).expect("successful root hash"),
+        second_root_hash
+    );
+
+    let restored = GroveDb::restore_from(
+        backup_dir.path(),
+        restore_dir.path().join("first"),
+        Some(first_backup),
+        |_| {},
+    )
+    .expect("successful restore");
+    assert_eq!(
+        restored.root_hash(None).expect("successful root hash"),
+        first_root_hash
+    );
+    assert!(matches!(
+        restored.get([TEST_LEAF], b"key2", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+
+    let mut events = Vec::new();
+    db.backup_to(backup_dir.path(), 1, |p| events.push(p))
+        .expect("successful backup");
+    assert_eq!(
+        events.last(),
+        Some(&BackupProgress::OldBackupsPurged { kept: 1 })
+    );
+    assert_eq!(
+        GroveDb::backup_ids(backup_dir.path())
+            .expect("successful backups listing")
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn test_iter_keys_only() {
+    let db = make_grovedb();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert([TEST_LEAF], b"key2", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"key2"],
+        b"key3",
+        Element::Item(b"value3".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+
+    let keys = db
+        .iter([TEST_LEAF], true, None)
+        .expect("successful keys only iteration")
+        .collect::<Result<Vec<_>, Error>>()
+        .expect("successful keys only iteration");
+    assert_eq!(
+        keys,
+        vec![(b"key1".to_vec(), None), (b"key2".to_vec(), None)]
+    );
+
+    let elements = db
+        .iter([TEST_LEAF], false, None)
+        .expect("successful iteration")
+        .collect::<Result<Vec<_>, Error>>()
+        .expect("successful iteration");
+    assert_eq!(elements.len(), 2);
+    assert_eq!(
+        elements[0],
+        (b"key1".to_vec(), Some(Element::Item(b"value1".to_vec())))
+    );
+    assert!(matches!(elements[1].1, Some(Element::Tree(_))));
+
+    assert!(matches!(
+        db.iter([TEST_LEAF, b"missing"], true, None),
+        Err(Error::PathNotFound(_))
+    ));
+}
+
+#[test]
+fn test_find_by_value_hash() {
+    let db = make_grovedb();
+    let item = Element::Item(b"shared".to_vec());
+    let hash = merk::tree::value_hash(&bincode::serialize(&item).unwrap());
+
+    db.insert([TEST_LEAF], b"key1", item.clone(), None)
+        .expect("successful item insert");
+    db.insert([ANOTHER_TEST_LEAF], b"key2", item.clone(), None)
+        .expect("successful item insert");
+    assert!(db
+        .find_by_value_hash(&hash, None)
+        .expect("successful lookup")
+        .is_empty());
+
+    db.enable_value_hash_index([TEST_LEAF], None)
+        .expect("successful index enabling");
+    db.insert([TEST_LEAF], b"innertree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.enable_value_hash_index([TEST_LEAF, b"innertree"], None)
+        .expect("successful index enabling");
+    db.insert([TEST_LEAF, b"innertree"], b"key3", item.clone(), None)
+        .expect("successful item insert");
+    assert_eq!(
+        db.find_by_value_hash(&hash, None)
+            .expect("successful lookup"),
+        vec![
+            (vec![TEST_LEAF.to_vec()], b"key1".to_vec()),
+            (
+                vec![TEST_LEAF.to_vec(), b"innertree".to_vec()],
+                b"key3".to_vec()
+            ),
+        ]
+    );
+    // The index is not stored with user auxiliary data
+    assert!(db
+        .get_aux([b"value_hash_index".as_ref(), &hash].concat(), None)
+        .expect("successful aux get")
+        .is_none());
+
+    db.insert([TEST_LEAF], b"key1", Element::Item(b"other".to_vec()), None)
+        .expect("successful item insert");
+    db.delete([TEST_LEAF], b"innertree", None)
+        .expect("successful subtree delete");
+    assert!(db
+        .find_by_value_hash(&hash, None)
+        .expect("successful lookup")
+        .is_empty());
+}
+
+#[test]
+fn test_lock_subtree() {
+    let db = make_grovedb();
+    let guard = db
+        .lock_subtree([TEST_LEAF], LockWait::FailFast)
+        .expect("successful lock");
+    assert!(matches!(
+        db.lock_subtree([TEST_LEAF, b"innertree"], LockWait::FailFast),
+        Err(Error::SubtreeLocked)
+    ));
+    assert!(matches!(
+        db.lock_subtree([], LockWait::FailFast),
+        Err(Error::SubtreeLocked)
+    ));
+    let another_guard = db
+        .lock_subtree([ANOTHER_TEST_LEAF], LockWait::FailFast)
+        .expect("successful lock of a disjoint subtree");
+
+    std::thread::scope(|s| {
+        let waiter = s.spawn(|| {
+            let _guard = db
+                .lock_subtree([TEST_LEAF, b"innertree"], LockWait::Block)
+                .expect("successful lock after release");
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        drop(guard);
+        waiter.join().expect("waiter thread finished");
+    });
+
+    drop(another_guard);
+    db.lock_subtree([], LockWait::FailFast)
+        .expect("successful lock of the root tree");
+}
+
+#[test]
+fn test_lock_subtree_concurrent_batches() {
+    let batch = |leaf: &[u8]| -> Vec<(Option<Vec<u8>>, GroveDbOp)> {
+        (0..50u8)
+            .map(|i| {
+                let op = GroveDbOp::Insert {
+                    path: vec![leaf.to_vec()],
+                    key: vec![i],
+                    element: Element::Item(vec![i; 32]),
+                };
+                (None, op)
+            })
+            .collect()
+    };
+
+    // Both batches propagate through the root tree
+    let db = make_grovedb();
+    std::thread::scope(|s| {
+        for leaf in [TEST_LEAF, ANOTHER_TEST_LEAF] {
+            let db = &db;
+            s.spawn(move || {
+                let tx = db
+                    .lock_subtree([leaf], LockWait::FailFast)
+                    .expect("successful lock")
+                    .into_transaction()
+                    .expect("successful transaction start");
+                db.apply_batch_with_op_ids(batch(leaf), Some(tx.transaction()))
+                    .expect("successful batch");
+                tx.commit().expect("successful commit");
+            });
+        }
+    });
+
+    let expected = make_grovedb();
+    for leaf in [TEST_LEAF, ANOTHER_TEST_LEAF] {
+        expected
+            .apply_batch_with_op_ids(batch(leaf), None)
+            .expect("successful batch");
+    }
+    assert_eq!(
+        db.root_hash(None).expect("successful root hash"),
+        expected.root_hash(None).expect("successful root hash")
+    );
+    assert!(matches!(
+        db.lock_subtree([], LockWait::FailFast)
+            .expect("successful lock of the root tree")
+            .into_transaction(),
+        Err(Error::InvalidPath(_))
+    ));
+}
+
+#[test]
+fn test_path_query_with_options() {
+    let db = make_grovedb();
+    for i in 0u8..10 {
+        db.insert([TEST_LEAF], &[i], Element::Item(vec![i]), None)
+            .expect("successful item insert");
+    }
+    let mut query = Query::new();
+    query.insert_range(vec![2]..vec![7]);
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+
+    let options = QueryOptions {
+        readahead_bytes: 2 * 1024 * 1024,
+        fill_cache: false,
+        ..Default::default()
+    };
+    let (scanned, _) = db
+        .get_path_query_with_options(&path_query, options, None)
+        .expect("successful path query with options");
+    assert_eq!(scanned, vec![vec![2], vec![3], vec![4], vec![5], vec![6]]);
+    assert_eq!(
+        db.get_path_query(&path_query, None)
+            .expect("successful path query"),
+        (scanned, 0)
+    );
+}
+
+#[test]
+fn test_open_with_shared_cache() {
+    let cache = rocksdb_storage::Cache::new_lru_cache(8 * 1024 * 1024).expect("successful cache");
+    let tmp_dir = TempDir::new().unwrap();
+    let db_one =
+        GroveDb::open_with_cache(tmp_dir.path().join("one"), &cache).expect("successful open");
+    let db_two =
+        GroveDb::open_with_cache(tmp_dir.path().join("two"), &cache).expect("successful open");
+
+    for db in [&db_one, &db_two] {
+        db.insert([], TEST_LEAF, Element::empty_tree(), None)
+            .expect("successful root leaf insert");
+        db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+            .expect("successful item insert");
+    }
+    assert_eq!(
+        db_one.root_hash(None).expect("successful root hash"),
+        db_two.root_hash(None).expect("successful root hash")
+    );
+    assert_eq!(
+        db_two
+            .get([TEST_LEAF], b"key", None)
+            .expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+}
+
+#[test]
+fn test_memory_usage() {
+    let db = make_grovedb();
+    let before = db.memory_usage().expect("successful memory usage report");
+    for i in 0u32..100 {
+        db.insert(
+            [TEST_LEAF],
+            &i.to_be_bytes(),
+            Element::Item(vec![0; 1024]),
+            None,
+        )
+        .expect("successful item insert");
+    }
+    let after = db.memory_usage().expect("successful memory usage report");
+    assert!(after.memtables_bytes > before.memtables_bytes);
+
+    let tx = db.start_transaction();
+    db.get([TEST_LEAF], &0u32.to_be_bytes(), Some(&tx))
+        .expect("successful get");
+    db.commit_transaction(tx).expect("successful commit");
+    assert_eq!(
+        db.memory_usage()
+            .expect("successful memory usage report")
+            .snapshots,
+        0
+    );
+}
+
+#[test]
+fn test_background_maintenance() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut db = GroveDb::open(tmp_dir.path()).unwrap();
+    add_test_leafs(&mut db);
+    let db = std::sync::Arc::new(db);
+    let handle = db.start_maintenance(MaintenancePolicy {
+        idle_period: std::time::Duration::from_millis(10),
+        compact: true,
+    });
+    let wait_for_runs = |runs: u64| {
+        for _ in 0..500 {
+            if handle.runs() >= runs {
+                return true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        false
+    };
+    assert!(wait_for_runs(1));
+
+    handle.pause();
+    let runs = handle.runs();
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+        .expect("successful item insert");
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(handle.runs(), runs);
+
+    handle.resume();
+    assert!(wait_for_runs(runs + 1));
+    handle.stop();
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+}
+
+#[test]
+fn test_open_with_rate_limit() {
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = GroveDbOpts {
+        rate_limit_bytes_per_sec: Some(16 * 1024 * 1024),
+        ..Default::default()
+    };
+    let mut db = GroveDb::open_with_opts(tmp_dir.path(), &opts).expect("successful open");
+    add_test_leafs(&mut db);
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+        .expect("successful item insert");
+    db.flush().expect("successful flush");
+    drop(db);
+
+    let db =
+        GroveDb::open_with_opts(tmp_dir.path(), &GroveDbOpts::default()).expect("successful open");
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+}
+
+#[test]
+fn test_recording_and_replay() {
+    let tmp_dir = TempDir::new().unwrap();
+    let log_path = tmp_dir.path().join("recording");
+    let run = |db: &mut GroveDb| {
+        add_test_leafs(db);
+        let rolled_back = db.start_transaction();
+        db.insert(
+            [TEST_LEAF],
+            b"key1",
+            Element::Item(b"rolled back".to_vec()),
+            Some(&rolled_back),
+        )
+        .expect("successful item insert");
+        db.rollback_transaction(&rolled_back)
+            .expect("successful rollback");
+        drop(rolled_back);
+
+        let tx = db.start_transaction();
+        db.insert([TEST_LEAF], b"key2", Element::Item(b"value".to_vec()), Some(&tx))
+            .expect("successful item insert");
+        let uncommitted = db.get([TEST_LEAF], b"key2", None).is_err();
+        db.commit_transaction(tx).expect("successful commit");
+        (
+            uncommitted,
+            db.get([TEST_LEAF], b"key1", None).is_err(),
+            db.get([TEST_LEAF], b"key2", None).expect("successful get"),
+            db.root_hash(None).expect("successful root hash"),
+        )
+    };
+
+    let mut db = GroveDb::open_with_recording(tmp_dir.path().join("db"), &log_path)
+        .expect("successful open");
+    let recorded = run(&mut db);
+    assert!(recorded.0);
+    assert!(recorded.1);
+    assert_eq!(recorded.2, Element::Item(b"value".to_vec()));
+    db.flush().expect("successful flush");
+    drop(db);
+
+    let mut replay = GroveDb::open_replay(&log_path).expect("successful replay open");
+    assert_eq!(run(&mut replay), recorded);
+}
+
+#[test]
+fn test_open_with_storage() {
+    let tmp_dir = TempDir::new().unwrap();
+    let log_dir = TempDir::new().unwrap();
+    let storage = RocksDbStorage::default_rocksdb_with_path(tmp_dir.path())
+        .expect("successful storage open");
+    let recording = RecordingStorage::new(Box::new(storage), log_dir.path().join("log"))
+        .expect("successful recording start");
+    let mut db = GroveDb::open_with_storage(Box::new(recording)).expect("successful open");
+    add_test_leafs(&mut db);
+
+    let tx = db.start_transaction();
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), Some(&tx))
+        .expect("successful item insert");
+    assert!(matches!(
+        db.get([TEST_LEAF], b"key", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    db.commit_transaction(tx).expect("successful commit");
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+
+    // RocksDB features are not available through a decorator
+    let checkpoint_dir = TempDir::new().unwrap();
+    assert!(matches!(
+        db.checkpoint(checkpoint_dir.path().join("checkpoint"), false, false),
+        Err(Error::NotSupported(_))
+    ));
+    assert!(matches!(
+        db.prepare_transaction(db.start_transaction()),
+        Err(Error::NotSupported(_))
+    ));
+    drop(db);
+
+    let db = GroveDb::open(tmp_dir.path()).expect("successful open");
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_batch_atomicity_under_write_failures() {
+    use storage::fault_injection::FaultInjectionStorage;
+
+    let ops = || {
+        (0..3u8)
+            .map(|i| {
+                (
+                    None,
+                    GroveDbOp::Insert {
+                        path: vec![TEST_LEAF.to_vec()],
+                        key: vec![b'k', i],
+                        element: Element::Item(vec![i]),
+                    },
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Fail every write of the batch in turn, until it has no more writes to fail
+    let mut succeeded = false;
+    for failed_write in 1..1000 {
+        let tmp_dir = TempDir::new().unwrap();
+        let faulty = FaultInjectionStorage::new(Box::new(
+            RocksDbStorage::default_rocksdb_with_path(tmp_dir.path())
+                .expect("successful storage open"),
+        ));
+        let injector = faulty.injector();
+        let mut db = GroveDb::open_with_storage(Box::new(faulty)).expect("successful open");
+        add_test_leafs(&mut db);
+        let root_hash = db.root_hash(None).expect("successful root hash");
+
+        injector.fail_write(injector.writes() + failed_write);
+        if db.apply_batch_with_op_ids(ops(), None).is_ok() {
+            succeeded = true;
+            break;
+        }
+        injector.clear_faults();
+        assert_eq!(
+            db.root_hash(None).expect("successful root hash"),
+            root_hash
+        );
+        for i in 0..3u8 {
+            assert!(matches!(
+                db.get([TEST_LEAF], &[b'k', i], None),
+                Err(Error::PathKeyNotFound(_))
+            ));
+        }
+    }
+    assert!(succeeded);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_insert_atomicity_under_write_failures() {
+    use storage::fault_injection::FaultInjectionStorage;
+
+    // Fail every write of the insertion in turn, until it has no more writes to
+    // fail
+    let mut succeeded = false;
+    for failed_write in 1..1000 {
+        let tmp_dir = TempDir::new().unwrap();
+        let faulty = FaultInjectionStorage::new(Box::new(
+            RocksDbStorage::default_rocksdb_with_path(tmp_dir.path())
+                .expect("successful storage open"),
+        ));
+        let injector = faulty.injector();
+        let mut db = GroveDb::open_with_storage(Box::new(faulty)).expect("successful open");
+        add_test_leafs(&mut db);
+        db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+            .expect("successful item insert");
+        let root_hash = db.root_hash(None).expect("successful root hash");
+
+        injector.fail_write(injector.writes() + failed_write);
+        let reference = Element::Reference(vec![TEST_LEAF.to_vec(), b"key".to_vec()]);
+        if db.insert([TEST_LEAF], b"reference", reference, None).is_ok() {
+            succeeded = true;
+            break;
+        }
+        injector.clear_faults();
+        assert_eq!(
+            db.root_hash(None).expect("successful root hash"),
+            root_hash
+        );
+        assert!(matches!(
+            db.get([TEST_LEAF], b"reference", None),
+            Err(Error::PathKeyNotFound(_))
+        ));
+        assert!(db
+            .references_to([TEST_LEAF], b"key", None)
+            .expect("successful references lookup")
+            .is_empty());
+    }
+    assert!(succeeded);
+}
+
+#[cfg(feature = "sled-backend")]
+#[test]
+fn test_open_sled() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut db = GroveDb::open_sled(tmp_dir.path()).expect("successful open");
+    add_test_leafs(&mut db);
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+        .expect("successful item insert");
+
+    // Transactions writing the same key conflict
+    let tx1 = db.start_transaction();
+    let tx2 = db.start_transaction();
+    db.insert([TEST_LEAF], b"key", Element::Item(b"tx1".to_vec()), Some(&tx1))
+        .expect("successful item insert");
+    db.insert([TEST_LEAF], b"key", Element::Item(b"tx2".to_vec()), Some(&tx2))
+        .expect("successful item insert");
+    db.commit_transaction(tx1).expect("successful commit");
+    assert!(matches!(
+        db.commit_transaction(tx2),
+        Err(Error::BackendError(_))
+    ));
+    let root_hash = db.root_hash(None).expect("successful root hash");
+    db.flush().expect("successful flush");
+    drop(db);
+
+    let db = GroveDb::open_sled(tmp_dir.path()).expect("successful open");
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(b"tx1".to_vec())
+    );
+    assert_eq!(db.root_hash(None).expect("successful root hash"), root_hash);
+}
+
+#[test]
+fn test_maintenance_events_and_statistics() {
+    struct CountingListener(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl MaintenanceEvents for CountingListener {
+        fn on_flush_completed(&self, _info: &FlushInfo) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = GroveDbOpts {
+        enable_statistics: true,
+        ..Default::default()
+    };
+    let mut db = GroveDb::open_with_opts(tmp_dir.path(), &opts).expect("successful open");
+    let flushes = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    db.register_maintenance_events(Box::new(CountingListener(flushes.clone())));
+    add_test_leafs(&mut db);
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+        .expect("successful item insert");
+    db.flush().expect("successful flush");
+    db.compact();
+    assert_eq!(flushes.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    let statistics = db.statistics().expect("statistics are enabled");
+    assert!(statistics.tickers.contains_key("rocksdb.bytes.written"));
+    assert!(statistics
+        .histograms
+        .contains_key("rocksdb.db.write.micros"));
+
+    assert!(make_grovedb().statistics().is_none());
+}
+
+#[test]
+fn test_get_many_by_paths() {
+    let db = make_grovedb();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"reference",
+        Element::Reference(vec![TEST_LEAF.to_vec(), b"key2".to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+
+    let results = db
+        .get_many_by_paths(
+            vec![
+                (vec![TEST_LEAF.to_vec()], b"key2".to_vec()),
+                (vec![ANOTHER_TEST_LEAF.to_vec()], b"reference".to_vec()),
+                (vec![TEST_LEAF.to_vec()], b"missing".to_vec()),
+                (
+                    vec![TEST_LEAF.to_vec(), b"missing".to_vec()],
+                    b"key".to_vec(),
+                ),
+                (vec![TEST_LEAF.to_vec()], b"key1".to_vec()),
+            ],
+            None,
+        )
+        .expect("successful batched get");
+    assert_eq!(
+        results,
+        vec![
+            Some(Element::Item(b"value2".to_vec())),
+            Some(Element::Item(b"value2".to_vec())),
+            None,
+            None,
+            Some(Element::Item(b"value1".to_vec())),
+        ]
+    );
+}
+
+#[test]
+fn test_query_items_normalized() {
+    let db = make_grovedb();
+    for i in 0u8..10 {
+        db.insert([TEST_LEAF], &[i], Element::Item(vec![i]), None)
+            .expect("successful item insert");
+    }
+    let query = Query::from(vec![
+        QueryItem::Range(vec![2]..vec![4]),
+        QueryItem::Key(vec![4]),
+        QueryItem::RangeAfterTo(vec![4]..vec![7]),
+        QueryItem::Range(vec![9]..vec![9]),
+    ]);
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    let (elements, _) = db
+        .get_path_query(&path_query, None)
+        .expect("successful path query");
+    assert_eq!(elements, vec![vec![2], vec![3], vec![4], vec![5], vec![6]]);
+
+    let proof = db
+        .prove(&[path_query], None)
+        .expect("successful proof generation");
+    let (root_hash, results) = GroveDb::execute_proof(&proof).expect("successful proof execution");
+    assert_eq!(Some(root_hash), db.root_hash(None).unwrap());
+    let proved = &results[&vec![TEST_LEAF.to_vec()]];
+    for i in 2u8..7 {
+        assert!(proved
+            .get(&[i])
+            .expect("successful proved value get")
+            .is_some());
+    }
+}
+
+#[test]
+fn test_verify_query_with_limits() {
+    let db = make_grovedb();
+    for i in 0u8..10 {
+        db.insert([TEST_LEAF], &[i], Element::Item(vec![i]), None)
+            .expect("successful item insert");
+    }
+    let mut query = Query::new();
+    query.insert_all();
+    let proof = db
+        .prove(
+            &[PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query)],
+            None,
+        )
+        .expect("successful proof generation");
+
+    let limits = ProofLimits {
+        max_proof_bytes: proof.len(),
+        max_ops: 1000,
+        max_results: 100,
+    };
+    let (root_hash, results) =
+        GroveDb::verify_query_with_limits(&proof, &limits).expect("successful proof verification");
+    assert_eq!(Some(root_hash), db.root_hash(None).unwrap());
+    assert_eq!(results[&vec![TEST_LEAF.to_vec()]].all().count(), 10);
+
+    for exceeded in [
+        ProofLimits {
+            max_proof_bytes: proof.len() - 1,
+            ..limits
+        },
+        ProofLimits {
+            max_ops: 10,
+            ..limits
+        },
+        ProofLimits {
+            max_results: 10,
+            ..limits
+        },
+    ] {
+        assert!(matches!(
+            GroveDb::verify_query_with_limits(&proof, &exceeded),
+            Err(Error::InvalidProof(_))
+        ));
+    }
+}
+
+#[test]
+fn test_evict_and_fetch_cold_subtree() {
+    type Objects = std::sync::Arc<std::sync::Mutex<HashMap<[u8; 32], Vec<u8>>>>;
+
+    struct MemoryArchive(Objects);
+
+    impl ArchiveUploader for MemoryArchive {
+        fn upload(&self, root_hash: &[u8; 32], data: &[u8]) -> Result<(), Error> {
+            self.0.lock().unwrap().insert(*root_hash, data.to_vec());
+            Ok(())
+        }
+
+        fn fetch(&self, root_hash: &[u8; 32]) -> Result<Option<Vec<u8>>, Error> {
+            Ok(self.0.lock().unwrap().get(root_hash).cloned())
+        }
+    }
+
+    let mut db = make_grovedb();
+    db.insert([TEST_LEAF], b"subtree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    for i in 0u8..10 {
+        db.insert(
+            [TEST_LEAF, b"subtree"],
+            &[i],
+            Element::Item(vec![i; 8]),
+            None,
+        )
+        .expect("successful item insert");
+    }
+    let root_hash = db.root_hash(None).expect("successful root hash");
+
+    assert!(matches!(
+        db.evict_subtree([TEST_LEAF, b"subtree"]),
+        Err(Error::ArchiveError(_))
+    ));
+    let objects = Objects::default();
+    db.set_archive(Box::new(MemoryArchive(objects.clone())));
+    assert!(matches!(
+        db.evict_subtree([TEST_LEAF]),
+        Err(Error::InvalidPath(_))
+    ));
+
+    db.evict_subtree([TEST_LEAF, b"subtree"])
+        .expect("successful eviction");
+    assert!(db
+        .is_cold([TEST_LEAF, b"subtree"])
+        .expect("successful cold check"));
+    assert_eq!(objects.lock().unwrap().len(), 1);
+    assert_eq!(db.root_hash(None).expect("successful root hash"), root_hash);
+    db.verify_subtree_hashes(&[], None)
+        .expect("cold subtree is skipped");
+
+    // Subqueries don't return a cold subtree as empty
+    let mut query = Query::new();
+    query.insert_key(b"subtree".to_vec());
+    let mut subquery = Query::new();
+    subquery.insert_all();
+    query.set_subquery(subquery);
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    assert!(matches!(
+        db.get_path_query(&path_query, None),
+        Err(Error::ArchiveError(_))
+    ));
+
+    assert_eq!(
+        db.get([TEST_LEAF, b"subtree"], &[5], None)
+            .expect("successful get from cold subtree"),
+        Element::Item(vec![5; 8])
+    );
+    assert!(!db
+        .is_cold([TEST_LEAF, b"subtree"])
+        .expect("successful cold check"));
+    db.verify_subtree_hashes(&[], None)
+        .expect("fetched subtree matches its hash");
+
+    // Tampered archive data is rejected on fetch
+    db.evict_subtree([TEST_LEAF, b"subtree"])
+        .expect("successful eviction");
+    for data in objects.lock().unwrap().values_mut() {
+        let last = data.len() - 1;
+        data[last] ^= 1;
+    }
+    assert!(matches!(
+        db.get([TEST_LEAF, b"subtree"], &[5], None),
+        Err(Error::CorruptedData(_))
+    ));
+
+    // Frozen subtrees are not evicted
+    db.insert([TEST_LEAF], b"frozen", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.freeze_subtree([TEST_LEAF, b"frozen"])
+        .expect("successful freeze");
+    assert!(matches!(
+        db.evict_subtree([TEST_LEAF, b"frozen"]),
+        Err(Error::SubtreeFrozen)
+    ));
+
+    // Cold subtrees are fetched before deletion, so they are not taken for
+    // empty and their cold marks are removed with them
+    db.insert([TEST_LEAF], b"deleted", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"deleted"],
+        b"key",
+        Element::Item(b"ayy".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.evict_subtree([TEST_LEAF, b"deleted"])
+        .expect("successful eviction");
+    assert!(!db
+        .delete_if_empty_tree([TEST_LEAF], b"deleted", None)
+        .expect("successful delete"));
+    assert_eq!(
+        db.get([TEST_LEAF, b"deleted"], b"key", None)
+            .expect("successful get"),
+        Element::Item(b"ayy".to_vec())
+    );
+    db.evict_subtree([TEST_LEAF, b"deleted"])
+        .expect("successful eviction");
+    db.delete([TEST_LEAF], b"deleted", None)
+        .expect("successful delete");
+    assert!(!db
+        .is_cold([TEST_LEAF, b"deleted"])
+        .expect("successful cold check"));
+}
+
+#[test]
+fn test_prune_subtree() {
+    let db = make_grovedb();
+    db.enable_feature(Feature::PrunedTrees, None)
+        .expect("successful feature enabling");
+    db.insert([TEST_LEAF], b"subtree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"subtree"],
+        b"inner",
+        Element::empty_tree(),
+        None,
+    )
+    .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"subtree", b"inner"],
+        b"key",
+        Element::Item(b"value".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let subtree_hash = match db
+        .get([TEST_LEAF], b"subtree", None)
+        .expect("successful get")
+    {
+        Element::Tree(hash) => hash,
+        _ => panic!("expected a subtree"),
+    };
+    let root_hash_before = db.root_hash(None).unwrap().unwrap();
+
+    assert!(matches!(
+        db.prune_subtree([TEST_LEAF, b"subtree", b"inner", b"key"], true, None),
+        Err(Error::InvalidPath(_))
+    ));
+    db.prune_subtree([TEST_LEAF, b"subtree"], true, None)
+        .expect("successful prune");
+    assert_eq!(db.root_hash(None).unwrap().unwrap(), root_hash_before);
+    assert_eq!(
+        db.get([TEST_LEAF], b"subtree", None)
+            .expect("successful get"),
+        Element::PrunedTree(subtree_hash)
+    );
+    assert!(matches!(
+        db.get([TEST_LEAF, b"subtree"], b"inner", None),
+        Err(Error::SubtreePruned)
+    ));
+    assert!(db
+        .is_empty_tree([TEST_LEAF, b"subtree", b"inner"], None)
+        .is_err());
+
+    // The pruned digest is provable through the parent
+    let mut query = Query::new();
+    query.insert_key(b"subtree".to_vec());
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    let proof = db.prove(&[path_query], None).expect("successful prove");
+    let (root_hash, result_maps) = GroveDb::execute_proof(&proof).expect("successful execute");
+    assert_eq!(root_hash, root_hash_before);
+    // The pruned status is not hashed, so the subtree is proved as it was
+    let element: Element = bincode::deserialize(
+        result_maps[&vec![TEST_LEAF.to_vec()]]
+            .get(b"subtree")
+            .expect("successful map get")
+            .expect("subtree element is proved"),
+    )
+    .expect("successful deserialize");
+    assert_eq!(element, Element::Tree(subtree_hash));
+
+    assert!(matches!(
+        db.insert(
+            [TEST_LEAF],
+            b"other",
+            Element::PrunedTree(subtree_hash),
+            None
+        ),
+        Err(Error::InvalidQuery(_))
+    ));
+    db.insert([TEST_LEAF], b"subtree", Element::empty_tree(), None)
+        .expect("successful subtree insert over pruned one");
+    assert!(db
+        .is_empty_tree([TEST_LEAF, b"subtree"], None)
+        .expect("successful empty check"));
+
+    db.prune_subtree([TEST_LEAF, b"subtree"], false, None)
+        .expect("successful prune");
+    assert!(matches!(
+        db.get([TEST_LEAF], b"subtree", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+}
+
+#[test]
+fn test_collect_garbage() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"subtree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"subtree"],
+        b"key",
+        Element::Item(b"value".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.put_aux(b"aux_key", b"aux_value", None)
+        .expect("successful aux insert");
+    db.insert([TEST_LEAF], b"pruned", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.prune_subtree([TEST_LEAF, b"pruned"], true, None)
+        .expect("successful prune");
+    // Leave data of a subtree which is not in the tree bypassing GroveDB
+    {
+        let storage = db.db.db.get_storage_context([TEST_LEAF, b"orphan"]);
+        storage.put(b"key", b"value").expect("cannot put");
+        storage.put_aux(b"key", b"aux").expect("cannot put aux");
+    }
+    let root_hash = db.root_hash(None).expect("successful root hash");
+
+    let tx = db.start_transaction();
+    assert!(matches!(db.collect_garbage(), Err(Error::TransactionsInProgress)));
+    drop(tx);
+
+    let collected = db.collect_garbage().expect("successful garbage collection");
+    assert_eq!(collected.orphaned_prefixes, 1);
+    assert!(collected.reclaimed_bytes > 0);
+    assert!(db
+        .db
+        .db
+        .get_storage_context([TEST_LEAF, b"orphan"])
+        .get(b"key")
+        .expect("cannot get")
+        .is_none());
+
+    assert_eq!(db.root_hash(None).expect("successful root hash"), root_hash);
+    assert_eq!(
+        db.get([TEST_LEAF, b"subtree"], b"key", None)
+            .expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+    assert_eq!(
+        db.get_aux(b"aux_key", None).expect("successful aux get"),
+        Some(b"aux_value".to_vec())
+    );
+    assert_eq!(
+        db.collect_garbage().expect("successful garbage collection"),
+        CollectedGarbage::default()
+    );
+}
+
+#[test]
+fn test_transaction_scoped() {
+    let db = make_grovedb();
+    for key in [b"a", b"b"] {
+        db.insert([TEST_LEAF], key, Element::empty_tree(), None)
+            .expect("successful subtree insert");
+    }
+
+    let tx_a = db
+        .transaction_scoped(vec![vec![TEST_LEAF.to_vec(), b"a".to_vec()]])
+        .expect("successful scoped transaction start");
+    let tx_b = db
+        .transaction_scoped(vec![vec![TEST_LEAF.to_vec(), b"b".to_vec()]])
+        .expect("successful scoped transaction start");
+    assert!(matches!(
+        db.transaction_scoped(vec![vec![TEST_LEAF.to_vec()]]),
+        Err(Error::SubtreeLocked)
+    ));
+
+    db.insert(
+        [TEST_LEAF, b"a"],
+        b"key",
+        Element::Item(b"value_a".to_vec()),
+        Some(tx_a.transaction()),
+    )
+    .expect("successful insert in scope");
+    db.insert(
+        [TEST_LEAF, b"b"],
+        b"key",
+        Element::Item(b"value_b".to_vec()),
+        Some(tx_b.transaction()),
+    )
+    .expect("successful insert in scope");
+
+    // Both transactions commit although they share ancestors
+    tx_b.commit().expect("successful commit");
+    tx_a.commit().expect("successful commit");
+
+    let expected = make_grovedb();
+    for key in [b"a", b"b"] {
+        expected
+            .insert([TEST_LEAF], key, Element::empty_tree(), None)
+            .expect("successful subtree insert");
+    }
+    expected
+        .insert(
+            [TEST_LEAF, b"a"],
+            b"key",
+            Element::Item(b"value_a".to_vec()),
+            None,
+        )
+        .expect("successful insert");
+    expected
+        .insert(
+            [TEST_LEAF, b"b"],
+            b"key",
+            Element::Item(b"value_b".to_vec()),
+            None,
+        )
+        .expect("successful insert");
+    assert_eq!(
+        db.root_hash(None).expect("successful root hash"),
+        expected.root_hash(None).expect("successful root hash")
+    );
+
+    let tx = db
+        .transaction_scoped(vec![vec![TEST_LEAF.to_vec(), b"a".to_vec()]])
+        .expect("successful scoped transaction start");
+    assert!(matches!(
+        db.insert(
+            [TEST_LEAF],
+            b"key",
+            Element::Item(b"value".to_vec()),
+            Some(tx.transaction()),
+        ),
+        Err(Error::OutOfScope)
+    ));
+    assert!(matches!(tx.commit(), Err(Error::OutOfScope)));
+    assert!(matches!(
+        db.get([TEST_LEAF], b"key", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+}
+
+#[test]
+fn test_proof_decode_and_pretty_print() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+        .expect("successful item insert");
+    let mut query = Query::new();
+    query.insert_key(b"key".to_vec());
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    let proof = db.prove(&[path_query], None).expect("successful prove");
+
+    let ops = Proof::decode(&proof).expect("successful decode");
+    let layers: Vec<&ProofOp> = ops
+        .iter()
+        .filter(|op| matches!(op, ProofOp::Layer(_)))
+        .collect();
+    assert_eq!(
+        layers,
+        vec![
+            &ProofOp::Layer(vec![]),
+            &ProofOp::Layer(vec![TEST_LEAF.to_vec()])
+        ]
+    );
+    let element_bytes =
+        bincode::serialize(&Element::Item(b"value".to_vec())).expect("successful serialize");
+    assert!(ops.contains(&ProofOp::Push(merk::proofs::Node::KV(
+        b"key".to_vec(),
+        element_bytes
+    ))));
+
+    let printed = Proof::pretty_print(&proof).expect("successful pretty print");
+    let mut lines = printed.lines();
+    assert_eq!(lines.next(), Some("Layer []"));
+    assert!(printed.contains(&format!("Layer [{}]\n", hex::encode(TEST_LEAF))));
+    assert_eq!(printed.lines().count(), ops.len());
+    assert!(lines.all(|line| line.starts_with("  Push(")
+        || line.starts_with("  Parent")
+        || line.starts_with("  Child")
+        || line.starts_with("Layer [")));
+
+    assert!(matches!(
+        Proof::decode(b"garbage"),
+        Err(Error::InvalidProof(_))
+    ));
+}
+
+#[test]
+fn test_prove_compressed() {
+    let db = make_grovedb();
+    db.enable_feature(Feature::ProofCompression, None)
+        .expect("successful feature enabling");
+    for i in 0u8..100 {
+        let mut document = b"{\"$type\":\"note\",\"message\":\"hello world\",\"index\":".to_vec();
+        document.push(i);
+        db.insert([TEST_LEAF], &[i], Element::Item(document), None)
+            .expect("successful item insert");
+    }
+    let mut query = Query::new();
+    query.insert_all();
+    let path_queries = [PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query)];
+
+    let plain = db
+        .prove(&path_queries, None)
+        .expect("successful proof generation");
+    let compressed = db
+        .prove_compressed(&path_queries, None)
+        .expect("successful compressed proof generation");
+    assert!(compressed.len() < plain.len());
+
+    let (plain_hash, plain_results) =
+        GroveDb::execute_proof(&plain).expect("successful proof execution");
+    let (compressed_hash, compressed_results) =
+        GroveDb::execute_proof(&compressed).expect("successful compressed proof execution");
+    assert_eq!(Some(compressed_hash), db.root_hash(None).unwrap());
+    assert_eq!(compressed_hash, plain_hash);
+    assert_eq!(
+        compressed_results[&vec![TEST_LEAF.to_vec()]]
+            .all()
+            .collect::<Vec<_>>(),
+        plain_results[&vec![TEST_LEAF.to_vec()]]
+            .all()
+            .collect::<Vec<_>>()
+    );
+
+    // Decompressed length is limited too
+    let limits = ProofLimits {
+        max_proof_bytes: compressed.len(),
+        max_ops: 10000,
+        max_results: 1000,
+    };
+    assert!(matches!(
+        GroveDb::verify_query_with_limits(&compressed, &limits),
+        Err(Error::InvalidProof(_))
+    ));
+
+    for header_byte in 0..2 {
+        let mut unknown = compressed.clone();
+        unknown[header_byte] = 0xff;
+        assert!(matches!(
+            GroveDb::execute_proof(&unknown),
+            Err(Error::InvalidProof(_))
+        ));
+    }
+    let mut corrupted = compressed;
+    corrupted.truncate(10);
+    assert!(matches!(
+        GroveDb::execute_proof(&corrupted),
+        Err(Error::InvalidProof(_))
+    ));
+}
+
+#[test]
+fn test_proof_shared_nodes() {
+    let db = make_grovedb();
+    for subtree in [b"a".as_ref(), b"b".as_ref()] {
+        db.insert([TEST_LEAF], subtree, Element::empty_tree(), None)
+            .expect("successful subtree insert");
+        for i in 0u8..10 {
+            db.insert([TEST_LEAF, subtree], &[i], Element::Item(vec![i; 50]), None)
+                .expect("successful item insert");
+        }
+    }
+    let mut query = Query::new();
+    query.insert_all();
+    let path_queries = [
+        PathQuery::new_unsized(vec![TEST_LEAF.to_vec(), b"a".to_vec()], query.clone()),
+        PathQuery::new_unsized(vec![TEST_LEAF.to_vec(), b"b".to_vec()], query),
+    ];
+    let proof = db
+        .prove(&path_queries, None)
+        .expect("successful proof generation");
+
+    // Items of both subtrees are the same, so they are encoded once
+    let raw: Proof = bincode::deserialize(&proof[2..]).expect("successful proof deserialization");
+    assert_eq!(raw.shared_nodes.len(), 10);
+
+    let (root_hash, results) = GroveDb::execute_proof(&proof).expect("successful proof execution");
+    assert_eq!(Some(root_hash), db.root_hash(None).unwrap());
+    for subtree in [b"a".as_ref(), b"b".as_ref()] {
+        let result_map = &results[&vec![TEST_LEAF.to_vec(), subtree.to_vec()]];
+        assert_eq!(result_map.all().count(), 10);
+    }
+    let ops = Proof::decode(&proof).expect("successful proof decoding");
+    assert_eq!(
+        ops.iter()
+            .filter(|op| matches!(op, ProofOp::Push(merk::proofs::Node::KV(..))))
+            .count(),
+        23
+    );
+
+    // Shared node references expand to no more operations than the limit
+    let mut amplified: Proof =
+        bincode::deserialize(&proof[2..]).expect("successful proof deserialization");
+    let subtree_proof = amplified
+        .proofs
+        .values_mut()
+        .next()
+        .expect("proof has subtree proofs");
+    subtree_proof.clear();
+    for _ in 0..1000 {
+        subtree_proof.push(0x20);
+        subtree_proof.extend(0u32.to_be_bytes());
+    }
+    let mut bytes = proof[..2].to_vec();
+    bytes.extend(bincode::serialize(&amplified).expect("successful proof serialization"));
+    let limits = ProofLimits {
+        max_proof_bytes: usize::MAX,
+        max_ops: 100,
+        max_results: usize::MAX,
+    };
+    assert!(matches!(
+        GroveDb::verify_query_with_limits(&bytes, &limits),
+        Err(Error::InvalidProof("proof operation limit exceeded"))
+    ));
+
+    let mut dangling = raw;
+    dangling.shared_nodes.pop();
+    let mut bytes = proof[..2].to_vec();
+    bytes.extend(bincode::serialize(&dangling).expect("successful proof serialization"));
+    assert!(matches!(
+        GroveDb::execute_proof(&bytes),
+        Err(Error::InvalidProof(_))
+    ));
+}
+
+#[test]
+fn test_query_many() {
+    let db = make_grovedb();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+
+    let mut query = Query::new();
+    query.insert_all();
+    let path_queries = vec![
+        PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query.clone()),
+        PathQuery::new_unsized(vec![ANOTHER_TEST_LEAF.to_vec()], query),
+    ];
+    let results = db
+        .query_many(path_queries.clone(), None)
+        .expect("successful queries");
+    assert_eq!(
+        results,
+        vec![(vec![b"value1".to_vec()], 0), (vec![b"value2".to_vec()], 0)]
+    );
+
+    let tx = db.start_transaction();
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key3",
+        Element::Item(b"value3".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    let results = db
+        .query_many(path_queries, Some(&tx))
+        .expect("successful queries");
+    assert_eq!(
+        results[1],
+        (vec![b"value2".to_vec(), b"value3".to_vec()], 0)
+    );
+}
+
+#[test]
+fn test_query_result_helpers() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"a", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"a"],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [TEST_LEAF, b"a"],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"ref",
+        Element::Reference(vec![TEST_LEAF.to_vec(), b"a".to_vec(), b"key1".to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+
+    let mut subquery = Query::new();
+    subquery.insert_all();
+    let mut query = Query::new();
+    query.insert_all();
+    query.set_subquery(subquery);
+    let (results, skipped) = db
+        .get_path_query_result_elements(
+            &PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query),
+            None,
+        )
+        .expect("successful query");
+    assert_eq!(skipped, 0);
+    assert_eq!(results.len(), 2);
+    assert!(results
+        .elements
+        .iter()
+        .all(|result| result.path == vec![TEST_LEAF.to_vec(), b"a".to_vec()]));
+    assert_eq!(
+        results.clone().into_key_elements(),
+        vec![
+            (b"key1".to_vec(), Element::Item(b"value1".to_vec())),
+            (b"key2".to_vec(), Element::Item(b"value2".to_vec()))
+        ]
+    );
+    assert_eq!(
+        results.clone().into_items_bytes().expect("only items"),
+        vec![b"value1".to_vec(), b"value2".to_vec()]
+    );
+    assert!(matches!(
+        results.into_paths(),
+        Err(Error::UnexpectedElement(_))
+    ));
+
+    let mut query = Query::new();
+    query.insert_all();
+    let (results, _) = db
+        .get_path_query_result_elements(
+            &PathQuery::new_unsized(vec![ANOTHER_TEST_LEAF.to_vec()], query),
+            None,
+        )
+        .expect("successful query");
+    assert_eq!(
+        results.clone().into_paths().expect("only references"),
+        vec![vec![TEST_LEAF.to_vec(), b"a".to_vec(), b"key1".to_vec()]]
+    );
+    assert!(matches!(
+        results.into_items_bytes(),
+        Err(Error::UnexpectedElement(_))
+    ));
+}
+
+#[test]
+fn test_normalized_keys() {
+    let db = make_grovedb();
+    db.insert_normalized(
+        [TEST_LEAF],
+        b"Alice",
+        Element::Item(b"alice's value".to_vec()),
+        &AsciiLowercase,
+        None,
+    )
+    .expect("successful normalized insert");
+    db.insert_normalized(
+        [TEST_LEAF],
+        b"BOB",
+        Element::Item(b"bob's value".to_vec()),
+        &AsciiLowercase,
+        None,
+    )
+    .expect("successful normalized insert");
+
+    assert_eq!(
+        db.get([TEST_LEAF], b"alice", None).expect("successful get"),
+        Element::Item(b"alice's value".to_vec())
+    );
+    assert_eq!(
+        db.get_normalized([TEST_LEAF], b"ALICE", &AsciiLowercase, None)
+            .expect("successful normalized get"),
+        Element::Item(b"alice's value".to_vec())
+    );
+    assert_eq!(
+        db.get_original_key([TEST_LEAF], b"alice", None)
+            .expect("successful original key get"),
+        Some(b"Alice".to_vec())
+    );
+    assert!(matches!(
+        db.get([TEST_LEAF], b"Alice", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+
+    let mut query = Query::new();
+    query.insert_key(b"aLiCe".to_vec());
+    query.insert_range_inclusive(b"B".to_vec()..=b"C".to_vec());
+    let (results, _) = db
+        .query_normalized(
+            &PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query),
+            &AsciiLowercase,
+            None,
+        )
+        .expect("successful normalized query");
+    assert_eq!(
+        results,
+        vec![b"alice's value".to_vec(), b"bob's value".to_vec()]
+    );
+
+    // Inserting another form of the same key replaces the element
+    let tx = db.start_transaction();
+    db.insert_normalized(
+        [TEST_LEAF],
+        b"ALICE",
+        Element::Item(b"new value".to_vec()),
+        &AsciiLowercase,
+        Some(&tx),
+    )
+    .expect("successful normalized insert");
+    assert_eq!(
+        db.get_original_key([TEST_LEAF], b"alice", Some(&tx))
+            .expect("successful original key get"),
+        Some(b"ALICE".to_vec())
+    );
+    assert_eq!(
+        db.get_original_key([TEST_LEAF], b"alice", None)
+            .expect("successful original key get"),
+        Some(b"Alice".to_vec())
+    );
+    drop(tx);
+
+    // Original keys are removed with their elements
+    db.delete([TEST_LEAF], b"alice", None).expect("successful delete");
+    assert_eq!(
+        db.get_original_key([TEST_LEAF], b"alice", None)
+            .expect("successful original key get"),
+        None
+    );
+    db.insert([TEST_LEAF], b"bob", Element::Item(b"plain".to_vec()), None)
+        .expect("successful insert");
+    assert_eq!(
+        db.get_original_key([TEST_LEAF], b"bob", None)
+            .expect("successful original key get"),
+        None
+    );
+
+    // and with their subtrees
+    db.insert([TEST_LEAF], b"names", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert_normalized(
+        [TEST_LEAF, b"names"],
+        b"Carol",
+        Element::Item(b"carol's value".to_vec()),
+        &AsciiLowercase,
+        None,
+    )
+    .expect("successful normalized insert");
+    db.delete([TEST_LEAF], b"names", None).expect("successful subtree delete");
+    db.insert([TEST_LEAF], b"names", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    assert_eq!(
+        db.get_original_key([TEST_LEAF, b"names"], b"carol", None)
+            .expect("successful original key get"),
+        None
+    );
+}
+
+#[test]
+fn test_normalized_query_with_conditional_subqueries() {
+    let db = make_grovedb();
+    for key in [b"A", b"b"] {
+        db.insert_normalized(
+            [TEST_LEAF],
+            key,
+            Element::empty_tree(),
+            &AsciiLowercase,
+            None,
+        )
+        .expect("successful normalized insert");
+        let normalized_key = key.to_ascii_lowercase();
+        for subquery_key in [b"x", b"y"] {
+            let mut value = subquery_key.to_vec();
+            value.extend_from_slice(&normalized_key);
+            db.insert(
+                [TEST_LEAF, normalized_key.as_slice()],
+                subquery_key,
+                Element::Item(value),
+                None,
+            )
+            .expect("successful item insert");
+        }
+    }
+
+    // Ranges are disjoint until lowercased, then both are `a..c`, which
+    // the first condition applies to
+    let mut query = Query::new();
+    query.insert_range(b"A".to_vec()..b"C".to_vec());
+    query.insert_range(b"a".to_vec()..b"c".to_vec());
+    query.add_conditional_subquery(
+        QueryItem::Range(b"A".to_vec()..b"C".to_vec()),
+        Some(b"x".to_vec()),
+        None,
+    );
+    query.add_conditional_subquery(
+        QueryItem::Range(b"a".to_vec()..b"c".to_vec()),
+        Some(b"y".to_vec()),
+        None,
+    );
+    let (results, _) = db
+        .query_normalized(
+            &PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query),
+            &AsciiLowercase,
+            None,
+        )
+        .expect("successful normalized query");
+    assert_eq!(results, vec![b"xa".to_vec(), b"xb".to_vec()]);
+
+    // A condition on a part of a merged range applies to that part only
+    let mut query = Query::new();
+    query.insert_range_inclusive(b"A".to_vec()..=b"A".to_vec());
+    query.insert_range(b"a".to_vec()..b"c".to_vec());
+    query.set_subquery_key(b"y".to_vec());
+    query.add_conditional_subquery(
+        QueryItem::RangeInclusive(b"A".to_vec()..=b"A".to_vec()),
+        Some(b"x".to_vec()),
+        None,
+    );
+    let (results, _) = db
+        .query_normalized(
+            &PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query),
+            &AsciiLowercase,
+            None,
+        )
+        .expect("successful normalized query");
+    assert_eq!(results, vec![b"xa".to_vec(), b"yb".to_vec()]);
+}
+
+#[test]
+fn test_apply_aux_batch() {
+    let db = make_grovedb();
+    db.put_aux(b"epoch0", b"old", None).expect("cannot put aux");
+    db.apply_aux_batch(
+        vec![
+            AuxOp::Put {
+                key: b"epoch1".to_vec(),
+                value: b"fees1".to_vec(),
+            },
+            AuxOp::Put {
+                key: b"epoch2".to_vec(),
+                value: b"fees2".to_vec(),
+            },
+            AuxOp::Delete {
+                key: b"epoch0".to_vec(),
+            },
+        ],
+        None,
+    )
+    .expect("successful aux batch");
+    assert_eq!(db.get_aux(b"epoch0", None).expect("cannot get aux"), None);
+    assert_eq!(
+        db.get_aux(b"epoch1", None).expect("cannot get aux"),
+        Some(b"fees1".to_vec())
+    );
+    assert_eq!(
+        db.get_aux(b"epoch2", None).expect("cannot get aux"),
+        Some(b"fees2".to_vec())
+    );
+
+    let tx = db.start_transaction();
+    db.apply_aux_batch(
+        vec![
+            AuxOp::Delete {
+                key: b"epoch1".to_vec(),
+            },
+            AuxOp::Put {
+                key: b"epoch3".to_vec(),
+                value: b"fees3".to_vec(),
+            },
+        ],
+        Some(&tx),
+    )
+    .expect("successful aux batch");
+    assert_eq!(
+        db.get_aux(b"epoch1", None).expect("cannot get aux"),
+        Some(b"fees1".to_vec())
+    );
+    assert_eq!(db.get_aux(b"epoch3", None).expect("cannot get aux"), None);
+    assert_eq!(
+        db.get_aux(b"epoch3", Some(&tx)).expect("cannot get aux"),
+        Some(b"fees3".to_vec())
+    );
+    db.commit_transaction(tx)
+        .expect("cannot commit transaction");
+    assert_eq!(db.get_aux(b"epoch1", None).expect("cannot get aux"), None);
+    assert_eq!(
+        db.get_aux(b"epoch3", None).expect("cannot get aux"),
+        Some(b"fees3".to_vec())
+    );
+}
+
+#[test]
+fn test_aux_iter() {
+    let db = make_grovedb();
+    db.put_aux(b"epoch/2", b"fees2", None)
+        .expect("cannot put aux");
+    db.put_aux(b"epoch/1", b"fees1", None)
+        .expect("cannot put aux");
+    db.put_aux(b"epoch0", b"other", None)
+        .expect("cannot put aux");
+    db.put_aux(b"pool", b"pool", None).expect("cannot put aux");
+    // Subtree data and aux data of other subtrees are not iterated
+    db.insert(
+        [TEST_LEAF],
+        b"epoch/3",
+        Element::Item(b"item".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    // Bookkeeping of GroveDB itself is not stored with auxiliary data
+    db.apply_batch_with_op_ids(
+        vec![(
+            Some(b"op".to_vec()),
+            GroveDbOp::Insert {
+                path: vec![TEST_LEAF.to_vec()],
+                key: b"key".to_vec(),
+                element: Element::Item(b"item".to_vec()),
+            },
+        )],
+        None,
+    )
+    .expect("successful batch");
+    db.enable_value_hash_index([TEST_LEAF], None)
+        .expect("successful index enabling");
+    db.insert_normalized(
+        [],
+        b"Registry",
+        Element::empty_tree(),
+        &AsciiLowercase,
+        None,
+    )
+    .expect("successful normalized insert");
+
+    assert_eq!(
+        db.aux_iter(b"epoch/", None).collect::<Vec<_>>(),
+        vec![
+            (b"epoch/1".to_vec(), b"fees1".to_vec()),
+            (b"epoch/2".to_vec(), b"fees2".to_vec())
+        ]
+    );
+    assert_eq!(db.aux_iter(b"", None).count(), 4);
+    assert_eq!(db.aux_iter(b"missing", None).count(), 0);
+
+    let tx = db.start_transaction();
+    db.put_aux(b"epoch/3", b"fees3", Some(&tx))
+        .expect("cannot put aux");
+    db.delete_aux(b"epoch/1", Some(&tx))
+        .expect("cannot delete aux");
+    assert_eq!(
+        db.aux_iter(b"epoch/", Some(&tx))
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>(),
+        vec![b"epoch/2".to_vec(), b"epoch/3".to_vec()]
+    );
+    assert_eq!(db.aux_iter(b"epoch/", None).count(), 2);
+}
+
+#[test]
+fn test_version_and_features() {
+    let tmp_dir = TempDir::new().unwrap();
+    {
+        let db = GroveDb::open(tmp_dir.path()).unwrap();
+        let version = db.version().expect("successful version read");
+        assert_eq!(version.format_version, GROVE_FORMAT_VERSION);
+        assert!(version.features().is_empty());
+
+        db.insert([], TEST_LEAF, Element::empty_tree(), None)
+            .expect("successful root tree leaf insert");
+        db.insert([TEST_LEAF], b"subtree", Element::empty_tree(), None)
+            .expect("successful subtree insert");
+        assert!(matches!(
+            db.prune_subtree([TEST_LEAF, b"subtree"], true, None),
+            Err(Error::FeatureNotEnabled(_))
+        ));
+        let mut query = Query::new();
+        query.insert_all();
+        assert!(matches!(
+            db.prove_compressed(
+                &[PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query)],
+                None
+            ),
+            Err(Error::FeatureNotEnabled(_))
+        ));
+
+        // A feature enabled in a transaction is enabled once it is committed
+        let tx = db.start_transaction();
+        db.enable_feature(Feature::ProofCompression, Some(&tx))
+            .expect("successful feature enabling");
+        assert!(!db
+            .is_feature_enabled(Feature::ProofCompression)
+            .expect("successful version read"));
+        drop(tx);
+
+        db.enable_feature(Feature::PrunedTrees, None)
+            .expect("successful feature enabling");
+        db.prune_subtree([TEST_LEAF, b"subtree"], true, None)
+            .expect("successful subtree pruning");
+    }
+
+    // Enabled features are kept
+    {
+        let db = GroveDb::open(tmp_dir.path()).unwrap();
+        assert!(db
+            .is_feature_enabled(Feature::PrunedTrees)
+            .expect("successful version read"));
+        assert!(!db
+            .is_feature_enabled(Feature::ProofCompression)
+            .expect("successful version read"));
+
+        // Pretend the data was written by a newer release
+        let version = GroveVersion {
+            format_version: GROVE_FORMAT_VERSION + 1,
+            ..db.version().expect("successful version read")
+        };
+        let meta_storage = db.db.get_storage_context(std::iter::empty());
+        meta_storage
+            .put_meta(b"grove_version", &bincode::serialize(&version).unwrap())
+            .expect("successful version write");
+    }
+    assert!(matches!(
+        GroveDb::open(tmp_dir.path()),
+        Err(Error::IncompatibleVersion(_))
+    ));
+}
+
+#[test]
+fn test_version_record_missing() {
+    let tmp_dir = TempDir::new().unwrap();
+    let checkpoint_dir = TempDir::new().unwrap();
+    let checkpoint_path = checkpoint_dir.path().join("checkpoint");
+    {
+        let db = GroveDb::open(tmp_dir.path()).unwrap();
+        db.insert([], TEST_LEAF, Element::empty_tree(), None)
+            .expect("successful root tree leaf insert");
+
+        // Pretend the data was written before version records
+        let meta_storage = db.db.get_storage_context(std::iter::empty());
+        meta_storage
+            .delete_meta(b"grove_version")
+            .expect("successful version delete");
+        assert_eq!(
+            db.version()
+                .expect("successful version read")
+                .format_version,
+            0
+        );
+        db.checkpoint(&checkpoint_path, false, false)
+            .expect("successful checkpoint");
+    }
+
+    // Data of an older format can't be read without a migration
+    assert!(matches!(
+        GroveDbReader::open(&checkpoint_path),
+        Err(Error::IncompatibleVersion(_))
+    ));
+
+    let db = GroveDb::open(tmp_dir.path()).unwrap();
+    assert_eq!(
+        db.version()
+            .expect("successful version read")
+            .format_version,
+        GROVE_FORMAT_VERSION
+    );
+    db.get([], TEST_LEAF, None)
+        .expect("successful root tree leaf get");
+}
+
+#[test]
+fn test_legacy_root_leaves_migration() {
+    let tmp_dir = TempDir::new().unwrap();
+    let expected_subtree_hash = {
+        // Root tree leaves kept in meta storage, as written by format version 0
+        let storage = RocksDbStorage::default_rocksdb_with_path(tmp_dir.path())
+            .expect("successful storage open");
+        let mut subtree = Merk::open(storage.get_storage_context([TEST_LEAF]))
+            .expect("successful subtree open");
+        Element::Item(b"value".to_vec())
+            .insert(&mut subtree, b"key")
+            .expect("successful item insert");
+        let root_leaf_keys: std::collections::BTreeMap<Vec<u8>, usize> =
+            [(TEST_LEAF.to_vec(), 0)].into_iter().collect();
+        storage
+            .get_storage_context(std::iter::empty())
+            .put_meta(
+                b"rootLeafsSerialized",
+                &bincode::serialize(&root_leaf_keys).unwrap(),
+            )
+            .expect("successful root leaves write");
+        subtree.root_hash()
+    };
+
+    let db = GroveDb::open(tmp_dir.path()).unwrap();
+    assert_eq!(
+        db.version()
+            .expect("successful version read")
+            .format_version,
+        GROVE_FORMAT_VERSION
+    );
+    assert_eq!(
+        db.get([], TEST_LEAF, None)
+            .expect("successful root tree leaf get"),
+        Element::Tree(expected_subtree_hash)
+    );
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+    assert!(db
+        .db
+        .get_storage_context(std::iter::empty())
+        .get_meta(b"rootLeafsSerialized")
+        .expect("successful meta read")
+        .is_none());
+}
+
+#[test]
+fn test_error_codes() {
+    // Codes are part of the consensus interface and must never change
+    let errors = [
+        (Error::CyclicReference, 100),
+        (Error::ReferenceLimit, 101),
+        (Error::SubtreeLocked, 102),
+        (Error::SubtreePruned, 103),
+        (Error::OutOfScope, 104),
+        (Error::FeatureNotEnabled(""), 105),
+        (Error::ReferentialIntegrity(""), 106),
+        (Error::InternalError(""), 107),
+        (Error::InvalidProof(""), 108),
+        (Error::NotSupported(""), 114),
+        (Error::TransactionsInProgress, 115),
+        (Error::TenantRootNotFound, 116),
+        (Error::ReferenceOutOfView, 117),
+        (Error::PathKeyNotFound(String::new()), 200),
+        (Error::PathNotFound(""), 201),
+        (Error::InvalidPath(""), 202),
+        (Error::CorruptedPath(""), 203),
+        (Error::InvalidQuery(""), 300),
+        (Error::MissingParameter(""), 301),
+        (Error::UnexpectedElement(String::new()), 302),
+        (Error::CorruptedData(String::new()), 401),
+        (
+            Error::IoError(std::io::Error::new(std::io::ErrorKind::Other, "")),
+            402,
+        ),
+        (Error::ArchiveError(String::new()), 403),
+        (Error::IncompatibleVersion(String::new()), 404),
+    ];
+    for (error, code) in &errors {
+        assert_eq!(error.code(), *code, "code of {:?} has changed", error);
+    }
+
+    let db = make_grovedb();
+    let error = db
+        .get([TEST_LEAF], b"missing", None)
+        .expect_err("key should not exist");
+    assert_eq!(error.code(), 200);
+}
+
+#[test]
+fn test_dry_run_batch() {
+    let db = make_grovedb();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let root_hash = db.root_hash(None).expect("successful root hash");
+    let ops = vec![
+        GroveDbOp::Insert {
+            path: vec![TEST_LEAF.to_vec()],
+            key: b"key2".to_vec(),
+            element: Element::Item(b"value2".to_vec()),
+        },
+        GroveDbOp::Delete {
+            path: vec![TEST_LEAF.to_vec()],
+            key: b"key1".to_vec(),
+        },
+    ];
+
+    let projected = db
+        .dry_run_batch(ops.clone(), None)
+        .expect("successful dry run");
+    assert_ne!(projected, root_hash);
+    assert_eq!(db.root_hash(None).expect("successful root hash"), root_hash);
+    assert!(db.get([TEST_LEAF], b"key1", None).is_ok());
+
+    // Changes of the transaction are kept, the dry run ones are not
+    let tx = db.start_transaction();
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key3",
+        Element::Item(b"value3".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    let tx_root_hash = db.root_hash(Some(&tx)).expect("successful root hash");
+    let projected_on_tx = db
+        .dry_run_batch(ops.clone(), Some(&tx))
+        .expect("successful dry run");
+    assert_eq!(
+        db.root_hash(Some(&tx)).expect("successful root hash"),
+        tx_root_hash
+    );
+    assert!(db.get([TEST_LEAF], b"key2", Some(&tx)).is_err());
+    assert!(db.get([ANOTHER_TEST_LEAF], b"key3", Some(&tx)).is_ok());
+    drop(tx);
+
+    db.apply_batch(ops, None).expect("successful batch");
+    assert_eq!(db.root_hash(None).expect("successful root hash"), projected);
+    assert_ne!(projected_on_tx, projected);
+
+    // A failing operation fails the dry run
+    assert!(db
+        .dry_run_batch(
+            vec![GroveDbOp::Delete {
+                path: vec![b"missing".to_vec()],
+                key: b"key".to_vec(),
+            }],
+            None
+        )
+        .is_err());
+}
+
+#[test]
+fn test_commit_transaction_with_hook() {
+    let db = make_grovedb();
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    let expected_root_hash = db.root_hash(Some(&tx)).expect("successful root hash");
+    let mut block_store = Vec::new();
+    db.commit_transaction_with_hook(tx, |root_hash| {
+        block_store.push(root_hash);
+        Ok(())
+    })
+    .expect("successful commit");
+    assert_eq!(block_store, vec![expected_root_hash]);
+    assert_eq!(
+        db.root_hash(None).expect("successful root hash"),
+        expected_root_hash
+    );
+
+    // A failing hook aborts the commit
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    assert!(matches!(
+        db.commit_transaction_with_hook(tx, |_| Err(Error::InternalError("block store is full"))),
+        Err(Error::InternalError("block store is full"))
+    ));
+    assert!(matches!(
+        db.get([TEST_LEAF], b"key2", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    assert_eq!(
+        db.root_hash(None).expect("successful root hash"),
+        expected_root_hash
+    );
+}
+
+#[test]
+fn test_prepared_transactions() {
+    let db = make_grovedb();
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    let committed = db.prepare_transaction(tx).expect("successful prepare");
+
+    // Writes of different subtrees conflict as well, as all of them change
+    // the root tree
+    let tx = db.start_transaction();
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    assert!(matches!(
+        db.prepare_transaction(tx),
+        Err(Error::PreparedTransactionConflict)
+    ));
+
+    // Prepared writes are not visible until committed
+    assert!(matches!(
+        db.get([TEST_LEAF], b"key1", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    assert_eq!(
+        db.prepared_transactions()
+            .expect("successful prepared transactions listing"),
+        vec![committed]
+    );
+
+    // Writes touching keys of the prepared transaction fail rather than wait
+    // for it, so it can't become stale, while other writes go through
+    assert!(matches!(
+        db.insert(
+            [TEST_LEAF],
+            b"key3",
+            Element::Item(b"value3".to_vec()),
+            None,
+        ),
+        Err(Error::PreparedTransactionConflict)
+    ));
+    assert!(matches!(
+        db.get([TEST_LEAF], b"key3", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    db.put_aux(b"aux", b"value", None)
+        .expect("successful aux put");
+
+    db.commit_prepared(PreparedToken::from_id(committed.id()))
+        .expect("successful commit");
+    assert_eq!(
+        db.get([TEST_LEAF], b"key1", None).expect("successful get"),
+        Element::Item(b"value1".to_vec())
+    );
+    db.insert(
+        [TEST_LEAF],
+        b"key3",
+        Element::Item(b"value3".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    assert!(db
+        .prepared_transactions()
+        .expect("successful prepared transactions listing")
+        .is_empty());
+    assert!(matches!(
+        db.commit_prepared(committed),
+        Err(Error::PreparedTransactionNotFound)
+    ));
+
+    // Tokens are not reused
+    let tx = db.start_transaction();
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    let rolled_back = db.prepare_transaction(tx).expect("successful prepare");
+    assert!(rolled_back.id() > committed.id());
+    db.rollback_prepared(rolled_back)
+        .expect("successful rollback");
+    assert!(matches!(
+        db.get([ANOTHER_TEST_LEAF], b"key2", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+
+    // A write made before the prepare conflicts if it touched keys the
+    // transaction writes, as its optimistic commit would fail, but auxiliary
+    // data of other keys doesn't
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key4",
+        Element::Item(b"value4".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    db.put_aux(b"aux", b"value", None)
+        .expect("successful aux put");
+    let prepared = db.prepare_transaction(tx).expect("successful prepare");
+    db.rollback_prepared(prepared).expect("successful rollback");
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key4",
+        Element::Item(b"value4".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key5",
+        Element::Item(b"value5".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    assert!(matches!(
+        db.prepare_transaction(tx),
+        Err(Error::PreparedTransactionConflict)
+    ));
+}
+
+#[test]
+fn test_commit_prepared_after_restart() {
+    let tmp_dir = TempDir::new().unwrap();
+    let (token, expected_root_hash) = {
+        let mut db = GroveDb::open(tmp_dir.path()).unwrap();
+        add_test_leafs(&mut db);
+        let tx = db.start_transaction();
+        db.insert(
+            [TEST_LEAF],
+            b"key",
+            Element::Item(b"value".to_vec()),
+            Some(&tx),
+        )
+        .expect("successful item insert");
+        let root_hash = db.root_hash(Some(&tx)).expect("successful root hash");
+        (
+            db.prepare_transaction(tx).expect("successful prepare"),
+            root_hash,
+        )
+    };
+
+    let db = GroveDb::open(tmp_dir.path()).expect("successful open");
+    assert_eq!(
+        db.prepared_transactions()
+            .expect("successful prepared transactions listing"),
+        vec![token]
+    );
+    // Keys of the prepared transaction stay reserved after the restart
+    assert!(matches!(
+        db.insert(
+            [TEST_LEAF],
+            b"another_key",
+            Element::Item(b"value".to_vec()),
+            None,
+        ),
+        Err(Error::PreparedTransactionConflict)
+    ));
+    db.commit_prepared(token).expect("successful commit");
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+    assert_eq!(
+        db.root_hash(None).expect("successful root hash"),
+        expected_root_hash
+    );
+}
+
+#[test]
+fn test_subscribe() {
+    let db = make_grovedb();
+    let leaf_hash = || match db.get([], TEST_LEAF, None).expect("successful get") {
+        Element::Tree(hash) => hash,
+        _ => panic!("expected a tree"),
+    };
+    let receiver = db.subscribe([TEST_LEAF]).expect("successful subscribe");
+    let old_hash = leaf_hash();
+
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let new_hash = leaf_hash();
+    let change = receiver.try_recv().expect("a notification");
+    assert_eq!(change.path, vec![TEST_LEAF.to_vec()]);
+    assert_eq!(change.old_hash, old_hash);
+    assert_eq!(change.new_hash, new_hash);
+
+    // Changes of other subtrees are not notified about
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    assert!(receiver.try_recv().is_err());
+
+    // Transactional changes are notified about on commit
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    assert!(receiver.try_recv().is_err());
+    db.commit_transaction(tx).expect("successful commit");
+    let next_change = receiver.try_recv().expect("a notification");
+    assert_eq!(next_change.old_hash, new_hash);
+    assert!(next_change.sequence_number > change.sequence_number);
+
+    // Commits of scoped transactions are notified about, with hashes
+    // propagated from the scope on commit
+    db.insert([TEST_LEAF], b"scope", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    let scoped_receiver = db
+        .subscribe([TEST_LEAF, b"scope"])
+        .expect("successful subscription");
+    let hash_before_scoped = leaf_hash();
+    while receiver.try_recv().is_ok() {}
+    let scoped_tx = db
+        .transaction_scoped(vec![vec![TEST_LEAF.to_vec(), b"scope".to_vec()]])
+        .expect("successful scoped transaction start");
+    db.insert(
+        [TEST_LEAF, b"scope"],
+        b"key",
+        Element::Item(b"value".to_vec()),
+        Some(scoped_tx.transaction()),
+    )
+    .expect("successful item insert");
+    scoped_tx.commit().expect("successful commit");
+    let scoped_change = scoped_receiver.try_recv().expect("a notification");
+    assert_eq!(scoped_change.path, vec![TEST_LEAF.to_vec(), b"scope".to_vec()]);
+    let leaf_change = receiver.try_recv().expect("a notification");
+    assert_eq!(leaf_change.old_hash, hash_before_scoped);
+    assert_eq!(leaf_change.new_hash, leaf_hash());
+
+    // Deleting a subscribed subtree is notified about
+    db.delete([TEST_LEAF], b"scope", None)
+        .expect("successful subtree delete");
+    assert_eq!(
+        scoped_receiver.try_recv().expect("a notification").old_hash,
+        scoped_change.new_hash
+    );
+
+    assert!(matches!(
+        db.subscribe([TEST_LEAF, b"missing"]),
+        Err(Error::PathNotFound(_))
+    ));
+}
+
+#[test]
+fn test_subscribe_changes() {
+    let db = make_grovedb();
+    let receiver = db.subscribe_changes();
+
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    assert_eq!(
+        receiver.try_recv().expect("changes"),
+        vec![KeyChange {
+            path: vec![TEST_LEAF.to_vec()],
+            key: b"key1".to_vec(),
+            op: KeyChangeOp::Put,
+        }]
+    );
+
+    // Changes of a transaction are sent together on commit
+    let tx = db.start_transaction();
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    db.delete([TEST_LEAF], b"key1", Some(&tx))
+        .expect("successful delete");
+    assert!(receiver.try_recv().is_err());
+    db.commit_transaction(tx).expect("successful commit");
+    assert_eq!(
+        receiver.try_recv().expect("changes"),
+        vec![
+            KeyChange {
+                path: vec![ANOTHER_TEST_LEAF.to_vec()],
+                key: b"key2".to_vec(),
+                op: KeyChangeOp::Put,
+            },
+            KeyChange {
+                path: vec![TEST_LEAF.to_vec()],
+                key: b"key1".to_vec(),
+                op: KeyChangeOp::Delete,
+            },
+        ]
+    );
+
+    // Rolled back changes are not sent
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key3",
+        Element::Item(b"value3".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    db.rollback_transaction(&tx).expect("successful rollback");
+    db.commit_transaction(tx).expect("successful commit");
+    assert!(receiver.try_recv().is_err());
+
+    // Changes are tracked by transaction ID, so they follow a moved transaction
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key4",
+        Element::Item(b"value4".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    let moved = Box::new(tx);
+    db.commit_transaction(*moved).expect("successful commit");
+    assert_eq!(
+        receiver.try_recv().expect("changes"),
+        vec![KeyChange {
+            path: vec![TEST_LEAF.to_vec()],
+            key: b"key4".to_vec(),
+            op: KeyChangeOp::Put,
+        }]
+    );
+
+    // Changes of a dropped transaction are discarded
+    let tx = db.start_transaction();
+    let tx_id = tx.id();
+    db.insert(
+        [TEST_LEAF],
+        b"key5",
+        Element::Item(b"value5".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    assert_eq!(db.transaction_changes_count(tx_id), 1);
+    drop(tx);
+    assert_eq!(db.transaction_changes_count(tx_id), 0);
+
+    // Changes rolled back to a savepoint are not sent
+    let tx = db.start_transaction();
+    let ops = vec![GroveDbOp::Insert {
+        path: vec![TEST_LEAF.to_vec()],
+        key: b"key6".to_vec(),
+        element: Element::Item(b"value6".to_vec()),
+    }];
+    db.dry_run_batch(ops.clone(), Some(&tx)).expect("successful dry run");
+    db.validate_batch(ops, Some(&tx)).expect("successful validation");
+    assert_eq!(db.transaction_changes_count(tx.id()), 0);
+    assert!(tx.take_written_subtrees().is_empty());
+    db.commit_transaction(tx).expect("successful commit");
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn test_cached_grovedb() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut db = GroveDb::open(tmp_dir.path()).unwrap();
+    add_test_leafs(&mut db);
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let db = CachedGroveDb::new(db, 16).expect("successful cache creation");
+
+    let mut query = Query::new();
+    query.insert_all();
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    assert_eq!(
+        db.get([TEST_LEAF], b"key1").expect("successful get"),
+        Element::Item(b"value1".to_vec())
+    );
+    assert_eq!(
+        db.get_path_query(&path_query)
+            .expect("successful path query")
+            .0,
+        vec![b"value1".to_vec()]
+    );
+    // Repeated reads are served from the cache
+    assert_eq!(
+        db.get([TEST_LEAF], b"key1").expect("successful get"),
+        Element::Item(b"value1".to_vec())
+    );
+
+    // Commits invalidate cached results
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value2".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    assert_eq!(
+        db.get([TEST_LEAF], b"key1").expect("successful get"),
+        Element::Item(b"value2".to_vec())
+    );
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key2",
+        Element::Item(b"value3".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    assert_eq!(
+        db.get_path_query(&path_query)
+            .expect("successful path query")
+            .0,
+        vec![b"value2".to_vec()]
+    );
+    db.commit_transaction(tx).expect("successful commit");
+    assert_eq!(
+        db.get_path_query(&path_query)
+            .expect("successful path query")
+            .0,
+        vec![b"value2".to_vec(), b"value3".to_vec()]
+    );
+}
+
+#[test]
+fn test_query_memoization() {
+    let db = make_grovedb();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [TEST_LEAF],
+        b"reference",
+        Element::Reference(vec![ANOTHER_TEST_LEAF.to_vec(), b"key2".to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+
+    let mut query = Query::new();
+    query.insert_key(b"key1".to_vec());
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    let mut query = Query::new();
+    query.insert_all();
+    let all_path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+
+    for _ in 0..2 {
+        assert_eq!(
+            db.get_path_query(&path_query, None)
+                .expect("successful path query")
+                .0,
+            vec![b"value1".to_vec()]
+        );
+        assert_eq!(
+            db.get_path_query(&all_path_query, None)
+                .expect("successful path query")
+                .0,
+            vec![b"value1".to_vec(), b"value2".to_vec()]
+        );
+    }
+
+    // Results with references follow changes of referenced items
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key2",
+        Element::Item(b"value3".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    assert_eq!(
+        db.get_path_query(&all_path_query, None)
+            .expect("successful path query")
+            .0,
+        vec![b"value1".to_vec(), b"value3".to_vec()]
+    );
+
+    // Changes of the queried subtree change its root hash
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value4".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    assert_eq!(
+        db.get_path_query(&path_query, None)
+            .expect("successful path query")
+            .0,
+        vec![b"value4".to_vec()]
+    );
+}
+
+#[test]
+fn test_subtree_ids() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"subtree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"subtree"],
+        b"key",
+        Element::Item(b"ayy".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let id = db
+        .subtree_id([TEST_LEAF, b"subtree"], None)
+        .expect("successful subtree id");
+    assert_ne!(
+        id.to_vec(),
+        RocksDbStorage::build_prefix([TEST_LEAF, b"subtree"])
+    );
+    assert_ne!(
+        db.subtree_id([TEST_LEAF], None)
+            .expect("successful subtree id"),
+        id
+    );
+    // The root tree has no element, its identifier is derived on read
+    assert_eq!(
+        db.subtree_id([], None)
+            .expect("successful subtree id")
+            .to_vec(),
+        RocksDbStorage::build_prefix([])
+    );
+    assert!(matches!(
+        db.subtree_id([TEST_LEAF, b"missing"], None),
+        Err(Error::PathNotFound(_))
+    ));
+
+    // Data of the subtree is not stored under the path-derived prefix
+    let storage = db
+        .rocksdb()
+        .expect("RocksDB storage")
+        .get_storage_context([TEST_LEAF, b"subtree"]);
+    assert!(storage.get(b"key").expect("successful get").is_none());
+
+    // Inserting a tree over the subtree keeps it
+    db.insert([TEST_LEAF], b"subtree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    assert_eq!(
+        db.subtree_id([TEST_LEAF, b"subtree"], None)
+            .expect("successful subtree id"),
+        id
+    );
+    assert!(db.get([TEST_LEAF, b"subtree"], b"key", None).is_ok());
+
+    // A subtree created again at the same path gets a new identifier
+    db.delete([TEST_LEAF], b"subtree", None)
+        .expect("successful subtree delete");
+    db.insert([TEST_LEAF], b"subtree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    assert_ne!(
+        db.subtree_id([TEST_LEAF, b"subtree"], None)
+            .expect("successful subtree id"),
+        id
+    );
+    assert!(matches!(
+        db.get([TEST_LEAF, b"subtree"], b"key", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+}
+
+#[test]
+fn test_path_limits() {
+    let mut db = make_grovedb();
+    assert!(matches!(
+        db.insert(
+            [TEST_LEAF],
+            &[0; 256],
+            Element::Item(b"value".to_vec()),
+            None
+        ),
+        Err(Error::InvalidPath("path segment size limit exceeded"))
+    ));
+
+    let mut nested = Query::new();
+    nested.insert_all();
+    for _ in 0..100 {
+        let mut query = Query::new();
+        query.insert_all();
+        query.set_subquery(nested);
+        nested = query;
+    }
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], nested);
+    assert!(matches!(
+        db.get_path_query(&path_query, None),
+        Err(Error::InvalidQuery("subquery depth limit exceeded"))
+    ));
+
+    db.set_path_limits(PathLimits {
+        max_path_depth: 2,
+        ..Default::default()
+    });
+    db.insert([TEST_LEAF], b"subtree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    assert!(matches!(
+        db.insert(
+            [TEST_LEAF, b"subtree"],
+            b"key",
+            Element::Item(b"value".to_vec()),
+            None
+        ),
+        Err(Error::InvalidPath("path depth limit exceeded"))
+    ));
+    let mut query = Query::new();
+    query.insert_all();
+    assert!(matches!(
+        db.get_path_query(
+            &PathQuery::new_unsized(
+                vec![TEST_LEAF.to_vec(), b"subtree".to_vec(), b"nested".to_vec()],
+                query
+            ),
+            None
+        ),
+        Err(Error::InvalidPath("path depth limit exceeded"))
+    ));
+}
+
+#[test]
+fn test_query_cost_limit() {
+    let db = make_grovedb();
+    for i in 0..100u8 {
+        db.insert([TEST_LEAF], &[i], Element::Item(vec![i; 32]), None)
+            .expect("successful item insert");
+    }
+    let mut query = Query::new();
+    query.insert_all();
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    // Memoized results are not returned to metered queries without a charge
+    db.get_path_query(&path_query, None).expect("successful path query");
+
+    let ((results, _), cost) = db
+        .get_path_query_with_cost_limit(&path_query, u64::MAX, None)
+        .expect("successful path query");
+    assert_eq!(results.len(), 100);
+    assert_eq!(cost.seek_count, 100);
+    assert!(cost.loaded_bytes > 100 * 32);
+
+    match db.get_path_query_with_cost_limit(&path_query, cost.total() / 2, None) {
+        Err(Error::CostLimitExceeded(partial)) => {
+            assert!(partial.total() > cost.total() / 2);
+            assert!(partial.seek_count < cost.seek_count);
+        }
+        _ => panic!("expected cost limit to be exceeded"),
+    }
+
+    let (proof, proof_cost) = db
+        .prove_with_cost_limit(&[path_query.clone()], u64::MAX, None)
+        .expect("successful proof");
+    assert_eq!(
+        proof,
+        db.prove(&[path_query.clone()], None)
+            .expect("successful proof")
+    );
+    assert!(matches!(
+        db.prove_with_cost_limit(&[path_query], proof_cost.total() - 1, None),
+        Err(Error::CostLimitExceeded(_))
+    ));
+}
+
+#[test]
+fn test_insert_compressed() {
+    let document = b"{\"$type\":\"note\",\"message\":\"hello world\"}".repeat(100);
+    let db = make_grovedb();
+    let plain_db = make_grovedb();
+    assert!(matches!(
+        db.insert_compressed([TEST_LEAF], b"key", Element::Item(document.clone()), None),
+        Err(Error::FeatureNotEnabled(_))
+    ));
+    db.enable_feature(Feature::ItemCompression, None)
+        .expect("successful feature enabling");
+    assert!(matches!(
+        db.insert_compressed([TEST_LEAF], b"tree", Element::empty_tree(), None),
+        Err(Error::InvalidQuery(_))
+    ));
+
+    db.insert_compressed([TEST_LEAF], b"key", Element::Item(document.clone()), None)
+        .expect("successful compressed item insert");
+    plain_db
+        .insert([TEST_LEAF], b"key", Element::Item(document.clone()), None)
+        .expect("successful item insert");
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(document.clone())
+    );
+    assert_eq!(
+        db.root_hash(None).expect("successful root hash"),
+        plain_db.root_hash(None).expect("successful root hash")
+    );
+
+    let mut query = Query::new();
+    query.insert_all();
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    assert_eq!(
+        db.get_path_query(&path_query, None)
+            .expect("successful path query")
+            .0,
+        vec![document]
+    );
+    assert_eq!(
+        db.prove(&[path_query.clone()], None)
+            .expect("successful proof"),
+        plain_db
+            .prove(&[path_query], None)
+            .expect("successful proof")
+    );
+}
+
+#[test]
+fn test_insert_deduplicated() {
+    let document = b"{\"$type\":\"note\",\"message\":\"hello world\"}".repeat(100);
+    let hash = merk::tree::value_hash(&document);
+    let db = make_grovedb();
+    assert!(matches!(
+        db.insert_deduplicated([TEST_LEAF], b"key1", &document, None),
+        Err(Error::FeatureNotEnabled(_))
+    ));
+    db.enable_feature(Feature::ItemDeduplication, None)
+        .expect("successful feature enabling");
+    assert!(matches!(
+        db.insert([TEST_LEAF], b"key1", Element::DedupItem(hash), None),
+        Err(Error::InvalidQuery(_))
+    ));
+
+    db.insert([TEST_LEAF], b"tree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert_deduplicated([TEST_LEAF], b"key1", &document, None)
+        .expect("successful deduplicated insert");
+    db.insert_deduplicated([TEST_LEAF], b"key2", &document, None)
+        .expect("successful deduplicated insert");
+    db.insert_deduplicated([TEST_LEAF, b"tree"], b"key3", &document, None)
+        .expect("successful deduplicated insert");
+    assert_eq!(
+        db.dedup_reference_count(&hash, None)
+            .expect("successful reference count"),
+        3
+    );
+    assert_eq!(
+        db.get([TEST_LEAF], b"key1", None).expect("successful get"),
+        Element::Item(document.clone())
+    );
+    let mut query = Query::new();
+    query.insert_range(b"key1".to_vec()..b"key3".to_vec());
+    let path_query = PathQuery::new_unsized(vec![TEST_LEAF.to_vec()], query);
+    assert_eq!(
+        db.get_path_query(&path_query, None)
+            .expect("successful path query")
+            .0,
+        vec![document.clone(), document.clone()]
+    );
+    assert_eq!(
+        db.get_path_query_raw(&path_query, None)
+            .expect("successful path query")
+            .0,
+        vec![Element::DedupItem(hash), Element::DedupItem(hash)]
+    );
+
+    // Proofs carry deduplicated values, which are resolved on verification
+    let proof = db
+        .prove(&[path_query.clone()], None)
+        .expect("successful proof generation");
+    let (_, results) = GroveDb::execute_proof(&proof).expect("successful proof execution");
+    let element_bytes = results[&path_query.path]
+        .get(b"key1")
+        .expect("key should be proved")
+        .expect("key should exist");
+    assert_eq!(
+        bincode::deserialize::<Element>(element_bytes).expect("successful deserialization"),
+        Element::Item(document.clone())
+    );
+
+    // Values are exported for state sync and references are added on import
+    assert_eq!(
+        db.export_dedup_values([TEST_LEAF], None)
+            .expect("successful export"),
+        vec![document.clone()]
+    );
+    db.import_dedup_values([TEST_LEAF, b"tree"], &[document.clone()], None)
+        .expect("successful import");
+    assert_eq!(
+        db.dedup_reference_count(&hash, None)
+            .expect("successful reference count"),
+        4
+    );
+
+    // Importing values of the same subtree again adds no references
+    db.import_dedup_values([TEST_LEAF, b"tree"], &[document.clone()], None)
+        .expect("successful import");
+    assert_eq!(
+        db.dedup_reference_count(&hash, None)
+            .expect("successful reference count"),
+        4
+    );
+    db.release_dedup_item(Some(&Element::DedupItem(hash)), None)
+        .expect("successful release");
+
+    // Replacing and deleting items release their values
+    db.insert([TEST_LEAF], b"key1", Element::Item(b"small".to_vec()), None)
+        .expect("successful item insert");
+    db.delete([TEST_LEAF], b"key2", None)
+        .expect("successful delete");
+    assert_eq!(
+        db.dedup_reference_count(&hash, None)
+            .expect("successful reference count"),
+        1
+    );
+    db.delete([TEST_LEAF], b"tree", None)
+        .expect("successful subtree delete");
+    assert_eq!(
+        db.dedup_reference_count(&hash, None)
+            .expect("successful reference count"),
+        0
+    );
+}
+
+#[test]
+fn test_export_root_layer() {
+    let tmp_dir = TempDir::new().unwrap();
+    let empty_db = GroveDb::open(tmp_dir.path()).expect("successful open");
+    let root_layer =
+        GroveDb::verify_root_layer(&empty_db.export_root_layer(None).expect("successful export"))
+            .expect("successful verification");
+    assert_eq!(root_layer.root_hash, None);
+    assert!(root_layer.subtrees.is_empty());
+
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+        .expect("successful item insert");
+    let blob = db.export_root_layer(None).expect("successful export");
+    let root_layer = GroveDb::verify_root_layer(&blob).expect("successful verification");
+    assert_eq!(
+        root_layer.root_hash,
+        db.root_hash(None).expect("successful root hash")
+    );
+    assert_eq!(
+        root_layer.subtrees.keys().cloned().collect::<Vec<_>>(),
+        vec![TEST_LEAF.to_vec(), ANOTHER_TEST_LEAF.to_vec()]
+    );
+    assert_eq!(
+        Element::Tree(root_layer.subtrees[TEST_LEAF.as_slice()]),
+        db.get([], TEST_LEAF, None).expect("successful get")
+    );
+
+    let mut tampered = blob.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    assert!(GroveDb::verify_root_layer(&tampered).map_or(true, |layer| layer != root_layer));
+    assert!(GroveDb::verify_root_layer(&[]).is_err());
+}
+
+#[test]
+fn test_sync_handshake() {
+    let db = make_grovedb();
+    let handshake = db.sync_handshake().expect("successful handshake");
+    assert_eq!(handshake, SyncHandshake::default());
+
+    db.enable_feature(Feature::ItemCompression, None)
+        .expect("successful feature enabling");
+    let with_compression = db.sync_handshake().expect("successful handshake");
+    assert!(handshake.negotiate(&with_compression).is_err());
+    let agreed = with_compression
+        .negotiate(
+            &SyncHandshake::default()
+                .with_application_capability(Feature::ItemCompression.flag(), false),
+        )
+        .expect("successful negotiation");
+    assert_eq!(agreed.required, with_compression.required);
+
+    db.enable_feature(Feature::ProofCompression, None)
+        .expect("successful feature enabling");
+    let with_proof_compression = db.sync_handshake().expect("successful handshake");
+    assert_eq!(with_proof_compression.required, with_compression.required);
+    assert!(with_proof_compression.negotiate(&with_compression).is_ok());
+}
+
+#[test]
+fn test_subtree_height_and_proof_depth() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"innertree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    assert_eq!(
+        db.subtree_height([TEST_LEAF, b"innertree"], None)
+            .expect("successful height"),
+        0
+    );
+    for i in 0u32..100 {
+        db.insert(
+            [TEST_LEAF, b"innertree"],
+            &i.to_be_bytes(),
+            Element::Item(vec![]),
+            None,
+        )
+        .expect("successful item insert");
+    }
+
+    let root_height = db.subtree_height([], None).expect("successful height");
+    let leaf_height = db
+        .subtree_height([TEST_LEAF], None)
+        .expect("successful height");
+    let inner_height = db
+        .subtree_height([TEST_LEAF, b"innertree"], None)
+        .expect("successful height");
+    assert_eq!(root_height, 2);
+    assert_eq!(leaf_height, 1);
+    assert_eq!(
+        inner_height,
+        db.subtree_balance_info([TEST_LEAF, b"innertree"], None)
+            .expect("successful balance info")
+            .height
+    );
+    assert_eq!(
+        db.worst_case_proof_depth([TEST_LEAF, b"innertree"], None)
+            .expect("successful proof depth"),
+        (root_height + leaf_height + inner_height) as usize
+    );
+
+    assert!(matches!(
+        db.worst_case_proof_depth([TEST_LEAF, b"missing"], None),
+        Err(Error::PathNotFound(_))
+    ));
+}
+
+#[test]
+fn test_flush_policy_and_flush_cf() {
+    struct FlushListener(std::sync::Arc<std::sync::Mutex<Vec<Option<ColumnFamily>>>>);
+
+    impl MaintenanceEvents for FlushListener {
+        fn on_flush_completed(&self, info: &FlushInfo) {
+            self.0.lock().unwrap().push(info.column_family);
+        }
+    }
+
+    let mut db = make_grovedb();
+    let flushes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    db.register_maintenance_events(Box::new(FlushListener(flushes.clone())));
+    let flushed_count = |cf: Option<ColumnFamily>| {
+        flushes.lock().unwrap().iter().filter(|x| **x == cf).count()
+    };
+    let flush_count = || flushed_count(None);
+    let commit_insert = |db: &GroveDb, key: &[u8]| {
+        let tx = db.start_transaction();
+        db.insert([TEST_LEAF], key, Element::Item(vec![]), Some(&tx))
+            .expect("successful insert");
+        db.commit_transaction(tx).expect("successful commit");
+    };
+
+    assert_eq!(db.flush_policy(), FlushPolicy::Manual);
+    commit_insert(&db, b"key1");
+    assert_eq!(flush_count(), 0);
+
+    db.set_flush_policy(FlushPolicy::OnCommit);
+    commit_insert(&db, b"key2");
+    assert_eq!(flush_count(), 1);
+
+    db.set_flush_policy(FlushPolicy::Periodic(std::time::Duration::from_secs(3600)));
+    commit_insert(&db, b"key3");
+    assert_eq!(flush_count(), 1);
+    db.set_flush_policy(FlushPolicy::Periodic(std::time::Duration::ZERO));
+    commit_insert(&db, b"key4");
+    assert_eq!(flush_count(), 2);
+
+    // Writes made outside of a transaction are checked as well
+    db.insert([TEST_LEAF], b"key5", Element::Item(vec![]), None)
+        .expect("successful insert");
+    assert_eq!(flush_count(), 3);
+
+    db.set_flush_policy(FlushPolicy::Manual);
+    db.insert([TEST_LEAF], b"key6", Element::Item(vec![]), None)
+        .expect("successful insert");
+    db.flush_cf(ColumnFamily::Roots).expect("successful flush");
+    assert_eq!(flushed_count(Some(ColumnFamily::Roots)), 1);
+    assert_eq!(flush_count(), 3);
+    assert_eq!(
+        db.get([TEST_LEAF], b"key4", None).expect("successful get"),
+        Element::Item(vec![])
+    );
+}
+
+#[test]
+fn test_open_with_recovery() {
+    let tmp_dir = TempDir::new().unwrap();
+    {
+        let db = GroveDb::open(tmp_dir.path()).unwrap();
+        db.insert([], TEST_LEAF, Element::empty_tree(), None)
+            .expect("successful root tree leaf insert");
+        db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+            .expect("successful item insert");
+    }
+    for mode in [
+        rocksdb_storage::WalRecoveryMode::AbsoluteConsistency,
+        rocksdb_storage::WalRecoveryMode::TolerateCorruptedTail,
+        rocksdb_storage::WalRecoveryMode::PointInTime,
+        rocksdb_storage::WalRecoveryMode::SkipCorrupted,
+    ] {
+        let db = GroveDb::open_with_recovery(tmp_dir.path(), mode).expect("successful open");
+        assert_eq!(
+            db.get([TEST_LEAF], b"key", None).expect("successful get"),
+            Element::Item(b"value".to_vec())
+        );
+    }
+
+    // A corrupted node is detected although hashes stored in links and parent
+    // elements are intact
+    {
+        let db = GroveDb::open(tmp_dir.path()).unwrap();
+        let storage = db.db.get_storage_context([TEST_LEAF]);
+        let mut node = storage
+            .get(b"key")
+            .expect("successful node read")
+            .expect("node exists");
+        let last = node.len() - 1;
+        node[last] ^= 1;
+        storage.put(b"key", &node).expect("successful node write");
+    }
+    assert!(matches!(
+        GroveDb::open_with_recovery(
+            tmp_dir.path(),
+            rocksdb_storage::WalRecoveryMode::PointInTime
+        ),
+        Err(Error::CorruptedData(_))
+    ));
+}
+
+#[test]
+fn test_freeze_subtree() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"contract", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"contract"],
+        b"inner",
+        Element::empty_tree(),
+        None,
+    )
+    .expect("successful subtree insert");
+    db.insert(
+        [TEST_LEAF, b"contract", b"inner"],
+        b"key",
+        Element::Item(b"value".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+
+    db.freeze_subtree([TEST_LEAF, b"contract"])
+        .expect("successful freeze");
+    assert!(db
+        .is_subtree_frozen([TEST_LEAF, b"contract", b"inner"])
+        .expect("successful frozen check"));
+    assert!(!db
+        .is_subtree_frozen([TEST_LEAF])
+        .expect("successful frozen check"));
+
+    assert!(matches!(
+        db.insert(
+            [TEST_LEAF, b"contract", b"inner"],
+            b"key",
+            Element::Item(b"changed".to_vec()),
+            None
+        ),
+        Err(Error::SubtreeFrozen)
+    ));
+    assert!(matches!(
+        db.delete([TEST_LEAF, b"contract", b"inner"], b"key", None),
+        Err(Error::SubtreeFrozen)
+    ));
+    assert!(matches!(
+        db.delete([TEST_LEAF], b"contract", None),
+        Err(Error::SubtreeFrozen)
+    ));
+    // Ancestors of a frozen subtree can't be deleted or replaced either
+    assert!(matches!(
+        db.delete([], TEST_LEAF, None),
+        Err(Error::SubtreeFrozen)
+    ));
+    assert!(matches!(
+        db.insert([], TEST_LEAF, Element::empty_tree(), None),
+        Err(Error::SubtreeFrozen)
+    ));
+    assert!(matches!(
+        db.insert([TEST_LEAF], b"contract", Element::Item(vec![]), None),
+        Err(Error::SubtreeFrozen)
+    ));
+    assert!(matches!(
+        db.rebuild_subtree([TEST_LEAF, b"contract"], None),
+        Err(Error::SubtreeFrozen)
+    ));
+    assert_eq!(
+        db.get([TEST_LEAF, b"contract", b"inner"], b"key", None)
+            .expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+
+    // Siblings of a frozen subtree can be changed
+    db.insert([TEST_LEAF], b"other", Element::Item(vec![]), None)
+        .expect("successful item insert");
+    assert!(matches!(
+        db.freeze_subtree([TEST_LEAF, b"missing"]),
+        Err(Error::PathNotFound(_))
+    ));
+}
+
+#[test]
+fn test_access_policy() {
+    /// Lets callers change only subtrees under the leaf named as the caller
+    struct TenantPolicy;
+
+    impl AccessPolicy for TenantPolicy {
+        fn allows(&self, path: &[&[u8]], key: &[u8], kind: MutationKind, caller: &[u8]) -> bool {
+            kind != MutationKind::Aux && path.first().copied().unwrap_or(key) == caller
+        }
+    }
+
+    let mut db = make_grovedb();
+    db.set_access_policy(Box::new(TenantPolicy));
+
+    GroveDb::with_caller_context(TEST_LEAF, || {
+        db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+    })
+    .expect("successful item insert");
+    assert!(matches!(
+        GroveDb::with_caller_context(ANOTHER_TEST_LEAF, || {
+            db.insert(
+                [TEST_LEAF],
+                b"key",
+                Element::Item(b"changed".to_vec()),
+                None,
+            )
+        }),
+        Err(Error::AccessDenied)
+    ));
+    assert!(matches!(
+        GroveDb::with_caller_context(ANOTHER_TEST_LEAF, || db.delete([TEST_LEAF], b"key", None)),
+        Err(Error::AccessDenied)
+    ));
+    // Outside of a scope the caller context is empty
+    assert!(matches!(
+        db.delete([TEST_LEAF], b"key", None),
+        Err(Error::AccessDenied)
+    ));
+    assert!(matches!(
+        db.put_aux(b"aux", b"value", None),
+        Err(Error::AccessDenied)
+    ));
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+
+    // Operations of a batch are checked one by one
+    let ops = vec![GroveDbOp::Insert {
+        path: vec![TEST_LEAF.to_vec()],
+        key: b"batched".to_vec(),
+        element: Element::Item(vec![]),
+    }];
+    assert!(matches!(
+        GroveDb::with_caller_context(ANOTHER_TEST_LEAF, || db.apply_batch(ops.clone(), None)),
+        Err(Error::AccessDenied)
+    ));
+    GroveDb::with_caller_context(TEST_LEAF, || db.apply_batch(ops, None))
+        .expect("successful batch");
+
+    // Whole subtree mutations are checked before anything is written
+    assert!(matches!(
+        GroveDb::with_caller_context(ANOTHER_TEST_LEAF, || {
+            db.load_subtree(
+                [TEST_LEAF],
+                DumpFormat::Json,
+                br#"{"6c6f61646564": {"type": "item", "value": "00"}}"#.as_slice(),
+                None,
+            )
+        }),
+        Err(Error::AccessDenied)
+    ));
+    assert!(matches!(
+        db.get([TEST_LEAF], b"loaded", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    assert!(matches!(
+        GroveDb::with_caller_context(ANOTHER_TEST_LEAF, || db.collect_garbage()),
+        Err(Error::AccessDenied)
+    ));
+    assert!(matches!(
+        db.enable_feature(Feature::PrunedTrees, None),
+        Err(Error::AccessDenied)
+    ));
+    assert!(!db
+        .is_feature_enabled(Feature::PrunedTrees)
+        .expect("successful version read"));
+}
+
+#[test]
+fn test_tenant_view() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"tenant", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    let view = db
+        .tenant_view([TEST_LEAF, b"tenant"], None)
+        .expect("successful tenant view");
+
+    view.insert([], b"inner", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    view.insert(
+        [b"inner".as_ref()],
+        b"key",
+        Element::Item(b"value".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    view.insert(
+        [],
+        b"ref",
+        Element::Reference(vec![b"inner".to_vec(), b"key".to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+
+    assert_eq!(
+        db.get([TEST_LEAF, b"tenant", b"inner"], b"key", None)
+            .expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+    assert_eq!(
+        view.get([], b"ref", None).expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+    assert!(!view
+        .is_empty_tree([b"inner".as_ref()], None)
+        .expect("successful empty check"));
+
+    let mut query = Query::new();
+    query.insert_all();
+    let path_query = PathQuery::new_unsized(vec![b"inner".to_vec()], query);
+    let (values, _) = view
+        .get_path_query(&path_query, None)
+        .expect("successful path query");
+    assert_eq!(values, vec![b"value".to_vec()]);
+    let results = view
+        .query_many(vec![path_query.clone(), path_query.clone()], None)
+        .expect("successful queries");
+    assert_eq!(results.len(), 2);
+    assert!(results
+        .iter()
+        .all(|(values, _)| values == &[b"value".to_vec()]));
+
+    // Proofs are verified against the GroveDB root hash
+    let proof = view
+        .prove(&[path_query], None)
+        .expect("successful proof generation");
+    let (root_hash, results) = GroveDb::execute_proof(&proof).expect("successful proof execution");
+    assert_eq!(Some(root_hash), db.root_hash(None).unwrap());
+    assert!(results.contains_key(&vec![
+        TEST_LEAF.to_vec(),
+        b"tenant".to_vec(),
+        b"inner".to_vec()
+    ]));
+
+    let tenant_element = db
+        .get([TEST_LEAF], b"tenant", None)
+        .expect("successful get");
+    assert_eq!(
+        tenant_element,
+        Element::Tree(view.root_hash(None).expect("successful root hash"))
+    );
+
+    // References leading out of the view are not followed, even if they were
+    // inserted around it
+    db.insert(
+        [TEST_LEAF],
+        b"secret",
+        Element::Item(b"secret".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [TEST_LEAF, b"tenant"],
+        b"escape",
+        Element::Reference(vec![TEST_LEAF.to_vec(), b"secret".to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+    assert!(matches!(
+        view.get([], b"escape", None),
+        Err(Error::ReferenceOutOfView)
+    ));
+    let mut query = Query::new();
+    query.insert_key(b"escape".to_vec());
+    assert!(matches!(
+        view.get_path_query(&PathQuery::new_unsized(vec![], query), None),
+        Err(Error::ReferenceOutOfView)
+    ));
+
+    // Batches are rooted at the view
+    view.apply_batch(
+        vec![
+            GroveDbOp::Insert {
+                path: vec![b"inner".to_vec()],
+                key: b"batched".to_vec(),
+                element: Element::Item(b"batched".to_vec()),
+            },
+            GroveDbOp::Delete {
+                path: vec![],
+                key: b"escape".to_vec(),
+            },
+        ],
+        None,
+    )
+    .expect("successful batch");
+    assert_eq!(
+        view.get([b"inner".as_ref()], b"batched", None)
+            .expect("successful get"),
+        Element::Item(b"batched".to_vec())
+    );
+
+    // Auxiliary data of the view has a key space of its own
+    view.put_aux(b"aux", b"value", None)
+        .expect("successful aux put");
+    assert_eq!(
+        view.get_aux(b"aux", None).expect("successful aux get"),
+        Some(b"value".to_vec())
+    );
+    assert_eq!(db.get_aux(b"aux", None).expect("successful aux get"), None);
+    view.delete_aux(b"aux", None)
+        .expect("successful aux delete");
+    assert_eq!(
+        view.get_aux(b"aux", None).expect("successful aux get"),
+        None
+    );
+
+    view.delete([b"inner".as_ref()], b"key", None)
+        .expect("successful delete");
+    view.delete([], b"ref", None).expect("successful delete");
+
+    // Emptied subtrees are deleted up to the root subtree of the view
+    assert_eq!(
+        view.delete_up_tree_while_empty([b"inner".as_ref()], b"batched", None, None)
+            .expect("successful delete"),
+        2
+    );
+    assert!(view
+        .is_empty_tree([], None)
+        .expect("successful empty check"));
+
+    assert!(matches!(
+        db.tenant_view([TEST_LEAF, b"missing"], None),
+        Err(Error::TenantRootNotFound)
+    ));
+    // A tenant root created in a transaction is seen within it only
+    let tx = db.start_transaction();
+    db.insert([TEST_LEAF], b"other", Element::empty_tree(), Some(&tx))
+        .expect("successful subtree insert");
+    assert!(matches!(
+        db.tenant_view([TEST_LEAF, b"other"], None),
+        Err(Error::TenantRootNotFound)
+    ));
+    db.tenant_view([TEST_LEAF, b"other"], Some(&tx))
+        .expect("successful tenant view");
+    drop(tx);
+
+    // Operations fail once the root subtree is deleted
+    db.delete([TEST_LEAF], b"tenant", None)
+        .expect("successful delete");
+    assert!(matches!(
+        view.get([], b"ref", None),
+        Err(Error::TenantRootNotFound)
+    ));
+    assert!(matches!(
+        view.insert([], b"key", Element::Item(vec![]), None),
+        Err(Error::TenantRootNotFound)
+    ));
+}
+
+#[test]
+fn test_parse_path_query() {
+    let db = make_grovedb();
+    db.insert([TEST_LEAF], b"inner", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    for key in [b"a", b"b", b"c", b"d"] {
+        db.insert(
+            [TEST_LEAF, b"inner"],
+            key,
+            Element::Item(key.to_vec()),
+            None,
+        )
+        .expect("successful item insert");
+    }
+
+    let path_query: PathQuery = "path: test_leaf/inner, range: [b, d), limit 10, desc"
+        .parse()
+        .expect("valid query");
+    assert_eq!(
+        db.get_path_query(&path_query, None)
+            .expect("successful path query")
+            .0,
+        vec![b"c".to_vec(), b"b".to_vec()]
+    );
+
+    let path_query: PathQuery = "path: 0x746573745f6c656166/inner, range: (a, ], key: a, offset 1"
+        .parse()
+        .expect("valid query");
+    assert_eq!(
+        db.get_path_query(&path_query, None)
+            .expect("successful path query")
+            .0,
+        vec![b"b".to_vec(), b"c".to_vec(), b"d".to_vec()]
+    );
+
+    let path_query: PathQuery = "path: test_leaf/inner, range: (, b]"
+        .parse()
+        .expect("valid query");
+    assert_eq!(
+        db.get_path_query(&path_query, None)
+            .expect("successful path query")
+            .0,
+        vec![b"a".to_vec(), b"b".to_vec()]
+    );
+    assert!("path: /, all".parse::<PathQuery>().is_ok());
+
+    for invalid in [
+        "path: test_leaf",
+        "path: test_leaf, range: [a, b",
+        "path: test_leaf, range: a, b",
+        "path: test_leaf, all, limit ten",
+        "path: test_leaf, all, order desc",
+        "path: 0xzz, all",
+    ] {
+        assert!(
+            matches!(invalid.parse::<PathQuery>(), Err(Error::InvalidQuery(_))),
+            "{} should be invalid",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_path_display() {
+    let path: Vec<&[u8]> = vec![b"contracts", &[0xde, 0xad], b"0xname"];
+    assert_eq!(
+        PathDisplay::new(path.iter().copied()).to_string(),
+        "/contracts/0xdead/0x30786e616d65"
+    );
+    assert_eq!(PathDisplay::new(std::iter::empty()).to_string(), "/");
+    assert_eq!(
+        PathDisplay::new(path.iter().copied())
+            .with_encoding(ByteEncoding::Hex)
+            .to_string(),
+        "/0x636f6e747261637473/0xdead/0x30786e616d65"
+    );
+    assert_eq!(
+        BytesDisplay::new(b"hello world")
+            .with_encoding(ByteEncoding::Base58)
+            .to_string(),
+        "StV1DL6CwTryKyV"
+    );
+    assert_eq!(BytesDisplay::new(b"a/b").to_string(), "0x612f62");
+    assert_eq!(BytesDisplay::new(b"").to_string(), "0x");
+
+    // Displayed paths are accepted by the textual query syntax
+    let path_query: PathQuery = format!("path: {}, all", PathDisplay::new(path.iter().copied()))
+        .parse()
+        .expect("valid query");
+    assert_eq!(
+        path_query.path(),
+        path.iter().map(|x| x.to_vec()).collect::<Vec<_>>()
+    );
+
+    assert_eq!(
+        format!("{:?}", Element::Reference(vec![b"a".to_vec(), vec![0]])),
+        "Reference(/a/0x00)"
+    );
+    let db = make_grovedb();
+    let error = db.get([TEST_LEAF], b"missing", None).unwrap_err();
+    assert!(error.to_string().contains("missing"), "{}", error);
+}
+
+#[test]
+fn test_slow_operation_threshold() {
+    let mut db = make_grovedb();
+    assert_eq!(db.slow_operation_threshold(), None);
+    // Every operation is logged with a zero threshold, which must not change
+    // its results
+    db.set_slow_operation_threshold(Some(std::time::Duration::ZERO));
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+        .expect("successful item insert");
+    assert_eq!(
+        db.get([TEST_LEAF], b"key", None).expect("successful get"),
+        Element::Item(b"value".to_vec())
+    );
+    let path_query: PathQuery = "path: test_leaf, all".parse().expect("valid query");
+    let (values, _) = db
+        .get_path_query(&path_query, None)
+        .expect("successful query");
+    assert_eq!(values, vec![b"value".to_vec()]);
+    let proof = db.prove(&[path_query], None).expect("successful prove");
+    let (root_hash, _) = GroveDb::execute_proof(&proof).expect("valid proof");
+    assert_eq!(Some(root_hash), db.root_hash(None).unwrap());
+    assert!(matches!(
+        db.get([TEST_LEAF], b"missing", None),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    db.delete([TEST_LEAF], b"key", None)
+        .expect("successful delete");
+    db.set_slow_operation_threshold(None);
+    assert_eq!(db.slow_operation_threshold(), None);
+}
+
+#[test]
+fn test_with_perf() {
+    let db = make_grovedb();
+    let ((), report) = db.with_perf(|db| {
+        for i in 0u8..10 {
+            db.insert([TEST_LEAF], &[i], Element::Item(vec![i; 100]), None)
+                .expect("successful item insert");
+        }
+    });
+    assert!(report.duration > std::time::Duration::ZERO);
+
+    db.flush().expect("successful flush");
+    let (element, report) = db.with_perf(|db| db.get([TEST_LEAF], &[5], None));
+    assert_eq!(
+        element.expect("successful get"),
+        Element::Item(vec![5; 100])
+    );
+    assert!(report.read_bytes > 0);
+    assert!(report.block_cache_hit_count + report.block_read_count > 0);
+}
+
+#[test]
+fn test_query_cancellation() {
+    let db = make_grovedb();
+    for i in 0u8..200 {
+        db.insert([TEST_LEAF], &[i], Element::Item(vec![i]), None)
+            .expect("successful item insert");
+    }
+    let path_query: PathQuery = "path: test_leaf, all".parse().expect("valid query");
+
+    let cancellation = CancellationToken::new();
+    let options = QueryOptions {
+        cancellation: Some(&cancellation),
+        ..Default::default()
+    };
+    let (values, _) = db
+        .get_path_query_with_options(&path_query, options, None)
+        .expect("successful path query");
+    assert_eq!(values.len(), 200);
+    db.prove_with_cancellation(&[path_query.clone()], &cancellation, None)
+        .expect("successful prove");
+
+    cancellation.cancel();
+    assert!(cancellation.is_cancelled());
+    // Clones share the cancellation
+    let cloned = cancellation.clone();
+    let options = QueryOptions {
+        cancellation: Some(&cloned),
+        ..Default::default()
+    };
+    assert!(matches!(
+        db.get_path_query_with_options(&path_query, options, None),
+        Err(Error::Cancelled)
+    ));
+    assert!(matches!(
+        db.prove_with_cancellation(&[path_query], &cancellation, None),
+        Err(Error::Cancelled)
+    ));
+}
+
+#[test]
+fn test_max_concurrent_queries() {
+    let mut db = make_grovedb();
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value".to_vec()), None)
+        .expect("successful item insert");
+    let path_query: PathQuery = "path: test_leaf, all".parse().expect("valid query");
+
+    db.set_max_concurrent_queries(Some(1), LockWait::FailFast);
+    let slot = db.acquire_query_slot().expect("free query slot");
+    // Queries made by the slot holder don't need another slot
+    db.get_path_query_raw(&path_query, None)
+        .expect("successful path query");
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            assert!(matches!(
+                db.get_path_query_raw(&path_query, None),
+                Err(Error::TooManyQueries)
+            ));
+            assert!(matches!(
+                db.prove(&[path_query.clone()], None),
+                Err(Error::TooManyQueries)
+            ));
+        });
+    });
+    drop(slot);
+    db.prove(&[path_query.clone()], None)
+        .expect("successful prove");
+
+    db.set_max_concurrent_queries(Some(1), LockWait::Block);
+    let slot = db.acquire_query_slot().expect("free query slot");
+    std::thread::scope(|s| {
+        let waiting = s.spawn(|| db.get_path_query_raw(&path_query, None));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!waiting.is_finished());
+        drop(slot);
+        let (elements, _) = waiting
+            .join()
+            .expect("query thread doesn't panic")
+            .expect("successful path query");
+        assert_eq!(elements, vec![Element::Item(b"value".to_vec())]);
+    });
+}
+
+#[test]
+fn test_aux_cost_meter() {
+    let db = make_grovedb();
+    let meter = CostMeter::new(u64::MAX);
+    db.put_aux_with_cost_meter(b"key", b"value", &meter, None)
+        .expect("successful aux put");
+    assert_eq!(
+        meter.cost(),
+        QueryCost {
+            seek_count: 0,
+            loaded_bytes: 0,
+            written_bytes: 8,
+        }
+    );
+    assert_eq!(
+        db.get_aux_with_cost_meter(b"key", &meter, None)
+            .expect("successful aux get"),
+        Some(b"value".to_vec())
+    );
+    assert_eq!(meter.cost().seek_count, 1);
+    assert_eq!(meter.cost().loaded_bytes, 8);
+    db.apply_aux_batch_with_cost_meter(
+        vec![
+            AuxOp::Put {
+                key: b"a".to_vec(),
+                value: b"b".to_vec(),
+            },
+            AuxOp::Delete {
+                key: b"key".to_vec(),
+            },
+        ],
+        &meter,
+        None,
+    )
+    .expect("successful aux batch");
+    db.delete_aux_with_cost_meter(b"a", &meter, None)
+        .expect("successful aux delete");
+    assert_eq!(meter.cost().written_bytes, 8 + 2 + 3 + 1);
+
+    // Nothing is written once the limit is exceeded
+    let meter = CostMeter::new(4);
+    assert!(matches!(
+        db.put_aux_with_cost_meter(b"key", b"value", &meter, None),
+        Err(Error::CostLimitExceeded(_))
+    ));
+    assert_eq!(db.get_aux(b"key", None).expect("successful aux get"), None);
+}
+
+#[test]
+fn test_estimated_case_cost_for_op() {
+    let small = EstimatedLayerInfo {
+        element_count: 3,
+        average_key_size: 8,
+        average_value_size: 16,
+    };
+    let large = EstimatedLayerInfo {
+        element_count: 1_000_000,
+        ..small
+    };
+    let op = GroveDbOp::Insert {
+        path: vec![TEST_LEAF.to_vec()],
+        key: b"key".to_vec(),
+        element: Element::Item(vec![0; 100]),
+    };
+    let small_cost =
+        GroveDb::estimated_case_cost_for_op(&op, &[small, small]).expect("valid layer info");
+    let large_cost =
+        GroveDb::estimated_case_cost_for_op(&op, &[small, large]).expect("valid layer info");
+    // A balanced tree of 3 elements has 2 levels and of a million has 20
+    assert_eq!(small_cost.seek_count, 4);
+    assert_eq!(large_cost.seek_count, 22);
+    assert!(large_cost.total() > small_cost.total());
+    assert_eq!(small_cost.loaded_bytes, small_cost.written_bytes);
+
+    let delete = GroveDbOp::Delete {
+        path: vec![TEST_LEAF.to_vec()],
+        key: b"key".to_vec(),
+    };
+    let delete_cost =
+        GroveDb::estimated_case_cost_for_op(&delete, &[small, small]).expect("valid layer info");
+    assert!(delete_cost.total() < small_cost.total());
+    assert!(matches!(
+        GroveDb::estimated_case_cost_for_op(&op, &[small]),
+        Err(Error::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn test_verify_cost_determinism() {
+    let mut ops = vec![GroveDbOp::Insert {
+        path: vec![],
+        key: b"tree".to_vec(),
+        element: Element::empty_tree(),
+    }];
+    for i in 0u8..20 {
+        ops.push(GroveDbOp::Insert {
+            path: vec![b"tree".to_vec()],
+            key: vec![i],
+            element: Element::Item(vec![i; i as usize]),
+        });
+    }
+    ops.push(GroveDbOp::Insert {
+        path: vec![b"tree".to_vec()],
+        key: vec![5],
+        element: Element::Item(b"replaced".to_vec()),
+    });
+    ops.push(GroveDbOp::Delete {
+        path: vec![b"tree".to_vec()],
+        key: vec![10],
+    });
+    ops.push(GroveDbOp::Delete {
+        path: vec![],
+        key: b"tree".to_vec(),
+    });
+
+    let reports = costs::verify_determinism(&ops).expect("deterministic costs within bounds");
+    assert_eq!(reports.len(), ops.len());
+    assert!(reports.iter().all(|report| report.actual.written_bytes > 0));
+    assert!(reports[..reports.len() - 1]
+        .iter()
+        .all(|report| report.bounded));
+    assert!(!reports[reports.len() - 1].bounded);
+    assert!(matches!(
+        costs::verify_determinism(&[GroveDbOp::Delete {
+            path: vec![b"missing".to_vec()],
+            key: b"key".to_vec(),
+        }]),
+        Err(Error::PathNotFound(_))
+    ));
+}
+
+#[test]
+fn test_get_node_raw() {
+    let db = make_grovedb();
+    for i in 0u8..7 {
+        db.insert([TEST_LEAF], &[i], Element::Item(vec![i]), None)
+            .expect("successful item insert");
+    }
+    let root_key = db
+        .get_root_node_key([TEST_LEAF], None)
+        .expect("successful root key get")
+        .expect("subtree is not empty");
+    let encoded = db
+        .get_node_raw([TEST_LEAF], &root_key, None)
+        .expect("successful node get")
+        .expect("root node exists");
+    let root = merk::tree::Tree::decode(root_key.clone(), &encoded).expect("valid node");
+    assert_eq!(
+        db.get([], TEST_LEAF, None).expect("successful get"),
+        Element::Tree(root.hash())
+    );
+    assert_eq!(
+        subtree::raw_decode(root.value()).expect("valid element"),
+        Element::Item(root_key.clone())
+    );
+
+    // Child links lead to other nodes
+    let left = root.link(true).expect("root has a left child");
+    let left_node = db
+        .get_node_raw([TEST_LEAF], left.key(), None)
+        .expect("successful node get")
+        .expect("child node exists");
+    assert_eq!(
+        &merk::tree::Tree::decode(left.key().to_vec(), &left_node)
+            .expect("valid node")
+            .hash(),
+        left.hash()
+    );
+
+    assert_eq!(
+        db.get_node_raw([TEST_LEAF], b"missing", None)
+            .expect("successful node get"),
+        None
+    );
+    assert_eq!(
+        db.get_root_node_key([ANOTHER_TEST_LEAF], None)
+            .expect("successful root key get"),
+        None
+    );
+    assert!(matches!(
+        db.get_node_raw([b"missing".as_ref()], b"key", None),
+        Err(Error::PathNotFound(_))
+    ));
+}
+
+#[test]
+fn test_fixture_tree_builder() {
+    use crate::fixtures::TreeBuilder;
+
+    let fixture = TreeBuilder::new()
+        .tree("a", |t| {
+            t.item("k1", b"v1")
+                .reference("r", [b"b".as_slice(), b"nested", b"k2"])
+        })
+        .tree("b", |t| t.tree("nested", |t| t.item("k2", b"v2")));
+
+    let tmp_dir = TempDir::new().unwrap();
+    let db = GroveDb::open(tmp_dir.path()).unwrap();
+    fixture.build(&db).expect("successful fixture build");
+    assert_eq!(
+        db.get([b"a".as_slice()], b"r", None)
+            .expect("successful get"),
+        Element::Item(b"v2".to_vec())
+    );
+
+    // The same fixture always builds the same state
+    let other_tmp_dir = TempDir::new().unwrap();
+    let other_db = GroveDb::open(other_tmp_dir.path()).unwrap();
+    fixture.build(&other_db).expect("successful fixture build");
+    assert_eq!(
+        db.root_hash(None).unwrap(),
+        other_db.root_hash(None).unwrap()
+    );
+
+    // Fixtures may extend existing subtrees
+    TreeBuilder::new()
+        .item("k3", b"v3")
+        .build_at(&db, [b"a".as_slice()], None)
+        .expect("successful fixture build");
+    assert_eq!(
+        db.get([b"a".as_slice()], b"k3", None)
+            .expect("successful get"),
+        Element::Item(b"v3".to_vec())
+    );
+    assert!(TreeBuilder::new().item("k", b"v").build(&db).is_err());
+}
+
+#[test]
+fn test_prove_state_bundle() {
+    let db = make_grovedb();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let queries: Vec<PathQuery> = [(TEST_LEAF, b"key1"), (ANOTHER_TEST_LEAF, b"key2")]
+        .iter()
+        .map(|(leaf, key)| {
+            let mut query = Query::new();
+            query.insert_key(key.to_vec());
+            PathQuery::new_unsized(vec![leaf.to_vec()], query)
+        })
+        .collect();
+
+    let bundle = db
+        .prove_state_bundle(&queries, None)
+        .expect("successful bundle generation");
+    assert_eq!(
+        Some(bundle.root_hash),
+        db.root_hash(None).expect("successful root hash")
+    );
+    let results = GroveDb::verify_state_bundle(&bundle).expect("successful bundle verification");
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+        results[1][&vec![ANOTHER_TEST_LEAF.to_vec()]]
+            .get(b"key2")
+            .expect("key should be proved")
+            .expect("key should exist"),
+        bincode::serialize(&Element::Item(b"value2".to_vec())).unwrap()
+    );
+
+    // Proofs of another state don't fit the bundle
+    db.insert(
+        [TEST_LEAF],
+        b"key3",
+        Element::Item(b"value3".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    let other_bundle = db
+        .prove_state_bundle(&queries, None)
+        .expect("successful bundle generation");
+    let mut mixed_bundle = bundle.clone();
+    mixed_bundle.proofs[0] = other_bundle.proofs[0].clone();
+    assert!(matches!(
+        GroveDb::verify_state_bundle(&mixed_bundle),
+        Err(Error::InvalidProof(_))
+    ));
+    mixed_bundle.commitment =
+        ProvedStateBundle::compute_commitment(&mixed_bundle.root_hash, &mixed_bundle.proofs);
+    assert!(matches!(
+        GroveDb::verify_state_bundle(&mixed_bundle),
+        Err(Error::InvalidProof(_))
+    ));
+}
+
+#[test]
+fn test_swap() {
+    let db = make_grovedb();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"key2",
+        Element::Item(b"value2".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert([TEST_LEAF], b"tree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+
+    db.swap([TEST_LEAF], b"key1", [ANOTHER_TEST_LEAF], b"key2", None)
+        .expect("successful swap");
+    assert_eq!(
+        db.get([TEST_LEAF], b"key1", None).expect("successful get"),
+        Element::Item(b"value2".to_vec())
+    );
+    assert_eq!(
+        db.get([ANOTHER_TEST_LEAF], b"key2", None)
+            .expect("successful get"),
+        Element::Item(b"value1".to_vec())
+    );
+
+    // Root hashes are propagated as if the swapped elements were inserted
+    let expected_db = make_grovedb();
+    expected_db
+        .insert(
+            [TEST_LEAF],
+            b"key1",
+            Element::Item(b"value2".to_vec()),
+            None,
+        )
+        .expect("successful item insert");
+    expected_db
+        .insert(
+            [ANOTHER_TEST_LEAF],
+            b"key2",
+            Element::Item(b"value1".to_vec()),
+            None,
+        )
+        .expect("successful item insert");
+    expected_db
+        .insert([TEST_LEAF], b"tree", Element::empty_tree(), None)
+        .expect("successful subtree insert");
+    assert_eq!(
+        db.root_hash(None).expect("successful root hash"),
+        expected_db.root_hash(None).expect("successful root hash")
+    );
+
+    // Deduplicated items keep their references when moved
+    db.enable_feature(Feature::ItemDeduplication, None)
+        .expect("successful feature enabling");
+    let document = b"document".repeat(100);
+    let hash = merk::tree::value_hash(&document);
+    db.insert_deduplicated([TEST_LEAF], b"key3", &document, None)
+        .expect("successful deduplicated insert");
+    db.swap([TEST_LEAF], b"key1", [TEST_LEAF], b"key3", None)
+        .expect("successful swap");
+    assert_eq!(
+        db.get_raw_optional([TEST_LEAF], b"key1", None)
+            .expect("successful get"),
+        Some(Element::DedupItem(hash))
+    );
+    assert_eq!(
+        db.get([TEST_LEAF], b"key3", None).expect("successful get"),
+        Element::Item(b"value2".to_vec())
+    );
+    assert_eq!(
+        db.dedup_reference_count(&hash, None)
+            .expect("successful reference count"),
+        1
+    );
+    db.swap([TEST_LEAF], b"key1", [TEST_LEAF], b"key3", None)
+        .expect("successful swap");
+
+    // A failed swap leaves both sides in place
+    let tx = db.start_transaction();
+    let root_hash = db.root_hash(Some(&tx)).expect("successful root hash");
+    assert!(matches!(
+        db.swap([TEST_LEAF], b"key1", [TEST_LEAF], b"tree", Some(&tx)),
+        Err(Error::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        db.swap([TEST_LEAF], b"key1", [TEST_LEAF], b"missing", Some(&tx)),
+        Err(Error::PathKeyNotFound(_))
+    ));
+    assert_eq!(
+        db.root_hash(Some(&tx)).expect("successful root hash"),
+        root_hash
+    );
+}
+
+#[test]
+fn test_insert_with_references() {
+    let db = make_grovedb();
+    let ops = GroveDbOp::insert_with_references(
+        vec![TEST_LEAF.to_vec()],
+        b"doc".to_vec(),
+        Element::Item(b"value".to_vec()),
+        vec![
+            ReferenceIndexSpec {
+                path: vec![ANOTHER_TEST_LEAF.to_vec()],
+                key: b"by_name".to_vec(),
+            },
+            ReferenceIndexSpec {
+                path: vec![ANOTHER_TEST_LEAF.to_vec()],
+                key: b"by_age".to_vec(),
+            },
+        ],
+    )
+    .expect("valid index specs");
+    assert_eq!(ops.len(), 3);
+    db.apply_batch(ops, None).expect("successful batch");
+    for key in [b"by_name".as_slice(), b"by_age"] {
+        assert_eq!(
+            db.get([ANOTHER_TEST_LEAF], key, None)
+                .expect("successful get"),
+            Element::Item(b"value".to_vec())
+        );
+    }
+
+    let duplicate = ReferenceIndexSpec {
+        path: vec![TEST_LEAF.to_vec()],
+        key: b"doc".to_vec(),
+    };
+    assert!(matches!(
+        GroveDbOp::insert_with_references(
+            vec![TEST_LEAF.to_vec()],
+            b"doc".to_vec(),
+            Element::Item(b"value".to_vec()),
+            vec![duplicate],
+        ),
+        Err(Error::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        GroveDbOp::insert_with_references(
+            vec![TEST_LEAF.to_vec()],
+            b"tree".to_vec(),
+            Element::empty_tree(),
+            Vec::new(),
+        ),
+        Err(Error::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn test_prove_with_reference_targets() {
+    let db = make_grovedb();
+    db.insert(
+        [TEST_LEAF],
+        b"key1",
+        Element::Item(b"value1".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"ref1",
+        Element::Reference(vec![TEST_LEAF.to_vec(), b"key1".to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+    db.insert(
+        [ANOTHER_TEST_LEAF],
+        b"ref2",
+        Element::Reference(vec![ANOTHER_TEST_LEAF.to_vec(), b"ref1".to_vec()]),
+        None,
+    )
+    .expect("successful reference insert");
+
+    let mut query = Query::new();
+    query.insert_key(b"ref2".to_vec());
+    let path_query = PathQuery::new_unsized(vec![ANOTHER_TEST_LEAF.to_vec()], query);
+    let proof = db
+        .prove_with_reference_targets(&[path_query], None)
+        .expect("successful proof generation");
+    let (root_hash, results) = GroveDb::execute_proof(&proof).expect("successful proof execution");
+    assert_eq!(
+        Some(root_hash),
+        db.root_hash(None).expect("successful root hash")
+    );
+
+    // Every hop of the chain is proved
+    let another_leaf_results = &results[&vec![ANOTHER_TEST_LEAF.to_vec()]];
+    for key in [b"ref1".as_slice(), b"ref2"] {
+        assert!(another_leaf_results
+            .get(key)
+            .expect("key should be proved")
+            .is_some());
+    }
+    assert_eq!(
+        results[&vec![TEST_LEAF.to_vec()]]
+            .get(b"key1")
+            .expect("key should be proved")
+            .expect("key should exist"),
+        bincode::serialize(&Element::Item(b"value1".to_vec())).unwrap()
+    );
+
+    // Targets can't be added to a query with a limit
+    let mut query = Query::new();
+    query.insert_all();
+    let limited_query = PathQuery::new(
+        vec![ANOTHER_TEST_LEAF.to_vec()],
+        SizedQuery::new(query, Some(2), None),
+    );
+    assert!(matches!(
+        db.prove_with_reference_targets(&[limited_query], None),
+        Err(Error::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn test_prove_key_history() {
+    let db = make_grovedb();
+    let checkpoints_dir = TempDir::new().unwrap();
+    let checkpoints: Vec<_> = (0..3)
+        .map(|i| checkpoints_dir.path().join(format!("checkpoint{}", i)))
+        .collect();
+    let path = vec![TEST_LEAF.to_vec()];
+
+    db.insert(
+        [TEST_LEAF],
+        b"other",
+        Element::Item(b"value".to_vec()),
+        None,
+    )
+    .expect("successful item insert");
+    db.checkpoint(&checkpoints[0], false, false)
+        .expect("successful checkpoint");
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value1".to_vec()), None)
+        .expect("successful item insert");
+    db.checkpoint(&checkpoints[1], false, false)
+        .expect("successful checkpoint");
+    db.insert([TEST_LEAF], b"key", Element::Item(b"value2".to_vec()), None)
+        .expect("successful item insert");
+    db.checkpoint(&checkpoints[2], false, false)
+        .expect("successful checkpoint");
+
+    let proofs = GroveDb::prove_key_history(&checkpoints, path.clone(), b"key".to_vec())
+        .expect("successful key history proof");
+    let root_hashes: Vec<[u8; 32]> = checkpoints
+        .iter()
+        .map(|checkpoint| {
+            GroveDbReader::open(checkpoint)
+                .expect("successful checkpoint open")
+                .root_hash()
+                .expect("successful root hash")
+                .expect("checkpoint is not empty")
+        })
+        .collect();
+    assert_eq!(
+        GroveDb::verify_key_history(&proofs, &root_hashes, &path, b"key")
+            .expect("successful key history verification"),
+        vec![
+            None,
+            Some(Element::Item(b"value1".to_vec())),
+            Some(Element::Item(b"value2".to_vec())),
+        ]
+    );
+
+    let mut swapped_root_hashes = root_hashes.clone();
+    swapped_root_hashes.swap(1, 2);
+    assert!(matches!(
+        GroveDb::verify_key_history(&proofs, &swapped_root_hashes, &path, b"key"),
+        Err(Error::InvalidProof(_))
+    ));
+}
+
+#[test]
+fn test_root_hash_with_app_hash() {
+    let db = make_grovedb();
+    let prev_app_hash = [1; 32];
+    let root_hash = db
+        .root_hash(None)
+        .expect("successful root hash")
+        .expect("GroveDB is not empty");
+    let app_hash = db
+        .root_hash_with_app_hash(&prev_app_hash, None)
+        .expect("successful app hash");
+    assert_eq!(
+        app_hash,
+        GroveDb::combine_app_hash(&prev_app_hash, &root_hash)
+    );
+    assert_ne!(
+        app_hash,
+        GroveDb::combine_app_hash(&root_hash, &prev_app_hash)
+    );
+
+    // Changes made within a transaction are committed to
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"key",
+        Element::Item(b"value".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    assert_ne!(
+        db.root_hash_with_app_hash(&prev_app_hash, Some(&tx))
+            .expect("successful app hash"),
+        app_hash
+    );
+
+    let signature = b"quorum signature";
+    let committed = GroveDb::combine_app_hash_with_commitment(&app_hash, signature);
+    assert_ne!(committed, app_hash);
+    assert_ne!(
+        committed,
+        GroveDb::combine_app_hash_with_commitment(&app_hash, b"other signature")
+    );
+}
+
+#[test]
+fn test_validate_batch() {
+    let db = make_grovedb();
+    let ops = vec![
+        GroveDbOp::Insert {
+            path: vec![TEST_LEAF.to_vec()],
+            key: b"innertree".to_vec(),
+            element: Element::empty_tree(),
+        },
+        // Valid because of the previous operation
+        GroveDbOp::Insert {
+            path: vec![TEST_LEAF.to_vec(), b"innertree".to_vec()],
+            key: b"key".to_vec(),
+            element: Element::Item(b"value".to_vec()),
+        },
+        GroveDbOp::Insert {
+            path: vec![TEST_LEAF.to_vec(), b"missing".to_vec()],
+            key: b"key".to_vec(),
+            element: Element::Item(b"value".to_vec()),
+        },
+        GroveDbOp::Insert {
+            path: vec![],
+            key: b"key".to_vec(),
+            element: Element::Item(b"value".to_vec()),
+        },
+        GroveDbOp::Delete {
+            path: vec![ANOTHER_TEST_LEAF.to_vec()],
+            key: b"missing".to_vec(),
+        },
+    ];
+    let root_hash = db.root_hash(None).expect("successful root hash");
+
+    let errors = db
+        .validate_batch(ops.clone(), None)
+        .expect("successful validation");
+    assert_eq!(
+        errors.iter().map(|(idx, _)| *idx).collect::<Vec<_>>(),
+        vec![2, 3, 4]
+    );
+    assert!(matches!(errors[1].1, Error::InvalidPath(_)));
+    assert_eq!(db.root_hash(None).expect("successful root hash"), root_hash);
+
+    // Validation within a transaction keeps its changes only
+    let tx = db.start_transaction();
+    db.insert(
+        [TEST_LEAF],
+        b"txkey",
+        Element::Item(b"value".to_vec()),
+        Some(&tx),
+    )
+    .expect("successful item insert");
+    let tx_root_hash = db.root_hash(Some(&tx)).expect("successful root hash");
+    let errors = db
+        .validate_batch(ops, Some(&tx))
+        .expect("successful validation");
+    assert_eq!(errors.len(), 3);
+    assert_eq!(
+        db.root_hash(Some(&tx)).expect("successful root hash"),
+        tx_root_hash
+    );
+    assert!(matches!(
+        db.get([TEST_LEAF, b"innertree"], b"key", Some(&tx)),
+        Err(Error::PathNotFound(_))
+    ));
+}
+
+#[test]
+fn test_apply_batch_with_op_ids() {
+    let db = make_grovedb();
+    let insert = |key: &[u8], value: &[u8]| GroveDbOp::Insert {
+        path: vec![TEST_LEAF.to_vec()],
+        key: key.to_vec(),
+        element: Element::Item(value.to_vec()),
+    };
+    let ops = vec![
+        (Some(b"op1".to_vec()), insert(b"key1", b"value1")),
+        (None, insert(b"key2", b"value2")),
+    ];
+    assert_eq!(
+        db.apply_batch_with_op_ids(ops, None)
+            .expect("successful batch"),
+        0
+    );
+    assert!(db
+        .is_op_applied(b"op1", None)
+        .expect("successful op ID check"));
+    // IDs are not stored with user auxiliary data
+    assert!(db
+        .get_aux(b"applied_op_id/op1", None)
+        .expect("successful aux get")
+        .is_none());
+
+    // The item changed since, replaying its operation doesn't overwrite it
+    db.insert([TEST_LEAF], b"key1", Element::Item(b"newer".to_vec()), None)
+        .expect("successful item insert");
+    let ops = vec![
+        (Some(b"op1".to_vec()), insert(b"key1", b"value1")),
+        (Some(b"op3".to_vec()), insert(b"key3", b"value3")),
+    ];
+    assert_eq!(
+        db.apply_batch_with_op_ids(ops, None)
+            .expect("successful batch"),
+        1
+    );
+    assert_eq!(
+        db.get([TEST_LEAF], b"key1", None).expect("successful get"),
+        Element::Item(b"newer".to_vec())
+    );
+    assert_eq!(
+        db.get([TEST_LEAF], b"key3", None).expect("successful get"),
+        Element::Item(b"value3".to_vec())
+    );
+
+    // IDs of a failed batch are not recorded
+    let ops = vec![
+        (Some(b"op4".to_vec()), insert(b"key4", b"value4")),
+        (
+            Some(b"op5".to_vec()),
+            GroveDbOp::Delete {
+                path: vec![TEST_LEAF.to_vec()],
+                key: b"missing".to_vec(),
+            },
+        ),
+    ];
+    assert!(db.apply_batch_with_op_ids(ops, None).is_err());
+    assert!(!db
+        .is_op_applied(b"op4", None)
+        .expect("successful op ID check"));
+
+    // A forgotten operation is applied again
+    db.forget_applied_op(b"op1", None)
+        .expect("successful op ID removal");
+    let ops = vec![(Some(b"op1".to_vec()), insert(b"key1", b"value1"))];
+    assert_eq!(
+        db.apply_batch_with_op_ids(ops, None)
+            .expect("successful batch"),
+        0
+    );
+    assert_eq!(
+        db.get([TEST_LEAF], b"key1", None).expect("successful get"),
+        Element::Item(b"value1".to_vec())
+    );
+}
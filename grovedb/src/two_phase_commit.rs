@@ -0,0 +1,93 @@
+//! Module for two-phase commits.
+//! A node may keep its state in several local stores which must be updated
+//! atomically. A transaction prepared for a two-phase commit has its writes
+//! durably recorded, so once every store is prepared the coordinator can
+//! commit all of them, and after a crash decide on the recorded outcome
+//! whether to commit or roll back the prepared transactions left. While a
+//! transaction is prepared, writes touching any key it writes fail with
+//! [`Error::PreparedTransactionConflict`] instead of waiting for it to be
+//! resolved, so a prepared transaction can always be committed and a pending
+//! one never holds other writes.
+
+use storage::rocksdb_storage::PreparedCommit;
+
+use crate::{Error, GroveDb, Transaction};
+
+/// Identifies a transaction prepared by [`GroveDb::prepare_transaction`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PreparedToken(u64);
+
+impl PreparedToken {
+    /// Returns the id of the prepared transaction, stable across restarts
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+
+    /// Restores a token by an id returned by [`PreparedToken::id`]
+    pub fn from_id(id: u64) -> Self {
+        PreparedToken(id)
+    }
+}
+
+impl GroveDb {
+    /// Prepares the transaction for a two-phase commit. Its writes are
+    /// durably recorded but not visible until [`GroveDb::commit_prepared`],
+    /// or discarded by [`GroveDb::rollback_prepared`]. Tokens are never
+    /// reused, even across restarts. Fails with
+    /// [`Error::PreparedTransactionConflict`] if a key the transaction writes
+    /// was written since it started, or is written by another pending
+    /// prepared transaction. Every change of a subtree propagates to the root
+    /// tree, so such changes conflict with each other, while auxiliary data
+    /// of other keys doesn't.
+    ///
+    /// Until the prepared transaction is committed or rolled back, writes
+    /// touching its keys, including transaction commits, fail with
+    /// [`Error::PreparedTransactionConflict`] and may be retried once it is
+    /// resolved.
+    pub fn prepare_transaction(&self, transaction: Transaction) -> Result<PreparedToken, Error> {
+        let db = self.rocksdb()?;
+        let inner = transaction.rocksdb()?;
+        let changes = self.discard_transaction_changes(transaction.id());
+        let written_subtrees = transaction.take_written_subtrees();
+        let token = match db.prepare_transaction(inner, transaction.write_version)? {
+            Some(id) => PreparedToken(id),
+            None => return Err(Error::PreparedTransactionConflict),
+        };
+        self.prepare_transaction_changes(changes, written_subtrees, token);
+        Ok(token)
+    }
+
+    /// Atomically applies writes of a prepared transaction. Its keys are not
+    /// written by anything else while it is pending, so it can't conflict,
+    /// including after a restart.
+    pub fn commit_prepared(&self, token: PreparedToken) -> Result<(), Error> {
+        match self.rocksdb()?.commit_prepared(token.0)? {
+            PreparedCommit::Committed => {
+                // Subtrees written by transactions prepared before a restart
+                // are not known, so all subscribers are checked for them
+                let written_subtrees = self.resolve_prepared_changes(token, true);
+                self.notify_subscribers(written_subtrees.as_ref());
+                Ok(())
+            }
+            PreparedCommit::NotFound => Err(Error::PreparedTransactionNotFound),
+        }
+    }
+
+    /// Discards writes of a prepared transaction
+    pub fn rollback_prepared(&self, token: PreparedToken) -> Result<(), Error> {
+        self.rocksdb()?.rollback_prepared(token.0)?;
+        self.resolve_prepared_changes(token, false);
+        Ok(())
+    }
+
+    /// Returns transactions prepared but neither committed nor rolled back,
+    /// e.g. left by a crash, for the coordinator to resolve
+    pub fn prepared_transactions(&self) -> Result<Vec<PreparedToken>, Error> {
+        Ok(self
+            .rocksdb()?
+            .prepared_transactions()?
+            .into_iter()
+            .map(PreparedToken)
+            .collect())
+    }
+}
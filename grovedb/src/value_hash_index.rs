@@ -0,0 +1,213 @@
+//! Module for value hash lookups.
+//! Subtrees can be flagged to have their elements indexed by value hash, so
+//! deduplication and content-addressed lookups don't require full scans. The
+//! index keeps an entry per element location in roots storage of the root
+//! tree under a dedicated prefix, so lookups are prefix scans and maintaining
+//! it doesn't rewrite a record growing with the number of locations. Trees are
+//! not indexed as their values change on every nested update.
+
+use merk::tree::value_hash;
+use storage::{RawIterator, StorageContext};
+
+use crate::{
+    util::{meta_storage_context_optional_tx, storage_context_optional_tx},
+    Element, Error, GroveDb, TransactionArg,
+};
+
+/// A key in roots storage of a subtree to flag it as indexed by value hash
+const VALUE_HASH_INDEXED_KEY: &[u8] = b"value_hash_indexed";
+/// A prefix of keys in roots storage of the root tree to store locations of
+/// elements, followed by their value hash and a serialized location
+const VALUE_HASH_INDEX_PREFIX: &[u8] = b"value_hash_index/";
+
+/// Location of an element: subtree path and key
+type ElementLocation = (Vec<Vec<u8>>, Vec<u8>);
+
+impl GroveDb {
+    /// Flags a subtree to have its elements indexed by value hash and indexes
+    /// elements already present, see [`GroveDb::find_by_value_hash`]
+    pub fn enable_value_hash_index<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone + DoubleEndedIterator + ExactSizeIterator,
+    {
+        let path_iter = path.into_iter();
+        self.check_subtree_exists_path_not_found(path_iter.clone(), transaction)?;
+        if self.is_value_hash_indexed(path_iter.clone(), transaction)? {
+            return Ok(());
+        }
+        storage_context_optional_tx!(self.db, path_iter.clone(), transaction, storage, {
+            storage.put_root(VALUE_HASH_INDEXED_KEY, &[1])?;
+        });
+        let path: Vec<Vec<u8>> = path_iter.map(|x| x.to_vec()).collect();
+        for (key, element) in self.iter(path.iter().map(|x| x.as_slice()), false, transaction)? {
+            if let Some(element) = element {
+                self.add_value_hash_entry(&path, &key, &element, transaction)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns locations of all elements of indexed subtrees with the value
+    /// hash, which is the hash of a serialized element
+    pub fn find_by_value_hash(
+        &self,
+        hash: &[u8; 32],
+        transaction: TransactionArg,
+    ) -> Result<Vec<ElementLocation>, Error> {
+        let index_prefix = Self::value_hash_index_key(hash, &[]);
+        let mut serialized_locations = Vec::new();
+        meta_storage_context_optional_tx!(self.db, transaction, storage, {
+            let mut raw_iter = storage.raw_iter_roots();
+            raw_iter.seek(&index_prefix);
+            while let Some(location) = raw_iter
+                .key()
+                .and_then(|key| key.strip_prefix(index_prefix.as_slice()))
+            {
+                serialized_locations.push(location.to_vec());
+                raw_iter.next();
+            }
+            raw_iter.status()?;
+        });
+        serialized_locations
+            .iter()
+            .map(|location| {
+                bincode::deserialize(location).map_err(|_| {
+                    Error::CorruptedData(String::from("unable to deserialize value hash index"))
+                })
+            })
+            .collect()
+    }
+
+    /// Updates value hash index on element change if the subtree is indexed
+    pub(crate) fn update_value_hash_index<'p, P>(
+        &self,
+        path: P,
+        key: &[u8],
+        old_element: Option<&Element>,
+        new_element: Option<&Element>,
+        transaction: TransactionArg,
+    ) -> Result<(), Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+        <P as IntoIterator>::IntoIter: Clone,
+    {
+        let path_iter = path.into_iter();
+        if !self.is_value_hash_indexed(path_iter.clone(), transaction)? {
+            return Ok(());
+        }
+        let path: Vec<Vec<u8>> = path_iter.map(|x| x.to_vec()).collect();
+        if let Some(old_element) = old_element {
+            self.remove_value_hash_entry(&path, key, old_element, transaction)?;
+        }
+        if let Some(new_element) = new_element {
+            self.add_value_hash_entry(&path, key, new_element, transaction)?;
+        }
+        Ok(())
+    }
+
+    /// Removes index entries of all elements of a subtree which is being
+    /// deleted and drops its flag
+    pub(crate) fn drop_value_hash_index(
+        &self,
+        path: &[Vec<u8>],
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        let path_iter = path.iter().map(|x| x.as_slice());
+        if !self.is_value_hash_indexed(path_iter.clone(), transaction)? {
+            return Ok(());
+        }
+        for (key, element) in self.iter(path_iter.clone(), false, transaction)? {
+            if let Some(element) = element {
+                self.remove_value_hash_entry(path, &key, &element, transaction)?;
+            }
+        }
+        storage_context_optional_tx!(self.db, path_iter, transaction, storage, {
+            storage.delete_root(VALUE_HASH_INDEXED_KEY)?;
+        });
+        Ok(())
+    }
+
+    fn is_value_hash_indexed<'p, P>(
+        &self,
+        path: P,
+        transaction: TransactionArg,
+    ) -> Result<bool, Error>
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        Ok(
+            storage_context_optional_tx!(self.db, path, transaction, storage, {
+                storage.get_root(VALUE_HASH_INDEXED_KEY)?
+            })
+            .is_some(),
+        )
+    }
+
+    fn add_value_hash_entry(
+        &self,
+        path: &[Vec<u8>],
+        key: &[u8],
+        element: &Element,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        if let Element::Tree(_) | Element::PrunedTree(_) = element {
+            return Ok(());
+        }
+        let index_key = Self::value_hash_entry_key(path, key, element)?;
+        meta_storage_context_optional_tx!(self.db, transaction, storage, {
+            storage.put_root(index_key, &[])?;
+        });
+        Ok(())
+    }
+
+    fn remove_value_hash_entry(
+        &self,
+        path: &[Vec<u8>],
+        key: &[u8],
+        element: &Element,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        if let Element::Tree(_) | Element::PrunedTree(_) = element {
+            return Ok(());
+        }
+        let index_key = Self::value_hash_entry_key(path, key, element)?;
+        meta_storage_context_optional_tx!(self.db, transaction, storage, {
+            storage.delete_root(index_key)?;
+        });
+        Ok(())
+    }
+
+    /// Returns the index key of an element location
+    fn value_hash_entry_key(
+        path: &[Vec<u8>],
+        key: &[u8],
+        element: &Element,
+    ) -> Result<Vec<u8>, Error> {
+        let location: ElementLocation = (path.to_vec(), key.to_vec());
+        let serialized = bincode::serialize(&location).map_err(|_| {
+            Error::CorruptedData(String::from("unable to serialize value hash index"))
+        })?;
+        Ok(Self::value_hash_index_key(
+            &Self::element_value_hash(element)?,
+            &serialized,
+        ))
+    }
+
+    fn element_value_hash(element: &Element) -> Result<[u8; 32], Error> {
+        let serialized = bincode::serialize(element)
+            .map_err(|_| Error::CorruptedData(String::from("unable to serialize element")))?;
+        Ok(value_hash(&serialized))
+    }
+
+    fn value_hash_index_key(hash: &[u8; 32], serialized_location: &[u8]) -> Vec<u8> {
+        let mut key = VALUE_HASH_INDEX_PREFIX.to_vec();
+        key.extend_from_slice(hash);
+        key.extend_from_slice(serialized_location);
+        key
+    }
+}
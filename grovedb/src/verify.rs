@@ -0,0 +1,345 @@
+//! Module for proof verification.
+//! Verification needs neither storage nor Merk, so this module is available
+//! without the `full` feature to light clients which only check proofs.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::Read,
+};
+
+use merk::{
+    proofs::{
+        self,
+        query::{Map, MapBuilder},
+        Node,
+    },
+    tree::{value_hash, NULL_HASH},
+};
+
+use crate::{Element, Error, PathLimits, Proof, ProofLimits};
+
+/// Format version of proofs, the first byte of every proof. Version 2 proofs
+/// are serialized [`Proof`]s with shared nodes and values of deduplicated
+/// items, optionally compressed.
+pub(crate) const PROOF_FORMAT_VERSION: u8 = 2;
+/// Proof header flag of an uncompressed proof, following the version
+pub(crate) const PROOF_UNCOMPRESSED: u8 = 0;
+/// Proof header flag of a zstd compressed proof
+pub(crate) const PROOF_ZSTD: u8 = 1;
+/// Subtree proof operation referencing a shared node, followed by its index
+/// as big endian u32
+pub(crate) const PROOF_NODE_REF: u8 = 0x20;
+/// Length of an encoded shared node reference
+pub(crate) const PROOF_NODE_REF_LENGTH: usize = 5;
+
+/// Verifies a proof made with `GroveDb::prove` for consistency and
+/// returns a root hash it leads to alongside with proved data of each
+/// queried subtree. The root hash is to be compared with a trusted one.
+/// Deduplicated items are resolved into items with values carried by the
+/// proof. Proofs exceeding the default [`ProofLimits`] are rejected.
+pub fn execute_proof(proof: &[u8]) -> Result<([u8; 32], HashMap<Vec<Vec<u8>>, Map>), Error> {
+    verify_query_with_limits(proof, &ProofLimits::default())
+}
+
+fn execute_decoded_proof(proof: Proof) -> Result<([u8; 32], HashMap<Vec<Vec<u8>>, Map>), Error> {
+    let limits = PathLimits::default();
+    let mut root_hash = None;
+    let mut results = HashMap::new();
+    // Subtree proofs on paths to queried subtrees are shared by queries,
+    // each one is executed once
+    let mut ancestors = HashMap::new();
+    let dedup_values: HashMap<[u8; 32], &[u8]> = proof
+        .dedup_values
+        .iter()
+        .map(|value| (value_hash(value), value.as_slice()))
+        .collect();
+    for path in proof.query_paths {
+        limits
+            .check_path(path.iter().map(|x| x.as_slice()), None)
+            .map_err(|_| Error::InvalidProof("query path limits exceeded"))?;
+        let (hash, mut result_map) = execute_path(&path, &proof.proofs, &mut ancestors)?;
+        resolve_dedup_items(&mut result_map, &dedup_values)?;
+        if *root_hash.get_or_insert(hash) != hash {
+            return Err(Error::InvalidProof("root hashes mismatch"));
+        }
+        if results.insert(path, result_map).is_some() {
+            return Err(Error::InvalidProof("duplicate query path"));
+        }
+    }
+
+    let root_hash = root_hash.ok_or(Error::InvalidProof("proof has no queries"))?;
+    Ok((root_hash, results))
+}
+
+/// Executes a proof like [`execute_proof`] if it is within
+/// `limits`. The proof length is checked before deserialization, as well
+/// as the decompressed length of a compressed proof and the length of
+/// subtree proofs with shared nodes expanded, and then every subtree
+/// proof is decoded without hashing, operations and results are counted
+/// across all of them.
+pub fn verify_query_with_limits(
+    proof: &[u8],
+    limits: &ProofLimits,
+) -> Result<([u8; 32], HashMap<Vec<Vec<u8>>, Map>), Error> {
+    if proof.len() > limits.max_proof_bytes {
+        return Err(Error::InvalidProof("proof size limit exceeded"));
+    }
+    let decoded = Proof::from_bytes(proof, limits)?;
+
+    let mut remaining = *limits;
+    for subtree_proof in decoded.proofs.values() {
+        let size = merk::check_proof_limits(subtree_proof, &remaining)
+            .map_err(|_| Error::InvalidProof("proof limits exceeded"))?;
+        remaining.max_ops -= size.ops;
+        remaining.max_results -= size.results;
+    }
+
+    execute_decoded_proof(decoded)
+}
+
+/// Checks that subtrees on the path are connected to one another, i.e. root
+/// hash of a child subtree is in its parent under the right key. If so,
+/// returns the root hash of the root tree and proved data of the queried
+/// subtree. Executed proofs of ancestor subtrees are kept in `ancestors`.
+fn execute_path(
+    path: &[Vec<u8>],
+    proofs: &HashMap<Vec<u8>, Vec<u8>>,
+    ancestors: &mut HashMap<Vec<u8>, ([u8; 32], Map)>,
+) -> Result<([u8; 32], Map), Error> {
+    let (mut hash, result_map) = execute_subtree_proof(path, proofs)?;
+    for i in (0..path.len()).rev() {
+        let prefix = subtree_prefix(&path[..i]);
+        if !ancestors.contains_key(&prefix) {
+            let executed = execute_subtree_proof(&path[..i], proofs)?;
+            ancestors.insert(prefix.clone(), executed);
+        }
+        let (parent_hash, parent_map) = &ancestors[&prefix];
+        let element_bytes = parent_map
+            .get(&path[i])
+            .map_err(|_| Error::InvalidProof("subtree key is not proved"))?
+            .ok_or(Error::InvalidProof("subtree doesn't exist"))?;
+        let element: Element = bincode::deserialize(element_bytes)
+            .map_err(|_| Error::InvalidProof("unable to deserialize element"))?;
+        match element {
+            Element::Tree(tree_hash) if tree_hash == hash => hash = *parent_hash,
+            Element::Tree(_) => return Err(Error::InvalidProof("subtree hash mismatch")),
+            _ => {
+                return Err(Error::InvalidProof(
+                    "intermediate proofs should be for trees",
+                ))
+            }
+        }
+    }
+    Ok((hash, result_map))
+}
+
+/// Replaces deduplicated items of proved data with items of their values,
+/// which are looked up by hashes, so a value can't be substituted
+fn resolve_dedup_items(map: &mut Map, values: &HashMap<[u8; 32], &[u8]>) -> Result<(), Error> {
+    for value in map.values_mut() {
+        if let Ok(Element::DedupItem(hash)) = bincode::deserialize(value) {
+            let resolved = values
+                .get(&hash)
+                .ok_or(Error::InvalidProof("deduplicated value is missing"))?;
+            *value = bincode::serialize(&Element::Item(resolved.to_vec()))
+                .map_err(|_| Error::InvalidProof("unable to serialize element"))?;
+        }
+    }
+    Ok(())
+}
+
+fn execute_subtree_proof(
+    path: &[Vec<u8>],
+    proofs: &HashMap<Vec<u8>, Vec<u8>>,
+) -> Result<([u8; 32], Map), Error> {
+    let proof = proofs
+        .get(&subtree_prefix(path))
+        .ok_or(Error::InvalidProof("missing subtree proof"))?;
+    if proof.is_empty() {
+        // Empty subtrees cannot be proved by Merk, so an empty proof stands
+        // for an empty subtree
+        return Ok((NULL_HASH, MapBuilder::new().build()));
+    }
+    merk::execute_proof(proof).map_err(|_| Error::InvalidProof("invalid subtree proof"))
+}
+
+fn subtree_prefix(path: &[Vec<u8>]) -> Vec<u8> {
+    storage::prefix::build_prefix(path.iter().map(|x| x.as_slice()))
+}
+
+/// Parsed operation of a GroveDB proof, see [`Proof::decode`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProofOp {
+    /// Start of a subtree proof, operations up to the next layer belong to
+    /// the subtree at the path
+    Layer(Vec<Vec<u8>>),
+    /// Pushes a node on the stack
+    Push(Node),
+    /// Attaches the top stack item as the left child of the next one
+    Parent,
+    /// Attaches the top stack item as the right child of the next one
+    Child,
+}
+
+impl From<proofs::Op> for ProofOp {
+    fn from(op: proofs::Op) -> Self {
+        match op {
+            proofs::Op::Push(node) => ProofOp::Push(node),
+            proofs::Op::Parent => ProofOp::Parent,
+            proofs::Op::Child => ProofOp::Child,
+        }
+    }
+}
+
+impl fmt::Display for ProofOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofOp::Layer(path) => write!(
+                f,
+                "Layer [{}]",
+                path.iter().map(hex::encode).collect::<Vec<_>>().join("/")
+            ),
+            ProofOp::Push(node) => write!(f, "Push({})", node),
+            ProofOp::Parent => write!(f, "Parent"),
+            ProofOp::Child => write!(f, "Child"),
+        }
+    }
+}
+
+impl Proof {
+    /// Replaces shared node references in subtree proofs with the nodes,
+    /// subtree proofs must not be longer than `limits.max_proof_bytes` in
+    /// total after that, nor have more than `limits.max_ops` operations
+    fn expand_shared_nodes(&mut self, limits: &ProofLimits) -> Result<(), Error> {
+        let shared_nodes = std::mem::take(&mut self.shared_nodes);
+        if shared_nodes.len() > limits.max_ops {
+            return Err(Error::InvalidProof("proof operation limit exceeded"));
+        }
+        let mut total_bytes: usize = 0;
+        let mut total_ops: usize = 0;
+        for subtree_proof in self.proofs.values_mut() {
+            let mut expanded = Vec::with_capacity(subtree_proof.len());
+            let mut offset = 0;
+            while offset < subtree_proof.len() {
+                if subtree_proof[offset] == PROOF_NODE_REF {
+                    let index_bytes = subtree_proof
+                        .get(offset + 1..offset + PROOF_NODE_REF_LENGTH)
+                        .ok_or(Error::InvalidProof("truncated shared node reference"))?;
+                    let index = u32::from_be_bytes(index_bytes.try_into().expect("4 bytes"));
+                    let node = shared_nodes
+                        .get(index as usize)
+                        .ok_or(Error::InvalidProof("unknown shared node"))?;
+                    expanded.extend_from_slice(node);
+                    offset += PROOF_NODE_REF_LENGTH;
+                } else {
+                    let op = proofs::Op::decode(&subtree_proof[offset..])
+                        .map_err(|_| Error::InvalidProof("unable to decode subtree proof"))?;
+                    let length = expanded.len();
+                    proofs::encode_into(std::iter::once(&op), &mut expanded);
+                    offset += expanded.len() - length;
+                }
+                if total_bytes.saturating_add(expanded.len()) > limits.max_proof_bytes {
+                    return Err(Error::InvalidProof("proof size limit exceeded"));
+                }
+                total_ops += 1;
+                if total_ops > limits.max_ops {
+                    return Err(Error::InvalidProof("proof operation limit exceeded"));
+                }
+            }
+            total_bytes += expanded.len();
+            *subtree_proof = expanded;
+        }
+        Ok(())
+    }
+
+    /// Deserializes a proof and expands shared nodes, a compressed one must
+    /// not be longer than `limits.max_proof_bytes` when decompressed, as well
+    /// as subtree proofs after expansion, which must not have more than
+    /// `limits.max_ops` operations either
+    pub(crate) fn from_bytes(bytes: &[u8], limits: &ProofLimits) -> Result<Self, Error> {
+        let max_bytes = limits.max_proof_bytes;
+        let (version, bytes) = bytes
+            .split_first()
+            .ok_or(Error::InvalidProof("empty proof"))?;
+        if *version != PROOF_FORMAT_VERSION {
+            return Err(Error::InvalidProof("unsupported proof format version"));
+        }
+        let (flag, payload) = bytes
+            .split_first()
+            .ok_or(Error::InvalidProof("proof header is truncated"))?;
+        let decompressed;
+        let serialized = match *flag {
+            PROOF_UNCOMPRESSED => payload,
+            PROOF_ZSTD => {
+                let mut buffer = Vec::new();
+                zstd::stream::read::Decoder::new(payload)
+                    .and_then(|decoder| {
+                        decoder
+                            .take(max_bytes.saturating_add(1) as u64)
+                            .read_to_end(&mut buffer)
+                    })
+                    .map_err(|_| Error::InvalidProof("unable to decompress proof"))?;
+                if buffer.len() > max_bytes {
+                    return Err(Error::InvalidProof("proof size limit exceeded"));
+                }
+                decompressed = buffer;
+                decompressed.as_slice()
+            }
+            _ => return Err(Error::InvalidProof("unknown proof compression")),
+        };
+        let mut proof: Proof = bincode::deserialize(serialized)
+            .map_err(|_| Error::InvalidProof("unable to deserialize proof"))?;
+        proof.expand_shared_nodes(limits)?;
+        Ok(proof)
+    }
+
+    /// Decodes a proof made with `GroveDb::prove` into operations for
+    /// debugging, nothing is verified. Subtree proofs are ordered by path,
+    /// each one starts with a layer boundary; an empty subtree has no
+    /// operations.
+    pub fn decode(proof: &[u8]) -> Result<Vec<ProofOp>, Error> {
+        let proof = Proof::from_bytes(proof, &ProofLimits::default())?;
+
+        // Subtree proofs are stored by prefix, their paths are the query paths
+        // and paths to them
+        let mut paths = BTreeMap::new();
+        for query_path in &proof.query_paths {
+            for i in 0..=query_path.len() {
+                let path = &query_path[..i];
+                paths.insert(path.to_vec(), subtree_prefix(path));
+            }
+        }
+        if paths.len() != proof.proofs.len() {
+            return Err(Error::InvalidProof("subtree proof without a query path"));
+        }
+
+        let mut ops = Vec::new();
+        for (path, prefix) in paths {
+            let subtree_proof = proof
+                .proofs
+                .get(&prefix)
+                .ok_or(Error::InvalidProof("missing subtree proof"))?;
+            ops.push(ProofOp::Layer(path));
+            for op in proofs::Decoder::new(subtree_proof) {
+                let op = op.map_err(|_| Error::InvalidProof("unable to decode subtree proof"))?;
+                ops.push(op.into());
+            }
+        }
+        Ok(ops)
+    }
+
+    /// Decodes a proof like [`Proof::decode`] and formats it one operation per
+    /// line, operations of a subtree are indented under its layer
+    pub fn pretty_print(proof: &[u8]) -> Result<String, Error> {
+        let mut output = String::new();
+        for op in Self::decode(proof)? {
+            if !matches!(op, ProofOp::Layer(_)) {
+                output.push_str("  ");
+            }
+            output.push_str(&op.to_string());
+            output.push('\n');
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,298 @@
+//! Module for storage format versioning.
+//! A version record is kept in meta storage and checked on open, so a binary
+//! doesn't work with data written in a format it doesn't know. Data written
+//! before version records were introduced is of format version 0. Data of
+//! older formats is migrated on open. Features
+//! affecting the format, such as new element types or proof encodings, are
+//! disabled until explicitly enabled, so validators running different versions
+//! can coordinate their activation.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use merk::{handshake::SyncHandshake, Merk};
+use serde::{Deserialize, Serialize};
+use storage::{Storage, StorageContext};
+
+use crate::{
+    util::meta_storage_context_optional_tx, Element, Error, GroveDb, MutationKind, TransactionArg,
+};
+
+/// Storage format version written by this release. Version 2 keeps an entry
+/// per child subtree and back references in roots storage of every subtree.
+pub const GROVE_FORMAT_VERSION: u32 = 2;
+/// A key in meta storage to store the version record
+const GROVE_VERSION_KEY: &[u8] = b"grove_version";
+/// A key in meta storage the root tree leaves were stored under in format
+/// version 0, before the root tree became a Merk
+const LEGACY_ROOT_LEAFS_KEY: &[u8] = b"rootLeafsSerialized";
+
+/// Format-affecting features, their flags are stored in the version record
+/// and must never change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Feature {
+    /// Pruning subtrees keeping their root hashes, see
+    /// [`GroveDb::prune_subtree`]
+    PrunedTrees,
+    /// Compressed proofs made by [`GroveDb::prove_compressed`]
+    ProofCompression,
+    /// Items stored compressed by [`GroveDb::insert_compressed`]
+    ItemCompression,
+    /// Items stored once per value by [`GroveDb::insert_deduplicated`]
+    ItemDeduplication,
+}
+
+impl Feature {
+    /// Returns the flag of the feature in the version record
+    pub fn flag(self) -> u32 {
+        match self {
+            Feature::PrunedTrees => 1,
+            Feature::ProofCompression => 2,
+            Feature::ItemCompression => 3,
+            Feature::ItemDeduplication => 4,
+        }
+    }
+
+    fn from_flag(flag: u32) -> Option<Self> {
+        match flag {
+            1 => Some(Feature::PrunedTrees),
+            2 => Some(Feature::ProofCompression),
+            3 => Some(Feature::ItemCompression),
+            4 => Some(Feature::ItemDeduplication),
+            _ => None,
+        }
+    }
+
+    /// Returns whether data written with the feature differs from data
+    /// written without it, rather than only the proofs made
+    fn affects_stored_data(self) -> bool {
+        !matches!(self, Feature::ProofCompression)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Feature::PrunedTrees => "pruned trees",
+            Feature::ProofCompression => "proof compression",
+            Feature::ItemCompression => "item compression",
+            Feature::ItemDeduplication => "item deduplication",
+        }
+    }
+}
+
+/// Storage format version and enabled features of a GroveDB
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroveVersion {
+    pub format_version: u32,
+    pub(crate) features: BTreeSet<u32>,
+}
+
+impl Default for GroveVersion {
+    fn default() -> Self {
+        GroveVersion {
+            format_version: GROVE_FORMAT_VERSION,
+            features: BTreeSet::new(),
+        }
+    }
+}
+
+impl GroveVersion {
+    /// Returns whether the feature is enabled
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.features.contains(&feature.flag())
+    }
+
+    /// Returns enabled features
+    pub fn features(&self) -> Vec<Feature> {
+        self.features
+            .iter()
+            .filter_map(|flag| Feature::from_flag(*flag))
+            .collect()
+    }
+
+    fn check_compatible(&self) -> Result<(), Error> {
+        if self.format_version > GROVE_FORMAT_VERSION {
+            return Err(Error::IncompatibleVersion(format!(
+                "storage format version {} is newer than supported version {}",
+                self.format_version, GROVE_FORMAT_VERSION
+            )));
+        }
+        if let Some(flag) = self
+            .features
+            .iter()
+            .find(|flag| Feature::from_flag(**flag).is_none())
+        {
+            return Err(Error::IncompatibleVersion(format!(
+                "unknown feature {} is enabled",
+                flag
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl GroveDb {
+    /// Returns the version record. Without one the data predates version
+    /// records and is of format version 0, unless there is no data yet, which
+    /// is of the current format version.
+    pub fn version(&self) -> Result<GroveVersion, Error> {
+        self.read_version(None)
+    }
+
+    /// Returns whether the feature is enabled for this GroveDB
+    pub fn is_feature_enabled(&self, feature: Feature) -> Result<bool, Error> {
+        Ok(self.version()?.is_enabled(feature))
+    }
+
+    /// Returns the state sync handshake of this GroveDB. Enabled features
+    /// affecting stored data are required application capabilities, as their
+    /// data can't be read by a peer without them, others are only supported.
+    pub fn sync_handshake(&self) -> Result<SyncHandshake, Error> {
+        Ok(self.version()?.features().into_iter().fold(
+            SyncHandshake::default(),
+            |handshake, feature| {
+                handshake.with_application_capability(feature.flag(), feature.affects_stored_data())
+            },
+        ))
+    }
+
+    /// Enables a format-affecting feature. Features can't be disabled, as data
+    /// written with a feature may not be readable without it.
+    pub fn enable_feature(
+        &self,
+        feature: Feature,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        self.check_access(
+            std::iter::empty(),
+            &feature.flag().to_be_bytes(),
+            MutationKind::EnableFeature,
+        )?;
+        let mut version = self.read_version(transaction)?;
+        if version.features.insert(feature.flag()) {
+            self.write_version(&version, transaction)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that data is in a format this release supports. With `migrate`
+    /// data of an older format is migrated and the version record is written
+    /// if there is none, otherwise an older format is an error, as it can't be
+    /// read as is.
+    pub(crate) fn check_version(&self, migrate: bool) -> Result<(), Error> {
+        let meta_storage = self.db.get_storage_context(std::iter::empty());
+        let has_record = meta_storage.get_meta(GROVE_VERSION_KEY)?.is_some();
+        let mut version = self.version()?;
+        version.check_compatible()?;
+        if has_record && version.format_version == GROVE_FORMAT_VERSION {
+            return Ok(());
+        }
+        if !migrate {
+            return if version.format_version == GROVE_FORMAT_VERSION {
+                Ok(())
+            } else {
+                Err(Error::IncompatibleVersion(format!(
+                    "storage format version {} must be migrated by opening with GroveDb::open",
+                    version.format_version
+                )))
+            };
+        }
+        self.migrate(version.format_version)?;
+        version.format_version = GROVE_FORMAT_VERSION;
+        self.write_version(&version, None)
+    }
+
+    /// Migrates data of the format version to the current one. Each migration
+    /// step must be safe to repeat, as the version record is written after
+    /// all of them.
+    fn migrate(&self, format_version: u32) -> Result<(), Error> {
+        if format_version == 0 {
+            self.migrate_root_leaves()?;
+        }
+        if format_version < 2 {
+            self.backfill_child_subtrees()?;
+            self.backfill_back_references()?;
+        }
+        Ok(())
+    }
+
+    /// Moves root tree leaves of format version 0, stored in meta storage by
+    /// their keys with a hash of the root tree computed over the subtrees,
+    /// into the root tree Merk as tree elements. Root hash changes, as it is
+    /// the Merk one now. The subtrees are kept where they are.
+    fn migrate_root_leaves(&self) -> Result<(), Error> {
+        let meta_storage = self.db.get_storage_context(std::iter::empty());
+        let serialized = match meta_storage.get_meta(LEGACY_ROOT_LEAFS_KEY)? {
+            Some(serialized) => serialized,
+            None => return Ok(()),
+        };
+        let root_leaf_keys: BTreeMap<Vec<u8>, usize> = bincode::deserialize(&serialized)
+            .map_err(|_| Error::CorruptedData(String::from("unable to deserialize root leafs")))?;
+        let transaction = self.start_transaction();
+        for key in root_leaf_keys.keys() {
+            let subtree_storage = self
+                .db
+                .get_transactional_storage_context([key.as_slice()], &transaction);
+            let subtree = Merk::open(subtree_storage)
+                .map_err(|_| Error::CorruptedData("cannot open a subtree".to_owned()))?;
+            let element = Element::Tree(subtree.root_hash());
+            let root_storage = self
+                .db
+                .get_transactional_storage_context(std::iter::empty(), &transaction);
+            let mut root_tree = Merk::open(root_storage)
+                .map_err(|_| Error::CorruptedData("cannot open a subtree".to_owned()))?;
+            element.insert(&mut root_tree, key)?;
+        }
+        self.db
+            .get_transactional_storage_context(std::iter::empty(), &transaction)
+            .delete_meta(LEGACY_ROOT_LEAFS_KEY)?;
+        self.commit_transaction(transaction)
+    }
+
+    /// Returns whether nothing was written yet, in any format
+    fn is_empty(&self) -> Result<bool, Error> {
+        let meta_storage = self.db.get_storage_context(std::iter::empty());
+        if meta_storage.get_meta(LEGACY_ROOT_LEAFS_KEY)?.is_some() {
+            return Ok(false);
+        }
+        let root_tree = Merk::open(self.db.get_storage_context(std::iter::empty()))
+            .map_err(|_| Error::CorruptedData("cannot open a subtree".to_owned()))?;
+        Ok(root_tree.is_empty_tree())
+    }
+
+    pub(crate) fn require_feature(&self, feature: Feature) -> Result<(), Error> {
+        if self.is_feature_enabled(feature)? {
+            Ok(())
+        } else {
+            Err(Error::FeatureNotEnabled(feature.name()))
+        }
+    }
+
+    fn read_version(&self, transaction: TransactionArg) -> Result<GroveVersion, Error> {
+        let serialized = meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            meta_storage.get_meta(GROVE_VERSION_KEY)?
+        });
+        match serialized {
+            Some(serialized) => bincode::deserialize(&serialized).map_err(|_| {
+                Error::CorruptedData(String::from("unable to deserialize version record"))
+            }),
+            None if self.is_empty()? => Ok(GroveVersion::default()),
+            None => Ok(GroveVersion {
+                format_version: 0,
+                features: BTreeSet::new(),
+            }),
+        }
+    }
+
+    fn write_version(
+        &self,
+        version: &GroveVersion,
+        transaction: TransactionArg,
+    ) -> Result<(), Error> {
+        let serialized = bincode::serialize(version).map_err(|_| {
+            Error::CorruptedData(String::from("unable to serialize version record"))
+        })?;
+        meta_storage_context_optional_tx!(self.db, transaction, meta_storage, {
+            meta_storage.put_meta(GROVE_VERSION_KEY, &serialized)?;
+        });
+        Ok(())
+    }
+}
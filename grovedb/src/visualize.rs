@@ -3,7 +3,10 @@ use std::io::{Result, Write};
 use itertools::Itertools;
 use storage::StorageContext;
 
-use crate::{subtree::Element, util::storage_context_optional_tx, GroveDb, TransactionArg};
+use crate::{
+    path_display::printable_str, subtree::Element, util::storage_context_optional_tx, GroveDb,
+    TransactionArg,
+};
 
 static HEX_LEN: usize = 8;
 static STR_LEN: usize = 32;
@@ -65,13 +68,13 @@ fn to_hex(bytes: &[u8]) -> String {
 impl Visualize for [u8] {
     fn visualize<'a, W: Write>(&self, mut drawer: Drawer<'a, W>) -> Result<Drawer<'a, W>> {
         let hex_repr = to_hex(self);
-        let str_repr = String::from_utf8(self.to_vec());
+        let str_repr = printable_str(self);
         drawer.write(format!("[hex: {hex_repr}").as_bytes())?;
-        if let Ok(str_repr) = str_repr {
+        if let Some(str_repr) = str_repr {
             let str_part = if str_repr.len() > STR_LEN {
                 &str_repr[..=STR_LEN]
             } else {
-                &str_repr
+                str_repr
             };
             drawer.write(format!(", str: {str_part}").as_bytes())?;
         }
@@ -104,6 +107,14 @@ impl Visualize for Element {
                 drawer.write(b"tree: ")?;
                 drawer = hash.visualize(drawer)?;
             }
+            Element::PrunedTree(hash) => {
+                drawer.write(b"pruned tree: ")?;
+                drawer = hash.visualize(drawer)?;
+            }
+            Element::DedupItem(hash) => {
+                drawer.write(b"dedup item: ")?;
+                drawer = hash.visualize(drawer)?;
+            }
         }
         Ok(drawer)
     }
@@ -150,40 +161,13 @@ impl GroveDb {
         Ok(drawer)
     }
 
-    fn draw_root_tree<'a, W: Write>(
-        &self,
-        mut drawer: Drawer<'a, W>,
-        transaction: TransactionArg,
-    ) -> Result<Drawer<'a, W>> {
-        drawer.down();
-        let root_leaf_keys = self
-            .get_root_leaf_keys(transaction)
-            .expect("cannot get root leaf keys");
-        let keys = root_leaf_keys.iter().fold(
-            vec![Vec::new(); root_leaf_keys.len()],
-            |mut acc, (key, idx)| {
-                acc[*idx] = key.clone();
-                acc
-            },
-        );
-
-        for k in keys {
-            drawer.write(b"\n")?;
-            drawer = k.visualize(drawer)?;
-            drawer.write(b" tree:")?;
-            drawer = self.draw_subtree(drawer, vec![k], transaction)?
-        }
-        drawer.up();
-        Ok(drawer)
-    }
-
     fn visualize_start<'a, W: Write>(
         &self,
         mut drawer: Drawer<'a, W>,
         transaction: TransactionArg,
     ) -> Result<Drawer<'a, W>> {
         drawer.write(b"root")?;
-        drawer = self.draw_root_tree(drawer, transaction)?;
+        drawer = self.draw_subtree(drawer, Vec::new(), transaction)?;
         drawer.flush()?;
         Ok(drawer)
     }
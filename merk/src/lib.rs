@@ -1,3 +1,7 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 /// The top-level store API.
 #[cfg(feature = "full")]
 mod merk;
@@ -10,15 +14,25 @@ pub mod owner;
 pub mod proofs;
 
 /// Various helpers useful for tests or benchmarks.
+#[cfg(feature = "full")]
 pub mod test_utils;
 /// The core tree data structure.
+#[cfg(feature = "std")]
+pub mod tree;
+/// Hashes of tree nodes, the only part of the tree proof verification needs.
+#[cfg(not(feature = "std"))]
+#[path = "tree/hash.rs"]
 pub mod tree;
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 pub use proofs::query::verify_query;
-pub use proofs::query::{execute_proof, verify};
-pub use tree::{BatchEntry, Hash, MerkBatch, Op, PanicSource, HASH_LENGTH};
+pub use proofs::query::{
+    check_proof_limits, execute_proof, execute_proof_with_limits, verify, ProofLimits,
+};
+#[cfg(feature = "std")]
+pub use tree::{BatchEntry, MerkBatch, Op, PanicSource};
+pub use tree::{Hash, HASH_LENGTH};
 
-// #[cfg(feature = "full")]
-// // pub use crate::merk::{chunks, restore, Merk};
-pub use crate::merk::Merk;
+#[cfg(feature = "full")]
+pub use crate::merk::{chunks, handshake, restore, BalanceInfo, Merk, ROOT_KEY_KEY};
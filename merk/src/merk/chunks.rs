@@ -1,13 +1,126 @@
 //! Provides `ChunkProducer`, which creates chunk proofs for full replication of
 //! a Merk.
-use std::error::Error;
+use std::{
+    error::Error,
+    fmt,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, bail, Result};
 use ed::Encode;
 use storage::{RawIterator, StorageContext};
 
 use super::Merk;
-use crate::proofs::{chunk::get_next_chunk, Node, Op};
+use crate::{
+    proofs::{
+        chunk::{get_next_chunk, verify_leaf, verify_trunk, MIN_TRUNK_HEIGHT},
+        Decoder, Node, Op, Tree as ProofTree,
+    },
+    Hash, HASH_LENGTH,
+};
+
+/// A manifest of the chunks of a Merk, fetched before the chunks themselves to
+/// know what to expect and to resume an interrupted restore with
+/// [`super::restore::Restorer::resume`]. Chunks are identified by their index,
+/// the hash of the trunk chunk is the root hash and hashes of the following
+/// chunks are hashes of the subtrees the trunk connects to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkManifest {
+    trunk: Vec<u8>,
+    chunk_hashes: Vec<Hash>,
+}
+
+impl ChunkManifest {
+    /// Returns the number of chunks
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.chunk_hashes.len()
+    }
+
+    /// Returns the encoded trunk chunk
+    pub fn trunk(&self) -> &[u8] {
+        &self.trunk
+    }
+
+    /// Returns expected hashes of chunks ordered by their index
+    pub fn chunk_hashes(&self) -> &[Hash] {
+        &self.chunk_hashes
+    }
+
+    /// Checks that the trunk matches `expected_root_hash` and the chunk hashes
+    /// are the ones the trunk connects to, so the manifest can be trusted
+    /// regardless of the peer it was fetched from.
+    pub fn verify(&self, expected_root_hash: Hash) -> Result<()> {
+        let (trunk, height) = verify_trunk(Decoder::new(&self.trunk))?;
+        if trunk.hash() != expected_root_hash {
+            bail!("Manifest trunk does not match expected root hash");
+        }
+        if self.chunk_hashes != chunk_hashes(&trunk, height) {
+            bail!("Manifest chunk hashes do not match its trunk");
+        }
+        Ok(())
+    }
+
+    /// Checks a chunk against its hash in the manifest before processing it
+    pub fn verify_chunk(&self, index: usize, chunk_bytes: &[u8]) -> Result<()> {
+        let expected_hash = match self.chunk_hashes.get(index) {
+            Some(hash) => *hash,
+            None => bail!("Chunk index out-of-bounds"),
+        };
+        let hash = if index == 0 {
+            verify_trunk(Decoder::new(chunk_bytes))?.0.hash()
+        } else {
+            verify_leaf(Decoder::new(chunk_bytes), expected_hash)?.hash()
+        };
+        if hash != expected_hash {
+            bail!("Chunk {} does not match its hash in the manifest", index);
+        }
+        Ok(())
+    }
+
+    /// Encodes the manifest as the length of the trunk followed by the trunk
+    /// and chunk hashes
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + self.trunk.len() + self.len() * HASH_LENGTH);
+        bytes.extend_from_slice(&(self.trunk.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.trunk);
+        for hash in &self.chunk_hashes {
+            bytes.extend_from_slice(hash);
+        }
+        bytes
+    }
+
+    /// Decodes a manifest encoded by [`ChunkManifest::encode`]
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 4 {
+            bail!("Manifest is too short");
+        }
+        let (trunk_len, rest) = bytes.split_at(4);
+        let trunk_len = u32::from_be_bytes(trunk_len.try_into().unwrap()) as usize;
+        if rest.len() < trunk_len || (rest.len() - trunk_len) % HASH_LENGTH != 0 {
+            bail!("Manifest has invalid length");
+        }
+        let (trunk, hashes) = rest.split_at(trunk_len);
+        Ok(ChunkManifest {
+            trunk: trunk.to_vec(),
+            chunk_hashes: hashes
+                .chunks(HASH_LENGTH)
+                .map(|hash| hash.try_into().unwrap())
+                .collect(),
+        })
+    }
+}
+
+/// Returns hashes of the trunk and leaf chunks given by a verified trunk
+fn chunk_hashes(trunk: &ProofTree, height: usize) -> Vec<Hash> {
+    let mut hashes = vec![trunk.hash()];
+    let trunk_height = height / 2;
+    if trunk_height >= MIN_TRUNK_HEIGHT {
+        hashes.extend(trunk.layer(trunk_height).map(|node| node.hash()));
+    }
+    hashes
+}
 
 /// A `ChunkProducer` allows the creation of chunk proofs, used for trustlessly
 /// replicating entire Merk trees. Chunks can be generated on the fly in a
@@ -79,6 +192,58 @@ where
         self.next_chunk()
     }
 
+    /// Returns the manifest of chunks of the underlying Merk tree. Errors if
+    /// the tree is empty.
+    pub fn manifest(&self) -> Result<ChunkManifest> {
+        if self.trunk.is_empty() {
+            bail!("Attempted to fetch manifest of empty tree");
+        }
+        let trunk = self
+            .trunk
+            .encode()
+            .map_err(|e| anyhow!("cannot encode trunk: {}", e))?;
+        let (tree, height) = verify_trunk(Decoder::new(&trunk))?;
+        let chunk_hashes = chunk_hashes(&tree, height);
+        debug_assert_eq!(chunk_hashes.len(), self.len());
+        Ok(ChunkManifest {
+            trunk,
+            chunk_hashes,
+        })
+    }
+
+    /// Gets consecutive chunks starting from the given index until their total
+    /// size reaches `target_bytes` or there are no more chunks, at least one
+    /// chunk is returned. Chunks themselves can't be resized as their bounds
+    /// are given by the trunk, but batches of a target size can be sent to
+    /// peers and processed with `Restorer::process_chunks`.
+    pub fn chunk_batch(&mut self, index: usize, target_bytes: usize) -> Result<Vec<Vec<u8>>> {
+        let mut batch = vec![self.chunk(index)?];
+        let mut size = batch[0].len();
+        while size < target_bytes && self.index < self.len() {
+            let chunk = self.next_chunk()?;
+            size += chunk.len();
+            batch.push(chunk);
+        }
+        Ok(batch)
+    }
+
+    /// Gets the chunk with the given index like `chunk` does, waiting for the
+    /// pacer to let it out. The chunk is read from disk only once the pacer
+    /// lets it out, so reads are paced as well. The chunk counts as in flight
+    /// until the returned permit is dropped, e.g. once it's sent or
+    /// acknowledged by the peer.
+    pub fn chunk_paced<'p>(
+        &mut self,
+        index: usize,
+        pacer: &'p ChunkPacer,
+    ) -> Result<(Vec<u8>, ChunkPermit<'p>)> {
+        let permit = pacer.acquire();
+        pacer.throttle();
+        let chunk = self.chunk(index)?;
+        pacer.charge(chunk.len());
+        Ok((chunk, permit))
+    }
+
     /// Returns the total number of chunks for the underlying Merk tree.
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
@@ -121,6 +286,153 @@ where
     }
 }
 
+/// Source of time of a [`ChunkPacer`]
+pub trait PacerClock: fmt::Debug + Send + Sync {
+    /// Returns the time elapsed since a fixed point
+    fn now(&self) -> Duration;
+
+    /// Blocks the current thread for the duration
+    fn sleep(&self, duration: Duration);
+}
+
+/// Wall clock time
+#[derive(Debug)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl PacerClock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+impl<C: PacerClock + ?Sized> PacerClock for Arc<C> {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+/// Paces chunks served to syncing peers, so a node can feed many of them
+/// without saturating its disk or network. One pacer is meant to be shared by
+/// producers of all peers.
+#[derive(Debug)]
+pub struct ChunkPacer {
+    bytes_per_sec: Option<u64>,
+    max_in_flight: Option<usize>,
+    clock: Box<dyn PacerClock>,
+    state: Mutex<PacerState>,
+    released: Condvar,
+}
+
+#[derive(Debug)]
+struct PacerState {
+    in_flight: usize,
+    /// Clock time from which the next chunk may be read
+    next_send: Duration,
+}
+
+impl ChunkPacer {
+    /// Creates a pacer limiting served bytes per second and the number of
+    /// chunks in flight, `None` means no limit.
+    pub fn new(bytes_per_sec: Option<u64>, max_in_flight: Option<usize>) -> Self {
+        Self::with_clock(bytes_per_sec, max_in_flight, SystemClock::default())
+    }
+
+    /// Creates a pacer like `new` does, which measures time with the clock
+    pub fn with_clock(
+        bytes_per_sec: Option<u64>,
+        max_in_flight: Option<usize>,
+        clock: impl PacerClock + 'static,
+    ) -> Self {
+        let next_send = clock.now();
+        ChunkPacer {
+            bytes_per_sec,
+            max_in_flight,
+            clock: Box::new(clock),
+            state: Mutex::new(PacerState {
+                in_flight: 0,
+                next_send,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Returns the number of chunks in flight
+    pub fn in_flight(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .in_flight
+    }
+
+    /// Waits for a free in-flight slot
+    fn acquire(&self) -> ChunkPermit {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(max_in_flight) = self.max_in_flight {
+            while state.in_flight >= max_in_flight.max(1) {
+                state = self.released.wait(state).unwrap_or_else(|e| e.into_inner());
+            }
+        }
+        state.in_flight += 1;
+        ChunkPermit { pacer: self }
+    }
+
+    /// Waits until bytes read so far were sent at the rate
+    fn throttle(&self) {
+        if self.bytes_per_sec.is_none() {
+            return;
+        }
+        let send_at = self
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .next_send;
+        self.clock.sleep(send_at.saturating_sub(self.clock.now()));
+    }
+
+    /// Accounts for `bytes` read, delaying the next read accordingly
+    fn charge(&self, bytes: usize) {
+        let bytes_per_sec = match self.bytes_per_sec {
+            Some(bytes_per_sec) => bytes_per_sec.max(1),
+            None => return,
+        };
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.next_send = state.next_send.max(self.clock.now())
+            + Duration::from_secs_f64(bytes as f64 / bytes_per_sec as f64);
+    }
+}
+
+/// An in-flight slot of a [`ChunkPacer`], released on drop
+#[derive(Debug)]
+pub struct ChunkPermit<'p> {
+    pacer: &'p ChunkPacer,
+}
+
+impl Drop for ChunkPermit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.pacer.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.in_flight -= 1;
+        self.pacer.released.notify_one();
+    }
+}
+
 impl<'db, 'ctx, S> IntoIterator for ChunkProducer<'db, 'ctx, S>
 where
     S: StorageContext<'db, 'ctx> + 'ctx,
@@ -182,13 +494,23 @@ mod tests {
     use tempfile::TempDir;
 
     use super::*;
-    use crate::{
-        proofs::{
-            chunk::{verify_leaf, verify_trunk},
-            Decoder,
-        },
-        test_utils::*,
-    };
+    use crate::test_utils::*;
+
+    /// Clock advanced only by sleeping on it
+    #[derive(Debug, Default)]
+    struct ManualClock {
+        now: Mutex<Duration>,
+    }
+
+    impl PacerClock for ManualClock {
+        fn now(&self) -> Duration {
+            *self.now.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
 
     #[test]
     fn len_small() {
@@ -421,4 +743,81 @@ mod tests {
         let _chunk1 = producer.next_chunk();
         let _chunk2 = producer.next_chunk();
     }
+
+    #[test]
+    fn manifest() {
+        let mut merk = TempMerk::new();
+        let batch = make_batch_seq(1..10_000);
+        merk.apply::<_, Vec<_>>(&batch, &[]).unwrap();
+
+        let manifest = merk.chunks().unwrap().manifest().unwrap();
+        assert_eq!(manifest.len(), 129);
+        assert_eq!(manifest.chunk_hashes()[0], merk.root_hash());
+        manifest.verify(merk.root_hash()).unwrap();
+        assert!(manifest.verify([0; 32]).is_err());
+        assert_eq!(ChunkManifest::decode(&manifest.encode()).unwrap(), manifest);
+
+        let chunks = merk.chunks().unwrap().into_iter().map(Result::unwrap);
+        for (index, chunk) in chunks.enumerate() {
+            manifest.verify_chunk(index, &chunk).unwrap();
+        }
+        let chunk = merk.chunks().unwrap().chunk(1).unwrap();
+        assert!(manifest.verify_chunk(2, &chunk).is_err());
+    }
+
+    #[test]
+    fn chunk_batch() {
+        let mut merk = TempMerk::new();
+        let batch = make_batch_seq(1..10_000);
+        merk.apply::<_, Vec<_>>(&batch, &[]).unwrap();
+
+        let chunks = merk
+            .chunks()
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        let mut producer = merk.chunks().unwrap();
+
+        assert_eq!(producer.chunk_batch(3, 0).unwrap(), chunks[3..4]);
+        let target = chunks[3].len() + chunks[4].len() + 1;
+        assert_eq!(producer.chunk_batch(3, target).unwrap(), chunks[3..6]);
+        assert_eq!(
+            producer.chunk_batch(120, usize::MAX).unwrap(),
+            chunks[120..]
+        );
+    }
+
+    #[test]
+    fn chunk_paced() {
+        let mut merk = TempMerk::new();
+        let batch = make_batch_seq(1..10_000);
+        merk.apply::<_, Vec<_>>(&batch, &[]).unwrap();
+
+        let mut producer = merk.chunks().unwrap();
+        let chunk_len = producer.chunk(1).unwrap().len() as u64;
+        // two chunks per second
+        let clock = Arc::new(ManualClock::default());
+        let pacer = ChunkPacer::with_clock(Some(chunk_len * 2), Some(2), clock.clone());
+
+        let (chunk, first) = producer.chunk_paced(1, &pacer).unwrap();
+        assert_eq!(chunk.len() as u64, chunk_len);
+        assert_eq!(clock.now(), Duration::ZERO);
+        let (_, second) = producer.chunk_paced(1, &pacer).unwrap();
+        assert_eq!(clock.now(), Duration::from_millis(500));
+        assert_eq!(pacer.in_flight(), 2);
+        drop(first);
+        let (_, _third) = producer.chunk_paced(1, &pacer).unwrap();
+        assert_eq!(clock.now(), Duration::from_secs(1));
+
+        std::thread::scope(|scope| {
+            let waiting = scope.spawn(|| {
+                let _fourth = pacer.acquire();
+            });
+            assert_eq!(pacer.in_flight(), 2);
+            drop(second);
+            waiting.join().unwrap();
+        });
+        assert_eq!(pacer.in_flight(), 1);
+    }
 }
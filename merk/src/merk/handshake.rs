@@ -0,0 +1,178 @@
+//! Provides `SyncHandshake`, which peers exchange before replicating a Merk
+//! with chunk proofs to agree on the protocol version and capabilities, or to
+//! refuse syncing when they have nothing in common.
+
+use anyhow::{bail, Result};
+
+/// State sync protocol version of this release
+pub const SYNC_PROTOCOL_VERSION: u32 = 1;
+/// The oldest state sync protocol version this release can sync with
+pub const MIN_SYNC_PROTOCOL_VERSION: u32 = 1;
+
+/// Nodes are hashed with blake3
+pub const CAPABILITY_HASH_BLAKE3: u64 = 1 << 0;
+/// Trunk and leaf chunks as produced by `ChunkProducer`
+pub const CAPABILITY_CHUNK_FORMAT_V1: u64 = 1 << 1;
+/// Chunk manifests for resuming restores
+pub const CAPABILITY_CHUNK_MANIFEST: u64 = 1 << 2;
+/// Values stored compressed, see `Op::PutCompressed`
+pub const CAPABILITY_COMPRESSED_VALUES: u64 = 1 << 3;
+/// Capabilities below this bit are defined by Merk, the ones above by the
+/// application, e.g. for the element types it stores
+pub const APPLICATION_CAPABILITIES_SHIFT: u32 = 32;
+
+/// Capabilities a peer of this release has
+pub const DEFAULT_CAPABILITIES: u64 = CAPABILITY_HASH_BLAKE3
+    | CAPABILITY_CHUNK_FORMAT_V1
+    | CAPABILITY_CHUNK_MANIFEST
+    | CAPABILITY_COMPRESSED_VALUES;
+/// Capabilities without which chunks of this release can't be verified
+pub const DEFAULT_REQUIRED_CAPABILITIES: u64 = CAPABILITY_HASH_BLAKE3 | CAPABILITY_CHUNK_FORMAT_V1;
+
+/// Protocol version and capability bits a peer sends before syncing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncHandshake {
+    pub protocol_version: u32,
+    /// Capabilities the peer supports
+    pub capabilities: u64,
+    /// Capabilities the peer can't sync without, a subset of `capabilities`
+    pub required: u64,
+}
+
+impl Default for SyncHandshake {
+    fn default() -> Self {
+        SyncHandshake {
+            protocol_version: SYNC_PROTOCOL_VERSION,
+            capabilities: DEFAULT_CAPABILITIES,
+            required: DEFAULT_REQUIRED_CAPABILITIES,
+        }
+    }
+}
+
+impl SyncHandshake {
+    /// Returns the handshake with an application capability, `bit` is counted
+    /// from `APPLICATION_CAPABILITIES_SHIFT` and must be below 32
+    pub fn with_application_capability(mut self, bit: u32, required: bool) -> Self {
+        assert!(
+            bit < u64::BITS - APPLICATION_CAPABILITIES_SHIFT,
+            "application capability bit {} is out of range",
+            bit
+        );
+        let capability = 1u64 << (APPLICATION_CAPABILITIES_SHIFT + bit);
+        self.capabilities |= capability;
+        if required {
+            self.required |= capability;
+        }
+        self
+    }
+
+    /// Returns whether all of the capabilities are supported
+    pub fn supports(&self, capabilities: u64) -> bool {
+        self.capabilities & capabilities == capabilities
+    }
+
+    /// Agrees on the highest protocol version and the capabilities both peers
+    /// support, fails if the peer's version is too old or either side misses
+    /// a capability the other requires.
+    pub fn negotiate(&self, peer: &SyncHandshake) -> Result<SyncHandshake> {
+        let protocol_version = self.protocol_version.min(peer.protocol_version);
+        if protocol_version < MIN_SYNC_PROTOCOL_VERSION {
+            bail!(
+                "State sync protocol version {} is older than supported version {}",
+                protocol_version,
+                MIN_SYNC_PROTOCOL_VERSION
+            );
+        }
+        let capabilities = self.capabilities & peer.capabilities;
+        let required = self.required | peer.required;
+        let missing = required & !capabilities;
+        if missing != 0 {
+            bail!(
+                "Required state sync capabilities {:#x} are missing",
+                missing
+            );
+        }
+        Ok(SyncHandshake {
+            protocol_version,
+            capabilities,
+            required,
+        })
+    }
+
+    /// Encodes the handshake as big endian protocol version, capabilities and
+    /// required capabilities
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(20);
+        bytes.extend_from_slice(&self.protocol_version.to_be_bytes());
+        bytes.extend_from_slice(&self.capabilities.to_be_bytes());
+        bytes.extend_from_slice(&self.required.to_be_bytes());
+        bytes
+    }
+
+    /// Decodes a handshake encoded by [`SyncHandshake::encode`]
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != 20 {
+            bail!("Handshake has invalid length");
+        }
+        Ok(SyncHandshake {
+            protocol_version: u32::from_be_bytes(bytes[..4].try_into().unwrap()),
+            capabilities: u64::from_be_bytes(bytes[4..12].try_into().unwrap()),
+            required: u64::from_be_bytes(bytes[12..].try_into().unwrap()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        let local = SyncHandshake::default();
+        let peer = SyncHandshake {
+            capabilities: DEFAULT_REQUIRED_CAPABILITIES,
+            ..Default::default()
+        };
+        let agreed = local.negotiate(&peer).unwrap();
+        assert_eq!(agreed.capabilities, DEFAULT_REQUIRED_CAPABILITIES);
+        assert!(!agreed.supports(CAPABILITY_CHUNK_MANIFEST));
+        assert_eq!(agreed, peer.negotiate(&local).unwrap());
+
+        let newer = SyncHandshake {
+            protocol_version: SYNC_PROTOCOL_VERSION + 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            local.negotiate(&newer).unwrap().protocol_version,
+            SYNC_PROTOCOL_VERSION
+        );
+
+        let older = SyncHandshake {
+            protocol_version: MIN_SYNC_PROTOCOL_VERSION - 1,
+            ..Default::default()
+        };
+        assert!(local.negotiate(&older).is_err());
+
+        let with_application = local.with_application_capability(3, true);
+        assert!(local.negotiate(&with_application).is_err());
+        assert!(with_application
+            .negotiate(&SyncHandshake::default().with_application_capability(3, false))
+            .is_ok());
+    }
+
+    #[test]
+    fn encode_decode() {
+        let handshake = SyncHandshake::default().with_application_capability(1, true);
+        assert_eq!(
+            SyncHandshake::decode(&handshake.encode()).unwrap(),
+            handshake
+        );
+        assert!(SyncHandshake::decode(&[0; 19]).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn application_capability_out_of_range() {
+        SyncHandshake::default().with_application_capability(32, false);
+    }
+}
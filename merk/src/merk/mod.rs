@@ -1,6 +1,6 @@
 pub mod chunks;
-// TODO
-// pub mod restore;
+pub mod handshake;
+pub mod restore;
 use std::{cell::Cell, cmp::Ordering, collections::LinkedList, fmt};
 
 use anyhow::{anyhow, bail, Result};
@@ -11,7 +11,8 @@ use crate::{
     tree::{Commit, Fetch, Hash, Link, MerkBatch, Op, RefWalker, Tree, Walker, NULL_HASH},
 };
 
-const ROOT_KEY_KEY: &[u8] = b"root";
+/// A key in roots storage to store the key of the tree root node
+pub const ROOT_KEY_KEY: &[u8] = b"root";
 
 /// A handle to a Merkle key/value store backed by RocksDB.
 pub struct Merk<S> {
@@ -27,6 +28,22 @@ impl<S> fmt::Debug for Merk<S> {
 
 pub type UseTreeMutResult = Result<Vec<(Vec<u8>, Option<Vec<u8>>)>>;
 
+/// Statistics on the shape of a Merk tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BalanceInfo {
+    /// Number of nodes in the tree
+    pub node_count: usize,
+    /// Actual height of the tree
+    pub height: u8,
+    /// Minimal possible height of a tree with the same number of nodes
+    pub optimal_height: u8,
+    /// Number of nodes with balance factor out of `[-1, 1]`, which are the
+    /// nodes rebalancing would rotate
+    pub unbalanced_nodes: usize,
+    /// The largest absolute balance factor among the tree nodes
+    pub max_balance_factor: u8,
+}
+
 impl<'db, 'ctx, S> Merk<S>
 where
     S: StorageContext<'db, 'ctx> + 'ctx,
@@ -44,18 +61,51 @@ where
 
     /// Deletes tree data
     pub fn clear(&'ctx mut self) -> Result<()> {
+        let mut to_delete = self.storage.new_batch();
+        to_delete.clear()?;
+        self.storage.commit_batch(to_delete)?;
+        self.tree.set(None);
+        Ok(())
+    }
+
+    /// Collects statistics on the tree shape by reading every node from
+    /// storage.
+    pub fn balance_info(&self) -> Result<BalanceInfo> {
+        let mut info = BalanceInfo {
+            height: self.height(),
+            ..Default::default()
+        };
         let mut iter = self.storage.raw_iter();
         iter.seek_to_first();
-        let mut to_delete = self.storage.new_batch();
-        while iter.valid() {
-            if let Some(key) = iter.key() {
-                to_delete.delete(key)?;
+        while let Some(value) = iter.value() {
+            let node = Tree::decode_raw(value)?;
+            let balance_factor = node.balance_factor().unsigned_abs();
+            if balance_factor > 1 {
+                info.unbalanced_nodes += 1;
             }
+            info.max_balance_factor = info.max_balance_factor.max(balance_factor);
+            info.node_count += 1;
+            iter.next();
+        }
+        info.optimal_height = (usize::BITS - info.node_count.leading_zeros()) as u8;
+        Ok(info)
+    }
+
+    /// Rewrites the tree into a balanced form keeping all its key/value pairs.
+    /// Since all keys are kept, the new nodes overwrite the old ones within
+    /// one batch. Note that the root hash changes as it depends on the tree
+    /// structure.
+    pub fn rebuild(&'ctx mut self) -> Result<()> {
+        let mut batch = Vec::new();
+        let mut iter = self.storage.raw_iter();
+        iter.seek_to_first();
+        while let Some((key, value)) = iter.key().zip(iter.value()) {
+            let node = Tree::decode_raw(value)?;
+            batch.push((key.to_vec(), Op::Put(node.value().to_vec())));
             iter.next();
         }
-        self.storage.commit_batch(to_delete)?;
         self.tree.set(None);
-        Ok(())
+        self.apply::<_, Vec<u8>>(&batch, &[])
     }
 
     /// Gets an auxiliary value.
@@ -72,6 +122,23 @@ where
         self.get_node_fn(key, |node| node.value().to_vec())
     }
 
+    /// Gets values for many keys with one batched storage read, in the order
+    /// of `keys`. `None` is returned for keys which are not found.
+    ///
+    /// Nodes are read from storage and not from the tree held in memory, which
+    /// is the same as all of its nodes are committed.
+    pub fn get_many(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.storage
+            .get_many(keys)?
+            .into_iter()
+            .map(|bytes| {
+                bytes
+                    .map(|bytes| Tree::decode_raw(&bytes).map(|node| node.value().to_vec()))
+                    .transpose()
+            })
+            .collect()
+    }
+
     /// Gets a hash of a node by a given key, `None` is returned in case
     /// when node not found by the key.
     pub fn get_hash(&self, key: &[u8]) -> Result<Option<[u8; 32]>> {
@@ -120,6 +187,13 @@ where
         self.use_tree(|tree| tree.map_or(NULL_HASH, |tree| tree.hash()))
     }
 
+    /// Returns the height of the tree, which bounds the number of nodes on the
+    /// path to any key and so the depth of proofs. An empty tree has zero
+    /// height.
+    pub fn height(&self) -> u8 {
+        self.use_tree(|tree| tree.map_or(0, |tree| tree.height()))
+    }
+
     /// Applies a batch of operations (puts and deletes) to the tree.
     ///
     /// This will fail if the keys in `batch` are not sorted and unique. This
@@ -308,7 +382,7 @@ where
 
         for (key, value) in aux {
             match value {
-                Op::Put(value) => batch.put_aux(key, value)?,
+                Op::Put(value) | Op::PutCompressed(value) => batch.put_aux(key, value)?,
                 Op::Delete => batch.delete_aux(key)?,
             };
         }
@@ -450,7 +524,7 @@ mod test {
     };
     use tempfile::TempDir;
 
-    use super::{Merk, MerkSource, RefWalker};
+    use super::{BalanceInfo, Merk, MerkSource, RefWalker};
     use crate::{test_utils::*, Op};
 
     // TODO: Close and then reopen test
@@ -530,6 +604,24 @@ mod test {
         assert_eq!(val, Some(vec![4, 5, 6]));
     }
 
+    #[test]
+    fn get_many() {
+        let mut merk = TempMerk::new();
+        let batch = make_batch_seq(0..100);
+        merk.apply::<_, Vec<_>>(&batch, &[]).expect("apply failed");
+
+        let keys = [seq_key(42), seq_key(1000), seq_key(0), seq_key(99)];
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+        let values = merk.get_many(&keys).expect("get_many failed");
+        let expected: Vec<_> = keys
+            .iter()
+            .map(|key| merk.get(key).expect("get failed"))
+            .collect();
+        assert_eq!(values, expected);
+        assert!(values[0].is_some());
+        assert!(values[1].is_none());
+    }
+
     #[test]
     fn simulated_crash() {
         let mut merk = CrashMerk::open().expect("failed to open merk");
@@ -550,6 +642,36 @@ mod test {
         assert_eq!(merk.get_aux(&[2]).unwrap(), Some(vec![3]));
     }
 
+    #[test]
+    fn balance_info_and_rebuild() {
+        let mut merk = TempMerk::new();
+        assert_eq!(
+            merk.balance_info().expect("balance info failed"),
+            BalanceInfo::default()
+        );
+
+        let batch = make_batch_seq(0..100);
+        merk.apply::<_, Vec<_>>(&batch, &[]).expect("apply failed");
+        merk.apply::<_, Vec<_>>(&make_batch_seq(100..150), &[])
+            .expect("apply failed");
+        let info = merk.balance_info().expect("balance info failed");
+        assert_eq!(info.node_count, 150);
+        assert_eq!(info.optimal_height, 8);
+        assert_eq!(info.unbalanced_nodes, 0);
+        assert!(info.max_balance_factor <= 1);
+
+        merk.rebuild().expect("rebuild failed");
+        assert_invariants(&merk);
+        let info = merk.balance_info().expect("balance info failed");
+        assert_eq!(info.node_count, 150);
+        assert_eq!(info.height, info.optimal_height);
+        for (key, op) in batch {
+            if let Op::Put(value) = op {
+                assert_eq!(merk.get(&key).expect("get failed"), Some(value));
+            }
+        }
+    }
+
     #[test]
     fn get_not_found() {
         let mut merk = TempMerk::new();
@@ -656,4 +778,37 @@ mod test {
 
         assert_eq!(reopen_nodes, original_nodes);
     }
+
+    #[test]
+    fn reopen_compressed_values() {
+        let tmp_dir = TempDir::new().expect("cannot open tempdir");
+        let batch: Vec<_> = (0u8..100)
+            .map(|i| (vec![i], Op::PutCompressed(vec![i; 1000])))
+            .collect();
+        let plain_batch: Vec<_> = (0u8..100)
+            .map(|i| (vec![i], Op::Put(vec![i; 1000])))
+            .collect();
+
+        {
+            let storage = RocksDbStorage::default_rocksdb_with_path(tmp_dir.path())
+                .expect("cannot open rocksdb storage");
+            let mut merk =
+                Merk::open(storage.get_storage_context(empty())).expect("cannot open merk");
+            merk.apply::<_, Vec<_>>(batch.as_slice(), &[]).unwrap();
+        }
+        let mut plain_merk = TempMerk::new();
+        plain_merk
+            .apply::<_, Vec<_>>(plain_batch.as_slice(), &[])
+            .unwrap();
+
+        let storage = RocksDbStorage::default_rocksdb_with_path(tmp_dir.path())
+            .expect("cannot open rocksdb storage");
+        let merk = Merk::open(storage.get_storage_context(empty())).expect("cannot open merk");
+        assert_eq!(merk.root_hash(), plain_merk.root_hash());
+        assert_eq!(merk.get(&[42]).unwrap(), Some(vec![42; 1000]));
+
+        let mut iter = merk.storage.raw_iter();
+        iter.seek_to_first();
+        assert!(iter.value().unwrap().len() < 1000);
+    }
 }
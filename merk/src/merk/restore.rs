@@ -1,68 +1,124 @@
 //! Provides `Restorer`, which can create a replica of a Merk instance by
 //! receiving chunk proofs.
 
-use super::Merk;
+use std::iter::Peekable;
+
+use anyhow::{bail, Result};
+use storage::{Batch, StorageContext};
+
+use super::{chunks::ChunkManifest, Merk, MerkSource, ROOT_KEY_KEY};
 use crate::{
-    merk::MerkSource,
     proofs::{
         chunk::{verify_leaf, verify_trunk, MIN_TRUNK_HEIGHT},
         tree::{Child, Tree as ProofTree},
         Decoder, Node,
     },
     tree::{Link, RefWalker, Tree},
-    Hash, Result,
+    Hash,
 };
-use failure::bail;
-use rocksdb::WriteBatch;
-use std::iter::Peekable;
-use std::{path::Path, u8};
 
 /// A `Restorer` handles decoding, verifying, and storing chunk proofs to
 /// replicate an entire Merk tree. It expects the chunks to be processed in
 /// order, retrying the last chunk if verification fails.
-pub struct Restorer<'a> {
+pub struct Restorer<S> {
     leaf_hashes: Option<Peekable<std::vec::IntoIter<Hash>>>,
     parent_keys: Option<Peekable<std::vec::IntoIter<Vec<u8>>>>,
     trunk_height: Option<usize>,
-    merk: Merk<'a>,
+    merk: Merk<S>,
     expected_root_hash: Hash,
     stated_length: usize,
+    workers: usize,
 }
 
-impl<'a> Restorer<'a> {
-    /// Creates a new `Restorer`, which will initialize a new Merk at the given
-    /// file path. The first chunk (the "trunk") will be compared against
-    /// `expected_root_hash`, then each subsequent chunk will be compared
-    /// against the hashes stored in the trunk, so that the restore process will
-    /// never allow malicious peers to send more than a single invalid chunk.
+/// Encoded nodes of a verified chunk by their keys
+type ChunkNodes = Vec<(Vec<u8>, Vec<u8>)>;
+
+impl<'db, S> Restorer<S>
+where
+    S: for<'ctx> StorageContext<'db, 'ctx>,
+{
+    /// Creates a new `Restorer`, which will write a new Merk into the given
+    /// storage context, which must be empty. The first chunk (the "trunk")
+    /// will be compared against `expected_root_hash`, then each subsequent
+    /// chunk will be compared against the hashes stored in the trunk, so that
+    /// the restore process will never allow malicious peers to send more than
+    /// a single invalid chunk.
     ///
     /// The `stated_length` should be the number of chunks stated by the peer,
     /// which will be verified after processing a valid first chunk to make it
     /// easier to download chunks from peers without needing to trust this
     /// length.
-    pub fn new<P: AsRef<Path>>(
-        db_path: P,
-        prefix: &[u8],
-        expected_root_hash: Hash,
-        stated_length: usize,
-    ) -> Result<Self> {
-        if db_path.as_ref().exists() {
-            bail!("The given path already exists");
+    pub fn new(storage: S, expected_root_hash: Hash, stated_length: usize) -> Result<Self> {
+        let merk = Merk::open(storage)?;
+        if !merk.is_empty_tree() {
+            bail!("The given storage is not empty");
         }
 
         Ok(Self {
             expected_root_hash,
             stated_length,
             trunk_height: None,
-            merk: Merk::open(db_path, prefix)?,
+            merk,
             leaf_hashes: None,
             parent_keys: None,
+            workers: 1,
         })
     }
 
-    /// Verifies a chunk and writes it to the working RocksDB instance. Expects
-    /// to be called for each chunk in order. Returns the number of remaining
-    /// chunks.
+    /// Continues an interrupted restore into the given storage context, which
+    /// holds the first `already_have` chunks of the manifest processed by a
+    /// previous `Restorer`. The trunk is taken from the manifest and verified
+    /// against `expected_root_hash` without being written again, so the next
+    /// chunk to process is the one with index `already_have`.
+    pub fn resume(
+        storage: S,
+        expected_root_hash: Hash,
+        manifest: &ChunkManifest,
+        already_have: usize,
+    ) -> Result<Self> {
+        if already_have == 0 {
+            return Self::new(storage, expected_root_hash, manifest.len());
+        }
+        if already_have > manifest.len() {
+            bail!(
+                "Already have {} chunks but the manifest has only {}",
+                already_have,
+                manifest.len()
+            );
+        }
+        manifest.verify(expected_root_hash)?;
+
+        let merk = Merk::open(storage)?;
+        if merk.is_empty_tree() {
+            bail!("The given storage has no chunks to resume from");
+        }
+
+        let mut restorer = Self {
+            expected_root_hash,
+            stated_length: manifest.len(),
+            trunk_height: None,
+            merk,
+            leaf_hashes: None,
+            parent_keys: None,
+            workers: 1,
+        };
+        restorer.load_trunk(Decoder::new(manifest.trunk()))?;
+        for _ in 1..already_have {
+            restorer.skip_leaf();
+        }
+
+        Ok(restorer)
+    }
+
+    /// Sets the number of threads verifying leaf chunks passed to
+    /// `process_chunks`, one by default.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Verifies a chunk and writes it to the storage. Expects to be called for
+    /// each chunk in order. Returns the number of remaining chunks.
     ///
     /// Once there are no remaining chunks to be processed, `finalize` should
     /// be called.
@@ -75,12 +131,74 @@ impl<'a> Restorer<'a> {
         }
     }
 
+    /// Processes consecutive chunks like `process_chunk` does one by one, with
+    /// leaf chunks verified on up to the configured number of worker threads.
+    /// Chunks are still written in order, so if one fails verification the
+    /// chunks preceding it are kept and the restore continues from it.
+    /// Returns the number of remaining chunks.
+    pub fn process_chunks<C: AsRef<[u8]> + Sync>(&mut self, chunks: &[C]) -> Result<usize> {
+        let mut chunks = chunks;
+        if self.leaf_hashes.is_none() {
+            match chunks.split_first() {
+                Some((trunk, rest)) => {
+                    self.process_chunk(trunk.as_ref())?;
+                    chunks = rest;
+                }
+                None => bail!("Expected the trunk chunk first"),
+            }
+        }
+
+        let leaf_hashes: Vec<Hash> = self
+            .leaf_hashes
+            .clone()
+            .unwrap()
+            .take(chunks.len())
+            .collect();
+        if leaf_hashes.is_empty() {
+            if !chunks.is_empty() {
+                bail!("Received more chunks than expected");
+            }
+            return Ok(self.remaining_chunks_unchecked());
+        }
+
+        let per_worker = (leaf_hashes.len() + self.workers - 1) / self.workers;
+        let verified: Vec<Result<(Vec<u8>, ChunkNodes)>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .chunks(per_worker)
+                .zip(leaf_hashes.chunks(per_worker))
+                .map(|(chunks, leaf_hashes)| {
+                    scope.spawn(move || {
+                        chunks
+                            .iter()
+                            .zip(leaf_hashes)
+                            .map(|(chunk, leaf_hash)| verify_leaf_chunk(chunk.as_ref(), *leaf_hash))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("chunk verification panicked"))
+                .collect()
+        });
+
+        for leaf in verified {
+            let (leaf_key, nodes) = leaf?;
+            self.write_leaf(&leaf_key, nodes)?;
+        }
+        if chunks.len() > leaf_hashes.len() {
+            bail!("Received more chunks than expected");
+        }
+
+        Ok(self.remaining_chunks_unchecked())
+    }
+
     /// Consumes the `Restorer` and returns the newly-created, fully-populated
     /// Merk instance. This method will return an error if called before
     /// processing all chunks (e.g. `restorer.remaining_chunks()` is not equal
     /// to 0).
-    pub fn finalize(mut self) -> Result<Merk<'a>> {
-        if self.remaining_chunks().is_none() || self.remaining_chunks().unwrap() != 0 {
+    pub fn finalize(mut self) -> Result<Merk<S>> {
+        if self.remaining_chunks() != Some(0) {
             bail!("Called finalize before all chunks were processed");
         }
 
@@ -88,7 +206,6 @@ impl<'a> Restorer<'a> {
             self.rewrite_trunk_child_heights()?;
         }
 
-        self.merk.flush()?;
         self.merk.load_root()?;
 
         Ok(self.merk)
@@ -101,35 +218,27 @@ impl<'a> Restorer<'a> {
         self.leaf_hashes.as_ref().map(|lh| lh.len())
     }
 
-    /// Writes the data contained in `tree` (extracted from a verified chunk
-    /// proof) to the RocksDB.
-    fn write_chunk(&mut self, tree: ProofTree) -> Result<()> {
-        let mut batch = WriteBatch::default();
-
-        tree.visit_refs(&mut |proof_node| {
-            let (key, value) = match &proof_node.node {
-                Node::KV(key, value) => (key, value),
-                _ => return,
-            };
-
-            // TODO: encode tree node without cloning key/value
-            let mut node = Tree::new(key.clone(), value.clone());
-            *node.slot_mut(true) = proof_node.left.as_ref().map(Child::as_link);
-            *node.slot_mut(false) = proof_node.right.as_ref().map(Child::as_link);
-
-            let bytes = node.encode();
-            batch.put(key, bytes);
-        });
-
-        self.merk.write(batch)
+    /// Writes nodes of a verified chunk to the storage in one batch, with the
+    /// root key if given, so a chunk is either written entirely or not at all.
+    fn write_nodes(&self, nodes: ChunkNodes, root_key: Option<&[u8]>) -> Result<()> {
+        let mut batch = self.merk.storage.new_batch();
+        for (key, bytes) in nodes {
+            batch.put(key, &bytes)?;
+        }
+        if let Some(root_key) = root_key {
+            batch.put_root(ROOT_KEY_KEY, root_key)?;
+        }
+        self.merk.storage.commit_batch(batch)?;
+        Ok(())
     }
 
-    /// Verifies the trunk then writes its data to the RocksDB.
+    /// Verifies the trunk and sets up the leaf chunks it connects to, without
+    /// writing anything.
     ///
     /// The trunk contains a height proof which lets us verify the total number
     /// of expected chunks is the same as `stated_length` as passed into
     /// `Restorer::new()`. We also verify the expected root hash at this step.
-    fn process_trunk(&mut self, ops: Decoder) -> Result<usize> {
+    fn load_trunk(&mut self, ops: Decoder) -> Result<ProofTree> {
         let (trunk, height) = verify_trunk(ops)?;
 
         if trunk.hash() != self.expected_root_hash {
@@ -140,145 +249,143 @@ impl<'a> Restorer<'a> {
             );
         }
 
-        let root_key = trunk.key().to_vec();
-
         let trunk_height = height / 2;
-        self.trunk_height = Some(trunk_height);
 
         let chunks_remaining = if trunk_height >= MIN_TRUNK_HEIGHT {
             let leaf_hashes = trunk
                 .layer(trunk_height)
                 .map(|node| node.hash())
-                .collect::<Vec<Hash>>()
-                .into_iter()
-                .peekable();
-            self.leaf_hashes = Some(leaf_hashes);
-
+                .collect::<Vec<Hash>>();
             let parent_keys = trunk
                 .layer(trunk_height - 1)
                 .map(|node| node.key().to_vec())
-                .collect::<Vec<Vec<u8>>>()
-                .into_iter()
-                .peekable();
-            self.parent_keys = Some(parent_keys);
-            assert_eq!(
-                self.parent_keys.as_ref().unwrap().len(),
-                self.leaf_hashes.as_ref().unwrap().len() / 2
-            );
+                .collect::<Vec<Vec<u8>>>();
+            assert_eq!(parent_keys.len(), leaf_hashes.len() / 2);
+            assert_eq!(leaf_hashes.len(), 2_usize.pow(trunk_height as u32));
 
-            let chunks_remaining = (2_usize).pow(trunk_height as u32);
-            assert_eq!(self.remaining_chunks_unchecked(), chunks_remaining);
-            chunks_remaining
+            self.leaf_hashes = Some(leaf_hashes.into_iter().peekable());
+            self.parent_keys = Some(parent_keys.into_iter().peekable());
+            self.remaining_chunks_unchecked()
         } else {
             self.leaf_hashes = Some(vec![].into_iter().peekable());
             self.parent_keys = Some(vec![].into_iter().peekable());
             0
         };
+        self.trunk_height = Some(trunk_height);
+
+        if self.stated_length != chunks_remaining + 1 {
+            bail!(
+                "Stated length {} does not match the number of chunks {}",
+                self.stated_length,
+                chunks_remaining + 1
+            );
+        }
 
-        // FIXME: this one shouldn't be an assert because it comes from a peer
-        assert_eq!(self.stated_length, chunks_remaining + 1);
+        Ok(trunk)
+    }
 
-        // note that these writes don't happen atomically, which is fine here
-        // because if anything fails during the restore process we will just
-        // scrap the whole restore and start over
-        self.write_chunk(trunk)?;
-        self.merk.set_root_key(root_key)?;
+    /// Verifies the trunk then writes its data to the storage.
+    fn process_trunk(&mut self, ops: Decoder) -> Result<usize> {
+        let trunk = self.load_trunk(ops)?;
+        self.write_nodes(chunk_nodes(&trunk), Some(trunk.key()))?;
 
-        Ok(chunks_remaining)
+        Ok(self.remaining_chunks_unchecked())
     }
 
-    /// Verifies a leaf chunk then writes it to the RocksDB. This needs to be
+    /// Verifies a leaf chunk then writes it to the storage. This needs to be
     /// called in order, retrying the last chunk for any failed verifications.
     fn process_leaf(&mut self, ops: Decoder) -> Result<usize> {
         let leaf_hashes = self.leaf_hashes.as_mut().unwrap();
-        let leaf_hash = leaf_hashes
-            .peek()
-            .expect("Received more chunks than expected");
-
-        let leaf = verify_leaf(ops, *leaf_hash)?;
-        self.rewrite_parent_link(&leaf)?;
-        self.write_chunk(leaf)?;
+        let leaf_hash = match leaf_hashes.peek() {
+            Some(leaf_hash) => *leaf_hash,
+            None => bail!("Received more chunks than expected"),
+        };
 
-        let leaf_hashes = self.leaf_hashes.as_mut().unwrap();
-        leaf_hashes.next();
+        let leaf = verify_leaf(ops, leaf_hash)?;
+        self.write_leaf(leaf.key(), chunk_nodes(&leaf))?;
 
         Ok(self.remaining_chunks_unchecked())
     }
 
+    /// Writes nodes of a verified leaf chunk together with its parent linking
+    /// to it, then moves on to the next leaf chunk.
+    fn write_leaf(&mut self, leaf_key: &[u8], mut nodes: ChunkNodes) -> Result<()> {
+        nodes.push(self.rewrite_parent_link(leaf_key)?);
+        self.write_nodes(nodes, None)?;
+        self.skip_leaf();
+        Ok(())
+    }
+
+    /// Moves on to the next leaf chunk, advancing to the next parent after the
+    /// right child of the current one.
+    fn skip_leaf(&mut self) {
+        let is_left_child = self.remaining_chunks_unchecked() % 2 == 0;
+        self.leaf_hashes.as_mut().unwrap().next();
+        if !is_left_child {
+            self.parent_keys.as_mut().unwrap().next();
+        }
+    }
+
     /// The parent of the root node of the leaf does not know the key of its
     /// children when it is first written. Now that we have verified this leaf,
     /// we can write the key into the parent node's entry. Note that this does
     /// not need to recalcuate hashes since it already had the child hash.
-    fn rewrite_parent_link(&mut self, leaf: &ProofTree) -> Result<()> {
+    /// Returns the key and the encoding of the rewritten parent.
+    fn rewrite_parent_link(&mut self, leaf_key: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
         let parent_keys = self.parent_keys.as_mut().unwrap();
         let parent_key = parent_keys.peek().unwrap().clone();
-        let mut parent = self
-            .merk
-            .fetch_node(parent_key.as_slice())?
+        let mut parent = Tree::get(&self.merk.storage, parent_key.as_slice())?
             .expect("Could not find parent of leaf chunk");
 
         let is_left_child = self.remaining_chunks_unchecked() % 2 == 0;
         if let Some(Link::Reference { ref mut key, .. }) = parent.link_mut(is_left_child) {
-            *key = leaf.key().to_vec();
+            *key = leaf_key.to_vec();
         } else {
             panic!("Expected parent links to be type Link::Reference");
         };
 
-        let parent_bytes = parent.encode();
-        self.merk.db.put(parent_key, parent_bytes)?;
-
-        if !is_left_child {
-            let parent_keys = self.parent_keys.as_mut().unwrap();
-            parent_keys.next();
-        }
-
-        Ok(())
+        Ok((parent_key, parent.encode()))
     }
 
     fn rewrite_trunk_child_heights(&mut self) -> Result<()> {
-        fn recurse(
-            mut node: RefWalker<MerkSource>,
+        fn recurse<'db, 'ctx, S: StorageContext<'db, 'ctx>>(
+            mut node: RefWalker<MerkSource<S>>,
             remaining_depth: usize,
-            batch: &mut WriteBatch,
+            nodes: &mut Vec<(Vec<u8>, Vec<u8>)>,
         ) -> Result<(u8, u8)> {
             if remaining_depth == 0 {
                 return Ok(node.tree().child_heights());
             }
 
             let mut cloned_node =
-                Tree::decode(node.tree().key().to_vec(), node.tree().encode().as_slice());
+                Tree::decode(node.tree().key().to_vec(), node.tree().encode().as_slice())?;
 
             let left_child = node.walk(true)?.unwrap();
-            let left_child_heights = recurse(left_child, remaining_depth - 1, batch)?;
+            let left_child_heights = recurse(left_child, remaining_depth - 1, nodes)?;
             let left_height = left_child_heights.0.max(left_child_heights.1) + 1;
             *cloned_node.link_mut(true).unwrap().child_heights_mut() = left_child_heights;
 
             let right_child = node.walk(false)?.unwrap();
-            let right_child_heights = recurse(right_child, remaining_depth - 1, batch)?;
+            let right_child_heights = recurse(right_child, remaining_depth - 1, nodes)?;
             let right_height = right_child_heights.0.max(right_child_heights.1) + 1;
             *cloned_node.link_mut(false).unwrap().child_heights_mut() = right_child_heights;
 
-            let bytes = cloned_node.encode();
-            batch.put(node.tree().key(), bytes);
+            nodes.push((node.tree().key().to_vec(), cloned_node.encode()));
 
             Ok((left_height, right_height))
         }
 
-        self.merk.flush()?;
         self.merk.load_root()?;
 
-        let mut batch = WriteBatch::default();
-
+        let mut nodes = vec![];
         let depth = self.trunk_height.unwrap();
         self.merk.use_tree_mut(|maybe_tree| {
             let tree = maybe_tree.unwrap();
             let walker = RefWalker::new(tree, self.merk.source());
-            recurse(walker, depth, &mut batch)
+            recurse(walker, depth, &mut nodes)
         })?;
 
-        self.merk.write(batch)?;
-
-        Ok(())
+        self.write_nodes(nodes, None)
     }
 
     /// Returns the number of remaining chunks to be processed. This method will
@@ -289,25 +396,52 @@ impl<'a> Restorer<'a> {
     }
 }
 
-impl Merk {
+impl<'db, S> Merk<S>
+where
+    S: for<'ctx> StorageContext<'db, 'ctx>,
+{
     /// Creates a new `Restorer`, which can be used to verify chunk proofs to
-    /// replicate an entire Merk tree. A new Merk instance will be initialized
-    /// by creating a RocksDB at `path`.
+    /// replicate an entire Merk tree into the given empty storage context.
     ///
     /// The restoration process will verify integrity by checking that the
     /// incoming chunk proofs match `expected_root_hash`. The `stated_length`
     /// should be the number of chunks as stated by peers, which will also be
     /// verified during the restoration process.
-    pub fn restore<P: AsRef<Path>>(
-        path: P,
-        prefix: &[u8],
+    pub fn restore(
+        storage: S,
         expected_root_hash: Hash,
         stated_length: usize,
-    ) -> Result<Restorer> {
-        Restorer::new(path, prefix, expected_root_hash, stated_length)
+    ) -> Result<Restorer<S>> {
+        Restorer::new(storage, expected_root_hash, stated_length)
     }
 }
 
+/// Verifies a leaf chunk and encodes its nodes, returns the key of the leaf
+/// root with the nodes
+fn verify_leaf_chunk(chunk_bytes: &[u8], leaf_hash: Hash) -> Result<(Vec<u8>, ChunkNodes)> {
+    let leaf = verify_leaf(Decoder::new(chunk_bytes), leaf_hash)?;
+    Ok((leaf.key().to_vec(), chunk_nodes(&leaf)))
+}
+
+/// Encodes nodes contained in `tree` (extracted from a verified chunk proof)
+fn chunk_nodes(tree: &ProofTree) -> ChunkNodes {
+    let mut nodes = vec![];
+    tree.visit_refs(&mut |proof_node| {
+        let (key, value) = match &proof_node.node {
+            Node::KV(key, value) => (key, value),
+            _ => return,
+        };
+
+        // TODO: encode tree node without cloning key/value
+        let mut node = Tree::new(key.clone(), value.clone());
+        *node.slot_mut(true) = proof_node.left.as_ref().map(Child::as_link);
+        *node.slot_mut(false) = proof_node.right.as_ref().map(Child::as_link);
+
+        nodes.push((key.clone(), node.encode()));
+    });
+    nodes
+}
+
 impl ProofTree {
     fn child_heights(&self) -> (u8, u8) {
         (
@@ -337,26 +471,37 @@ impl Child {
 
 #[cfg(test)]
 mod tests {
+    use std::iter::empty;
+
+    use storage::{
+        rocksdb_storage::{test_utils::TempStorage, PrefixedRocksDbStorageContext},
+        RawIterator, Storage,
+    };
+
     use super::*;
-    use crate::test_utils::*;
-    use crate::tree::{Batch, Op};
-    use std::path::PathBuf;
+    use crate::{test_utils::*, tree::MerkBatch, Op};
+
+    const RESTORED_PREFIX: &[u8] = b"restored";
 
-    fn restore_test(batches: &[&Batch], expected_nodes: usize) {
-        let mut original = TempMerk::new().unwrap();
+    fn make_original(batches: &[&MerkBatch<Vec<u8>>]) -> TempMerk {
+        let mut original = TempMerk::new();
         for batch in batches {
-            original.apply(batch, &[]).unwrap();
+            original.apply::<_, Vec<_>>(batch, &[]).unwrap();
         }
-        original.flush().unwrap();
+        original
+    }
 
+    fn restore_test(batches: &[&MerkBatch<Vec<u8>>], expected_nodes: usize) {
+        let original = make_original(batches);
         let chunks = original.chunks().unwrap();
 
-        let path: PathBuf = std::thread::current().name().unwrap().into();
-        if path.exists() {
-            std::fs::remove_dir_all(&path).unwrap();
-        }
-
-        let mut restorer = Merk::restore(&path, original.root_hash(), chunks.len()).unwrap();
+        let storage = TempStorage::new();
+        let mut restorer = Merk::restore(
+            storage.get_storage_context(std::iter::once(RESTORED_PREFIX)),
+            original.root_hash(),
+            chunks.len(),
+        )
+        .unwrap();
 
         assert_eq!(restorer.remaining_chunks(), None);
 
@@ -374,8 +519,6 @@ mod tests {
         let restored = restorer.finalize().unwrap();
         assert_eq!(restored.root_hash(), original.root_hash());
         assert_raw_db_entries_eq(&restored, &original, expected_nodes);
-
-        std::fs::remove_dir_all(&path).unwrap();
     }
 
     #[test]
@@ -391,7 +534,10 @@ mod tests {
     #[test]
     fn restore_2_left_heavy() {
         restore_test(
-            &[&[(vec![0], Op::Put(vec![]))], &[(vec![1], Op::Put(vec![]))]],
+            &[
+                &vec![(vec![0], Op::Put(vec![]))],
+                &vec![(vec![1], Op::Put(vec![]))],
+            ],
             2,
         );
     }
@@ -399,7 +545,10 @@ mod tests {
     #[test]
     fn restore_2_right_heavy() {
         restore_test(
-            &[&[(vec![1], Op::Put(vec![]))], &[(vec![0], Op::Put(vec![]))]],
+            &[
+                &vec![(vec![1], Op::Put(vec![]))],
+                &vec![(vec![0], Op::Put(vec![]))],
+            ],
             2,
         );
     }
@@ -409,9 +558,117 @@ mod tests {
         restore_test(&[&make_batch_seq(0..1)], 1);
     }
 
-    fn assert_raw_db_entries_eq(restored: &Merk, original: &Merk, length: usize) {
-        let mut original_entries = original.raw_iter();
-        let mut restored_entries = restored.raw_iter();
+    #[test]
+    fn restore_non_empty_storage() {
+        let original = make_original(&[&make_batch_seq(0..10)]);
+        let storage = TempStorage::new();
+        let mut merk = Merk::open(storage.get_storage_context(empty())).unwrap();
+        merk.apply::<_, Vec<_>>(&make_batch_seq(0..1), &[]).unwrap();
+
+        assert!(Merk::restore(merk.storage, original.root_hash(), 1).is_err());
+    }
+
+    #[test]
+    fn restore_resume() {
+        let original = make_original(&[&make_batch_seq(0..10_000)]);
+        let chunks = original
+            .chunks()
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        let manifest = original.chunks().unwrap().manifest().unwrap();
+        assert_eq!(manifest.len(), chunks.len());
+        let manifest = ChunkManifest::decode(&manifest.encode()).unwrap();
+
+        let storage = TempStorage::new();
+        let context = || storage.get_storage_context(std::iter::once(RESTORED_PREFIX));
+        let already_have = 42;
+        {
+            let mut restorer =
+                Restorer::resume(context(), original.root_hash(), &manifest, 0).unwrap();
+            for chunk in &chunks[..already_have] {
+                restorer.process_chunk(chunk).unwrap();
+            }
+            // interrupted before processing the rest of chunks
+        }
+
+        let mut restorer =
+            Restorer::resume(context(), original.root_hash(), &manifest, already_have).unwrap();
+        assert_eq!(
+            restorer.remaining_chunks(),
+            Some(chunks.len() - already_have)
+        );
+        assert!(restorer.process_chunk(&chunks[already_have - 1]).is_err());
+        for (i, chunk) in chunks.iter().enumerate().skip(already_have) {
+            manifest.verify_chunk(i, chunk).unwrap();
+            restorer.process_chunk(chunk).unwrap();
+        }
+
+        let restored = restorer.finalize().unwrap();
+        assert_eq!(restored.root_hash(), original.root_hash());
+        assert_raw_db_entries_eq(&restored, &original, 10_000);
+    }
+
+    #[test]
+    fn resume_with_invalid_manifest() {
+        let original = make_original(&[&make_batch_seq(0..10_000)]);
+        let other = make_original(&[&make_batch_seq(0..9_999)]);
+        let manifest = other.chunks().unwrap().manifest().unwrap();
+
+        let storage = TempStorage::new();
+        assert!(Restorer::resume(
+            storage.get_storage_context(empty()),
+            original.root_hash(),
+            &manifest,
+            1
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn restore_parallel() {
+        let original = make_original(&[&make_batch_seq(0..10_000)]);
+        let mut chunks = original
+            .chunks()
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+
+        let storage = TempStorage::new();
+        let mut restorer = Merk::restore(
+            storage.get_storage_context(std::iter::once(RESTORED_PREFIX)),
+            original.root_hash(),
+            chunks.len(),
+        )
+        .unwrap()
+        .with_workers(4);
+
+        let valid_chunk = chunks[50].clone();
+        chunks[50] = chunks[49].clone();
+        assert!(restorer.process_chunks(&chunks[..60]).is_err());
+        assert_eq!(restorer.remaining_chunks(), Some(chunks.len() - 50));
+
+        chunks[50] = valid_chunk;
+        for batch in chunks[50..].chunks(30) {
+            restorer.process_chunks(batch).unwrap();
+        }
+        assert_eq!(restorer.remaining_chunks(), Some(0));
+        assert!(restorer.process_chunks(&chunks[..1]).is_err());
+
+        let restored = restorer.finalize().unwrap();
+        assert_eq!(restored.root_hash(), original.root_hash());
+        assert_raw_db_entries_eq(&restored, &original, 10_000);
+    }
+
+    fn assert_raw_db_entries_eq(
+        restored: &Merk<PrefixedRocksDbStorageContext>,
+        original: &Merk<PrefixedRocksDbStorageContext>,
+        length: usize,
+    ) {
+        let mut original_entries = original.storage.raw_iter();
+        let mut restored_entries = restored.storage.raw_iter();
         original_entries.seek_to_first();
         restored_entries.seek_to_first();
 
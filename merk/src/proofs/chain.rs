@@ -0,0 +1,123 @@
+//! Verification of root hash chains.
+//! A chain is a series of root hashes committed externally, e.g. signed by
+//! validators, with a diff proof for every transition between two consecutive
+//! root hashes, so verifiers can track state evolution without storing state.
+//! Verification only needs `core` and `alloc`: without the `std` feature,
+//! e.g. with `default-features = false, features = ["verify"]`, the crate is
+//! built as `no_std` with proof verification only.
+
+use alloc::{collections::BTreeSet, vec::Vec};
+
+use anyhow::{bail, Result};
+
+use super::query::{verify, Map};
+use crate::tree::Hash;
+
+/// Proof of a transition between two root hashes, consists of proofs of the
+/// same changed keys against the old and the new root hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffProof {
+    /// Encoded proof against the old root hash
+    pub old: Vec<u8>,
+    /// Encoded proof against the new root hash
+    pub new: Vec<u8>,
+}
+
+/// Proved change of a key value, `None` stands for a proved absence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyChange {
+    pub key: Vec<u8>,
+    pub old_value: Option<Vec<u8>>,
+    pub new_value: Option<Vec<u8>>,
+}
+
+/// Verifies a chain of root hashes: `diffs[i]` must prove transition from
+/// `roots[i]` to `roots[i + 1]`. Returns changes proved by each diff, every key
+/// proved by one side of a diff must be proved by the other side too.
+pub fn verify_root_hash_chain(roots: &[Hash], diffs: &[DiffProof]) -> Result<Vec<Vec<KeyChange>>> {
+    if roots.len() != diffs.len() + 1 {
+        bail!(
+            "Expected {} diff proofs for {} root hashes, got {}",
+            roots.len().saturating_sub(1),
+            roots.len(),
+            diffs.len()
+        );
+    }
+
+    let mut changes = Vec::with_capacity(diffs.len());
+    for (i, diff) in diffs.iter().enumerate() {
+        let old_map = verify(&diff.old, roots[i])?;
+        let new_map = verify(&diff.new, roots[i + 1])?;
+        changes.push(diff_maps(&old_map, &new_map)?);
+    }
+    Ok(changes)
+}
+
+fn diff_maps(old_map: &Map, new_map: &Map) -> Result<Vec<KeyChange>> {
+    let keys: BTreeSet<&Vec<u8>> = old_map
+        .all()
+        .chain(new_map.all())
+        .map(|(key, _)| key)
+        .collect();
+
+    let mut changes = Vec::new();
+    for key in keys {
+        let old_value = old_map.get(key)?;
+        let new_value = new_map.get(key)?;
+        if old_value != new_value {
+            changes.push(KeyChange {
+                key: key.clone(),
+                old_value: old_value.map(|v| v.to_vec()),
+                new_value: new_value.map(|v| v.to_vec()),
+            });
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        proofs::{encode_into, query::QueryItem},
+        test_utils::{apply_memonly, make_tree_seq, seq_key},
+        tree::{Op, PanicSource, RefWalker, Tree},
+    };
+
+    fn prove_key(tree: &mut Tree, key: &[u8]) -> Vec<u8> {
+        let mut walker = RefWalker::new(tree, PanicSource {});
+        let (proof, ..) = walker
+            .create_full_proof(&[QueryItem::Key(key.to_vec())])
+            .expect("create_proof errored");
+        let mut bytes = vec![];
+        encode_into(proof.iter(), &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn root_hash_chain() {
+        let key = seq_key(3).to_vec();
+        let mut tree = make_tree_seq(10);
+        let old_root = tree.hash();
+        let old = prove_key(&mut tree, &key);
+
+        let mut tree = apply_memonly(tree, &[(key.clone(), Op::Put(vec![1]))]);
+        let new_root = tree.hash();
+        let new = prove_key(&mut tree, &key);
+
+        let diffs = vec![DiffProof { old, new }];
+        let changes =
+            verify_root_hash_chain(&[old_root, new_root], &diffs).expect("valid root hash chain");
+        assert_eq!(
+            changes,
+            vec![vec![KeyChange {
+                key,
+                old_value: Some(vec![123; 60]),
+                new_value: Some(vec![1]),
+            }]]
+        );
+
+        assert!(verify_root_hash_chain(&[new_root, old_root], &diffs).is_err());
+        assert!(verify_root_hash_chain(&[old_root], &diffs).is_err());
+    }
+}
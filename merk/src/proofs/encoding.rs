@@ -1,11 +1,17 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use std::io::{Read, Write};
 
-use anyhow::{anyhow, Result};
+#[cfg(feature = "std")]
+use anyhow::anyhow;
+use anyhow::{bail, Result};
+#[cfg(feature = "std")]
 use ed::{Decode, Encode, Error, Terminated};
 
 use super::{Node, Op};
 use crate::tree::HASH_LENGTH;
 
+#[cfg(feature = "std")]
 impl Encode for Op {
     fn encode_into<W: Write>(&self, dest: &mut W) -> ed::Result<()> {
         match self {
@@ -33,16 +39,11 @@ impl Encode for Op {
     }
 
     fn encoding_length(&self) -> ed::Result<usize> {
-        Ok(match self {
-            Op::Push(Node::Hash(_)) => 1 + HASH_LENGTH,
-            Op::Push(Node::KVHash(_)) => 1 + HASH_LENGTH,
-            Op::Push(Node::KV(key, value)) => 4 + key.len() + value.len(),
-            Op::Parent => 1,
-            Op::Child => 1,
-        })
+        Ok(Op::encoding_length(self))
     }
 }
 
+#[cfg(feature = "std")]
 impl Decode for Op {
     fn decode<R: Read>(mut input: R) -> ed::Result<Self> {
         let variant: u8 = Decode::decode(&mut input)?;
@@ -77,9 +78,11 @@ impl Decode for Op {
     }
 }
 
+#[cfg(feature = "std")]
 impl Terminated for Op {}
 
 impl Op {
+    #[cfg(feature = "std")]
     fn encode_into<W: Write>(&self, dest: &mut W) -> Result<()> {
         Encode::encode_into(self, dest).map_err(|e| match e {
             Error::UnexpectedByte(byte) => anyhow!(
@@ -93,28 +96,70 @@ impl Op {
     }
 
     fn encoding_length(&self) -> usize {
-        Encode::encoding_length(self).unwrap()
+        match self {
+            Op::Push(Node::Hash(_)) => 1 + HASH_LENGTH,
+            Op::Push(Node::KVHash(_)) => 1 + HASH_LENGTH,
+            Op::Push(Node::KV(key, value)) => 4 + key.len() + value.len(),
+            Op::Parent => 1,
+            Op::Child => 1,
+        }
     }
 
+    /// Decodes an operator from the start of `bytes`, the same way as its
+    /// `ed::Decode` implementation, but without requiring `std`
     pub fn decode(bytes: &[u8]) -> Result<Self> {
-        Decode::decode(bytes).map_err(|e| match e {
-            Error::UnexpectedByte(byte) => anyhow!(
+        let (variant, bytes) = split_bytes(bytes, 1)?;
+
+        Ok(match variant[0] {
+            0x01 => Self::Push(Node::Hash(decode_hash(bytes)?)),
+            0x02 => Self::Push(Node::KVHash(decode_hash(bytes)?)),
+            0x03 => {
+                let (key_len, bytes) = split_bytes(bytes, 1)?;
+                let (key, bytes) = split_bytes(bytes, key_len[0] as usize)?;
+
+                let (value_len, bytes) = split_bytes(bytes, 2)?;
+                let value_len = u16::from_be_bytes([value_len[0], value_len[1]]);
+                let (value, _) = split_bytes(bytes, value_len as usize)?;
+
+                Self::Push(Node::KV(key.to_vec(), value.to_vec()))
+            }
+            0x10 => Op::Parent,
+            0x11 => Op::Child,
+            byte => bail!(
                 "failed to decode an proofs::Op structure (UnexpectedByte: {})",
                 byte
             ),
-            Error::IOError(error) => {
-                anyhow!("failed to decode an proofs::Op structure ({})", error)
-            }
         })
     }
 }
 
+/// Splits `len` bytes off the start of `bytes`
+fn split_bytes(bytes: &[u8], len: usize) -> Result<(&[u8], &[u8])> {
+    if bytes.len() < len {
+        bail!("failed to decode an proofs::Op structure (unexpected end of input)");
+    }
+    Ok(bytes.split_at(len))
+}
+
+fn decode_hash(bytes: &[u8]) -> Result<[u8; HASH_LENGTH]> {
+    let (hash_bytes, _) = split_bytes(bytes, HASH_LENGTH)?;
+    let mut hash = [0; HASH_LENGTH];
+    hash.copy_from_slice(hash_bytes);
+    Ok(hash)
+}
+
+#[cfg(feature = "std")]
 pub fn encode_into<'a, T: Iterator<Item = &'a Op>>(ops: T, output: &mut Vec<u8>) {
     for op in ops {
         op.encode_into(output).unwrap();
     }
 }
 
+/// Decodes all operators of a proof
+pub fn decode_proof(bytes: &[u8]) -> Result<Vec<Op>> {
+    Decoder::new(bytes).collect()
+}
+
 pub struct Decoder<'a> {
     offset: usize,
     bytes: &'a [u8],
@@ -148,7 +193,10 @@ impl<'a> Iterator for Decoder<'a> {
 
 #[cfg(test)]
 mod test {
-    use super::super::{Node, Op};
+    use super::{
+        super::{Node, Op},
+        decode_proof,
+    };
     use crate::tree::HASH_LENGTH;
 
     #[test]
@@ -269,4 +317,28 @@ mod test {
         let bytes = [0x88];
         assert!(Op::decode(&bytes[..]).is_err());
     }
+
+    #[test]
+    fn decode_and_display_proof() {
+        let mut bytes = vec![0x03, 1, 1, 0, 1, 2, 0x02];
+        bytes.extend([171; HASH_LENGTH]);
+        bytes.push(0x10);
+        let ops = decode_proof(&bytes).expect("decode failed");
+        assert_eq!(
+            ops,
+            vec![
+                Op::Push(Node::KV(vec![1], vec![2])),
+                Op::Push(Node::KVHash([171; HASH_LENGTH])),
+                Op::Parent
+            ]
+        );
+        assert_eq!(ops[0].to_string(), "Push(KV(0x01, 0x02))");
+        assert_eq!(
+            ops[1].to_string(),
+            format!("Push(KVHash(0x{}))", "ab".repeat(HASH_LENGTH))
+        );
+        assert_eq!(ops[2].to_string(), "Parent");
+
+        assert!(decode_proof(&[0x03, 1]).is_err());
+    }
 }
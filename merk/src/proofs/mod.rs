@@ -1,16 +1,37 @@
+pub mod chain;
+#[cfg(feature = "std")]
 pub mod chunk;
 pub mod encoding;
+#[cfg(feature = "std")]
 pub mod query;
+/// Proof verification, the part of queries available without `std`.
+#[cfg(not(feature = "std"))]
+pub mod query {
+    mod map;
+    mod verify;
+
+    pub use map::*;
+    pub use verify::*;
+}
 pub mod tree;
 
-pub use encoding::{encode_into, Decoder};
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "std")]
+pub use encoding::encode_into;
+pub use encoding::{decode_proof, Decoder};
+#[cfg(feature = "std")]
 pub use query::Query;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 pub use tree::Tree;
 
 use crate::tree::Hash;
 
 /// A proof operator, executed to verify the data in a Merkle proof.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Op {
     /// Pushes a node on the stack.
     Push(Node),
@@ -29,6 +50,7 @@ pub enum Op {
 /// A selected piece of data about a single tree node, to be contained in a
 /// `Push` operator in a proof.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Node {
     /// Represents the hash of a tree node.
     Hash(Hash),
@@ -39,3 +61,40 @@ pub enum Node {
     /// Represents the key and value of a tree node.
     KV(Vec<u8>, Vec<u8>),
 }
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    write!(f, "0x")?;
+    bytes.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Push(node) => write!(f, "Push({})", node),
+            Op::Parent => write!(f, "Parent"),
+            Op::Child => write!(f, "Child"),
+        }
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Hash(hash) => {
+                write!(f, "Hash(")?;
+                write_hex(f, hash)?;
+            }
+            Node::KVHash(kv_hash) => {
+                write!(f, "KVHash(")?;
+                write_hex(f, kv_hash)?;
+            }
+            Node::KV(key, value) => {
+                write!(f, "KV(")?;
+                write_hex(f, key)?;
+                write!(f, ", ")?;
+                write_hex(f, value)?;
+            }
+        }
+        write!(f, ")")
+    }
+}
@@ -1,9 +1,10 @@
 #![allow(unstable_name_collisions)]
 
-use std::{
+use alloc::{
     collections::{btree_map, btree_map::Iter, BTreeMap},
-    ops::{Bound, RangeBounds},
+    vec::Vec,
 };
+use core::ops::{Bound, RangeBounds};
 
 use anyhow::{anyhow, bail, ensure, Result};
 
@@ -86,6 +87,12 @@ impl Map {
         self.entries.iter()
     }
 
+    /// Returns mutable references to values of all entries, so values proved
+    /// can be replaced with ones derived from them, e.g. resolved by a hash
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut Vec<u8>> {
+        self.entries.values_mut().map(|(_, value)| value)
+    }
+
     /// Returns an iterator over all (key, value) entries in the requested range
     /// of keys. If during iteration we encounter a gap in the data (e.g. the
     /// proof did not include all nodes within the range), the iterator will
@@ -1,4 +1,5 @@
 mod map;
+mod verify;
 
 use std::{
     cmp,
@@ -11,6 +12,9 @@ use std::{
 use anyhow::{bail, Result};
 use indexmap::IndexMap;
 pub use map::*;
+pub use verify::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use storage::RawIterator;
 #[cfg(feature = "full")]
 use {super::Op, std::collections::LinkedList};
@@ -19,6 +23,7 @@ use super::{tree::execute, Decoder, Node};
 use crate::tree::{Fetch, Hash as MerkHash, Link, RefWalker};
 
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SubqueryBranch {
     pub subquery_key: Option<Vec<u8>>,
     pub subquery: Option<Box<Query>>,
@@ -27,13 +32,29 @@ pub struct SubqueryBranch {
 /// `Query` represents one or more keys or ranges of keys, which can be used to
 /// resolve a proof which will include all of the requested values.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Query {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_items"))]
     items: BTreeSet<QueryItem>,
     pub default_subquery_branch: SubqueryBranch,
     pub conditional_subquery_branches: IndexMap<QueryItem, SubqueryBranch>,
     pub left_to_right: bool,
 }
 
+/// Deserializes query items as a sequence and merges colliding ones like
+/// [`Query::insert_item`], as a `BTreeSet` would drop items comparing equal
+#[cfg(feature = "serde")]
+fn deserialize_items<'de, D>(deserializer: D) -> Result<BTreeSet<QueryItem>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut query = Query::new();
+    for item in Vec::<QueryItem>::deserialize(deserializer)? {
+        query.insert_item(item);
+    }
+    Ok(query.items)
+}
+
 type ProofOffsetLimit = (LinkedList<Op>, (bool, bool), Option<u16>, Option<u16>);
 
 impl Query {
@@ -105,6 +126,19 @@ impl Query {
         self.items.insert(key);
     }
 
+    /// Adds multiple keys to the query, see [`Query::insert_key`].
+    ///
+    /// Keys are sorted byte-wise and deduplicated first, so the resulting
+    /// query doesn't depend on the order of `keys` and results are always
+    /// returned in storage key order.
+    pub fn insert_keys(&mut self, mut keys: Vec<Vec<u8>>) {
+        keys.sort_unstable();
+        keys.dedup();
+        for key in keys {
+            self.insert_key(key);
+        }
+    }
+
     /// Adds a range to the query, so that all the entries in the tree with keys
     /// in the range will be included in the resulting proof.
     ///
@@ -223,6 +257,37 @@ impl Query {
 
         self.items.insert(item);
     }
+
+    /// Normalizes query items before execution: empty ranges are dropped,
+    /// and overlapping or adjacent items are merged, so keys covered by ranges
+    /// are dropped too. Queries built with `insert_*` methods are mostly
+    /// normalized already, but ones deserialized or built from a `Vec` may
+    /// contain redundant items which would inflate proofs. Returns `false` if
+    /// the query selects no keys.
+    pub fn normalize(&mut self) -> bool {
+        let items = std::mem::take(&mut self.items);
+        for item in items {
+            if !item.is_empty_range() {
+                self.insert_item(item);
+            }
+        }
+
+        let mut merged: Vec<QueryItem> = Vec::with_capacity(self.items.len());
+        for item in std::mem::take(&mut self.items) {
+            match merged.pop() {
+                Some(previous) if previous.is_adjacent_to(&item) => {
+                    merged.push(previous.merge(item))
+                }
+                Some(previous) => {
+                    merged.push(previous);
+                    merged.push(item);
+                }
+                None => merged.push(item),
+            }
+        }
+        self.items = merged.into_iter().collect();
+        !self.items.is_empty()
+    }
 }
 
 impl<Q: Into<QueryItem>> From<Vec<Q>> for Query {
@@ -257,6 +322,11 @@ impl IntoIterator for Query {
 
 /// A `QueryItem` represents a key or range of keys to be included in a proof.
 #[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(from = "QueryItemRepr", into = "QueryItemRepr")
+)]
 pub enum QueryItem {
     Key(Vec<u8>),
     Range(Range<Vec<u8>>),
@@ -270,6 +340,67 @@ pub enum QueryItem {
     RangeAfterToInclusive(RangeInclusive<Vec<u8>>),
 }
 
+/// Serialization form of `QueryItem`, as `std::ops` ranges have no uniform
+/// serde support.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+enum QueryItemRepr {
+    Key(Vec<u8>),
+    Range(Vec<u8>, Vec<u8>),
+    RangeInclusive(Vec<u8>, Vec<u8>),
+    RangeFull,
+    RangeFrom(Vec<u8>),
+    RangeTo(Vec<u8>),
+    RangeToInclusive(Vec<u8>),
+    RangeAfter(Vec<u8>),
+    RangeAfterTo(Vec<u8>, Vec<u8>),
+    RangeAfterToInclusive(Vec<u8>, Vec<u8>),
+}
+
+#[cfg(feature = "serde")]
+impl From<QueryItem> for QueryItemRepr {
+    fn from(item: QueryItem) -> Self {
+        match item {
+            QueryItem::Key(key) => QueryItemRepr::Key(key),
+            QueryItem::Range(range) => QueryItemRepr::Range(range.start, range.end),
+            QueryItem::RangeInclusive(range) => {
+                let (start, end) = range.into_inner();
+                QueryItemRepr::RangeInclusive(start, end)
+            }
+            QueryItem::RangeFull(..) => QueryItemRepr::RangeFull,
+            QueryItem::RangeFrom(range) => QueryItemRepr::RangeFrom(range.start),
+            QueryItem::RangeTo(range) => QueryItemRepr::RangeTo(range.end),
+            QueryItem::RangeToInclusive(range) => QueryItemRepr::RangeToInclusive(range.end),
+            QueryItem::RangeAfter(range) => QueryItemRepr::RangeAfter(range.start),
+            QueryItem::RangeAfterTo(range) => QueryItemRepr::RangeAfterTo(range.start, range.end),
+            QueryItem::RangeAfterToInclusive(range) => {
+                let (start, end) = range.into_inner();
+                QueryItemRepr::RangeAfterToInclusive(start, end)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<QueryItemRepr> for QueryItem {
+    fn from(repr: QueryItemRepr) -> Self {
+        match repr {
+            QueryItemRepr::Key(key) => QueryItem::Key(key),
+            QueryItemRepr::Range(start, end) => QueryItem::Range(start..end),
+            QueryItemRepr::RangeInclusive(start, end) => QueryItem::RangeInclusive(start..=end),
+            QueryItemRepr::RangeFull => QueryItem::RangeFull(..),
+            QueryItemRepr::RangeFrom(start) => QueryItem::RangeFrom(start..),
+            QueryItemRepr::RangeTo(end) => QueryItem::RangeTo(..end),
+            QueryItemRepr::RangeToInclusive(end) => QueryItem::RangeToInclusive(..=end),
+            QueryItemRepr::RangeAfter(start) => QueryItem::RangeAfter(start..),
+            QueryItemRepr::RangeAfterTo(start, end) => QueryItem::RangeAfterTo(start..end),
+            QueryItemRepr::RangeAfterToInclusive(start, end) => {
+                QueryItem::RangeAfterToInclusive(start..=end)
+            }
+        }
+    }
+}
+
 impl std::hash::Hash for QueryItem {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.enum_value().hash(state);
@@ -400,6 +531,32 @@ impl QueryItem {
         }
     }
 
+    /// Checks if the item can't contain any key, like a range with its end
+    /// before its start
+    fn is_empty_range(&self) -> bool {
+        if self.lower_unbounded() || self.upper_unbounded() {
+            return false;
+        }
+        let (start, start_non_inclusive) = self.lower_bound();
+        let (end, end_inclusive) = self.upper_bound();
+        match start.cmp(end) {
+            Ordering::Less => false,
+            Ordering::Equal => start_non_inclusive || !end_inclusive,
+            Ordering::Greater => true,
+        }
+    }
+
+    /// Checks if `next` item, which is after this one, starts right where this
+    /// one ends, so they can be merged without covering extra keys
+    fn is_adjacent_to(&self, next: &Self) -> bool {
+        if self.upper_unbounded() || next.lower_unbounded() {
+            return false;
+        }
+        let (end, end_inclusive) = self.upper_bound();
+        let (start, start_non_inclusive) = next.lower_bound();
+        end == start && end_inclusive == start_non_inclusive
+    }
+
     fn enum_value(&self) -> u32 {
         match self {
             QueryItem::Key(_) => 0,
@@ -928,32 +1085,6 @@ where
     }
 }
 
-pub fn verify(bytes: &[u8], expected_hash: MerkHash) -> Result<Map> {
-    let ops = Decoder::new(bytes);
-    let mut map_builder = MapBuilder::new();
-
-    let root = execute(ops, true, |node| map_builder.insert(node))?;
-
-    if root.hash() != expected_hash {
-        bail!(
-            "Proof did not match expected hash\n\tExpected: {:?}\n\tActual: {:?}",
-            expected_hash,
-            root.hash()
-        );
-    }
-
-    Ok(map_builder.build())
-}
-
-pub fn execute_proof(bytes: &[u8]) -> Result<(MerkHash, Map)> {
-    let ops = Decoder::new(bytes);
-    let mut map_builder = MapBuilder::new();
-
-    let root = execute(ops, true, |node| map_builder.insert(node))?;
-
-    Ok((root.hash(), map_builder.build()))
-}
-
 /// Verifies the encoded proof with the given query and expected hash.
 ///
 /// Every key in `keys` is checked to either have a key/value pair in the proof,
@@ -1635,6 +1766,104 @@ mod test {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn query_insert_keys() {
+        let mut query = Query::new();
+        query.insert_range(vec![3]..vec![5]);
+        query.insert_keys(vec![vec![7], vec![1], vec![4], vec![7], vec![0, 1]]);
+
+        let mut reordered = Query::new();
+        reordered.insert_range(vec![3]..vec![5]);
+        reordered.insert_keys(vec![vec![0, 1], vec![7], vec![4], vec![1]]);
+
+        let items: Vec<QueryItem> = query.into();
+        assert_eq!(
+            format!("{:?}", items),
+            format!("{:?}", Vec::<QueryItem>::from(reordered))
+        );
+        assert_eq!(
+            format!("{:?}", items),
+            "[Key([0, 1]), Key([1]), Range([3]..[5]), Key([7])]"
+        );
+    }
+
+    #[test]
+    fn proof_limits() {
+        let mut tree = make_tree_seq(10);
+        let mut walker = RefWalker::new(&mut tree, PanicSource {});
+        let (proof, ..) = walker
+            .create_full_proof(vec![QueryItem::RangeFull(..)].as_slice())
+            .expect("create_proof errored");
+        let mut bytes = vec![];
+        encode_into(proof.iter(), &mut bytes);
+
+        let limits = ProofLimits {
+            max_proof_bytes: bytes.len(),
+            max_ops: proof.len(),
+            max_results: 10,
+        };
+        let size = check_proof_limits(&bytes, &limits).expect("proof within limits");
+        assert_eq!(size.ops, proof.len());
+        assert_eq!(size.results, 10);
+        let (hash, map) =
+            execute_proof_with_limits(&bytes, &limits).expect("proof execution within limits");
+        assert_eq!(hash, tree.hash());
+        assert_eq!(map.all().count(), 10);
+
+        for exceeded in [
+            ProofLimits {
+                max_proof_bytes: bytes.len() - 1,
+                ..limits
+            },
+            ProofLimits {
+                max_ops: proof.len() - 1,
+                ..limits
+            },
+            ProofLimits {
+                max_results: 9,
+                ..limits
+            },
+        ] {
+            assert!(check_proof_limits(&bytes, &exceeded).is_err());
+            assert!(execute_proof_with_limits(&bytes, &exceeded).is_err());
+        }
+    }
+
+    #[test]
+    fn query_normalize() {
+        let mut query = Query::from(vec![
+            QueryItem::Range(vec![1]..vec![5]),
+            QueryItem::Range(vec![3]..vec![8]),
+            QueryItem::Key(vec![8]),
+            QueryItem::Key(vec![4]),
+            QueryItem::RangeAfter(vec![8]..),
+            QueryItem::Range(vec![0, 5]..vec![0, 2]),
+        ]);
+        assert!(query.normalize());
+        let items: Vec<QueryItem> = query.into();
+        assert_eq!(format!("{:?}", items), "[RangeFrom([1]..)]");
+
+        let mut query = Query::new();
+        query.insert_key(vec![1]);
+        query.insert_range_after(vec![1]..);
+        query.insert_key(vec![5]);
+        assert!(query.normalize());
+        let items: Vec<QueryItem> = query.into();
+        assert_eq!(format!("{:?}", items), "[RangeFrom([1]..)]");
+
+        let mut query = Query::new();
+        query.insert_range(vec![1]..vec![3]);
+        query.insert_range_after(vec![3]..);
+        assert!(query.normalize());
+        assert_eq!(query.len(), 2);
+
+        let mut query = Query::from(vec![
+            QueryItem::Range(vec![5]..vec![5]),
+            QueryItem::RangeAfterToInclusive(vec![5]..=vec![5]),
+        ]);
+        assert!(!query.normalize());
+    }
+
     #[test]
     fn range_proof() {
         let mut tree = make_tree_seq(10);
@@ -0,0 +1,98 @@
+use anyhow::{bail, Result};
+
+use super::map::{Map, MapBuilder};
+use crate::{
+    proofs::{tree::execute, Decoder, Node, Op},
+    tree::Hash as MerkHash,
+};
+
+pub fn verify(bytes: &[u8], expected_hash: MerkHash) -> Result<Map> {
+    let ops = Decoder::new(bytes);
+    let mut map_builder = MapBuilder::new();
+
+    let root = execute(ops, true, |node| map_builder.insert(node))?;
+
+    if root.hash() != expected_hash {
+        bail!(
+            "Proof did not match expected hash\n\tExpected: {:?}\n\tActual: {:?}",
+            expected_hash,
+            root.hash()
+        );
+    }
+
+    Ok(map_builder.build())
+}
+
+pub fn execute_proof(bytes: &[u8]) -> Result<(MerkHash, Map)> {
+    let ops = Decoder::new(bytes);
+    let mut map_builder = MapBuilder::new();
+
+    let root = execute(ops, true, |node| map_builder.insert(node))?;
+
+    Ok((root.hash(), map_builder.build()))
+}
+
+/// Limits on a proof enforced while decoding it, before any hashing, so light
+/// clients don't spend memory on maliciously oversized proofs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofLimits {
+    /// Maximum length of an encoded proof in bytes
+    pub max_proof_bytes: usize,
+    /// Maximum number of proof operations
+    pub max_ops: usize,
+    /// Maximum number of proved key/value pairs
+    pub max_results: usize,
+}
+
+impl Default for ProofLimits {
+    /// Limits generous enough for any proof a node serves, yet low enough
+    /// that a verifier can hold a proof at the limits in memory
+    fn default() -> Self {
+        ProofLimits {
+            max_proof_bytes: 64 * 1024 * 1024,
+            max_ops: 4 * 1024 * 1024,
+            max_results: 1024 * 1024,
+        }
+    }
+}
+
+/// Size of a decoded proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProofSize {
+    /// Number of proof operations
+    pub ops: usize,
+    /// Number of proved key/value pairs
+    pub results: usize,
+}
+
+/// Decodes the encoded proof without executing it and checks it against
+/// `limits`, decoding stops at the first exceeded limit.
+pub fn check_proof_limits(bytes: &[u8], limits: &ProofLimits) -> Result<ProofSize> {
+    if bytes.len() > limits.max_proof_bytes {
+        bail!(
+            "Proof is {} bytes long, the limit is {}",
+            bytes.len(),
+            limits.max_proof_bytes
+        );
+    }
+    let mut size = ProofSize::default();
+    for op in Decoder::new(bytes) {
+        if let Op::Push(Node::KV(..)) = op? {
+            size.results += 1;
+            if size.results > limits.max_results {
+                bail!("Proof has more than {} results", limits.max_results);
+            }
+        }
+        size.ops += 1;
+        if size.ops > limits.max_ops {
+            bail!("Proof has more than {} operations", limits.max_ops);
+        }
+    }
+    Ok(size)
+}
+
+/// Executes the encoded proof like [`execute_proof`] if it is within `limits`
+pub fn execute_proof_with_limits(bytes: &[u8], limits: &ProofLimits) -> Result<(MerkHash, Map)> {
+    check_proof_limits(bytes, limits)?;
+    execute_proof(bytes)
+}
@@ -1,3 +1,5 @@
+use alloc::{boxed::Box, vec::Vec};
+
 use anyhow::{bail, Result};
 
 use super::{Node, Op};
@@ -143,13 +145,13 @@ impl Tree {
         Node::Hash(self.hash()).into()
     }
 
-    // #[cfg(feature = "full")]
-    // pub(crate) fn key(&self) -> &[u8] {
-    //     match self.node {
-    //         Node::KV(ref key, _) => key,
-    //         _ => panic!("Expected node to be type KV"),
-    //     }
-    // }
+    #[cfg(feature = "full")]
+    pub(crate) fn key(&self) -> &[u8] {
+        match self.node {
+            Node::KV(ref key, _) => key,
+            _ => panic!("Expected node to be type KV"),
+        }
+    }
 }
 
 /// `LayerIter` iterates over the nodes in a `Tree` at a given depth. Nodes are
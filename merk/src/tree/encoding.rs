@@ -1,8 +1,54 @@
+use std::io::{Read, Write};
+
 use anyhow::{anyhow, Error};
 use ed::{Decode, Encode};
 use storage::StorageContext;
 
-use super::Tree;
+use super::{kv::KV, Link, Tree, TreeInner};
+
+/// Flag in the first byte of an encoded node, next to the presence of the left
+/// link, marking the value as stored compressed
+const COMPRESSED_VALUE_FLAG: u8 = 0x80;
+
+impl Encode for TreeInner {
+    fn encode_into<W: Write>(&self, dest: &mut W) -> ed::Result<()> {
+        let mut tag = self.left.is_some() as u8;
+        if self.kv.compressed() {
+            tag |= COMPRESSED_VALUE_FLAG;
+        }
+        dest.write_all(&[tag])?;
+        if let Some(left) = &self.left {
+            left.encode_into(dest)?;
+        }
+        self.right.encode_into(dest)?;
+        self.kv.encode_into(dest)
+    }
+
+    fn encoding_length(&self) -> ed::Result<usize> {
+        let left_length = match &self.left {
+            Some(left) => left.encoding_length()?,
+            None => 0,
+        };
+        Ok(1 + left_length + self.right.encoding_length()? + self.kv.encoding_length()?)
+    }
+}
+
+impl Decode for TreeInner {
+    fn decode<R: Read>(mut input: R) -> ed::Result<Self> {
+        let tag: u8 = Decode::decode(&mut input)?;
+        let left = match tag & !COMPRESSED_VALUE_FLAG {
+            0 => None,
+            1 => Some(Link::decode(&mut input)?),
+            _ => return Err(ed::Error::UnexpectedByte(tag)),
+        };
+        let right: Option<Link> = Decode::decode(&mut input)?;
+        let mut kv = KV::decode(&mut input)?;
+        if tag & COMPRESSED_VALUE_FLAG != 0 {
+            kv.decompress_value()?;
+        }
+        Ok(TreeInner { left, right, kv })
+    }
+}
 
 impl Tree {
     pub fn decode_raw(bytes: &[u8]) -> Result<Self, Error> {
@@ -45,20 +91,23 @@ impl Tree {
         Encode::encoding_length(self).unwrap()
     }
 
+    /// Decodes a node into this one, fails if the encoding is invalid or a
+    /// compressed value can't be decompressed
     #[inline]
-    pub fn decode_into(&mut self, key: Vec<u8>, input: &[u8]) {
-        // operation is infallible so it's ok to unwrap
-        Decode::decode_into(self, input).unwrap();
+    pub fn decode_into(&mut self, key: Vec<u8>, input: &[u8]) -> Result<(), Error> {
+        Decode::decode_into(self, input)
+            .map_err(|e| anyhow!("failed to decode a Tree structure ({})", e))?;
         self.inner.kv.key = key;
+        Ok(())
     }
 
+    /// Decodes a node, fails if the encoding is invalid or a compressed value
+    /// can't be decompressed
     #[inline]
-    pub fn decode(key: Vec<u8>, input: &[u8]) -> Self {
-        // operation is infallible so it's ok to unwrap
-        // TODO: how said that its infallible?
-        let mut tree: Self = Decode::decode(input).unwrap();
+    pub fn decode(key: Vec<u8>, input: &[u8]) -> Result<Self, Error> {
+        let mut tree = Self::decode_raw(input)?;
         tree.inner.kv.key = key;
-        tree
+        Ok(tree)
     }
 }
 
@@ -175,7 +224,7 @@ mod tests {
             0, 0, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55,
             1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
         ];
-        let tree = Tree::decode(vec![0], bytes.as_slice());
+        let tree = Tree::decode(vec![0], bytes.as_slice()).expect("should decode correctly");
         assert_eq!(tree.key(), &[0]);
         assert_eq!(tree.value(), &[1]);
     }
@@ -188,7 +237,7 @@ mod tests {
             55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55, 55,
             55, 55, 55, 55, 55, 1,
         ];
-        let tree = Tree::decode(vec![0], bytes.as_slice());
+        let tree = Tree::decode(vec![0], bytes.as_slice()).expect("should decode correctly");
         assert_eq!(tree.key(), &[0]);
         assert_eq!(tree.value(), &[1]);
         if let Some(Link::Reference {
@@ -204,4 +253,21 @@ mod tests {
             panic!("Expected Link::Reference");
         }
     }
+
+    #[test]
+    fn encode_decode_compressed_value_tree() {
+        let plain = Tree::new(vec![0], vec![1; 1000]);
+        let compressed = Tree::new(vec![0], vec![]).with_compressed_value(vec![1; 1000]);
+        assert_eq!(compressed.kv_hash(), plain.kv_hash());
+
+        let bytes = compressed.encode();
+        assert_eq!(bytes[0], COMPRESSED_VALUE_FLAG);
+        assert_eq!(bytes.len(), compressed.encoding_length());
+        assert!(bytes.len() < plain.encoding_length());
+
+        let tree = Tree::decode(vec![0], bytes.as_slice()).expect("should decode correctly");
+        assert_eq!(tree.value(), &[1; 1000]);
+        assert!(tree.value_compressed());
+        assert_eq!(tree.kv_hash(), plain.kv_hash());
+    }
 }
@@ -118,7 +118,7 @@ fn make_batch(maybe_tree: Option<&Tree>, size: u64, seed: u64) -> Vec<BatchEntry
 fn apply_to_map(map: &mut Map, batch: &Batch) {
     for entry in batch.iter() {
         match entry {
-            (key, Op::Put(value)) => {
+            (key, Op::Put(value)) | (key, Op::PutCompressed(value)) => {
                 map.insert(key.to_vec(), value.to_vec());
             }
             (key, Op::Delete) => {
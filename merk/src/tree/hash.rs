@@ -1,5 +1,3 @@
-use integer_encoding::*;
-
 /// The length of a `Hash` (in bytes).
 pub const HASH_LENGTH: usize = 32;
 
@@ -9,12 +7,24 @@ pub const NULL_HASH: Hash = [0; HASH_LENGTH];
 /// A cryptographic hash digest.
 pub type Hash = [u8; HASH_LENGTH];
 
+/// Encodes a length as an unsigned LEB128 varint, the same as
+/// `integer_encoding::VarInt::encode_var` does, without requiring `std`.
+fn encode_length(mut length: usize, buf: &mut [u8; 10]) -> &[u8] {
+    let mut i = 0;
+    while length >= 0x80 {
+        buf[i] = length as u8 | 0x80;
+        length >>= 7;
+        i += 1;
+    }
+    buf[i] = length as u8;
+    &buf[..=i]
+}
+
 pub fn value_hash(value: &[u8]) -> Hash {
     // TODO: make generic to allow other hashers
     let mut hasher = blake3::Hasher::new();
 
-    let val_length = value.len().encode_var_vec();
-    hasher.update(val_length.as_slice());
+    hasher.update(encode_length(value.len(), &mut [0; 10]));
     hasher.update(value);
 
     let res = hasher.finalize();
@@ -30,8 +40,7 @@ pub fn kv_hash(key: &[u8], value: &[u8]) -> Hash {
     // TODO: make generic to allow other hashers
     let mut hasher = blake3::Hasher::new();
 
-    let key_length = key.len().encode_var_vec();
-    hasher.update(key_length.as_slice());
+    hasher.update(encode_length(key.len(), &mut [0; 10]));
     hasher.update(key);
 
     let value_hash = value_hash(value);
@@ -57,3 +66,20 @@ pub fn node_hash(kv: &Hash, left: &Hash, right: &Hash) -> Hash {
     hash.copy_from_slice(res.as_bytes());
     hash
 }
+
+#[cfg(test)]
+mod test {
+    use integer_encoding::VarInt;
+
+    use super::*;
+
+    #[test]
+    fn encode_length_matches_varint() {
+        for length in [0, 1, 127, 128, 300, 16_383, 16_384, usize::MAX] {
+            assert_eq!(
+                encode_length(length, &mut [0; 10]),
+                length.encode_var_vec().as_slice()
+            );
+        }
+    }
+}
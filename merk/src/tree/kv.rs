@@ -4,6 +4,9 @@ use ed::{Decode, Encode, Result, Terminated};
 
 use super::hash::{kv_hash, Hash, HASH_LENGTH, NULL_HASH};
 
+/// Compression level of values stored compressed
+const VALUE_ZSTD_LEVEL: i32 = 3;
+
 // TODO: maybe use something similar to Vec but without capacity field,
 //       (should save 16 bytes per entry). also, maybe a shorter length
 //       field to save even more. also might be possible to combine key
@@ -15,6 +18,10 @@ pub struct KV {
     pub(super) key: Vec<u8>,
     pub(super) value: Vec<u8>,
     pub(super) hash: Hash,
+    /// The value compressed as it is stored if it is stored compressed, kept so
+    /// it is compressed once. The hash is always computed over the uncompressed
+    /// value.
+    pub(super) compressed: Option<Vec<u8>>,
 }
 
 impl KV {
@@ -23,14 +30,24 @@ impl KV {
     pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
         // TODO: length checks?
         let hash = kv_hash(key.as_slice(), value.as_slice());
-        Self { key, value, hash }
+        Self {
+            key,
+            value,
+            hash,
+            compressed: None,
+        }
     }
 
     /// Creates a new `KV` with the given key, value, and hash. The hash is not
     /// checked to be correct for the given key/value.
     #[inline]
     pub fn from_fields(key: Vec<u8>, value: Vec<u8>, hash: Hash) -> Self {
-        Self { key, value, hash }
+        Self {
+            key,
+            value,
+            hash,
+            compressed: None,
+        }
     }
 
     /// Replaces the `KV`'s value with the given value, updates the hash, and
@@ -40,9 +57,21 @@ impl KV {
         // TODO: length check?
         self.value = value;
         self.hash = kv_hash(self.key(), self.value());
+        self.compressed = None;
         self
     }
 
+    /// Like `with_value`, but the value is stored compressed with zstd. The
+    /// hash is computed over the uncompressed value, so it doesn't depend on
+    /// how the value is stored. If compression fails the value is stored
+    /// uncompressed.
+    #[inline]
+    pub fn with_compressed_value(self, value: Vec<u8>) -> Self {
+        let mut kv = self.with_value(value);
+        kv.compressed = zstd::bulk::compress(kv.value(), VALUE_ZSTD_LEVEL).ok();
+        kv
+    }
+
     /// Returns the key as a slice.
     #[inline]
     pub fn key(&self) -> &[u8] {
@@ -61,6 +90,21 @@ impl KV {
         &self.hash
     }
 
+    /// Returns whether the value is stored compressed.
+    #[inline]
+    pub const fn compressed(&self) -> bool {
+        self.compressed.is_some()
+    }
+
+    /// Decompresses a value decoded from its stored form and marks it as
+    /// stored compressed.
+    pub(super) fn decompress_value(&mut self) -> Result<()> {
+        let compressed = std::mem::take(&mut self.value);
+        self.value = zstd::stream::decode_all(compressed.as_slice())?;
+        self.compressed = Some(compressed);
+        Ok(())
+    }
+
     /// Consumes the `KV` and returns its key without allocating or cloning.
     #[inline]
     pub fn take_key(self) -> Vec<u8> {
@@ -72,14 +116,20 @@ impl Encode for KV {
     #[inline]
     fn encode_into<W: Write>(&self, out: &mut W) -> Result<()> {
         out.write_all(&self.hash[..])?;
-        out.write_all(self.value.as_slice())?;
+        match &self.compressed {
+            Some(compressed) => out.write_all(compressed)?,
+            None => out.write_all(self.value.as_slice())?,
+        }
         Ok(())
     }
 
     #[inline]
     fn encoding_length(&self) -> Result<usize> {
         debug_assert!(self.key().len() < 256, "Key length must be less than 256");
-        Ok(HASH_LENGTH + self.value.len())
+        match &self.compressed {
+            Some(compressed) => Ok(HASH_LENGTH + compressed.len()),
+            None => Ok(HASH_LENGTH + self.value.len()),
+        }
     }
 }
 
@@ -90,6 +140,7 @@ impl Decode for KV {
             key: Vec::with_capacity(0),
             value: Vec::with_capacity(128),
             hash: NULL_HASH,
+            compressed: None,
         };
         Self::decode_into(&mut kv, input)?;
         Ok(kv)
@@ -103,6 +154,7 @@ impl Decode for KV {
 
         self.value.clear();
         input.read_to_end(self.value.as_mut())?;
+        self.compressed = None;
 
         Ok(())
     }
@@ -131,4 +183,16 @@ mod test {
         assert_eq!(kv.value(), &[7, 8, 9]);
         assert_ne!(kv.hash(), &super::super::hash::NULL_HASH);
     }
+
+    #[test]
+    fn with_compressed_value() {
+        let kv = KV::new(vec![1, 2, 3], vec![4, 5, 6]);
+        let compressed = kv.clone().with_compressed_value(vec![7; 100]);
+        let plain = kv.with_value(vec![7; 100]);
+
+        assert!(compressed.compressed());
+        assert!(!plain.compressed());
+        assert_eq!(compressed.hash(), plain.hash());
+        assert!(compressed.encoding_length().unwrap() < plain.encoding_length().unwrap());
+    }
 }
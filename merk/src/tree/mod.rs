@@ -15,7 +15,7 @@ use std::cmp::max;
 use anyhow::Result;
 pub use commit::{Commit, NoopCommit};
 use ed::{Decode, Encode, Terminated};
-pub use hash::{kv_hash, node_hash, Hash, HASH_LENGTH, NULL_HASH};
+pub use hash::{kv_hash, node_hash, value_hash, Hash, HASH_LENGTH, NULL_HASH};
 use kv::KV;
 pub use link::Link;
 pub use ops::{BatchEntry, MerkBatch, Op, PanicSource};
@@ -25,7 +25,7 @@ pub use walk::{Fetch, RefWalker, Walker};
 // relevant methods
 
 /// The fields of the `Tree` type, stored on the heap.
-#[derive(Clone, Encode, Decode)]
+#[derive(Clone)]
 struct TreeInner {
     left: Option<Link>,
     right: Option<Link>,
@@ -99,6 +99,12 @@ impl Tree {
         self.inner.kv.value()
     }
 
+    /// Returns whether the root node's value is stored compressed.
+    #[inline]
+    pub const fn value_compressed(&self) -> bool {
+        self.inner.kv.compressed()
+    }
+
     /// Returns the hash of the root node's key/value pair.
     #[inline]
     pub const fn kv_hash(&self) -> &Hash {
@@ -323,6 +329,15 @@ impl Tree {
         self
     }
 
+    /// Replaces the root node's value with the given value to be stored
+    /// compressed and returns the modified `Tree`. The hash is computed over
+    /// the uncompressed value.
+    #[inline]
+    pub fn with_compressed_value(mut self, value: Vec<u8>) -> Self {
+        self.inner.kv = self.inner.kv.with_compressed_value(value);
+        self
+    }
+
     // TODO: add compute_hashes method
 
     /// Called to finalize modifications to a tree, recompute its hashes, and
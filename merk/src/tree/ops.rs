@@ -8,6 +8,9 @@ use super::{Fetch, Link, Tree, Walker};
 /// An operation to be applied to a key in the store.
 pub enum Op {
     Put(Vec<u8>),
+    /// Puts a value stored compressed, the node hash is computed over the
+    /// uncompressed value, so it's the same as for `Put`
+    PutCompressed(Vec<u8>),
     Delete,
 }
 
@@ -18,6 +21,7 @@ impl fmt::Debug for Op {
             "{}",
             match self {
                 Put(value) => format!("Put({:?})", value),
+                PutCompressed(value) => format!("PutCompressed({:?})", value),
                 Delete => "Delete".to_string(),
             }
         )
@@ -77,7 +81,8 @@ where
 
         let mid_index = batch.len() / 2;
         let (mid_key, mid_op) = &batch[mid_index];
-        let mid_value = match mid_op {
+        // TODO: take from batch so we don't have to clone
+        let mid_tree = match mid_op {
             Delete => {
                 let left_batch = &batch[..mid_index];
                 let right_batch = &batch[mid_index + 1..];
@@ -91,11 +96,12 @@ where
                 };
                 return Ok(maybe_tree.map(|tree| tree.into()));
             }
-            Put(value) => value,
+            Put(value) => Tree::new(mid_key.as_ref().to_vec(), value.to_vec()),
+            PutCompressed(value) => {
+                Tree::new(mid_key.as_ref().to_vec(), vec![]).with_compressed_value(value.to_vec())
+            }
         };
 
-        // TODO: take from batch so we don't have to clone
-        let mid_tree = Tree::new(mid_key.as_ref().to_vec(), mid_value.to_vec());
         let mid_walker = Walker::new(mid_tree, PanicSource {});
 
         // use walker, ignore deleted_keys since it should be empty
@@ -121,6 +127,7 @@ where
             match &batch[index].1 {
                 // TODO: take vec from batch so we don't need to clone
                 Put(value) => self.with_value(value.to_vec()),
+                PutCompressed(value) => self.with_compressed_value(value.to_vec()),
                 Delete => {
                     // TODO: we shouldn't have to do this as 2 different calls to apply
                     let source = self.clone_source();
@@ -135,6 +135,12 @@ where
         self.tree.own(|t| t.with_value(value));
         self
     }
+
+    /// Similar to `Tree#with_compressed_value`.
+    pub fn with_compressed_value(mut self, value: Vec<u8>) -> Self {
+        self.tree.own(|t| t.with_compressed_value(value));
+        self
+    }
 }
 
 impl<S> From<Walker<S>> for Tree
@@ -6,6 +6,8 @@ fn element_to_string(element: Element) -> String {
         Element::Item(_) => "item".to_string(),
         Element::Reference(_) => "reference".to_string(),
         Element::Tree(_) => "tree".to_string(),
+        Element::PrunedTree(_) => "pruned_tree".to_string(),
+        Element::DedupItem(_) => "dedup_item".to_string(),
     }
 }
 
@@ -59,7 +61,7 @@ pub fn element_to_js_object<'a, C: Context<'a>>(
             js_buffer.upcast()
         }
         Element::Reference(reference) => nested_vecs_to_js(reference, cx)?,
-        Element::Tree(tree) => {
+        Element::Tree(tree) | Element::PrunedTree(tree) | Element::DedupItem(tree) => {
             let js_buffer = JsBuffer::external(cx, tree);
             js_buffer.upcast()
         }
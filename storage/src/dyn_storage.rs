@@ -0,0 +1,794 @@
+//! Object-safe facade of storage traits.
+//! [`Storage`], [`StorageContext`] and [`RawIterator`] have generic methods so
+//! they can't be used as trait objects. Traits here mirror them with
+//! object-safe methods, and boxed trait objects implement the original traits,
+//! so code generic over storage such as Merk can run on a backend chosen at
+//! runtime, e.g. a caching or recording decorator wrapping RocksDB.
+//! Capabilities specific to RocksDB, such as checkpoints, are reached through
+//! [`DynStorage::rocksdb`] and are unavailable on other backends.
+//! Unlike contexts of [`Storage`] implementations, which use a prefix derived
+//! from the subtree path, contexts made by backends through the facade address
+//! data of a subtree by its identifier stored under [`subtree_id_key`], if the
+//! subtree has one.
+
+use std::fmt;
+#[cfg(any(feature = "rocksdb_storage", feature = "sled-backend"))]
+use std::sync::Arc;
+
+#[cfg(feature = "rocksdb_storage")]
+use rocksdb::{OptimisticTransactionDB, Transaction};
+
+#[cfg(feature = "rocksdb_storage")]
+use crate::rocksdb_storage::{ReadOnlyRocksDbStorage, RocksDbStorage};
+use crate::{Batch, RawIterator, Storage, StorageContext};
+
+/// A prefix of keys in meta storage to store subtree identifiers with,
+/// followed by the path-derived prefix of a subtree, see [`subtree_id_key`]
+pub const SUBTREE_ID_KEY: &[u8] = b"subtree_id";
+
+/// Returns the key in meta storage of the identifier of the subtree with the
+/// path-derived prefix. The identifier is the prefix data of the subtree is
+/// stored under, subtrees without one use the path-derived prefix.
+pub fn subtree_id_key(path_prefix: &[u8]) -> Vec<u8> {
+    let mut key = SUBTREE_ID_KEY.to_vec();
+    key.extend_from_slice(path_prefix);
+    key
+}
+
+/// Storage backend chosen at runtime
+pub type BoxedStorage = Box<dyn for<'db> DynStorage<'db> + Send + Sync>;
+
+/// Error of a storage backend behind the facade
+#[derive(Debug)]
+pub struct DynStorageError(Box<dyn std::error::Error + Send + Sync>);
+
+impl DynStorageError {
+    pub fn new<E: std::error::Error + Send + Sync + 'static>(error: E) -> Self {
+        DynStorageError(Box::new(error))
+    }
+
+    /// Returns the backend error if it is of the type
+    pub fn downcast_ref<E: std::error::Error + 'static>(&self) -> Option<&E> {
+        self.0.downcast_ref()
+    }
+}
+
+impl fmt::Display for DynStorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for DynStorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Object-safe counterpart of [`Storage`]
+pub trait DynStorage<'db> {
+    /// Starts a new transaction
+    fn start_transaction(&'db self) -> Box<dyn DynTransaction + 'db>;
+
+    /// Starts a new transaction reading from a snapshot taken at its start, so
+    /// reads are repeatable. Backends reading a consistent state within any
+    /// transaction start a regular one.
+    fn start_snapshot_transaction(&'db self) -> Box<dyn DynTransaction + 'db> {
+        self.start_transaction()
+    }
+
+    /// Takes a snapshot which transactions reading the same state can be
+    /// started at on several threads, `None` if the backend can't do that
+    fn snapshot(&'db self) -> Option<Box<dyn DynSnapshot + 'db>> {
+        None
+    }
+
+    /// Forces data to be written
+    fn flush(&self) -> Result<(), DynStorageError>;
+
+    /// Make storage context for a subtree with path
+    fn storage_context(&'db self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'db> + 'db>;
+
+    /// Returns RocksDB storage if this is the backend, decorators don't expose
+    /// the storage they wrap as its operations would bypass them
+    #[cfg(feature = "rocksdb_storage")]
+    fn rocksdb(&self) -> Option<&RocksDbStorage> {
+        None
+    }
+
+    /// Returns RocksDB storage opened read-only if this is the backend
+    #[cfg(feature = "rocksdb_storage")]
+    fn read_only_rocksdb(&self) -> Option<&ReadOnlyRocksDbStorage> {
+        None
+    }
+}
+
+/// Point-in-time view of a [`DynStorage`] shared between threads
+pub trait DynSnapshot: Send + Sync {
+    /// Starts a transaction reading from the snapshot instead of the latest
+    /// data, its own writes are visible to it
+    fn start_transaction<'a>(&'a self) -> Box<dyn DynTransaction + 'a>;
+}
+
+/// Transaction of a [`DynStorage`]. It has no lifetime parameter, so a boxed
+/// transaction borrowing storage for `'db` is usable where a shorter borrow is
+/// expected.
+pub trait DynTransaction {
+    /// Make storage context for a subtree on transactional data
+    fn storage_context<'a>(&'a self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'a> + 'a>;
+
+    /// Consumes and commits the transaction
+    fn commit(self: Box<Self>) -> Result<(), DynStorageError>;
+
+    /// Rollback the transaction
+    fn rollback(&self) -> Result<(), DynStorageError>;
+
+    /// Returns the RocksDB transaction if this is a transaction of
+    /// [`DynStorage::rocksdb`] storage
+    #[cfg(feature = "rocksdb_storage")]
+    fn rocksdb(&self) -> Option<&Transaction<'_, OptimisticTransactionDB>> {
+        None
+    }
+}
+
+/// Object-safe counterpart of [`StorageContext`]. Batches are recorded into
+/// [`DynBatch`] and replayed into a batch of the backend on commit.
+pub trait DynStorageContext<'db> {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError>;
+
+    fn put_aux(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError>;
+
+    fn put_root(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError>;
+
+    fn put_meta(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError>;
+
+    fn delete(&self, key: &[u8]) -> Result<(), DynStorageError>;
+
+    fn delete_range(&self, from: &[u8], to: &[u8]) -> Result<(), DynStorageError>;
+
+    fn clear(&self) -> Result<(), DynStorageError>;
+
+    fn delete_aux(&self, key: &[u8]) -> Result<(), DynStorageError>;
+
+    fn delete_root(&self, key: &[u8]) -> Result<(), DynStorageError>;
+
+    fn delete_meta(&self, key: &[u8]) -> Result<(), DynStorageError>;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError>;
+
+    fn get_many(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, DynStorageError> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    fn get_aux(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError>;
+
+    fn get_root(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError>;
+
+    fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError>;
+
+    fn commit_batch(&self, batch: DynBatch) -> Result<(), DynStorageError>;
+
+    fn raw_iter(&self) -> Box<dyn DynRawIterator + 'db>;
+
+    fn raw_iter_opt(
+        &self,
+        readahead_bytes: usize,
+        fill_cache: bool,
+    ) -> Box<dyn DynRawIterator + 'db>;
+
+    fn raw_iter_keys_only(&self) -> Box<dyn DynRawIterator + 'db>;
+
+    fn raw_iter_aux(&self) -> Box<dyn DynRawIterator + 'db>;
+
+    fn raw_iter_roots(&self) -> Box<dyn DynRawIterator + 'db>;
+}
+
+/// Object-safe counterpart of [`RawIterator`]
+pub trait DynRawIterator {
+    fn seek_to_first(&mut self);
+
+    fn seek_to_last(&mut self);
+
+    fn seek(&mut self, key: &[u8]);
+
+    fn seek_for_prev(&mut self, key: &[u8]);
+
+    fn next(&mut self);
+
+    fn prev(&mut self);
+
+    fn value(&self) -> Option<&[u8]>;
+
+    fn key(&self) -> Option<&[u8]>;
+
+    fn valid(&self) -> bool;
+
+    fn status(&self) -> Result<(), DynStorageError>;
+}
+
+impl<I: RawIterator> DynRawIterator for I {
+    fn seek_to_first(&mut self) {
+        RawIterator::seek_to_first(self)
+    }
+
+    fn seek_to_last(&mut self) {
+        RawIterator::seek_to_last(self)
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        RawIterator::seek(self, key)
+    }
+
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        RawIterator::seek_for_prev(self, key)
+    }
+
+    fn next(&mut self) {
+        RawIterator::next(self)
+    }
+
+    fn prev(&mut self) {
+        RawIterator::prev(self)
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        RawIterator::value(self)
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        RawIterator::key(self)
+    }
+
+    fn valid(&self) -> bool {
+        RawIterator::valid(self)
+    }
+
+    fn status(&self) -> Result<(), DynStorageError> {
+        RawIterator::status(self)
+    }
+}
+
+impl<'a> RawIterator for Box<dyn DynRawIterator + 'a> {
+    fn seek_to_first(&mut self) {
+        DynRawIterator::seek_to_first(self.as_mut())
+    }
+
+    fn seek_to_last(&mut self) {
+        DynRawIterator::seek_to_last(self.as_mut())
+    }
+
+    fn seek<K: AsRef<[u8]>>(&mut self, key: K) {
+        DynRawIterator::seek(self.as_mut(), key.as_ref())
+    }
+
+    fn seek_for_prev<K: AsRef<[u8]>>(&mut self, key: K) {
+        DynRawIterator::seek_for_prev(self.as_mut(), key.as_ref())
+    }
+
+    fn next(&mut self) {
+        DynRawIterator::next(self.as_mut())
+    }
+
+    fn prev(&mut self) {
+        DynRawIterator::prev(self.as_mut())
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        DynRawIterator::value(self.as_ref())
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        DynRawIterator::key(self.as_ref())
+    }
+
+    fn valid(&self) -> bool {
+        DynRawIterator::valid(self.as_ref())
+    }
+
+    fn status(&self) -> Result<(), DynStorageError> {
+        DynRawIterator::status(self.as_ref())
+    }
+}
+
+/// Operation recorded by [`DynBatch`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DynBatchOp {
+    Put(Vec<u8>, Vec<u8>),
+    PutAux(Vec<u8>, Vec<u8>),
+    PutRoot(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    DeleteRange(Vec<u8>, Vec<u8>),
+    Clear,
+    DeleteAux(Vec<u8>),
+    DeleteRoot(Vec<u8>),
+}
+
+/// Batch of a [`DynStorageContext`] which records operations
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DynBatch {
+    ops: Vec<DynBatchOp>,
+}
+
+impl DynBatch {
+    /// Returns recorded operations
+    pub fn ops(&self) -> &[DynBatchOp] {
+        &self.ops
+    }
+
+    /// Replays recorded operations into a batch of a backend
+    pub fn replay_into<B: Batch>(self, batch: &mut B) -> Result<(), B::Error> {
+        for op in self.ops {
+            match op {
+                DynBatchOp::Put(key, value) => batch.put(key, &value)?,
+                DynBatchOp::PutAux(key, value) => batch.put_aux(key, &value)?,
+                DynBatchOp::PutRoot(key, value) => batch.put_root(key, &value)?,
+                DynBatchOp::Delete(key) => batch.delete(key)?,
+                DynBatchOp::DeleteRange(from, to) => batch.delete_range(from, to)?,
+                DynBatchOp::Clear => batch.clear()?,
+                DynBatchOp::DeleteAux(key) => batch.delete_aux(key)?,
+                DynBatchOp::DeleteRoot(key) => batch.delete_root(key)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Batch for DynBatch {
+    type Error = DynStorageError;
+
+    fn put<K: AsRef<[u8]>>(&mut self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.ops
+            .push(DynBatchOp::Put(key.as_ref().to_vec(), value.to_vec()));
+        Ok(())
+    }
+
+    fn put_aux<K: AsRef<[u8]>>(&mut self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.ops
+            .push(DynBatchOp::PutAux(key.as_ref().to_vec(), value.to_vec()));
+        Ok(())
+    }
+
+    fn put_root<K: AsRef<[u8]>>(&mut self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.ops
+            .push(DynBatchOp::PutRoot(key.as_ref().to_vec(), value.to_vec()));
+        Ok(())
+    }
+
+    fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
+        self.ops.push(DynBatchOp::Delete(key.as_ref().to_vec()));
+        Ok(())
+    }
+
+    fn delete_range<K: AsRef<[u8]>>(&mut self, from: K, to: K) -> Result<(), Self::Error> {
+        self.ops.push(DynBatchOp::DeleteRange(
+            from.as_ref().to_vec(),
+            to.as_ref().to_vec(),
+        ));
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), Self::Error> {
+        self.ops.push(DynBatchOp::Clear);
+        Ok(())
+    }
+
+    fn delete_aux<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
+        self.ops.push(DynBatchOp::DeleteAux(key.as_ref().to_vec()));
+        Ok(())
+    }
+
+    fn delete_root<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
+        self.ops.push(DynBatchOp::DeleteRoot(key.as_ref().to_vec()));
+        Ok(())
+    }
+}
+
+/// Makes a context for the subtree at `path` with `context` made for a prefix.
+/// Data of the subtree is addressed by the identifier stored in meta storage
+/// under [`subtree_id_key`], or by the path-derived prefix if there is none.
+/// The root tree is never assigned an identifier, so it is not looked up.
+/// If the identifier can't be read, operations of the context fail with the
+/// error.
+#[cfg(any(feature = "rocksdb_storage", feature = "sled-backend"))]
+pub(crate) fn subtree_storage_context<'db, F>(
+    path: &[&[u8]],
+    context: F,
+) -> Box<dyn DynStorageContext<'db> + 'db>
+where
+    F: Fn(Vec<u8>) -> Box<dyn DynStorageContext<'db> + 'db>,
+{
+    let path_prefix = crate::prefix::build_prefix(path.iter().copied());
+    if path.is_empty() {
+        return context(path_prefix);
+    }
+    let meta_storage = context(crate::prefix::build_prefix(std::iter::empty()));
+    match meta_storage.get_meta(&subtree_id_key(&path_prefix)) {
+        Ok(Some(id)) => context(id),
+        Ok(None) => context(path_prefix),
+        Err(e) => Box::new(FailedStorageContext(Arc::new(e))),
+    }
+}
+
+/// Context of a subtree which identifier couldn't be read, all operations fail
+/// with the error, as do raw iterators on [`RawIterator::status`]
+#[cfg(any(feature = "rocksdb_storage", feature = "sled-backend"))]
+struct FailedStorageContext(Arc<DynStorageError>);
+
+#[cfg(any(feature = "rocksdb_storage", feature = "sled-backend"))]
+impl FailedStorageContext {
+    fn fail<T>(&self) -> Result<T, DynStorageError> {
+        Err(DynStorageError::new(self.0.clone()))
+    }
+
+    fn failed_iter<'db>(&self) -> Box<dyn DynRawIterator + 'db> {
+        Box::new(FailedRawIterator(self.0.clone()))
+    }
+}
+
+#[cfg(any(feature = "rocksdb_storage", feature = "sled-backend"))]
+impl<'db> DynStorageContext<'db> for FailedStorageContext {
+    fn put(&self, _key: &[u8], _value: &[u8]) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn put_aux(&self, _key: &[u8], _value: &[u8]) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn put_root(&self, _key: &[u8], _value: &[u8]) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn put_meta(&self, _key: &[u8], _value: &[u8]) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn delete(&self, _key: &[u8]) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn delete_range(&self, _from: &[u8], _to: &[u8]) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn clear(&self) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn delete_aux(&self, _key: &[u8]) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn delete_root(&self, _key: &[u8]) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn delete_meta(&self, _key: &[u8]) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn get(&self, _key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.fail()
+    }
+
+    fn get_aux(&self, _key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.fail()
+    }
+
+    fn get_root(&self, _key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.fail()
+    }
+
+    fn get_meta(&self, _key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.fail()
+    }
+
+    fn commit_batch(&self, _batch: DynBatch) -> Result<(), DynStorageError> {
+        self.fail()
+    }
+
+    fn raw_iter(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.failed_iter()
+    }
+
+    fn raw_iter_opt(
+        &self,
+        _readahead_bytes: usize,
+        _fill_cache: bool,
+    ) -> Box<dyn DynRawIterator + 'db> {
+        self.failed_iter()
+    }
+
+    fn raw_iter_keys_only(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.failed_iter()
+    }
+
+    fn raw_iter_aux(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.failed_iter()
+    }
+
+    fn raw_iter_roots(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.failed_iter()
+    }
+}
+
+/// Raw iterator which is never valid and reports the error it is made with
+#[cfg(any(feature = "rocksdb_storage", feature = "sled-backend"))]
+struct FailedRawIterator(Arc<DynStorageError>);
+
+#[cfg(any(feature = "rocksdb_storage", feature = "sled-backend"))]
+impl RawIterator for FailedRawIterator {
+    fn seek_to_first(&mut self) {}
+
+    fn seek_to_last(&mut self) {}
+
+    fn seek<K: AsRef<[u8]>>(&mut self, _key: K) {}
+
+    fn seek_for_prev<K: AsRef<[u8]>>(&mut self, _key: K) {}
+
+    fn next(&mut self) {}
+
+    fn prev(&mut self) {}
+
+    fn value(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn valid(&self) -> bool {
+        false
+    }
+
+    fn status(&self) -> Result<(), DynStorageError> {
+        Err(DynStorageError::new(self.0.clone()))
+    }
+}
+
+/// Implements [`DynStorageContext`] for a storage context of a backend by
+/// delegating to [`StorageContext`], the traits used and [`DynBatch`],
+/// [`DynRawIterator`] and [`DynStorageError`] must be in scope
+#[cfg(any(feature = "rocksdb_storage", feature = "sled-backend"))]
+macro_rules! impl_dyn_storage_context {
+    ($context:ident) => {
+        impl<'db> DynStorageContext<'db> for $context<'db> {
+            fn put(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+                StorageContext::put(self, key, value).map_err(DynStorageError::new)
+            }
+
+            fn put_aux(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+                StorageContext::put_aux(self, key, value).map_err(DynStorageError::new)
+            }
+
+            fn put_root(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+                StorageContext::put_root(self, key, value).map_err(DynStorageError::new)
+            }
+
+            fn put_meta(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+                StorageContext::put_meta(self, key, value).map_err(DynStorageError::new)
+            }
+
+            fn delete(&self, key: &[u8]) -> Result<(), DynStorageError> {
+                StorageContext::delete(self, key).map_err(DynStorageError::new)
+            }
+
+            fn delete_range(&self, from: &[u8], to: &[u8]) -> Result<(), DynStorageError> {
+                StorageContext::delete_range(self, from, to).map_err(DynStorageError::new)
+            }
+
+            fn clear(&self) -> Result<(), DynStorageError> {
+                StorageContext::clear(self).map_err(DynStorageError::new)
+            }
+
+            fn delete_aux(&self, key: &[u8]) -> Result<(), DynStorageError> {
+                StorageContext::delete_aux(self, key).map_err(DynStorageError::new)
+            }
+
+            fn delete_root(&self, key: &[u8]) -> Result<(), DynStorageError> {
+                StorageContext::delete_root(self, key).map_err(DynStorageError::new)
+            }
+
+            fn delete_meta(&self, key: &[u8]) -> Result<(), DynStorageError> {
+                StorageContext::delete_meta(self, key).map_err(DynStorageError::new)
+            }
+
+            fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+                StorageContext::get(self, key).map_err(DynStorageError::new)
+            }
+
+            fn get_many(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, DynStorageError> {
+                StorageContext::get_many(self, keys).map_err(DynStorageError::new)
+            }
+
+            fn get_aux(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+                StorageContext::get_aux(self, key).map_err(DynStorageError::new)
+            }
+
+            fn get_root(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+                StorageContext::get_root(self, key).map_err(DynStorageError::new)
+            }
+
+            fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+                StorageContext::get_meta(self, key).map_err(DynStorageError::new)
+            }
+
+            fn commit_batch(&self, batch: DynBatch) -> Result<(), DynStorageError> {
+                let mut inner = StorageContext::new_batch(self);
+                batch
+                    .replay_into(&mut inner)
+                    .map_err(DynStorageError::new)?;
+                StorageContext::commit_batch(self, inner).map_err(DynStorageError::new)
+            }
+
+            fn raw_iter(&self) -> Box<dyn DynRawIterator + 'db> {
+                Box::new(StorageContext::raw_iter(self))
+            }
+
+            fn raw_iter_opt(
+                &self,
+                readahead_bytes: usize,
+                fill_cache: bool,
+            ) -> Box<dyn DynRawIterator + 'db> {
+                Box::new(StorageContext::raw_iter_opt(
+                    self,
+                    readahead_bytes,
+                    fill_cache,
+                ))
+            }
+
+            fn raw_iter_keys_only(&self) -> Box<dyn DynRawIterator + 'db> {
+                Box::new(StorageContext::raw_iter_keys_only(self))
+            }
+
+            fn raw_iter_aux(&self) -> Box<dyn DynRawIterator + 'db> {
+                Box::new(StorageContext::raw_iter_aux(self))
+            }
+
+            fn raw_iter_roots(&self) -> Box<dyn DynRawIterator + 'db> {
+                Box::new(StorageContext::raw_iter_roots(self))
+            }
+        }
+    };
+}
+#[cfg(any(feature = "rocksdb_storage", feature = "sled-backend"))]
+pub(crate) use impl_dyn_storage_context;
+
+impl<'db, 'ctx> StorageContext<'db, 'ctx> for Box<dyn DynStorageContext<'db> + 'db> {
+    type Batch = DynBatch;
+    type Error = DynStorageError;
+    type RawIterator = Box<dyn DynRawIterator + 'db>;
+
+    fn put<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.as_ref().put(key.as_ref(), value)
+    }
+
+    fn put_aux<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.as_ref().put_aux(key.as_ref(), value)
+    }
+
+    fn put_root<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.as_ref().put_root(key.as_ref(), value)
+    }
+
+    fn put_meta<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.as_ref().put_meta(key.as_ref(), value)
+    }
+
+    fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
+        self.as_ref().delete(key.as_ref())
+    }
+
+    fn delete_range<K: AsRef<[u8]>>(&self, from: K, to: K) -> Result<(), Self::Error> {
+        self.as_ref().delete_range(from.as_ref(), to.as_ref())
+    }
+
+    fn clear(&self) -> Result<(), Self::Error> {
+        self.as_ref().clear()
+    }
+
+    fn delete_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
+        self.as_ref().delete_aux(key.as_ref())
+    }
+
+    fn delete_root<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
+        self.as_ref().delete_root(key.as_ref())
+    }
+
+    fn delete_meta<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
+        self.as_ref().delete_meta(key.as_ref())
+    }
+
+    fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.as_ref().get(key.as_ref())
+    }
+
+    fn get_many<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<Vec<Option<Vec<u8>>>, Self::Error> {
+        let keys: Vec<&[u8]> = keys.iter().map(AsRef::as_ref).collect();
+        self.as_ref().get_many(&keys)
+    }
+
+    fn get_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.as_ref().get_aux(key.as_ref())
+    }
+
+    fn get_root<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.as_ref().get_root(key.as_ref())
+    }
+
+    fn get_meta<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.as_ref().get_meta(key.as_ref())
+    }
+
+    fn new_batch(&'ctx self) -> Self::Batch {
+        DynBatch::default()
+    }
+
+    fn commit_batch(&'ctx self, batch: Self::Batch) -> Result<(), Self::Error> {
+        self.as_ref().commit_batch(batch)
+    }
+
+    fn raw_iter(&self) -> Self::RawIterator {
+        self.as_ref().raw_iter()
+    }
+
+    fn raw_iter_opt(&self, readahead_bytes: usize, fill_cache: bool) -> Self::RawIterator {
+        self.as_ref().raw_iter_opt(readahead_bytes, fill_cache)
+    }
+
+    fn raw_iter_keys_only(&self) -> Self::RawIterator {
+        self.as_ref().raw_iter_keys_only()
+    }
+
+    fn raw_iter_aux(&self) -> Self::RawIterator {
+        self.as_ref().raw_iter_aux()
+    }
+
+    fn raw_iter_roots(&self) -> Self::RawIterator {
+        self.as_ref().raw_iter_roots()
+    }
+}
+
+impl<'db> Storage<'db> for BoxedStorage {
+    type Error = DynStorageError;
+    type StorageContext = Box<dyn DynStorageContext<'db> + 'db>;
+    type Transaction = Box<dyn DynTransaction + 'db>;
+    type TransactionalStorageContext = Box<dyn DynStorageContext<'db> + 'db>;
+
+    fn start_transaction(&'db self) -> Self::Transaction {
+        self.as_ref().start_transaction()
+    }
+
+    fn commit_transaction(&self, transaction: Self::Transaction) -> Result<(), Self::Error> {
+        transaction.commit()
+    }
+
+    fn rollback_transaction(&self, transaction: &Self::Transaction) -> Result<(), Self::Error> {
+        transaction.rollback()
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        self.as_ref().flush()
+    }
+
+    fn get_storage_context<'p, P>(&'db self, path: P) -> Self::StorageContext
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path: Vec<&[u8]> = path.into_iter().collect();
+        self.as_ref().storage_context(&path)
+    }
+
+    fn get_transactional_storage_context<'p, P>(
+        &'db self,
+        path: P,
+        transaction: &'db Self::Transaction,
+    ) -> Self::TransactionalStorageContext
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        let path: Vec<&[u8]> = path.into_iter().collect();
+        transaction.as_ref().storage_context(&path)
+    }
+}
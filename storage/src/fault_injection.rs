@@ -0,0 +1,386 @@
+//! Storage with simulated failures for crash testing.
+//! [`FaultInjectionStorage`] wraps a storage backend behind the object-safe
+//! facade and can fail a write, drop flushes or corrupt values of specific
+//! keys, so atomicity and recovery of code built on top of storage can be
+//! tested systematically. Faults are configured through a [`FaultInjector`]
+//! handle, which stays usable once the storage is moved into its user, e.g.
+//! GroveDB opened over it.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::dyn_storage::{
+    BoxedStorage, DynBatch, DynRawIterator, DynStorage, DynStorageContext, DynStorageError,
+    DynTransaction,
+};
+
+/// Error returned by an injected write failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InjectedFault {
+    /// Number of the failed write
+    pub write: u64,
+}
+
+impl fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "injected failure of write {}", self.write)
+    }
+}
+
+impl std::error::Error for InjectedFault {}
+
+#[derive(Default)]
+struct Faults {
+    fail_write: Option<u64>,
+    drop_flushes: bool,
+    corrupted_keys: HashSet<Vec<u8>>,
+}
+
+/// Faults injected by [`FaultInjectionStorage`]. Every put, delete, batch
+/// commit and transaction commit counts as a write.
+#[derive(Default)]
+pub struct FaultInjector {
+    faults: Mutex<Faults>,
+    writes: AtomicU64,
+}
+
+impl FaultInjector {
+    /// Makes the write with the number to fail without reaching the wrapped
+    /// storage, writes are numbered from 1 since the storage was created
+    pub fn fail_write(&self, write: u64) {
+        self.faults().fail_write = Some(write);
+    }
+
+    /// Makes flushes succeed without flushing the wrapped storage if set
+    pub fn drop_flushes(&self, drop: bool) {
+        self.faults().drop_flushes = drop;
+    }
+
+    /// Makes reads of values with `key` in any subtree and column to return
+    /// corrupted bytes
+    pub fn corrupt_key<K: AsRef<[u8]>>(&self, key: K) {
+        self.faults().corrupted_keys.insert(key.as_ref().to_vec());
+    }
+
+    /// Removes all configured faults
+    pub fn clear_faults(&self) {
+        *self.faults() = Faults::default();
+    }
+
+    /// Returns the number of writes made so far, including the failed ones
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::SeqCst)
+    }
+
+    fn faults(&self) -> std::sync::MutexGuard<Faults> {
+        self.faults.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write<T, F>(&self, write_fn: F) -> Result<T, DynStorageError>
+    where
+        F: FnOnce() -> Result<T, DynStorageError>,
+    {
+        let write = self.writes.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.faults().fail_write == Some(write) {
+            return Err(DynStorageError::new(InjectedFault { write }));
+        }
+        write_fn()
+    }
+
+    fn read(
+        &self,
+        key: &[u8],
+        value: Result<Option<Vec<u8>>, DynStorageError>,
+    ) -> Result<Option<Vec<u8>>, DynStorageError> {
+        let value = value?;
+        if self.faults().corrupted_keys.contains(key) {
+            Ok(value.map(corrupt))
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+/// Storage decorator which injects failures configured by its
+/// [`FaultInjector`]
+pub struct FaultInjectionStorage {
+    inner: BoxedStorage,
+    injector: Arc<FaultInjector>,
+}
+
+impl FaultInjectionStorage {
+    /// Wraps `inner` storage, no faults are injected until configured
+    pub fn new(inner: BoxedStorage) -> Self {
+        FaultInjectionStorage {
+            inner,
+            injector: Arc::new(FaultInjector::default()),
+        }
+    }
+
+    /// Returns the handle to configure faults with
+    pub fn injector(&self) -> Arc<FaultInjector> {
+        self.injector.clone()
+    }
+
+    fn write<T, F>(&self, write_fn: F) -> Result<T, DynStorageError>
+    where
+        F: FnOnce() -> Result<T, DynStorageError>,
+    {
+        self.injector.write(write_fn)
+    }
+
+    fn read(
+        &self,
+        key: &[u8],
+        value: Result<Option<Vec<u8>>, DynStorageError>,
+    ) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.injector.read(key, value)
+    }
+}
+
+/// Flips bits of every byte, empty values get a byte appended
+fn corrupt(mut value: Vec<u8>) -> Vec<u8> {
+    if value.is_empty() {
+        value.push(0xff);
+    }
+    value.iter_mut().for_each(|b| *b = !*b);
+    value
+}
+
+impl<'db> DynStorage<'db> for FaultInjectionStorage {
+    fn start_transaction(&'db self) -> Box<dyn DynTransaction + 'db> {
+        Box::new(FaultInjectionTransaction {
+            storage: self,
+            inner: self.inner.start_transaction(),
+        })
+    }
+
+    fn flush(&self) -> Result<(), DynStorageError> {
+        if self.injector.faults().drop_flushes {
+            Ok(())
+        } else {
+            self.inner.flush()
+        }
+    }
+
+    fn storage_context(&'db self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'db> + 'db> {
+        Box::new(FaultInjectionContext {
+            storage: self,
+            inner: self.inner.storage_context(path),
+        })
+    }
+}
+
+struct FaultInjectionTransaction<'db> {
+    storage: &'db FaultInjectionStorage,
+    inner: Box<dyn DynTransaction + 'db>,
+}
+
+impl<'db> DynTransaction for FaultInjectionTransaction<'db> {
+    fn storage_context<'a>(&'a self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'a> + 'a> {
+        Box::new(FaultInjectionContext {
+            storage: self.storage,
+            inner: self.inner.storage_context(path),
+        })
+    }
+
+    fn commit(self: Box<Self>) -> Result<(), DynStorageError> {
+        let FaultInjectionTransaction { storage, inner } = *self;
+        storage.write(|| inner.commit())
+    }
+
+    fn rollback(&self) -> Result<(), DynStorageError> {
+        self.inner.rollback()
+    }
+}
+
+struct FaultInjectionContext<'db> {
+    storage: &'db FaultInjectionStorage,
+    inner: Box<dyn DynStorageContext<'db> + 'db>,
+}
+
+impl<'db> DynStorageContext<'db> for FaultInjectionContext<'db> {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.put(key, value))
+    }
+
+    fn put_aux(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.put_aux(key, value))
+    }
+
+    fn put_root(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.put_root(key, value))
+    }
+
+    fn put_meta(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.put_meta(key, value))
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.delete(key))
+    }
+
+    fn delete_range(&self, from: &[u8], to: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.delete_range(from, to))
+    }
+
+    fn clear(&self) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.clear())
+    }
+
+    fn delete_aux(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.delete_aux(key))
+    }
+
+    fn delete_root(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.delete_root(key))
+    }
+
+    fn delete_meta(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.delete_meta(key))
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.storage.read(key, self.inner.get(key))
+    }
+
+    fn get_aux(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.storage.read(key, self.inner.get_aux(key))
+    }
+
+    fn get_root(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.storage.read(key, self.inner.get_root(key))
+    }
+
+    fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.storage.read(key, self.inner.get_meta(key))
+    }
+
+    fn commit_batch(&self, batch: DynBatch) -> Result<(), DynStorageError> {
+        self.storage.write(|| self.inner.commit_batch(batch))
+    }
+
+    fn raw_iter(&self) -> Box<dyn DynRawIterator + 'db> {
+        Box::new(FaultInjectionIterator::new(
+            self.storage,
+            self.inner.raw_iter(),
+        ))
+    }
+
+    fn raw_iter_opt(
+        &self,
+        readahead_bytes: usize,
+        fill_cache: bool,
+    ) -> Box<dyn DynRawIterator + 'db> {
+        Box::new(FaultInjectionIterator::new(
+            self.storage,
+            self.inner.raw_iter_opt(readahead_bytes, fill_cache),
+        ))
+    }
+
+    fn raw_iter_keys_only(&self) -> Box<dyn DynRawIterator + 'db> {
+        Box::new(FaultInjectionIterator::new(
+            self.storage,
+            self.inner.raw_iter_keys_only(),
+        ))
+    }
+
+    fn raw_iter_aux(&self) -> Box<dyn DynRawIterator + 'db> {
+        Box::new(FaultInjectionIterator::new(
+            self.storage,
+            self.inner.raw_iter_aux(),
+        ))
+    }
+
+    fn raw_iter_roots(&self) -> Box<dyn DynRawIterator + 'db> {
+        Box::new(FaultInjectionIterator::new(
+            self.storage,
+            self.inner.raw_iter_roots(),
+        ))
+    }
+}
+
+/// Raw iterator which returns corrupted values of configured keys
+struct FaultInjectionIterator<'db> {
+    storage: &'db FaultInjectionStorage,
+    inner: Box<dyn DynRawIterator + 'db>,
+    corrupted_value: Option<Vec<u8>>,
+}
+
+impl<'db> FaultInjectionIterator<'db> {
+    fn new(storage: &'db FaultInjectionStorage, inner: Box<dyn DynRawIterator + 'db>) -> Self {
+        FaultInjectionIterator {
+            storage,
+            inner,
+            corrupted_value: None,
+        }
+    }
+
+    fn moved(&mut self) {
+        self.corrupted_value = match (self.inner.key(), self.inner.value()) {
+            (Some(key), Some(value))
+                if self.storage.injector.faults().corrupted_keys.contains(key) =>
+            {
+                Some(corrupt(value.to_vec()))
+            }
+            _ => None,
+        };
+    }
+}
+
+impl DynRawIterator for FaultInjectionIterator<'_> {
+    fn seek_to_first(&mut self) {
+        self.inner.seek_to_first();
+        self.moved();
+    }
+
+    fn seek_to_last(&mut self) {
+        self.inner.seek_to_last();
+        self.moved();
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        self.inner.seek(key);
+        self.moved();
+    }
+
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        self.inner.seek_for_prev(key);
+        self.moved();
+    }
+
+    fn next(&mut self) {
+        self.inner.next();
+        self.moved();
+    }
+
+    fn prev(&mut self) {
+        self.inner.prev();
+        self.moved();
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        match &self.corrupted_value {
+            Some(value) => Some(value),
+            None => self.inner.value(),
+        }
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        self.inner.key()
+    }
+
+    fn valid(&self) -> bool {
+        self.inner.valid()
+    }
+
+    fn status(&self) -> Result<(), DynStorageError> {
+        self.inner.status()
+    }
+}
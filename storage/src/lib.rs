@@ -1,5 +1,16 @@
+pub mod dyn_storage;
+#[cfg(feature = "testing")]
+pub mod fault_injection;
+#[cfg(feature = "prefix")]
+pub mod prefix;
+pub mod recording;
 #[cfg(feature = "rocksdb_storage")]
 pub mod rocksdb_storage;
+#[cfg(feature = "sled-backend")]
+pub mod sled_storage;
 mod storage;
 
-pub use crate::storage::{Batch, RawIterator, Storage, StorageContext};
+pub use crate::{
+    dyn_storage::{BoxedStorage, DynSnapshot, DynStorage, DynStorageContext, DynTransaction},
+    storage::{Batch, RawIterator, Storage, StorageContext},
+};
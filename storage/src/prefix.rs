@@ -0,0 +1,27 @@
+//! Subtree prefixes shared by storage backends.
+
+/// Length of every subtree prefix
+pub const PREFIX_LENGTH: usize = blake3::OUT_LEN;
+
+/// Builds a prefix of storage keys of a subtree with the path, it also
+/// identifies a subtree in `subtrees` map
+pub fn build_prefix<'a, P>(path: P) -> Vec<u8>
+where
+    P: IntoIterator<Item = &'a [u8]>,
+{
+    let segments_iter = path.into_iter();
+    let mut segments_count: usize = 0;
+    let mut res = Vec::new();
+    let mut lengthes = Vec::new();
+
+    for s in segments_iter {
+        segments_count += 1;
+        res.extend_from_slice(s);
+        lengthes.extend(s.len().to_ne_bytes());
+    }
+
+    res.extend(segments_count.to_ne_bytes());
+    res.extend(lengthes);
+    res = blake3::hash(&res).as_bytes().to_vec();
+    res
+}
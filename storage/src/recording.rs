@@ -0,0 +1,1210 @@
+//! Recording and replay of storage operations.
+//! [`RecordingStorage`] wraps a storage backend behind the object-safe facade
+//! and logs every read and write to a file, [`ReplayStorage`] serves reads
+//! from such a recording. This way a failed execution on a production node can
+//! be reproduced having only the storage data it touched instead of a copy of
+//! the whole database.
+//!
+//! Transaction boundaries are recorded too, so replay keeps writes of a
+//! transaction visible only through it until it's committed, and discards
+//! them on rollback, as the recorded backend did.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    dyn_storage::{
+        BoxedStorage, DynBatch, DynBatchOp, DynRawIterator, DynStorage, DynStorageContext,
+        DynStorageError, DynTransaction,
+    },
+    Batch,
+};
+
+/// Storage column an operation refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+    Data,
+    Aux,
+    Roots,
+    Meta,
+}
+
+impl Column {
+    fn from_tag(tag: u8) -> io::Result<Self> {
+        match tag {
+            0 => Ok(Column::Data),
+            1 => Ok(Column::Aux),
+            2 => Ok(Column::Roots),
+            3 => Ok(Column::Meta),
+            _ => Err(invalid_data("unknown column")),
+        }
+    }
+}
+
+/// Subtree path of a storage context
+type SubtreePath = Vec<Vec<u8>>;
+
+/// Recorded storage operation. Operations made through a transaction have
+/// its ID, which is assigned by [`Record::Begin`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    /// Value read by key, `None` if there was no value
+    Get {
+        transaction: Option<u64>,
+        path: SubtreePath,
+        column: Column,
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    },
+    /// Value written either directly or with a batch
+    Put {
+        transaction: Option<u64>,
+        path: SubtreePath,
+        column: Column,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    /// Value deleted either directly or with a batch
+    Delete {
+        transaction: Option<u64>,
+        path: SubtreePath,
+        column: Column,
+        key: Vec<u8>,
+    },
+    /// Data entries in range `from..to` deleted
+    DeleteRange {
+        transaction: Option<u64>,
+        path: SubtreePath,
+        from: Vec<u8>,
+        to: Vec<u8>,
+    },
+    /// All data entries deleted
+    Clear {
+        transaction: Option<u64>,
+        path: SubtreePath,
+    },
+    /// Entry a raw iterator stopped at
+    Iterated {
+        transaction: Option<u64>,
+        path: SubtreePath,
+        column: Column,
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    },
+    /// Transaction started
+    Begin { transaction: u64 },
+    /// Transaction committed
+    Commit { transaction: u64 },
+    /// Transaction rolled back, or failed to commit and was discarded
+    Rollback { transaction: u64 },
+}
+
+impl Record {
+    /// Writes the record in a length-prefixed binary format
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Record::Get {
+                transaction,
+                path,
+                column,
+                key,
+                value,
+            } => {
+                writer.write_all(&[0, *column as u8])?;
+                write_transaction(writer, *transaction)?;
+                write_path(writer, path)?;
+                write_bytes(writer, key)?;
+                write_optional_bytes(writer, value.as_deref())
+            }
+            Record::Put {
+                transaction,
+                path,
+                column,
+                key,
+                value,
+            } => {
+                writer.write_all(&[1, *column as u8])?;
+                write_transaction(writer, *transaction)?;
+                write_path(writer, path)?;
+                write_bytes(writer, key)?;
+                write_bytes(writer, value)
+            }
+            Record::Delete {
+                transaction,
+                path,
+                column,
+                key,
+            } => {
+                writer.write_all(&[2, *column as u8])?;
+                write_transaction(writer, *transaction)?;
+                write_path(writer, path)?;
+                write_bytes(writer, key)
+            }
+            Record::DeleteRange {
+                transaction,
+                path,
+                from,
+                to,
+            } => {
+                writer.write_all(&[3])?;
+                write_transaction(writer, *transaction)?;
+                write_path(writer, path)?;
+                write_bytes(writer, from)?;
+                write_bytes(writer, to)
+            }
+            Record::Clear { transaction, path } => {
+                writer.write_all(&[4])?;
+                write_transaction(writer, *transaction)?;
+                write_path(writer, path)
+            }
+            Record::Iterated {
+                transaction,
+                path,
+                column,
+                key,
+                value,
+            } => {
+                writer.write_all(&[5, *column as u8])?;
+                write_transaction(writer, *transaction)?;
+                write_path(writer, path)?;
+                write_bytes(writer, key)?;
+                write_optional_bytes(writer, value.as_deref())
+            }
+            Record::Begin { transaction } => {
+                writer.write_all(&[6])?;
+                writer.write_all(&transaction.to_be_bytes())
+            }
+            Record::Commit { transaction } => {
+                writer.write_all(&[7])?;
+                writer.write_all(&transaction.to_be_bytes())
+            }
+            Record::Rollback { transaction } => {
+                writer.write_all(&[8])?;
+                writer.write_all(&transaction.to_be_bytes())
+            }
+        }
+    }
+
+    /// Reads a record written by [`Record::write_to`], `None` at the end of
+    /// input
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut tag = [0u8];
+        if reader.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        let record = match tag[0] {
+            0 => Record::Get {
+                column: read_column(reader)?,
+                transaction: read_transaction(reader)?,
+                path: read_path(reader)?,
+                key: read_bytes(reader)?,
+                value: read_optional_bytes(reader)?,
+            },
+            1 => Record::Put {
+                column: read_column(reader)?,
+                transaction: read_transaction(reader)?,
+                path: read_path(reader)?,
+                key: read_bytes(reader)?,
+                value: read_bytes(reader)?,
+            },
+            2 => Record::Delete {
+                column: read_column(reader)?,
+                transaction: read_transaction(reader)?,
+                path: read_path(reader)?,
+                key: read_bytes(reader)?,
+            },
+            3 => Record::DeleteRange {
+                transaction: read_transaction(reader)?,
+                path: read_path(reader)?,
+                from: read_bytes(reader)?,
+                to: read_bytes(reader)?,
+            },
+            4 => Record::Clear {
+                transaction: read_transaction(reader)?,
+                path: read_path(reader)?,
+            },
+            5 => Record::Iterated {
+                column: read_column(reader)?,
+                transaction: read_transaction(reader)?,
+                path: read_path(reader)?,
+                key: read_bytes(reader)?,
+                value: read_optional_bytes(reader)?,
+            },
+            6 => Record::Begin {
+                transaction: read_u64(reader)?,
+            },
+            7 => Record::Commit {
+                transaction: read_u64(reader)?,
+            },
+            8 => Record::Rollback {
+                transaction: read_u64(reader)?,
+            },
+            _ => return Err(invalid_data("unknown record")),
+        };
+        Ok(Some(record))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn write_optional_bytes<W: Write>(writer: &mut W, bytes: Option<&[u8]>) -> io::Result<()> {
+    match bytes {
+        Some(bytes) => {
+            writer.write_all(&[1])?;
+            write_bytes(writer, bytes)
+        }
+        None => writer.write_all(&[0]),
+    }
+}
+
+fn write_transaction<W: Write>(writer: &mut W, transaction: Option<u64>) -> io::Result<()> {
+    match transaction {
+        Some(transaction) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&transaction.to_be_bytes())
+        }
+        None => writer.write_all(&[0]),
+    }
+}
+
+fn write_path<W: Write>(writer: &mut W, path: &[Vec<u8>]) -> io::Result<()> {
+    writer.write_all(&(path.len() as u32).to_be_bytes())?;
+    for segment in path {
+        write_bytes(writer, segment)?;
+    }
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn read_transaction<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    let mut tag = [0u8];
+    reader.read_exact(&mut tag)?;
+    match tag[0] {
+        0 => Ok(None),
+        1 => Ok(Some(read_u64(reader)?)),
+        _ => Err(invalid_data("invalid transaction")),
+    }
+}
+
+fn read_column<R: Read>(reader: &mut R) -> io::Result<Column> {
+    let mut tag = [0u8];
+    reader.read_exact(&mut tag)?;
+    Column::from_tag(tag[0])
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u32(reader)? as usize;
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(invalid_data("truncated record"));
+    }
+    Ok(bytes)
+}
+
+fn read_optional_bytes<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut tag = [0u8];
+    reader.read_exact(&mut tag)?;
+    match tag[0] {
+        0 => Ok(None),
+        1 => Ok(Some(read_bytes(reader)?)),
+        _ => Err(invalid_data("invalid optional value")),
+    }
+}
+
+fn read_path<R: Read>(reader: &mut R) -> io::Result<SubtreePath> {
+    let len = read_u32(reader)?;
+    (0..len).map(|_| read_bytes(reader)).collect()
+}
+
+fn batch_op_record(transaction: Option<u64>, path: &[Vec<u8>], op: &DynBatchOp) -> Record {
+    let path = path.to_vec();
+    match op.clone() {
+        DynBatchOp::Put(key, value) => Record::Put {
+            transaction,
+            path,
+            column: Column::Data,
+            key,
+            value,
+        },
+        DynBatchOp::PutAux(key, value) => Record::Put {
+            transaction,
+            path,
+            column: Column::Aux,
+            key,
+            value,
+        },
+        DynBatchOp::PutRoot(key, value) => Record::Put {
+            transaction,
+            path,
+            column: Column::Roots,
+            key,
+            value,
+        },
+        DynBatchOp::Delete(key) => Record::Delete {
+            transaction,
+            path,
+            column: Column::Data,
+            key,
+        },
+        DynBatchOp::DeleteRange(from, to) => Record::DeleteRange {
+            transaction,
+            path,
+            from,
+            to,
+        },
+        DynBatchOp::Clear => Record::Clear { transaction, path },
+        DynBatchOp::DeleteAux(key) => Record::Delete {
+            transaction,
+            path,
+            column: Column::Aux,
+            key,
+        },
+        DynBatchOp::DeleteRoot(key) => Record::Delete {
+            transaction,
+            path,
+            column: Column::Roots,
+            key,
+        },
+    }
+}
+
+/// Storage decorator which logs every operation to a file
+pub struct RecordingStorage {
+    inner: BoxedStorage,
+    log: Mutex<BufWriter<File>>,
+    next_transaction_id: AtomicU64,
+}
+
+impl RecordingStorage {
+    /// Wraps `inner` storage, records are written into a new file at
+    /// `log_path`
+    pub fn new<P: AsRef<Path>>(inner: BoxedStorage, log_path: P) -> io::Result<Self> {
+        Ok(RecordingStorage {
+            inner,
+            log: Mutex::new(BufWriter::new(File::create(log_path)?)),
+            next_transaction_id: AtomicU64::new(0),
+        })
+    }
+
+    /// Writes buffered records to the file
+    pub fn flush_log(&self) -> io::Result<()> {
+        self.log.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+
+    fn record(&self, record: Record) -> Result<(), DynStorageError> {
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        record.write_to(&mut *log).map_err(DynStorageError::new)
+    }
+
+    fn recording_transaction<'db>(
+        &'db self,
+        inner: Box<dyn DynTransaction + 'db>,
+    ) -> Box<dyn DynTransaction + 'db> {
+        let id = self.next_transaction_id.fetch_add(1, Ordering::Relaxed);
+        // Transactions are started infallibly, a failed write is discovered on
+        // the next recorded operation or log flush
+        let _ = self.record(Record::Begin { transaction: id });
+        Box::new(RecordingTransaction {
+            storage: self,
+            inner,
+            id,
+        })
+    }
+}
+
+impl<'db> DynStorage<'db> for RecordingStorage {
+    fn start_transaction(&'db self) -> Box<dyn DynTransaction + 'db> {
+        self.recording_transaction(self.inner.start_transaction())
+    }
+
+    fn start_snapshot_transaction(&'db self) -> Box<dyn DynTransaction + 'db> {
+        self.recording_transaction(self.inner.start_snapshot_transaction())
+    }
+
+    fn flush(&self) -> Result<(), DynStorageError> {
+        self.flush_log().map_err(DynStorageError::new)?;
+        self.inner.flush()
+    }
+
+    fn storage_context(&'db self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'db> + 'db> {
+        Box::new(RecordingContext {
+            storage: self,
+            inner: self.inner.storage_context(path),
+            path: path.iter().map(|x| x.to_vec()).collect(),
+            transaction: None,
+        })
+    }
+}
+
+struct RecordingTransaction<'db> {
+    storage: &'db RecordingStorage,
+    inner: Box<dyn DynTransaction + 'db>,
+    id: u64,
+}
+
+impl<'db> DynTransaction for RecordingTransaction<'db> {
+    fn storage_context<'a>(&'a self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'a> + 'a> {
+        Box::new(RecordingContext {
+            storage: self.storage,
+            inner: self.inner.storage_context(path),
+            path: path.iter().map(|x| x.to_vec()).collect(),
+            transaction: Some(self.id),
+        })
+    }
+
+    fn commit(self: Box<Self>) -> Result<(), DynStorageError> {
+        let RecordingTransaction { storage, inner, id } = *self;
+        match inner.commit() {
+            Ok(()) => storage.record(Record::Commit { transaction: id }),
+            Err(e) => {
+                // A transaction which failed to commit is discarded
+                storage.record(Record::Rollback { transaction: id })?;
+                Err(e)
+            }
+        }
+    }
+
+    fn rollback(&self) -> Result<(), DynStorageError> {
+        self.inner.rollback()?;
+        self.storage.record(Record::Rollback {
+            transaction: self.id,
+        })
+    }
+}
+
+struct RecordingContext<'db> {
+    storage: &'db RecordingStorage,
+    inner: Box<dyn DynStorageContext<'db> + 'db>,
+    path: SubtreePath,
+    /// ID of the transaction the context belongs to
+    transaction: Option<u64>,
+}
+
+impl<'db> RecordingContext<'db> {
+    fn record_put(&self, column: Column, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.record(Record::Put {
+            transaction: self.transaction,
+            path: self.path.clone(),
+            column,
+            key: key.to_vec(),
+            value: value.to_vec(),
+        })
+    }
+
+    fn record_delete(&self, column: Column, key: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.record(Record::Delete {
+            transaction: self.transaction,
+            path: self.path.clone(),
+            column,
+            key: key.to_vec(),
+        })
+    }
+
+    fn record_get(
+        &self,
+        column: Column,
+        key: &[u8],
+        value: Result<Option<Vec<u8>>, DynStorageError>,
+    ) -> Result<Option<Vec<u8>>, DynStorageError> {
+        let value = value?;
+        self.storage.record(Record::Get {
+            transaction: self.transaction,
+            path: self.path.clone(),
+            column,
+            key: key.to_vec(),
+            value: value.clone(),
+        })?;
+        Ok(value)
+    }
+
+    fn recording_iter(
+        &self,
+        column: Column,
+        inner: Box<dyn DynRawIterator + 'db>,
+    ) -> Box<dyn DynRawIterator + 'db> {
+        Box::new(RecordingIterator {
+            storage: self.storage,
+            inner,
+            path: self.path.clone(),
+            column,
+            transaction: self.transaction,
+        })
+    }
+}
+
+impl<'db> DynStorageContext<'db> for RecordingContext<'db> {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.record_put(Column::Data, key, value)?;
+        self.inner.put(key, value)
+    }
+
+    fn put_aux(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.record_put(Column::Aux, key, value)?;
+        self.inner.put_aux(key, value)
+    }
+
+    fn put_root(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.record_put(Column::Roots, key, value)?;
+        self.inner.put_root(key, value)
+    }
+
+    fn put_meta(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.record_put(Column::Meta, key, value)?;
+        self.inner.put_meta(key, value)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.record_delete(Column::Data, key)?;
+        self.inner.delete(key)
+    }
+
+    fn delete_range(&self, from: &[u8], to: &[u8]) -> Result<(), DynStorageError> {
+        self.storage.record(Record::DeleteRange {
+            transaction: self.transaction,
+            path: self.path.clone(),
+            from: from.to_vec(),
+            to: to.to_vec(),
+        })?;
+        self.inner.delete_range(from, to)
+    }
+
+    fn clear(&self) -> Result<(), DynStorageError> {
+        self.storage.record(Record::Clear {
+            transaction: self.transaction,
+            path: self.path.clone(),
+        })?;
+        self.inner.clear()
+    }
+
+    fn delete_aux(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.record_delete(Column::Aux, key)?;
+        self.inner.delete_aux(key)
+    }
+
+    fn delete_root(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.record_delete(Column::Roots, key)?;
+        self.inner.delete_root(key)
+    }
+
+    fn delete_meta(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.record_delete(Column::Meta, key)?;
+        self.inner.delete_meta(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.record_get(Column::Data, key, self.inner.get(key))
+    }
+
+    fn get_aux(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.record_get(Column::Aux, key, self.inner.get_aux(key))
+    }
+
+    fn get_root(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.record_get(Column::Roots, key, self.inner.get_root(key))
+    }
+
+    fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.record_get(Column::Meta, key, self.inner.get_meta(key))
+    }
+
+    fn commit_batch(&self, batch: DynBatch) -> Result<(), DynStorageError> {
+        for op in batch.ops() {
+            self.storage
+                .record(batch_op_record(self.transaction, &self.path, op))?;
+        }
+        self.inner.commit_batch(batch)
+    }
+
+    fn raw_iter(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.recording_iter(Column::Data, self.inner.raw_iter())
+    }
+
+    fn raw_iter_opt(
+        &self,
+        readahead_bytes: usize,
+        fill_cache: bool,
+    ) -> Box<dyn DynRawIterator + 'db> {
+        self.recording_iter(
+            Column::Data,
+            self.inner.raw_iter_opt(readahead_bytes, fill_cache),
+        )
+    }
+
+    fn raw_iter_keys_only(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.recording_iter(Column::Data, self.inner.raw_iter_keys_only())
+    }
+
+    fn raw_iter_aux(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.recording_iter(Column::Aux, self.inner.raw_iter_aux())
+    }
+
+    fn raw_iter_roots(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.recording_iter(Column::Roots, self.inner.raw_iter_roots())
+    }
+}
+
+/// Raw iterator which records every entry it stops at
+struct RecordingIterator<'db> {
+    storage: &'db RecordingStorage,
+    inner: Box<dyn DynRawIterator + 'db>,
+    path: SubtreePath,
+    column: Column,
+    transaction: Option<u64>,
+}
+
+impl RecordingIterator<'_> {
+    fn record_position(&self) {
+        if let Some(key) = self.inner.key() {
+            // Iterators can't return errors, a failed write is discovered on
+            // the next recorded operation or log flush
+            let _ = self.storage.record(Record::Iterated {
+                transaction: self.transaction,
+                path: self.path.clone(),
+                column: self.column,
+                key: key.to_vec(),
+                value: self.inner.value().map(|v| v.to_vec()),
+            });
+        }
+    }
+}
+
+impl DynRawIterator for RecordingIterator<'_> {
+    fn seek_to_first(&mut self) {
+        self.inner.seek_to_first();
+        self.record_position();
+    }
+
+    fn seek_to_last(&mut self) {
+        self.inner.seek_to_last();
+        self.record_position();
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        self.inner.seek(key);
+        self.record_position();
+    }
+
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        self.inner.seek_for_prev(key);
+        self.record_position();
+    }
+
+    fn next(&mut self) {
+        self.inner.next();
+        self.record_position();
+    }
+
+    fn prev(&mut self) {
+        self.inner.prev();
+        self.record_position();
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        self.inner.value()
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        self.inner.key()
+    }
+
+    fn valid(&self) -> bool {
+        self.inner.valid()
+    }
+
+    fn status(&self) -> Result<(), DynStorageError> {
+        self.inner.status()
+    }
+}
+
+/// Error of reading data which is not in the recording
+#[derive(Debug)]
+pub struct NotRecordedError {
+    pub path: SubtreePath,
+    pub column: Column,
+    pub key: Vec<u8>,
+}
+
+impl fmt::Display for NotRecordedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} key {:?} of subtree {:?} is not in the recording",
+            self.column, self.key, self.path
+        )
+    }
+}
+
+impl std::error::Error for NotRecordedError {}
+
+/// Known entries of a column of a subtree, `None` for a known absence
+type RecordedEntries = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Known entries by subtree and column
+type ColumnEntries = HashMap<(SubtreePath, Column), RecordedEntries>;
+
+/// Storage which serves reads from a recording made by [`RecordingStorage`].
+/// Writes are applied in memory, reading data the recording doesn't have
+/// fails with [`NotRecordedError`].
+pub struct ReplayStorage {
+    entries: Mutex<ColumnEntries>,
+}
+
+impl ReplayStorage {
+    /// Loads a recording from a file
+    pub fn open<P: AsRef<Path>>(log_path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(log_path)?);
+        let mut records = Vec::new();
+        while let Some(record) = Record::read_from(&mut reader)? {
+            records.push(record);
+        }
+        Ok(Self::from_records(records))
+    }
+
+    /// Builds replay storage from records. Values which were read are known
+    /// as they were before the first write to them visible to the read, that
+    /// is a committed write or a write of the same transaction. Keys-only
+    /// iterations don't reveal values, so such entries are known only from
+    /// other reads.
+    pub fn from_records<I: IntoIterator<Item = Record>>(records: I) -> Self {
+        let mut entries = ColumnEntries::new();
+        let mut committed: HashSet<WrittenKey> = HashSet::new();
+        let mut uncommitted: HashMap<u64, HashSet<WrittenKey>> = HashMap::new();
+        for record in records {
+            let (transaction, path, column, key, value) = match record {
+                Record::Get {
+                    transaction,
+                    path,
+                    column,
+                    key,
+                    value,
+                } => (transaction, path, column, key, value),
+                Record::Iterated {
+                    transaction,
+                    path,
+                    column,
+                    key,
+                    value: Some(value),
+                } => (transaction, path, column, key, Some(value)),
+                Record::Put {
+                    transaction,
+                    path,
+                    column,
+                    key,
+                    ..
+                }
+                | Record::Delete {
+                    transaction,
+                    path,
+                    column,
+                    key,
+                } => {
+                    let written = match transaction {
+                        Some(transaction) => uncommitted.entry(transaction).or_default(),
+                        None => &mut committed,
+                    };
+                    written.insert((path, column, key));
+                    continue;
+                }
+                Record::Commit { transaction } => {
+                    committed.extend(uncommitted.remove(&transaction).unwrap_or_default());
+                    continue;
+                }
+                Record::Rollback { transaction } => {
+                    uncommitted.remove(&transaction);
+                    continue;
+                }
+                // Range deletions and clears are replayed on entries known
+                // from reads
+                Record::Iterated { value: None, .. }
+                | Record::DeleteRange { .. }
+                | Record::Clear { .. }
+                | Record::Begin { .. } => continue,
+            };
+            let written_key = (path, column, key);
+            let written = committed.contains(&written_key)
+                || transaction
+                    .and_then(|transaction| uncommitted.get(&transaction))
+                    .map_or(false, |written| written.contains(&written_key));
+            if !written {
+                let (path, column, key) = written_key;
+                entries
+                    .entry((path, column))
+                    .or_default()
+                    .entry(key)
+                    .or_insert(value);
+            }
+        }
+        ReplayStorage {
+            entries: Mutex::new(entries),
+        }
+    }
+
+    fn with_entries<T, F>(&self, path: &[Vec<u8>], column: Column, f: F) -> T
+    where
+        F: FnOnce(&mut RecordedEntries) -> T,
+    {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        f(entries.entry((path.to_vec(), column)).or_default())
+    }
+}
+
+/// Key written to a column of a subtree
+type WrittenKey = (SubtreePath, Column, Vec<u8>);
+
+impl<'db> DynStorage<'db> for ReplayStorage {
+    fn start_transaction(&'db self) -> Box<dyn DynTransaction + 'db> {
+        Box::new(ReplayTransaction {
+            storage: self,
+            writes: Mutex::new(ColumnEntries::new()),
+        })
+    }
+
+    fn flush(&self) -> Result<(), DynStorageError> {
+        Ok(())
+    }
+
+    fn storage_context(&'db self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'db> + 'db> {
+        Box::new(ReplayContext {
+            storage: self,
+            path: path.iter().map(|x| x.to_vec()).collect(),
+            writes: None,
+        })
+    }
+}
+
+/// Transaction of replay storage, which keeps its writes apart until commit
+struct ReplayTransaction<'db> {
+    storage: &'db ReplayStorage,
+    writes: Mutex<ColumnEntries>,
+}
+
+impl<'db> DynTransaction for ReplayTransaction<'db> {
+    fn storage_context<'a>(&'a self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'a> + 'a> {
+        Box::new(ReplayContext {
+            storage: self.storage,
+            path: path.iter().map(|x| x.to_vec()).collect(),
+            writes: Some(&self.writes),
+        })
+    }
+
+    fn commit(self: Box<Self>) -> Result<(), DynStorageError> {
+        let ReplayTransaction { storage, writes } = *self;
+        let writes = writes.into_inner().unwrap_or_else(|e| e.into_inner());
+        let mut entries = storage.entries.lock().unwrap_or_else(|e| e.into_inner());
+        for (column, written) in writes {
+            entries.entry(column).or_default().extend(written);
+        }
+        Ok(())
+    }
+
+    fn rollback(&self) -> Result<(), DynStorageError> {
+        self.writes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        Ok(())
+    }
+}
+
+struct ReplayContext<'db> {
+    storage: &'db ReplayStorage,
+    path: SubtreePath,
+    /// Writes of the transaction the context belongs to
+    writes: Option<&'db Mutex<ColumnEntries>>,
+}
+
+impl ReplayContext<'_> {
+    /// Returns known entries of a column with uncommitted writes of the
+    /// transaction applied
+    fn visible_entries(&self, column: Column) -> RecordedEntries {
+        let mut entries = self
+            .storage
+            .with_entries(&self.path, column, |entries| entries.clone());
+        if let Some(writes) = self.writes {
+            let writes = writes.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(written) = writes.get(&(self.path.clone(), column)) {
+                entries.extend(written.clone());
+            }
+        }
+        entries
+    }
+
+    fn put_entry(&self, column: Column, key: &[u8], value: Option<&[u8]>) {
+        let value = value.map(|v| v.to_vec());
+        match self.writes {
+            Some(writes) => {
+                writes
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .entry((self.path.clone(), column))
+                    .or_default()
+                    .insert(key.to_vec(), value);
+            }
+            None => self.storage.with_entries(&self.path, column, |entries| {
+                entries.insert(key.to_vec(), value);
+            }),
+        }
+    }
+
+    fn get_entry(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        let written = self.writes.and_then(|writes| {
+            writes
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&(self.path.clone(), column))
+                .and_then(|written| written.get(key).cloned())
+        });
+        written
+            .or_else(|| {
+                self.storage
+                    .with_entries(&self.path, column, |entries| entries.get(key).cloned())
+            })
+            .ok_or_else(|| {
+                DynStorageError::new(NotRecordedError {
+                    path: self.path.clone(),
+                    column,
+                    key: key.to_vec(),
+                })
+            })
+    }
+
+    fn delete_data_range(&self, from: Option<&[u8]>, to: Option<&[u8]>) {
+        for key in self.visible_entries(Column::Data).into_keys() {
+            let after_from = from.map(|from| key.as_slice() >= from).unwrap_or(true);
+            let before_to = to.map(|to| key.as_slice() < to).unwrap_or(true);
+            if after_from && before_to {
+                self.put_entry(Column::Data, &key, None);
+            }
+        }
+    }
+
+    fn replay_iter(&self, column: Column) -> Box<dyn DynRawIterator + 'static> {
+        let entries = self
+            .visible_entries(column)
+            .into_iter()
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .collect();
+        Box::new(ReplayIterator {
+            entries,
+            position: None,
+        })
+    }
+}
+
+impl<'db> DynStorageContext<'db> for ReplayContext<'db> {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.put_entry(Column::Data, key, Some(value));
+        Ok(())
+    }
+
+    fn put_aux(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.put_entry(Column::Aux, key, Some(value));
+        Ok(())
+    }
+
+    fn put_root(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.put_entry(Column::Roots, key, Some(value));
+        Ok(())
+    }
+
+    fn put_meta(&self, key: &[u8], value: &[u8]) -> Result<(), DynStorageError> {
+        self.put_entry(Column::Meta, key, Some(value));
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.put_entry(Column::Data, key, None);
+        Ok(())
+    }
+
+    fn delete_range(&self, from: &[u8], to: &[u8]) -> Result<(), DynStorageError> {
+        self.delete_data_range(Some(from), Some(to));
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), DynStorageError> {
+        self.delete_data_range(None, None);
+        Ok(())
+    }
+
+    fn delete_aux(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.put_entry(Column::Aux, key, None);
+        Ok(())
+    }
+
+    fn delete_root(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.put_entry(Column::Roots, key, None);
+        Ok(())
+    }
+
+    fn delete_meta(&self, key: &[u8]) -> Result<(), DynStorageError> {
+        self.put_entry(Column::Meta, key, None);
+        Ok(())
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.get_entry(Column::Data, key)
+    }
+
+    fn get_aux(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.get_entry(Column::Aux, key)
+    }
+
+    fn get_root(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.get_entry(Column::Roots, key)
+    }
+
+    fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DynStorageError> {
+        self.get_entry(Column::Meta, key)
+    }
+
+    fn commit_batch(&self, batch: DynBatch) -> Result<(), DynStorageError> {
+        let mut context = self;
+        batch.replay_into(&mut context)
+    }
+
+    fn raw_iter(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.replay_iter(Column::Data)
+    }
+
+    fn raw_iter_opt(&self, _: usize, _: bool) -> Box<dyn DynRawIterator + 'db> {
+        self.replay_iter(Column::Data)
+    }
+
+    fn raw_iter_keys_only(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.replay_iter(Column::Data)
+    }
+
+    fn raw_iter_aux(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.replay_iter(Column::Aux)
+    }
+
+    fn raw_iter_roots(&self) -> Box<dyn DynRawIterator + 'db> {
+        self.replay_iter(Column::Roots)
+    }
+}
+
+impl Batch for &ReplayContext<'_> {
+    type Error = DynStorageError;
+
+    fn put<K: AsRef<[u8]>>(&mut self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        DynStorageContext::put(*self, key.as_ref(), value)
+    }
+
+    fn put_aux<K: AsRef<[u8]>>(&mut self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        DynStorageContext::put_aux(*self, key.as_ref(), value)
+    }
+
+    fn put_root<K: AsRef<[u8]>>(&mut self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        DynStorageContext::put_root(*self, key.as_ref(), value)
+    }
+
+    fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
+        DynStorageContext::delete(*self, key.as_ref())
+    }
+
+    fn delete_range<K: AsRef<[u8]>>(&mut self, from: K, to: K) -> Result<(), Self::Error> {
+        DynStorageContext::delete_range(*self, from.as_ref(), to.as_ref())
+    }
+
+    fn clear(&mut self) -> Result<(), Self::Error> {
+        DynStorageContext::clear(*self)
+    }
+
+    fn delete_aux<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
+        DynStorageContext::delete_aux(*self, key.as_ref())
+    }
+
+    fn delete_root<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
+        DynStorageContext::delete_root(*self, key.as_ref())
+    }
+}
+
+/// Raw iterator over a snapshot of recorded data entries
+struct ReplayIterator {
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    position: Option<usize>,
+}
+
+impl DynRawIterator for ReplayIterator {
+    fn seek_to_first(&mut self) {
+        self.position = if self.entries.is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+    }
+
+    fn seek_to_last(&mut self) {
+        self.position = self.entries.len().checked_sub(1);
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        let idx = self.entries.partition_point(|(k, _)| k.as_slice() < key);
+        self.position = if idx < self.entries.len() {
+            Some(idx)
+        } else {
+            None
+        };
+    }
+
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        let idx = self.entries.partition_point(|(k, _)| k.as_slice() <= key);
+        self.position = idx.checked_sub(1);
+    }
+
+    fn next(&mut self) {
+        self.position = self
+            .position
+            .map(|p| p + 1)
+            .filter(|p| *p < self.entries.len());
+    }
+
+    fn prev(&mut self) {
+        self.position = self.position.and_then(|p| p.checked_sub(1));
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        self.position.map(|p| self.entries[p].1.as_slice())
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        self.position.map(|p| self.entries[p].0.as_slice())
+    }
+
+    fn valid(&self) -> bool {
+        self.position.is_some()
+    }
+
+    fn status(&self) -> Result<(), DynStorageError> {
+        Ok(())
+    }
+}
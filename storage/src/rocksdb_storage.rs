@@ -1,14 +1,24 @@
 //! GroveDB storage layer implemented over RocksDB backend.
+mod dyn_storage;
+mod perf;
+mod read_only;
 mod storage;
 mod storage_context;
 pub mod test_utils;
 #[cfg(test)]
 mod tests;
+mod write_gate;
 
-pub use rocksdb::Error;
+pub use perf::{DataAccessCounters, PerfCounters, PerfScope};
+pub use read_only::{ReadOnlyError, ReadOnlyRocksDbStorage};
+pub use write_gate::WriteError;
+pub use rocksdb::{Cache, Error};
 pub use storage_context::{
     PrefixedRocksDbBatch, PrefixedRocksDbRawIterator, PrefixedRocksDbStorageContext,
-    PrefixedRocksDbTransactionContext,
+    PrefixedRocksDbTransactionContext, ReadOnlyRocksDbStorageContext,
 };
 
-pub use self::storage::RocksDbStorage;
+pub use self::storage::{
+    ColumnFamily, HistogramData, MemoryUsage, PreparedCommit, RocksDbStorage, RocksDbTuning,
+    StorageStatistics, WalRecoveryMode, WritesBlocked,
+};
@@ -0,0 +1,129 @@
+//! Implementation of the object-safe storage facade for RocksDB backend.
+use rocksdb::{OptimisticTransactionDB, SnapshotWithThreadMode, Transaction};
+
+use super::{
+    PrefixedRocksDbStorageContext, PrefixedRocksDbTransactionContext, RocksDbStorage, WritesBlocked,
+};
+use crate::{
+    dyn_storage::{
+        impl_dyn_storage_context, subtree_storage_context, DynBatch, DynRawIterator, DynSnapshot,
+        DynStorage, DynStorageContext, DynStorageError,
+    },
+    DynTransaction, Storage, StorageContext,
+};
+
+/// Transaction of RocksDB storage used through the facade
+struct RocksDbDynTransaction<'db> {
+    storage: &'db RocksDbStorage,
+    transaction: Transaction<'db, OptimisticTransactionDB>,
+    /// Snapshot shared with other transactions to read from, if the
+    /// transaction was started at one
+    snapshot: Option<&'db SnapshotWithThreadMode<'db, OptimisticTransactionDB>>,
+}
+
+/// Snapshot of RocksDB storage used through the facade
+struct RocksDbDynSnapshot<'db> {
+    storage: &'db RocksDbStorage,
+    snapshot: SnapshotWithThreadMode<'db, OptimisticTransactionDB>,
+}
+
+impl DynSnapshot for RocksDbDynSnapshot<'_> {
+    fn start_transaction<'a>(&'a self) -> Box<dyn DynTransaction + 'a> {
+        Box::new(RocksDbDynTransaction {
+            storage: self.storage,
+            transaction: Storage::start_transaction(self.storage),
+            snapshot: Some(&self.snapshot),
+        })
+    }
+}
+
+impl WritesBlocked<'_> {
+    /// Starts a transaction reading from the snapshot writes are blocked at.
+    /// Its writes are blocked too, so it must be only read from.
+    pub fn start_transaction(&self) -> Box<dyn DynTransaction + '_> {
+        Box::new(RocksDbDynTransaction {
+            storage: self.storage,
+            transaction: Storage::start_transaction(self.storage),
+            snapshot: Some(&self.snapshot),
+        })
+    }
+}
+
+impl<'db> DynStorage<'db> for RocksDbStorage {
+    fn start_transaction(&'db self) -> Box<dyn DynTransaction + 'db> {
+        Box::new(RocksDbDynTransaction {
+            storage: self,
+            transaction: Storage::start_transaction(self),
+            snapshot: None,
+        })
+    }
+
+    fn start_snapshot_transaction(&'db self) -> Box<dyn DynTransaction + 'db> {
+        Box::new(RocksDbDynTransaction {
+            storage: self,
+            transaction: RocksDbStorage::start_snapshot_transaction(self),
+            snapshot: None,
+        })
+    }
+
+    fn snapshot(&'db self) -> Option<Box<dyn DynSnapshot + 'db>> {
+        Some(Box::new(RocksDbDynSnapshot {
+            storage: self,
+            snapshot: self.db.snapshot(),
+        }))
+    }
+
+    fn flush(&self) -> Result<(), DynStorageError> {
+        Storage::flush(self).map_err(DynStorageError::new)
+    }
+
+    fn storage_context(&'db self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'db> + 'db> {
+        subtree_storage_context(path, |prefix| {
+            Box::new(PrefixedRocksDbStorageContext::new(self, prefix))
+        })
+    }
+
+    fn rocksdb(&self) -> Option<&RocksDbStorage> {
+        Some(self)
+    }
+}
+
+impl<'db> DynTransaction for RocksDbDynTransaction<'db> {
+    fn storage_context<'a>(&'a self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'a> + 'a> {
+        subtree_storage_context(path, |prefix| {
+            let context = match self.snapshot {
+                Some(snapshot) => PrefixedRocksDbTransactionContext::new_at_snapshot(
+                    &self.storage.db,
+                    &self.transaction,
+                    snapshot,
+                    prefix,
+                ),
+                None => PrefixedRocksDbTransactionContext::new(
+                    &self.storage.db,
+                    &self.transaction,
+                    prefix,
+                ),
+            };
+            Box::new(context)
+        })
+    }
+
+    fn commit(self: Box<Self>) -> Result<(), DynStorageError> {
+        self.storage
+            .commit_transaction(self.transaction)
+            .map_err(DynStorageError::new)
+    }
+
+    fn rollback(&self) -> Result<(), DynStorageError> {
+        self.storage
+            .rollback_transaction(&self.transaction)
+            .map_err(DynStorageError::new)
+    }
+
+    fn rocksdb(&self) -> Option<&Transaction<'_, OptimisticTransactionDB>> {
+        Some(&self.transaction)
+    }
+}
+
+impl_dyn_storage_context!(PrefixedRocksDbStorageContext);
+impl_dyn_storage_context!(PrefixedRocksDbTransactionContext);
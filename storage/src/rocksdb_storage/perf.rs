@@ -0,0 +1,137 @@
+//! RocksDB perf context counters and subtree data access counters of storage
+//! operations made on the current thread.
+
+use std::cell::Cell;
+
+use rocksdb::perf::{set_perf_stats, PerfContext, PerfMetric, PerfStatsLevel};
+
+thread_local! {
+    /// Number of perf scopes open on the thread, perf stats are collected
+    /// while it's above zero
+    static OPEN_SCOPES: Cell<usize> = Cell::new(0);
+    static DATA_ACCESS: Cell<DataAccessCounters> = Cell::new(DataAccessCounters::default());
+}
+
+/// RocksDB perf context counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PerfCounters {
+    /// Number of blocks read from storage files
+    pub block_read_count: u64,
+    /// Number of bytes of blocks read from storage files
+    pub block_read_bytes: u64,
+    /// Number of blocks found in the block cache
+    pub block_cache_hit_count: u64,
+    /// Number of seeks in storage files
+    pub seek_count: u64,
+    /// Number of bytes of values read by point lookups and iterators
+    pub read_bytes: u64,
+    /// Nanoseconds writes were delayed or stopped by write stalls
+    pub write_stall_nanos: u64,
+}
+
+impl PerfCounters {
+    fn read(context: &PerfContext) -> Self {
+        PerfCounters {
+            block_read_count: context.metric(PerfMetric::BlockReadCount),
+            block_read_bytes: context.metric(PerfMetric::BlockReadByte),
+            block_cache_hit_count: context.metric(PerfMetric::BlockCacheHitCount),
+            seek_count: context.metric(PerfMetric::SeekChildSeekCount),
+            read_bytes: context.metric(PerfMetric::GetReadBytes)
+                + context.metric(PerfMetric::IterReadBytes),
+            write_stall_nanos: context.metric(PerfMetric::WriteDelayTime),
+        }
+    }
+
+    fn since(&self, start: &Self) -> Self {
+        PerfCounters {
+            block_read_count: self.block_read_count.saturating_sub(start.block_read_count),
+            block_read_bytes: self.block_read_bytes.saturating_sub(start.block_read_bytes),
+            block_cache_hit_count: self
+                .block_cache_hit_count
+                .saturating_sub(start.block_cache_hit_count),
+            seek_count: self.seek_count.saturating_sub(start.seek_count),
+            read_bytes: self.read_bytes.saturating_sub(start.read_bytes),
+            write_stall_nanos: self
+                .write_stall_nanos
+                .saturating_sub(start.write_stall_nanos),
+        }
+    }
+}
+
+/// Collects perf counters of storage operations made on the current thread
+/// from its start until it's finished. Scopes may be nested, the collection
+/// stops once the outermost one is dropped.
+pub struct PerfScope {
+    context: PerfContext,
+    start: PerfCounters,
+}
+
+impl PerfScope {
+    pub fn start() -> Self {
+        let mut context = PerfContext::default();
+        if OPEN_SCOPES.with(|scopes| scopes.replace(scopes.get() + 1)) == 0 {
+            set_perf_stats(PerfStatsLevel::EnableTimeExceptForMutex);
+            context.reset();
+        }
+        let start = PerfCounters::read(&context);
+        PerfScope { context, start }
+    }
+
+    /// Returns counters of operations made since the scope start
+    pub fn finish(self) -> PerfCounters {
+        PerfCounters::read(&self.context).since(&self.start)
+    }
+}
+
+impl Drop for PerfScope {
+    fn drop(&mut self) {
+        if OPEN_SCOPES.with(|scopes| scopes.replace(scopes.get() - 1)) == 1 {
+            set_perf_stats(PerfStatsLevel::Disable);
+        }
+    }
+}
+
+/// Numbers of subtree data reads and writes, without aux, roots and meta
+/// data. Sizes are of unprefixed keys and values, as they are seen by Merk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DataAccessCounters {
+    /// Number of point lookups
+    pub get_count: u64,
+    /// Number of bytes of keys and found values of point lookups
+    pub loaded_bytes: u64,
+    /// Number of bytes of keys and values of puts and keys of deletions
+    pub written_bytes: u64,
+}
+
+impl DataAccessCounters {
+    /// Returns counters of accesses made on the current thread so far
+    pub fn current() -> Self {
+        DATA_ACCESS.with(|counters| counters.get())
+    }
+
+    /// Returns counters of accesses made since `start` was taken
+    pub fn since(&self, start: &Self) -> Self {
+        DataAccessCounters {
+            get_count: self.get_count.saturating_sub(start.get_count),
+            loaded_bytes: self.loaded_bytes.saturating_sub(start.loaded_bytes),
+            written_bytes: self.written_bytes.saturating_sub(start.written_bytes),
+        }
+    }
+}
+
+pub(crate) fn record_get(loaded_bytes: usize) {
+    DATA_ACCESS.with(|counters| {
+        let mut current = counters.get();
+        current.get_count += 1;
+        current.loaded_bytes += loaded_bytes as u64;
+        counters.set(current);
+    });
+}
+
+pub(crate) fn record_write(written_bytes: usize) {
+    DATA_ACCESS.with(|counters| {
+        let mut current = counters.get();
+        current.written_bytes += written_bytes as u64;
+        counters.set(current);
+    });
+}
@@ -0,0 +1,149 @@
+//! RocksDB storage opened read-only, for serving checkpoints.
+use std::{fmt, path::Path};
+
+use lazy_static::lazy_static;
+use rocksdb::{Error, DB};
+
+use super::{
+    storage::{
+        AUX_CF_NAME, DEDUP_CF_NAME, DEDUP_REFERENCE_COUNT_LENGTH, META_CF_NAME, ROOTS_CF_NAME,
+    },
+    ReadOnlyRocksDbStorageContext,
+};
+use crate::{
+    dyn_storage::{
+        impl_dyn_storage_context, subtree_storage_context, DynBatch, DynRawIterator, DynSnapshot,
+        DynStorage, DynStorageContext, DynStorageError,
+    },
+    DynTransaction, StorageContext,
+};
+
+lazy_static! {
+    static ref READ_ONLY_OPTS: rocksdb::Options = {
+        let mut opts = rocksdb::Options::default();
+        opts.set_allow_mmap_reads(true);
+        opts.set_max_open_files(64);
+        opts
+    };
+}
+
+/// Error of storage opened read-only
+#[derive(Debug)]
+pub enum ReadOnlyError {
+    /// Error of RocksDB itself
+    RocksDb(Error),
+    /// Storage can't be written to
+    Write,
+}
+
+impl fmt::Display for ReadOnlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadOnlyError::RocksDb(e) => e.fmt(f),
+            ReadOnlyError::Write => write!(f, "storage is opened read-only"),
+        }
+    }
+}
+
+impl std::error::Error for ReadOnlyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadOnlyError::RocksDb(e) => Some(e),
+            ReadOnlyError::Write => None,
+        }
+    }
+}
+
+impl From<Error> for ReadOnlyError {
+    fn from(e: Error) -> Self {
+        ReadOnlyError::RocksDb(e)
+    }
+}
+
+/// RocksDB storage opened read-only. Data never changes while it's open, so
+/// transactions read the same state without a snapshot and writes through
+/// them fail with [`ReadOnlyError::Write`].
+pub struct ReadOnlyRocksDbStorage {
+    db: DB,
+}
+
+impl ReadOnlyRocksDbStorage {
+    /// Opens an existing database, usually a checkpoint, for reading. Nothing
+    /// is created if missing, no lock is taken and no extra background threads
+    /// or file handles are requested, so many checkpoints can be kept open at
+    /// once.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let db = DB::open_cf_for_read_only(
+            &READ_ONLY_OPTS,
+            path,
+            [AUX_CF_NAME, ROOTS_CF_NAME, META_CF_NAME, DEDUP_CF_NAME],
+            false,
+        )?;
+        Ok(ReadOnlyRocksDbStorage { db })
+    }
+
+    /// Returns a deduplicated value by its hash
+    pub fn get_dedup_value(&self, hash: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let cf_dedup = self
+            .db
+            .cf_handle(DEDUP_CF_NAME)
+            .expect("dedup column family must exist");
+        Ok(self
+            .db
+            .get_cf(cf_dedup, hash)?
+            .map(|record| record[DEDUP_REFERENCE_COUNT_LENGTH..].to_vec()))
+    }
+}
+
+/// Transaction of read-only storage, which has nothing to commit
+struct ReadOnlyDynTransaction<'db> {
+    storage: &'db ReadOnlyRocksDbStorage,
+}
+
+impl DynSnapshot for ReadOnlyDynTransaction<'_> {
+    fn start_transaction<'a>(&'a self) -> Box<dyn DynTransaction + 'a> {
+        Box::new(ReadOnlyDynTransaction {
+            storage: self.storage,
+        })
+    }
+}
+
+impl<'db> DynStorage<'db> for ReadOnlyRocksDbStorage {
+    fn start_transaction(&'db self) -> Box<dyn DynTransaction + 'db> {
+        Box::new(ReadOnlyDynTransaction { storage: self })
+    }
+
+    fn snapshot(&'db self) -> Option<Box<dyn DynSnapshot + 'db>> {
+        Some(Box::new(ReadOnlyDynTransaction { storage: self }))
+    }
+
+    fn flush(&self) -> Result<(), DynStorageError> {
+        Ok(())
+    }
+
+    fn storage_context(&'db self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'db> + 'db> {
+        subtree_storage_context(path, |prefix| {
+            Box::new(ReadOnlyRocksDbStorageContext::new(&self.db, prefix))
+        })
+    }
+
+    fn read_only_rocksdb(&self) -> Option<&ReadOnlyRocksDbStorage> {
+        Some(self)
+    }
+}
+
+impl DynTransaction for ReadOnlyDynTransaction<'_> {
+    fn storage_context<'a>(&'a self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'a> + 'a> {
+        DynStorage::storage_context(self.storage, path)
+    }
+
+    fn commit(self: Box<Self>) -> Result<(), DynStorageError> {
+        Ok(())
+    }
+
+    fn rollback(&self) -> Result<(), DynStorageError> {
+        Ok(())
+    }
+}
+
+impl_dyn_storage_context!(ReadOnlyRocksDbStorageContext);
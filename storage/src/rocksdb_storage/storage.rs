@@ -1,11 +1,21 @@
 //! Impementation for a storage abstraction over RocksDB.
-use std::path::Path;
+use std::{collections::BTreeMap, path::Path, sync::RwLockWriteGuard};
 
 use lazy_static::lazy_static;
-use rocksdb::{ColumnFamilyDescriptor, Error, OptimisticTransactionDB, Transaction};
+use rocksdb::{
+    backup::{BackupEngine, BackupEngineOptions, RestoreOptions},
+    checkpoint::Checkpoint,
+    BlockBasedOptions, Cache, ColumnFamilyDescriptor, DBRecoveryMode, Error,
+    OptimisticTransactionDB, OptimisticTransactionOptions, SnapshotWithThreadMode, Transaction,
+    WriteBatchWithTransaction, WriteOptions, DEFAULT_COLUMN_FAMILY_NAME,
+};
 
-use super::{PrefixedRocksDbStorageContext, PrefixedRocksDbTransactionContext};
-use crate::Storage;
+use super::{
+    storage_context::make_prefix_upper_bound,
+    write_gate::{WriteError, WriteGate, WriteSet},
+    PrefixedRocksDbStorageContext, PrefixedRocksDbTransactionContext,
+};
+use crate::{prefix::PREFIX_LENGTH, Storage};
 
 /// Name of column family used to store auxiliary data
 pub(super) const AUX_CF_NAME: &str = "aux";
@@ -13,7 +23,29 @@ pub(super) const AUX_CF_NAME: &str = "aux";
 pub(super) const ROOTS_CF_NAME: &str = "roots";
 /// Name of column family used to store metadata
 pub(super) const META_CF_NAME: &str = "meta";
+/// Name of column family used to store deduplicated values by their hashes.
+/// Its keys are not prefixed by subtrees, so it's skipped by garbage
+/// collection of unreachable prefixes.
+pub(super) const DEDUP_CF_NAME: &str = "dedup";
+/// Length of a reference count preceding a deduplicated value
+pub(super) const DEDUP_REFERENCE_COUNT_LENGTH: usize = 8;
+/// Period in microseconds the rate limiter refills its budget with
+const RATE_LIMITER_REFILL_PERIOD_US: i64 = 100_000;
+/// Rate limiter's chance to serve low priority requests before high priority
+/// ones, `1 / RATE_LIMITER_FAIRNESS`
+const RATE_LIMITER_FAIRNESS: i32 = 10;
+/// Prefix of meta column family keys of prepared transactions, followed by a
+/// big-endian id. The keys are shorter than a subtree prefix, so they are
+/// never taken for data of a subtree.
+const PREPARED_TRANSACTION_PREFIX: &[u8] = b"prepared";
+/// Meta column family key of the id the next prepared transaction gets
+const PREPARED_NEXT_ID_KEY: &[u8] = b"two_phase_next_id";
 
+// RocksDB's default comparator is used for all column families: it orders keys
+// byte-wise, which is the iteration order required by `RawIterator`. It is
+// never replaced, as a comparator implemented in Rust would add a call across
+// FFI to every key comparison, and RocksDB refuses to open a database with a
+// comparator of another name than the one it was created with.
 lazy_static! {
     static ref DEFAULT_OPTS: rocksdb::Options = {
         let mut opts = rocksdb::Options::default();
@@ -27,24 +59,607 @@ lazy_static! {
     };
 }
 
+/// Memory used by RocksDB, in bytes unless stated otherwise
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Active and unflushed immutable memtables of all column families
+    pub memtables_bytes: u64,
+    /// Block cache usage, the cache may be shared with other databases
+    pub block_cache_bytes: u64,
+    /// Part of the block cache pinned by live iterators and table readers
+    pub block_cache_pinned_bytes: u64,
+    /// Index and filter blocks held by table readers outside of block cache
+    pub table_readers_bytes: u64,
+    /// Number of unreleased snapshots, including ones held by iterators
+    pub snapshots: u64,
+}
+
+/// Adjustments of default RocksDB options
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RocksDbTuning {
+    /// Limit of flush and compaction writes in bytes per second, so background
+    /// IO doesn't starve foreground reads; not limited if `None`
+    pub rate_limit_bytes_per_sec: Option<i64>,
+    /// Whether RocksDB statistics are collected, see
+    /// [`RocksDbStorage::statistics`]
+    pub enable_statistics: bool,
+    /// How write-ahead log records are replayed on open, RocksDB default if
+    /// `None`
+    pub wal_recovery_mode: Option<WalRecoveryMode>,
+}
+
+/// How RocksDB replays its write-ahead log on open after an unclean shutdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalRecoveryMode {
+    /// Fail to open on any corruption, even of a record incompletely written
+    /// at the end of the log
+    AbsoluteConsistency,
+    /// Ignore records incompletely written at the end of the log, RocksDB
+    /// default
+    TolerateCorruptedTail,
+    /// Replay records up to the first corruption and drop everything after
+    /// it, so the database is consistent as of some point in time
+    PointInTime,
+    /// Replay all records except the corrupted ones. A record holds a whole
+    /// write batch, so batches are never applied partially, but the database
+    /// may miss a batch while having later ones, so it may not be consistent
+    /// as of any point in time
+    SkipCorrupted,
+}
+
+impl From<WalRecoveryMode> for DBRecoveryMode {
+    fn from(mode: WalRecoveryMode) -> Self {
+        match mode {
+            WalRecoveryMode::AbsoluteConsistency => DBRecoveryMode::AbsoluteConsistency,
+            WalRecoveryMode::TolerateCorruptedTail => DBRecoveryMode::TolerateCorruptedTailRecords,
+            WalRecoveryMode::PointInTime => DBRecoveryMode::PointInTime,
+            WalRecoveryMode::SkipCorrupted => DBRecoveryMode::SkipAnyCorruptedRecord,
+        }
+    }
+}
+
+/// Percentiles and totals of a RocksDB histogram
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HistogramData {
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub p100: f64,
+    pub count: u64,
+    pub sum: u64,
+}
+
+/// RocksDB statistics tickers and histograms by their names
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StorageStatistics {
+    pub tickers: BTreeMap<String, u64>,
+    pub histograms: BTreeMap<String, HistogramData>,
+}
+
+impl StorageStatistics {
+    /// Parses RocksDB statistics dump, a ticker per line is
+    /// `name COUNT : value` and a histogram per line is
+    /// `name P50 : value P95 : value ... COUNT : value SUM : value`
+    fn parse(dump: &str) -> Self {
+        let mut statistics = StorageStatistics::default();
+        for line in dump.lines() {
+            let mut tokens = line.split_whitespace();
+            let name = match tokens.next() {
+                Some(name) => name.to_owned(),
+                None => continue,
+            };
+            let mut fields = BTreeMap::new();
+            while let (Some(field), Some(":"), Some(value)) =
+                (tokens.next(), tokens.next(), tokens.next())
+            {
+                fields.insert(field, value);
+            }
+            let int_field = |field: &str| fields.get(field).and_then(|v| v.parse().ok());
+            let float_field = |field: &str| {
+                fields
+                    .get(field)
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_default()
+            };
+            if fields.len() == 1 {
+                if let Some(count) = int_field("COUNT") {
+                    statistics.tickers.insert(name, count);
+                }
+            } else if fields.contains_key("P50") {
+                statistics.histograms.insert(
+                    name,
+                    HistogramData {
+                        p50: float_field("P50"),
+                        p95: float_field("P95"),
+                        p99: float_field("P99"),
+                        p100: float_field("P100"),
+                        count: int_field("COUNT").unwrap_or_default(),
+                        sum: int_field("SUM").unwrap_or_default(),
+                    },
+                );
+            }
+        }
+        statistics
+    }
+}
+
+/// Outcome of committing a prepared transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreparedCommit {
+    /// Writes of the prepared transaction were applied
+    Committed,
+    /// There is no prepared transaction with the id
+    NotFound,
+}
+
+/// Column families of the storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnFamily {
+    /// Subtree data, stored in RocksDB default column family
+    Data,
+    Aux,
+    Roots,
+    Meta,
+    Dedup,
+}
+
+impl ColumnFamily {
+    fn name(self) -> &'static str {
+        match self {
+            ColumnFamily::Data => DEFAULT_COLUMN_FAMILY_NAME,
+            ColumnFamily::Aux => AUX_CF_NAME,
+            ColumnFamily::Roots => ROOTS_CF_NAME,
+            ColumnFamily::Meta => META_CF_NAME,
+            ColumnFamily::Dedup => DEDUP_CF_NAME,
+        }
+    }
+}
+
 /// Storage which uses RocksDB as its backend.
 pub struct RocksDbStorage {
-    db: OptimisticTransactionDB,
+    pub(super) db: OptimisticTransactionDB,
+    /// Options the database is opened with, kept to read statistics
+    opts: rocksdb::Options,
+    /// Serializes writes against prepares and commits of prepared
+    /// transactions, logs their keys and fails ones touching keys of pending
+    /// prepared transactions
+    pub(super) write_gate: WriteGate,
 }
 
 impl RocksDbStorage {
     pub fn default_rocksdb_with_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::rocksdb_with_path_and_opts(path, &DEFAULT_OPTS)
+    }
+
+    /// Opens a database like `default_rocksdb_with_path` but with a block
+    /// cache shared by all column families, which may be shared by several
+    /// databases to bound their total memory usage
+    pub fn default_rocksdb_with_path_and_cache<P: AsRef<Path>>(
+        path: P,
+        cache: &Cache,
+    ) -> Result<Self, Error> {
+        let mut block_opts = BlockBasedOptions::default();
+        block_opts.set_block_cache(cache);
+        let mut opts = DEFAULT_OPTS.clone();
+        opts.set_block_based_table_factory(&block_opts);
+        Self::rocksdb_with_path_and_opts(path, &opts)
+    }
+
+    /// Opens a database like `default_rocksdb_with_path` with default options
+    /// adjusted by `tuning`
+    pub fn default_rocksdb_with_path_and_tuning<P: AsRef<Path>>(
+        path: P,
+        tuning: &RocksDbTuning,
+    ) -> Result<Self, Error> {
+        let mut opts = DEFAULT_OPTS.clone();
+        if let Some(bytes_per_sec) = tuning.rate_limit_bytes_per_sec {
+            opts.set_ratelimiter(
+                bytes_per_sec,
+                RATE_LIMITER_REFILL_PERIOD_US,
+                RATE_LIMITER_FAIRNESS,
+            );
+        }
+        if tuning.enable_statistics {
+            opts.enable_statistics();
+        }
+        if let Some(mode) = tuning.wal_recovery_mode {
+            opts.set_wal_recovery_mode(mode.into());
+        }
+        Self::rocksdb_with_path_and_opts(path, &opts)
+    }
+
+    fn rocksdb_with_path_and_opts<P: AsRef<Path>>(
+        path: P,
+        opts: &rocksdb::Options,
+    ) -> Result<Self, Error> {
         let db = rocksdb::OptimisticTransactionDB::open_cf_descriptors(
-            &DEFAULT_OPTS,
+            opts,
             &path,
             [
-                ColumnFamilyDescriptor::new(AUX_CF_NAME, DEFAULT_OPTS.clone()),
-                ColumnFamilyDescriptor::new(ROOTS_CF_NAME, DEFAULT_OPTS.clone()),
-                ColumnFamilyDescriptor::new(META_CF_NAME, DEFAULT_OPTS.clone()),
+                ColumnFamilyDescriptor::new(DEFAULT_COLUMN_FAMILY_NAME, opts.clone()),
+                ColumnFamilyDescriptor::new(AUX_CF_NAME, opts.clone()),
+                ColumnFamilyDescriptor::new(ROOTS_CF_NAME, opts.clone()),
+                ColumnFamilyDescriptor::new(META_CF_NAME, opts.clone()),
+                ColumnFamilyDescriptor::new(DEDUP_CF_NAME, opts.clone()),
             ],
         )?;
 
-        Ok(RocksDbStorage { db })
+        let storage = RocksDbStorage {
+            db,
+            opts: opts.clone(),
+            write_gate: WriteGate::default(),
+        };
+        // Keys of transactions prepared before a restart stay reserved, so they
+        // can still be committed
+        for id in storage.prepared_transactions()? {
+            if let Some(record) = storage
+                .db
+                .get_cf(storage.cf_meta(), prepared_transaction_key(id))?
+            {
+                storage
+                    .write_gate
+                    .add_pending(id, WriteSet::of_batch(&record));
+            }
+        }
+        Ok(storage)
+    }
+
+    /// Starts a transaction which reads from a snapshot taken at its start, so
+    /// reads through it don't observe commits made after that, as opposed to
+    /// an ordinary transaction reading the latest committed data
+    pub fn start_snapshot_transaction(&self) -> Transaction<OptimisticTransactionDB> {
+        let mut opts = OptimisticTransactionOptions::default();
+        opts.set_snapshot(true);
+        self.db.transaction_opt(&WriteOptions::default(), &opts)
+    }
+
+    /// Returns the number of writes made since open. It is passed to
+    /// [`RocksDbStorage::prepare_transaction`] as read before the transaction
+    /// is started.
+    pub fn write_version(&self) -> u64 {
+        self.write_gate.version()
+    }
+
+    /// Prepares the transaction for a two-phase commit: its write batch is
+    /// durably recorded in the meta column family and the transaction is left
+    /// to be dropped. Returns the id to commit or roll back the prepared
+    /// transaction with, prepared transactions survive restarts until
+    /// either is done. Ids are never reused, even after all prepared
+    /// transactions are done.
+    ///
+    /// `base_version` is the [`RocksDbStorage::write_version`] read before
+    /// the transaction was started. If a write made since then touched a key
+    /// the transaction writes, as an optimistic commit would fail for, or a
+    /// pending prepared transaction writes one of its keys, `None` is
+    /// returned without preparing it. Writes of other keys don't conflict,
+    /// though every GroveDB write of a subtree changes the root tree, so such
+    /// writes conflict with each other.
+    ///
+    /// Until the prepared transaction is committed or rolled back, writes
+    /// touching its keys, including commits of transactions, fail with
+    /// [`WriteError::PreparedTransactionConflict`] rather than wait, so its
+    /// commit can't fail on stale writes and nothing waits for it to be
+    /// resolved. Keys of prepared transactions found on open are reserved the
+    /// same way.
+    pub fn prepare_transaction(
+        &self,
+        transaction: &Transaction<OptimisticTransactionDB>,
+        base_version: u64,
+    ) -> Result<Option<u64>, Error> {
+        let _gate = self.write_gate.exclusive();
+        let cf_meta = self.cf_meta();
+        let id = read_meta_counter(&self.db, PREPARED_NEXT_ID_KEY)?;
+        let key = prepared_transaction_key(id);
+        // The record is deleted by the same write batch which applies it
+        transaction.delete_cf(cf_meta, &key)?;
+        let writes = transaction.get_writebatch();
+        let write_set = WriteSet::of_batch(writes.data());
+        if !self.write_gate.can_prepare(base_version, &write_set) {
+            return Ok(None);
+        }
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.put_cf(cf_meta, &key, writes.data());
+        batch.put_cf(cf_meta, PREPARED_NEXT_ID_KEY, (id + 1).to_be_bytes());
+        self.db.write_opt(batch, &synced_write_options())?;
+        self.write_gate.add_pending(id, write_set);
+        Ok(Some(id))
+    }
+
+    /// Atomically applies writes of a prepared transaction and removes its
+    /// record. Writes touching its keys fail while the transaction is
+    /// pending, so the writes are applied as prepared.
+    pub fn commit_prepared(&self, id: u64) -> Result<PreparedCommit, Error> {
+        let _gate = self.write_gate.exclusive();
+        let cf_meta = self.cf_meta();
+        let record = match self.db.get_cf(cf_meta, prepared_transaction_key(id))? {
+            Some(record) => record,
+            None => return Ok(PreparedCommit::NotFound),
+        };
+        let batch = WriteBatchWithTransaction::<true>::from_data(&record);
+        self.db.write_opt(batch, &synced_write_options())?;
+        self.write_gate.resolve_pending(id);
+        self.write_gate.record(WriteSet::of_batch(&record));
+        Ok(PreparedCommit::Committed)
+    }
+
+    /// Discards a prepared transaction, does nothing if there is no prepared
+    /// transaction with the id
+    pub fn rollback_prepared(&self, id: u64) -> Result<(), Error> {
+        let _gate = self.write_gate.exclusive();
+        let cf_meta = self.cf_meta();
+        let key = prepared_transaction_key(id);
+        if self.db.get_cf(cf_meta, &key)?.is_none() {
+            return Ok(());
+        }
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.delete_cf(cf_meta, key);
+        self.db.write_opt(batch, &synced_write_options())?;
+        self.write_gate.resolve_pending(id);
+        Ok(())
+    }
+
+    /// Returns ids of prepared transactions neither committed nor rolled back
+    /// in ascending order
+    pub fn prepared_transactions(&self) -> Result<Vec<u64>, Error> {
+        let mut ids = Vec::new();
+        let mut iter = self.db.raw_iterator_cf(self.cf_meta());
+        iter.seek(PREPARED_TRANSACTION_PREFIX);
+        while let Some(key) = iter.key() {
+            if !key.starts_with(PREPARED_TRANSACTION_PREFIX) {
+                break;
+            }
+            let id_bytes = &key[PREPARED_TRANSACTION_PREFIX.len()..];
+            if let Ok(id_bytes) = id_bytes.try_into() {
+                ids.push(u64::from_be_bytes(id_bytes));
+            }
+            iter.next();
+        }
+        iter.status()?;
+        Ok(ids)
+    }
+
+    /// Returns a deduplicated value by its hash
+    pub fn get_dedup_value(
+        &self,
+        hash: &[u8],
+        transaction: Option<&Transaction<OptimisticTransactionDB>>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self
+            .get_dedup_record(hash, transaction)?
+            .map(|record| record[DEDUP_REFERENCE_COUNT_LENGTH..].to_vec()))
+    }
+
+    /// Returns the number of references to a deduplicated value, zero if
+    /// there is no such value
+    pub fn dedup_reference_count(
+        &self,
+        hash: &[u8],
+        transaction: Option<&Transaction<OptimisticTransactionDB>>,
+    ) -> Result<u64, Error> {
+        Ok(self
+            .get_dedup_record(hash, transaction)?
+            .map_or(0, |record| dedup_reference_count(&record)))
+    }
+
+    /// Adds a reference to a value stored once under its hash, the value is
+    /// stored with the first reference. Returns the new number of references.
+    pub fn add_dedup_reference(
+        &self,
+        hash: &[u8],
+        value: &[u8],
+        transaction: Option<&Transaction<OptimisticTransactionDB>>,
+    ) -> Result<u64, WriteError> {
+        let count = self.dedup_reference_count(hash, transaction)? + 1;
+        let mut record = count.to_be_bytes().to_vec();
+        record.extend_from_slice(value);
+        let cf_dedup = self.cf_dedup();
+        match transaction {
+            Some(tx) => tx.put_cf(cf_dedup, hash, record)?,
+            None => {
+                let mut batch = WriteBatchWithTransaction::<true>::default();
+                batch.put_cf(cf_dedup, hash, record);
+                self.write_batch(batch)?;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Removes a reference to a deduplicated value, the value is deleted with
+    /// the last reference. Returns the number of references left.
+    pub fn remove_dedup_reference(
+        &self,
+        hash: &[u8],
+        transaction: Option<&Transaction<OptimisticTransactionDB>>,
+    ) -> Result<u64, WriteError> {
+        let mut record = match self.get_dedup_record(hash, transaction)? {
+            Some(record) => record,
+            None => return Ok(0),
+        };
+        let count = dedup_reference_count(&record).saturating_sub(1);
+        let cf_dedup = self.cf_dedup();
+        if count != 0 {
+            record[..DEDUP_REFERENCE_COUNT_LENGTH].copy_from_slice(&count.to_be_bytes());
+        }
+        match transaction {
+            Some(tx) if count == 0 => tx.delete_cf(cf_dedup, hash)?,
+            Some(tx) => tx.put_cf(cf_dedup, hash, record)?,
+            None => {
+                let mut batch = WriteBatchWithTransaction::<true>::default();
+                if count == 0 {
+                    batch.delete_cf(cf_dedup, hash);
+                } else {
+                    batch.put_cf(cf_dedup, hash, record);
+                }
+                self.write_batch(batch)?;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Writes a batch outside of transactions unless it touches keys of a
+    /// pending prepared transaction
+    pub(super) fn write_batch(
+        &self,
+        batch: WriteBatchWithTransaction<true>,
+    ) -> Result<(), WriteError> {
+        let writes = WriteSet::of_batch(batch.data());
+        self.write_gate.write(writes, || self.db.write(batch))
+    }
+
+    fn get_dedup_record(
+        &self,
+        hash: &[u8],
+        transaction: Option<&Transaction<OptimisticTransactionDB>>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        match transaction {
+            Some(tx) => tx.get_cf(self.cf_dedup(), hash),
+            None => self.db.get_cf(self.cf_dedup(), hash),
+        }
+    }
+
+    fn cf_dedup(&self) -> &rocksdb::ColumnFamily {
+        self.db
+            .cf_handle(DEDUP_CF_NAME)
+            .expect("dedup column family must exist")
+    }
+
+    fn cf_meta(&self) -> &rocksdb::ColumnFamily {
+        self.db
+            .cf_handle(META_CF_NAME)
+            .expect("meta column family must exist")
+    }
+
+    /// Creates a checkpoint of all column families in a new database at
+    /// `path`, which must not exist. Memtables are flushed first and table
+    /// files are hard-linked rather than copied when `path` is on the same
+    /// filesystem, so it takes time proportional to the number of files
+    /// instead of the amount of data.
+    pub fn checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        Checkpoint::new(&self.db)?.create_checkpoint(path)
+    }
+
+    /// Creates a new backup in `backup_dir` after flushing memtables. Backups
+    /// are incremental: files shared with previous backups in the same
+    /// directory are not copied again. Returns the id of the new backup.
+    pub fn create_backup<P: AsRef<Path>>(&self, backup_dir: P) -> Result<u32, Error> {
+        let mut engine = BackupEngine::open(&BackupEngineOptions::default(), &backup_dir)?;
+        engine.create_new_backup_flush(&self.db, true)?;
+        Ok(engine
+            .get_backup_info()
+            .last()
+            .map(|info| info.backup_id)
+            .expect("backup was just created"))
+    }
+
+    /// Deletes all backups in `backup_dir` except `num_backups_to_keep` most
+    /// recent ones
+    pub fn purge_old_backups<P: AsRef<Path>>(
+        backup_dir: P,
+        num_backups_to_keep: usize,
+    ) -> Result<(), Error> {
+        let mut engine = BackupEngine::open(&BackupEngineOptions::default(), &backup_dir)?;
+        engine.purge_old_backups(num_backups_to_keep)
+    }
+
+    /// Returns ids of backups available in `backup_dir`, oldest first
+    pub fn backup_ids<P: AsRef<Path>>(backup_dir: P) -> Result<Vec<u32>, Error> {
+        let engine = BackupEngine::open(&BackupEngineOptions::default(), &backup_dir)?;
+        Ok(engine
+            .get_backup_info()
+            .into_iter()
+            .map(|info| info.backup_id)
+            .collect())
+    }
+
+    /// Restores a backup from `backup_dir` into `db_dir`, the latest one if
+    /// `backup_id` is `None`. The database at `db_dir` must not be open.
+    pub fn restore_backup<P: AsRef<Path>, Q: AsRef<Path>>(
+        backup_dir: P,
+        db_dir: Q,
+        backup_id: Option<u32>,
+    ) -> Result<(), Error> {
+        let mut engine = BackupEngine::open(&BackupEngineOptions::default(), &backup_dir)?;
+        let opts = RestoreOptions::default();
+        match backup_id {
+            Some(backup_id) => engine.restore_from_backup(&db_dir, &db_dir, &opts, backup_id),
+            None => engine.restore_from_latest_backup(&db_dir, &db_dir, &opts),
+        }
+    }
+
+    /// Returns statistics collected since open, `None` if statistics are not
+    /// enabled
+    pub fn statistics(&self) -> Option<StorageStatistics> {
+        self.opts
+            .get_statistics()
+            .map(|dump| StorageStatistics::parse(&dump))
+    }
+
+    /// Returns the sequence number of the latest write, it doesn't change
+    /// while there are no writes
+    pub fn latest_sequence_number(&self) -> u64 {
+        self.db.latest_sequence_number()
+    }
+
+    /// Forces memtables of one column family to be written to storage files
+    pub fn flush_cf(&self, cf: ColumnFamily) -> Result<(), Error> {
+        let cf = self
+            .db
+            .cf_handle(cf.name())
+            .expect("column family must exist");
+        self.db.flush_cf(cf)
+    }
+
+    /// Compacts the whole key space of all column families
+    pub fn compact(&self) {
+        self.db.compact_range(None::<&[u8]>, None::<&[u8]>);
+        for cf_name in [AUX_CF_NAME, ROOTS_CF_NAME, META_CF_NAME, DEDUP_CF_NAME] {
+            let cf = self
+                .db
+                .cf_handle(cf_name)
+                .expect("column family must exist");
+            self.db.compact_range_cf(cf, None::<&[u8]>, None::<&[u8]>);
+        }
+    }
+
+    /// Reports memory used by RocksDB from its properties
+    pub fn memory_usage(&self) -> Result<MemoryUsage, Error> {
+        let int_property = |name: &str| -> Result<u64, Error> {
+            Ok(self.db.property_int_value(name)?.unwrap_or(0))
+        };
+        let mut usage = MemoryUsage {
+            memtables_bytes: int_property("rocksdb.cur-size-all-mem-tables")?,
+            block_cache_bytes: int_property("rocksdb.block-cache-usage")?,
+            block_cache_pinned_bytes: int_property("rocksdb.block-cache-pinned-usage")?,
+            table_readers_bytes: int_property("rocksdb.estimate-table-readers-mem")?,
+            snapshots: int_property("rocksdb.num-snapshots")?,
+        };
+        for cf_name in [AUX_CF_NAME, ROOTS_CF_NAME, META_CF_NAME, DEDUP_CF_NAME] {
+            let cf = self
+                .db
+                .cf_handle(cf_name)
+                .expect("column family must exist");
+            usage.memtables_bytes += self
+                .db
+                .property_int_value_cf(cf, "rocksdb.cur-size-all-mem-tables")?
+                .unwrap_or(0);
+            usage.table_readers_bytes += self
+                .db
+                .property_int_value_cf(cf, "rocksdb.estimate-table-readers-mem")?
+                .unwrap_or(0);
+        }
+        Ok(usage)
+    }
+
+    /// Blocks all writes until the returned guard is dropped and takes a
+    /// snapshot of the state they are blocked at. Blocked writes wait, so they
+    /// must not be made on the thread holding the guard.
+    pub fn block_writes(&self) -> WritesBlocked {
+        let gate = self.write_gate.exclusive();
+        WritesBlocked {
+            storage: self,
+            _gate: gate,
+            snapshot: self.db.snapshot(),
+        }
     }
 
     /// A helper method to build a prefix to rocksdb keys or identify a subtree
@@ -53,26 +668,105 @@ impl RocksDbStorage {
     where
         P: IntoIterator<Item = &'a [u8]>,
     {
-        let segments_iter = path.into_iter();
-        let mut segments_count: usize = 0;
-        let mut res = Vec::new();
-        let mut lengthes = Vec::new();
+        crate::prefix::build_prefix(path)
+    }
+}
 
-        for s in segments_iter {
-            segments_count += 1;
-            res.extend_from_slice(s);
-            lengthes.extend(s.len().to_ne_bytes());
-        }
+/// Guard of writes blocked by [`RocksDbStorage::block_writes`] and the
+/// snapshot of the state they are blocked at
+pub struct WritesBlocked<'db> {
+    pub(super) storage: &'db RocksDbStorage,
+    _gate: RwLockWriteGuard<'db, ()>,
+    pub(super) snapshot: SnapshotWithThreadMode<'db, OptimisticTransactionDB>,
+}
 
-        res.extend(segments_count.to_ne_bytes());
-        res.extend(lengthes);
-        res = blake3::hash(&res).as_bytes().to_vec();
-        res
+impl WritesBlocked<'_> {
+    /// Deletes entries of all column families whose subtree prefix is not
+    /// reachable according to `is_reachable`, keys shorter than a prefix are
+    /// kept. Entries are read from the snapshot, so the reachability should be
+    /// decided by reading it too. Returns sizes of deleted keys and values in
+    /// bytes by prefix.
+    pub fn delete_unreachable_prefixes<F>(
+        &self,
+        is_reachable: F,
+    ) -> Result<BTreeMap<Vec<u8>, u64>, Error>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        let db = &self.storage.db;
+        let mut deleted = BTreeMap::new();
+        let mut writes = WriteSet::default();
+        for cf_name in [
+            DEFAULT_COLUMN_FAMILY_NAME,
+            AUX_CF_NAME,
+            ROOTS_CF_NAME,
+            META_CF_NAME,
+        ] {
+            let cf = db.cf_handle(cf_name).expect("column family must exist");
+            let mut iter = self.snapshot.raw_iterator_cf(cf);
+            iter.seek_to_first();
+            while let Some(key) = iter.key() {
+                if key.len() < PREFIX_LENGTH {
+                    iter.next();
+                    continue;
+                }
+                let prefix = key[..PREFIX_LENGTH].to_vec();
+                let upper_bound = make_prefix_upper_bound(&prefix);
+                if is_reachable(&prefix) {
+                    iter.seek(&upper_bound);
+                    continue;
+                }
+                let mut bytes = 0;
+                while let Some((key, value)) = iter.key().zip(iter.value()) {
+                    if !key.starts_with(&prefix) {
+                        break;
+                    }
+                    bytes += (key.len() + value.len()) as u64;
+                    iter.next();
+                }
+                db.delete_range_cf(cf, &prefix, &upper_bound)?;
+                writes.add_range(None, &prefix, &upper_bound);
+                *deleted.entry(prefix).or_default() += bytes;
+            }
+            iter.status()?;
+        }
+        // Transactions started before may have read the deleted entries
+        self.storage.write_gate.record(writes);
+        Ok(deleted)
     }
 }
 
+fn dedup_reference_count(record: &[u8]) -> u64 {
+    record
+        .get(..DEDUP_REFERENCE_COUNT_LENGTH)
+        .and_then(|count| count.try_into().ok())
+        .map_or(0, u64::from_be_bytes)
+}
+
+fn read_meta_counter(db: &OptimisticTransactionDB, key: &[u8]) -> Result<u64, Error> {
+    let cf_meta = db
+        .cf_handle(META_CF_NAME)
+        .expect("meta column family must exist");
+    Ok(db
+        .get_cf(cf_meta, key)?
+        .and_then(|value| value.as_slice().try_into().ok())
+        .map_or(0, u64::from_be_bytes))
+}
+
+fn prepared_transaction_key(id: u64) -> Vec<u8> {
+    let mut key = PREPARED_TRANSACTION_PREFIX.to_vec();
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+fn synced_write_options() -> WriteOptions {
+    let mut opts = WriteOptions::default();
+    opts.set_sync(true);
+    opts
+}
+
 impl<'db> Storage<'db> for RocksDbStorage {
-    type Error = Error;
+    type Error = WriteError;
     type StorageContext = PrefixedRocksDbStorageContext<'db>;
     type Transaction = Transaction<'db, OptimisticTransactionDB>;
     type TransactionalStorageContext = PrefixedRocksDbTransactionContext<'db>;
@@ -82,15 +776,16 @@ impl<'db> Storage<'db> for RocksDbStorage {
     }
 
     fn commit_transaction(&self, transaction: Self::Transaction) -> Result<(), Self::Error> {
-        transaction.commit()
+        let writes = WriteSet::of_batch(transaction.get_writebatch().data());
+        self.write_gate.write(writes, || transaction.commit())
     }
 
     fn rollback_transaction(&self, transaction: &Self::Transaction) -> Result<(), Self::Error> {
-        transaction.rollback()
+        Ok(transaction.rollback()?)
     }
 
     fn flush(&self) -> Result<(), Self::Error> {
-        self.db.flush()
+        Ok(self.db.flush()?)
     }
 
     fn get_storage_context<'p, P>(&'db self, path: P) -> Self::StorageContext
@@ -98,7 +793,7 @@ impl<'db> Storage<'db> for RocksDbStorage {
         P: IntoIterator<Item = &'p [u8]>,
     {
         let prefix = Self::build_prefix(path);
-        PrefixedRocksDbStorageContext::new(&self.db, prefix)
+        PrefixedRocksDbStorageContext::new(self, prefix)
     }
 
     fn get_transactional_storage_context<'p, P>(
@@ -1,14 +1,16 @@
 //! Implementation of prefixed storage context.
 mod batch;
 mod context_no_tx;
+mod context_read_only;
 mod context_tx;
 mod raw_iterator;
 
 pub use batch::PrefixedRocksDbBatch;
 pub use context_no_tx::PrefixedRocksDbStorageContext;
+pub use context_read_only::ReadOnlyRocksDbStorageContext;
 pub use context_tx::PrefixedRocksDbTransactionContext;
 pub use raw_iterator::PrefixedRocksDbRawIterator;
-use rocksdb::{OptimisticTransactionDB, Transaction};
+use rocksdb::{OptimisticTransactionDB, SnapshotWithThreadMode, Transaction};
 
 /// Type alias for a database
 type Db = OptimisticTransactionDB;
@@ -16,7 +18,23 @@ type Db = OptimisticTransactionDB;
 /// Type alias for a transaction
 type Tx<'db> = Transaction<'db, Db>;
 
+/// Type alias for a snapshot
+type Snapshot<'db> = SnapshotWithThreadMode<'db, Db>;
+
 pub fn make_prefixed_key<K: AsRef<[u8]>>(mut prefix: Vec<u8>, key: K) -> Vec<u8> {
     prefix.extend_from_slice(key.as_ref());
     prefix
 }
+
+/// Returns the smallest key greater than every key starting with `prefix`.
+/// Prefixes are hashes, so it's expected that not all bytes are `0xff`.
+pub fn make_prefix_upper_bound(prefix: &[u8]) -> Vec<u8> {
+    let mut bound = prefix.to_vec();
+    while let Some(last) = bound.pop() {
+        if last != u8::MAX {
+            bound.push(last + 1);
+            return bound;
+        }
+    }
+    panic!("prefix must not consist of 0xff bytes only");
+}
@@ -3,8 +3,8 @@ use std::convert::Infallible;
 
 use rocksdb::{ColumnFamily, WriteBatchWithTransaction};
 
-use super::{make_prefixed_key, PrefixedRocksDbTransactionContext};
-use crate::{Batch, StorageContext};
+use super::{make_prefix_upper_bound, make_prefixed_key, PrefixedRocksDbTransactionContext};
+use crate::{rocksdb_storage::perf::record_write, Batch, StorageContext};
 
 /// Wrapper to RocksDB batch
 pub struct PrefixedRocksDbBatch<'db, B> {
@@ -19,6 +19,7 @@ impl<'db> Batch for PrefixedRocksDbBatch<'db, WriteBatchWithTransaction<true>> {
     type Error = Infallible;
 
     fn put<K: AsRef<[u8]>>(&mut self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        record_write(key.as_ref().len() + value.len());
         self.batch
             .put(make_prefixed_key(self.prefix.clone(), key), value);
         Ok(())
@@ -43,11 +44,26 @@ impl<'db> Batch for PrefixedRocksDbBatch<'db, WriteBatchWithTransaction<true>> {
     }
 
     fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
+        record_write(key.as_ref().len());
         self.batch
             .delete(make_prefixed_key(self.prefix.clone(), key));
         Ok(())
     }
 
+    fn delete_range<K: AsRef<[u8]>>(&mut self, from: K, to: K) -> Result<(), Self::Error> {
+        self.batch.delete_range(
+            make_prefixed_key(self.prefix.clone(), from),
+            make_prefixed_key(self.prefix.clone(), to),
+        );
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), Self::Error> {
+        self.batch
+            .delete_range(&self.prefix, make_prefix_upper_bound(&self.prefix));
+        Ok(())
+    }
+
     fn delete_aux<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
         self.batch
             .delete_cf(self.cf_aux, make_prefixed_key(self.prefix.clone(), key));
@@ -82,6 +98,14 @@ impl<'db, 'ctx> Batch for &'ctx PrefixedRocksDbTransactionContext<'db> {
         StorageContext::delete(*self, key)
     }
 
+    fn delete_range<K: AsRef<[u8]>>(&mut self, from: K, to: K) -> Result<(), Self::Error> {
+        StorageContext::delete_range(*self, from, to)
+    }
+
+    fn clear(&mut self) -> Result<(), Self::Error> {
+        StorageContext::clear(*self)
+    }
+
     fn delete_aux<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
         StorageContext::delete_aux(*self, key)
     }
@@ -1,26 +1,51 @@
-use rocksdb::{ColumnFamily, DBRawIteratorWithThreadMode, Error, WriteBatchWithTransaction};
+use rocksdb::{
+    ColumnFamily, DBRawIteratorWithThreadMode, ReadOptions, WriteBatchWithTransaction,
+    DEFAULT_COLUMN_FAMILY_NAME,
+};
 
-use super::{make_prefixed_key, Db, PrefixedRocksDbBatch, PrefixedRocksDbRawIterator};
+use super::{
+    make_prefix_upper_bound, make_prefixed_key, Db, PrefixedRocksDbBatch,
+    PrefixedRocksDbRawIterator,
+};
 use crate::{
-    rocksdb_storage::storage::{AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
+    rocksdb_storage::{
+        perf::{record_get, record_write},
+        storage::{AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
+        write_gate::{WriteError, WriteGate, WriteSet, DEFAULT_COLUMN_FAMILY_ID},
+        RocksDbStorage,
+    },
     StorageContext,
 };
 
 /// Storage context with a prefix applied to be used in a subtree to be used
-/// outside of transaction.
+/// outside of transaction. Writes are made under the storage write gate, so
+/// they are serialized against two-phase commit and fail if they touch keys of
+/// a pending prepared transaction.
 pub struct PrefixedRocksDbStorageContext<'db> {
     storage: &'db Db,
+    write_gate: &'db WriteGate,
     prefix: Vec<u8>,
 }
 
 impl<'db> PrefixedRocksDbStorageContext<'db> {
     /// Create a new prefixed storage context instance
-    pub fn new(storage: &'db Db, prefix: Vec<u8>) -> Self {
-        PrefixedRocksDbStorageContext { storage, prefix }
+    pub fn new(storage: &'db RocksDbStorage, prefix: Vec<u8>) -> Self {
+        PrefixedRocksDbStorageContext {
+            storage: &storage.db,
+            write_gate: &storage.write_gate,
+            prefix,
+        }
     }
 }
 
 impl<'db> PrefixedRocksDbStorageContext<'db> {
+    /// Get data column family
+    fn cf_default(&self) -> &'db ColumnFamily {
+        self.storage
+            .cf_handle(DEFAULT_COLUMN_FAMILY_NAME)
+            .expect("default column family must exist")
+    }
+
     /// Get auxiliary data column family
     fn cf_aux(&self) -> &'db ColumnFamily {
         self.storage
@@ -41,80 +66,144 @@ impl<'db> PrefixedRocksDbStorageContext<'db> {
             .cf_handle(META_CF_NAME)
             .expect("meta column family must exist")
     }
+
+    /// Writes a batch unless it touches keys of a pending prepared
+    /// transaction
+    fn write_batch(&self, batch: WriteBatchWithTransaction<true>) -> Result<(), WriteError> {
+        let writes = WriteSet::of_batch(batch.data());
+        self.write_gate.write(writes, || self.storage.write(batch))
+    }
+
+    /// Deletes a range of data keys unless it overlaps keys of a pending
+    /// prepared transaction. Write batches of transactions can't hold range
+    /// deletions, so it's written directly.
+    fn delete_default_range(&self, from: &[u8], to: &[u8]) -> Result<(), WriteError> {
+        let mut writes = WriteSet::default();
+        writes.add_range(Some(DEFAULT_COLUMN_FAMILY_ID), from, to);
+        self.write_gate.write(writes, || {
+            self.storage.delete_range_cf(self.cf_default(), from, to)
+        })
+    }
 }
 
 impl<'db, 'ctx> StorageContext<'db, 'ctx> for PrefixedRocksDbStorageContext<'db> {
     type Batch = PrefixedRocksDbBatch<'db, WriteBatchWithTransaction<true>>;
-    type Error = Error;
+    type Error = WriteError;
     type RawIterator = PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<'db, Db>>;
 
     fn put<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
-        self.storage
-            .put(make_prefixed_key(self.prefix.clone(), key), value)
+        record_write(key.as_ref().len() + value.len());
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.put(make_prefixed_key(self.prefix.clone(), key), value);
+        self.write_batch(batch)
     }
 
     fn put_aux<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
-        self.storage.put_cf(
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.put_cf(
             self.cf_aux(),
             make_prefixed_key(self.prefix.clone(), key),
             value,
-        )
+        );
+        self.write_batch(batch)
     }
 
     fn put_root<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
-        self.storage.put_cf(
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.put_cf(
             self.cf_roots(),
             make_prefixed_key(self.prefix.clone(), key),
             value,
-        )
+        );
+        self.write_batch(batch)
     }
 
     fn put_meta<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
-        self.storage.put_cf(
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.put_cf(
             self.cf_meta(),
             make_prefixed_key(self.prefix.clone(), key),
             value,
-        )
+        );
+        self.write_batch(batch)
     }
 
     fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
-        self.storage
-            .delete(make_prefixed_key(self.prefix.clone(), key))
+        record_write(key.as_ref().len());
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.delete(make_prefixed_key(self.prefix.clone(), key));
+        self.write_batch(batch)
+    }
+
+    fn delete_range<K: AsRef<[u8]>>(&self, from: K, to: K) -> Result<(), Self::Error> {
+        let from = make_prefixed_key(self.prefix.clone(), from);
+        let to = make_prefixed_key(self.prefix.clone(), to);
+        self.delete_default_range(&from, &to)
+    }
+
+    fn clear(&self) -> Result<(), Self::Error> {
+        self.delete_default_range(&self.prefix, &make_prefix_upper_bound(&self.prefix))
     }
 
     fn delete_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
-        self.storage
-            .delete_cf(self.cf_aux(), make_prefixed_key(self.prefix.clone(), key))
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.delete_cf(self.cf_aux(), make_prefixed_key(self.prefix.clone(), key));
+        self.write_batch(batch)
     }
 
     fn delete_root<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
-        self.storage
-            .delete_cf(self.cf_roots(), make_prefixed_key(self.prefix.clone(), key))
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.delete_cf(self.cf_roots(), make_prefixed_key(self.prefix.clone(), key));
+        self.write_batch(batch)
     }
 
     fn delete_meta<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
-        self.storage
-            .delete_cf(self.cf_meta(), make_prefixed_key(self.prefix.clone(), key))
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.delete_cf(self.cf_meta(), make_prefixed_key(self.prefix.clone(), key));
+        self.write_batch(batch)
     }
 
     fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
-        self.storage
-            .get(make_prefixed_key(self.prefix.clone(), key))
+        let key_len = key.as_ref().len();
+        let value = self
+            .storage
+            .get(make_prefixed_key(self.prefix.clone(), key))?;
+        record_get(key_len + value.as_ref().map_or(0, Vec::len));
+        Ok(value)
+    }
+
+    fn get_many<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<Vec<Option<Vec<u8>>>, Self::Error> {
+        let cf = self.cf_default();
+        let values = self
+            .storage
+            .multi_get_cf(
+                keys.iter()
+                    .map(|key| (cf, make_prefixed_key(self.prefix.clone(), key))),
+            )
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        for (key, value) in keys.iter().zip(&values) {
+            record_get(key.as_ref().len() + value.as_ref().map_or(0, Vec::len));
+        }
+        Ok(values)
     }
 
     fn get_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
-        self.storage
-            .get_cf(self.cf_aux(), make_prefixed_key(self.prefix.clone(), key))
+        Ok(self
+            .storage
+            .get_cf(self.cf_aux(), make_prefixed_key(self.prefix.clone(), key))?)
     }
 
     fn get_root<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
-        self.storage
-            .get_cf(self.cf_roots(), make_prefixed_key(self.prefix.clone(), key))
+        Ok(self
+            .storage
+            .get_cf(self.cf_roots(), make_prefixed_key(self.prefix.clone(), key))?)
     }
 
     fn get_meta<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
-        self.storage
-            .get_cf(self.cf_meta(), make_prefixed_key(self.prefix.clone(), key))
+        Ok(self
+            .storage
+            .get_cf(self.cf_meta(), make_prefixed_key(self.prefix.clone(), key))?)
     }
 
     fn new_batch(&self) -> Self::Batch {
@@ -127,13 +216,53 @@ impl<'db, 'ctx> StorageContext<'db, 'ctx> for PrefixedRocksDbStorageContext<'db>
     }
 
     fn commit_batch(&self, batch: Self::Batch) -> Result<(), Self::Error> {
-        self.storage.write(batch.batch)
+        self.write_batch(batch.batch)
     }
 
     fn raw_iter(&self) -> Self::RawIterator {
         PrefixedRocksDbRawIterator {
             prefix: self.prefix.clone(),
             raw_iterator: self.storage.raw_iterator(),
+            keys_only: false,
+        }
+    }
+
+    fn raw_iter_opt(&self, readahead_bytes: usize, fill_cache: bool) -> Self::RawIterator {
+        let mut opts = ReadOptions::default();
+        if readahead_bytes > 0 {
+            opts.set_readahead_size(readahead_bytes);
+        }
+        opts.fill_cache(fill_cache);
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.storage.raw_iterator_opt(opts),
+            keys_only: false,
+        }
+    }
+
+    fn raw_iter_keys_only(&self) -> Self::RawIterator {
+        let mut opts = ReadOptions::default();
+        opts.fill_cache(false);
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.storage.raw_iterator_opt(opts),
+            keys_only: true,
+        }
+    }
+
+    fn raw_iter_aux(&self) -> Self::RawIterator {
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.storage.raw_iterator_cf(self.cf_aux()),
+            keys_only: false,
+        }
+    }
+
+    fn raw_iter_roots(&self) -> Self::RawIterator {
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.storage.raw_iterator_cf(self.cf_roots()),
+            keys_only: false,
         }
     }
 }
@@ -0,0 +1,207 @@
+//! Prefixed storage context over a database opened read-only.
+use rocksdb::{
+    ColumnFamily, DBRawIteratorWithThreadMode, ReadOptions, DB, DEFAULT_COLUMN_FAMILY_NAME,
+};
+
+use super::{make_prefixed_key, PrefixedRocksDbRawIterator};
+use crate::{
+    dyn_storage::DynBatch,
+    rocksdb_storage::{
+        perf::record_get,
+        storage::{AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
+        ReadOnlyError,
+    },
+    StorageContext,
+};
+
+/// Storage context with a prefix applied to be used in a subtree of a
+/// database opened read-only, all writes fail with [`ReadOnlyError::Write`].
+pub struct ReadOnlyRocksDbStorageContext<'db> {
+    storage: &'db DB,
+    prefix: Vec<u8>,
+}
+
+impl<'db> ReadOnlyRocksDbStorageContext<'db> {
+    /// Create a new prefixed storage context instance
+    pub fn new(storage: &'db DB, prefix: Vec<u8>) -> Self {
+        ReadOnlyRocksDbStorageContext { storage, prefix }
+    }
+}
+
+impl<'db> ReadOnlyRocksDbStorageContext<'db> {
+    /// Get data column family
+    fn cf_default(&self) -> &'db ColumnFamily {
+        self.storage
+            .cf_handle(DEFAULT_COLUMN_FAMILY_NAME)
+            .expect("default column family must exist")
+    }
+
+    /// Get auxiliary data column family
+    fn cf_aux(&self) -> &'db ColumnFamily {
+        self.storage
+            .cf_handle(AUX_CF_NAME)
+            .expect("aux column family must exist")
+    }
+
+    /// Get trees roots data column family
+    fn cf_roots(&self) -> &'db ColumnFamily {
+        self.storage
+            .cf_handle(ROOTS_CF_NAME)
+            .expect("roots column family must exist")
+    }
+
+    /// Get metadata column family
+    fn cf_meta(&self) -> &'db ColumnFamily {
+        self.storage
+            .cf_handle(META_CF_NAME)
+            .expect("meta column family must exist")
+    }
+}
+
+impl<'db, 'ctx> StorageContext<'db, 'ctx> for ReadOnlyRocksDbStorageContext<'db> {
+    type Batch = DynBatch;
+    type Error = ReadOnlyError;
+    type RawIterator = PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<'db, DB>>;
+
+    fn put<K: AsRef<[u8]>>(&self, _key: K, _value: &[u8]) -> Result<(), Self::Error> {
+        Err(ReadOnlyError::Write)
+    }
+
+    fn put_aux<K: AsRef<[u8]>>(&self, _key: K, _value: &[u8]) -> Result<(), Self::Error> {
+        Err(ReadOnlyError::Write)
+    }
+
+    fn put_root<K: AsRef<[u8]>>(&self, _key: K, _value: &[u8]) -> Result<(), Self::Error> {
+        Err(ReadOnlyError::Write)
+    }
+
+    fn put_meta<K: AsRef<[u8]>>(&self, _key: K, _value: &[u8]) -> Result<(), Self::Error> {
+        Err(ReadOnlyError::Write)
+    }
+
+    fn delete<K: AsRef<[u8]>>(&self, _key: K) -> Result<(), Self::Error> {
+        Err(ReadOnlyError::Write)
+    }
+
+    fn delete_range<K: AsRef<[u8]>>(&self, _from: K, _to: K) -> Result<(), Self::Error> {
+        Err(ReadOnlyError::Write)
+    }
+
+    fn clear(&self) -> Result<(), Self::Error> {
+        Err(ReadOnlyError::Write)
+    }
+
+    fn delete_aux<K: AsRef<[u8]>>(&self, _key: K) -> Result<(), Self::Error> {
+        Err(ReadOnlyError::Write)
+    }
+
+    fn delete_root<K: AsRef<[u8]>>(&self, _key: K) -> Result<(), Self::Error> {
+        Err(ReadOnlyError::Write)
+    }
+
+    fn delete_meta<K: AsRef<[u8]>>(&self, _key: K) -> Result<(), Self::Error> {
+        Err(ReadOnlyError::Write)
+    }
+
+    fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        let key_len = key.as_ref().len();
+        let value = self
+            .storage
+            .get(make_prefixed_key(self.prefix.clone(), key))?;
+        record_get(key_len + value.as_ref().map_or(0, Vec::len));
+        Ok(value)
+    }
+
+    fn get_many<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<Vec<Option<Vec<u8>>>, Self::Error> {
+        let cf = self.cf_default();
+        let values = self
+            .storage
+            .multi_get_cf(
+                keys.iter()
+                    .map(|key| (cf, make_prefixed_key(self.prefix.clone(), key))),
+            )
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        for (key, value) in keys.iter().zip(&values) {
+            record_get(key.as_ref().len() + value.as_ref().map_or(0, Vec::len));
+        }
+        Ok(values)
+    }
+
+    fn get_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self
+            .storage
+            .get_cf(self.cf_aux(), make_prefixed_key(self.prefix.clone(), key))?)
+    }
+
+    fn get_root<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self
+            .storage
+            .get_cf(self.cf_roots(), make_prefixed_key(self.prefix.clone(), key))?)
+    }
+
+    fn get_meta<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self
+            .storage
+            .get_cf(self.cf_meta(), make_prefixed_key(self.prefix.clone(), key))?)
+    }
+
+    fn new_batch(&self) -> Self::Batch {
+        DynBatch::default()
+    }
+
+    fn commit_batch(&self, batch: Self::Batch) -> Result<(), Self::Error> {
+        if batch.ops().is_empty() {
+            Ok(())
+        } else {
+            Err(ReadOnlyError::Write)
+        }
+    }
+
+    fn raw_iter(&self) -> Self::RawIterator {
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.storage.raw_iterator(),
+            keys_only: false,
+        }
+    }
+
+    fn raw_iter_opt(&self, readahead_bytes: usize, fill_cache: bool) -> Self::RawIterator {
+        let mut opts = ReadOptions::default();
+        if readahead_bytes > 0 {
+            opts.set_readahead_size(readahead_bytes);
+        }
+        opts.fill_cache(fill_cache);
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.storage.raw_iterator_opt(opts),
+            keys_only: false,
+        }
+    }
+
+    fn raw_iter_keys_only(&self) -> Self::RawIterator {
+        let mut opts = ReadOptions::default();
+        opts.fill_cache(false);
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.storage.raw_iterator_opt(opts),
+            keys_only: true,
+        }
+    }
+
+    fn raw_iter_aux(&self) -> Self::RawIterator {
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.storage.raw_iterator_cf(self.cf_aux()),
+            keys_only: false,
+        }
+    }
+
+    fn raw_iter_roots(&self) -> Self::RawIterator {
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.storage.raw_iterator_cf(self.cf_roots()),
+            keys_only: false,
+        }
+    }
+}
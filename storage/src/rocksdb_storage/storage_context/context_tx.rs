@@ -1,10 +1,15 @@
 //! Storage context implementation with a transaction.
-use rocksdb::{ColumnFamily, DBRawIteratorWithThreadMode, Error};
+use rocksdb::{
+    ColumnFamily, DBRawIteratorWithThreadMode, Error, ReadOptions, DEFAULT_COLUMN_FAMILY_NAME,
+};
 
-use super::{make_prefixed_key, Db, PrefixedRocksDbRawIterator, Tx};
+use super::{make_prefixed_key, Db, PrefixedRocksDbRawIterator, Snapshot, Tx};
 use crate::{
-    rocksdb_storage::storage::{AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
-    StorageContext,
+    rocksdb_storage::{
+        perf::{record_get, record_write},
+        storage::{AUX_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
+    },
+    RawIterator, StorageContext,
 };
 
 /// Storage context with a prefix applied to be used in a subtree to be used in
@@ -13,6 +18,8 @@ pub struct PrefixedRocksDbTransactionContext<'db> {
     storage: &'db Db,
     transaction: &'db Tx<'db>,
     prefix: Vec<u8>,
+    /// Snapshot to read from instead of the transaction snapshot
+    snapshot: Option<&'db Snapshot<'db>>,
 }
 
 impl<'db> PrefixedRocksDbTransactionContext<'db> {
@@ -22,11 +29,35 @@ impl<'db> PrefixedRocksDbTransactionContext<'db> {
             storage,
             transaction,
             prefix,
+            snapshot: None,
+        }
+    }
+
+    /// Create a new prefixed transaction context instance reading from a
+    /// snapshot, which may be shared by transactions on several threads
+    pub fn new_at_snapshot(
+        storage: &'db Db,
+        transaction: &'db Tx<'db>,
+        snapshot: &'db Snapshot<'db>,
+        prefix: Vec<u8>,
+    ) -> Self {
+        PrefixedRocksDbTransactionContext {
+            storage,
+            transaction,
+            prefix,
+            snapshot: Some(snapshot),
         }
     }
 }
 
 impl<'db> PrefixedRocksDbTransactionContext<'db> {
+    /// Get data column family
+    fn cf_default(&self) -> &'db ColumnFamily {
+        self.storage
+            .cf_handle(DEFAULT_COLUMN_FAMILY_NAME)
+            .expect("default column family must exist")
+    }
+
     /// Get auxiliary data column family
     fn cf_aux(&self) -> &'db ColumnFamily {
         self.storage
@@ -47,6 +78,17 @@ impl<'db> PrefixedRocksDbTransactionContext<'db> {
             .cf_handle(META_CF_NAME)
             .expect("meta column family must exist")
     }
+
+    /// Read options to read from the context snapshot or the transaction
+    /// snapshot, if the transaction was started with one
+    fn read_options(&self) -> ReadOptions {
+        let mut opts = ReadOptions::default();
+        match self.snapshot {
+            Some(snapshot) => opts.set_snapshot(snapshot),
+            None => opts.set_snapshot(&self.transaction.snapshot()),
+        }
+        opts
+    }
 }
 
 impl<'db, 'ctx> StorageContext<'db, 'ctx> for PrefixedRocksDbTransactionContext<'db>
@@ -58,6 +100,7 @@ where
     type RawIterator = PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<'db, Tx<'db>>>;
 
     fn put<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        record_write(key.as_ref().len() + value.len());
         self.transaction
             .put(make_prefixed_key(self.prefix.clone(), key), value)
     }
@@ -87,10 +130,38 @@ where
     }
 
     fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
+        record_write(key.as_ref().len());
         self.transaction
             .delete(make_prefixed_key(self.prefix.clone(), key))
     }
 
+    /// Transactions don't support range deletions, so keys are deleted one
+    /// by one
+    fn delete_range<K: AsRef<[u8]>>(&self, from: K, to: K) -> Result<(), Self::Error> {
+        let mut iter = self.raw_iter();
+        iter.seek(from);
+        while let Some(key) = iter.key() {
+            if key >= to.as_ref() {
+                break;
+            }
+            self.delete(key)?;
+            iter.next();
+        }
+        Ok(())
+    }
+
+    /// Transactions don't support range deletions, so keys are deleted one
+    /// by one
+    fn clear(&self) -> Result<(), Self::Error> {
+        let mut iter = self.raw_iter();
+        iter.seek_to_first();
+        while let Some(key) = iter.key() {
+            self.delete(key)?;
+            iter.next();
+        }
+        Ok(())
+    }
+
     fn delete_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
         self.transaction
             .delete_cf(self.cf_aux(), make_prefixed_key(self.prefix.clone(), key))
@@ -107,23 +178,54 @@ where
     }
 
     fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
-        self.transaction
-            .get(make_prefixed_key(self.prefix.clone(), key))
+        let key_len = key.as_ref().len();
+        let value = self.transaction.get_opt(
+            make_prefixed_key(self.prefix.clone(), key),
+            &self.read_options(),
+        )?;
+        record_get(key_len + value.as_ref().map_or(0, Vec::len));
+        Ok(value)
+    }
+
+    fn get_many<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<Vec<Option<Vec<u8>>>, Self::Error> {
+        let cf = self.cf_default();
+        let values = self
+            .transaction
+            .multi_get_cf_opt(
+                keys.iter()
+                    .map(|key| (cf, make_prefixed_key(self.prefix.clone(), key))),
+                &self.read_options(),
+            )
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        for (key, value) in keys.iter().zip(&values) {
+            record_get(key.as_ref().len() + value.as_ref().map_or(0, Vec::len));
+        }
+        Ok(values)
     }
 
     fn get_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
-        self.transaction
-            .get_cf(self.cf_aux(), make_prefixed_key(self.prefix.clone(), key))
+        self.transaction.get_cf_opt(
+            self.cf_aux(),
+            make_prefixed_key(self.prefix.clone(), key),
+            &self.read_options(),
+        )
     }
 
     fn get_root<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
-        self.transaction
-            .get_cf(self.cf_roots(), make_prefixed_key(self.prefix.clone(), key))
+        self.transaction.get_cf_opt(
+            self.cf_roots(),
+            make_prefixed_key(self.prefix.clone(), key),
+            &self.read_options(),
+        )
     }
 
     fn get_meta<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
-        self.transaction
-            .get_cf(self.cf_meta(), make_prefixed_key(self.prefix.clone(), key))
+        self.transaction.get_cf_opt(
+            self.cf_meta(),
+            make_prefixed_key(self.prefix.clone(), key),
+            &self.read_options(),
+        )
     }
 
     fn new_batch(&'ctx self) -> Self::Batch {
@@ -137,7 +239,51 @@ where
     fn raw_iter(&self) -> Self::RawIterator {
         PrefixedRocksDbRawIterator {
             prefix: self.prefix.clone(),
-            raw_iterator: self.transaction.raw_iterator(),
+            raw_iterator: self.transaction.raw_iterator_opt(self.read_options()),
+            keys_only: false,
+        }
+    }
+
+    fn raw_iter_opt(&self, readahead_bytes: usize, fill_cache: bool) -> Self::RawIterator {
+        let mut opts = self.read_options();
+        if readahead_bytes > 0 {
+            opts.set_readahead_size(readahead_bytes);
+        }
+        opts.fill_cache(fill_cache);
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.transaction.raw_iterator_opt(opts),
+            keys_only: false,
+        }
+    }
+
+    fn raw_iter_keys_only(&self) -> Self::RawIterator {
+        let mut opts = self.read_options();
+        opts.fill_cache(false);
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self.transaction.raw_iterator_opt(opts),
+            keys_only: true,
+        }
+    }
+
+    fn raw_iter_aux(&self) -> Self::RawIterator {
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self
+                .transaction
+                .raw_iterator_cf_opt(self.cf_aux(), self.read_options()),
+            keys_only: false,
+        }
+    }
+
+    fn raw_iter_roots(&self) -> Self::RawIterator {
+        PrefixedRocksDbRawIterator {
+            prefix: self.prefix.clone(),
+            raw_iterator: self
+                .transaction
+                .raw_iterator_cf_opt(self.cf_roots(), self.read_options()),
+            keys_only: false,
         }
     }
 }
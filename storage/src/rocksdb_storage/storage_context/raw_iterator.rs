@@ -1,19 +1,20 @@
 //! Prefixed storage raw iterator implementation for RocksDB backend.
-use rocksdb::DBRawIteratorWithThreadMode;
+use rocksdb::{DBAccess, DBRawIteratorWithThreadMode};
 
 use super::make_prefixed_key;
-use crate::{
-    rocksdb_storage::storage_context::{Db, Tx},
-    RawIterator,
-};
+use crate::{dyn_storage::DynStorageError, RawIterator};
 
 /// Raw iterator over prefixed storage.
 pub struct PrefixedRocksDbRawIterator<I> {
     pub(super) prefix: Vec<u8>,
     pub(super) raw_iterator: I,
+    /// Values are not returned if set
+    pub(super) keys_only: bool,
 }
 
-impl<'a> RawIterator for PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<'a, Db>> {
+impl<'a, D: DBAccess> RawIterator
+    for PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<'a, D>>
+{
     fn seek_to_first(&mut self) {
         self.raw_iterator.seek(&self.prefix)
     }
@@ -49,7 +50,7 @@ impl<'a> RawIterator for PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<
     }
 
     fn value(&self) -> Option<&[u8]> {
-        if self.valid() {
+        if self.valid() && !self.keys_only {
             self.raw_iterator.value()
         } else {
             None
@@ -72,65 +73,8 @@ impl<'a> RawIterator for PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<
             .map(|k| k.starts_with(&self.prefix))
             .unwrap_or(false)
     }
-}
-
-impl<'a> RawIterator for PrefixedRocksDbRawIterator<DBRawIteratorWithThreadMode<'a, Tx<'a>>> {
-    fn seek_to_first(&mut self) {
-        self.raw_iterator.seek(&self.prefix)
-    }
-
-    fn seek_to_last(&mut self) {
-        let mut prefix_vec = self.prefix.to_vec();
-        for i in (0..prefix_vec.len()).rev() {
-            prefix_vec[i] += 1;
-            if prefix_vec[i] != 0 {
-                // if it is == 0 then we need to go to next bit
-                break;
-            }
-        }
-        self.raw_iterator.seek_for_prev(prefix_vec)
-    }
-
-    fn seek<K: AsRef<[u8]>>(&mut self, key: K) {
-        self.raw_iterator
-            .seek(make_prefixed_key(self.prefix.to_vec(), key))
-    }
 
-    fn seek_for_prev<K: AsRef<[u8]>>(&mut self, key: K) {
-        self.raw_iterator
-            .seek_for_prev(make_prefixed_key(self.prefix.to_vec(), key))
-    }
-
-    fn next(&mut self) {
-        self.raw_iterator.next()
-    }
-
-    fn prev(&mut self) {
-        self.raw_iterator.prev()
-    }
-
-    fn value(&self) -> Option<&[u8]> {
-        if self.valid() {
-            self.raw_iterator.value()
-        } else {
-            None
-        }
-    }
-
-    fn key(&self) -> Option<&[u8]> {
-        if self.valid() {
-            self.raw_iterator
-                .key()
-                .map(|k| k.split_at(self.prefix.len()).1)
-        } else {
-            None
-        }
-    }
-
-    fn valid(&self) -> bool {
-        self.raw_iterator
-            .key()
-            .map(|k| k.starts_with(&self.prefix))
-            .unwrap_or(false)
+    fn status(&self) -> Result<(), DynStorageError> {
+        self.raw_iterator.status().map_err(DynStorageError::new)
     }
 }
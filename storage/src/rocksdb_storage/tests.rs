@@ -6,7 +6,27 @@ fn to_path(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
 
 mod no_transaction {
     use super::*;
-    use crate::{Batch, RawIterator, Storage, StorageContext};
+    use crate::{rocksdb_storage::ColumnFamily, Batch, RawIterator, Storage, StorageContext};
+
+    #[test]
+    fn test_perf_scope_nesting() {
+        use crate::rocksdb_storage::PerfScope;
+
+        let storage = TempStorage::new();
+        let context = storage.get_storage_context(to_path(b"ayya"));
+        context.put(b"key1", b"value1").expect("cannot insert data");
+
+        let outer = PerfScope::start();
+        let inner = PerfScope::start();
+        let mut iter = context.raw_iter();
+        iter.seek_to_first();
+        assert!(iter.valid());
+        drop(iter);
+        let inner = inner.finish();
+        let outer = outer.finish();
+        assert!(outer.seek_count >= inner.seek_count);
+        assert!(outer.block_read_count >= inner.block_read_count);
+    }
 
     #[test]
     fn test_aux_cf_methods() {
@@ -268,6 +288,56 @@ mod no_transaction {
             .is_none());
     }
 
+    #[test]
+    fn test_delete_range_and_clear() {
+        let storage = TempStorage::new();
+        let context_ayya = storage.get_storage_context(to_path(b"ayya"));
+        let context_ayyb = storage.get_storage_context(to_path(b"ayyb"));
+
+        for key in [b"key1", b"key2", b"key3", b"key4"] {
+            context_ayya
+                .put(key, b"ayyavalue")
+                .expect("cannot insert into storage");
+            context_ayyb
+                .put(key, b"ayybvalue")
+                .expect("cannot insert into storage");
+        }
+
+        context_ayya
+            .delete_range(b"key2", b"key4")
+            .expect("cannot delete range");
+        assert!(context_ayya
+            .get(b"key1")
+            .expect("cannot get from storage")
+            .is_some());
+        assert!(context_ayya
+            .get(b"key2")
+            .expect("cannot get from storage")
+            .is_none());
+        assert!(context_ayya
+            .get(b"key3")
+            .expect("cannot get from storage")
+            .is_none());
+        assert!(context_ayya
+            .get(b"key4")
+            .expect("cannot get from storage")
+            .is_some());
+
+        let mut batch = context_ayya.new_batch();
+        batch.clear().expect("infallible");
+        context_ayya
+            .commit_batch(batch)
+            .expect("cannot commit a batch");
+        let mut iter = context_ayya.raw_iter();
+        iter.seek_to_first();
+        assert!(!iter.valid());
+
+        context_ayyb.clear().expect("cannot clear storage");
+        let mut iter = context_ayyb.raw_iter();
+        iter.seek_to_first();
+        assert!(!iter.valid());
+    }
+
     #[test]
     fn test_raw_iterator() {
         let storage = TempStorage::new();
@@ -343,11 +413,145 @@ mod no_transaction {
         iter.next();
         assert!(!iter.valid());
     }
+
+    #[test]
+    fn test_raw_iterator_keys_only() {
+        let storage = TempStorage::new();
+        let context = storage.get_storage_context(to_path(b"someprefix"));
+
+        context
+            .put(b"key1", b"value1")
+            .expect("cannot insert into storage");
+        context
+            .put(b"key2", b"value2")
+            .expect("cannot insert into storage");
+
+        let mut iter = context.raw_iter_keys_only();
+        iter.seek_to_first();
+        assert_eq!(iter.key(), Some(b"key1".as_ref()));
+        assert!(iter.value().is_none());
+        iter.next();
+        assert_eq!(iter.key(), Some(b"key2".as_ref()));
+        iter.next();
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_raw_iterator_bytewise_order() {
+        let storage = TempStorage::new();
+        let context = storage.get_storage_context(to_path(b"someprefix"));
+        let keys: [&[u8]; 6] = [b"\xff", b"a", b"\x00", b"ab", b"\x00\x00", b"B"];
+        for key in keys {
+            context
+                .put(key, b"value")
+                .expect("cannot insert into storage");
+            context
+                .put_aux(key, b"value")
+                .expect("cannot insert into aux cf");
+            context
+                .put_root(key, b"value")
+                .expect("cannot insert into roots cf");
+        }
+
+        // All column families keep RocksDB's default byte-wise comparator
+        let mut expected = keys.to_vec();
+        expected.sort();
+        for mut iter in [
+            context.raw_iter(),
+            context.raw_iter_aux(),
+            context.raw_iter_roots(),
+        ] {
+            iter.seek_to_first();
+            for key in &expected {
+                assert_eq!(iter.key(), Some(*key));
+                iter.next();
+            }
+            assert!(!iter.valid());
+        }
+    }
+
+    #[test]
+    fn test_bytewise_comparator_enforced() {
+        use rocksdb::{ColumnFamilyDescriptor, OptimisticTransactionDB, Options};
+        use tempfile::TempDir;
+
+        use crate::rocksdb_storage::{
+            storage::{AUX_CF_NAME, DEDUP_CF_NAME, META_CF_NAME, ROOTS_CF_NAME},
+            RocksDbStorage,
+        };
+
+        fn create_db(path: &std::path::Path, opts: Options) {
+            let mut opts = opts;
+            opts.create_if_missing(true);
+            opts.create_missing_column_families(true);
+            OptimisticTransactionDB::open_cf_descriptors(
+                &opts,
+                path,
+                [AUX_CF_NAME, ROOTS_CF_NAME, META_CF_NAME, DEDUP_CF_NAME]
+                    .map(|name| ColumnFamilyDescriptor::new(name, opts.clone())),
+            )
+            .expect("cannot create database");
+        }
+
+        // Databases created with the built-in comparator open
+        let tmp_dir = TempDir::new().expect("cannot create temporary dir");
+        create_db(tmp_dir.path(), Options::default());
+        let storage =
+            RocksDbStorage::default_rocksdb_with_path(tmp_dir.path()).expect("cannot open storage");
+        let context = storage.get_storage_context(to_path(b"ayya"));
+        context
+            .put_aux(b"key", b"value")
+            .expect("cannot insert into aux cf");
+        assert_eq!(
+            context.get_aux(b"key").expect("cannot get from aux cf"),
+            Some(b"value".to_vec())
+        );
+
+        // Databases ordering keys otherwise don't
+        let tmp_dir = TempDir::new().expect("cannot create temporary dir");
+        let mut opts = Options::default();
+        opts.set_comparator("reverse", |a, b| b.cmp(a));
+        create_db(tmp_dir.path(), opts);
+        assert!(RocksDbStorage::default_rocksdb_with_path(tmp_dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_flush_cf() {
+        let storage = TempStorage::new();
+        let context = storage.get_storage_context(to_path(b"ayya"));
+        for i in 0u32..1000 {
+            context
+                .put_aux(i.to_be_bytes(), &[0; 1000])
+                .expect("cannot insert into aux cf");
+        }
+        let before = storage
+            .memory_usage()
+            .expect("cannot get memory usage")
+            .memtables_bytes;
+
+        storage
+            .flush_cf(ColumnFamily::Aux)
+            .expect("cannot flush aux cf");
+        let after = storage
+            .memory_usage()
+            .expect("cannot get memory usage")
+            .memtables_bytes;
+        assert!(after + 500_000 < before);
+        assert_eq!(
+            context
+                .get_aux(999u32.to_be_bytes())
+                .expect("cannot get from aux cf"),
+            Some(vec![0; 1000])
+        );
+    }
 }
 
 mod transaction {
     use super::*;
-    use crate::{RawIterator, Storage, StorageContext};
+    use crate::{
+        rocksdb_storage::{PreparedCommit, RocksDbStorage, WriteError},
+        RawIterator, Storage, StorageContext,
+    };
 
     #[test]
     fn test_aux_cf_methods() {
@@ -642,6 +846,54 @@ mod transaction {
             .is_none());
     }
 
+    #[test]
+    fn test_delete_range_and_clear() {
+        let storage = TempStorage::new();
+        let tx = storage.start_transaction();
+        let context_ayya = storage.get_transactional_storage_context(to_path(b"ayya"), &tx);
+        let context_ayyb = storage.get_transactional_storage_context(to_path(b"ayyb"), &tx);
+
+        for key in [b"key1", b"key2", b"key3", b"key4"] {
+            context_ayya
+                .put(key, b"ayyavalue")
+                .expect("cannot insert into storage");
+            context_ayyb
+                .put(key, b"ayybvalue")
+                .expect("cannot insert into storage");
+        }
+
+        context_ayya
+            .delete_range(b"key2", b"key4")
+            .expect("cannot delete range");
+        assert!(context_ayya
+            .get(b"key1")
+            .expect("cannot get from storage")
+            .is_some());
+        assert!(context_ayya
+            .get(b"key3")
+            .expect("cannot get from storage")
+            .is_none());
+        assert!(context_ayya
+            .get(b"key4")
+            .expect("cannot get from storage")
+            .is_some());
+
+        context_ayya.clear().expect("cannot clear storage");
+        storage
+            .commit_transaction(tx)
+            .expect("cannot commit transaction");
+
+        let context_ayya = storage.get_storage_context(to_path(b"ayya"));
+        let mut iter = context_ayya.raw_iter();
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        let context_ayyb = storage.get_storage_context(to_path(b"ayyb"));
+        assert!(context_ayyb
+            .get(b"key2")
+            .expect("cannot get from storage")
+            .is_some());
+    }
+
     #[test]
     fn test_raw_iterator() {
         let storage = TempStorage::new();
@@ -744,4 +996,633 @@ mod transaction {
             assert!(expected_iter.next().is_none());
         }
     }
+
+    #[test]
+    fn test_snapshot_transaction() {
+        let storage = TempStorage::new();
+        let context = storage.get_storage_context(to_path(b"someprefix"));
+        context
+            .put(b"key1", b"value1")
+            .expect("expected successful insertion");
+        context
+            .put_aux(b"key1", b"aux1")
+            .expect("expected successful insertion");
+
+        let snapshot_tx = storage.start_snapshot_transaction();
+        let tx = storage.start_transaction();
+        let snapshot_context =
+            storage.get_transactional_storage_context(to_path(b"someprefix"), &snapshot_tx);
+        let tx_context = storage.get_transactional_storage_context(to_path(b"someprefix"), &tx);
+
+        // Commits after the snapshot transaction start are not visible to it
+        context
+            .put(b"key1", b"value2")
+            .expect("expected successful insertion");
+        context
+            .put(b"key2", b"value2")
+            .expect("expected successful insertion");
+        context
+            .put_aux(b"key1", b"aux2")
+            .expect("expected successful insertion");
+
+        assert_eq!(
+            snapshot_context
+                .get(b"key1")
+                .expect("cannot get from storage"),
+            Some(b"value1".to_vec())
+        );
+        assert_eq!(
+            snapshot_context
+                .get_aux(b"key1")
+                .expect("cannot get from storage"),
+            Some(b"aux1".to_vec())
+        );
+        let mut iter = snapshot_context.raw_iter();
+        iter.seek_to_first();
+        assert_eq!(iter.key(), Some(b"key1".as_ref()));
+        iter.next();
+        assert!(!iter.valid());
+
+        // Its own writes are visible
+        snapshot_context
+            .put(b"key3", b"value3")
+            .expect("expected successful insertion");
+        assert_eq!(
+            snapshot_context
+                .get(b"key3")
+                .expect("cannot get from storage"),
+            Some(b"value3".to_vec())
+        );
+
+        // An ordinary transaction reads the latest committed data
+        assert_eq!(
+            tx_context.get(b"key1").expect("cannot get from storage"),
+            Some(b"value2".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_prepared_transaction() {
+        let storage = TempStorage::new();
+        let context = storage.get_storage_context(to_path(b"someprefix"));
+
+        let version = storage.write_version();
+        let tx = storage.start_transaction();
+        let tx_context = storage.get_transactional_storage_context(to_path(b"someprefix"), &tx);
+        tx_context
+            .put(b"key1", b"value1")
+            .expect("expected successful insertion");
+        tx_context
+            .put_aux(b"key1", b"aux1")
+            .expect("expected successful insertion");
+        drop(tx_context);
+        let committed_id = storage
+            .prepare_transaction(&tx, version)
+            .expect("expected successful prepare")
+            .expect("no conflict");
+
+        // Another transaction writing a key of the pending one can't be
+        // prepared, as its commit would make the other stale
+        let version = storage.write_version();
+        let tx = storage.start_transaction();
+        let tx_context = storage.get_transactional_storage_context(to_path(b"someprefix"), &tx);
+        tx_context
+            .put(b"key1", b"value2")
+            .expect("expected successful insertion");
+        drop(tx_context);
+        assert_eq!(
+            storage
+                .prepare_transaction(&tx, version)
+                .expect("expected successful prepare"),
+            None
+        );
+
+        // One writing other keys can
+        let version = storage.write_version();
+        let tx = storage.start_transaction();
+        let tx_context = storage.get_transactional_storage_context(to_path(b"someprefix"), &tx);
+        tx_context
+            .put(b"key2", b"value2")
+            .expect("expected successful insertion");
+        drop(tx_context);
+        let rolled_back_id = storage
+            .prepare_transaction(&tx, version)
+            .expect("expected successful prepare")
+            .expect("no conflict");
+        assert!(rolled_back_id > committed_id);
+
+        // Writes of other keys are not held by prepared transactions
+        context
+            .put(b"key3", b"value3")
+            .expect("expected successful insertion");
+        context
+            .put_aux(b"key2", b"aux2")
+            .expect("expected successful insertion");
+
+        // Writes of keys of a prepared transaction fail instead of waiting for
+        // it, so they can't make it stale
+        assert!(matches!(
+            context.put(b"key1", b"newer"),
+            Err(WriteError::PreparedTransactionConflict)
+        ));
+        assert!(matches!(
+            context.delete_aux(b"key1"),
+            Err(WriteError::PreparedTransactionConflict)
+        ));
+        assert!(matches!(
+            context.clear(),
+            Err(WriteError::PreparedTransactionConflict)
+        ));
+        let tx = storage.start_transaction();
+        let tx_context = storage.get_transactional_storage_context(to_path(b"someprefix"), &tx);
+        tx_context
+            .put(b"key2", b"newer")
+            .expect("expected successful insertion");
+        drop(tx_context);
+        assert!(matches!(
+            storage.commit_transaction(tx),
+            Err(WriteError::PreparedTransactionConflict)
+        ));
+
+        // Prepared writes are not visible until committed
+        assert!(context
+            .get(b"key1")
+            .expect("cannot get from storage")
+            .is_none());
+        assert_eq!(
+            storage
+                .prepared_transactions()
+                .expect("cannot list prepared transactions"),
+            vec![committed_id, rolled_back_id]
+        );
+
+        assert_eq!(
+            storage
+                .commit_prepared(committed_id)
+                .expect("expected successful commit"),
+            PreparedCommit::Committed
+        );
+        assert_eq!(
+            context.get(b"key1").expect("cannot get from storage"),
+            Some(b"value1".to_vec())
+        );
+        assert_eq!(
+            context.get_aux(b"key1").expect("cannot get from storage"),
+            Some(b"aux1".to_vec())
+        );
+        assert_eq!(
+            storage
+                .commit_prepared(committed_id)
+                .expect("expected successful commit"),
+            PreparedCommit::NotFound
+        );
+
+        // Keys are released once the prepared transaction is resolved
+        context
+            .put(b"key1", b"newer")
+            .expect("expected successful insertion");
+        storage
+            .rollback_prepared(rolled_back_id)
+            .expect("expected successful rollback");
+        assert!(context
+            .get(b"key2")
+            .expect("cannot get from storage")
+            .is_none());
+        context
+            .put(b"key2", b"newer")
+            .expect("expected successful insertion");
+        assert!(storage
+            .prepared_transactions()
+            .expect("cannot list prepared transactions")
+            .is_empty());
+
+        // A write of a key the transaction writes, made after it started but
+        // before the prepare, would fail an optimistic commit
+        let version = storage.write_version();
+        let tx = storage.start_transaction();
+        let tx_context = storage.get_transactional_storage_context(to_path(b"someprefix"), &tx);
+        tx_context
+            .put(b"key4", b"value4")
+            .expect("expected successful insertion");
+        drop(tx_context);
+        context
+            .put(b"key5", b"value5")
+            .expect("expected successful insertion");
+        let id = storage
+            .prepare_transaction(&tx, version)
+            .expect("expected successful prepare")
+            .expect("no conflict");
+        storage
+            .rollback_prepared(id)
+            .expect("expected successful rollback");
+        context
+            .put(b"key4", b"value5")
+            .expect("expected successful insertion");
+        assert_eq!(
+            storage
+                .prepare_transaction(&tx, version)
+                .expect("expected successful prepare"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_dedup_references() {
+        let storage = TempStorage::new();
+        let hash = [7; 32];
+
+        let tx = storage.start_transaction();
+        assert_eq!(
+            storage
+                .add_dedup_reference(&hash, b"value", Some(&tx))
+                .expect("cannot add dedup reference"),
+            1
+        );
+        assert!(storage
+            .get_dedup_value(&hash, None)
+            .expect("cannot get dedup value")
+            .is_none());
+        storage
+            .commit_transaction(tx)
+            .expect("cannot commit transaction");
+
+        assert_eq!(
+            storage
+                .add_dedup_reference(&hash, b"value", None)
+                .expect("cannot add dedup reference"),
+            2
+        );
+        assert_eq!(
+            storage
+                .remove_dedup_reference(&hash, None)
+                .expect("cannot remove dedup reference"),
+            1
+        );
+        assert_eq!(
+            storage
+                .get_dedup_value(&hash, None)
+                .expect("cannot get dedup value"),
+            Some(b"value".to_vec())
+        );
+        assert_eq!(
+            storage
+                .remove_dedup_reference(&hash, None)
+                .expect("cannot remove dedup reference"),
+            0
+        );
+        assert!(storage
+            .get_dedup_value(&hash, None)
+            .expect("cannot get dedup value")
+            .is_none());
+    }
+}
+
+mod dyn_storage {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        dyn_storage::subtree_id_key,
+        recording::{Record, RecordingStorage, ReplayStorage},
+        rocksdb_storage::{ReadOnlyRocksDbStorage, RocksDbStorage},
+        Batch, BoxedStorage, RawIterator, Storage, StorageContext,
+    };
+
+    #[test]
+    fn test_boxed_storage() {
+        let tmp_dir = TempDir::new().expect("cannot create tempdir");
+        let storage: BoxedStorage = Box::new(
+            RocksDbStorage::default_rocksdb_with_path(tmp_dir.path())
+                .expect("cannot open RocksDB storage"),
+        );
+        let context = storage.get_storage_context(to_path(b"ayya"));
+        context.put(b"key1", b"value1").expect("cannot insert data");
+        let mut batch = context.new_batch();
+        batch
+            .put(b"key2", b"value2")
+            .expect("cannot put into batch");
+        batch
+            .put_aux(b"key3", b"value3")
+            .expect("cannot put into batch");
+        context.commit_batch(batch).expect("cannot commit batch");
+
+        assert_eq!(
+            context
+                .get(b"key2")
+                .expect("cannot get data")
+                .expect("data should exist"),
+            b"value2"
+        );
+        assert_eq!(
+            context
+                .get_aux(b"key3")
+                .expect("cannot get from aux cf")
+                .expect("data should exist"),
+            b"value3"
+        );
+
+        let mut iter = context.raw_iter();
+        iter.seek_to_first();
+        let mut keys = Vec::new();
+        while iter.valid() {
+            keys.push(iter.key().expect("key should exist").to_vec());
+            iter.next();
+        }
+        assert_eq!(keys, vec![b"key1".to_vec(), b"key2".to_vec()]);
+
+        let tx = storage.start_transaction();
+        let tx_context = storage.get_transactional_storage_context(to_path(b"ayya"), &tx);
+        tx_context
+            .put(b"key4", b"value4")
+            .expect("cannot insert data");
+        assert!(context.get(b"key4").expect("cannot get data").is_none());
+        storage
+            .commit_transaction(tx)
+            .expect("cannot commit transaction");
+        assert_eq!(
+            context
+                .get(b"key4")
+                .expect("cannot get data")
+                .expect("data should exist"),
+            b"value4"
+        );
+    }
+
+    #[test]
+    fn test_subtree_contexts() {
+        let tmp_dir = TempDir::new().expect("cannot create tempdir");
+        let storage = RocksDbStorage::default_rocksdb_with_path(tmp_dir.path())
+            .expect("cannot open RocksDB storage");
+        storage
+            .get_storage_context(to_path(b"ayya"))
+            .put(b"key1", b"value1")
+            .expect("cannot insert data");
+
+        // Subtrees without an identifier are addressed by the path-derived
+        // prefix through the facade too
+        let boxed: BoxedStorage = Box::new(storage);
+        let context = boxed.get_storage_context(to_path(b"ayya"));
+        assert_eq!(
+            context
+                .get(b"key1")
+                .expect("cannot get data")
+                .expect("data should exist"),
+            b"value1"
+        );
+        let mut iter = context.raw_iter();
+        iter.seek_to_first();
+        iter.next();
+        assert!(!iter.valid());
+        iter.status().expect("end of data is not an error");
+
+        // Subtree moved to another path keeps its data by its identifier
+        let tx = boxed.start_transaction();
+        boxed
+            .get_transactional_storage_context(std::iter::empty(), &tx)
+            .put_meta(
+                subtree_id_key(&RocksDbStorage::build_prefix(to_path(b"ayyb"))),
+                &RocksDbStorage::build_prefix(to_path(b"ayya")),
+            )
+            .expect("cannot insert into meta cf");
+        let moved = boxed.get_transactional_storage_context(to_path(b"ayyb"), &tx);
+        assert_eq!(
+            moved
+                .get(b"key1")
+                .expect("cannot get data")
+                .expect("data should exist"),
+            b"value1"
+        );
+        moved.put(b"key2", b"value2").expect("cannot insert data");
+        assert!(boxed
+            .get_storage_context(to_path(b"ayyb"))
+            .get(b"key2")
+            .expect("cannot get data")
+            .is_none());
+        boxed
+            .commit_transaction(tx)
+            .expect("cannot commit transaction");
+        assert_eq!(
+            context
+                .get(b"key2")
+                .expect("cannot get data")
+                .expect("data should exist"),
+            b"value2"
+        );
+    }
+
+    #[test]
+    fn test_read_only_storage() {
+        let tmp_dir = TempDir::new().expect("cannot create tempdir");
+        let storage = RocksDbStorage::default_rocksdb_with_path(tmp_dir.path())
+            .expect("cannot open RocksDB storage");
+        let context = storage.get_storage_context(to_path(b"ayya"));
+        context.put(b"key1", b"value1").expect("cannot insert data");
+        context
+            .put_aux(b"key2", b"value2")
+            .expect("cannot insert into aux cf");
+        Storage::flush(&storage).expect("cannot flush storage");
+
+        // Read-only storage can be opened while the database is open
+        let read_only: BoxedStorage = Box::new(
+            ReadOnlyRocksDbStorage::open(tmp_dir.path()).expect("cannot open read-only storage"),
+        );
+        let tx = read_only.start_transaction();
+        let context = read_only.get_transactional_storage_context(to_path(b"ayya"), &tx);
+        assert_eq!(
+            context
+                .get(b"key1")
+                .expect("cannot get data")
+                .expect("data should exist"),
+            b"value1"
+        );
+        assert_eq!(
+            context
+                .get_aux(b"key2")
+                .expect("cannot get from aux cf")
+                .expect("data should exist"),
+            b"value2"
+        );
+        let mut iter = context.raw_iter();
+        iter.seek_to_first();
+        assert_eq!(iter.key(), Some(b"key1".as_ref()));
+
+        assert!(context.put(b"key3", b"value3").is_err());
+        let mut batch = context.new_batch();
+        batch
+            .put(b"key3", b"value3")
+            .expect("cannot put into batch");
+        assert!(context.commit_batch(batch).is_err());
+    }
+
+    #[test]
+    fn test_recording_and_replay() {
+        let tmp_dir = TempDir::new().expect("cannot create tempdir");
+        let log_path = tmp_dir.path().join("recording");
+        let inner: BoxedStorage = Box::new(
+            RocksDbStorage::default_rocksdb_with_path(tmp_dir.path().join("db"))
+                .expect("cannot open RocksDB storage"),
+        );
+        let context = inner.get_storage_context(to_path(b"ayya"));
+        context.put(b"key1", b"value1").expect("cannot insert data");
+        context.put(b"key2", b"value2").expect("cannot insert data");
+        drop(context);
+
+        let storage: BoxedStorage =
+            Box::new(RecordingStorage::new(inner, &log_path).expect("cannot create recording"));
+        let read_all = |storage: &BoxedStorage| {
+            let context = storage.get_storage_context(to_path(b"ayya"));
+            let value = context.get(b"key1").expect("cannot get data");
+            let missing = context.get_aux(b"key1").expect("cannot get from aux cf");
+            let mut batch = context.new_batch();
+            batch
+                .put(b"key3", b"value3")
+                .expect("cannot put into batch");
+            context.commit_batch(batch).expect("cannot commit batch");
+            let mut iter = context.raw_iter();
+            iter.seek_to_first();
+            let mut entries = Vec::new();
+            while iter.valid() {
+                entries.push((
+                    iter.key().expect("key should exist").to_vec(),
+                    iter.value().expect("value should exist").to_vec(),
+                ));
+                iter.next();
+            }
+            (value, missing, entries)
+        };
+
+        let recorded = read_all(&storage);
+        assert_eq!(recorded.0, Some(b"value1".to_vec()));
+        assert_eq!(recorded.2.len(), 3);
+        storage.flush().expect("cannot flush recording");
+        drop(storage);
+
+        let replay: BoxedStorage =
+            Box::new(ReplayStorage::open(&log_path).expect("cannot open recording"));
+        assert_eq!(read_all(&replay), recorded);
+        assert!(replay
+            .get_storage_context(to_path(b"ayyb"))
+            .get(b"key1")
+            .is_err());
+    }
+
+    #[test]
+    fn test_recording_transactions() {
+        let tmp_dir = TempDir::new().expect("cannot create tempdir");
+        let log_path = tmp_dir.path().join("recording");
+        let inner: BoxedStorage = Box::new(
+            RocksDbStorage::default_rocksdb_with_path(tmp_dir.path().join("db"))
+                .expect("cannot open RocksDB storage"),
+        );
+        let storage: BoxedStorage =
+            Box::new(RecordingStorage::new(inner, &log_path).expect("cannot create recording"));
+        let run = |storage: &BoxedStorage| {
+            let context = storage.get_storage_context(to_path(b"ayya"));
+            let rolled_back = storage.start_transaction();
+            storage
+                .get_transactional_storage_context(to_path(b"ayya"), &rolled_back)
+                .put(b"key1", b"value1")
+                .expect("cannot insert data");
+            storage
+                .rollback_transaction(&rolled_back)
+                .expect("cannot rollback transaction");
+
+            let tx = storage.start_transaction();
+            let tx_context = storage.get_transactional_storage_context(to_path(b"ayya"), &tx);
+            tx_context
+                .put(b"key2", b"value2")
+                .expect("cannot insert data");
+            let in_transaction = tx_context.get(b"key2").expect("cannot get data");
+            let outside = context.get(b"key2").expect("cannot get data");
+            drop(tx_context);
+            storage
+                .commit_transaction(tx)
+                .expect("cannot commit transaction");
+            (
+                in_transaction,
+                outside,
+                context.get(b"key1").expect("cannot get data"),
+                context.get(b"key2").expect("cannot get data"),
+            )
+        };
+
+        let recorded = run(&storage);
+        assert_eq!(
+            recorded,
+            (
+                Some(b"value2".to_vec()),
+                None,
+                None,
+                Some(b"value2".to_vec())
+            )
+        );
+        storage.flush().expect("cannot flush recording");
+        drop(storage);
+
+        let mut reader =
+            std::io::BufReader::new(std::fs::File::open(&log_path).expect("cannot open recording"));
+        let mut boundaries = Vec::new();
+        while let Some(record) = Record::read_from(&mut reader).expect("cannot read record") {
+            if let Record::Begin { .. } | Record::Commit { .. } | Record::Rollback { .. } = record {
+                boundaries.push(record);
+            }
+        }
+        assert_eq!(
+            boundaries,
+            vec![
+                Record::Begin { transaction: 0 },
+                Record::Rollback { transaction: 0 },
+                Record::Begin { transaction: 1 },
+                Record::Commit { transaction: 1 },
+            ]
+        );
+
+        let replay: BoxedStorage =
+            Box::new(ReplayStorage::open(&log_path).expect("cannot open recording"));
+        assert_eq!(run(&replay), recorded);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_fault_injection() {
+        use crate::fault_injection::{FaultInjectionStorage, InjectedFault};
+
+        let tmp_dir = TempDir::new().expect("cannot create tempdir");
+        let faulty = FaultInjectionStorage::new(Box::new(
+            RocksDbStorage::default_rocksdb_with_path(tmp_dir.path())
+                .expect("cannot open RocksDB storage"),
+        ));
+        let injector = faulty.injector();
+        injector.fail_write(2);
+        injector.corrupt_key(b"key1");
+        let storage: BoxedStorage = Box::new(faulty);
+        let context = storage.get_storage_context(to_path(b"ayya"));
+
+        context.put(b"key1", b"value1").expect("cannot insert data");
+        let error = context
+            .put(b"key2", b"value2")
+            .expect_err("write should fail");
+        assert!(error
+            .to_string()
+            .contains(&InjectedFault { write: 2 }.to_string()));
+        assert!(context.get(b"key2").expect("cannot get data").is_none());
+        context.put(b"key2", b"value2").expect("cannot insert data");
+
+        assert_eq!(
+            context
+                .get(b"key2")
+                .expect("cannot get data")
+                .expect("data should exist"),
+            b"value2"
+        );
+        let corrupted = context
+            .get(b"key1")
+            .expect("cannot get data")
+            .expect("data should exist");
+        assert_ne!(corrupted, b"value1");
+
+        let mut iter = context.raw_iter();
+        iter.seek_to_first();
+        assert_eq!(iter.value(), Some(corrupted.as_slice()));
+    }
 }
@@ -0,0 +1,358 @@
+//! Conflict detection of writes against two-phase commit. Keys of every write
+//! are read from its write batch and logged with the write's version, so a
+//! transaction is prepared only if no write made since it started touched a
+//! key it writes. Keys of pending prepared transactions are reserved until
+//! they are committed or rolled back: writes touching them fail with
+//! [`WriteError::PreparedTransactionConflict`] instead of waiting, so a
+//! prepared transaction can't be made stale and other writes go on, including
+//! after a restart. Prepares, commits and rollbacks of prepared transactions
+//! take the lock exclusively, so no write runs between their checks and their
+//! own writes.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+    sync::{Mutex, MutexGuard, RwLock, RwLockWriteGuard},
+};
+
+use rocksdb::Error;
+
+/// Number of keys of recent writes logged to check transactions being
+/// prepared against. A transaction started before the oldest logged write
+/// can't be checked, so it isn't prepared.
+const WRITE_LOG_CAPACITY: usize = 1 << 16;
+/// Id of RocksDB default column family
+pub(super) const DEFAULT_COLUMN_FAMILY_ID: u32 = 0;
+/// Length of the sequence number and the number of records preceding records
+/// of a serialized write batch
+const BATCH_HEADER_LENGTH: usize = 12;
+
+// Tags of serialized write batch records, see `ValueType` in RocksDB's
+// `db/dbformat.h`
+const TAG_DELETION: u8 = 0x0;
+const TAG_VALUE: u8 = 0x1;
+const TAG_MERGE: u8 = 0x2;
+const TAG_LOG_DATA: u8 = 0x3;
+const TAG_CF_DELETION: u8 = 0x4;
+const TAG_CF_VALUE: u8 = 0x5;
+const TAG_CF_MERGE: u8 = 0x6;
+const TAG_SINGLE_DELETION: u8 = 0x7;
+const TAG_CF_SINGLE_DELETION: u8 = 0x8;
+const TAG_BEGIN_PREPARE_XID: u8 = 0x9;
+const TAG_END_PREPARE_XID: u8 = 0xA;
+const TAG_COMMIT_XID: u8 = 0xB;
+const TAG_ROLLBACK_XID: u8 = 0xC;
+const TAG_NOOP: u8 = 0xD;
+const TAG_CF_RANGE_DELETION: u8 = 0xE;
+const TAG_RANGE_DELETION: u8 = 0xF;
+const TAG_CF_BLOB_INDEX: u8 = 0x10;
+const TAG_BLOB_INDEX: u8 = 0x11;
+const TAG_BEGIN_PERSISTED_PREPARE_XID: u8 = 0x12;
+const TAG_BEGIN_UNPREPARE_XID: u8 = 0x13;
+
+/// Error of a write checked against pending prepared transactions
+#[derive(Debug)]
+pub enum WriteError {
+    /// Error of RocksDB itself
+    RocksDb(Error),
+    /// The write touches a key written by a pending prepared transaction
+    PreparedTransactionConflict,
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::RocksDb(e) => e.fmt(f),
+            WriteError::PreparedTransactionConflict => {
+                write!(f, "write conflicts with a pending prepared transaction")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WriteError::RocksDb(e) => Some(e),
+            WriteError::PreparedTransactionConflict => None,
+        }
+    }
+}
+
+impl From<Error> for WriteError {
+    fn from(e: Error) -> Self {
+        WriteError::RocksDb(e)
+    }
+}
+
+/// Range of keys deleted by a write, the end is excluded
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DeletedRange {
+    /// Column family id, the range is deleted in all of them if it's `None`
+    column_family: Option<u32>,
+    from: Vec<u8>,
+    to: Vec<u8>,
+}
+
+impl DeletedRange {
+    fn contains(&self, (column_family, key): &(u32, Vec<u8>)) -> bool {
+        (self.column_family.is_none() || self.column_family == Some(*column_family))
+            && self.from <= *key
+            && *key < self.to
+    }
+
+    fn overlaps(&self, other: &DeletedRange) -> bool {
+        (self.column_family.is_none()
+            || other.column_family.is_none()
+            || self.column_family == other.column_family)
+            && self.from < other.to
+            && other.from < self.to
+    }
+}
+
+/// Keys touched by a write, with their column family ids
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct WriteSet {
+    keys: BTreeSet<(u32, Vec<u8>)>,
+    ranges: Vec<DeletedRange>,
+    /// The write batch couldn't be read, so the write is taken to touch every
+    /// key
+    everything: bool,
+}
+
+impl WriteSet {
+    /// Reads keys a write batch touches from its serialized form, see
+    /// RocksDB's `db/write_batch.cc`
+    pub(super) fn of_batch(data: &[u8]) -> Self {
+        let mut writes = WriteSet::default();
+        if writes.read_batch(data).is_none() {
+            writes.everything = true;
+        }
+        writes
+    }
+
+    /// Adds a range of deleted keys, in all column families if
+    /// `column_family` is `None`
+    pub(super) fn add_range(&mut self, column_family: Option<u32>, from: &[u8], to: &[u8]) {
+        self.ranges.push(DeletedRange {
+            column_family,
+            from: from.to_vec(),
+            to: to.to_vec(),
+        });
+    }
+
+    fn read_batch(&mut self, data: &[u8]) -> Option<()> {
+        let mut reader = BatchReader(data.get(BATCH_HEADER_LENGTH..)?);
+        while !reader.0.is_empty() {
+            let tag = reader.byte()?;
+            let column_family = match tag {
+                TAG_CF_DELETION
+                | TAG_CF_VALUE
+                | TAG_CF_MERGE
+                | TAG_CF_SINGLE_DELETION
+                | TAG_CF_RANGE_DELETION
+                | TAG_CF_BLOB_INDEX => reader.varint32()?,
+                _ => DEFAULT_COLUMN_FAMILY_ID,
+            };
+            match tag {
+                TAG_VALUE | TAG_CF_VALUE | TAG_MERGE | TAG_CF_MERGE | TAG_BLOB_INDEX
+                | TAG_CF_BLOB_INDEX => {
+                    self.keys.insert((column_family, reader.slice()?.to_vec()));
+                    reader.slice()?;
+                }
+                TAG_DELETION | TAG_CF_DELETION | TAG_SINGLE_DELETION | TAG_CF_SINGLE_DELETION => {
+                    self.keys.insert((column_family, reader.slice()?.to_vec()));
+                }
+                TAG_RANGE_DELETION | TAG_CF_RANGE_DELETION => {
+                    let from = reader.slice()?;
+                    let to = reader.slice()?;
+                    self.add_range(Some(column_family), from, to);
+                }
+                TAG_LOG_DATA | TAG_END_PREPARE_XID | TAG_COMMIT_XID | TAG_ROLLBACK_XID => {
+                    reader.slice()?;
+                }
+                TAG_NOOP
+                | TAG_BEGIN_PREPARE_XID
+                | TAG_BEGIN_PERSISTED_PREPARE_XID
+                | TAG_BEGIN_UNPREPARE_XID => {}
+                _ => return None,
+            }
+        }
+        Some(())
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.everything && self.keys.is_empty() && self.ranges.is_empty()
+    }
+
+    /// Returns the number of keys and ranges, counted against the log
+    /// capacity
+    fn len(&self) -> usize {
+        self.keys.len() + self.ranges.len() + usize::from(self.everything)
+    }
+
+    /// Returns whether both writes touch a key
+    fn intersects(&self, other: &WriteSet) -> bool {
+        if self.is_empty() || other.is_empty() {
+            return false;
+        }
+        if self.everything || other.everything {
+            return true;
+        }
+        self.keys.iter().any(|key| other.keys.contains(key))
+            || self.keys.iter().any(|key| other.covers(key))
+            || other.keys.iter().any(|key| self.covers(key))
+            || self
+                .ranges
+                .iter()
+                .any(|range| other.ranges.iter().any(|other| range.overlaps(other)))
+    }
+
+    /// Returns whether the key is in a deleted range
+    fn covers(&self, key: &(u32, Vec<u8>)) -> bool {
+        self.ranges.iter().any(|range| range.contains(key))
+    }
+}
+
+/// Reader of fields of a serialized write batch
+struct BatchReader<'a>(&'a [u8]);
+
+impl<'a> BatchReader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(byte)
+    }
+
+    /// Reads a base-128 varint of up to 32 bits, least significant group
+    /// first
+    fn varint32(&mut self) -> Option<u32> {
+        let mut value = 0;
+        for shift in (0..32).step_by(7) {
+            let byte = self.byte()?;
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Reads a slice preceded by its length
+    fn slice(&mut self) -> Option<&'a [u8]> {
+        let length = self.varint32()? as usize;
+        let slice = self.0.get(..length)?;
+        self.0 = &self.0[length..];
+        Some(slice)
+    }
+}
+
+/// Log of recent writes and keys of pending prepared transactions
+#[derive(Default)]
+struct GateState {
+    /// Number of writes counted since the storage was opened
+    version: u64,
+    /// Keys of recent writes with their versions, oldest first
+    log: VecDeque<(u64, WriteSet)>,
+    /// Number of keys and ranges in the log
+    logged: usize,
+    /// Version of the latest write dropped from the log
+    dropped_version: u64,
+    /// Keys of pending prepared transactions by their ids
+    pending: BTreeMap<u64, WriteSet>,
+}
+
+impl GateState {
+    fn record(&mut self, writes: WriteSet) {
+        self.version += 1;
+        if writes.is_empty() {
+            return;
+        }
+        self.logged += writes.len();
+        self.log.push_back((self.version, writes));
+        while self.logged > WRITE_LOG_CAPACITY {
+            let (version, writes) = self.log.pop_front().expect("logged keys are in the log");
+            self.logged -= writes.len();
+            self.dropped_version = version;
+        }
+    }
+
+    fn conflicts_with_pending(&self, writes: &WriteSet) -> bool {
+        self.pending
+            .values()
+            .any(|pending| pending.intersects(writes))
+    }
+}
+
+/// Lock, log of writes and keys reserved by prepared transactions
+#[derive(Default)]
+pub(super) struct WriteGate {
+    lock: RwLock<()>,
+    state: Mutex<GateState>,
+}
+
+impl WriteGate {
+    /// Runs a write touching `writes` unless a pending prepared transaction
+    /// touches any of them, and logs its keys once it is done, so a
+    /// transaction started while it runs is checked against it
+    pub(super) fn write<T>(
+        &self,
+        writes: WriteSet,
+        f: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, WriteError> {
+        let _shared = self.lock.read().unwrap_or_else(|e| e.into_inner());
+        if self.state().conflicts_with_pending(&writes) {
+            return Err(WriteError::PreparedTransactionConflict);
+        }
+        let result = f()?;
+        self.state().record(writes);
+        Ok(result)
+    }
+
+    /// Blocks writes made through [`WriteGate::write`] while the guard is held
+    pub(super) fn exclusive(&self) -> RwLockWriteGuard<()> {
+        self.lock.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the number of writes counted since the storage was opened
+    pub(super) fn version(&self) -> u64 {
+        self.state().version
+    }
+
+    /// Returns whether a transaction started at `base_version` and touching
+    /// `writes` may be prepared: no write made since then touched its keys
+    /// and no pending prepared transaction does. It's checked under the
+    /// exclusive lock.
+    pub(super) fn can_prepare(&self, base_version: u64, writes: &WriteSet) -> bool {
+        let state = self.state();
+        state.dropped_version <= base_version
+            && !state
+                .log
+                .iter()
+                .rev()
+                .take_while(|(version, _)| *version > base_version)
+                .any(|(_, logged)| logged.intersects(writes))
+            && !state.conflicts_with_pending(writes)
+    }
+
+    /// Logs a write made under the exclusive lock
+    pub(super) fn record(&self, writes: WriteSet) {
+        self.state().record(writes);
+    }
+
+    /// Reserves keys of a prepared transaction made pending under the
+    /// exclusive lock, or found on open
+    pub(super) fn add_pending(&self, id: u64, writes: WriteSet) {
+        self.state().pending.insert(id, writes);
+    }
+
+    /// Releases keys of a prepared transaction committed or rolled back under
+    /// the exclusive lock
+    pub(super) fn resolve_pending(&self, id: u64) {
+        self.state().pending.remove(&id);
+    }
+
+    fn state(&self) -> MutexGuard<GateState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
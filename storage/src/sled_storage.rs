@@ -0,0 +1,14 @@
+//! GroveDB storage layer implemented over sled, a pure Rust backend for
+//! platforms where building RocksDB is impractical.
+//! Sled has no column families, so data, auxiliary, roots and meta storages
+//! are namespaces in a single tree distinguished by the first key byte.
+mod dyn_storage;
+mod storage;
+mod storage_context;
+#[cfg(test)]
+mod tests;
+
+pub use sled::Error;
+pub use storage_context::{PrefixedSledBatch, PrefixedSledRawIterator, PrefixedSledStorageContext};
+
+pub use self::storage::{SledStorage, SledStorageError, SledTransaction};
@@ -0,0 +1,60 @@
+//! Implementation of the object-safe storage facade for sled backend.
+use super::{PrefixedSledStorageContext, SledStorage, SledTransaction};
+use crate::{
+    dyn_storage::{
+        impl_dyn_storage_context, subtree_storage_context, DynBatch, DynRawIterator, DynStorage,
+        DynStorageContext, DynStorageError,
+    },
+    DynTransaction, Storage, StorageContext,
+};
+
+/// Transaction of sled storage used through the facade
+struct SledDynTransaction<'db> {
+    storage: &'db SledStorage,
+    transaction: SledTransaction,
+}
+
+impl<'db> DynStorage<'db> for SledStorage {
+    fn start_transaction(&'db self) -> Box<dyn DynTransaction + 'db> {
+        Box::new(SledDynTransaction {
+            storage: self,
+            transaction: Storage::start_transaction(self),
+        })
+    }
+
+    fn flush(&self) -> Result<(), DynStorageError> {
+        Storage::flush(self).map_err(DynStorageError::new)
+    }
+
+    fn storage_context(&'db self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'db> + 'db> {
+        subtree_storage_context(path, |prefix| {
+            Box::new(PrefixedSledStorageContext::new(&self.db, None, prefix))
+        })
+    }
+}
+
+impl<'db> DynTransaction for SledDynTransaction<'db> {
+    fn storage_context<'a>(&'a self, path: &[&[u8]]) -> Box<dyn DynStorageContext<'a> + 'a> {
+        subtree_storage_context(path, |prefix| {
+            Box::new(PrefixedSledStorageContext::new(
+                &self.storage.db,
+                Some(&self.transaction),
+                prefix,
+            ))
+        })
+    }
+
+    fn commit(self: Box<Self>) -> Result<(), DynStorageError> {
+        self.storage
+            .commit_transaction(self.transaction)
+            .map_err(DynStorageError::new)
+    }
+
+    fn rollback(&self) -> Result<(), DynStorageError> {
+        self.storage
+            .rollback_transaction(&self.transaction)
+            .map_err(DynStorageError::new)
+    }
+}
+
+impl_dyn_storage_context!(PrefixedSledStorageContext);
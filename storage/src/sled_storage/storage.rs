@@ -0,0 +1,186 @@
+//! Implementation for a storage abstraction over sled.
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError},
+    Db, Error, Tree,
+};
+
+use super::PrefixedSledStorageContext;
+use crate::Storage;
+
+/// Storage changes of a transaction by namespaced key, `None` for deletions
+pub(super) type PendingChanges = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Error of sled storage operations
+#[derive(Debug)]
+pub enum SledStorageError {
+    /// Error of sled itself
+    Sled(Error),
+    /// Transaction was not committed as a key it wrote was changed by someone
+    /// else after the transaction first wrote it
+    Conflict,
+}
+
+impl fmt::Display for SledStorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SledStorageError::Sled(e) => e.fmt(f),
+            SledStorageError::Conflict => write!(f, "transaction conflict"),
+        }
+    }
+}
+
+impl std::error::Error for SledStorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SledStorageError::Sled(e) => Some(e),
+            SledStorageError::Conflict => None,
+        }
+    }
+}
+
+impl From<Error> for SledStorageError {
+    fn from(e: Error) -> Self {
+        SledStorageError::Sled(e)
+    }
+}
+
+/// Storage which uses sled under the hood
+pub struct SledStorage {
+    pub(super) db: Db,
+}
+
+impl SledStorage {
+    /// Create sled storage with default parameters using `path`
+    pub fn default_sled_with_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(SledStorage {
+            db: sled::open(path)?,
+        })
+    }
+
+    /// Create sled storage which is removed on drop, for tests
+    pub fn temporary() -> Result<Self, Error> {
+        Ok(SledStorage {
+            db: sled::Config::new().temporary(true).open()?,
+        })
+    }
+
+    /// A helper method to build a prefix to sled keys or identify a subtree
+    /// in `subtrees` map by tree path, the same as RocksDB backend uses
+    pub fn build_prefix<'a, P>(path: P) -> Vec<u8>
+    where
+        P: IntoIterator<Item = &'a [u8]>,
+    {
+        crate::prefix::build_prefix(path)
+    }
+}
+
+/// Transaction over sled storage.
+/// Changes are buffered in memory and applied atomically on commit, reads
+/// inside the transaction see them. Like RocksDB optimistic transactions, the
+/// commit fails with [`SledStorageError::Conflict`] if a key the transaction
+/// wrote holds a different value than when the transaction first wrote it.
+#[derive(Default)]
+pub struct SledTransaction {
+    pending: Mutex<PendingChanges>,
+    /// Stored values of written keys as the transaction first wrote them
+    originals: Mutex<PendingChanges>,
+}
+
+impl SledTransaction {
+    pub(super) fn pending(&self) -> MutexGuard<PendingChanges> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn originals(&self) -> MutexGuard<PendingChanges> {
+        self.originals.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adds changes to the transaction, remembering stored values of keys
+    /// written for the first time to check them for conflicts on commit
+    pub(super) fn write(&self, tree: &Tree, changes: PendingChanges) -> Result<(), Error> {
+        let mut originals = self.originals();
+        for key in changes.keys() {
+            if !originals.contains_key(key) {
+                let original = tree.get(key)?.map(|v| v.to_vec());
+                originals.insert(key.clone(), original);
+            }
+        }
+        self.pending().extend(changes);
+        Ok(())
+    }
+}
+
+impl<'db> Storage<'db> for SledStorage {
+    type Error = SledStorageError;
+    type StorageContext = PrefixedSledStorageContext<'db>;
+    type Transaction = SledTransaction;
+    type TransactionalStorageContext = PrefixedSledStorageContext<'db>;
+
+    fn start_transaction(&'db self) -> Self::Transaction {
+        SledTransaction::default()
+    }
+
+    fn commit_transaction(&self, transaction: Self::Transaction) -> Result<(), Self::Error> {
+        let pending = transaction.pending();
+        let originals = transaction.originals();
+        // Checks and writes are made in a sled transaction, so no write gets
+        // between them
+        self.db
+            .transaction(|tree| {
+                for (key, original) in originals.iter() {
+                    if tree.get(key)?.as_deref() != original.as_deref() {
+                        return Err(ConflictableTransactionError::Abort(
+                            SledStorageError::Conflict,
+                        ));
+                    }
+                }
+                for (key, value) in pending.iter() {
+                    match value {
+                        Some(value) => tree.insert(key.as_slice(), value.as_slice())?,
+                        None => tree.remove(key.as_slice())?,
+                    };
+                }
+                Ok(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(e) => e,
+                TransactionError::Storage(e) => SledStorageError::Sled(e),
+            })
+    }
+
+    fn rollback_transaction(&self, transaction: &Self::Transaction) -> Result<(), Self::Error> {
+        transaction.pending().clear();
+        transaction.originals().clear();
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn get_storage_context<'p, P>(&'db self, path: P) -> Self::StorageContext
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        PrefixedSledStorageContext::new(&self.db, None, Self::build_prefix(path))
+    }
+
+    fn get_transactional_storage_context<'p, P>(
+        &'db self,
+        path: P,
+        transaction: &'db Self::Transaction,
+    ) -> Self::TransactionalStorageContext
+    where
+        P: IntoIterator<Item = &'p [u8]>,
+    {
+        PrefixedSledStorageContext::new(&self.db, Some(transaction), Self::build_prefix(path))
+    }
+}
@@ -0,0 +1,509 @@
+//! Implementation of prefixed storage context, batch and raw iterator for sled
+//! backend.
+use std::{convert::Infallible, ops::Bound};
+
+use sled::{Error, Tree};
+
+use super::{storage::PendingChanges, SledTransaction};
+use crate::{dyn_storage::DynStorageError, Batch, RawIterator, StorageContext};
+
+/// Namespace of data storage keys
+const DATA_NAMESPACE: u8 = 0;
+/// Namespace of auxiliary data storage keys
+const AUX_NAMESPACE: u8 = 1;
+/// Namespace of trees roots storage keys
+const ROOTS_NAMESPACE: u8 = 2;
+/// Namespace of GroveDB metadata storage keys
+const META_NAMESPACE: u8 = 3;
+
+/// Returns the smallest key greater than every key starting with `prefix`,
+/// `None` if there is no such key
+fn make_prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut bound = prefix.to_vec();
+    while let Some(last) = bound.pop() {
+        if last != u8::MAX {
+            bound.push(last + 1);
+            return Some(bound);
+        }
+    }
+    None
+}
+
+fn is_empty_range(lower: &Bound<Vec<u8>>, upper: &Bound<Vec<u8>>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(l), Bound::Included(u)) => l > u,
+        (Bound::Included(l), Bound::Excluded(u))
+        | (Bound::Excluded(l), Bound::Included(u))
+        | (Bound::Excluded(l), Bound::Excluded(u)) => l >= u,
+        _ => false,
+    }
+}
+
+/// Sled data as seen from a context: stored data with changes of a
+/// transaction, if any, on top
+#[derive(Clone, Copy)]
+struct SledView<'db> {
+    tree: &'db Tree,
+    transaction: Option<&'db SledTransaction>,
+}
+
+impl<'db> SledView<'db> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(transaction) = self.transaction {
+            if let Some(value) = transaction.pending().get(key) {
+                return Ok(value.clone());
+            }
+        }
+        Ok(self.tree.get(key)?.map(|v| v.to_vec()))
+    }
+
+    fn write(&self, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<(), Error> {
+        let mut changes = PendingChanges::new();
+        changes.insert(key, value);
+        self.apply(changes)
+    }
+
+    /// Applies changes atomically, or adds them to the transaction
+    fn apply(&self, changes: PendingChanges) -> Result<(), Error> {
+        if let Some(transaction) = self.transaction {
+            return transaction.write(self.tree, changes);
+        }
+        let mut batch = sled::Batch::default();
+        for (key, value) in changes {
+            match value {
+                Some(value) => batch.insert(key, value),
+                None => batch.remove(key),
+            }
+        }
+        self.tree.apply_batch(batch)
+    }
+
+    /// Finds the first entry in the range, or the last one if not `forward`
+    fn find(
+        &self,
+        mut lower: Bound<Vec<u8>>,
+        mut upper: Bound<Vec<u8>>,
+        forward: bool,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+        loop {
+            if is_empty_range(&lower, &upper) {
+                return Ok(None);
+            }
+            let mut stored_range = self.tree.range((lower.clone(), upper.clone()));
+            let stored = if forward {
+                stored_range.next()
+            } else {
+                stored_range.next_back()
+            }
+            .transpose()?
+            .map(|(k, v)| (k.to_vec(), Some(v.to_vec())));
+            let pending = self.transaction.and_then(|transaction| {
+                let changes = transaction.pending();
+                let mut pending_range = changes.range((lower.clone(), upper.clone()));
+                if forward {
+                    pending_range.next()
+                } else {
+                    pending_range.next_back()
+                }
+                .map(|(k, v)| (k.clone(), v.clone()))
+            });
+
+            // Transaction changes take precedence over stored data
+            let entry = match (stored, pending) {
+                (Some(stored), Some(pending)) => {
+                    if stored.0 == pending.0 || (pending.0 < stored.0) == forward {
+                        pending
+                    } else {
+                        stored
+                    }
+                }
+                (stored, pending) => match stored.or(pending) {
+                    Some(entry) => entry,
+                    None => return Ok(None),
+                },
+            };
+            match entry {
+                (key, Some(value)) => return Ok(Some((key, value))),
+                // Deleted in the transaction, continue past it
+                (key, None) => {
+                    if forward {
+                        lower = Bound::Excluded(key);
+                    } else {
+                        upper = Bound::Excluded(key);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Marks keys in range `from..to` as deleted in `changes`, both stored
+    /// ones and ones already in `changes`
+    fn delete_range_into(
+        &self,
+        changes: &mut PendingChanges,
+        from: Vec<u8>,
+        to: Option<Vec<u8>>,
+    ) -> Result<(), Error> {
+        let upper = to.map_or(Bound::Unbounded, Bound::Excluded);
+        let mut lower = Bound::Included(from.clone());
+        while let Some((key, _)) = self.find(lower, upper.clone(), true)? {
+            lower = Bound::Excluded(key.clone());
+            changes.insert(key, None);
+        }
+        let lower = Bound::Included(from);
+        if !is_empty_range(&lower, &upper) {
+            for (_, value) in changes.range_mut((lower, upper)) {
+                *value = None;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Storage context with a prefix applied to be used in a subtree, changes go
+/// to the transaction if there is one
+pub struct PrefixedSledStorageContext<'db> {
+    view: SledView<'db>,
+    prefix: Vec<u8>,
+}
+
+impl<'db> PrefixedSledStorageContext<'db> {
+    /// Create a new prefixed storage context instance
+    pub fn new(
+        tree: &'db Tree,
+        transaction: Option<&'db SledTransaction>,
+        prefix: Vec<u8>,
+    ) -> Self {
+        PrefixedSledStorageContext {
+            view: SledView { tree, transaction },
+            prefix,
+        }
+    }
+
+    fn key<K: AsRef<[u8]>>(&self, namespace: u8, key: K) -> Vec<u8> {
+        make_namespaced_key(namespace, &self.prefix, key.as_ref())
+    }
+}
+
+fn make_namespaced_key(namespace: u8, prefix: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut namespaced_key = Vec::with_capacity(1 + prefix.len() + key.len());
+    namespaced_key.push(namespace);
+    namespaced_key.extend_from_slice(prefix);
+    namespaced_key.extend_from_slice(key);
+    namespaced_key
+}
+
+impl<'db, 'ctx> StorageContext<'db, 'ctx> for PrefixedSledStorageContext<'db> {
+    type Batch = PrefixedSledBatch<'db>;
+    type Error = Error;
+    type RawIterator = PrefixedSledRawIterator<'db>;
+
+    fn put<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.view
+            .write(self.key(DATA_NAMESPACE, key), Some(value.to_vec()))
+    }
+
+    fn put_aux<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.view
+            .write(self.key(AUX_NAMESPACE, key), Some(value.to_vec()))
+    }
+
+    fn put_root<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.view
+            .write(self.key(ROOTS_NAMESPACE, key), Some(value.to_vec()))
+    }
+
+    fn put_meta<K: AsRef<[u8]>>(&self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.view
+            .write(self.key(META_NAMESPACE, key), Some(value.to_vec()))
+    }
+
+    fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
+        self.view.write(self.key(DATA_NAMESPACE, key), None)
+    }
+
+    fn delete_range<K: AsRef<[u8]>>(&self, from: K, to: K) -> Result<(), Self::Error> {
+        let mut changes = PendingChanges::new();
+        self.view.delete_range_into(
+            &mut changes,
+            self.key(DATA_NAMESPACE, from),
+            Some(self.key(DATA_NAMESPACE, to)),
+        )?;
+        self.view.apply(changes)
+    }
+
+    fn clear(&self) -> Result<(), Self::Error> {
+        let start = self.key(DATA_NAMESPACE, []);
+        let end = make_prefix_upper_bound(&start);
+        let mut changes = PendingChanges::new();
+        self.view.delete_range_into(&mut changes, start, end)?;
+        self.view.apply(changes)
+    }
+
+    fn delete_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
+        self.view.write(self.key(AUX_NAMESPACE, key), None)
+    }
+
+    fn delete_root<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
+        self.view.write(self.key(ROOTS_NAMESPACE, key), None)
+    }
+
+    fn delete_meta<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error> {
+        self.view.write(self.key(META_NAMESPACE, key), None)
+    }
+
+    fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.view.get(&self.key(DATA_NAMESPACE, key))
+    }
+
+    fn get_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.view.get(&self.key(AUX_NAMESPACE, key))
+    }
+
+    fn get_root<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.view.get(&self.key(ROOTS_NAMESPACE, key))
+    }
+
+    fn get_meta<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.view.get(&self.key(META_NAMESPACE, key))
+    }
+
+    fn new_batch(&'ctx self) -> Self::Batch {
+        PrefixedSledBatch {
+            view: self.view,
+            prefix: self.prefix.clone(),
+            ops: Vec::new(),
+        }
+    }
+
+    fn commit_batch(&'ctx self, batch: Self::Batch) -> Result<(), Self::Error> {
+        let mut changes = PendingChanges::new();
+        for op in batch.ops {
+            match op {
+                BatchOp::Write(key, value) => {
+                    changes.insert(key, value);
+                }
+                BatchOp::DeleteRange(from, to) => {
+                    self.view.delete_range_into(&mut changes, from, to)?;
+                }
+            }
+        }
+        self.view.apply(changes)
+    }
+
+    fn raw_iter(&self) -> Self::RawIterator {
+        PrefixedSledRawIterator::new(self.view, self.key(DATA_NAMESPACE, []), false)
+    }
+
+    fn raw_iter_opt(&self, _readahead_bytes: usize, _fill_cache: bool) -> Self::RawIterator {
+        // Sled has no read tuning options
+        StorageContext::raw_iter(self)
+    }
+
+    fn raw_iter_keys_only(&self) -> Self::RawIterator {
+        PrefixedSledRawIterator::new(self.view, self.key(DATA_NAMESPACE, []), true)
+    }
+
+    fn raw_iter_aux(&self) -> Self::RawIterator {
+        PrefixedSledRawIterator::new(self.view, self.key(AUX_NAMESPACE, []), false)
+    }
+
+    fn raw_iter_roots(&self) -> Self::RawIterator {
+        PrefixedSledRawIterator::new(self.view, self.key(ROOTS_NAMESPACE, []), false)
+    }
+}
+
+enum BatchOp {
+    /// Write of a namespaced key, `None` for deletion
+    Write(Vec<u8>, Option<Vec<u8>>),
+    /// Deletion of data keys from the first key up to the second one
+    DeleteRange(Vec<u8>, Option<Vec<u8>>),
+}
+
+/// Batch of sled storage context changes applied atomically on commit
+pub struct PrefixedSledBatch<'db> {
+    view: SledView<'db>,
+    prefix: Vec<u8>,
+    ops: Vec<BatchOp>,
+}
+
+impl PrefixedSledBatch<'_> {
+    fn write<K: AsRef<[u8]>>(&mut self, namespace: u8, key: K, value: Option<&[u8]>) {
+        self.ops.push(BatchOp::Write(
+            make_namespaced_key(namespace, &self.prefix, key.as_ref()),
+            value.map(|v| v.to_vec()),
+        ));
+    }
+}
+
+impl Batch for PrefixedSledBatch<'_> {
+    type Error = Infallible;
+
+    fn put<K: AsRef<[u8]>>(&mut self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.write(DATA_NAMESPACE, key, Some(value));
+        Ok(())
+    }
+
+    fn put_aux<K: AsRef<[u8]>>(&mut self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.write(AUX_NAMESPACE, key, Some(value));
+        Ok(())
+    }
+
+    fn put_root<K: AsRef<[u8]>>(&mut self, key: K, value: &[u8]) -> Result<(), Self::Error> {
+        self.write(ROOTS_NAMESPACE, key, Some(value));
+        Ok(())
+    }
+
+    fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
+        self.write(DATA_NAMESPACE, key, None);
+        Ok(())
+    }
+
+    fn delete_range<K: AsRef<[u8]>>(&mut self, from: K, to: K) -> Result<(), Self::Error> {
+        self.ops.push(BatchOp::DeleteRange(
+            make_namespaced_key(DATA_NAMESPACE, &self.prefix, from.as_ref()),
+            Some(make_namespaced_key(
+                DATA_NAMESPACE,
+                &self.prefix,
+                to.as_ref(),
+            )),
+        ));
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), Self::Error> {
+        let start = make_namespaced_key(DATA_NAMESPACE, &self.prefix, &[]);
+        let end = make_prefix_upper_bound(&start);
+        self.ops.push(BatchOp::DeleteRange(start, end));
+        Ok(())
+    }
+
+    fn delete_aux<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
+        self.write(AUX_NAMESPACE, key, None);
+        Ok(())
+    }
+
+    fn delete_root<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error> {
+        self.write(ROOTS_NAMESPACE, key, None);
+        Ok(())
+    }
+}
+
+/// Raw iterator over prefixed data of sled storage. Each move looks up the
+/// next entry in the storage, so it sees changes made after its creation.
+/// Storage errors make the iterator invalid as `RawIterator` can't return
+/// them.
+pub struct PrefixedSledRawIterator<'db> {
+    view: SledView<'db>,
+    /// Namespaced prefix of data keys
+    prefix: Vec<u8>,
+    upper_bound: Bound<Vec<u8>>,
+    /// Current entry with a namespaced key
+    current: Option<(Vec<u8>, Vec<u8>)>,
+    /// Values are not returned if set
+    keys_only: bool,
+    /// Error of the latest move, which made the iterator invalid
+    error: Option<Error>,
+}
+
+impl<'db> PrefixedSledRawIterator<'db> {
+    fn new(view: SledView<'db>, prefix: Vec<u8>, keys_only: bool) -> Self {
+        let upper_bound =
+            make_prefix_upper_bound(&prefix).map_or(Bound::Unbounded, Bound::Excluded);
+        PrefixedSledRawIterator {
+            view,
+            prefix,
+            upper_bound,
+            current: None,
+            keys_only,
+            error: None,
+        }
+    }
+
+    fn find(&mut self, lower: Bound<Vec<u8>>, upper: Bound<Vec<u8>>, forward: bool) {
+        match self.view.find(lower, upper, forward) {
+            Ok(current) => {
+                self.current = current;
+                self.error = None;
+            }
+            Err(e) => {
+                self.current = None;
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+impl RawIterator for PrefixedSledRawIterator<'_> {
+    fn seek_to_first(&mut self) {
+        self.find(
+            Bound::Included(self.prefix.clone()),
+            self.upper_bound.clone(),
+            true,
+        )
+    }
+
+    fn seek_to_last(&mut self) {
+        self.find(
+            Bound::Included(self.prefix.clone()),
+            self.upper_bound.clone(),
+            false,
+        )
+    }
+
+    fn seek<K: AsRef<[u8]>>(&mut self, key: K) {
+        let mut from = self.prefix.clone();
+        from.extend_from_slice(key.as_ref());
+        self.find(Bound::Included(from), self.upper_bound.clone(), true)
+    }
+
+    fn seek_for_prev<K: AsRef<[u8]>>(&mut self, key: K) {
+        let mut to = self.prefix.clone();
+        to.extend_from_slice(key.as_ref());
+        self.find(
+            Bound::Included(self.prefix.clone()),
+            Bound::Included(to),
+            false,
+        )
+    }
+
+    fn next(&mut self) {
+        if let Some((key, _)) = self.current.take() {
+            self.find(Bound::Excluded(key), self.upper_bound.clone(), true)
+        }
+    }
+
+    fn prev(&mut self) {
+        if let Some((key, _)) = self.current.take() {
+            self.find(
+                Bound::Included(self.prefix.clone()),
+                Bound::Excluded(key),
+                false,
+            )
+        }
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        if self.keys_only {
+            None
+        } else {
+            self.current.as_ref().map(|(_, value)| value.as_slice())
+        }
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        self.current
+            .as_ref()
+            .map(|(key, _)| &key[self.prefix.len()..])
+    }
+
+    fn valid(&self) -> bool {
+        self.current.is_some()
+    }
+
+    fn status(&self) -> Result<(), DynStorageError> {
+        self.error
+            .clone()
+            .map_or(Ok(()), |e| Err(DynStorageError::new(e)))
+    }
+}
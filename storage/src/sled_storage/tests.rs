@@ -0,0 +1,238 @@
+use super::{SledStorage, SledStorageError};
+use crate::{Batch, BoxedStorage, RawIterator, Storage, StorageContext};
+
+fn to_path(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::once(bytes)
+}
+
+fn collect_keys<I: RawIterator>(mut iter: I) -> Vec<Vec<u8>> {
+    let mut keys = Vec::new();
+    iter.seek_to_first();
+    while iter.valid() {
+        keys.push(iter.key().expect("key should exist").to_vec());
+        iter.next();
+    }
+    keys
+}
+
+#[test]
+fn test_namespaces_and_prefixes() {
+    let storage = SledStorage::temporary().expect("cannot open sled storage");
+    let context_ayya = storage.get_storage_context(to_path(b"ayya"));
+    let context_ayyb = storage.get_storage_context(to_path(b"ayyb"));
+
+    context_ayya
+        .put(b"key1", b"data")
+        .expect("cannot insert data");
+    context_ayya
+        .put_aux(b"key1", b"aux")
+        .expect("cannot insert into aux");
+    context_ayya
+        .put_root(b"key1", b"root")
+        .expect("cannot insert into roots");
+    context_ayya
+        .put_meta(b"key1", b"meta")
+        .expect("cannot insert into meta");
+
+    assert_eq!(
+        context_ayya.get(b"key1").expect("cannot get data"),
+        Some(b"data".to_vec())
+    );
+    assert_eq!(
+        context_ayya.get_aux(b"key1").expect("cannot get from aux"),
+        Some(b"aux".to_vec())
+    );
+    assert_eq!(
+        context_ayya
+            .get_root(b"key1")
+            .expect("cannot get from roots"),
+        Some(b"root".to_vec())
+    );
+    assert_eq!(
+        context_ayya
+            .get_meta(b"key1")
+            .expect("cannot get from meta"),
+        Some(b"meta".to_vec())
+    );
+    assert!(context_ayyb
+        .get(b"key1")
+        .expect("cannot get data")
+        .is_none());
+
+    context_ayya
+        .delete_aux(b"key1")
+        .expect("cannot delete from aux");
+    assert!(context_ayya
+        .get_aux(b"key1")
+        .expect("cannot get from aux")
+        .is_none());
+    assert_eq!(
+        collect_keys(context_ayya.raw_iter()),
+        vec![b"key1".to_vec()]
+    );
+}
+
+#[test]
+fn test_batch_and_raw_iterator() {
+    let storage = SledStorage::temporary().expect("cannot open sled storage");
+    let context = storage.get_storage_context(to_path(b"ayya"));
+    let other_context = storage.get_storage_context(to_path(b"ayyb"));
+    other_context
+        .put(b"key0", b"value0")
+        .expect("cannot insert data");
+
+    let mut batch = context.new_batch();
+    for key in [b"key1", b"key2", b"key3", b"key4"] {
+        batch.put(key, b"value").expect("cannot put into batch");
+    }
+    batch
+        .delete_range(b"key2", b"key4")
+        .expect("cannot delete range in batch");
+    context.commit_batch(batch).expect("cannot commit batch");
+    assert_eq!(
+        collect_keys(context.raw_iter()),
+        vec![b"key1".to_vec(), b"key4".to_vec()]
+    );
+
+    let mut iter = context.raw_iter();
+    iter.seek(b"key2");
+    assert_eq!(iter.key(), Some(b"key4".as_ref()));
+    iter.prev();
+    assert_eq!(iter.key(), Some(b"key1".as_ref()));
+    iter.seek_for_prev(b"key3");
+    assert_eq!(iter.key(), Some(b"key1".as_ref()));
+    iter.seek_to_last();
+    assert_eq!(iter.key(), Some(b"key4".as_ref()));
+    assert_eq!(iter.value(), Some(b"value".as_ref()));
+    iter.next();
+    assert!(!iter.valid());
+
+    let mut keys_only = context.raw_iter_keys_only();
+    keys_only.seek_to_first();
+    assert_eq!(keys_only.key(), Some(b"key1".as_ref()));
+    assert!(keys_only.value().is_none());
+
+    context.clear().expect("cannot clear data");
+    assert!(collect_keys(context.raw_iter()).is_empty());
+    assert_eq!(
+        collect_keys(other_context.raw_iter()),
+        vec![b"key0".to_vec()]
+    );
+}
+
+#[test]
+fn test_transaction() {
+    let storage = SledStorage::temporary().expect("cannot open sled storage");
+    let context = storage.get_storage_context(to_path(b"ayya"));
+    context.put(b"key1", b"value1").expect("cannot insert data");
+    context.put(b"key2", b"value2").expect("cannot insert data");
+
+    let tx = storage.start_transaction();
+    let tx_context = storage.get_transactional_storage_context(to_path(b"ayya"), &tx);
+    tx_context
+        .put(b"key3", b"value3")
+        .expect("cannot insert data");
+    tx_context.delete(b"key1").expect("cannot delete data");
+
+    assert!(tx_context.get(b"key1").expect("cannot get data").is_none());
+    assert_eq!(
+        collect_keys(tx_context.raw_iter()),
+        vec![b"key2".to_vec(), b"key3".to_vec()]
+    );
+    assert_eq!(
+        collect_keys(context.raw_iter()),
+        vec![b"key1".to_vec(), b"key2".to_vec()]
+    );
+
+    storage
+        .commit_transaction(tx)
+        .expect("cannot commit transaction");
+    assert_eq!(
+        collect_keys(context.raw_iter()),
+        vec![b"key2".to_vec(), b"key3".to_vec()]
+    );
+
+    let tx = storage.start_transaction();
+    let tx_context = storage.get_transactional_storage_context(to_path(b"ayya"), &tx);
+    tx_context.clear().expect("cannot clear data");
+    assert!(collect_keys(tx_context.raw_iter()).is_empty());
+    storage
+        .rollback_transaction(&tx)
+        .expect("cannot rollback transaction");
+    assert_eq!(
+        collect_keys(tx_context.raw_iter()),
+        vec![b"key2".to_vec(), b"key3".to_vec()]
+    );
+}
+
+#[test]
+fn test_transaction_conflicts() {
+    let storage = SledStorage::temporary().expect("cannot open sled storage");
+    let context = storage.get_storage_context(to_path(b"ayya"));
+    context.put(b"key1", b"value1").expect("cannot insert data");
+
+    let tx1 = storage.start_transaction();
+    let tx2 = storage.start_transaction();
+    storage
+        .get_transactional_storage_context(to_path(b"ayya"), &tx1)
+        .put(b"key1", b"tx1")
+        .expect("cannot insert data");
+    storage
+        .get_transactional_storage_context(to_path(b"ayya"), &tx2)
+        .put(b"key1", b"tx2")
+        .expect("cannot insert data");
+    storage
+        .commit_transaction(tx1)
+        .expect("cannot commit transaction");
+    assert!(matches!(
+        storage.commit_transaction(tx2),
+        Err(SledStorageError::Conflict)
+    ));
+    assert_eq!(
+        context.get(b"key1").expect("cannot get data"),
+        Some(b"tx1".to_vec())
+    );
+
+    // Transactions writing different keys don't conflict
+    let tx1 = storage.start_transaction();
+    let tx2 = storage.start_transaction();
+    storage
+        .get_transactional_storage_context(to_path(b"ayya"), &tx1)
+        .put(b"key2", b"tx1")
+        .expect("cannot insert data");
+    storage
+        .get_transactional_storage_context(to_path(b"ayya"), &tx2)
+        .delete(b"key1")
+        .expect("cannot delete data");
+    context.put(b"key3", b"value3").expect("cannot insert data");
+    storage
+        .commit_transaction(tx1)
+        .expect("cannot commit transaction");
+    storage
+        .commit_transaction(tx2)
+        .expect("cannot commit transaction");
+    assert_eq!(
+        collect_keys(context.raw_iter()),
+        vec![b"key2".to_vec(), b"key3".to_vec()]
+    );
+}
+
+#[test]
+fn test_boxed_storage() {
+    let storage: BoxedStorage =
+        Box::new(SledStorage::temporary().expect("cannot open sled storage"));
+    let tx = storage.start_transaction();
+    storage
+        .get_transactional_storage_context(to_path(b"ayya"), &tx)
+        .put(b"key1", b"value1")
+        .expect("cannot insert data");
+    let context = storage.get_storage_context(to_path(b"ayya"));
+    assert!(context.get(b"key1").expect("cannot get data").is_none());
+    storage
+        .commit_transaction(tx)
+        .expect("cannot commit transaction");
+    assert_eq!(
+        context.get(b"key1").expect("cannot get data"),
+        Some(b"value1".to_vec())
+    );
+}
@@ -1,3 +1,5 @@
+use crate::dyn_storage::DynStorageError;
+
 /// Top-level storage abstraction.
 /// Should be able to hold storage connection and to start transaction when
 /// needed. All query operations will be exposed using [StorageContext].
@@ -74,6 +76,12 @@ pub trait StorageContext<'db, 'ctx> {
     /// Delete entry with `key` from data storage
     fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error>;
 
+    /// Delete entries with keys in range `from..to` from data storage
+    fn delete_range<K: AsRef<[u8]>>(&self, from: K, to: K) -> Result<(), Self::Error>;
+
+    /// Delete all entries from data storage
+    fn clear(&self) -> Result<(), Self::Error>;
+
     /// Delete entry with `key` from auxiliary data storage
     fn delete_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Self::Error>;
 
@@ -86,6 +94,12 @@ pub trait StorageContext<'db, 'ctx> {
     /// Get entry by `key` from data storage
     fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error>;
 
+    /// Get entries by `keys` from data storage in the order of `keys`. They
+    /// are read one by one unless the backend can read them in a batch.
+    fn get_many<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<Vec<Option<Vec<u8>>>, Self::Error> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
     /// Get entry by `key` from auxiliary data storage
     fn get_aux<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Self::Error>;
 
@@ -103,6 +117,22 @@ pub trait StorageContext<'db, 'ctx> {
 
     /// Get raw iterator over storage
     fn raw_iter(&self) -> Self::RawIterator;
+
+    /// Get raw iterator over storage with `readahead_bytes` of read-ahead,
+    /// `0` for the backend default, which puts blocks it reads into the block
+    /// cache only if `fill_cache` is set
+    fn raw_iter_opt(&self, readahead_bytes: usize, fill_cache: bool) -> Self::RawIterator;
+
+    /// Get raw iterator over storage which yields keys only: values are never
+    /// returned and blocks read by the scan don't fill the block cache, to cut
+    /// IO of existence scans and key-set diffs over large subtrees
+    fn raw_iter_keys_only(&self) -> Self::RawIterator;
+
+    /// Get raw iterator over auxiliary data storage
+    fn raw_iter_aux(&self) -> Self::RawIterator;
+
+    /// Get raw iterator over trees roots data storage
+    fn raw_iter_roots(&self) -> Self::RawIterator;
 }
 
 pub trait Batch {
@@ -116,11 +146,21 @@ pub trait Batch {
 
     fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error>;
 
+    /// Delete entries with keys in range `from..to` from data storage
+    fn delete_range<K: AsRef<[u8]>>(&mut self, from: K, to: K) -> Result<(), Self::Error>;
+
+    /// Delete all entries from data storage
+    fn clear(&mut self) -> Result<(), Self::Error>;
+
     fn delete_aux<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error>;
 
     fn delete_root<K: AsRef<[u8]>>(&mut self, key: K) -> Result<(), Self::Error>;
 }
 
+/// Raw iterator over a storage context.
+/// Keys are iterated in byte-wise lexicographic order, which is the order of
+/// query results and proofs, so all backends must keep it to produce the same
+/// results for the same data.
 pub trait RawIterator {
     fn seek_to_first(&mut self);
 
@@ -139,4 +179,8 @@ pub trait RawIterator {
     fn key(&self) -> Option<&[u8]>;
 
     fn valid(&self) -> bool;
+
+    /// Returns the error which made the iterator invalid, if any, so an error
+    /// is not mistaken for the end of data
+    fn status(&self) -> Result<(), DynStorageError>;
 }